@@ -178,6 +178,9 @@ pub enum ErrorKind {
     /// Invalid Balance Burnt
     #[fail(display = "Invalid Balance Burnt")]
     InvalidBalanceBurnt,
+    /// Invalid Congestion Info
+    #[fail(display = "Invalid Congestion Info")]
+    InvalidCongestionInfo,
     /// Invalid shard id
     #[fail(display = "Shard id {} does not exist", _0)]
     InvalidShardId(ShardId),
@@ -214,6 +217,9 @@ pub enum ErrorKind {
     /// GC error.
     #[fail(display = "GC Error: {}", _0)]
     GCError(String),
+    /// Requested data has been garbage collected.
+    #[fail(display = "Garbage Collected: {}", _0)]
+    GarbageCollected(String),
     /// Anything else
     #[fail(display = "Other Error: {}", _0)]
     Other(String),
@@ -275,6 +281,7 @@ impl Error {
             | ErrorKind::ChallengedBlockOnChain
             | ErrorKind::StorageError(_)
             | ErrorKind::GCError(_)
+            | ErrorKind::GarbageCollected(_)
             | ErrorKind::DBNotFoundErr(_) => false,
             ErrorKind::InvalidBlockPastTime(_, _)
             | ErrorKind::InvalidBlockFutureTime(_)
@@ -307,6 +314,7 @@ impl Error {
             | ErrorKind::InvalidGasPrice
             | ErrorKind::InvalidGasUsed
             | ErrorKind::InvalidBalanceBurnt
+            | ErrorKind::InvalidCongestionInfo
             | ErrorKind::InvalidShardId(_)
             | ErrorKind::InvalidStateRequest(_)
             | ErrorKind::InvalidRandomnessBeaconOutput
@@ -11,6 +11,7 @@ use rand::seq::SliceRandom;
 use rand::SeedableRng;
 use tracing::{debug, error, info, warn};
 
+use near_chain_configs::GCRetentionConfig;
 use near_chain_primitives::error::{Error, ErrorKind, LogTransientStorageError};
 use near_primitives::block::{genesis_chunks, Tip};
 use near_primitives::challenge::{
@@ -22,7 +23,8 @@ use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{
     combine_hash, merklize, verify_path, Direction, MerklePath, MerklePathItem,
 };
-use near_primitives::receipt::Receipt;
+use near_primitives::receipt::{CongestionInfo, Receipt};
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::sharding::{
     ChunkHash, ChunkHashHeight, ReceiptList, ReceiptProof, ShardChunk, ShardChunkHeader, ShardInfo,
     ShardProof, StateSyncInfo,
@@ -36,7 +38,7 @@ use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{
     AccountId, Balance, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
-    NumBlocks, ShardId,
+    NumBlocks, ShardId, StateRoot,
 };
 use near_primitives::unwrap_or_return;
 #[cfg(feature = "protocol_feature_block_header_v3")]
@@ -46,17 +48,20 @@ use near_primitives::views::{
     FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
     SignedTransactionView,
 };
-use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
+use near_store::{
+    ColFlatState, ColFlatStateDeltas, ColFlatStateMisc, ColState, ColStateHeaders, ColStateParts,
+    ShardTries, StateChangesForSplitStates, StoreUpdate,
+};
 
 use near_primitives::state_record::StateRecord;
 
 use crate::lightclient::get_epoch_block_producers_view;
 use crate::migrations::check_if_block_is_first_with_chunk_of_version;
 use crate::missing_chunks::{BlockLike, MissingChunksPool};
-use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCMode};
+use crate::store::{ChainStore, ChainStoreAccess, ChainStoreUpdate, GCDataCategory, GCMode};
 use crate::types::{
-    AcceptedBlock, ApplyTransactionResult, Block, BlockEconomicsConfig, BlockHeader,
-    BlockHeaderInfo, BlockStatus, ChainGenesis, Provenance, RuntimeAdapter,
+    AcceptedBlock, ApplySplitStateResult, ApplyTransactionResult, Block, BlockEconomicsConfig,
+    BlockHeader, BlockHeaderInfo, BlockStatus, ChainGenesis, Provenance, RuntimeAdapter,
 };
 use crate::validate::{
     validate_challenge, validate_chunk_proofs, validate_chunk_with_chunk_extra,
@@ -89,9 +94,29 @@ pub const NUM_EPOCHS_TO_KEEP_STORE_DATA: u64 = 5;
 /// Maximum number of height to go through at each step when cleaning forks during garbage collection.
 const GC_FORK_CLEAN_STEP: u64 = 1000;
 
+/// Which shards to apply when applying the chunks of a block.
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum ApplyChunksMode {
-    ThisEpoch,
-    NextEpoch,
+    /// The states of the shards we care about in the next epoch are ready: apply the shards we
+    /// care about in this epoch or the next one. If the shard layout changes in the next epoch,
+    /// the states of the split shards are updated as well.
+    IsCaughtUp,
+    /// The states of the next epoch are being caught up: apply only the shards we will care about
+    /// in the next epoch, and update the split states of the shards whose transactions were
+    /// applied before the catch up started.
+    CatchingUp,
+    /// The states of the next epoch are not ready: apply only the shards we care about in this
+    /// epoch. If the shard layout changes, the state changes are saved to update the split
+    /// states later.
+    NotCaughtUp,
+}
+
+/// What to do with the split states of a shard after applying its chunk.
+enum ApplySplitStateResultOrStateChanges {
+    /// The split states were updated.
+    ApplySplitStateResults(Vec<ApplySplitStateResult>),
+    /// The split states are not ready yet, the state changes have to be saved.
+    StateChangesForSplitStates(StateChangesForSplitStates),
 }
 
 pub struct Orphan {
@@ -207,6 +232,9 @@ pub struct Chain {
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
     pending_states_to_patch: Option<Vec<StateRecord>>,
+    /// Shards whose flat storage is created in the background once the final head reaches the
+    /// height, see `schedule_flat_storage_creation`.
+    flat_storages_to_create: HashMap<ShardUId, BlockHeight>,
 }
 
 impl Chain {
@@ -219,7 +247,7 @@ impl Chain {
         let store = ChainStore::new(store, chain_genesis.height);
         let genesis_chunks = genesis_chunks(
             state_roots.clone(),
-            runtime_adapter.num_shards(),
+            runtime_adapter.num_shards(&EpochId::default())?,
             chain_genesis.gas_limit,
             chain_genesis.height,
             chain_genesis.protocol_version,
@@ -244,6 +272,7 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            flat_storages_to_create: HashMap::new(),
         })
     }
 
@@ -257,7 +286,7 @@ impl Chain {
         let mut store = ChainStore::new(store, chain_genesis.height);
         let genesis_chunks = genesis_chunks(
             state_roots.clone(),
-            runtime_adapter.num_shards(),
+            runtime_adapter.num_shards(&EpochId::default())?,
             chain_genesis.gas_limit,
             chain_genesis.height,
             chain_genesis.protocol_version,
@@ -323,7 +352,8 @@ impl Chain {
                     {
                         store_update.save_chunk_extra(
                             &genesis.hash(),
-                            chunk_header.shard_id(),
+                            &runtime_adapter
+                                .shard_id_to_uid(chunk_header.shard_id(), &EpochId::default())?,
                             ChunkExtra::new(
                                 state_root,
                                 CryptoHash::default(),
@@ -331,6 +361,8 @@ impl Chain {
                                 0,
                                 chain_genesis.gas_limit,
                                 0,
+                                CongestionInfo::default(),
+                                0,
                             ),
                         );
                     }
@@ -345,6 +377,8 @@ impl Chain {
             },
         }
         store_update.commit()?;
+        let flat_storages_to_create =
+            Chain::schedule_missing_flat_storages(&mut store, &*runtime_adapter)?;
 
         info!(target: "chain", "Init: head @ {} [{}]", head.height, head.last_block_hash);
 
@@ -359,9 +393,105 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            flat_storages_to_create,
         })
     }
 
+    /// Schedules the creation of the flat storages of the shards with state at the final head
+    /// which don't have one yet: at genesis, after the DB migration adding the flat state or
+    /// after a restart interrupting the creation. The reads of these shards are served by the
+    /// trie until their flat storages are created.
+    fn schedule_missing_flat_storages(
+        store: &mut ChainStore,
+        runtime_adapter: &dyn RuntimeAdapter,
+    ) -> Result<HashMap<ShardUId, BlockHeight>, Error> {
+        let head = store.head()?;
+        let final_head = store.final_head()?;
+        let tries = runtime_adapter.get_tries();
+        let mut flat_storages_to_create = HashMap::new();
+        for shard_id in 0..runtime_adapter.num_shards(&final_head.epoch_id)? {
+            let shard_uid = runtime_adapter.shard_id_to_uid(shard_id, &final_head.epoch_id)?;
+            if tries.get_flat_storage_head(&shard_uid).is_some()
+                || store.get_chunk_extra(&final_head.last_block_hash, &shard_uid).is_err()
+            {
+                continue;
+            }
+            tries
+                .start_flat_storage_creation(shard_uid)
+                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+            // The deltas of the blocks up to the head were not recorded before the restart.
+            flat_storages_to_create.insert(shard_uid, head.height);
+        }
+        Ok(flat_storages_to_create)
+    }
+
+    /// Schedules the creation of the flat storage of `shard_uid`, replacing the existing one.
+    /// The deltas of the blocks applied from now on are recorded, and the flat storage is
+    /// created in the background from the state at the first final head at or above `height`.
+    fn schedule_flat_storage_creation(
+        &mut self,
+        shard_uid: ShardUId,
+        height: BlockHeight,
+    ) -> Result<(), Error> {
+        info!(target: "chain", "Scheduling creation of flat storage of shard {:?} at #{}", shard_uid, height);
+        self.runtime_adapter
+            .get_tries()
+            .start_flat_storage_creation(shard_uid)
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        self.flat_storages_to_create.insert(shard_uid, height);
+        Ok(())
+    }
+
+    /// Moves the flat storage heads to the last final block and starts the scheduled flat
+    /// storage creations which are due.
+    fn update_flat_storage_heads(&mut self) -> Result<(), Error> {
+        let final_head = self.store.final_head()?;
+        let tries = self.runtime_adapter.get_tries();
+        tries
+            .move_flat_storage_heads(&final_head.last_block_hash)
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+
+        let due_shards: Vec<_> = self
+            .flat_storages_to_create
+            .iter()
+            .filter(|(_, height)| **height <= final_head.height)
+            .map(|(shard_uid, _)| *shard_uid)
+            .collect();
+        for shard_uid in due_shards {
+            // The state may not be there yet, e.g. for the shards still catching up.
+            let state_root =
+                match self.store.get_chunk_extra(&final_head.last_block_hash, &shard_uid) {
+                    Ok(chunk_extra) => *chunk_extra.state_root(),
+                    Err(_) => continue,
+                };
+            self.flat_storages_to_create.remove(&shard_uid);
+            Chain::spawn_flat_storage_creation(tries.clone(), shard_uid, &final_head, state_root)?;
+        }
+        Ok(())
+    }
+
+    fn spawn_flat_storage_creation(
+        tries: ShardTries,
+        shard_uid: ShardUId,
+        final_head: &Tip,
+        state_root: StateRoot,
+    ) -> Result<(), Error> {
+        let (block_hash, height) = (final_head.last_block_hash, final_head.height);
+        info!(target: "chain", "Creating flat storage of shard {:?} at {}", shard_uid, block_hash);
+        std::thread::Builder::new()
+            .name("flat_storage".to_string())
+            .spawn(move || {
+                if let Err(err) =
+                    tries.create_flat_storage(shard_uid, block_hash, height, state_root)
+                {
+                    // Reads of the shard are served by the trie without flat storage.
+                    warn!(target: "chain", "Failed to create flat storage of shard {:?}: {}", shard_uid, err);
+                }
+            })
+            .map_err(|err| ErrorKind::Other(err.to_string()))?;
+        Ok(())
+    }
+
     #[cfg(feature = "adversarial")]
     pub fn adv_disable_doomslug(&mut self) {
         self.doomslug_threshold_mode = DoomslugThresholdMode::NoApprovals
@@ -559,6 +689,7 @@ impl Chain {
         &mut self,
         tries: ShardTries,
         gc_blocks_limit: NumBlocks,
+        gc_retention: &GCRetentionConfig,
     ) -> Result<(), Error> {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("GC".into());
@@ -587,10 +718,19 @@ impl Chain {
         }
         let mut gc_blocks_remaining = gc_blocks_limit;
 
+        // Clearing the data kept longer than blocks
+        self.clear_gc_retained_data(&head, gc_retention, &mut gc_blocks_remaining)?;
+        let retained_categories = GCDataCategory::retained(gc_retention);
+
         // Forks Cleaning
         let stop_height = std::cmp::max(tail, fork_tail.saturating_sub(GC_FORK_CLEAN_STEP));
         for height in (stop_height..fork_tail).rev() {
-            self.clear_forks_data(tries.clone(), height, &mut gc_blocks_remaining)?;
+            self.clear_forks_data(
+                tries.clone(),
+                height,
+                &retained_categories,
+                &mut gc_blocks_remaining,
+            )?;
             if gc_blocks_remaining == 0 {
                 return Ok(());
             }
@@ -605,6 +745,7 @@ impl Chain {
                 return Ok(());
             }
             let mut chain_store_update = self.store.store_update();
+            chain_store_update.set_gc_retained_categories(retained_categories.clone());
             if let Ok(blocks_current_height) =
                 chain_store_update.get_chain_store().get_all_block_hashes_by_height(height)
             {
@@ -618,8 +759,11 @@ impl Chain {
                         break;
                     } else if prev_block_refcount == 1 {
                         debug_assert_eq!(blocks_current_height.len(), 1);
-                        chain_store_update
-                            .clear_block_data(*block_hash, GCMode::Canonical(tries.clone()))?;
+                        chain_store_update.clear_block_data(
+                            &*self.runtime_adapter,
+                            *block_hash,
+                            GCMode::Canonical(tries.clone()),
+                        )?;
                         gc_blocks_remaining -= 1;
                     } else {
                         return Err(ErrorKind::GCError(
@@ -639,6 +783,7 @@ impl Chain {
         &mut self,
         tries: ShardTries,
         height: BlockHeight,
+        retained_categories: &[GCDataCategory],
         gc_blocks_remaining: &mut NumBlocks,
     ) -> Result<(), Error> {
         if let Ok(blocks_current_height) = self.store.get_all_block_hashes_by_height(height) {
@@ -655,13 +800,17 @@ impl Chain {
                     // and it may be safely deleted
                     // and all its ancestors while there are no other sibling blocks rely on it.
                     let mut chain_store_update = self.store.store_update();
+                    chain_store_update.set_gc_retained_categories(retained_categories.to_vec());
                     if *chain_store_update.get_block_refcount(&current_hash)? == 0 {
                         let prev_hash =
                             *chain_store_update.get_block_header(&current_hash)?.prev_hash();
 
                         // It's safe to call `clear_block_data` for prev data because it clears fork only here
-                        chain_store_update
-                            .clear_block_data(current_hash, GCMode::Fork(tries.clone()))?;
+                        chain_store_update.clear_block_data(
+                            &*self.runtime_adapter,
+                            current_hash,
+                            GCMode::Fork(tries.clone()),
+                        )?;
                         chain_store_update.commit()?;
                         *gc_blocks_remaining -= 1;
 
@@ -677,6 +826,39 @@ impl Chain {
         Ok(())
     }
 
+    /// Deletes the data GC kept longer than blocks once it leaves the retention window of its
+    /// category. Data of the categories which are no longer retained is deleted with the blocks.
+    fn clear_gc_retained_data(
+        &mut self,
+        head: &Tip,
+        gc_retention: &GCRetentionConfig,
+        gc_blocks_remaining: &mut NumBlocks,
+    ) -> Result<(), Error> {
+        for category in GCDataCategory::RETAINABLE.iter() {
+            let retained_tail = match self.store.gc_retained_tail(*category)? {
+                Some(retained_tail) => retained_tail,
+                None => continue,
+            };
+            let stop_height = self.runtime_adapter.get_gc_stop_height_for_num_epochs(
+                &head.last_block_hash,
+                category.num_epochs_to_keep(gc_retention),
+            );
+            let mut chain_store_update = self.store.store_update();
+            let mut height = retained_tail;
+            while height < stop_height && *gc_blocks_remaining > 0 {
+                if chain_store_update.clear_gc_retained_data(*category, height)? {
+                    *gc_blocks_remaining -= 1;
+                }
+                height += 1;
+            }
+            if height != retained_tail {
+                chain_store_update.update_gc_retained_tail(*category, height);
+                chain_store_update.commit()?;
+            }
+        }
+        Ok(())
+    }
+
     /// Do Basic validation of a block upon receiving it. Check that header is valid
     /// and block is well-formed (various roots match).
     pub fn validate_block(&mut self, block: &Block) -> Result<(), Error> {
@@ -921,12 +1103,13 @@ impl Chain {
                 let blocks_current_height =
                     blocks_current_height.values().flatten().cloned().collect::<Vec<_>>();
                 for block_hash in blocks_current_height {
-                    let mut chain_store_update = self.mut_store().store_update();
+                    let mut chain_store_update = self.store.store_update();
                     if !tail_prev_block_cleaned {
                         let prev_block_hash =
                             *chain_store_update.get_block_header(&block_hash)?.prev_hash();
                         if chain_store_update.get_block(&prev_block_hash).is_ok() {
                             chain_store_update.clear_block_data(
+                                &*self.runtime_adapter,
                                 prev_block_hash,
                                 GCMode::StateSync { clear_block_info: true },
                             )?;
@@ -934,6 +1117,7 @@ impl Chain {
                         tail_prev_block_cleaned = true;
                     }
                     chain_store_update.clear_block_data(
+                        &*self.runtime_adapter,
                         block_hash,
                         GCMode::StateSync { clear_block_info: block_hash != prev_hash },
                     )?;
@@ -949,12 +1133,30 @@ impl Chain {
         chain_store_update.clear_chunk_data_and_headers(chunk_height)?;
         chain_store_update.commit()?;
 
+        // Clear the data GC kept longer than blocks
+        for category in GCDataCategory::RETAINABLE.iter() {
+            if let Some(retained_tail) = self.store.gc_retained_tail(*category)? {
+                for height in retained_tail..chunk_height {
+                    let mut chain_store_update = self.mut_store().store_update();
+                    if chain_store_update.clear_gc_retained_data(*category, height)? {
+                        chain_store_update.commit()?;
+                    }
+                }
+                let mut chain_store_update = self.mut_store().store_update();
+                chain_store_update.update_gc_retained_tail(*category, chunk_height);
+                chain_store_update.commit()?;
+            }
+        }
+
         // clear all trie data
 
         let tries = self.runtime_adapter.get_tries();
         let mut chain_store_update = self.mut_store().store_update();
         let mut store_update = StoreUpdate::new_with_tries(tries);
         store_update.delete_all(ColState);
+        store_update.delete_all(ColFlatState);
+        store_update.delete_all(ColFlatStateDeltas);
+        store_update.delete_all(ColFlatStateMisc);
         chain_store_update.merge(store_update);
 
         // The reason to reset tail here is not to allow Tail be greater than Head
@@ -1010,7 +1212,7 @@ impl Chain {
         block: &Block,
     ) -> Result<(), Error> {
         let prev_hash = *block.header().prev_hash();
-        let shards_to_dl = self.get_shards_to_dl_state(me, &prev_hash);
+        let shards_to_dl = self.get_shards_to_dl_state(me, &prev_hash)?;
         let prev_block = self.get_block(&prev_hash)?;
 
         debug!(target: "chain", "Downloading state for {:?}, I'm {:?}", shards_to_dl, me);
@@ -1060,6 +1262,12 @@ impl Chain {
             Ok((head, needs_to_start_fetching_state)) => {
                 chain_update.chain_store_update.save_block_height_processed(block_height);
                 chain_update.commit()?;
+                if head.is_some() {
+                    self.update_flat_storage_heads()?;
+                    if let Err(err) = self.prefetch_receipts_of_block(me, &block) {
+                        debug!(target: "chain", "Failed to prefetch receipts of block {}: {}", block.hash(), err);
+                    }
+                }
 
                 self.pending_states_to_patch = None;
 
@@ -1175,24 +1383,83 @@ impl Chain {
         Ok(!self.store.get_blocks_to_catchup(prev_prev_hash)?.contains(&prev_hash))
     }
 
+    /// Shards of the epoch of the block after `parent_hash` whose states have to be caught up
+    /// for the next epoch: the ones we will care about and don't have the state of, and the ones
+    /// whose states have to be split because the shard layout changes.
     fn get_shards_to_dl_state(
         &self,
         me: &Option<AccountId>,
         parent_hash: &CryptoHash,
-    ) -> Vec<ShardId> {
-        (0..self.runtime_adapter.num_shards())
-            .filter(|shard_id| {
-                self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
-                    parent_hash,
-                    *shard_id,
-                    true,
-                ) && !self.runtime_adapter.cares_about_shard(
-                    me.as_ref(),
-                    parent_hash,
-                    *shard_id,
-                    true,
-                )
+    ) -> Result<Vec<ShardId>, Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
+        let will_shard_layout_change =
+            self.runtime_adapter.will_shard_layout_change_next_epoch(parent_hash)?;
+        let mut shards_to_dl = vec![];
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
+            let will_care_about_shard = Chain::will_care_about_shard_next_epoch(
+                &*self.runtime_adapter,
+                me,
+                parent_hash,
+                shard_id,
+            )?;
+            let cares_about_shard =
+                self.runtime_adapter.cares_about_shard(me.as_ref(), parent_hash, shard_id, true);
+            if will_care_about_shard && (will_shard_layout_change || !cares_about_shard) {
+                shards_to_dl.push(shard_id);
+            }
+        }
+        Ok(shards_to_dl)
+    }
+
+    /// Whether we will care in the next epoch about the shard `shard_id` of the epoch of the
+    /// block after `parent_hash`, or about one of the shards it is split into if the shard layout
+    /// changes.
+    pub fn will_care_about_shard_next_epoch(
+        runtime_adapter: &dyn RuntimeAdapter,
+        me: &Option<AccountId>,
+        parent_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<bool, Error> {
+        let next_epoch_shard_ids = if runtime_adapter
+            .will_shard_layout_change_next_epoch(parent_hash)?
+        {
+            let next_epoch_id = runtime_adapter.get_next_epoch_id_from_prev_block(parent_hash)?;
+            runtime_adapter
+                .get_shard_layout(&next_epoch_id)?
+                .get_split_shards(shard_id)
+                .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?
+        } else {
+            vec![shard_id]
+        };
+        Ok(next_epoch_shard_ids.into_iter().any(|next_epoch_shard_id| {
+            runtime_adapter.will_care_about_shard(
+                me.as_ref(),
+                parent_hash,
+                next_epoch_shard_id,
+                true,
+            )
+        }))
+    }
+
+    /// Chunk headers of `prev_block` that the chunks of the next block continue, in the order of
+    /// the shards of the next block. If the next block is the first one with a new shard layout,
+    /// each shard continues the chunk of its parent shard.
+    pub fn get_prev_chunk_headers(
+        runtime_adapter: &dyn RuntimeAdapter,
+        prev_block: &Block,
+    ) -> Result<Vec<ShardChunkHeader>, Error> {
+        let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(prev_block.hash())?;
+        let num_shards = runtime_adapter.num_shards(&epoch_id)?;
+        let prev_shard_ids =
+            runtime_adapter.get_prev_shard_ids(prev_block.hash(), (0..num_shards).collect())?;
+        prev_shard_ids
+            .into_iter()
+            .map(|shard_id| {
+                prev_block
+                    .chunks()
+                    .get(shard_id as usize)
+                    .cloned()
+                    .ok_or_else(|| ErrorKind::InvalidShardId(shard_id).into())
             })
             .collect()
     }
@@ -1306,13 +1573,56 @@ impl Chain {
         maybe_new_head
     }
 
+    /// Starts loading the state which the receipts sent in the block are going to read, when
+    /// the chunks of the next block apply them, into the trie caches of the tracked shards.
+    fn prefetch_receipts_of_block(
+        &mut self,
+        me: &Option<AccountId>,
+        block: &Block,
+    ) -> Result<(), Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(block.hash())?;
+        let mut receipts_by_shard: HashMap<ShardId, Vec<Receipt>> = HashMap::new();
+        for (from_shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            if chunk_header.height_included() != block.header().height() {
+                continue;
+            }
+            let receipts =
+                match self.store.get_outgoing_receipts(block.hash(), from_shard_id as ShardId) {
+                    Ok(receipts) => receipts.clone(),
+                    Err(_) => continue,
+                };
+            for receipt in receipts {
+                let shard_id =
+                    self.runtime_adapter.account_id_to_shard_id(&receipt.receiver_id, &epoch_id)?;
+                receipts_by_shard.entry(shard_id).or_default().push(receipt);
+            }
+        }
+        for (shard_id, receipts) in receipts_by_shard {
+            if !self.runtime_adapter.cares_about_shard(me.as_ref(), block.hash(), shard_id, true) {
+                continue;
+            }
+            let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+            // Missing when the shard layout changes with the next block
+            if let Ok(chunk_extra) = self.store.get_chunk_extra(block.hash(), &shard_uid) {
+                let state_root = *chunk_extra.state_root();
+                self.runtime_adapter.prefetch_state(shard_id, &state_root, &[], &receipts);
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_outgoing_receipts_for_shard(
         &mut self,
         prev_block_hash: CryptoHash,
         shard_id: ShardId,
         last_height_included: BlockHeight,
     ) -> Result<ReceiptResponse, Error> {
-        self.store.get_outgoing_receipts_for_shard(prev_block_hash, shard_id, last_height_included)
+        self.store.get_outgoing_receipts_for_shard(
+            &*self.runtime_adapter,
+            prev_block_hash,
+            shard_id,
+            last_height_included,
+        )
     }
 
     pub fn get_state_response_header(
@@ -1351,6 +1661,14 @@ impl Chain {
             )
             .into());
         }
+        if self.runtime_adapter.get_shard_layout(&sync_block_epoch_id)?
+            != self.runtime_adapter.get_shard_layout(sync_prev_block.header().epoch_id())?
+        {
+            return Err(ErrorKind::InvalidStateRequest(
+                "state sync across a shard layout change is not supported".into(),
+            )
+            .into());
+        }
         if shard_id as usize >= sync_prev_block.chunks().len() {
             return Err(ErrorKind::InvalidStateRequest("ShardId out of bounds".into()).into());
         }
@@ -1413,7 +1731,12 @@ impl Chain {
 
         // Getting all existing incoming_receipts from prev_chunk height to the new epoch.
         let incoming_receipts_proofs = ChainStoreUpdate::new(&mut self.store)
-            .get_incoming_receipts_for_shard(shard_id, sync_hash, prev_chunk_height_included)?;
+            .get_incoming_receipts_for_shard(
+                &*self.runtime_adapter,
+                shard_id,
+                sync_hash,
+                prev_chunk_height_included,
+            )?;
 
         // Collecting proofs for incoming receipts.
         let mut root_proofs = vec![];
@@ -1565,7 +1888,7 @@ impl Chain {
         let prev_chunk_header = shard_state_header.cloned_prev_chunk_header();
 
         // 1-2. Checking chunk validity
-        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter) {
+        if !validate_chunk_proofs(&chunk, &*self.runtime_adapter)? {
             byzantine_assert!(false);
             return Err(ErrorKind::Other(
                 "set_shard_state failed: chunk header proofs are invalid".into(),
@@ -1754,7 +2077,17 @@ impl Chain {
             .into());
         }
 
-        // Saving the part data.
+        self.save_state_part(shard_id, sync_hash, part_id, data)
+    }
+
+    /// Saves a state part which has already been checked with `validate_state_part`.
+    pub fn save_state_part(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+        data: &[u8],
+    ) -> Result<(), Error> {
         let mut store_update = self.store.owned_store().store_update();
         let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
         store_update.set(ColStateParts, &key, data);
@@ -1762,6 +2095,25 @@ impl Chain {
         Ok(())
     }
 
+    /// Returns ids of the state parts which were saved by an earlier run of state sync, e.g.
+    /// before the node restarted, so that they don't have to be downloaded again.
+    pub fn get_saved_state_part_ids(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+    ) -> Result<Vec<u64>, Error> {
+        let store = self.store.owned_store();
+        let mut part_ids = vec![];
+        for part_id in 0..num_parts {
+            let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+            if store.exists(ColStateParts, &key)? {
+                part_ids.push(part_id);
+            }
+        }
+        Ok(part_ids)
+    }
+
     pub fn set_state_finalize(
         &mut self,
         shard_id: ShardId,
@@ -1805,7 +2157,66 @@ impl Chain {
                 break;
             }
         }
-        Ok(())
+
+        // The blocks from `sync_hash` on record their deltas on top of the synced state.
+        let prev_hash = *self.get_block_header(&sync_hash)?.prev_hash();
+        let prev_height = self.get_block_header(&prev_hash)?.height();
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        self.schedule_flat_storage_creation(shard_uid, prev_height)
+    }
+
+    /// Returns the uid and the state root of shard `shard_id` before the block `sync_hash`, and
+    /// the shard layout of the next epoch that its state has to be split into.
+    pub fn get_state_to_split(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<(ShardUId, StateRoot, ShardLayout), Error> {
+        let prev_hash = *self.get_block_header(&sync_hash)?.prev_hash();
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&prev_hash)?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        let state_root = *self.get_chunk_extra(&prev_hash, &shard_uid)?.state_root();
+        let next_epoch_id = self.runtime_adapter.get_next_epoch_id_from_prev_block(&prev_hash)?;
+        let next_epoch_shard_layout = self.runtime_adapter.get_shard_layout(&next_epoch_id)?;
+        Ok((shard_uid, state_root, next_epoch_shard_layout))
+    }
+
+    /// Saves the state roots of the split states built from the state of shard `shard_id` before
+    /// the block `sync_hash`.
+    pub fn set_split_state_roots(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        state_roots: HashMap<ShardUId, StateRoot>,
+    ) -> Result<(), Error> {
+        let prev_hash = *self.get_block_header(&sync_hash)?.prev_hash();
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&prev_hash)?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        let gas_limit = self.get_chunk_extra(&prev_hash, &shard_uid)?.gas_limit();
+        let prev_height = self.get_block_header(&prev_hash)?.height();
+        for shard_uid in state_roots.keys() {
+            self.schedule_flat_storage_creation(*shard_uid, prev_height)?;
+        }
+        let mut chain_store_update = self.mut_store().store_update();
+        for (shard_uid, state_root) in state_roots {
+            // Split shards have no chunks until the shard layout changes, so their chunk extras
+            // only carry the state roots.
+            chain_store_update.save_chunk_extra(
+                &prev_hash,
+                &shard_uid,
+                ChunkExtra::new(
+                    &state_root,
+                    CryptoHash::default(),
+                    vec![],
+                    0,
+                    gas_limit,
+                    0,
+                    CongestionInfo::default(),
+                    0,
+                ),
+            );
+        }
+        chain_store_update.commit()
     }
 
     pub fn clear_downloaded_parts(
@@ -1842,7 +2253,7 @@ impl Chain {
         let prev_block = self.store.get_block(block.header().prev_hash())?.clone();
 
         let mut chain_update = self.chain_update();
-        chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::NextEpoch)?;
+        chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::CatchingUp)?;
         chain_update.commit()?;
 
         affected_blocks.insert(*block.header().hash());
@@ -1866,7 +2277,7 @@ impl Chain {
 
                 let mut chain_update = self.chain_update();
 
-                chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::NextEpoch)?;
+                chain_update.apply_chunks(me, &block, &prev_block, ApplyChunksMode::CatchingUp)?;
 
                 chain_update.commit()?;
 
@@ -1959,15 +2370,24 @@ impl Chain {
             })
             .expect("results should resolve to a final outcome");
         let receipts_outcome = outcomes.split_off(1);
-        let transaction: SignedTransactionView = self
-            .store
-            .get_transaction(transaction_hash)?
-            .ok_or_else(|| {
-                ErrorKind::DBNotFoundErr(format!("Transaction {} is not found", transaction_hash))
-            })?
-            .clone()
-            .into();
         let transaction_outcome = outcomes.pop().unwrap();
+        let transaction: SignedTransactionView =
+            match self.store.get_transaction(transaction_hash)? {
+                Some(transaction) => transaction.clone().into(),
+                None => {
+                    let error = ErrorKind::DBNotFoundErr(format!(
+                        "Transaction {} is not found",
+                        transaction_hash
+                    ))
+                    .into();
+                    let height = self.get_block_header(&transaction_outcome.block_hash)?.height();
+                    return Err(self.store.gc_error_for_height(
+                        error,
+                        GCDataCategory::Transactions,
+                        height,
+                    ));
+                }
+            };
         Ok(FinalExecutionOutcomeView { status, transaction, transaction_outcome, receipts_outcome })
     }
 
@@ -1987,12 +2407,28 @@ impl Chain {
                 if Some(outcome.id) == receipt_id_from_transaction && is_local_receipt {
                     None
                 } else {
-                    Some(self.store.get_receipt(&outcome.id).and_then(|r| {
-                        r.cloned().map(Into::into).ok_or_else(|| {
-                            ErrorKind::DBNotFoundErr(format!("Receipt {} is not found", outcome.id))
-                                .into()
-                        })
-                    }))
+                    Some(match self.store.get_receipt(&outcome.id) {
+                        Ok(Some(receipt)) => Ok(receipt.clone().into()),
+                        Ok(None) => {
+                            let error = ErrorKind::DBNotFoundErr(format!(
+                                "Receipt {} is not found",
+                                outcome.id
+                            ))
+                            .into();
+                            let height = self
+                                .store
+                                .get_block_header(&outcome.block_hash)
+                                .map(|header| header.height());
+                            height.and_then(|height| {
+                                Err(self.store.gc_error_for_height(
+                                    error,
+                                    GCDataCategory::Receipts,
+                                    height,
+                                ))
+                            })
+                        }
+                        Err(err) => Err(err),
+                    })
                 }
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -2362,9 +2798,9 @@ impl Chain {
     pub fn get_chunk_extra(
         &mut self,
         block_hash: &CryptoHash,
-        shard_id: ShardId,
+        shard_uid: &ShardUId,
     ) -> Result<&ChunkExtra, Error> {
-        self.store.get_chunk_extra(block_hash, shard_id)
+        self.store.get_chunk_extra(block_hash, shard_uid)
     }
 
     /// Get destination shard id for a given receipt id.
@@ -2520,19 +2956,28 @@ pub struct ChainUpdate<'a> {
 }
 
 struct SameHeightResult {
-    shard_id: ShardId,
+    shard_uid: ShardUId,
     gas_limit: Gas,
     apply_result: ApplyTransactionResult,
+    apply_split_result_or_state_changes: Option<ApplySplitStateResultOrStateChanges>,
 }
 
 struct DifferentHeightResult {
-    shard_id: ShardId,
+    shard_uid: ShardUId,
     apply_result: ApplyTransactionResult,
+    apply_split_result_or_state_changes: Option<ApplySplitStateResultOrStateChanges>,
+}
+
+struct SplitStateResult {
+    // parent shard of the split states
+    shard_uid: ShardUId,
+    results: Vec<ApplySplitStateResult>,
 }
 
 enum ApplyChunkResult {
     SameHeight(SameHeightResult),
     DifferentHeight(DifferentHeightResult),
+    SplitState(SplitStateResult),
 }
 
 impl<'a> ChainUpdate<'a> {
@@ -2600,14 +3045,15 @@ impl<'a> ChainUpdate<'a> {
         me: &Option<AccountId>,
         parent_hash: CryptoHash,
     ) -> Result<bool, Error> {
-        for shard_id in 0..self.runtime_adapter.num_shards() {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&parent_hash)?;
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
             if self.runtime_adapter.cares_about_shard(me.as_ref(), &parent_hash, shard_id, true)
-                || self.runtime_adapter.will_care_about_shard(
-                    me.as_ref(),
+                || Chain::will_care_about_shard_next_epoch(
+                    &*self.runtime_adapter,
+                    me,
                     &parent_hash,
                     shard_id,
-                    true,
-                )
+                )?
             {
                 return Ok(true);
             }
@@ -2729,6 +3175,7 @@ impl<'a> ChainUpdate<'a> {
             .unwrap();
         let receipt_proof_response: Vec<ReceiptProofResponse> =
             self.chain_store_update.get_incoming_receipts_for_shard(
+                &*self.runtime_adapter,
                 chunk_shard_id,
                 *prev_block.hash(),
                 prev_chunk_header.height_included(),
@@ -2762,6 +3209,7 @@ impl<'a> ChainUpdate<'a> {
                 prev_chunk_inner.validator_proposals(),
                 prev_block.header().gas_price(),
                 prev_chunk_inner.gas_limit(),
+                prev_chunk_header.congested_shards(),
                 &challenges_result,
                 *block.header().random_value(),
                 true,
@@ -2812,6 +3260,69 @@ impl<'a> ChainUpdate<'a> {
         )
     }
 
+    /// Returns the state roots of the shards that `shard_id` will be split into in the next
+    /// epoch, as of the previous block.
+    fn get_split_state_roots(
+        &mut self,
+        block: &Block,
+        shard_id: ShardId,
+    ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
+        let next_epoch_shard_layout = {
+            let next_epoch_id = self
+                .runtime_adapter
+                .get_next_epoch_id_from_prev_block(block.header().prev_hash())?;
+            self.runtime_adapter.get_shard_layout(&next_epoch_id)?
+        };
+        let split_shards = next_epoch_shard_layout
+            .get_split_shards(shard_id)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?;
+        split_shards
+            .into_iter()
+            .map(|split_shard_id| {
+                let shard_uid =
+                    ShardUId::from_shard_id_and_layout(split_shard_id, &next_epoch_shard_layout);
+                let chunk_extra = self
+                    .chain_store_update
+                    .get_chunk_extra(block.header().prev_hash(), &shard_uid)?;
+                Ok((shard_uid, *chunk_extra.state_root()))
+            })
+            .collect()
+    }
+
+    /// Computes the state changes of a parent shard for its split states, and applies them if the
+    /// split states are ready, i.e. `split_state_roots` is not `None`.
+    fn apply_split_state_changes(
+        runtime_adapter: &dyn RuntimeAdapter,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+        prev_state_root: &StateRoot,
+        apply_result: &ApplyTransactionResult,
+        next_epoch_shard_layout: &ShardLayout,
+        split_state_roots: Option<HashMap<ShardUId, StateRoot>>,
+    ) -> Result<ApplySplitStateResultOrStateChanges, Error> {
+        let state_changes = StateChangesForSplitStates::from_raw_state_changes(
+            &runtime_adapter.get_trie_for_shard(shard_id),
+            prev_state_root,
+            apply_result.trie_changes.state_changes().to_vec(),
+        )
+        .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        match split_state_roots {
+            Some(split_state_roots) => {
+                Ok(ApplySplitStateResultOrStateChanges::ApplySplitStateResults(
+                    runtime_adapter.apply_update_to_split_states(
+                        block_hash,
+                        split_state_roots,
+                        next_epoch_shard_layout,
+                        state_changes,
+                    )?,
+                ))
+            }
+            None => {
+                Ok(ApplySplitStateResultOrStateChanges::StateChangesForSplitStates(state_changes))
+            }
+        }
+    }
+
     /// Creates jobs that would apply chunks
     fn apply_chunks_preprocessing(
         &mut self,
@@ -2832,38 +3343,63 @@ impl<'a> ChainUpdate<'a> {
         self.chain_store_update.save_block_extra(&block.hash(), BlockExtra { challenges_result });
         let protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(block.header().epoch_id())?;
+        let shard_layout = self.runtime_adapter.get_shard_layout(block.header().epoch_id())?;
+        let will_shard_layout_change =
+            self.runtime_adapter.will_shard_layout_change_next_epoch(block.header().prev_hash())?;
+        let next_epoch_shard_layout = if will_shard_layout_change {
+            let next_epoch_id = self
+                .runtime_adapter
+                .get_next_epoch_id_from_prev_block(block.header().prev_hash())?;
+            self.runtime_adapter.get_shard_layout(&next_epoch_id)?
+        } else {
+            shard_layout.clone()
+        };
+        let prev_chunk_headers = Chain::get_prev_chunk_headers(&*self.runtime_adapter, prev_block)?;
 
         for (shard_id, (chunk_header, prev_chunk_header)) in
-            (block.chunks().iter().zip(prev_block.chunks().iter())).enumerate()
+            (block.chunks().iter().zip(prev_chunk_headers.iter())).enumerate()
         {
             let shard_id = shard_id as ShardId;
-            let care_about_shard = match mode {
-                ApplyChunksMode::ThisEpoch => self.runtime_adapter.cares_about_shard(
-                    me.as_ref(),
-                    &block.header().prev_hash(),
-                    shard_id,
-                    true,
-                ),
-                ApplyChunksMode::NextEpoch => {
-                    self.runtime_adapter.will_care_about_shard(
-                        me.as_ref(),
-                        &block.header().prev_hash(),
-                        shard_id,
-                        true,
-                    ) && !self.runtime_adapter.cares_about_shard(
-                        me.as_ref(),
-                        &block.header().prev_hash(),
-                        shard_id,
-                        true,
-                    )
+            let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+            let cares_about_shard_this_epoch = self.runtime_adapter.cares_about_shard(
+                me.as_ref(),
+                &block.header().prev_hash(),
+                shard_id,
+                true,
+            );
+            let cares_about_shard_next_epoch = Chain::will_care_about_shard_next_epoch(
+                &*self.runtime_adapter,
+                me,
+                &block.header().prev_hash(),
+                shard_id,
+            )?;
+            // Transactions of a shard must only be applied once, even though the chunks of a block
+            // may be applied twice: first with `NotCaughtUp`, then with `CatchingUp`.
+            let should_apply_transactions = match mode {
+                ApplyChunksMode::IsCaughtUp => {
+                    cares_about_shard_this_epoch || cares_about_shard_next_epoch
+                }
+                ApplyChunksMode::CatchingUp => {
+                    !cares_about_shard_this_epoch && cares_about_shard_next_epoch
                 }
+                ApplyChunksMode::NotCaughtUp => cares_about_shard_this_epoch,
+            };
+            let need_to_split_states = will_shard_layout_change && cares_about_shard_next_epoch;
+            // The split states exist only once the states of the next epoch are caught up. When
+            // they don't, the state changes are saved and applied to the split states while
+            // catching up.
+            let split_state_roots = if need_to_split_states && mode != ApplyChunksMode::NotCaughtUp
+            {
+                Some(self.get_split_state_roots(block, shard_id)?)
+            } else {
+                None
             };
-            if care_about_shard {
+            if should_apply_transactions {
                 if chunk_header.height_included() == block.header().height() {
                     // Validate state root.
                     let prev_chunk_extra = self
                         .chain_store_update
-                        .get_chunk_extra(&block.header().prev_hash(), shard_id)?
+                        .get_chunk_extra(&block.header().prev_hash(), &shard_uid)?
                         .clone();
 
                     // Validate that all next chunk information matches previous chunk extra.
@@ -2890,6 +3426,7 @@ impl<'a> ChainUpdate<'a> {
                     })?;
                     let receipt_proof_response: Vec<ReceiptProofResponse> =
                         self.chain_store_update.get_incoming_receipts_for_shard(
+                            &*self.runtime_adapter,
                             shard_id,
                             *block.hash(),
                             prev_chunk_header.height_included(),
@@ -2927,6 +3464,7 @@ impl<'a> ChainUpdate<'a> {
                         }
                     };
 
+                    let congested_shards = chunk_header.congested_shards().to_vec();
                     let chunk_inner = chunk.cloned_header().take_inner();
                     let gas_limit = chunk_inner.gas_limit();
 
@@ -2950,6 +3488,7 @@ impl<'a> ChainUpdate<'a> {
                     let random_seed = *block.header().random_value();
                     let height = chunk_header.height_included();
                     let prev_block_hash = chunk_header.prev_block_hash().clone();
+                    let next_epoch_shard_layout = next_epoch_shard_layout.clone();
                     #[cfg(feature = "sandbox")]
                     let states_to_patch = self.states_to_patch.take();
 
@@ -2966,6 +3505,7 @@ impl<'a> ChainUpdate<'a> {
                             chunk_inner.validator_proposals(),
                             gas_price,
                             gas_limit,
+                            &congested_shards,
                             &challenges_result,
                             random_seed,
                             true,
@@ -2976,10 +3516,24 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
+                                let apply_split_result_or_state_changes = if need_to_split_states {
+                                    Some(ChainUpdate::apply_split_state_changes(
+                                        &*runtime_adapter,
+                                        &block_hash,
+                                        shard_id,
+                                        chunk_inner.prev_state_root(),
+                                        &apply_result,
+                                        &next_epoch_shard_layout,
+                                        split_state_roots,
+                                    )?)
+                                } else {
+                                    None
+                                };
                                 Ok(ApplyChunkResult::SameHeight(SameHeightResult {
-                                    shard_id,
+                                    shard_uid,
                                     gas_limit,
                                     apply_result,
+                                    apply_split_result_or_state_changes,
                                 }))
                             }
                             Err(err) => Err(ErrorKind::Other(err.to_string()).into()),
@@ -2988,7 +3542,7 @@ impl<'a> ChainUpdate<'a> {
                 } else {
                     let new_extra = self
                         .chain_store_update
-                        .get_chunk_extra(&prev_block.hash(), shard_id)?
+                        .get_chunk_extra(&prev_block.hash(), &shard_uid)?
                         .clone();
 
                     let runtime_adapter = self.runtime_adapter.clone();
@@ -2999,6 +3553,7 @@ impl<'a> ChainUpdate<'a> {
                     let random_seed = *block.header().random_value();
                     let height = block.header().height();
                     let prev_block_hash = prev_block.hash().clone();
+                    let next_epoch_shard_layout = next_epoch_shard_layout.clone();
                     #[cfg(feature = "sandbox")]
                     let states_to_patch = self.states_to_patch.take();
                     #[cfg(not(feature = "sandbox"))]
@@ -3017,6 +3572,7 @@ impl<'a> ChainUpdate<'a> {
                             new_extra.validator_proposals(),
                             gas_price,
                             new_extra.gas_limit(),
+                            &[],
                             &challenges_result,
                             random_seed,
                             false,
@@ -3027,21 +3583,94 @@ impl<'a> ChainUpdate<'a> {
                             None,
                         ) {
                             Ok(apply_result) => {
+                                let apply_split_result_or_state_changes = if need_to_split_states {
+                                    Some(ChainUpdate::apply_split_state_changes(
+                                        &*runtime_adapter,
+                                        &block_hash,
+                                        shard_id,
+                                        new_extra.state_root(),
+                                        &apply_result,
+                                        &next_epoch_shard_layout,
+                                        split_state_roots,
+                                    )?)
+                                } else {
+                                    None
+                                };
                                 Ok(ApplyChunkResult::DifferentHeight(DifferentHeightResult {
-                                    shard_id,
+                                    shard_uid,
                                     apply_result,
+                                    apply_split_result_or_state_changes,
                                 }))
                             }
                             Err(err) => Err(ErrorKind::Other(err.to_string()).into()),
                         }
                     }));
                 }
+            } else if let Some(split_state_roots) = split_state_roots {
+                // The transactions of the shard were applied before catching up started, so its
+                // state changes for the split states were saved back then.
+                assert!(mode == ApplyChunksMode::CatchingUp && cares_about_shard_this_epoch);
+                let state_changes = self
+                    .chain_store_update
+                    .get_state_changes_for_split_states(block.hash(), &shard_uid)?;
+                let runtime_adapter = self.runtime_adapter.clone();
+                let block_hash = block.hash().clone();
+                let next_epoch_shard_layout = next_epoch_shard_layout.clone();
+                result.push(Box::new(move || -> Result<ApplyChunkResult, Error> {
+                    let results = runtime_adapter.apply_update_to_split_states(
+                        &block_hash,
+                        split_state_roots,
+                        &next_epoch_shard_layout,
+                        state_changes,
+                    )?;
+                    Ok(ApplyChunkResult::SplitState(SplitStateResult { shard_uid, results }))
+                }));
             }
         }
 
         Ok(result)
     }
 
+    /// Saves the results of updating the split states, or the state changes to update them later.
+    fn process_split_state(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_uid: &ShardUId,
+        gas_limit: Gas,
+        apply_split_result_or_state_changes: ApplySplitStateResultOrStateChanges,
+    ) {
+        match apply_split_result_or_state_changes {
+            ApplySplitStateResultOrStateChanges::ApplySplitStateResults(results) => {
+                // Split shards have no chunks until the shard layout changes, so their chunk
+                // extras only carry the state roots.
+                for result in results {
+                    self.chain_store_update.save_chunk_extra(
+                        block_hash,
+                        &result.shard_uid,
+                        ChunkExtra::new(
+                            &result.new_root,
+                            CryptoHash::default(),
+                            vec![],
+                            0,
+                            gas_limit,
+                            0,
+                            CongestionInfo::default(),
+                            0,
+                        ),
+                    );
+                    self.chain_store_update.save_trie_changes(result.trie_changes);
+                }
+            }
+            ApplySplitStateResultOrStateChanges::StateChangesForSplitStates(state_changes) => {
+                self.chain_store_update.add_state_changes_for_split_states(
+                    *block_hash,
+                    *shard_uid,
+                    state_changes,
+                );
+            }
+        }
+    }
+
     /// Processed results of applying chunk
     fn process_apply_chunk_result(
         &mut self,
@@ -3051,18 +3680,21 @@ impl<'a> ChainUpdate<'a> {
     ) -> Result<(), Error> {
         match result {
             ApplyChunkResult::SameHeight(SameHeightResult {
-                shard_id,
+                shard_uid,
                 gas_limit,
                 apply_result,
+                apply_split_result_or_state_changes,
             }) => {
+                let shard_id = shard_uid.shard_id();
                 let (outcome_root, outcome_paths) =
                     ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
+                set_congestion_metrics(shard_id, &apply_result.congestion_info);
 
                 self.chain_store_update.save_trie_changes(apply_result.trie_changes);
                 // Save state root after applying transactions.
                 self.chain_store_update.save_chunk_extra(
                     &block_hash,
-                    shard_id,
+                    &shard_uid,
                     ChunkExtra::new(
                         &apply_result.new_root,
                         outcome_root,
@@ -3070,6 +3702,8 @@ impl<'a> ChainUpdate<'a> {
                         apply_result.total_gas_burnt,
                         gas_limit,
                         apply_result.total_balance_burnt,
+                        apply_result.congestion_info,
+                        apply_result.total_priority_fees,
                     ),
                 );
                 self.chain_store_update.save_outgoing_receipt(
@@ -3084,15 +3718,50 @@ impl<'a> ChainUpdate<'a> {
                     apply_result.outcomes,
                     outcome_paths,
                 );
+                if let Some(apply_split_result_or_state_changes) =
+                    apply_split_result_or_state_changes
+                {
+                    self.process_split_state(
+                        &block_hash,
+                        &shard_uid,
+                        gas_limit,
+                        apply_split_result_or_state_changes,
+                    );
+                }
             }
-            ApplyChunkResult::DifferentHeight(DifferentHeightResult { shard_id, apply_result }) => {
+            ApplyChunkResult::DifferentHeight(DifferentHeightResult {
+                shard_uid,
+                apply_result,
+                apply_split_result_or_state_changes,
+            }) => {
                 let mut new_extra =
-                    self.chain_store_update.get_chunk_extra(&prev_block_hash, shard_id)?.clone();
+                    self.chain_store_update.get_chunk_extra(&prev_block_hash, &shard_uid)?.clone();
 
                 self.chain_store_update.save_trie_changes(apply_result.trie_changes);
                 *new_extra.state_root_mut() = apply_result.new_root;
 
-                self.chain_store_update.save_chunk_extra(&block_hash, shard_id, new_extra);
+                let gas_limit = new_extra.gas_limit();
+                self.chain_store_update.save_chunk_extra(&block_hash, &shard_uid, new_extra);
+                if let Some(apply_split_result_or_state_changes) =
+                    apply_split_result_or_state_changes
+                {
+                    self.process_split_state(
+                        &block_hash,
+                        &shard_uid,
+                        gas_limit,
+                        apply_split_result_or_state_changes,
+                    );
+                }
+            }
+            ApplyChunkResult::SplitState(SplitStateResult { shard_uid, results }) => {
+                let gas_limit =
+                    self.chain_store_update.get_chunk_extra(&block_hash, &shard_uid)?.gas_limit();
+                self.process_split_state(
+                    &block_hash,
+                    &shard_uid,
+                    gas_limit,
+                    ApplySplitStateResultOrStateChanges::ApplySplitStateResults(results),
+                );
             }
         };
         Ok(())
@@ -3113,7 +3782,9 @@ impl<'a> ChainUpdate<'a> {
     {
         debug!(target: "chain", "Process block {} at {}, approvals: {}, me: {:?}", block.hash(), block.header().height(), block.header().num_approvals(), me);
 
-        if block.chunks().len() != self.runtime_adapter.num_shards() as usize {
+        if block.chunks().len()
+            != self.runtime_adapter.num_shards(block.header().epoch_id())? as usize
+        {
             return Err(ErrorKind::IncorrectNumberOfChunkHeaders.into());
         }
 
@@ -3222,14 +3893,23 @@ impl<'a> ChainUpdate<'a> {
             return Err(ErrorKind::InvalidGasPrice.into());
         }
 
+        if block.header().priority_fees()
+            != Block::compute_priority_fees(block.chunks().iter(), block.header().height())
+        {
+            byzantine_assert!(false);
+            return Err(ErrorKind::Other("Invalid priority_fees".to_string()).into());
+        }
+
         let prev_block = self.chain_store_update.get_block(&prev_hash)?.clone();
 
         self.ping_missing_chunks(me, prev_hash, &block)?;
         self.save_incoming_receipts_from_block(me, &block)?;
 
         // Do basic validation of chunks before applying the transactions
+        let prev_chunk_headers =
+            Chain::get_prev_chunk_headers(&*self.runtime_adapter, &prev_block)?;
         for (chunk_header, prev_chunk_header) in
-            block.chunks().iter().zip(prev_block.chunks().iter())
+            block.chunks().iter().zip(prev_chunk_headers.iter())
         {
             if chunk_header.height_included() == block.header().height() {
                 if &chunk_header.prev_block_hash() != block.header().prev_hash() {
@@ -3242,30 +3922,16 @@ impl<'a> ChainUpdate<'a> {
             }
         }
 
-        let mut apply_chunk_work = Vec::new();
-
-        // Always apply state transition for shards in the current epoch
-        apply_chunk_work.extend(self.apply_chunks_preprocessing(
-            me,
-            block,
-            &prev_block,
-            ApplyChunksMode::ThisEpoch,
-        )?);
-
         // If we have the state for the next epoch already downloaded, apply the state transition for the next epoch as well,
         //    otherwise put the block into the permanent storage to have the state transition applied later
-        if is_caught_up {
-            apply_chunk_work.extend(self.apply_chunks_preprocessing(
-                me,
-                block,
-                &prev_block,
-                ApplyChunksMode::NextEpoch,
-            )?);
+        let apply_chunks_mode = if is_caught_up {
+            ApplyChunksMode::IsCaughtUp
         } else {
             self.chain_store_update.add_block_to_catchup(prev_hash, *block.hash());
-        }
+            ApplyChunksMode::NotCaughtUp
+        };
 
-        self.do_apply_chunks(block, &prev_block, apply_chunk_work)?;
+        self.apply_chunks(me, block, &prev_block, apply_chunks_mode)?;
 
         // Verify that proposals from chunks match block header proposals.
         let block_height = block.header().height();
@@ -3460,7 +4126,7 @@ impl<'a> ChainUpdate<'a> {
             }
         }
 
-        if header.chunk_mask().len() as u64 != self.runtime_adapter.num_shards() {
+        if header.chunk_mask().len() as u64 != self.runtime_adapter.num_shards(header.epoch_id())? {
             return Err(ErrorKind::InvalidChunkMask.into());
         }
 
@@ -3797,6 +4463,7 @@ impl<'a> ChainUpdate<'a> {
             chunk_header.validator_proposals(),
             gas_price,
             gas_limit,
+            chunk_header.congested_shards(),
             &block_header.challenges_result(),
             *block_header.random_value(),
             true,
@@ -3817,8 +4484,11 @@ impl<'a> ChainUpdate<'a> {
             apply_result.total_gas_burnt,
             gas_limit,
             apply_result.total_balance_burnt,
+            apply_result.congestion_info,
+            apply_result.total_priority_fees,
         );
-        self.chain_store_update.save_chunk_extra(block_header.hash(), shard_id, chunk_extra);
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, block_header.epoch_id())?;
+        self.chain_store_update.save_chunk_extra(block_header.hash(), &shard_uid, chunk_extra);
 
         self.chain_store_update.save_outgoing_receipt(
             &block_header.hash(),
@@ -3863,8 +4533,9 @@ impl<'a> ChainUpdate<'a> {
         let prev_block_header =
             self.chain_store_update.get_block_header(&block_header.prev_hash())?.clone();
 
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, block_header.epoch_id())?;
         let mut chunk_extra =
-            self.chain_store_update.get_chunk_extra(&prev_block_header.hash(), shard_id)?.clone();
+            self.chain_store_update.get_chunk_extra(&prev_block_header.hash(), &shard_uid)?.clone();
 
         let apply_result = self.runtime_adapter.apply_transactions(
             shard_id,
@@ -3878,6 +4549,7 @@ impl<'a> ChainUpdate<'a> {
             chunk_extra.validator_proposals(),
             prev_block_header.gas_price(),
             chunk_extra.gas_limit(),
+            &[],
             &block_header.challenges_result(),
             *block_header.random_value(),
             false,
@@ -3888,7 +4560,7 @@ impl<'a> ChainUpdate<'a> {
         self.chain_store_update.save_trie_changes(apply_result.trie_changes);
         *chunk_extra.state_root_mut() = apply_result.new_root;
 
-        self.chain_store_update.save_chunk_extra(&block_header.hash(), shard_id, chunk_extra);
+        self.chain_store_update.save_chunk_extra(&block_header.hash(), &shard_uid, chunk_extra);
         Ok(true)
     }
 
@@ -3960,3 +4632,14 @@ pub fn collect_receipts_from_response(
         receipt_proof_response.iter().flat_map(|ReceiptProofResponse(_, proofs)| proofs),
     )
 }
+
+/// Exports the size of the delayed receipts queue of the shard after the applied chunk.
+fn set_congestion_metrics(shard_id: ShardId, congestion_info: &CongestionInfo) {
+    let shard_id = shard_id.to_string();
+    if let Ok(gauge) = &metrics::DELAYED_RECEIPTS_COUNT {
+        gauge.with_label_values(&[&shard_id]).set(congestion_info.delayed_receipts_count as i64);
+    }
+    if let Ok(gauge) = &metrics::DELAYED_RECEIPTS_GAS {
+        gauge.with_label_values(&[&shard_id]).set(congestion_info.delayed_receipts_gas as i64);
+    }
+}
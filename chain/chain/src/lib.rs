@@ -6,8 +6,11 @@ pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThreshold
 pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
 pub use near_chain_primitives::{self, Error, ErrorKind};
 pub use near_primitives::receipt::ReceiptResult;
-pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
-pub use store_validator::{ErrorMessage, StoreValidator};
+pub use store::{
+    ChainStore, ChainStoreAccess, ChainStoreUpdate, GCDataCategory,
+    MAX_STATE_CHANGES_HISTORY_HEIGHTS,
+};
+pub use store_validator::{ErrorMessage, StoreRepair, StoreValidator};
 pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance, RuntimeAdapter};
 
 pub mod chain;
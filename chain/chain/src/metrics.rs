@@ -1,6 +1,6 @@
 use near_metrics::{
-    try_create_histogram, try_create_int_counter, try_create_int_gauge, Histogram, IntCounter,
-    IntGauge,
+    try_create_histogram, try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge,
+    try_create_int_gauge_vec, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 
 lazy_static! {
@@ -25,4 +25,41 @@ lazy_static! {
         "near_validator_active_total",
         "The total number of validators active after last block"
     );
+    pub static ref STORE_VALIDATOR_CHECKS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_store_validator_checks_total",
+            "Total number of conditions checked by the background store validator"
+        );
+    pub static ref STORE_VALIDATOR_ERRORS_TOTAL: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_store_validator_errors_total",
+            "Total number of inconsistencies found by the background store validator",
+            &["col"]
+        );
+    pub static ref STORE_VALIDATOR_LAST_PASS_ERRORS: near_metrics::Result<IntGauge> =
+        try_create_int_gauge(
+            "near_store_validator_last_pass_errors",
+            "Number of inconsistencies found by the last complete pass of the background store validator"
+        );
+    pub static ref STORE_VALIDATOR_PASSES_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_store_validator_passes_total",
+            "Total number of complete passes of the background store validator"
+        );
+    pub static ref STORE_VALIDATOR_COLUMN: near_metrics::Result<IntGauge> = try_create_int_gauge(
+        "near_store_validator_column",
+        "Index of the column the background store validator is checking"
+    );
+    pub static ref DELAYED_RECEIPTS_COUNT: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_delayed_receipts_count",
+            "Number of receipts in the delayed receipts queue of the shard after the last applied chunk",
+            &["shard_id"]
+        );
+    pub static ref DELAYED_RECEIPTS_GAS: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_delayed_receipts_gas",
+            "Gas attached to the receipts in the delayed receipts queue of the shard after the last applied chunk",
+            &["shard_id"]
+        );
 }
@@ -1,6 +1,6 @@
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
 use std::io;
 use std::sync::Arc;
 
@@ -8,12 +8,15 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use cached::{Cached, SizedCache};
 use chrono::Utc;
 
+use near_chain_configs::GCRetentionConfig;
 use near_chain_primitives::error::{Error, ErrorKind};
+use near_primitives::account::Account;
 use near_primitives::block::{Approval, Tip};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::receipt::{Receipt, ReceiptResult};
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardUId};
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, ReceiptProof, ShardChunk, ShardChunkHeader,
     StateSyncInfo,
@@ -28,28 +31,34 @@ use near_primitives::transaction::{
 use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{
-    AccountId, BlockExtra, BlockHeight, EpochId, GCCount, NumBlocks, ShardId, StateChanges,
-    StateChangesExt, StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
+    AccountId, BlockExtra, BlockHeight, BlockHeightDelta, EpochId, GCCount, NumBlocks,
+    RawStateChange, RawStateChangesWithTrieKey, ShardId, StateChanges, StateChangesExt,
+    StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
+};
+use near_primitives::utils::{
+    get_block_shard_id, get_block_shard_id_rev, get_block_shard_uid, index_to_bytes, to_timestamp,
 };
-use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
 use near_primitives::views::LightClientBlockView;
 use near_store::{
-    read_with_cache, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight, ColBlockInfo,
-    ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight, ColBlockRefCount,
-    ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra, ColChunkHashesByHeight,
-    ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount,
-    ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks, ColLastBlockWithNewChunk,
-    ColNextBlockHashes, ColNextBlockWithNewChunk, ColOutcomeIds, ColOutgoingReceipts,
-    ColPartialChunks, ColProcessedBlockHeights, ColReceiptIdToShardId, ColReceipts, ColState,
-    ColStateChanges, ColStateDlInfos, ColStateHeaders, ColStateParts, ColTransactionResult,
-    ColTransactions, ColTrieChanges, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate,
-    TrieChanges, WrappedTrieChanges, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
+    read_with_cache, ColAccountHistory, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight,
+    ColBlockInfo, ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight,
+    ColBlockRefCount, ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra,
+    ColChunkHashesByHeight, ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks,
+    ColGCCount, ColGCRetainedData, ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks,
+    ColLastBlockWithNewChunk, ColNextBlockHashes, ColNextBlockWithNewChunk, ColOutcomeIds,
+    ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights, ColReceiptIdToShardId,
+    ColReceipts, ColState, ColStateChanges, ColStateChangesByKey, ColStateChangesForSplitStates,
+    ColStateDlInfos, ColStateHeaders, ColStateParts, ColTransactionResult, ColTransactions,
+    ColTrieChanges, DBCol, KeyForStateChanges, KeyForStateChangesByKey, ShardTries,
+    StateChangesForSplitStates, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
+    ACCOUNT_HISTORY_START_KEY, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, GC_RETAINED_TAIL_KEY,
     HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC,
     TAIL_KEY,
 };
 
 use crate::byzantine_assert;
-use crate::types::{Block, BlockHeader, LatestKnown};
+use crate::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA;
+use crate::types::{Block, BlockHeader, LatestKnown, RuntimeAdapter};
 
 /// lru cache size
 #[cfg(not(feature = "no_cache"))]
@@ -62,6 +71,9 @@ const CACHE_SIZE: usize = 1;
 #[cfg(feature = "no_cache")]
 const CHUNK_CACHE_SIZE: usize = 1;
 
+/// Maximum number of heights whose state changes are returned by one history request.
+pub const MAX_STATE_CHANGES_HISTORY_HEIGHTS: BlockHeightDelta = 1000;
+
 #[derive(Clone)]
 pub enum GCMode {
     Fork(ShardTries),
@@ -69,6 +81,97 @@ pub enum GCMode {
     StateSync { clear_block_info: bool },
 }
 
+/// Categories of data with separate GC retention. Transactions, receipts and outcomes may be kept
+/// longer than the blocks they belong to: instead of deleting them with a block, GC stores their
+/// keys in `ColGCRetainedData` by height and deletes them once they leave the retention window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GCDataCategory {
+    Blocks = 0,
+    Transactions = 1,
+    Receipts = 2,
+    Outcomes = 3,
+}
+
+impl GCDataCategory {
+    /// Categories which can be kept longer than blocks.
+    pub const RETAINABLE: [GCDataCategory; 3] =
+        [GCDataCategory::Transactions, GCDataCategory::Receipts, GCDataCategory::Outcomes];
+
+    /// Number of epochs to keep the data of the category for.
+    pub fn num_epochs_to_keep(&self, gc_retention: &GCRetentionConfig) -> u64 {
+        let num_epochs = match self {
+            GCDataCategory::Blocks => None,
+            GCDataCategory::Transactions => gc_retention.transactions,
+            GCDataCategory::Receipts => gc_retention.receipts,
+            GCDataCategory::Outcomes => gc_retention.outcomes,
+        };
+        std::cmp::max(num_epochs.unwrap_or_default(), NUM_EPOCHS_TO_KEEP_STORE_DATA)
+    }
+
+    /// Categories which are kept longer than blocks with the given config.
+    pub fn retained(gc_retention: &GCRetentionConfig) -> Vec<GCDataCategory> {
+        Self::RETAINABLE
+            .iter()
+            .filter(|category| {
+                category.num_epochs_to_keep(gc_retention) > NUM_EPOCHS_TO_KEEP_STORE_DATA
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn parse_retained_data_key(key: &[u8]) -> Option<(GCDataCategory, BlockHeight)> {
+        if key.len() != 9 {
+            return None;
+        }
+        let category = match key[0] {
+            1 => GCDataCategory::Transactions,
+            2 => GCDataCategory::Receipts,
+            3 => GCDataCategory::Outcomes,
+            _ => return None,
+        };
+        let mut height = [0u8; 8];
+        height.copy_from_slice(&key[1..]);
+        Some((category, BlockHeight::from_be_bytes(height)))
+    }
+
+    pub(crate) fn retained_tail_key(&self) -> Vec<u8> {
+        let mut key = GC_RETAINED_TAIL_KEY.to_vec();
+        key.push(*self as u8);
+        key
+    }
+
+    fn retained_data_key(&self, height: BlockHeight) -> Vec<u8> {
+        let mut key = Vec::with_capacity(9);
+        key.push(*self as u8);
+        key.extend_from_slice(&height.to_be_bytes());
+        key
+    }
+}
+
+impl std::fmt::Display for GCDataCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GCDataCategory::Blocks => "blocks",
+            GCDataCategory::Transactions => "transactions",
+            GCDataCategory::Receipts => "receipts",
+            GCDataCategory::Outcomes => "execution outcomes",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Smallest key greater than all the keys starting with `prefix`, `None` if there is none.
+fn next_key_prefix(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut key = prefix.to_vec();
+    while let Some(last) = key.pop() {
+        if last < u8::MAX {
+            key.push(last + 1);
+            return Some(key);
+        }
+    }
+    None
+}
+
 fn get_height_shard_id(height: BlockHeight, shard_id: ShardId) -> Vec<u8> {
     let mut res = Vec::with_capacity(40);
     res.extend_from_slice(&height.to_le_bytes());
@@ -133,11 +236,11 @@ pub trait ChainStoreAccess {
     fn get_previous_header(&mut self, header: &BlockHeader) -> Result<&BlockHeader, Error>;
     /// GEt block extra for given block.
     fn get_block_extra(&mut self, block_hash: &CryptoHash) -> Result<&BlockExtra, Error>;
-    /// Get chunk extra info for given block hash + shard id.
+    /// Get chunk extra info for given block hash + shard uid.
     fn get_chunk_extra(
         &mut self,
         block_hash: &CryptoHash,
-        shard_id: ShardId,
+        shard_uid: &ShardUId,
     ) -> Result<&ChunkExtra, Error>;
     /// Get block header.
     fn get_block_header(&mut self, h: &CryptoHash) -> Result<&BlockHeader, Error>;
@@ -289,7 +392,9 @@ pub trait ChainStoreAccess {
         let mut candidate_hash = *hash;
         loop {
             let block_header = self.get_block_header(&candidate_hash)?;
-            if block_header.chunk_mask()[shard_id as usize] {
+            // Blocks with another shard layout may have fewer shards. Such blocks belong to an
+            // earlier epoch, which is all the callers need to know.
+            if block_header.chunk_mask().get(shard_id as usize).copied().unwrap_or(true) {
                 break Ok(block_header.epoch_id().clone());
             }
             candidate_hash = *block_header.prev_hash();
@@ -359,6 +464,36 @@ pub struct ChainStore {
     block_ordinal_to_hash: SizedCache<Vec<u8>, CryptoHash>,
     /// Processed block heights.
     processed_block_heights: SizedCache<Vec<u8>, ()>,
+    /// Whether to keep the accounts changed by each block in `ColAccountHistory`.
+    save_account_history: bool,
+}
+
+fn get_account_history_prefix(account_id: &AccountId) -> Vec<u8> {
+    let mut key = account_id.as_ref().as_bytes().to_vec();
+    key.push(b',');
+    key
+}
+
+fn get_account_history_key(
+    account_id: &AccountId,
+    height: BlockHeight,
+    block_hash: &CryptoHash,
+) -> Vec<u8> {
+    let mut key = get_account_history_prefix(account_id);
+    key.extend(&(!height).to_be_bytes());
+    key.extend(block_hash.as_ref());
+    key
+}
+
+/// Parses the height and the block hash following the account id in a `ColAccountHistory` key.
+fn parse_account_history_key(suffix: &[u8]) -> Result<(BlockHeight, CryptoHash), Error> {
+    if suffix.len() != std::mem::size_of::<BlockHeight>() + std::mem::size_of::<CryptoHash>() {
+        return Err(ErrorKind::Other("Invalid account history key".to_string()).into());
+    }
+    let (height, block_hash) = suffix.split_at(std::mem::size_of::<BlockHeight>());
+    let height = !BlockHeight::from_be_bytes(height.try_into().unwrap());
+    let block_hash = CryptoHash::try_from(block_hash).unwrap();
+    Ok((height, block_hash))
 }
 
 pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> Result<T, Error> {
@@ -402,6 +537,7 @@ impl ChainStore {
             block_merkle_tree: SizedCache::with_size(CACHE_SIZE),
             block_ordinal_to_hash: SizedCache::with_size(CACHE_SIZE),
             processed_block_heights: SizedCache::with_size(CACHE_SIZE),
+            save_account_history: false,
         }
     }
 
@@ -427,17 +563,37 @@ impl ChainStore {
 
     pub fn get_outgoing_receipts_for_shard(
         &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
         prev_block_hash: CryptoHash,
         shard_id: ShardId,
         last_included_height: BlockHeight,
     ) -> Result<ReceiptResponse, Error> {
+        let shard_layout = runtime_adapter.get_shard_layout_from_prev_block(&prev_block_hash)?;
         let mut receipts_block_hash = prev_block_hash;
         loop {
             let block_header = self.get_block_header(&receipts_block_hash)?;
 
             if block_header.height() == last_included_height {
+                let receipts_shard_layout =
+                    runtime_adapter.get_shard_layout(block_header.epoch_id())?;
+                // If the last chunk was included with the previous shard layout, the receipts
+                // were sent by the parent shard. All of them are attributed to its first split shard.
+                let receipts_shard_id = if receipts_shard_layout != shard_layout {
+                    let parent_shard_id = shard_layout
+                        .get_parent_shard_id(shard_id)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?;
+                    let split_shards = shard_layout
+                        .get_split_shards(parent_shard_id)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(parent_shard_id)))?;
+                    if split_shards.first() != Some(&shard_id) {
+                        return Ok(ReceiptResponse(receipts_block_hash, vec![]));
+                    }
+                    parent_shard_id
+                } else {
+                    shard_id
+                };
                 let receipts = if let Ok(cur_receipts) =
-                    self.get_outgoing_receipts(&receipts_block_hash, shard_id)
+                    self.get_outgoing_receipts(&receipts_block_hash, receipts_shard_id)
                 {
                     cur_receipts.clone()
                 } else {
@@ -513,6 +669,45 @@ impl ChainStore {
 }
 
 impl ChainStore {
+    /// Lowest height of the data kept by GC longer than blocks for the category, if any was kept.
+    pub fn gc_retained_tail(&self, category: GCDataCategory) -> Result<Option<BlockHeight>, Error> {
+        Ok(self.store.get_ser(ColBlockMisc, &category.retained_tail_key())?)
+    }
+
+    /// Lowest height from which the data of the category is kept.
+    pub fn gc_tail(&self, category: GCDataCategory) -> Result<BlockHeight, Error> {
+        let tail = match category {
+            GCDataCategory::Blocks | GCDataCategory::Outcomes => self.tail()?,
+            GCDataCategory::Transactions | GCDataCategory::Receipts => self.chunk_tail()?,
+        };
+        Ok(match self.gc_retained_tail(category)? {
+            Some(retained_tail) => std::cmp::min(retained_tail, tail),
+            None => tail,
+        })
+    }
+
+    /// Turns a not found error for the data of the category at the given height into
+    /// a `GarbageCollected` error if the height is below the tail of the category.
+    pub fn gc_error_for_height(
+        &self,
+        error: Error,
+        category: GCDataCategory,
+        height: BlockHeight,
+    ) -> Error {
+        if let ErrorKind::DBNotFoundErr(_) = error.kind() {
+            if let Ok(tail) = self.gc_tail(category) {
+                if height < tail {
+                    return ErrorKind::GarbageCollected(format!(
+                        "{} at height {} are garbage collected, the node keeps {} from height {}",
+                        category, height, category, tail
+                    ))
+                    .into();
+                }
+            }
+        }
+        error
+    }
+
     /// Returns all outcomes generated by applying transaction or receipt with the given id.
     pub fn get_outcomes_by_id(
         &self,
@@ -627,6 +822,20 @@ impl ChainStore {
         Ok(StateChangesKinds::from_changes(&mut block_changes)?)
     }
 
+    /// Retrieve the state changes of a parent shard saved while the states of its split shards
+    /// were not yet built.
+    pub fn get_state_changes_for_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        shard_uid: &ShardUId,
+    ) -> Result<StateChangesForSplitStates, Error> {
+        let key = get_block_shard_uid(block_hash, shard_uid);
+        option_to_not_found(
+            self.store.get_ser(ColStateChangesForSplitStates, &key),
+            &format!("STATE CHANGES FOR SPLIT STATES: {}:{:?}", block_hash, shard_uid),
+        )
+    }
+
     pub fn get_state_changes_with_cause_in_block(
         &self,
         block_hash: &CryptoHash,
@@ -731,6 +940,224 @@ impl ChainStore {
             }
         })
     }
+
+    /// Returns the changes matching the request in the canonical blocks with heights in
+    /// `from_height..=to_height`, ordered by height.
+    ///
+    /// Unlike `get_state_changes`, which looks at the changes of a single block, the changes are
+    /// found by the trie key in `ColStateChangesByKey`, so the blocks without matching changes
+    /// are never read. The callers serving requests limit the range, see
+    /// `MAX_STATE_CHANGES_HISTORY_HEIGHTS`.
+    pub fn get_state_changes_history(
+        &self,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        state_changes_request: &StateChangesRequest,
+    ) -> Result<Vec<(BlockHeight, CryptoHash, StateChanges)>, Error> {
+        // Raw trie keys to look up, and whether the changed key has to be equal to the raw key
+        // rather than just start with it.
+        let data_keys: Vec<(Vec<u8>, bool)> = match state_changes_request {
+            StateChangesRequest::AccountChanges { account_ids } => account_ids
+                .iter()
+                .map(|account_id| {
+                    (TrieKey::Account { account_id: account_id.clone() }.to_vec(), true)
+                })
+                .collect(),
+            StateChangesRequest::SingleAccessKeyChanges { keys } => keys
+                .iter()
+                .map(|key| {
+                    let data_key = TrieKey::AccessKey {
+                        account_id: key.account_id.clone(),
+                        public_key: key.public_key.clone(),
+                    };
+                    (data_key.to_vec(), true)
+                })
+                .collect(),
+            StateChangesRequest::AllAccessKeyChanges { account_ids } => account_ids
+                .iter()
+                .map(|account_id| {
+                    (trie_key_parsers::get_raw_prefix_for_access_keys(account_id), false)
+                })
+                .collect(),
+            StateChangesRequest::ContractCodeChanges { account_ids } => account_ids
+                .iter()
+                .map(|account_id| {
+                    (TrieKey::ContractCode { account_id: account_id.clone() }.to_vec(), true)
+                })
+                .collect(),
+            StateChangesRequest::DataChanges { account_ids, key_prefix } => account_ids
+                .iter()
+                .map(|account_id| {
+                    let data_key = trie_key_parsers::get_raw_prefix_for_contract_data(
+                        account_id,
+                        key_prefix.as_ref(),
+                    );
+                    (data_key, false)
+                })
+                .collect(),
+        };
+
+        let mut raw_changes: BTreeMap<(BlockHeight, CryptoHash), Vec<RawStateChangesWithTrieKey>> =
+            BTreeMap::new();
+        for (data_key, exact) in data_keys {
+            // Index keys are `trie_key || height || block_hash`, so the index keys of a trie key
+            // may be interleaved with the ones of longer trie keys starting with it.
+            let mut index_keys = vec![];
+            if exact {
+                // The index keys of the trie key with heights in the range are contiguous, only
+                // the ones of longer trie keys found among them are skipped.
+                let last_key: Vec<u8> =
+                    KeyForStateChangesByKey::new(&data_key, to_height, &CryptoHash([0xff; 32]))
+                        .into();
+                let mut seek_key: Vec<u8> =
+                    KeyForStateChangesByKey::new(&data_key, from_height, &CryptoHash::default())
+                        .into();
+                loop {
+                    let index_key = match self
+                        .store
+                        .iter_from_without_rc_logic(ColStateChangesByKey, &seek_key)
+                        .next()
+                    {
+                        Some((index_key, _)) if index_key[..] <= last_key[..] => index_key,
+                        _ => break,
+                    };
+                    if index_key.len() == last_key.len() {
+                        seek_key = index_key.to_vec();
+                        seek_key.push(0);
+                        index_keys.push(index_key);
+                    } else {
+                        // An index key of this length sharing the prefix with the longer one is
+                        // ordered before it, so all the keys with the prefix can be skipped.
+                        match next_key_prefix(&index_key[..last_key.len()]) {
+                            Some(next_key) => seek_key = next_key,
+                            None => break,
+                        }
+                    }
+                }
+            } else {
+                // Longer trie keys may have suffixes ordered before any height, so the scan starts
+                // at the prefix itself and the heights are checked for every key.
+                for (index_key, _) in
+                    self.store.iter_from_without_rc_logic(ColStateChangesByKey, &data_key)
+                {
+                    if !index_key.starts_with(&data_key) {
+                        break;
+                    }
+                    index_keys.push(index_key);
+                }
+            }
+            for index_key in index_keys {
+                let (trie_key, height, block_hash) = KeyForStateChangesByKey::parse(&index_key)?;
+                if height < from_height || height > to_height {
+                    continue;
+                }
+                if !self.is_height_of_canonical_block(height, &block_hash)? {
+                    continue;
+                }
+                let storage_key = KeyForStateChanges::new(&block_hash, trie_key);
+                let changes: RawStateChangesWithTrieKey = option_to_not_found(
+                    self.store.get_ser(ColStateChanges, storage_key.as_ref()),
+                    &format!("STATE CHANGES: {} {:?}", block_hash, trie_key),
+                )?;
+                raw_changes.entry((height, block_hash)).or_default().push(changes);
+            }
+        }
+
+        raw_changes
+            .into_iter()
+            .map(|((height, block_hash), changes)| -> Result<_, Error> {
+                let changes = changes.into_iter().map(Ok);
+                let changes = match state_changes_request {
+                    StateChangesRequest::AccountChanges { .. } => {
+                        StateChanges::from_account_changes(changes)?
+                    }
+                    StateChangesRequest::SingleAccessKeyChanges { .. }
+                    | StateChangesRequest::AllAccessKeyChanges { .. } => {
+                        StateChanges::from_access_key_changes(changes)?
+                    }
+                    StateChangesRequest::ContractCodeChanges { .. } => {
+                        StateChanges::from_contract_code_changes(changes)?
+                    }
+                    StateChangesRequest::DataChanges { .. } => {
+                        StateChanges::from_data_changes(changes)?
+                    }
+                };
+                Ok((height, block_hash, changes))
+            })
+            .collect()
+    }
+
+    fn is_height_of_canonical_block(
+        &self,
+        height: BlockHeight,
+        block_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        let canonical_hash: Option<CryptoHash> =
+            self.store.get_ser(ColBlockHeight, &index_to_bytes(height))?;
+        Ok(canonical_hash.as_ref() == Some(block_hash))
+    }
+
+    /// Starts or stops keeping the accounts changed by each block in `ColAccountHistory`.
+    /// Once stopped, the history kept so far is not used until it's started again.
+    pub fn set_save_account_history(&mut self, save_account_history: bool) -> Result<(), Error> {
+        let start: Option<BlockHeight> =
+            self.store.get_ser(ColBlockMisc, ACCOUNT_HISTORY_START_KEY)?;
+        let mut store_update = self.store.store_update();
+        match (save_account_history, start) {
+            (true, None) => {
+                let start = self.head().map(|head| head.height + 1).unwrap_or(self.genesis_height);
+                store_update.set_ser(ColBlockMisc, ACCOUNT_HISTORY_START_KEY, &start)?;
+            }
+            (false, Some(_)) => store_update.delete(ColBlockMisc, ACCOUNT_HISTORY_START_KEY),
+            _ => {}
+        }
+        store_update.commit()?;
+        self.save_account_history = save_account_history;
+        Ok(())
+    }
+
+    /// Returns the account as of the canonical block at the given height, where `Some(None)`
+    /// means that the account doesn't exist. Returns `None` if the account history doesn't
+    /// cover the height, e.g. because the account hasn't changed since the history started.
+    pub fn get_account_from_history(
+        &mut self,
+        account_id: &AccountId,
+        height: BlockHeight,
+    ) -> Result<Option<Option<Account>>, Error> {
+        let start: BlockHeight =
+            match self.store.get_ser(ColBlockMisc, ACCOUNT_HISTORY_START_KEY)? {
+                Some(start) => start,
+                None => return Ok(None),
+            };
+        if height < start {
+            return Ok(None);
+        }
+        let prefix = get_account_history_prefix(account_id);
+        let key_from = get_account_history_key(account_id, height, &CryptoHash::default());
+        // The heights are inverted in the keys, so the latest change at or below the height
+        // comes first. The changes made by the blocks on forks are skipped.
+        let store = self.store.clone();
+        for (key, value) in store.iter_from_without_rc_logic(ColAccountHistory, &key_from) {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let (change_height, block_hash) = parse_account_history_key(&key[prefix.len()..])?;
+            if change_height < start {
+                return Ok(None);
+            }
+            match self.get_block_hash_by_height(change_height) {
+                Ok(canonical_hash) if canonical_hash == block_hash => {
+                    return Ok(Some(Option::<Account>::try_from_slice(&value)?));
+                }
+                Ok(_) => {}
+                Err(err) => match err.kind() {
+                    ErrorKind::DBNotFoundErr(_) => {}
+                    _ => return Err(err),
+                },
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl ChainStoreAccess for ChainStore {
@@ -853,16 +1280,16 @@ impl ChainStoreAccess for ChainStore {
     fn get_chunk_extra(
         &mut self,
         block_hash: &CryptoHash,
-        shard_id: ShardId,
+        shard_uid: &ShardUId,
     ) -> Result<&ChunkExtra, Error> {
         option_to_not_found(
             read_with_cache(
                 &*self.store,
                 ColChunkExtra,
                 &mut self.chunk_extras,
-                &get_block_shard_id(block_hash, shard_id),
+                &get_block_shard_uid(block_hash, shard_uid),
             ),
-            &format!("CHUNK EXTRA: {}:{}", block_hash, shard_id),
+            &format!("CHUNK EXTRA: {}:{:?}", block_hash, shard_uid),
         )
     }
 
@@ -1107,7 +1534,7 @@ struct ChainStoreCacheUpdate {
     blocks: HashMap<CryptoHash, Block>,
     headers: HashMap<CryptoHash, BlockHeader>,
     block_extras: HashMap<CryptoHash, BlockExtra>,
-    chunk_extras: HashMap<(CryptoHash, ShardId), ChunkExtra>,
+    chunk_extras: HashMap<(CryptoHash, ShardUId), ChunkExtra>,
     chunks: HashMap<ChunkHash, ShardChunk>,
     partial_chunks: HashMap<ChunkHash, PartialEncodedChunk>,
     block_hash_per_height: HashMap<BlockHeight, HashMap<EpochId, HashSet<CryptoHash>>>,
@@ -1155,8 +1582,13 @@ pub struct ChainStoreUpdate<'a> {
     // A prev_hash to be removed with all the hashes associated with it
     remove_prev_blocks_to_catchup: Vec<CryptoHash>,
     add_state_dl_infos: Vec<StateSyncInfo>,
+    add_state_changes_for_split_states: HashMap<(CryptoHash, ShardUId), StateChangesForSplitStates>,
     remove_state_dl_infos: Vec<CryptoHash>,
     challenged_blocks: HashSet<CryptoHash>,
+    /// Categories of data GC keeps longer than the blocks they belong to.
+    gc_retained_categories: Vec<GCDataCategory>,
+    add_gc_retained_data: HashMap<(GCDataCategory, BlockHeight), Vec<Vec<u8>>>,
+    gc_retained_tails: HashMap<GCDataCategory, BlockHeight>,
 }
 
 impl<'a> ChainStoreUpdate<'a> {
@@ -1177,18 +1609,25 @@ impl<'a> ChainStoreUpdate<'a> {
             remove_blocks_to_catchup: vec![],
             remove_prev_blocks_to_catchup: vec![],
             add_state_dl_infos: vec![],
+            add_state_changes_for_split_states: HashMap::new(),
             remove_state_dl_infos: vec![],
             challenged_blocks: HashSet::default(),
+            gc_retained_categories: vec![],
+            add_gc_retained_data: HashMap::new(),
+            gc_retained_tails: HashMap::new(),
         }
     }
 
     pub fn get_incoming_receipts_for_shard(
         &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
         shard_id: ShardId,
         mut block_hash: CryptoHash,
         last_chunk_height_included: BlockHeight,
     ) -> Result<Vec<ReceiptProofResponse>, Error> {
         let mut ret = vec![];
+        let epoch_id = self.get_block_header(&block_hash)?.epoch_id().clone();
+        let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
 
         loop {
             let header = self.get_block_header(&block_hash)?;
@@ -1202,8 +1641,34 @@ impl<'a> ChainStoreUpdate<'a> {
             }
 
             let prev_hash = *header.prev_hash();
+            let receipts_epoch_id = header.epoch_id().clone();
 
-            if let Ok(receipt_proofs) = self.get_incoming_receipts(&block_hash, shard_id) {
+            if receipts_epoch_id != epoch_id
+                && runtime_adapter.get_shard_layout(&receipts_epoch_id)? != shard_layout
+            {
+                // The receipts were sent to the parent shard with the previous shard layout,
+                // only keep the ones which belong to this shard.
+                let parent_shard_id = shard_layout
+                    .get_parent_shard_id(shard_id)
+                    .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?;
+                let receipt_proofs = self
+                    .get_incoming_receipts(&block_hash, parent_shard_id)
+                    .map(|receipt_proofs| receipt_proofs.clone())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|ReceiptProof(receipts, shard_proof)| {
+                        let receipts = receipts
+                            .into_iter()
+                            .filter(|receipt| {
+                                account_id_to_shard_id(&receipt.receiver_id, &shard_layout)
+                                    == shard_id
+                            })
+                            .collect();
+                        ReceiptProof(receipts, shard_proof)
+                    })
+                    .collect();
+                ret.push(ReceiptProofResponse(block_hash, receipt_proofs));
+            } else if let Ok(receipt_proofs) = self.get_incoming_receipts(&block_hash, shard_id) {
                 ret.push(ReceiptProofResponse(block_hash, receipt_proofs.clone()));
             } else {
                 ret.push(ReceiptProofResponse(block_hash, vec![]));
@@ -1328,14 +1793,14 @@ impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
     fn get_chunk_extra(
         &mut self,
         block_hash: &CryptoHash,
-        shard_id: ShardId,
+        shard_uid: &ShardUId,
     ) -> Result<&ChunkExtra, Error> {
         if let Some(chunk_extra) =
-            self.chain_store_cache_update.chunk_extras.get(&(*block_hash, shard_id))
+            self.chain_store_cache_update.chunk_extras.get(&(*block_hash, *shard_uid))
         {
             Ok(chunk_extra)
         } else {
-            self.chain_store.get_chunk_extra(block_hash, shard_id)
+            self.chain_store.get_chunk_extra(block_hash, shard_uid)
         }
     }
 
@@ -1720,10 +2185,10 @@ impl<'a> ChainStoreUpdate<'a> {
     pub fn save_chunk_extra(
         &mut self,
         block_hash: &CryptoHash,
-        shard_id: ShardId,
+        shard_uid: &ShardUId,
         chunk_extra: ChunkExtra,
     ) {
-        self.chain_store_cache_update.chunk_extras.insert((*block_hash, shard_id), chunk_extra);
+        self.chain_store_cache_update.chunk_extras.insert((*block_hash, *shard_uid), chunk_extra);
     }
 
     pub fn save_chunk(&mut self, chunk: ShardChunk) {
@@ -1868,6 +2333,18 @@ impl<'a> ChainStoreUpdate<'a> {
         self.remove_state_dl_infos.push(hash);
     }
 
+    pub fn add_state_changes_for_split_states(
+        &mut self,
+        block_hash: CryptoHash,
+        shard_uid: ShardUId,
+        state_changes: StateChangesForSplitStates,
+    ) {
+        let prev =
+            self.add_state_changes_for_split_states.insert((block_hash, shard_uid), state_changes);
+        // We should not save state changes for the same chunk twice
+        assert!(prev.is_none());
+    }
+
     pub fn save_challenged_block(&mut self, hash: CryptoHash) {
         self.challenged_blocks.insert(hash);
     }
@@ -1964,10 +2441,20 @@ impl<'a> ChainStoreUpdate<'a> {
                 let chunk = self.get_chunk(&chunk_hash)?.clone();
                 debug_assert_eq!(chunk.cloned_header().height_created(), height);
                 for transaction in chunk.transactions() {
-                    self.gc_col(ColTransactions, &transaction.get_hash().into());
+                    self.gc_col_or_retain(
+                        GCDataCategory::Transactions,
+                        height,
+                        ColTransactions,
+                        transaction.get_hash().into(),
+                    );
                 }
                 for receipt in chunk.receipts() {
-                    self.gc_col(ColReceipts, &receipt.get_hash().into());
+                    self.gc_col_or_retain(
+                        GCDataCategory::Receipts,
+                        height,
+                        ColReceipts,
+                        receipt.get_hash().into(),
+                    );
                 }
 
                 // 2. Delete chunk_hash-indexed data
@@ -1992,31 +2479,55 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(())
     }
 
+    /// Uids of the shards which may have data stored for the block: the shards of its epoch and,
+    /// if the next epoch uses another shard layout, the shards their states are split into.
+    fn get_shard_uids_for_block(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        block_hash: &CryptoHash,
+    ) -> Result<Vec<ShardUId>, Error> {
+        let header = self.get_block_header(block_hash)?;
+        let epoch_id = header.epoch_id().clone();
+        let next_epoch_id = header.next_epoch_id().clone();
+        let shard_layout = runtime_adapter.get_shard_layout(&epoch_id)?;
+        let mut shard_uids = shard_layout.get_shard_uids();
+        // The next epoch may be unknown for blocks downloaded during state sync.
+        if let Ok(next_shard_layout) = runtime_adapter.get_shard_layout(&next_epoch_id) {
+            if next_shard_layout.version() != shard_layout.version() {
+                shard_uids.extend(next_shard_layout.get_shard_uids());
+            }
+        }
+        Ok(shard_uids)
+    }
+
     // Clearing block data of `block_hash`, if on a fork.
     // Clearing block data of `block_hash.prev`, if on the Canonical Chain.
     pub fn clear_block_data(
         &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
         mut block_hash: CryptoHash,
         gc_mode: GCMode,
     ) -> Result<(), Error> {
         let mut store_update = self.store().store_update();
-        let header = self.get_block_header(&block_hash).expect("block header must exist").clone();
+        let shard_uids = self.get_shard_uids_for_block(runtime_adapter, &block_hash)?;
 
         // 1. Apply revert insertions or deletions from ColTrieChanges for Trie
         match gc_mode.clone() {
             GCMode::Fork(tries) => {
                 // If the block is on a fork, we delete the state that's the result of applying this block
-                for shard_id in 0..header.chunk_mask().len() as ShardId {
+                for shard_uid in shard_uids {
+                    let key = get_block_shard_uid(&block_hash, &shard_uid);
                     self.store()
-                        .get_ser(ColTrieChanges, &get_block_shard_id(&block_hash, shard_id))?
+                        .get_ser(ColTrieChanges, &key)?
                         .map(|trie_changes: TrieChanges| {
                             tries
-                                .revert_insertions(&trie_changes, shard_id, &mut store_update)
+                                .revert_insertions(
+                                    &trie_changes,
+                                    shard_uid.shard_id(),
+                                    &mut store_update,
+                                )
                                 .map(|_| {
-                                    self.gc_col(
-                                        ColTrieChanges,
-                                        &get_block_shard_id(&block_hash, shard_id),
-                                    );
+                                    self.gc_col(ColTrieChanges, &key);
                                     self.inc_gc_col_state();
                                 })
                                 .map_err(|err| ErrorKind::Other(err.to_string()))
@@ -2026,17 +2537,19 @@ impl<'a> ChainStoreUpdate<'a> {
             }
             GCMode::Canonical(tries) => {
                 // If the block is on canonical chain, we delete the state that's before applying this block
-                for shard_id in 0..header.chunk_mask().len() as ShardId {
+                for shard_uid in shard_uids {
+                    let key = get_block_shard_uid(&block_hash, &shard_uid);
                     self.store()
-                        .get_ser(ColTrieChanges, &get_block_shard_id(&block_hash, shard_id))?
+                        .get_ser(ColTrieChanges, &key)?
                         .map(|trie_changes: TrieChanges| {
                             tries
-                                .apply_deletions(&trie_changes, shard_id, &mut store_update)
+                                .apply_deletions(
+                                    &trie_changes,
+                                    shard_uid.shard_id(),
+                                    &mut store_update,
+                                )
                                 .map(|_| {
-                                    self.gc_col(
-                                        ColTrieChanges,
-                                        &get_block_shard_id(&block_hash, shard_id),
-                                    );
+                                    self.gc_col(ColTrieChanges, &key);
                                     self.inc_gc_col_state();
                                 })
                                 .map_err(|err| ErrorKind::Other(err.to_string()))
@@ -2048,8 +2561,8 @@ impl<'a> ChainStoreUpdate<'a> {
             }
             GCMode::StateSync { .. } => {
                 // Not apply the data from ColTrieChanges
-                for shard_id in 0..header.chunk_mask().len() as ShardId {
-                    self.gc_col(ColTrieChanges, &get_block_shard_id(&block_hash, shard_id));
+                for shard_uid in shard_uids {
+                    self.gc_col(ColTrieChanges, &get_block_shard_uid(&block_hash, &shard_uid));
                 }
             }
         }
@@ -2067,7 +2580,6 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_col(ColIncomingReceipts, &block_shard_id);
             self.gc_col(ColChunkPerHeightShard, &block_shard_id);
             self.gc_col(ColNextBlockWithNewChunk, &block_shard_id);
-            self.gc_col(ColChunkExtra, &block_shard_id);
 
             // For incoming State Parts it's done in chain.clear_downloaded_parts()
             // The following code is mostly for outgoing State Parts.
@@ -2088,6 +2600,13 @@ impl<'a> ChainStoreUpdate<'a> {
             }
         }
 
+        // Chunk extras and state changes for split states are indexed by shard uid.
+        for shard_uid in self.get_shard_uids_for_block(runtime_adapter, &block_hash)? {
+            let block_shard_uid = get_block_shard_uid(&block_hash, &shard_uid);
+            self.gc_col(ColChunkExtra, &block_shard_uid);
+            self.gc_col(ColStateChangesForSplitStates, &block_shard_uid);
+        }
+
         // 3. Delete block_hash-indexed data
         let block_hash_vec: Vec<u8> = block_hash.as_ref().into();
         self.gc_col(ColBlock, &block_hash_vec);
@@ -2103,6 +2622,9 @@ impl<'a> ChainStoreUpdate<'a> {
             .map(|key| key.0.into())
             .collect();
         for key in stored_state_changes {
+            let trie_key = &key[storage_key.as_ref().len()..];
+            let index_key = KeyForStateChangesByKey::new(trie_key, height, &block_hash);
+            self.gc_col(ColStateChangesByKey, index_key.as_ref());
             self.gc_col(ColStateChanges, &key);
         }
         self.gc_col(ColBlockRefCount, &block_hash_vec);
@@ -2230,32 +2752,99 @@ impl<'a> ChainStoreUpdate<'a> {
 
     pub fn gc_outcomes(&mut self, block: &Block) -> Result<(), Error> {
         let block_hash = block.hash();
-        let mut store_update = self.store().store_update();
-        for chunk_header in
-            block.chunks().iter().filter(|h| h.height_included() == block.header().height())
-        {
+        let height = block.header().height();
+        for chunk_header in block.chunks().iter().filter(|h| h.height_included() == height) {
             let shard_id = chunk_header.shard_id();
-            let outcome_ids =
-                self.chain_store.get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?;
-            for outcome_id in outcome_ids {
-                let mut outcomes_with_id = self.chain_store.get_outcomes_by_id(&outcome_id)?;
-                outcomes_with_id.retain(|outcome| &outcome.block_hash != block_hash);
-                if outcomes_with_id.is_empty() {
-                    self.gc_col(ColTransactionResult, &outcome_id.as_ref().into());
-                } else {
-                    store_update.set_ser(
-                        ColTransactionResult,
-                        outcome_id.as_ref(),
-                        &outcomes_with_id,
-                    )?;
-                }
+            if self.gc_retained_categories.contains(&GCDataCategory::Outcomes) {
+                self.add_gc_retained_data
+                    .entry((GCDataCategory::Outcomes, height))
+                    .or_default()
+                    .push(get_block_shard_id(block_hash, shard_id));
+            } else {
+                self.gc_chunk_outcomes(block_hash, shard_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn gc_chunk_outcomes(
+        &mut self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<(), Error> {
+        let mut store_update = self.store().store_update();
+        let outcome_ids =
+            self.chain_store.get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?;
+        for outcome_id in outcome_ids {
+            let mut outcomes_with_id = self.chain_store.get_outcomes_by_id(&outcome_id)?;
+            outcomes_with_id.retain(|outcome| &outcome.block_hash != block_hash);
+            if outcomes_with_id.is_empty() {
+                self.gc_col(ColTransactionResult, &outcome_id.as_ref().into());
+            } else {
+                store_update.set_ser(
+                    ColTransactionResult,
+                    outcome_id.as_ref(),
+                    &outcomes_with_id,
+                )?;
             }
-            self.gc_col(ColOutcomeIds, &get_block_shard_id(block_hash, shard_id));
         }
+        self.gc_col(ColOutcomeIds, &get_block_shard_id(block_hash, shard_id));
         self.merge(store_update);
         Ok(())
     }
 
+    /// Sets the categories of data to keep when clearing blocks and chunks, their keys are stored
+    /// to be deleted later by `clear_gc_retained_data`.
+    pub fn set_gc_retained_categories(&mut self, categories: Vec<GCDataCategory>) {
+        self.gc_retained_categories = categories;
+    }
+
+    fn gc_col_or_retain(
+        &mut self,
+        category: GCDataCategory,
+        height: BlockHeight,
+        col: DBCol,
+        key: Vec<u8>,
+    ) {
+        if self.gc_retained_categories.contains(&category) {
+            self.add_gc_retained_data.entry((category, height)).or_default().push(key);
+        } else {
+            self.gc_col(col, &key);
+        }
+    }
+
+    /// Deletes the data of the category GC kept longer than the block at the given height.
+    /// Returns whether there was any data kept for the height.
+    pub fn clear_gc_retained_data(
+        &mut self,
+        category: GCDataCategory,
+        height: BlockHeight,
+    ) -> Result<bool, Error> {
+        let key = category.retained_data_key(height);
+        let data_keys: Vec<Vec<u8>> = match self.store().get_ser(ColGCRetainedData, &key)? {
+            Some(data_keys) => data_keys,
+            None => return Ok(false),
+        };
+        for data_key in data_keys {
+            match category {
+                GCDataCategory::Transactions => self.gc_col(ColTransactions, &data_key),
+                GCDataCategory::Receipts => self.gc_col(ColReceipts, &data_key),
+                GCDataCategory::Outcomes => {
+                    let (block_hash, shard_id) = get_block_shard_id_rev(&data_key)
+                        .map_err(|err| ErrorKind::Other(err.to_string()))?;
+                    self.gc_chunk_outcomes(&block_hash, shard_id)?;
+                }
+                GCDataCategory::Blocks => unreachable!("blocks are never kept longer"),
+            }
+        }
+        self.gc_col(ColGCRetainedData, &key);
+        Ok(true)
+    }
+
+    pub fn update_gc_retained_tail(&mut self, category: GCDataCategory, height: BlockHeight) {
+        self.gc_retained_tails.insert(category, height);
+    }
+
     fn gc_col(&mut self, col: DBCol, key: &Vec<u8>) {
         assert!(SHOULD_COL_GC[col as usize]);
         let mut store_update = self.store().store_update();
@@ -2305,6 +2894,12 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColStateChanges => {
                 store_update.delete(col, key);
             }
+            DBCol::ColStateChangesByKey => {
+                store_update.delete(col, key);
+            }
+            DBCol::ColStateChangesForSplitStates => {
+                store_update.delete(col, key);
+            }
             DBCol::ColBlockRefCount => {
                 store_update.delete(col, key);
                 self.chain_store.block_refcounts.cache_remove(key);
@@ -2374,6 +2969,9 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColHeaderHashesByHeight => {
                 store_update.delete(col, key);
             }
+            DBCol::ColGCRetainedData => {
+                store_update.delete(col, key);
+            }
             DBCol::ColDbVersion
             | DBCol::ColBlockMisc
             | DBCol::ColGCCount
@@ -2390,7 +2988,12 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::ColEpochValidatorInfo
             | DBCol::ColBlockOrdinal
             | DBCol::_ColTransactionRefCount
-            | DBCol::ColCachedContractCode => {
+            | DBCol::ColCachedContractCode
+            | DBCol::ColPeerLatency
+            | DBCol::ColFlatState
+            | DBCol::ColFlatStateDeltas
+            | DBCol::ColFlatStateMisc
+            | DBCol::ColAccountHistory => {
                 unreachable!();
             }
         }
@@ -2414,6 +3017,30 @@ impl<'a> ChainStoreUpdate<'a> {
         Ok(())
     }
 
+    /// Saves the accounts as of the end of the block at `height` for the accounts it changed.
+    fn account_history_into(
+        trie_changes: &WrappedTrieChanges,
+        height: BlockHeight,
+        store_update: &mut StoreUpdate,
+    ) -> Result<(), Error> {
+        for change_with_trie_key in trie_changes.state_changes() {
+            let account_id = match &change_with_trie_key.trie_key {
+                TrieKey::Account { account_id } => account_id,
+                _ => continue,
+            };
+            let account = match change_with_trie_key.changes.last() {
+                Some(RawStateChange { data: Some(data), .. }) => {
+                    Some(Account::try_from_slice(data)?)
+                }
+                Some(RawStateChange { data: None, .. }) => None,
+                None => continue,
+            };
+            let key = get_account_history_key(account_id, height, trie_changes.block_hash());
+            store_update.set_ser(ColAccountHistory, &key, &account)?;
+        }
+        Ok(())
+    }
+
     fn finalize(&mut self) -> Result<StoreUpdate, Error> {
         let mut store_update = self.store().store_update();
         Self::write_col_misc(&mut store_update, HEAD_KEY, &mut self.head)?;
@@ -2474,12 +3101,12 @@ impl<'a> ChainStoreUpdate<'a> {
         for (height, hash_set) in header_hashes_by_height {
             store_update.set_ser(ColHeaderHashesByHeight, &index_to_bytes(height), &hash_set)?;
         }
-        for ((block_hash, shard_id), chunk_extra) in
+        for ((block_hash, shard_uid), chunk_extra) in
             self.chain_store_cache_update.chunk_extras.iter()
         {
             store_update.set_ser(
                 ColChunkExtra,
-                &get_block_shard_id(block_hash, *shard_id),
+                &get_block_shard_uid(block_hash, shard_uid),
                 chunk_extra,
             )?;
         }
@@ -2618,9 +3245,18 @@ impl<'a> ChainStoreUpdate<'a> {
         {
             store_update.set_ser(ColBlockOrdinal, &index_to_bytes(*block_ordinal), block_hash)?;
         }
-        for mut wrapped_trie_changes in self.trie_changes.drain(..) {
+        let trie_changes = std::mem::take(&mut self.trie_changes);
+        for mut wrapped_trie_changes in trie_changes {
+            let header = self.get_block_header(wrapped_trie_changes.block_hash())?;
+            let (prev_block_hash, height) = (*header.prev_hash(), header.height());
+            if self.chain_store.save_account_history && wrapped_trie_changes.saves_state_changes() {
+                Self::account_history_into(&wrapped_trie_changes, height, &mut store_update)?;
+            }
             wrapped_trie_changes
-                .wrapped_into(&mut store_update)
+                .flat_state_delta_into(prev_block_hash, height, &mut store_update)
+                .map_err(|err| ErrorKind::Other(err.to_string()))?;
+            wrapped_trie_changes
+                .wrapped_into(height, &mut store_update)
                 .map_err(|err| ErrorKind::Other(err.to_string()))?;
         }
 
@@ -2691,6 +3327,32 @@ impl<'a> ChainStoreUpdate<'a> {
         for hash in self.remove_state_dl_infos.drain(..) {
             store_update.delete(ColStateDlInfos, hash.as_ref());
         }
+        for ((block_hash, shard_uid), state_changes) in
+            self.add_state_changes_for_split_states.drain()
+        {
+            store_update.set_ser(
+                ColStateChangesForSplitStates,
+                &get_block_shard_uid(&block_hash, &shard_uid),
+                &state_changes,
+            )?;
+        }
+        for ((category, height), mut data_keys) in self.add_gc_retained_data.drain() {
+            let key = category.retained_data_key(height);
+            if let Some(stored_keys) =
+                self.chain_store.store().get_ser::<Vec<Vec<u8>>>(ColGCRetainedData, &key)?
+            {
+                data_keys.extend(stored_keys);
+            }
+            store_update.set_ser(ColGCRetainedData, &key, &data_keys)?;
+            // The first data kept for the category starts its tail.
+            if self.chain_store.gc_retained_tail(category)?.is_none() {
+                let tail = self.gc_retained_tails.entry(category).or_insert(height);
+                *tail = std::cmp::min(*tail, height);
+            }
+        }
+        for (category, height) in self.gc_retained_tails.drain() {
+            store_update.set_ser(ColBlockMisc, &category.retained_tail_key(), &height)?;
+        }
         for hash in self.challenged_blocks.drain() {
             store_update.set_ser(ColChallengedBlocks, hash.as_ref(), &true)?;
         }
@@ -2759,8 +3421,8 @@ impl<'a> ChainStoreUpdate<'a> {
         for (hash, block_extra) in block_extras {
             self.chain_store.block_extras.cache_set(hash.into(), block_extra);
         }
-        for ((block_hash, shard_id), chunk_extra) in chunk_extras {
-            let key = get_block_shard_id(&block_hash, shard_id);
+        for ((block_hash, shard_uid), chunk_extra) in chunk_extras {
+            let key = get_block_shard_uid(&block_hash, &shard_uid);
             self.chain_store.chunk_extras.cache_set(key, chunk_extra);
         }
         for (hash, chunk) in chunks {
@@ -2861,21 +3523,29 @@ mod tests {
     use cached::Cached;
     use strum::IntoEnumIterator;
 
+    use near_chain_configs::GCRetentionConfig;
     use near_crypto::KeyType;
+    use near_primitives::account::Account;
     use near_primitives::block::{Block, Tip};
     #[cfg(feature = "expensive_tests")]
     use near_primitives::epoch_manager::block_info::BlockInfo;
     use near_primitives::errors::InvalidTxError;
-    use near_primitives::hash::hash;
-    use near_primitives::types::{BlockHeight, EpochId, GCCount, NumBlocks};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::{
+        AccountId, Balance, BlockHeight, EpochId, GCCount, NumBlocks, RawStateChange,
+        RawStateChangesWithTrieKey, StateChangeCause, StateChangeValue, StateChangesRequest,
+        StoreKey, StoreValue,
+    };
     use near_primitives::utils::index_to_bytes;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
     use near_store::test_utils::create_test_store;
-    use near_store::DBCol;
+    use near_store::{DBCol, ShardTries, Trie, TrieChanges, WrappedTrieChanges};
     #[cfg(feature = "expensive_tests")]
     use {crate::store_validator::StoreValidator, near_chain_configs::GenesisConfig};
 
-    use crate::store::{ChainStoreAccess, GCMode};
+    use crate::store::{ChainStore, ChainStoreAccess, GCMode};
     use crate::test_utils::KeyValueRuntime;
     use crate::{Chain, ChainGenesis, DoomslugThresholdMode};
 
@@ -3140,7 +3810,7 @@ mod tests {
 
         chain.epoch_length = 1;
         let trie = chain.runtime_adapter.get_tries();
-        assert!(chain.clear_data(trie, 100).is_ok());
+        assert!(chain.clear_data(trie, 100, &GCRetentionConfig::default()).is_ok());
 
         // epoch didn't change so no data is garbage collected.
         for i in 0..15 {
@@ -3252,9 +3922,12 @@ mod tests {
         );
         assert!(chain.mut_store().get_next_block_hash(&blocks[5].hash()).is_ok());
 
-        let trie = chain.runtime_adapter.get_tries();
+        let runtime_adapter = chain.runtime_adapter.clone();
+        let trie = runtime_adapter.get_tries();
         let mut store_update = chain.mut_store().store_update();
-        assert!(store_update.clear_block_data(*blocks[5].hash(), GCMode::Canonical(trie)).is_ok());
+        assert!(store_update
+            .clear_block_data(&*runtime_adapter, *blocks[5].hash(), GCMode::Canonical(trie))
+            .is_ok());
         store_update.commit().unwrap();
 
         assert!(chain.get_block(blocks[4].hash()).is_err());
@@ -3335,7 +4008,9 @@ mod tests {
 
         for iter in 0..10 {
             println!("ITERATION #{:?}", iter);
-            assert!(chain.clear_data(trie.clone(), gc_blocks_limit).is_ok());
+            assert!(chain
+                .clear_data(trie.clone(), gc_blocks_limit, &GCRetentionConfig::default())
+                .is_ok());
 
             // epoch didn't change so no data is garbage collected.
             for i in 0..1000 {
@@ -3366,4 +4041,198 @@ mod tests {
             assert!(!store_validator.is_failed());
         }
     }
+
+    #[test]
+    fn test_state_changes_history() {
+        let store = create_test_store();
+        let tries = ShardTries::new(store.clone(), 1);
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let alice_short: AccountId = "alice".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        // The index keys of this key are ordered before the ones of `key` at any height.
+        let zero_key = [b'k', b'e', 0, 0, 0, 0, 0, 0, 0, 0];
+        let data_change =
+            |account_id: &AccountId, key: &[u8], value: u8| RawStateChangesWithTrieKey {
+                trie_key: TrieKey::ContractData {
+                    account_id: account_id.clone(),
+                    key: key.to_vec(),
+                },
+                changes: vec![RawStateChange {
+                    cause: StateChangeCause::InitialState,
+                    data: Some(vec![value]),
+                }],
+            };
+        let code_change = |account_id: &AccountId, value: u8| RawStateChangesWithTrieKey {
+            trie_key: TrieKey::ContractCode { account_id: account_id.clone() },
+            changes: vec![RawStateChange {
+                cause: StateChangeCause::InitialState,
+                data: Some(vec![value]),
+            }],
+        };
+        let save_changes = |height: u8, block_hash: CryptoHash, state_changes| {
+            let mut wrapped_trie_changes = WrappedTrieChanges::new(
+                tries.clone(),
+                ShardUId::single_shard(),
+                TrieChanges::empty(Trie::empty_root()),
+                state_changes,
+                block_hash,
+            );
+            let mut store_update = store.store_update();
+            wrapped_trie_changes.state_changes_into(height as BlockHeight, &mut store_update);
+            store_update.commit().unwrap();
+        };
+        for height in 1..=5u8 {
+            let mut state_changes = vec![data_change(&bob, b"key", height)];
+            // Alice only changes her state in the odd blocks.
+            if height % 2 == 1 {
+                state_changes.push(data_change(&alice, b"key", height));
+                state_changes.push(data_change(&alice, b"other", height));
+            }
+            if height == 3 {
+                state_changes.push(data_change(&alice, &zero_key, height));
+            }
+            state_changes.push(code_change(&alice_short, height));
+            state_changes.push(code_change(&alice, height));
+            save_changes(height, hash(&[height]), state_changes);
+            let mut store_update = store.store_update();
+            store_update
+                .set_ser(
+                    DBCol::ColBlockHeight,
+                    &index_to_bytes(height as BlockHeight),
+                    &hash(&[height]),
+                )
+                .unwrap();
+            store_update.commit().unwrap();
+        }
+        // The changes of the blocks off the canonical chain are not returned.
+        save_changes(4, hash(&[100]), vec![data_change(&alice, b"key", 100)]);
+
+        let chain_store = ChainStore::new(store, 0);
+        let request = StateChangesRequest::DataChanges {
+            account_ids: vec![alice.clone()],
+            key_prefix: b"ke".to_vec().into(),
+        };
+        let history = chain_store.get_state_changes_history(2, 5, &request).unwrap();
+        let heights: Vec<_> = history.iter().map(|(height, _, _)| *height).collect();
+        assert_eq!(heights, vec![3, 5]);
+        for (height, block_hash, changes) in history {
+            assert_eq!(block_hash, hash(&[height as u8]));
+            let mut keys = vec![];
+            for change in changes {
+                match change.value {
+                    StateChangeValue::DataUpdate { account_id, key, value } => {
+                        assert_eq!(account_id, alice);
+                        assert_eq!(value, StoreValue::from(vec![height as u8]));
+                        keys.push(key);
+                    }
+                    value => panic!("Unexpected change {:?}", value),
+                }
+            }
+            let mut expected_keys = vec![StoreKey::from(b"key".to_vec())];
+            if height == 3 {
+                expected_keys.push(StoreKey::from(zero_key.to_vec()));
+            }
+            assert_eq!(keys.len(), expected_keys.len());
+            assert!(expected_keys.iter().all(|key| keys.contains(key)));
+        }
+
+        let request = StateChangesRequest::DataChanges {
+            account_ids: vec![alice, bob],
+            key_prefix: vec![].into(),
+        };
+        let history = chain_store.get_state_changes_history(1, 5, &request).unwrap();
+        let num_changes: Vec<_> = history.iter().map(|(_, _, changes)| changes.len()).collect();
+        assert_eq!(num_changes, vec![3, 1, 4, 1, 3]);
+        let history = chain_store.get_state_changes_history(2, 4, &request).unwrap();
+        let num_changes: Vec<_> = history.iter().map(|(_, _, changes)| changes.len()).collect();
+        assert_eq!(num_changes, vec![1, 4, 1]);
+
+        // Exact keys don't match the longer keys starting with them.
+        let request =
+            StateChangesRequest::ContractCodeChanges { account_ids: vec![alice_short.clone()] };
+        let history = chain_store.get_state_changes_history(2, 4, &request).unwrap();
+        let heights: Vec<_> = history.iter().map(|(height, _, _)| *height).collect();
+        assert_eq!(heights, vec![2, 3, 4]);
+        for (height, _, changes) in history {
+            assert_eq!(changes.len(), 1);
+            match &changes[0].value {
+                StateChangeValue::ContractCodeUpdate { account_id, code } => {
+                    assert_eq!(account_id, &alice_short);
+                    assert_eq!(code, &vec![height as u8]);
+                }
+                value => panic!("Unexpected change {:?}", value),
+            }
+        }
+    }
+
+    #[test]
+    fn test_account_history() {
+        let mut chain = get_chain();
+        chain.mut_store().set_save_account_history(true).unwrap();
+        let tries = chain.runtime_adapter.get_tries();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+        let signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test1".parse().unwrap(),
+            KeyType::ED25519,
+            "test1",
+        ));
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let account_change = |amount: Option<Balance>| RawStateChangesWithTrieKey {
+            trie_key: TrieKey::Account { account_id: alice.clone() },
+            changes: vec![RawStateChange {
+                cause: StateChangeCause::InitialState,
+                data: amount.map(|amount| {
+                    Account::new(amount, 0, CryptoHash::default(), 0).try_to_vec().unwrap()
+                }),
+            }],
+        };
+        let mut save_block =
+            |block: &Block, is_canonical: bool, state_changes: Vec<RawStateChangesWithTrieKey>| {
+                let mut store_update = chain.mut_store().store_update();
+                store_update.save_block(block.clone());
+                store_update.save_block_header(block.header().clone()).unwrap();
+                if is_canonical {
+                    store_update
+                        .chain_store_cache_update
+                        .height_to_hashes
+                        .insert(block.header().height(), Some(*block.hash()));
+                }
+                store_update.save_trie_changes(WrappedTrieChanges::new(
+                    tries.clone(),
+                    ShardUId::single_shard(),
+                    TrieChanges::empty(Trie::empty_root()),
+                    state_changes,
+                    *block.hash(),
+                ));
+                store_update.commit().unwrap();
+            };
+        // Alice is changed at the heights 1, 2 on a fork, 3 and deleted at 4.
+        let block1 = Block::empty_with_height(&genesis, 1, &*signer);
+        save_block(&block1, true, vec![account_change(Some(1))]);
+        let fork_block2 = Block::empty_with_height(&genesis, 2, &*signer);
+        save_block(&fork_block2, false, vec![account_change(Some(2))]);
+        let block2 = Block::empty_with_height(&block1, 2, &*signer);
+        save_block(&block2, true, vec![]);
+        let block3 = Block::empty_with_height(&block2, 3, &*signer);
+        save_block(&block3, true, vec![account_change(Some(3))]);
+        let block4 = Block::empty_with_height(&block3, 4, &*signer);
+        save_block(&block4, true, vec![account_change(None)]);
+
+        let store = chain.mut_store();
+        let amount_at = |store: &mut ChainStore, height| {
+            store
+                .get_account_from_history(&alice, height)
+                .unwrap()
+                .map(|account| account.map(|account| account.amount()))
+        };
+        assert_eq!(amount_at(store, 1), Some(Some(1)));
+        assert_eq!(amount_at(store, 2), Some(Some(1)));
+        assert_eq!(amount_at(store, 3), Some(Some(3)));
+        assert_eq!(amount_at(store, 4), Some(None));
+        assert_eq!(amount_at(store, 10), Some(None));
+        assert_eq!(store.get_account_from_history(&"bob.near".parse().unwrap(), 3).unwrap(), None);
+
+        store.set_save_account_history(false).unwrap();
+        assert_eq!(amount_at(store, 3), None);
+    }
 }
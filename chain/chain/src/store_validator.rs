@@ -1,31 +1,35 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::io;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use strum::IntoEnumIterator;
-use tracing::warn;
+use tracing::{error, warn};
 
 use near_chain_configs::GenesisConfig;
-use near_primitives::block::{Block, BlockHeader};
+use near_metrics::{inc_counter, inc_counter_by, inc_counter_vec, set_gauge};
+use near_primitives::block::{Block, BlockHeader, Tip};
 use near_primitives::borsh;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::AGGREGATOR_KEY;
 use near_primitives::hash::CryptoHash;
+use near_primitives::serialize::to_base;
 use near_primitives::sharding::{ChunkHash, ShardChunk, StateSyncInfo};
 use near_primitives::syncing::{ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey};
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{AccountId, BlockHeight, EpochId, GCCount, ShardId};
-use near_primitives::utils::get_block_shard_id_rev;
+use near_primitives::utils::{get_block_shard_id_rev, get_block_shard_uid_rev, index_to_bytes};
 use near_store::{
-    decode_value_with_rc, DBCol, Store, TrieChanges, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC,
+    decode_value_with_rc, DBCol, Store, TrieChanges, CHUNK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, STORE_VALIDATOR_CHECKPOINT_KEY, TAIL_KEY,
 };
 use validate::StoreValidatorError;
 
-use crate::RuntimeAdapter;
+use crate::{metrics, RuntimeAdapter};
 
 mod validate;
 
@@ -45,6 +49,8 @@ pub struct StoreValidatorCache {
     receipt_refcount: HashMap<CryptoHash, u64>,
     block_refcount: HashMap<CryptoHash, u64>,
     genesis_blocks: Vec<CryptoHash>,
+    /// Blocks which are garbage collected, but their outcomes are kept.
+    gc_retained_outcome_blocks: HashSet<CryptoHash>,
 }
 
 impl StoreValidatorCache {
@@ -60,10 +66,74 @@ impl StoreValidatorCache {
             receipt_refcount: HashMap::new(),
             block_refcount: HashMap::new(),
             genesis_blocks: vec![],
+            gc_retained_outcome_blocks: HashSet::new(),
         }
     }
 }
 
+/// Progress of the validation done in steps: the column being validated and the last validated
+/// key in it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct StoreValidatorCheckpoint {
+    pub col: DBCol,
+    pub last_key: Option<Vec<u8>>,
+}
+
+impl Default for StoreValidatorCheckpoint {
+    fn default() -> Self {
+        Self { col: DBCol::iter().next().unwrap(), last_key: None }
+    }
+}
+
+/// Change to the store which fixes an inconsistency found by the validation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreRepair {
+    /// Refcount of the record differs from the number of references found.
+    UpdateRefcount { col: DBCol, key: Vec<u8>, rc_delta: i64 },
+    /// Block Refcount is missing or differs from the number of children found.
+    SetBlockRefcount { block_hash: CryptoHash, refcount: u64 },
+    /// Some of the Chunks stored for the height don't exist.
+    SetChunkHashesByHeight { height: BlockHeight, chunk_hashes: HashSet<ChunkHash> },
+}
+
+impl std::fmt::Display for StoreRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreRepair::UpdateRefcount { col, key, rc_delta } => {
+                write!(f, "change refcount of {:?} in {} by {}", to_base(key), col, rc_delta)
+            }
+            StoreRepair::SetBlockRefcount { block_hash, refcount } => {
+                write!(f, "set refcount of Block {} to {}", block_hash, refcount)
+            }
+            StoreRepair::SetChunkHashesByHeight { height, chunk_hashes } => {
+                write!(f, "set Chunks of height {} to {:?}", height, chunk_hashes)
+            }
+        }
+    }
+}
+
+/// Validates the store in the background, in steps of `batch_size` entries every `period`, so
+/// that the node is not slowed down noticeably. Findings are exported as metrics.
+pub fn spawn_background_store_validator(
+    me: Option<AccountId>,
+    config: GenesisConfig,
+    runtime_adapter: Arc<dyn RuntimeAdapter>,
+    store: Arc<Store>,
+    period: Duration,
+    batch_size: u64,
+) {
+    std::thread::spawn(move || {
+        // Errors found are not `Send`, so the validator lives on its thread
+        let mut store_validator = StoreValidator::new(me, config, runtime_adapter, store);
+        loop {
+            if let Err(err) = store_validator.validate_step(batch_size) {
+                error!(target: "store", "Background store validation failed: {}", err);
+            }
+            std::thread::sleep(period);
+        }
+    });
+}
+
 #[derive(Debug)]
 pub struct ErrorMessage {
     pub col: String,
@@ -81,7 +151,12 @@ pub struct StoreValidator {
     start_time: Instant,
 
     pub errors: Vec<ErrorMessage>,
+    /// Repairs of the inconsistencies found which can be fixed without losing data.
+    pub repairs: Vec<StoreRepair>,
     tests: u64,
+    /// Whether the validation is done in steps, see `validate_step`.
+    incremental: bool,
+    checkpoint: Option<StoreValidatorCheckpoint>,
 }
 
 impl StoreValidator {
@@ -100,7 +175,10 @@ impl StoreValidator {
             timeout: None,
             start_time: Instant::now(),
             errors: vec![],
+            repairs: vec![],
             tests: 0,
+            incremental: false,
+            checkpoint: None,
         }
     }
     pub fn set_timeout(&mut self, timeout: u64) {
@@ -133,204 +211,14 @@ impl StoreValidator {
         self.tests
     }
     fn process_error<K: std::fmt::Debug>(&mut self, err: StoreValidatorError, key: K, col: DBCol) {
+        if self.incremental {
+            inc_counter_vec(&metrics::STORE_VALIDATOR_ERRORS_TOTAL, &[&to_string(&col)]);
+        }
         self.errors.push(ErrorMessage { key: to_string(&key), col: to_string(&col), err })
     }
     fn validate_col(&mut self, col: DBCol) -> Result<(), StoreValidatorError> {
         for (key, value) in self.store.clone().iter_without_rc_logic(col) {
-            let key_ref = key.as_ref();
-            let value_ref = value.as_ref();
-            match col {
-                DBCol::ColBlockHeader => {
-                    let block_hash = CryptoHash::try_from(key_ref)?;
-                    let header = BlockHeader::try_from_slice(value_ref)?;
-                    // Block Header Hash is valid
-                    self.check(&validate::block_header_hash_validity, &block_hash, &header, col);
-                    // Block Header Height is valid
-                    self.check(&validate::block_header_height_validity, &block_hash, &header, col);
-                    // Block Header can be indexed by Height
-                    self.check(&validate::header_hash_indexed_by_height, &block_hash, &header, col);
-                }
-                DBCol::ColBlock => {
-                    let block_hash = CryptoHash::try_from(key_ref)?;
-                    let block = Block::try_from_slice(value_ref)?;
-                    // Block Hash is valid
-                    self.check(&validate::block_hash_validity, &block_hash, &block, col);
-                    // Block Height is valid
-                    self.check(&validate::block_height_validity, &block_hash, &block, col);
-                    // Block can be indexed by its Height
-                    self.check(&validate::block_indexed_by_height, &block_hash, &block, col);
-                    // Block Header for current Block exists
-                    self.check(&validate::block_header_exists, &block_hash, &block, col);
-                    // Chunks for current Block exist
-                    self.check(&validate::block_chunks_exist, &block_hash, &block, col);
-                    // Chunks for current Block have Height Created not higher than Block Height
-                    self.check(&validate::block_chunks_height_validity, &block_hash, &block, col);
-                    // BlockInfo for current Block exists
-                    self.check(&validate::block_info_exists, &block_hash, &block, col);
-                    // EpochInfo for current Epoch id of Block exists
-                    self.check(&validate::block_epoch_exists, &block_hash, &block, col);
-                    // Increase Block Refcount
-                    self.check(&validate::block_increase_refcount, &block_hash, &block, col);
-                }
-                DBCol::ColBlockHeight => {
-                    let height = BlockHeight::try_from_slice(key_ref)?;
-                    let hash = CryptoHash::try_from(value_ref)?;
-                    // Block on the Canonical Chain is stored properly
-                    self.check(&validate::canonical_header_validity, &height, &hash, col);
-                    // If prev Block exists, it's also on the Canonical Chain and
-                    // there are no Blocks in range (prev_height, height) on the Canonical Chain
-                    self.check(&validate::canonical_prev_block_validity, &height, &hash, col);
-                }
-                DBCol::ColChunks => {
-                    let chunk_hash = ChunkHash::try_from_slice(key_ref)?;
-                    let shard_chunk = ShardChunk::try_from_slice(value_ref)?;
-                    // Chunk Hash is valid
-                    self.check(&validate::chunk_hash_validity, &chunk_hash, &shard_chunk, col);
-                    // Chunk Height Created is not lower than Chunk Tail
-                    self.check(&validate::chunk_tail_validity, &chunk_hash, &shard_chunk, col);
-                    // ShardChunk can be indexed by Height
-                    self.check(
-                        &validate::chunk_indexed_by_height_created,
-                        &chunk_hash,
-                        &shard_chunk,
-                        col,
-                    );
-                    // Check that all Txs in Chunk exist
-                    self.check(&validate::chunk_tx_exists, &chunk_hash, &shard_chunk, col);
-                }
-                DBCol::ColChunkExtra => {
-                    let (block_hash, _) = get_block_shard_id_rev(key_ref)?;
-                    let chunk_extra = ChunkExtra::try_from_slice(value_ref)?;
-                    self.check(&validate::chunk_extra_block_exists, &block_hash, &chunk_extra, col);
-                }
-                DBCol::ColTrieChanges => {
-                    let (block_hash, shard_id) = get_block_shard_id_rev(key_ref)?;
-                    let trie_changes = TrieChanges::try_from_slice(value_ref)?;
-                    // ShardChunk should exist for current TrieChanges
-                    self.check(
-                        &validate::trie_changes_chunk_extra_exists,
-                        &(block_hash, shard_id),
-                        &trie_changes,
-                        col,
-                    );
-                }
-                DBCol::ColChunkHashesByHeight => {
-                    let height = BlockHeight::try_from_slice(key_ref)?;
-                    let chunk_hashes = HashSet::<ChunkHash>::try_from_slice(value_ref)?;
-                    // ShardChunk which can be indexed by Height exists
-                    self.check(&validate::chunk_of_height_exists, &height, &chunk_hashes, col);
-                }
-                DBCol::ColHeaderHashesByHeight => {
-                    let height = BlockHeight::try_from_slice(key_ref)?;
-                    let header_hashes = HashSet::<CryptoHash>::try_from_slice(value_ref)?;
-                    // Headers which can be indexed by Height exists
-                    self.check(
-                        &validate::header_hash_of_height_exists,
-                        &height,
-                        &header_hashes,
-                        col,
-                    );
-                }
-                DBCol::ColOutcomeIds => {
-                    let (block_hash, _) = get_block_shard_id_rev(key_ref)?;
-                    let outcome_ids = Vec::<CryptoHash>::try_from_slice(value_ref)?;
-                    // TransactionResult which can be indexed by Outcome id exists
-                    self.check(
-                        &validate::outcome_by_outcome_id_exists,
-                        &block_hash,
-                        &outcome_ids,
-                        col,
-                    );
-                    // Block which can be indexed by Outcome block_hash exists
-                    self.check(&validate::outcome_id_block_exists, &block_hash, &outcome_ids, col);
-                }
-                DBCol::ColTransactionResult => {
-                    let outcome_id = CryptoHash::try_from_slice(key_ref)?;
-                    let outcomes =
-                        <Vec<ExecutionOutcomeWithIdAndProof>>::try_from_slice(value_ref)?;
-                    // Outcome is reachable in ColOutcomesByBlockHash
-                    self.check(
-                        &validate::outcome_indexed_by_block_hash,
-                        &outcome_id,
-                        &outcomes,
-                        col,
-                    );
-                }
-                DBCol::ColStateDlInfos => {
-                    let block_hash = CryptoHash::try_from(key_ref)?;
-                    let state_sync_info = StateSyncInfo::try_from_slice(value_ref)?;
-                    // StateSyncInfo is valid
-                    self.check(
-                        &validate::state_sync_info_valid,
-                        &block_hash,
-                        &state_sync_info,
-                        col,
-                    );
-                    // Block which can be indexed by StateSyncInfo exists
-                    self.check(
-                        &validate::state_sync_info_block_exists,
-                        &block_hash,
-                        &state_sync_info,
-                        col,
-                    );
-                }
-                DBCol::ColBlockInfo => {
-                    let block_hash = CryptoHash::try_from(key_ref)?;
-                    let block_info = BlockInfo::try_from_slice(value_ref)?;
-                    // Block which can be indexed by BlockInfo exists
-                    self.check(
-                        &validate::block_info_block_header_exists,
-                        &block_hash,
-                        &block_info,
-                        col,
-                    );
-                }
-                DBCol::ColEpochInfo => {
-                    if key_ref != AGGREGATOR_KEY {
-                        let epoch_id = EpochId::try_from_slice(key_ref)?;
-                        let epoch_info = EpochInfo::try_from_slice(value_ref)?;
-                        // Epoch should exist
-                        self.check(&validate::epoch_validity, &epoch_id, &epoch_info, col);
-                    }
-                }
-                DBCol::ColLastBlockWithNewChunk => {
-                    let shard_id = ShardId::try_from_slice(key_ref)?;
-                    let block_hash = CryptoHash::try_from(value_ref)?;
-                    // Block which is stored in ColLastBlockWithNewChunk exists and its ShardChunk is included
-                    self.check(&validate::last_block_chunk_included, &shard_id, &block_hash, col);
-                }
-                DBCol::ColGCCount => {
-                    let col = DBCol::try_from_slice(key_ref)?;
-                    let count = GCCount::try_from_slice(value_ref)?;
-                    self.check(&validate::gc_col_count, &col, &count, col);
-                }
-                DBCol::ColTransactions => {
-                    let (_value, rc) = decode_value_with_rc(value_ref);
-                    let tx_hash = CryptoHash::try_from(key_ref)?;
-                    self.check(&validate::tx_refcount, &tx_hash, &(rc as u64), col);
-                }
-                DBCol::ColReceipts => {
-                    let (_value, rc) = decode_value_with_rc(value_ref);
-                    let receipt_id = CryptoHash::try_from(key_ref)?;
-                    self.check(&validate::receipt_refcount, &receipt_id, &(rc as u64), col);
-                }
-                DBCol::ColBlockRefCount => {
-                    let block_hash = CryptoHash::try_from(key_ref)?;
-                    let refcount = u64::try_from_slice(value_ref)?;
-                    self.check(&validate::block_refcount, &block_hash, &refcount, col);
-                }
-                DBCol::ColStateHeaders => {
-                    let key = StateHeaderKey::try_from_slice(key_ref)?;
-                    let header = ShardStateSyncResponseHeader::try_from_slice(value_ref)?;
-                    self.check(&validate::state_header_block_exists, &key, &header, col);
-                }
-                DBCol::ColStateParts => {
-                    let key = StatePartKey::try_from_slice(key_ref)?;
-                    let part = value_ref.to_vec();
-                    self.check(&validate::state_part_header_exists, &key, &part, col);
-                }
-                _ => {}
-            }
+            self.validate_entry(col, key.as_ref(), value.as_ref())?;
             if let Some(timeout) = self.timeout {
                 if self.start_time.elapsed() > Duration::from_millis(timeout) {
                     return Ok(());
@@ -339,14 +227,228 @@ impl StoreValidator {
         }
         Ok(())
     }
-    pub fn validate(&mut self) {
-        self.start_time = Instant::now();
-
-        // Init checks
+    fn validate_entry(
+        &mut self,
+        col: DBCol,
+        key_ref: &[u8],
+        value_ref: &[u8],
+    ) -> Result<(), StoreValidatorError> {
+        match col {
+            DBCol::ColBlockHeader => {
+                let block_hash = CryptoHash::try_from(key_ref)?;
+                let header = BlockHeader::try_from_slice(value_ref)?;
+                // Block Header Hash is valid
+                self.check(&validate::block_header_hash_validity, &block_hash, &header, col);
+                // Block Header Height is valid
+                self.check(&validate::block_header_height_validity, &block_hash, &header, col);
+                // Block Header can be indexed by Height
+                self.check(&validate::header_hash_indexed_by_height, &block_hash, &header, col);
+            }
+            DBCol::ColBlock => {
+                let block_hash = CryptoHash::try_from(key_ref)?;
+                let block = Block::try_from_slice(value_ref)?;
+                // Block Hash is valid
+                self.check(&validate::block_hash_validity, &block_hash, &block, col);
+                // Block Height is valid
+                self.check(&validate::block_height_validity, &block_hash, &block, col);
+                // Block can be indexed by its Height
+                self.check(&validate::block_indexed_by_height, &block_hash, &block, col);
+                // Block Header for current Block exists
+                self.check(&validate::block_header_exists, &block_hash, &block, col);
+                // Chunks for current Block exist
+                self.check(&validate::block_chunks_exist, &block_hash, &block, col);
+                // Chunks for current Block have Height Created not higher than Block Height
+                self.check(&validate::block_chunks_height_validity, &block_hash, &block, col);
+                // BlockInfo for current Block exists
+                self.check(&validate::block_info_exists, &block_hash, &block, col);
+                // EpochInfo for current Epoch id of Block exists
+                self.check(&validate::block_epoch_exists, &block_hash, &block, col);
+                // Increase Block Refcount
+                self.check(&validate::block_increase_refcount, &block_hash, &block, col);
+            }
+            DBCol::ColBlockHeight => {
+                let height = BlockHeight::try_from_slice(key_ref)?;
+                let hash = CryptoHash::try_from(value_ref)?;
+                // Block on the Canonical Chain is stored properly
+                self.check(&validate::canonical_header_validity, &height, &hash, col);
+                // If prev Block exists, it's also on the Canonical Chain and
+                // there are no Blocks in range (prev_height, height) on the Canonical Chain
+                self.check(&validate::canonical_prev_block_validity, &height, &hash, col);
+            }
+            DBCol::ColChunks => {
+                let chunk_hash = ChunkHash::try_from_slice(key_ref)?;
+                let shard_chunk = ShardChunk::try_from_slice(value_ref)?;
+                // Chunk Hash is valid
+                self.check(&validate::chunk_hash_validity, &chunk_hash, &shard_chunk, col);
+                // Chunk Height Created is not lower than Chunk Tail
+                self.check(&validate::chunk_tail_validity, &chunk_hash, &shard_chunk, col);
+                // ShardChunk can be indexed by Height
+                self.check(
+                    &validate::chunk_indexed_by_height_created,
+                    &chunk_hash,
+                    &shard_chunk,
+                    col,
+                );
+                // Check that all Txs in Chunk exist
+                self.check(&validate::chunk_tx_exists, &chunk_hash, &shard_chunk, col);
+            }
+            DBCol::ColChunkExtra => {
+                let (block_hash, _) = get_block_shard_uid_rev(key_ref)?;
+                let chunk_extra = ChunkExtra::try_from_slice(value_ref)?;
+                self.check(&validate::chunk_extra_block_exists, &block_hash, &chunk_extra, col);
+            }
+            DBCol::ColTrieChanges => {
+                let (block_hash, shard_uid) = get_block_shard_uid_rev(key_ref)?;
+                let trie_changes = TrieChanges::try_from_slice(value_ref)?;
+                // ShardChunk should exist for current TrieChanges
+                self.check(
+                    &validate::trie_changes_chunk_extra_exists,
+                    &(block_hash, shard_uid),
+                    &trie_changes,
+                    col,
+                );
+            }
+            DBCol::ColChunkHashesByHeight => {
+                let height = BlockHeight::try_from_slice(key_ref)?;
+                let chunk_hashes = HashSet::<ChunkHash>::try_from_slice(value_ref)?;
+                // ShardChunk which can be indexed by Height exists
+                self.check(&validate::chunk_of_height_exists, &height, &chunk_hashes, col);
+            }
+            DBCol::ColHeaderHashesByHeight => {
+                let height = BlockHeight::try_from_slice(key_ref)?;
+                let header_hashes = HashSet::<CryptoHash>::try_from_slice(value_ref)?;
+                // Headers which can be indexed by Height exists
+                self.check(&validate::header_hash_of_height_exists, &height, &header_hashes, col);
+            }
+            DBCol::ColOutcomeIds => {
+                let (block_hash, _) = get_block_shard_id_rev(key_ref)?;
+                let outcome_ids = Vec::<CryptoHash>::try_from_slice(value_ref)?;
+                // TransactionResult which can be indexed by Outcome id exists
+                self.check(&validate::outcome_by_outcome_id_exists, &block_hash, &outcome_ids, col);
+                // Block which can be indexed by Outcome block_hash exists
+                self.check(&validate::outcome_id_block_exists, &block_hash, &outcome_ids, col);
+            }
+            DBCol::ColTransactionResult => {
+                let outcome_id = CryptoHash::try_from_slice(key_ref)?;
+                let outcomes = <Vec<ExecutionOutcomeWithIdAndProof>>::try_from_slice(value_ref)?;
+                // Outcome is reachable in ColOutcomesByBlockHash
+                self.check(&validate::outcome_indexed_by_block_hash, &outcome_id, &outcomes, col);
+            }
+            DBCol::ColStateDlInfos => {
+                let block_hash = CryptoHash::try_from(key_ref)?;
+                let state_sync_info = StateSyncInfo::try_from_slice(value_ref)?;
+                // StateSyncInfo is valid
+                self.check(&validate::state_sync_info_valid, &block_hash, &state_sync_info, col);
+                // Block which can be indexed by StateSyncInfo exists
+                self.check(
+                    &validate::state_sync_info_block_exists,
+                    &block_hash,
+                    &state_sync_info,
+                    col,
+                );
+            }
+            DBCol::ColBlockInfo => {
+                let block_hash = CryptoHash::try_from(key_ref)?;
+                let block_info = BlockInfo::try_from_slice(value_ref)?;
+                // Block which can be indexed by BlockInfo exists
+                self.check(
+                    &validate::block_info_block_header_exists,
+                    &block_hash,
+                    &block_info,
+                    col,
+                );
+            }
+            DBCol::ColEpochInfo => {
+                if key_ref != AGGREGATOR_KEY {
+                    let epoch_id = EpochId::try_from_slice(key_ref)?;
+                    let epoch_info = EpochInfo::try_from_slice(value_ref)?;
+                    // Epoch should exist
+                    self.check(&validate::epoch_validity, &epoch_id, &epoch_info, col);
+                }
+            }
+            DBCol::ColLastBlockWithNewChunk => {
+                let shard_id = ShardId::try_from_slice(key_ref)?;
+                let block_hash = CryptoHash::try_from(value_ref)?;
+                // Block which is stored in ColLastBlockWithNewChunk exists and its ShardChunk is included
+                self.check(&validate::last_block_chunk_included, &shard_id, &block_hash, col);
+            }
+            DBCol::ColGCCount => {
+                let col = DBCol::try_from_slice(key_ref)?;
+                let count = GCCount::try_from_slice(value_ref)?;
+                self.check(&validate::gc_col_count, &col, &count, col);
+            }
+            // Refcounts are compared with the references counted in the other columns, which
+            // may change between the steps of an incremental pass
+            DBCol::ColTransactions if !self.incremental => {
+                let (_value, rc) = decode_value_with_rc(value_ref);
+                let tx_hash = CryptoHash::try_from(key_ref)?;
+                self.check(&validate::tx_refcount, &tx_hash, &(rc as u64), col);
+            }
+            DBCol::ColReceipts if !self.incremental => {
+                let (_value, rc) = decode_value_with_rc(value_ref);
+                let receipt_id = CryptoHash::try_from(key_ref)?;
+                self.check(&validate::receipt_refcount, &receipt_id, &(rc as u64), col);
+            }
+            DBCol::ColBlockRefCount if !self.incremental => {
+                let block_hash = CryptoHash::try_from(key_ref)?;
+                let refcount = u64::try_from_slice(value_ref)?;
+                self.check(&validate::block_refcount, &block_hash, &refcount, col);
+            }
+            DBCol::ColStateHeaders => {
+                let key = StateHeaderKey::try_from_slice(key_ref)?;
+                let header = ShardStateSyncResponseHeader::try_from_slice(value_ref)?;
+                self.check(&validate::state_header_block_exists, &key, &header, col);
+            }
+            DBCol::ColStateParts => {
+                let key = StatePartKey::try_from_slice(key_ref)?;
+                let part = value_ref.to_vec();
+                self.check(&validate::state_part_header_exists, &key, &part, col);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    fn validate_init(&mut self) {
         // Check Head-Tail validity and fill cache with their values
         if let Err(e) = validate::head_tail_validity(self) {
             self.process_error(e, "HEAD / HEADER_HEAD / TAIL / CHUNK_TAIL", DBCol::ColBlockMisc)
         }
+        // Check the data kept by GC longer than blocks and count the references it holds
+        if let Err(e) = validate::gc_retained_data_validity(self) {
+            self.process_error(e, "GC_RETAINED_DATA", DBCol::ColGCRetainedData)
+        }
+    }
+    fn validate_final(&mut self) {
+        // Check GC counters
+        if let Err(_) = validate::gc_col_count_final(self) {
+            // TODO #2861
+        }
+        if self.incremental {
+            // Refcounts and blocks lower than Tail are not checked by incremental passes, as Tail
+            // moves between the steps
+            return;
+        }
+        // There is no more than one Block which Height is lower than Tail and not equal to Genesis
+        if let Err(e) = validate::block_height_cmp_tail_final(self) {
+            self.process_error(e, "TAIL", DBCol::ColBlockMisc)
+        }
+        // Check that all refs are counted
+        if let Err(e) = validate::tx_refcount_final(self) {
+            self.process_error(e, "TX_REFCOUNT", DBCol::ColTransactions)
+        }
+        if let Err(e) = validate::receipt_refcount_final(self) {
+            self.process_error(e, "RECEIPT_REFCOUNT", DBCol::ColReceipts)
+        }
+        // Check that all Block Refcounts are counted
+        if let Err(e) = validate::block_refcount_final(self) {
+            self.process_error(e, "BLOCK_REFCOUNT", DBCol::ColBlockRefCount)
+        }
+    }
+    pub fn validate(&mut self) {
+        self.start_time = Instant::now();
+
+        // Init checks
+        self.validate_init();
 
         // Main loop
         for col in DBCol::iter() {
@@ -369,25 +471,146 @@ impl StoreValidator {
         }
 
         // Final checks
-        // There is no more than one Block which Height is lower than Tail and not equal to Genesis
-        if let Err(e) = validate::block_height_cmp_tail_final(self) {
-            self.process_error(e, "TAIL", DBCol::ColBlockMisc)
+        self.validate_final();
+    }
+
+    /// Validates at most `max_entries` entries, continuing the pass from the checkpoint saved by
+    /// the previous step. Returns `true` when the pass is complete, the next step starts a new
+    /// one. Entries may change between the steps, so refcounts, which can only be checked
+    /// against a consistent view of all columns, are skipped.
+    pub fn validate_step(&mut self, max_entries: u64) -> Result<bool, io::Error> {
+        self.incremental = true;
+        let mut checkpoint = match self.checkpoint.take() {
+            Some(checkpoint) => checkpoint,
+            None => {
+                self.start_pass();
+                self.store
+                    .get_ser::<StoreValidatorCheckpoint>(
+                        DBCol::ColBlockMisc,
+                        STORE_VALIDATOR_CHECKPOINT_KEY,
+                    )?
+                    .unwrap_or_default()
+            }
+        };
+        let tests_before = self.tests;
+        let mut entries_left = max_entries;
+        loop {
+            set_gauge(&metrics::STORE_VALIDATOR_COLUMN, checkpoint.col as i64);
+            let store = self.store.clone();
+            let key_from = checkpoint.last_key.clone().unwrap_or_default();
+            // The iterator sees the column as of its creation. Tails only grow and heads only move
+            // forward, so reading tails before and heads after it keeps the checks against them
+            // from failing on entries added or removed since the start of the pass.
+            self.refresh_tails()?;
+            let iter = store.iter_from_without_rc_logic(checkpoint.col, &key_from);
+            self.refresh_heads()?;
+            for (key, value) in iter {
+                if checkpoint.last_key.as_ref().map_or(false, |last_key| &key[..] == &last_key[..])
+                {
+                    continue;
+                }
+                if entries_left == 0 {
+                    break;
+                }
+                entries_left -= 1;
+                if let Err(e) = self.validate_entry(checkpoint.col, &key, &value) {
+                    self.process_error(e, to_string(&key), checkpoint.col)
+                }
+                checkpoint.last_key = Some(key.to_vec());
+            }
+            if entries_left == 0 {
+                break;
+            }
+            match DBCol::iter().skip_while(|col| *col != checkpoint.col).nth(1) {
+                Some(col) => checkpoint = StoreValidatorCheckpoint { col, last_key: None },
+                None => {
+                    self.finish_pass()?;
+                    inc_counter_by(
+                        &metrics::STORE_VALIDATOR_CHECKS_TOTAL,
+                        self.tests - tests_before,
+                    );
+                    return Ok(true);
+                }
+            }
         }
-        // Check GC counters
-        if let Err(_) = validate::gc_col_count_final(self) {
-            // TODO #2861
+        inc_counter_by(&metrics::STORE_VALIDATOR_CHECKS_TOTAL, self.tests - tests_before);
+        let mut store_update = self.store.store_update();
+        store_update.set_ser(DBCol::ColBlockMisc, STORE_VALIDATOR_CHECKPOINT_KEY, &checkpoint)?;
+        store_update.commit()?;
+        self.checkpoint = Some(checkpoint);
+        Ok(false)
+    }
+    fn refresh_tails(&mut self) -> Result<(), io::Error> {
+        if let Some(tail) = self.store.get_ser::<BlockHeight>(DBCol::ColBlockMisc, TAIL_KEY)? {
+            self.inner.tail = tail;
         }
-        // Check that all refs are counted
-        if let Err(e) = validate::tx_refcount_final(self) {
-            self.process_error(e, "TX_REFCOUNT", DBCol::ColTransactions)
+        if let Some(chunk_tail) =
+            self.store.get_ser::<BlockHeight>(DBCol::ColBlockMisc, CHUNK_TAIL_KEY)?
+        {
+            self.inner.chunk_tail = chunk_tail;
         }
-        if let Err(e) = validate::receipt_refcount_final(self) {
-            self.process_error(e, "RECEIPT_REFCOUNT", DBCol::ColReceipts)
+        Ok(())
+    }
+    fn refresh_heads(&mut self) -> Result<(), io::Error> {
+        if let Some(head) = self.store.get_ser::<Tip>(DBCol::ColBlockMisc, HEAD_KEY)? {
+            self.inner.head = head.height;
         }
-        // Check that all Block Refcounts are counted
-        if let Err(e) = validate::block_refcount_final(self) {
-            self.process_error(e, "BLOCK_REFCOUNT", DBCol::ColBlockRefCount)
+        if let Some(header_head) =
+            self.store.get_ser::<Tip>(DBCol::ColBlockMisc, HEADER_HEAD_KEY)?
+        {
+            self.inner.header_head = header_head.height;
+        }
+        Ok(())
+    }
+    fn start_pass(&mut self) {
+        self.inner = StoreValidatorCache::new();
+        self.errors.clear();
+        self.repairs.clear();
+        self.tests = 0;
+        self.validate_init();
+    }
+    fn finish_pass(&mut self) -> Result<(), io::Error> {
+        self.validate_final();
+        set_gauge(&metrics::STORE_VALIDATOR_LAST_PASS_ERRORS, self.errors.len() as i64);
+        inc_counter(&metrics::STORE_VALIDATOR_PASSES_TOTAL);
+        if !self.errors.is_empty() {
+            warn!(target: "store", "Store validator found {} inconsistencies: {:?}", self.errors.len(), self.errors);
+        }
+        let mut store_update = self.store.store_update();
+        store_update.delete(DBCol::ColBlockMisc, STORE_VALIDATOR_CHECKPOINT_KEY);
+        store_update.commit()
+    }
+
+    /// Applies the repairs suggested by the last validation. Must not be called while the node is
+    /// running, as the repairs are computed from the state of the store at the time of validation.
+    pub fn apply_repairs(&mut self) -> Result<usize, io::Error> {
+        let mut store_update = self.store.store_update();
+        for repair in self.repairs.iter() {
+            match repair {
+                StoreRepair::UpdateRefcount { col, key, rc_delta } => {
+                    // Increasing refcount requires the value, which is not needed for decreasing
+                    let value = if *rc_delta > 0 {
+                        self.store.get(*col, key)?.unwrap_or_default()
+                    } else {
+                        vec![]
+                    };
+                    store_update.update_refcount(*col, key, &value, *rc_delta);
+                }
+                StoreRepair::SetBlockRefcount { block_hash, refcount } => {
+                    store_update.set_ser(DBCol::ColBlockRefCount, block_hash.as_ref(), refcount)?;
+                }
+                StoreRepair::SetChunkHashesByHeight { height, chunk_hashes } => {
+                    let key = index_to_bytes(*height);
+                    if chunk_hashes.is_empty() {
+                        store_update.delete(DBCol::ColChunkHashesByHeight, &key);
+                    } else {
+                        store_update.set_ser(DBCol::ColChunkHashesByHeight, &key, chunk_hashes)?;
+                    }
+                }
+            }
         }
+        store_update.commit()?;
+        Ok(std::mem::take(&mut self.repairs).len())
     }
 
     fn check<K: std::fmt::Debug, V>(
@@ -408,8 +631,8 @@ impl StoreValidator {
 mod tests {
     use near_store::test_utils::create_test_store;
 
-    use crate::test_utils::KeyValueRuntime;
-    use crate::{Chain, ChainGenesis, DoomslugThresholdMode};
+    use crate::test_utils::{setup, KeyValueRuntime};
+    use crate::{Chain, ChainGenesis, DoomslugThresholdMode, Provenance};
 
     use super::*;
 
@@ -480,6 +703,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_validate_in_steps() {
+        let (_chain, mut sv) = init();
+        assert!(!sv.validate_step(1).unwrap());
+        assert!(sv.checkpoint.is_some());
+        while !sv.validate_step(1).unwrap() {}
+        assert!(sv.checkpoint.is_none());
+        assert!(sv
+            .store
+            .get(DBCol::ColBlockMisc, STORE_VALIDATOR_CHECKPOINT_KEY)
+            .unwrap()
+            .is_none());
+        assert!(sv.tests_done() > 0);
+        assert!(sv.errors.is_empty(), "{:?}", sv.errors);
+    }
+
+    #[test]
+    fn test_validate_in_steps_while_adding_blocks() {
+        let (mut chain, runtime_adapter, signer) = setup();
+        let mut genesis = GenesisConfig::default();
+        genesis.genesis_height = 0;
+        let mut sv =
+            StoreValidator::new(None, genesis, runtime_adapter, chain.store().owned_store());
+        // Blocks, headers and chunks added after the pass starts are above the head and header
+        // head read at its start
+        for _ in 0..20 {
+            sv.validate_step(10).unwrap();
+            let prev_hash = chain.head().unwrap().last_block_hash;
+            let block = Block::empty(chain.get_block(&prev_hash).unwrap(), &*signer);
+            chain
+                .process_block(&None, block, Provenance::PRODUCED, |_| {}, |_| {}, |_| {})
+                .unwrap();
+        }
+        while !sv.validate_step(10).unwrap() {}
+        assert!(sv.tests_done() > 0);
+        assert!(sv.errors.is_empty(), "{:?}", sv.errors);
+    }
+
+    #[test]
+    fn test_repair_chunk_hashes_by_height() {
+        let (chain, mut sv) = init();
+        let store = chain.store().owned_store();
+        let mut store_update = store.store_update();
+        let chunk_hashes: HashSet<ChunkHash> =
+            vec![ChunkHash(CryptoHash::default())].into_iter().collect();
+        store_update
+            .set_ser(DBCol::ColChunkHashesByHeight, &index_to_bytes(1), &chunk_hashes)
+            .unwrap();
+        store_update.commit().unwrap();
+        sv.validate();
+        assert!(sv.is_failed());
+        assert_eq!(
+            sv.repairs,
+            vec![StoreRepair::SetChunkHashesByHeight { height: 1, chunk_hashes: HashSet::new() }]
+        );
+        assert_eq!(sv.apply_repairs().unwrap(), 1);
+        assert!(store.get(DBCol::ColChunkHashesByHeight, &index_to_bytes(1)).unwrap().is_none());
+    }
+
     #[test]
     fn test_validation_failed() {
         let (_chain, mut sv) = init();
@@ -1,12 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
-use borsh::BorshSerialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use thiserror::Error;
 
 use near_primitives::block::{Block, BlockHeader, Tip};
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::{ChunkHash, ShardChunk, StateSyncInfo};
 use near_primitives::syncing::{
     get_num_state_parts, ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey,
@@ -14,14 +16,18 @@ use near_primitives::syncing::{
 use near_primitives::transaction::{ExecutionOutcomeWithIdAndProof, SignedTransaction};
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{BlockHeight, EpochId, ShardId};
-use near_primitives::utils::{get_block_shard_id, index_to_bytes};
+use near_primitives::utils::{
+    get_block_shard_id, get_block_shard_id_rev, get_block_shard_uid, index_to_bytes,
+};
 use near_store::{
     ColBlock, ColBlockHeader, ColBlockHeight, ColBlockInfo, ColBlockMisc, ColBlockPerHeight,
-    ColChunkExtra, ColChunkHashesByHeight, ColChunks, ColHeaderHashesByHeight, ColOutcomeIds,
-    ColStateHeaders, ColTransactionResult, DBCol, TrieChanges, TrieIterator, CHUNK_TAIL_KEY,
-    FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, NUM_COLS, SHOULD_COL_GC, TAIL_KEY,
+    ColChunkExtra, ColChunkHashesByHeight, ColChunks, ColGCRetainedData, ColHeaderHashesByHeight,
+    ColOutcomeIds, ColStateHeaders, ColTransactionResult, DBCol, TrieChanges, TrieIterator,
+    CHUNK_TAIL_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, NUM_COLS, SHOULD_COL_GC, TAIL_KEY,
 };
 
+use crate::store::GCDataCategory;
+use crate::store_validator::StoreRepair;
 use crate::StoreValidator;
 
 #[derive(Error, Debug)]
@@ -164,6 +170,48 @@ pub(crate) fn head_tail_validity(sv: &mut StoreValidator) -> Result<(), StoreVal
     Ok(())
 }
 
+pub(crate) fn gc_retained_data_validity(
+    sv: &mut StoreValidator,
+) -> Result<(), StoreValidatorError> {
+    for (key, value) in sv.store.clone().iter(ColGCRetainedData) {
+        let (category, height) = match GCDataCategory::parse_retained_data_key(&key) {
+            Some(parsed_key) => parsed_key,
+            None => err!("Invalid key {:?} of the data kept by GC", key),
+        };
+        let retained_tail = unwrap_or_err!(
+            sv.store.get_ser::<BlockHeight>(ColBlockMisc, &category.retained_tail_key()),
+            "Can't get the tail of the kept {}",
+            category
+        );
+        if retained_tail.map_or(true, |retained_tail| retained_tail > height) {
+            err!("{} at height {} are kept below their tail {:?}", category, height, retained_tail);
+        }
+        let data_keys = Vec::<Vec<u8>>::try_from_slice(&value)?;
+        for data_key in data_keys {
+            match category {
+                GCDataCategory::Transactions => {
+                    let tx_hash = CryptoHash::try_from(data_key.as_ref())?;
+                    sv.inner.tx_refcount.entry(tx_hash).and_modify(|x| *x += 1).or_insert(1);
+                }
+                GCDataCategory::Receipts => {
+                    let receipt_id = CryptoHash::try_from(data_key.as_ref())?;
+                    sv.inner
+                        .receipt_refcount
+                        .entry(receipt_id)
+                        .and_modify(|x| *x += 1)
+                        .or_insert(1);
+                }
+                GCDataCategory::Outcomes => {
+                    let (block_hash, _) = get_block_shard_id_rev(&data_key)?;
+                    sv.inner.gc_retained_outcome_blocks.insert(block_hash);
+                }
+                GCDataCategory::Blocks => err!("Blocks are kept by GC at height {}", height),
+            }
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn block_header_hash_validity(
     _sv: &mut StoreValidator,
     block_hash: &CryptoHash,
@@ -384,10 +432,17 @@ pub(crate) fn block_chunks_exist(
                         chunk_header
                     );
                     if cares_about_shard {
-                        let block_shard_id =
-                            get_block_shard_id(block.hash(), chunk_header.shard_id());
+                        let shard_uid = unwrap_or_err!(
+                            sv.runtime_adapter.shard_id_to_uid(
+                                chunk_header.shard_id(),
+                                block.header().epoch_id()
+                            ),
+                            "Can't get shard uid for chunk {:?}",
+                            chunk_header
+                        );
+                        let block_shard_uid = get_block_shard_uid(block.hash(), &shard_uid);
                         unwrap_or_err_db!(
-                            sv.store.get_ser::<ChunkExtra>(ColChunkExtra, block_shard_id.as_ref()),
+                            sv.store.get_ser::<ChunkExtra>(ColChunkExtra, block_shard_uid.as_ref()),
                             "Can't get chunk extra for chunk {:?} from storage",
                             chunk_header
                         );
@@ -518,7 +573,7 @@ pub(crate) fn canonical_prev_block_validity(
 
 pub(crate) fn trie_changes_chunk_extra_exists(
     sv: &mut StoreValidator,
-    (block_hash, shard_id): &(CryptoHash, ShardId),
+    (block_hash, shard_uid): &(CryptoHash, ShardUId),
     trie_changes: &TrieChanges,
 ) -> Result<(), StoreValidatorError> {
     let new_root = trie_changes.new_root;
@@ -527,6 +582,30 @@ pub(crate) fn trie_changes_chunk_extra_exists(
         sv.store.get_ser::<Block>(ColBlock, block_hash.as_ref()),
         "Can't get Block from DB"
     );
+    let shard_layout = unwrap_or_err!(
+        sv.runtime_adapter.get_shard_layout(block.header().epoch_id()),
+        "Can't get shard layout for Block {:?}",
+        block_hash
+    );
+    if shard_uid.version != shard_layout.version() {
+        // Trie changes of a child shard built while splitting states for the next shard layout.
+        // There is no chunk for such a shard, but Chunk Extra should be saved with the same root.
+        let chunk_extra = unwrap_or_err_db!(
+            sv.store
+                .get_ser::<ChunkExtra>(ColChunkExtra, &get_block_shard_uid(block_hash, shard_uid)),
+            "Can't get Chunk Extra from storage with key {:?} {:?}",
+            block_hash,
+            shard_uid
+        );
+        check_discrepancy!(
+            chunk_extra.state_root(),
+            &new_root,
+            "State Root discrepancy, split shard {:?}",
+            shard_uid
+        );
+        return Ok(());
+    }
+    let shard_id = &shard_uid.shard_id();
     // 2. There should be ShardChunk with ShardId `shard_id`
     for chunk_header in block.chunks().iter() {
         if chunk_header.shard_id() == *shard_id {
@@ -541,7 +620,7 @@ pub(crate) fn trie_changes_chunk_extra_exists(
             let chunk_extra = unwrap_or_err_db!(
                 sv.store.get_ser::<ChunkExtra>(
                     ColChunkExtra,
-                    &get_block_shard_id(block_hash, *shard_id)
+                    &get_block_shard_uid(block_hash, shard_uid)
                 ),
                 "Can't get Chunk Extra from storage with key {:?} {:?}",
                 block_hash,
@@ -569,7 +648,7 @@ pub(crate) fn trie_changes_chunk_extra_exists(
             }
             if let Ok(Some(prev_chunk_extra)) = sv.store.get_ser::<ChunkExtra>(
                 ColChunkExtra,
-                &get_block_shard_id(block.header().prev_hash(), *shard_id),
+                &get_block_shard_uid(block.header().prev_hash(), shard_uid),
             ) {
                 check_discrepancy!(
                     prev_chunk_extra.state_root(),
@@ -597,18 +676,31 @@ pub(crate) fn chunk_of_height_exists(
     height: &BlockHeight,
     chunk_hashes: &HashSet<ChunkHash>,
 ) -> Result<(), StoreValidatorError> {
+    let mut existing_chunk_hashes = HashSet::new();
     for chunk_hash in chunk_hashes {
-        let shard_chunk = unwrap_or_err_db!(
-            sv.store.get_ser::<ShardChunk>(ColChunks, chunk_hash.as_ref()),
-            "Can't get Chunk from storage with ChunkHash {:?}",
-            chunk_hash
-        );
-        check_discrepancy!(
-            shard_chunk.height_created(),
-            *height,
-            "Invalid ShardChunk {:?} stored",
-            shard_chunk
-        );
+        if let Some(shard_chunk) = sv.store.get_ser::<ShardChunk>(ColChunks, chunk_hash.as_ref())? {
+            check_discrepancy!(
+                shard_chunk.height_created(),
+                *height,
+                "Invalid ShardChunk {:?} stored",
+                shard_chunk
+            );
+            existing_chunk_hashes.insert(chunk_hash.clone());
+        }
+    }
+    if existing_chunk_hashes.len() != chunk_hashes.len() {
+        let missing_chunk_hashes: Vec<_> =
+            chunk_hashes.difference(&existing_chunk_hashes).collect();
+        let reason =
+            format!("Can't get Chunks from storage with ChunkHashes {:?}", missing_chunk_hashes);
+        sv.repairs.push(StoreRepair::SetChunkHashesByHeight {
+            height: *height,
+            chunk_hashes: existing_chunk_hashes,
+        });
+        return Err(StoreValidatorError::DBNotFound {
+            func_name: get_parent_function_name!(),
+            reason,
+        });
     }
     Ok(())
 }
@@ -655,6 +747,10 @@ pub(crate) fn outcome_id_block_exists(
     block_hash: &CryptoHash,
     _outcome_ids: &Vec<CryptoHash>,
 ) -> Result<(), StoreValidatorError> {
+    if sv.inner.gc_retained_outcome_blocks.contains(block_hash) {
+        // Outcomes are kept longer than the Block
+        return Ok(());
+    }
     unwrap_or_err_db!(
         sv.store.get_ser::<Block>(ColBlock, block_hash.as_ref()),
         "Can't get Block from DB"
@@ -668,6 +764,10 @@ pub(crate) fn outcome_indexed_by_block_hash(
     outcomes: &Vec<ExecutionOutcomeWithIdAndProof>,
 ) -> Result<(), StoreValidatorError> {
     for outcome in outcomes {
+        if sv.inner.gc_retained_outcome_blocks.contains(&outcome.block_hash) {
+            // Outcomes are kept longer than the Block
+            continue;
+        }
         let block = unwrap_or_err_db!(
             sv.store.get_ser::<Block>(ColBlock, outcome.block_hash.as_ref()),
             "Can't get Block {} from DB",
@@ -816,6 +916,11 @@ pub(crate) fn tx_refcount(
 ) -> Result<(), StoreValidatorError> {
     let expected = sv.inner.tx_refcount.get(tx_hash).map(|&rc| rc).unwrap_or_default();
     if *refcount != expected {
+        sv.repairs.push(StoreRepair::UpdateRefcount {
+            col: DBCol::ColTransactions,
+            key: tx_hash.as_ref().to_vec(),
+            rc_delta: expected as i64 - *refcount as i64,
+        });
         err!("Invalid tx refcount, expected {:?}, found {:?}", expected, refcount)
     } else {
         sv.inner.tx_refcount.remove(tx_hash);
@@ -830,6 +935,11 @@ pub(crate) fn receipt_refcount(
 ) -> Result<(), StoreValidatorError> {
     let expected = sv.inner.receipt_refcount.get(receipt_id).map(|&rc| rc).unwrap_or_default();
     if *refcount != expected {
+        sv.repairs.push(StoreRepair::UpdateRefcount {
+            col: DBCol::ColReceipts,
+            key: receipt_id.as_ref().to_vec(),
+            rc_delta: expected as i64 - *refcount as i64,
+        });
         err!("Invalid receipt refcount, expected {:?}, found {:?}", expected, refcount)
     } else {
         sv.inner.receipt_refcount.remove(receipt_id);
@@ -844,6 +954,8 @@ pub(crate) fn block_refcount(
 ) -> Result<(), StoreValidatorError> {
     if let Some(found) = sv.inner.block_refcount.get(block_hash) {
         if refcount != found {
+            sv.repairs
+                .push(StoreRepair::SetBlockRefcount { block_hash: *block_hash, refcount: *found });
             err!("Invalid Block Refcount, expected {:?}, found {:?}", refcount, found)
         } else {
             sv.inner.block_refcount.remove(block_hash);
@@ -957,6 +1069,17 @@ pub(crate) fn receipt_refcount_final(sv: &mut StoreValidator) -> Result<(), Stor
 }
 
 pub(crate) fn block_refcount_final(sv: &mut StoreValidator) -> Result<(), StoreValidatorError> {
+    for (block_hash, refcount) in sv.inner.block_refcount.iter() {
+        // Previous Block of the Tail is already garbage collected
+        if sv.store.exists(ColBlock, block_hash.as_ref())?
+            && !sv.store.exists(DBCol::ColBlockRefCount, block_hash.as_ref())?
+        {
+            sv.repairs.push(StoreRepair::SetBlockRefcount {
+                block_hash: *block_hash,
+                refcount: *refcount,
+            });
+        }
+    }
     if sv.inner.block_refcount.len() > 1 {
         let len = sv.inner.block_refcount.len();
         for block_refcount in sv.inner.block_refcount.iter() {
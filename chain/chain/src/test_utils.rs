@@ -17,8 +17,9 @@ use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
+use near_primitives::receipt::{ActionReceipt, CongestionInfo, Receipt, ReceiptEnum};
 use near_primitives::serialize::to_base;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::transaction::{
     Action, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus,
@@ -32,19 +33,20 @@ use near_primitives::types::{
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
-    AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, DelayedReceiptsView,
+    EpochValidatorInfo, QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
-    ColBlockHeader, PartialStorage, ShardTries, Store, StoreUpdate, Trie, TrieChanges,
-    WrappedTrieChanges,
+    ColBlockHeader, PartialStorage, ShardTries, StateChangesForSplitStates, Store, StoreUpdate,
+    Trie, TrieChanges, WrappedTrieChanges,
 };
 
-use crate::chain::{Chain, NUM_EPOCHS_TO_KEEP_STORE_DATA};
+use crate::chain::Chain;
 use crate::store::ChainStoreAccess;
 use crate::types::{
-    ApplyTransactionResult, BlockHeaderInfo, ChainGenesis, ValidatorInfoIdentifier,
+    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ChainGenesis,
+    ValidatorInfoIdentifier,
 };
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use crate::Doomslug;
@@ -296,7 +298,7 @@ impl KeyValueRuntime {
 
 impl RuntimeAdapter for KeyValueRuntime {
     fn genesis_state(&self) -> (Arc<Store>, Vec<StateRoot>) {
-        (self.store.clone(), ((0..self.num_shards()).map(|_| StateRoot::default()).collect()))
+        (self.store.clone(), ((0..self.num_shards).map(|_| StateRoot::default()).collect()))
     }
 
     fn get_tries(&self) -> ShardTries {
@@ -311,6 +313,15 @@ impl RuntimeAdapter for KeyValueRuntime {
         self.tries.get_view_trie_for_shard(shard_id)
     }
 
+    fn prefetch_state(
+        &self,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+        _transactions: &[SignedTransaction],
+        _receipts: &[Receipt],
+    ) {
+    }
+
     fn verify_block_vrf(
         &self,
         _epoch_id: &EpochId,
@@ -446,17 +457,17 @@ impl RuntimeAdapter for KeyValueRuntime {
         shard_id: ShardId,
     ) -> Result<AccountId, Error> {
         let validators = &self.validators[self.get_valset_for_epoch(epoch_id)?];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         let delta = ((shard_id + height + 1) % validators_per_shard) as usize;
         Ok(validators[offset + delta].account_id().clone())
     }
 
-    fn num_shards(&self) -> ShardId {
-        self.num_shards
+    fn num_shards(&self, _epoch_id: &EpochId) -> Result<NumShards, Error> {
+        Ok(self.num_shards)
     }
 
     fn num_total_parts(&self) -> usize {
@@ -473,8 +484,31 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
     }
 
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId {
-        account_id_to_shard_id(account_id, self.num_shards())
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        _epoch_id: &EpochId,
+    ) -> Result<ShardId, Error> {
+        Ok(account_id_to_shard_id(account_id, self.num_shards))
+    }
+
+    fn get_shard_layout(&self, _epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        Ok(ShardLayout::v0(self.num_shards))
+    }
+
+    fn will_shard_layout_change_next_epoch(
+        &self,
+        _parent_hash: &CryptoHash,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn get_prev_shard_ids(
+        &self,
+        _prev_hash: &CryptoHash,
+        shard_ids: Vec<ShardId>,
+    ) -> Result<Vec<ShardId>, Error> {
+        Ok(shard_ids)
     }
 
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<AccountId, Error> {
@@ -497,10 +531,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
         let validators = &self.validators[epoch_valset.1];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         assert!(offset + validators_per_shard as usize <= validators.len());
         if let Some(account_id) = account_id {
@@ -525,10 +559,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         //    the calling function.
         let epoch_valset = self.get_epoch_and_valset(*parent_hash).unwrap();
         let validators = &self.validators[(epoch_valset.1 + 1) % self.validators.len()];
-        assert_eq!((validators.len() as u64) % self.num_shards(), 0);
+        assert_eq!((validators.len() as u64) % self.num_shards, 0);
         assert_eq!(0, validators.len() as u64 % self.validator_groups);
         let validators_per_shard = validators.len() as ShardId / self.validator_groups;
-        let coef = validators.len() as ShardId / self.num_shards();
+        let coef = validators.len() as ShardId / self.num_shards;
         let offset = (shard_id * coef / validators_per_shard * validators_per_shard) as usize;
         if let Some(account_id) = account_id {
             for validator in validators[offset..offset + (validators_per_shard as usize)].iter() {
@@ -604,6 +638,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         _gas_limit: Gas,
+        _congested_shards: &[ShardId],
         _challenges: &ChallengesResult,
         _random_seed: CryptoHash,
         generate_storage_proof: bool,
@@ -621,7 +656,7 @@ impl RuntimeAdapter for KeyValueRuntime {
 
         for receipt in receipts.iter() {
             if let ReceiptEnum::Action(action) = &receipt.receipt {
-                assert_eq!(self.account_id_to_shard_id(&receipt.receiver_id), shard_id);
+                assert_eq!(account_id_to_shard_id(&receipt.receiver_id, self.num_shards), shard_id);
                 if !state.receipt_nonces.contains(&receipt.receipt_id) {
                     state.receipt_nonces.insert(receipt.receipt_id);
                     if let Action::Transfer(TransferAction { deposit }) = action.actions[0] {
@@ -642,7 +677,10 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
 
         for transaction in transactions {
-            assert_eq!(self.account_id_to_shard_id(&transaction.transaction.signer_id), shard_id);
+            assert_eq!(
+                account_id_to_shard_id(&transaction.transaction.signer_id, self.num_shards),
+                shard_id
+            );
             if transaction.transaction.actions.is_empty() {
                 continue;
             }
@@ -682,7 +720,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         for (hash, from, to, amount, nonce) in balance_transfers {
             let mut good_to_go = false;
 
-            if self.account_id_to_shard_id(&from) != shard_id {
+            if account_id_to_shard_id(&from, self.num_shards) != shard_id {
                 // This is a receipt, was already debited
                 good_to_go = true;
             } else if let Some(balance) = state.amounts.get(&from) {
@@ -694,7 +732,8 @@ impl RuntimeAdapter for KeyValueRuntime {
             }
 
             if good_to_go {
-                let new_receipt_hashes = if self.account_id_to_shard_id(&to) == shard_id {
+                let new_receipt_hashes = if account_id_to_shard_id(&to, self.num_shards) == shard_id
+                {
                     state.amounts.insert(to.clone(), state.amounts.get(&to).unwrap_or(&0) + amount);
                     vec![]
                 } else {
@@ -714,7 +753,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                     };
                     let receipt_hash = receipt.get_hash();
                     new_receipts
-                        .entry(self.account_id_to_shard_id(&receipt.receiver_id))
+                        .entry(account_id_to_shard_id(&receipt.receiver_id, self.num_shards))
                         .or_insert_with(|| vec![])
                         .push(receipt);
                     vec![receipt_hash]
@@ -759,7 +798,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(ApplyTransactionResult {
             trie_changes: WrappedTrieChanges::new(
                 self.get_tries(),
-                shard_id,
+                ShardUId::from_shard_id_and_layout(shard_id, &ShardLayout::v0(self.num_shards)),
                 TrieChanges::empty(state_root),
                 Default::default(),
                 block_hash.clone(),
@@ -771,6 +810,8 @@ impl RuntimeAdapter for KeyValueRuntime {
             total_gas_burnt: 0,
             total_balance_burnt: 0,
             proof: None,
+            congestion_info: CongestionInfo::default(),
+            total_priority_fees: 0,
         })
     }
 
@@ -788,6 +829,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _last_validator_proposals: ValidatorStakeIter,
         _gas_price: Balance,
         _gas_limit: Gas,
+        _congested_shards: &[ShardId],
         _challenges: &ChallengesResult,
         _random_value: CryptoHash,
         _is_new_chunk: bool,
@@ -806,6 +848,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         block_hash: &CryptoHash,
         _epoch_id: &EpochId,
         request: &QueryRequest,
+        _include_proof: bool,
     ) -> Result<QueryResponse, near_chain_primitives::error::QueryError> {
         match request {
             QueryRequest::ViewAccount { account_id, .. } => Ok(QueryResponse {
@@ -823,6 +866,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 ),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
             QueryRequest::ViewCode { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::ViewCode(ContractCodeView {
@@ -831,6 +875,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 }),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
             QueryRequest::ViewAccessKeyList { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::AccessKeyList(AccessKeyList {
@@ -841,11 +886,13 @@ impl RuntimeAdapter for KeyValueRuntime {
                 }),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
             QueryRequest::ViewAccessKey { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::AccessKey(AccessKey::full_access().into()),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
             QueryRequest::ViewState { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::ViewState(ViewStateResult {
@@ -854,6 +901,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 }),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
             QueryRequest::CallFunction { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::CallResult(CallResult {
@@ -862,10 +910,28 @@ impl RuntimeAdapter for KeyValueRuntime {
                 }),
                 block_height,
                 block_hash: *block_hash,
+                proof: None,
             }),
         }
     }
 
+    fn view_delayed_receipts(
+        &self,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+        _from_index: Option<u64>,
+        _limit: u64,
+    ) -> Result<DelayedReceiptsView, Error> {
+        // Receipts are never delayed by this runtime.
+        Ok(DelayedReceiptsView {
+            first_index: 0,
+            next_available_index: 0,
+            length: 0,
+            total_prepaid_gas: Some(0),
+            receipts: vec![],
+        })
+    }
+
     fn obtain_state_part(
         &self,
         _shard_id: ShardId,
@@ -976,14 +1042,18 @@ impl RuntimeAdapter for KeyValueRuntime {
         }
     }
 
-    fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight {
+    fn get_gc_stop_height_for_num_epochs(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+    ) -> BlockHeight {
         if !self.no_gc {
             let block_height = self
                 .get_block_header(block_hash)
                 .unwrap_or_default()
                 .map(|h| h.height())
                 .unwrap_or_default();
-            block_height.saturating_sub(NUM_EPOCHS_TO_KEEP_STORE_DATA * self.epoch_length)
+            block_height.saturating_sub(num_epochs * self.epoch_length)
         } else {
             0
         }
@@ -1118,6 +1188,25 @@ impl RuntimeAdapter for KeyValueRuntime {
             }
         }
     }
+
+    fn build_state_for_split_shards(
+        &self,
+        _shard_uid: ShardUId,
+        _state_root: &StateRoot,
+        _next_epoch_shard_layout: &ShardLayout,
+    ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
+        unreachable!("the shard layout never changes in KeyValueRuntime");
+    }
+
+    fn apply_update_to_split_states(
+        &self,
+        _block_hash: &CryptoHash,
+        _state_roots: HashMap<ShardUId, StateRoot>,
+        _next_epoch_shard_layout: &ShardLayout,
+        _state_changes: StateChangesForSplitStates,
+    ) -> Result<Vec<ApplySplitStateResult>, Error> {
+        unreachable!("the shard layout never changes in KeyValueRuntime");
+    }
 }
 
 pub fn setup() -> (Chain, Arc<KeyValueRuntime>, Arc<InMemoryValidatorSigner>) {
@@ -1323,21 +1412,21 @@ mod test {
     use near_primitives::hash::{hash, CryptoHash};
     use near_primitives::receipt::Receipt;
     use near_primitives::sharding::ReceiptList;
-    use near_primitives::types::{AccountId, NumShards};
+    use near_primitives::types::{AccountId, EpochId, NumShards};
     use near_store::test_utils::create_test_store;
 
     use crate::RuntimeAdapter;
 
-    use super::KeyValueRuntime;
+    use super::{account_id_to_shard_id, KeyValueRuntime};
 
     impl KeyValueRuntime {
         fn naive_build_receipt_hashes(&self, receipts: &[Receipt]) -> Vec<CryptoHash> {
             let mut receipts_hashes = vec![];
-            for shard_id in 0..self.num_shards() {
+            for shard_id in 0..self.num_shards {
                 let shard_receipts: Vec<Receipt> = receipts
                     .iter()
                     .filter(|&receipt| {
-                        self.account_id_to_shard_id(&receipt.receiver_id) == shard_id
+                        account_id_to_shard_id(&receipt.receiver_id, self.num_shards) == shard_id
                     })
                     .cloned()
                     .collect();
@@ -1374,7 +1463,8 @@ mod test {
         let naive_result = runtime_adapter.naive_build_receipt_hashes(&receipts);
         let naive_duration = start.elapsed();
         let start = Instant::now();
-        let prod_result = runtime_adapter.build_receipts_hashes(&receipts);
+        let prod_result =
+            runtime_adapter.build_receipts_hashes(&receipts, &EpochId::default()).unwrap();
         let prod_duration = start.elapsed();
         assert_eq!(naive_result, prod_result);
         // production implementation is at least 50% faster
@@ -8,7 +8,7 @@ use num_rational::Rational;
 use serde::Serialize;
 
 use near_chain_configs::{GenesisConfig, ProtocolConfig};
-use near_chain_primitives::Error;
+use near_chain_primitives::{Error, ErrorKind};
 use near_crypto::Signature;
 use near_pool::types::PoolIterator;
 pub use near_primitives::block::{Block, BlockHeader, Tip};
@@ -19,20 +19,26 @@ use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
-use near_primitives::receipt::{Receipt, ReceiptResult};
+use near_primitives::receipt::{CongestionInfo, Receipt, ReceiptResult};
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
 use near_primitives::sharding::{ChunkHash, ReceiptList, ShardChunkHeader};
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
-    NumBlocks, ShardId, StateRoot, StateRootNode,
+    NumBlocks, NumShards, ShardId, StateRoot, StateRootNode,
 };
 use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
-use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
+use near_primitives::views::{
+    DelayedReceiptsView, EpochValidatorInfo, QueryRequest, QueryResponse,
+};
+use near_store::{
+    PartialStorage, ShardTries, StateChangesForSplitStates, Store, StoreUpdate, Trie,
+    WrappedTrieChanges,
+};
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use crate::DoomslugThresholdMode;
@@ -87,6 +93,16 @@ pub struct ApplyTransactionResult {
     pub total_gas_burnt: Gas,
     pub total_balance_burnt: Balance,
     pub proof: Option<PartialStorage>,
+    pub congestion_info: CongestionInfo,
+    /// Priority fees of the transactions, included in `total_balance_burnt`.
+    pub total_priority_fees: Balance,
+}
+
+/// Result of applying the state changes of a parent shard chunk to one of the split shards.
+pub struct ApplySplitStateResult {
+    pub shard_uid: ShardUId,
+    pub trie_changes: WrappedTrieChanges,
+    pub new_root: StateRoot,
 }
 
 impl ApplyTransactionResult {
@@ -116,6 +132,7 @@ pub struct BlockHeaderInfo {
     pub slashed_validators: Vec<SlashedValidator>,
     pub chunk_mask: Vec<bool>,
     pub total_supply: Balance,
+    pub priority_fees: Balance,
     pub latest_protocol_version: ProtocolVersion,
     pub timestamp_nanosec: u64,
 }
@@ -133,6 +150,7 @@ impl BlockHeaderInfo {
             slashed_validators: vec![],
             chunk_mask: header.chunk_mask().to_vec(),
             total_supply: header.total_supply(),
+            priority_fees: header.priority_fees(),
             latest_protocol_version: header.latest_protocol_version(),
             timestamp_nanosec: header.raw_timestamp(),
         }
@@ -249,6 +267,16 @@ pub trait RuntimeAdapter: Send + Sync {
     /// Returns trie with view cache
     fn get_view_trie_for_shard(&self, shard_id: ShardId) -> Trie;
 
+    /// Starts loading the parts of the state which the transactions and the receipts are going
+    /// to read into the trie cache of the shard in the background.
+    fn prefetch_state(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        transactions: &[SignedTransaction],
+        receipts: &[Receipt],
+    );
+
     fn verify_block_vrf(
         &self,
         epoch_id: &EpochId,
@@ -400,15 +428,75 @@ pub trait RuntimeAdapter: Send + Sync {
         account_id: &AccountId,
     ) -> Result<(ValidatorStake, bool), Error>;
 
-    /// Get current number of shards.
-    fn num_shards(&self) -> ShardId;
+    /// Get number of shards in the given epoch.
+    fn num_shards(&self, epoch_id: &EpochId) -> Result<NumShards, Error> {
+        Ok(self.get_shard_layout(epoch_id)?.num_shards())
+    }
 
     fn num_total_parts(&self) -> usize;
 
     fn num_data_parts(&self) -> usize;
 
-    /// Account Id to Shard Id mapping, given current number of shards.
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId;
+    /// Account Id to Shard Id mapping, given the shard layout of the epoch.
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        epoch_id: &EpochId,
+    ) -> Result<ShardId, Error> {
+        Ok(account_id_to_shard_id(account_id, &self.get_shard_layout(epoch_id)?))
+    }
+
+    /// Shard layout of the given epoch.
+    fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error>;
+
+    /// Shard layout of the epoch of the block after `parent_hash`.
+    fn get_shard_layout_from_prev_block(
+        &self,
+        parent_hash: &CryptoHash,
+    ) -> Result<ShardLayout, Error> {
+        let epoch_id = self.get_epoch_id_from_prev_block(parent_hash)?;
+        self.get_shard_layout(&epoch_id)
+    }
+
+    /// Uid of the shard `shard_id` of the shard layout of the given epoch.
+    fn shard_id_to_uid(&self, shard_id: ShardId, epoch_id: &EpochId) -> Result<ShardUId, Error> {
+        Ok(ShardUId::from_shard_id_and_layout(shard_id, &self.get_shard_layout(epoch_id)?))
+    }
+
+    /// Whether the epoch after the epoch of the block after `parent_hash` uses a different
+    /// shard layout, i.e. the shards have to be split during the current epoch.
+    fn will_shard_layout_change_next_epoch(&self, parent_hash: &CryptoHash) -> Result<bool, Error> {
+        let epoch_id = self.get_epoch_id_from_prev_block(parent_hash)?;
+        let next_epoch_id = self.get_next_epoch_id_from_prev_block(parent_hash)?;
+        Ok(self.get_shard_layout(&epoch_id)? != self.get_shard_layout(&next_epoch_id)?)
+    }
+
+    /// For the shards `shard_ids` of the block after `prev_hash`, returns the shards of the
+    /// previous block whose chunks they continue. Those are the same shards, unless the block
+    /// after `prev_hash` is the first block with a new shard layout, in which case a shard
+    /// continues its parent shard.
+    fn get_prev_shard_ids(
+        &self,
+        prev_hash: &CryptoHash,
+        shard_ids: Vec<ShardId>,
+    ) -> Result<Vec<ShardId>, Error> {
+        if self.is_next_block_epoch_start(prev_hash)? {
+            let shard_layout = self.get_shard_layout_from_prev_block(prev_hash)?;
+            let prev_shard_layout =
+                self.get_shard_layout(&self.get_prev_epoch_id_from_prev_block(prev_hash)?)?;
+            if shard_layout != prev_shard_layout {
+                return shard_ids
+                    .into_iter()
+                    .map(|shard_id| {
+                        shard_layout
+                            .get_parent_shard_id(shard_id)
+                            .ok_or_else(|| ErrorKind::InvalidShardId(shard_id).into())
+                    })
+                    .collect();
+            }
+        }
+        Ok(shard_ids)
+    }
 
     /// Returns `account_id` that suppose to have the `part_id` of all chunks given previous block hash.
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<AccountId, Error>;
@@ -455,7 +543,20 @@ pub trait RuntimeAdapter: Send + Sync {
     fn get_epoch_start_height(&self, block_hash: &CryptoHash) -> Result<BlockHeight, Error>;
 
     /// Get the block height for which garbage collection should not go over
-    fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight;
+    fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight {
+        self.get_gc_stop_height_for_num_epochs(
+            block_hash,
+            crate::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA,
+        )
+    }
+
+    /// Get the start height of the epoch `num_epochs - 1` epochs before the epoch of the block,
+    /// garbage collection of data kept for `num_epochs` epochs should not go over it.
+    fn get_gc_stop_height_for_num_epochs(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+    ) -> BlockHeight;
 
     /// Check if epoch exists.
     fn epoch_exists(&self, epoch_id: &EpochId) -> bool;
@@ -519,6 +620,7 @@ pub trait RuntimeAdapter: Send + Sync {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
         is_new_chunk: bool,
@@ -537,6 +639,7 @@ pub trait RuntimeAdapter: Send + Sync {
             last_validator_proposals,
             gas_price,
             gas_limit,
+            congested_shards,
             challenges_result,
             random_seed,
             false,
@@ -559,6 +662,7 @@ pub trait RuntimeAdapter: Send + Sync {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
         generate_storage_proof: bool,
@@ -581,6 +685,7 @@ pub trait RuntimeAdapter: Send + Sync {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges_result: &ChallengesResult,
         random_value: CryptoHash,
         is_new_chunk: bool,
@@ -588,6 +693,7 @@ pub trait RuntimeAdapter: Send + Sync {
     ) -> Result<ApplyTransactionResult, Error>;
 
     /// Query runtime with given `path` and `data`.
+    /// If `include_proof` is set, the trie nodes read to answer the query are returned as well.
     fn query(
         &self,
         shard_id: ShardId,
@@ -598,8 +704,19 @@ pub trait RuntimeAdapter: Send + Sync {
         block_hash: &CryptoHash,
         epoch_id: &EpochId,
         request: &QueryRequest,
+        include_proof: bool,
     ) -> Result<QueryResponse, near_chain_primitives::error::QueryError>;
 
+    /// Returns up to `limit` receipts of the delayed receipts queue of the shard, starting from
+    /// the queue position `from_index` or from the head of the queue.
+    fn view_delayed_receipts(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        from_index: Option<u64>,
+        limit: u64,
+    ) -> Result<DelayedReceiptsView, Error>;
+
     fn get_validator_info(
         &self,
         epoch_id: ValidatorInfoIdentifier,
@@ -671,34 +788,59 @@ pub trait RuntimeAdapter: Send + Sync {
         prev_block_hash: &CryptoHash,
     ) -> Result<EpochId, Error>;
 
-    /// Build receipts hashes.
+    /// Build receipts hashes, routing the receipts to the shards of the given epoch, which is the
+    /// epoch of the block including the chunk with these outgoing receipts.
     // Due to borsh serialization constraints, we have to use `&Vec<Receipt>` instead of `&[Receipt]`
     // here.
-    fn build_receipts_hashes(&self, receipts: &Vec<Receipt>) -> Vec<CryptoHash> {
-        if self.num_shards() == 1 {
-            return vec![hash(&ReceiptList(0, receipts).try_to_vec().unwrap())];
+    fn build_receipts_hashes(
+        &self,
+        receipts: &Vec<Receipt>,
+        epoch_id: &EpochId,
+    ) -> Result<Vec<CryptoHash>, Error> {
+        let num_shards = self.num_shards(epoch_id)?;
+        if num_shards == 1 {
+            return Ok(vec![hash(&ReceiptList(0, receipts).try_to_vec().unwrap())]);
         }
         let mut account_id_to_shard_id = HashMap::new();
-        let mut shard_receipts: Vec<_> = (0..self.num_shards()).map(|i| (i, Vec::new())).collect();
+        let mut shard_receipts: Vec<_> = (0..num_shards).map(|i| (i, Vec::new())).collect();
         for receipt in receipts.iter() {
             let shard_id = match account_id_to_shard_id.get(&receipt.receiver_id) {
                 Some(id) => *id,
                 None => {
-                    let id = self.account_id_to_shard_id(&receipt.receiver_id);
+                    let id = self.account_id_to_shard_id(&receipt.receiver_id, epoch_id)?;
                     account_id_to_shard_id.insert(receipt.receiver_id.clone(), id);
                     id
                 }
             };
             shard_receipts[shard_id as usize].1.push(receipt);
         }
-        shard_receipts
+        Ok(shard_receipts
             .into_iter()
             .map(|(i, rs)| {
                 let bytes = (i, rs).try_to_vec().unwrap();
                 hash(&bytes)
             })
-            .collect()
+            .collect())
     }
+
+    /// Builds the states of the shards `shard_uid` splits into in `next_epoch_shard_layout` from
+    /// the state of `shard_uid` at `state_root`. Returns the state roots of the new shards.
+    fn build_state_for_split_shards(
+        &self,
+        shard_uid: ShardUId,
+        state_root: &StateRoot,
+        next_epoch_shard_layout: &ShardLayout,
+    ) -> Result<HashMap<ShardUId, StateRoot>, Error>;
+
+    /// Applies the state changes a chunk of a parent shard made to the states of the shards it
+    /// splits into in `next_epoch_shard_layout`, given their current `state_roots`.
+    fn apply_update_to_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        state_roots: HashMap<ShardUId, StateRoot>,
+        next_epoch_shard_layout: &ShardLayout,
+        state_changes: StateChangesForSplitStates,
+    ) -> Result<Vec<ApplySplitStateResult>, Error>;
 }
 
 /// The last known / checked height and time when we have processed it.
@@ -23,13 +23,16 @@ use near_store::PartialStorage;
 
 use crate::byzantine_assert;
 use crate::types::ApplyTransactionResult;
-use crate::{ChainStore, Error, ErrorKind, RuntimeAdapter};
+use crate::{ChainStore, ChainStoreAccess, Error, ErrorKind, RuntimeAdapter};
 
 /// Gas limit cannot be adjusted for more than 0.1% at a time.
 const GAS_LIMIT_ADJUSTMENT_FACTOR: u64 = 1000;
 
 /// Verifies that chunk's proofs in the header match the body.
-pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAdapter) -> bool {
+pub fn validate_chunk_proofs(
+    chunk: &ShardChunk,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> Result<bool, Error> {
     let correct_chunk_hash = match chunk {
         ShardChunk::V1(chunk) => ShardChunkHeaderV1::compute_hash(&chunk.header.inner),
         ShardChunk::V2(chunk) => match &chunk.header {
@@ -48,14 +51,14 @@ pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAd
     // 1. Checking chunk.header.hash
     if header_hash != correct_chunk_hash {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
 
     // 2. Checking that chunk body is valid
     // 2a. Checking chunk hash
     if chunk.chunk_hash() != correct_chunk_hash {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
     let height_created = chunk.height_created();
     let outgoing_receipts_root = chunk.outgoing_receipts_root();
@@ -65,20 +68,22 @@ pub fn validate_chunk_proofs(chunk: &ShardChunk, runtime_adapter: &dyn RuntimeAd
     let (tx_root, _) = merklize(transactions);
     if tx_root != chunk.tx_root() {
         byzantine_assert!(false);
-        return false;
+        return Ok(false);
     }
     // 2c. Checking that chunk receipts are valid
     if height_created == 0 {
-        return receipts.len() == 0 && outgoing_receipts_root == CryptoHash::default();
+        return Ok(receipts.len() == 0 && outgoing_receipts_root == CryptoHash::default());
     } else {
-        let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(receipts);
+        let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(&chunk.prev_block_hash())?;
+        let outgoing_receipts_hashes =
+            runtime_adapter.build_receipts_hashes(receipts, &epoch_id)?;
         let (receipts_root, _) = merklize(&outgoing_receipts_hashes);
         if receipts_root != outgoing_receipts_root {
             byzantine_assert!(false);
-            return false;
+            return Ok(false);
         }
     }
-    true
+    Ok(true)
 }
 
 /// Validates that the given transactions are in proper valid order.
@@ -147,16 +152,35 @@ pub fn validate_chunk_with_chunk_extra(
         return Err(ErrorKind::InvalidGasUsed.into());
     }
 
-    if prev_chunk_extra.balance_burnt() != chunk_header.balance_burnt() {
+    if prev_chunk_extra.balance_burnt() != chunk_header.balance_burnt()
+        || prev_chunk_extra.priority_fees() != chunk_header.priority_fees()
+    {
         return Err(ErrorKind::InvalidBalanceBurnt.into());
     }
 
+    // The chunk header version enforces that the congestion info is present since
+    // `ProtocolFeature::CongestionControl`.
+    if let Some(congestion_info) = chunk_header.congestion_info() {
+        if prev_chunk_extra.congestion_info() != Some(congestion_info) {
+            return Err(ErrorKind::InvalidCongestionInfo.into());
+        }
+        let prev_block = chain_store.get_block(prev_block_hash)?;
+        if chunk_header.congested_shards()
+            != Block::compute_congested_shards(prev_block.chunks().iter()).as_slice()
+        {
+            return Err(ErrorKind::InvalidCongestionInfo.into());
+        }
+    }
+
     let receipt_response = chain_store.get_outgoing_receipts_for_shard(
+        runtime_adapter,
         *prev_block_hash,
         chunk_header.shard_id(),
         prev_chunk_header.height_included(),
     )?;
-    let outgoing_receipts_hashes = runtime_adapter.build_receipts_hashes(&receipt_response.1);
+    let epoch_id = runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+    let outgoing_receipts_hashes =
+        runtime_adapter.build_receipts_hashes(&receipt_response.1, &epoch_id)?;
     let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
 
     if outgoing_receipts_root != chunk_header.outgoing_receipts_root() {
@@ -278,7 +302,7 @@ fn validate_chunk_proofs_challenge(
         MaybeEncodedShardChunk::Decoded(chunk) => chunk,
     };
 
-    if !validate_chunk_proofs(chunk_ref, &*runtime_adapter) {
+    if !validate_chunk_proofs(chunk_ref, &*runtime_adapter)? {
         // Chunk proofs are invalid. Good challenge.
         return account_to_slash_for_valid_challenge;
     }
@@ -338,6 +362,7 @@ fn validate_chunk_state_challenge(
             ValidatorStakeIter::empty(),
             prev_block_header.gas_price(),
             prev_chunk_header.gas_limit(),
+            prev_chunk_header.congested_shards(),
             &ChallengesResult::default(),
             *block_header.random_value(),
             // TODO: set it properly when challenges are enabled
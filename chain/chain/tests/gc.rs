@@ -6,9 +6,11 @@ mod tests {
     use near_chain::test_utils::KeyValueRuntime;
     use near_chain::types::{ChainGenesis, Tip};
     use near_chain::DoomslugThresholdMode;
+    use near_chain_configs::GCRetentionConfig;
     use near_crypto::KeyType;
     use near_primitives::block::Block;
     use near_primitives::merkle::PartialMerkleTree;
+    use near_primitives::shard_layout::ShardUId;
     use near_primitives::types::{NumBlocks, NumShards, StateRoot};
     use near_primitives::validator_signer::InMemoryValidatorSigner;
     use near_store::test_utils::{create_test_store, gen_changes};
@@ -90,7 +92,7 @@ mod tests {
                 let new_root = trie_changes.new_root;
                 let wrapped_trie_changes = WrappedTrieChanges::new(
                     tries.clone(),
-                    shard_id,
+                    ShardUId { version: 0, shard_id: shard_id as u32 },
                     trie_changes,
                     Default::default(),
                     *block.hash(),
@@ -151,7 +153,7 @@ mod tests {
         }
 
         // GC execution
-        let clear_data = chain1.clear_data(tries1.clone(), 100);
+        let clear_data = chain1.clear_data(tries1.clone(), 100, &GCRetentionConfig::default());
         if clear_data.is_err() {
             println!("clear data failed = {:?}", clear_data);
             assert!(false);
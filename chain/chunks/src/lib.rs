@@ -23,7 +23,7 @@ use near_pool::{PoolIteratorWrapper, TransactionPool};
 use near_primitives::block::{BlockHeader, Tip};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
-use near_primitives::receipt::Receipt;
+use near_primitives::receipt::{CongestionInfo, Receipt};
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkPart,
     PartialEncodedChunkV1, PartialEncodedChunkV2, ReceiptList, ReceiptProof, ReedSolomonWrapper,
@@ -32,7 +32,7 @@ use near_primitives::sharding::{
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
-    AccountId, Balance, BlockHeight, BlockHeightDelta, Gas, MerkleHash, ShardId, StateRoot,
+    AccountId, Balance, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash, ShardId, StateRoot,
 };
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
@@ -510,7 +510,7 @@ impl ShardsManager {
 
         let shards_to_fetch_receipts =
         // TODO: only keep shards for which we don't have receipts yet
-            if request_full { HashSet::new() } else { self.get_tracking_shards(&parent_hash) };
+            if request_full { HashSet::new() } else { self.get_tracking_shards(&parent_hash)? };
 
         // The loop below will be sending PartialEncodedChunkRequestMsg to various block producers.
         // We need to send such a message to the original chunk producer if we do not have the receipts
@@ -582,8 +582,12 @@ impl ShardsManager {
         })
     }
 
-    fn get_tracking_shards(&self, parent_hash: &CryptoHash) -> HashSet<ShardId> {
-        (0..self.runtime_adapter.num_shards())
+    fn get_tracking_shards(
+        &self,
+        parent_hash: &CryptoHash,
+    ) -> Result<HashSet<ShardId>, near_chain::Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(parent_hash)?;
+        Ok((0..self.runtime_adapter.num_shards(&epoch_id)?)
             .filter(|chunk_shard_id| {
                 self.cares_about_shard_this_or_next_epoch(
                     self.me.as_ref(),
@@ -592,7 +596,7 @@ impl ShardsManager {
                     true,
                 )
             })
-            .collect::<HashSet<_>>()
+            .collect::<HashSet<_>>())
     }
 
     fn request_chunk_single(
@@ -795,14 +799,17 @@ impl ShardsManager {
     pub fn group_receipts_by_shard(
         &self,
         receipts: Vec<Receipt>,
-    ) -> HashMap<ShardId, Vec<Receipt>> {
-        let mut result = HashMap::with_capacity(self.runtime_adapter.num_shards() as usize);
+        epoch_id: &EpochId,
+    ) -> Result<HashMap<ShardId, Vec<Receipt>>, near_chain::Error> {
+        let mut result =
+            HashMap::with_capacity(self.runtime_adapter.num_shards(epoch_id)? as usize);
         for receipt in receipts {
-            let shard_id = self.runtime_adapter.account_id_to_shard_id(&receipt.receiver_id);
+            let shard_id =
+                self.runtime_adapter.account_id_to_shard_id(&receipt.receiver_id, epoch_id)?;
             let entry = result.entry(shard_id).or_insert_with(Vec::new);
             entry.push(receipt)
         }
-        result
+        Ok(result)
     }
 
     pub fn receipts_recipient_filter<T>(
@@ -1397,7 +1404,8 @@ impl ShardsManager {
         prev_block_hash: &CryptoHash,
         chunk_entry: &EncodedChunksCacheEntry,
     ) -> Result<bool, Error> {
-        for shard_id in 0..self.runtime_adapter.num_shards() {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(prev_block_hash)?;
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
             let shard_id = shard_id as ShardId;
             if !chunk_entry.receipts.contains_key(&shard_id) {
                 if self.need_receipt(&prev_block_hash, shard_id) {
@@ -1439,6 +1447,9 @@ impl ShardsManager {
         outgoing_receipts: &Vec<Receipt>,
         outgoing_receipts_root: CryptoHash,
         tx_root: CryptoHash,
+        congestion_info: CongestionInfo,
+        congested_shards: Vec<ShardId>,
+        priority_fees: Balance,
         signer: &dyn ValidatorSigner,
         rs: &mut ReedSolomonWrapper,
         protocol_version: ProtocolVersion,
@@ -1458,6 +1469,9 @@ impl ShardsManager {
             transactions,
             outgoing_receipts,
             outgoing_receipts_root,
+            congestion_info,
+            congested_shards,
+            priority_fees,
             signer,
             protocol_version,
         )
@@ -1530,7 +1544,7 @@ impl ShardsManager {
             .decode_chunk(self.runtime_adapter.num_data_parts())
             .map_err(|err| Error::from(err))
             .and_then(|shard_chunk| {
-                if !validate_chunk_proofs(&shard_chunk, &*self.runtime_adapter) {
+                if !validate_chunk_proofs(&shard_chunk, &*self.runtime_adapter)? {
                     return Err(Error::InvalidChunk);
                 }
                 Ok(shard_chunk)
@@ -1543,7 +1557,7 @@ impl ShardsManager {
                 merkle_paths,
                 shard_chunk.receipts().clone(),
                 &mut store_update,
-            );
+            )?;
 
             // Decoded a valid chunk, store it in the permanent store
             store_update.save_chunk(shard_chunk);
@@ -1569,17 +1583,19 @@ impl ShardsManager {
         merkle_paths: Vec<MerklePath>,
         outgoing_receipts: Vec<Receipt>,
         store_update: &mut ChainStoreUpdate<'_>,
-    ) {
+    ) -> Result<(), Error> {
         let header = encoded_chunk.cloned_header();
         let shard_id = header.shard_id();
+        let epoch_id =
+            self.runtime_adapter.get_epoch_id_from_prev_block(&header.prev_block_hash())?;
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, &epoch_id)?;
         let (outgoing_receipts_root, outgoing_receipts_proofs) =
             merklize(&outgoing_receipts_hashes);
         assert_eq!(header.outgoing_receipts_root(), outgoing_receipts_root);
 
         // Save this chunk into encoded_chunks & process encoded chunk to add to the store.
-        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts);
+        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts, &epoch_id)?;
         let receipts = outgoing_receipts_proofs
             .into_iter()
             .enumerate()
@@ -1613,6 +1629,7 @@ impl ShardsManager {
 
         // Save this chunk into encoded_chunks.
         self.encoded_chunks.insert(cache_entry.header.chunk_hash(), cache_entry);
+        Ok(())
     }
 
     pub fn distribute_encoded_chunk(
@@ -1626,8 +1643,9 @@ impl ShardsManager {
         let chunk_header = encoded_chunk.cloned_header();
         let prev_block_hash = chunk_header.prev_block_hash();
         let shard_id = chunk_header.shard_id();
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash)?;
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, &epoch_id)?;
         let (outgoing_receipts_root, outgoing_receipts_proofs) =
             merklize(&outgoing_receipts_hashes);
         assert_eq!(chunk_header.outgoing_receipts_root(), outgoing_receipts_root);
@@ -1642,7 +1660,7 @@ impl ShardsManager {
             entry.push(part_ord);
         }
 
-        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts);
+        let mut receipts_by_shard = self.group_receipts_by_shard(outgoing_receipts, &epoch_id)?;
         let receipt_proofs: Vec<_> = outgoing_receipts_proofs
             .into_iter()
             .enumerate()
@@ -1799,8 +1817,14 @@ mod test {
                 vec![],
                 vec![],
                 &vec![],
-                merklize(&runtime_adapter.build_receipts_hashes(&vec![])).0,
+                merklize(
+                    &runtime_adapter.build_receipts_hashes(&vec![], &EpochId::default()).unwrap(),
+                )
+                .0,
                 CryptoHash::default(),
+                CongestionInfo::default(),
+                vec![],
+                0,
                 &signer,
                 &mut rs,
                 PROTOCOL_VERSION,
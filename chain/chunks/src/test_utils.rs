@@ -14,7 +14,7 @@ use near_primitives::merkle;
 use near_primitives::sharding::{
     ChunkHash, PartialEncodedChunkPart, PartialEncodedChunkV2, ReedSolomonWrapper, ShardChunkHeader,
 };
-use near_primitives::types::{AccountId, ShardId};
+use near_primitives::types::{AccountId, EpochId, ShardId};
 use near_primitives::types::{BlockHeight, MerkleHash};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
@@ -207,7 +207,8 @@ impl Default for ChunkForwardingTestFixture {
             mock_network.clone(),
         );
         let receipts = Vec::new();
-        let receipts_hashes = mock_runtime.build_receipts_hashes(&receipts);
+        let receipts_hashes =
+            mock_runtime.build_receipts_hashes(&receipts, &EpochId::default()).unwrap();
         let (receipts_root, _) = merkle::merklize(&receipts_hashes);
         let (mock_chunk, mock_merkles) = producer_shard_manager
             .create_encoded_shard_chunk(
@@ -224,6 +225,9 @@ impl Default for ChunkForwardingTestFixture {
                 &receipts,
                 receipts_root,
                 MerkleHash::default(),
+                Default::default(),
+                Vec::new(),
+                0,
                 &signer,
                 &mut rs,
                 PROTOCOL_VERSION,
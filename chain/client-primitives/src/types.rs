@@ -7,22 +7,22 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use near_chain_configs::ProtocolConfigView;
-use near_network_primitives::types::{AccountOrPeerIdOrHash, KnownProducer, PeerInfo};
+use near_network_primitives::types::{AccountOrPeerIdOrHash, KnownProducer, PeerInfo, PeerLatency};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockHeightDelta, BlockReference, EpochReference, MaybeBlockId,
+    ShardId, TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
+    BlockView, ChunkView, DelayedReceiptsView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
     FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    QueryRequest, QueryResponse, ReceiptView, StateChangesHistoryView, StateChangesKindsView,
+    StateChangesRequestView, StateChangesView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -107,6 +107,9 @@ pub enum ShardSyncStatus {
     StateDownloadParts,
     StateDownloadFinalize,
     StateDownloadComplete,
+    StateSplitScheduling,
+    StateSplitApplying,
+    StateSplitDone,
 }
 
 #[derive(Clone, Debug)]
@@ -160,6 +163,8 @@ pub enum GetBlockError {
     IOError { error_message: String },
     #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
     UnknownBlock { error_message: String },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
@@ -179,6 +184,9 @@ impl From<near_chain_primitives::Error> for GetBlockError {
             near_chain_primitives::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::ErrorKind::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
             _ => Self::Unreachable { error_message: error.to_string() },
         }
     }
@@ -224,6 +232,8 @@ pub enum GetChunkError {
     IOError { error_message: String },
     #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
     UnknownBlock { error_message: String },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("Shard ID {shard_id} is invalid")]
     InvalidShardId { shard_id: u64 },
     #[error("Chunk with hash {chunk_hash:?} has never been observed on this node")]
@@ -245,6 +255,9 @@ impl From<near_chain_primitives::Error> for GetChunkError {
             near_chain_primitives::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::ErrorKind::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
             near_chain_primitives::ErrorKind::InvalidShardId(shard_id) => {
                 Self::InvalidShardId { shard_id }
             }
@@ -262,11 +275,22 @@ pub struct Query {
     pub query_id: String,
     pub block_reference: BlockReference,
     pub request: QueryRequest,
+    /// Whether to return the trie nodes needed to verify the result against the state root.
+    pub include_proof: bool,
 }
 
 impl Query {
     pub fn new(block_reference: BlockReference, request: QueryRequest) -> Self {
-        Query { query_id: generate_random_string(10), block_reference, request }
+        Query {
+            query_id: generate_random_string(10),
+            block_reference,
+            request,
+            include_proof: false,
+        }
+    }
+
+    pub fn with_proof(block_reference: BlockReference, request: QueryRequest) -> Self {
+        Query { include_proof: true, ..Self::new(block_reference, request) }
     }
 }
 
@@ -466,6 +490,8 @@ pub struct NetworkInfoResponse {
     pub received_bytes_per_sec: u64,
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
+    /// Round trip time statistics to reachable peers.
+    pub peer_latencies: Vec<PeerLatency>,
 }
 
 /// Status of given transaction including all the subsequent receipts.
@@ -479,6 +505,7 @@ pub struct TxStatus {
 pub enum TxStatusError {
     ChainError(near_chain_primitives::Error),
     MissingTransaction(CryptoHash),
+    GarbageCollected(String),
     InvalidTx(InvalidTxError),
     InternalError(String),
     TimeoutError,
@@ -491,6 +518,7 @@ impl From<TxStatusError> for String {
             TxStatusError::MissingTransaction(tx_hash) => {
                 format!("Transaction {} doesn't exist", tx_hash)
             }
+            TxStatusError::GarbageCollected(error_message) => error_message,
             TxStatusError::InternalError(debug_message) => {
                 format!("Internal error: {}", debug_message)
             }
@@ -560,6 +588,14 @@ pub enum GetStateChangesError {
     UnknownBlock { error_message: String },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
+    #[error(
+        "The range of heights {from_height}..={to_height} spans more than {max_heights} heights"
+    )]
+    TooManyHeights {
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        max_heights: BlockHeightDelta,
+    },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -586,6 +622,16 @@ impl Message for GetStateChanges {
     type Result = Result<StateChangesView, GetStateChangesError>;
 }
 
+pub struct GetStateChangesHistory {
+    pub from_height: BlockHeight,
+    pub to_height: BlockHeight,
+    pub state_changes_request: StateChangesRequestView,
+}
+
+impl Message for GetStateChangesHistory {
+    type Result = Result<StateChangesHistoryView, GetStateChangesError>;
+}
+
 pub struct GetStateChangesInBlock {
     pub block_hash: CryptoHash,
 }
@@ -610,6 +656,8 @@ pub struct GetExecutionOutcome {
 pub enum GetExecutionOutcomeError {
     #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
     UnknownBlock { error_message: String },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("Inconsistent state. Total number of shards is {number_or_shards} but the execution outcome is in shard {execution_outcome_shard_id}")]
     InconsistentState {
         number_or_shards: usize,
@@ -654,6 +702,9 @@ impl From<near_chain_primitives::error::Error> for GetExecutionOutcomeError {
             near_chain_primitives::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::ErrorKind::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
             _ => Self::Unreachable { error_message: error.to_string() },
         }
     }
@@ -718,6 +769,87 @@ impl Message for GetBlockProof {
     type Result = Result<GetBlockProofResponse, GetBlockProofError>;
 }
 
+/// Page of the delayed receipts queue of a shard in the state after the given block.
+pub struct GetDelayedReceipts {
+    pub block_reference: BlockReference,
+    pub shard_id: ShardId,
+    /// Queue position of the first receipt of the page, defaults to the head of the queue.
+    pub from_index: Option<u64>,
+    /// Maximum number of receipts in the page, capped by the view client.
+    pub limit: Option<u64>,
+}
+
+pub struct GetDelayedReceiptsResponse {
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    pub delayed_receipts: DelayedReceiptsView,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetDelayedReceiptsError {
+    #[error("IO Error: {error_message}")]
+    IOError { error_message: String },
+    #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
+    UnknownBlock { error_message: String },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
+    #[error("There are no fully synchronized blocks yet")]
+    NotSyncedYet,
+    #[error("Shard ID {shard_id} is invalid")]
+    InvalidShardId { shard_id: ShardId },
+    #[error("The node does not track the shard ID {shard_id}")]
+    UnavailableShard { shard_id: ShardId },
+    #[error("Internal error: {error_message}")]
+    InternalError { error_message: String },
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {error_message}")]
+    Unreachable { error_message: String },
+}
+
+impl From<near_chain_primitives::Error> for GetDelayedReceiptsError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(error_message) => {
+                Self::IOError { error_message }
+            }
+            near_chain_primitives::ErrorKind::DBNotFoundErr(error_message) => {
+                Self::UnknownBlock { error_message }
+            }
+            near_chain_primitives::ErrorKind::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
+            near_chain_primitives::ErrorKind::InvalidShardId(shard_id) => {
+                Self::InvalidShardId { shard_id }
+            }
+            near_chain_primitives::ErrorKind::Other(error_message) => {
+                Self::InternalError { error_message }
+            }
+            _ => Self::Unreachable { error_message: error.to_string() },
+        }
+    }
+}
+
+impl From<GetBlockError> for GetDelayedReceiptsError {
+    fn from(error: GetBlockError) -> Self {
+        match error {
+            GetBlockError::IOError { error_message } => Self::IOError { error_message },
+            GetBlockError::UnknownBlock { error_message } => Self::UnknownBlock { error_message },
+            GetBlockError::GarbageCollected { error_message } => {
+                Self::GarbageCollected { error_message }
+            }
+            GetBlockError::NotSyncedYet => Self::NotSyncedYet,
+            GetBlockError::Unreachable { error_message } => Self::Unreachable { error_message },
+        }
+    }
+}
+
+impl Message for GetDelayedReceipts {
+    type Result = Result<GetDelayedReceiptsResponse, GetDelayedReceiptsError>;
+}
+
 pub struct GetReceipt {
     pub receipt_id: CryptoHash,
 }
@@ -728,6 +860,8 @@ pub enum GetReceiptError {
     IOError(String),
     #[error("Receipt with id {0} has never been observed on this node")]
     UnknownReceipt(near_primitives::hash::CryptoHash),
+    #[error("{0}")]
+    GarbageCollected(String),
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -740,6 +874,7 @@ impl From<near_chain_primitives::Error> for GetReceiptError {
     fn from(error: near_chain_primitives::Error) -> Self {
         match error.kind() {
             near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            near_chain_primitives::ErrorKind::GarbageCollected(s) => Self::GarbageCollected(s),
             _ => Self::Unreachable(error.to_string()),
         }
     }
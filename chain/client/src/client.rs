@@ -31,7 +31,7 @@ use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     EncodedShardChunk, PartialEncodedChunk, PartialEncodedChunkV2, ReedSolomonWrapper,
-    ShardChunkHeader,
+    ShardChunkHeader, ShardInfo,
 };
 use near_primitives::syncing::ReceiptResponse;
 use near_primitives::transaction::SignedTransaction;
@@ -44,9 +44,10 @@ use near_primitives::utils::{to_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 
 use crate::metrics;
+use crate::state_dump::StateDumper;
 use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
 use crate::SyncStatus;
-use near_client_primitives::types::{Error, ShardSyncDownload};
+use near_client_primitives::types::{Error, ShardSyncDownload, ShardSyncStatus};
 use near_primitives::block_header::ApprovalType;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 
@@ -84,6 +85,8 @@ pub struct Client {
     pub block_sync: BlockSync,
     /// Keeps track of syncing state.
     pub state_sync: StateSync,
+    /// Dumps state parts for other nodes to sync from, if `state_parts_dump_dir` is set.
+    state_dumper: Option<StateDumper>,
     /// List of currently accumulated challenges.
     pub challenges: HashMap<CryptoHash, Challenge>,
     /// A ReedSolomon instance to reconstruct shard.
@@ -109,7 +112,9 @@ impl Client {
         } else {
             DoomslugThresholdMode::NoApprovals
         };
-        let chain = Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        let mut chain =
+            Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        chain.mut_store().set_save_account_history(config.archive && config.account_history)?;
         let shards_mgr = ShardsManager::new(
             validator_signer.as_ref().map(|x| x.validator_id().clone()),
             runtime_adapter.clone(),
@@ -141,7 +146,15 @@ impl Client {
         );
         let block_sync =
             BlockSync::new(network_adapter.clone(), config.block_fetch_horizon, config.archive);
-        let state_sync = StateSync::new(network_adapter.clone(), config.state_sync_timeout);
+        let state_sync = StateSync::new(
+            network_adapter.clone(),
+            config.state_sync_timeout,
+            config.state_sync_from_dump_dir.clone(),
+        );
+        let state_dumper = config
+            .state_parts_dump_dir
+            .clone()
+            .map(|dir| StateDumper::new(runtime_adapter.clone(), dir));
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = runtime_adapter.num_data_parts();
         let parity_parts = runtime_adapter.num_total_parts() - data_parts;
@@ -175,6 +188,7 @@ impl Client {
             header_sync,
             block_sync,
             state_sync,
+            state_dumper,
             challenges: Default::default(),
             rs: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: SizedCache::with_size(NUM_REBROADCAST_BLOCKS),
@@ -436,7 +450,7 @@ impl Client {
         let block_ordinal: NumBlocks = block_merkle_tree.size() + 1;
         let prev_block_extra = self.chain.get_block_extra(&prev_hash)?.clone();
         let prev_block = self.chain.get_block(&prev_hash)?;
-        let mut chunks: Vec<_> = prev_block.chunks().iter().cloned().collect();
+        let mut chunks = Chain::get_prev_chunk_headers(&*self.runtime_adapter, prev_block)?;
 
         // Collect new chunks.
         for (shard_id, mut chunk_header) in new_chunks {
@@ -548,14 +562,28 @@ impl Client {
             validator_signer.validator_id()
         );
 
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, epoch_id)?;
         let chunk_extra = self
             .chain
-            .get_chunk_extra(&prev_block_hash, shard_id)
+            .get_chunk_extra(&prev_block_hash, &shard_uid)
             .map_err(|err| Error::ChunkProducer(format!("No chunk extra available: {}", err)))?
             .clone();
 
         let prev_block_header = self.chain.get_block_header(&prev_block_hash)?.clone();
-        let transactions = self.prepare_transactions(shard_id, &chunk_extra, &prev_block_header)?;
+        // The shards which reported a congested delayed receipts queue in the previous block.
+        // Transactions towards them stay in the pool and the receipts towards them are limited
+        // when the chunk is applied, until the shards catch up.
+        let congested_shards = Block::compute_congested_shards(
+            self.chain.get_block(&prev_block_hash)?.chunks().iter(),
+        );
+        let transactions = self.prepare_transactions(
+            shard_id,
+            &chunk_extra,
+            &prev_block_header,
+            &congested_shards,
+        )?;
+        // The transactions are applied to this state once the chunk is included in a block
+        self.runtime_adapter.prefetch_state(shard_id, chunk_extra.state_root(), &transactions, &[]);
         let num_filtered_transactions = transactions.len();
         let (tx_root, _) = merklize(&transactions);
         let ReceiptResponse(_, outgoing_receipts) = self.chain.get_outgoing_receipts_for_shard(
@@ -577,7 +605,7 @@ impl Client {
         // will receive a piece of incoming receipts only
         // with merkle receipts proofs which can be checked locally
         let outgoing_receipts_hashes =
-            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts);
+            self.runtime_adapter.build_receipts_hashes(&outgoing_receipts, epoch_id)?;
         let (outgoing_receipts_root, _) = merklize(&outgoing_receipts_hashes);
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(epoch_id)?;
@@ -595,6 +623,9 @@ impl Client {
             &outgoing_receipts,
             outgoing_receipts_root,
             tx_root,
+            chunk_extra.congestion_info().unwrap_or_default(),
+            congested_shards,
+            chunk_extra.priority_fees(),
             &*validator_signer,
             &mut self.rs,
             protocol_version,
@@ -621,6 +652,7 @@ impl Client {
         shard_id: ShardId,
         chunk_extra: &ChunkExtra,
         prev_block_header: &BlockHeader,
+        congested_shards: &[ShardId],
     ) -> Result<Vec<SignedTransaction>, Error> {
         let Self { chain, shards_mgr, runtime_adapter, .. } = self;
 
@@ -628,6 +660,9 @@ impl Client {
             runtime_adapter.get_epoch_id_from_prev_block(&prev_block_header.hash())?;
         let protocol_version = runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
+        let prev_epoch_id = prev_block_header.epoch_id().clone();
+        let mut postponed_transactions = vec![];
+
         let transactions = if let Some(mut iter) = shards_mgr.get_pool_iterator(shard_id) {
             let transaction_validity_period = chain.transaction_validity_period;
             runtime_adapter.prepare_transactions(
@@ -641,14 +676,24 @@ impl Client {
                 prev_block_header.height() + 1,
                 &mut iter,
                 &mut |tx: &SignedTransaction| -> bool {
-                    chain
+                    let is_valid = chain
                         .mut_store()
                         .check_transaction_validity_period(
                             &prev_block_header,
                             &tx.transaction.block_hash,
                             transaction_validity_period,
                         )
-                        .is_ok()
+                        .is_ok();
+                    if !is_valid || congested_shards.is_empty() {
+                        return is_valid;
+                    }
+                    let is_receiver_congested = runtime_adapter
+                        .account_id_to_shard_id(&tx.transaction.receiver_id, &prev_epoch_id)
+                        .map_or(false, |shard_id| congested_shards.contains(&shard_id));
+                    if is_receiver_congested {
+                        postponed_transactions.push(tx.clone());
+                    }
+                    !is_receiver_congested
                 },
                 protocol_version,
             )?
@@ -658,6 +703,19 @@ impl Client {
         // Reintroduce valid transactions back to the pool. They will be removed when the chunk is
         // included into the block.
         shards_mgr.reintroduce_transactions(shard_id, &transactions);
+        if !postponed_transactions.is_empty() {
+            debug!(
+                target: "client",
+                "Postponed {} transactions towards congested shards {:?}",
+                postponed_transactions.len(),
+                congested_shards
+            );
+            near_metrics::inc_counter_by(
+                &metrics::TRANSACTIONS_POSTPONED_BY_CONGESTION_TOTAL,
+                postponed_transactions.len() as u64,
+            );
+            shards_mgr.reintroduce_transactions(shard_id, &postponed_transactions);
+        }
         Ok(transactions)
     }
 
@@ -944,6 +1002,47 @@ impl Client {
         Ok(())
     }
 
+    /// If `block` is the first block of an epoch, i.e. the sync hash other nodes use for state
+    /// sync, dumps the state parts of the shards we track to `state_parts_dump_dir`.
+    fn dump_state_parts_if_epoch_start(&mut self, block: &Block) {
+        let state_dumper = match &self.state_dumper {
+            Some(state_dumper) => state_dumper,
+            None => return,
+        };
+        if !self
+            .runtime_adapter
+            .is_next_block_epoch_start(block.header().prev_hash())
+            .unwrap_or(false)
+        {
+            return;
+        }
+        let me = self.validator_signer.as_ref().map(|x| x.validator_id().clone());
+        let sync_hash = *block.hash();
+        let num_shards = match self.runtime_adapter.num_shards(block.header().epoch_id()) {
+            Ok(num_shards) => num_shards,
+            Err(err) => {
+                error!(target: "client", "Can't get the number of shards to dump state parts at {}: {}", sync_hash, err);
+                return;
+            }
+        };
+        for shard_id in 0..num_shards {
+            if !self.runtime_adapter.cares_about_shard(
+                me.as_ref(),
+                block.header().prev_hash(),
+                shard_id,
+                true,
+            ) {
+                continue;
+            }
+            match self.chain.get_state_response_header(shard_id, sync_hash) {
+                Ok(header) => state_dumper.dump(sync_hash, shard_id, header),
+                Err(err) => {
+                    error!(target: "client", "Can't build state header to dump state parts for shard {} at {}: {}", shard_id, sync_hash, err);
+                }
+            }
+        }
+    }
+
     /// Gets called when block got accepted.
     /// Send updates over network, update tx pool and notify ourselves if it's time to produce next block.
     /// Blocks are passed in no particular order.
@@ -993,16 +1092,19 @@ impl Client {
             self.chain.blocks_with_missing_chunks.prune_blocks_below_height(last_finalized_height);
             if !self.config.archive {
                 let timer = near_metrics::start_timer(&metrics::GC_TIME);
-                if let Err(err) = self
-                    .chain
-                    .clear_data(self.runtime_adapter.get_tries(), self.config.gc_blocks_limit)
-                {
+                if let Err(err) = self.chain.clear_data(
+                    self.runtime_adapter.get_tries(),
+                    self.config.gc_blocks_limit,
+                    &self.config.gc_retention,
+                ) {
                     error!(target: "client", "Can't clear old data, {:?}", err);
                     debug_assert!(false);
                 };
                 near_metrics::stop_timer(timer);
             }
 
+            self.dump_state_parts_if_epoch_start(&block);
+
             if self.runtime_adapter.is_next_block_epoch_start(block.hash()).unwrap_or(false) {
                 let next_epoch_protocol_version = unwrap_or_return!(self
                     .runtime_adapter
@@ -1086,11 +1188,14 @@ impl Client {
 
             if provenance != Provenance::SYNC && !self.sync_status.is_syncing() {
                 // Produce new chunks
-                for shard_id in 0..self.runtime_adapter.num_shards() {
-                    let epoch_id = self
-                        .runtime_adapter
-                        .get_epoch_id_from_prev_block(&block.header().hash())
-                        .unwrap();
+                let epoch_id = self
+                    .runtime_adapter
+                    .get_epoch_id_from_prev_block(&block.header().hash())
+                    .unwrap();
+                let prev_chunk_headers =
+                    Chain::get_prev_chunk_headers(&*self.runtime_adapter, &block).unwrap();
+                for (shard_id, prev_chunk_header) in prev_chunk_headers.into_iter().enumerate() {
+                    let shard_id = shard_id as ShardId;
                     let chunk_proposer = self
                         .runtime_adapter
                         .get_chunk_producer(&epoch_id, block.header().height() + 1, shard_id)
@@ -1100,7 +1205,7 @@ impl Client {
                         match self.produce_chunk(
                             *block.hash(),
                             &epoch_id,
-                            block.chunks()[shard_id as usize].clone(),
+                            prev_chunk_header,
                             block.header().height() + 1,
                             shard_id,
                         ) {
@@ -1345,7 +1450,8 @@ impl Client {
 
     /// Forwards given transaction to upcoming validators.
     fn forward_tx(&self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id);
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, epoch_id)?;
         let head = self.chain.head()?;
         let maybe_next_epoch_id = self.get_next_epoch_id_if_at_boundary(&head)?;
 
@@ -1437,7 +1543,6 @@ impl Client {
     ) -> Result<NetworkClientResponses, Error> {
         let head = self.chain.head()?;
         let me = self.validator_signer.as_ref().map(|vs| vs.validator_id());
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id);
         let cur_block_header = self.chain.head_header()?.clone();
         let transaction_validity_period = self.chain.transaction_validity_period;
         // here it is fine to use `cur_block_header` as it is a best effort estimate. If the transaction
@@ -1455,6 +1560,9 @@ impl Client {
         let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, &epoch_id)?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
 
         if let Some(err) = self
             .runtime_adapter
@@ -1468,7 +1576,7 @@ impl Client {
         if self.runtime_adapter.cares_about_shard(me, &head.last_block_hash, shard_id, true)
             || self.runtime_adapter.will_care_about_shard(me, &head.last_block_hash, shard_id, true)
         {
-            let state_root = match self.chain.get_chunk_extra(&head.last_block_hash, shard_id) {
+            let state_root = match self.chain.get_chunk_extra(&head.last_block_hash, &shard_uid) {
                 Ok(chunk_extra) => *chunk_extra.state_root(),
                 Err(_) => {
                     // Not being able to fetch a state root most likely implies that we haven't
@@ -1569,11 +1677,39 @@ impl Client {
             let network_adapter1 = self.network_adapter.clone();
 
             let state_sync_timeout = self.config.state_sync_timeout;
+            let state_sync_from_dump_dir = self.config.state_sync_from_dump_dir.clone();
             let (state_sync, new_shard_sync) =
                 self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
-                    (StateSync::new(network_adapter1, state_sync_timeout), HashMap::new())
+                    (
+                        StateSync::new(
+                            network_adapter1,
+                            state_sync_timeout,
+                            state_sync_from_dump_dir,
+                        ),
+                        HashMap::new(),
+                    )
                 });
 
+            let prev_hash = *self.chain.get_block_header(&sync_hash)?.prev_hash();
+            let need_to_split_states =
+                self.runtime_adapter.will_shard_layout_change_next_epoch(&prev_hash)?;
+            if need_to_split_states {
+                // The states of the shards we already track only have to be split.
+                for ShardInfo(shard_id, _) in state_sync_info.shards.iter() {
+                    if self.runtime_adapter.cares_about_shard(
+                        me.as_ref(),
+                        &prev_hash,
+                        *shard_id,
+                        true,
+                    ) {
+                        new_shard_sync.entry(*shard_id).or_insert_with(|| ShardSyncDownload {
+                            downloads: vec![],
+                            status: ShardSyncStatus::StateSplitScheduling,
+                        });
+                    }
+                }
+            }
+
             debug!(
                 target: "client",
                 "Catchup me: {:?}: sync_hash: {:?}, sync_info: {:?}", me, sync_hash, new_shard_sync
@@ -1587,6 +1723,7 @@ impl Client {
                 &self.runtime_adapter,
                 highest_height_peers,
                 state_sync_info.shards.iter().map(|tuple| tuple.0).collect(),
+                need_to_split_states,
             )? {
                 StateSyncResult::Unchanged => {}
                 StateSyncResult::Changed(fetch_block) => {
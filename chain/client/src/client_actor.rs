@@ -153,6 +153,7 @@ impl ClientActor {
                 sent_bytes_per_sec: 0,
                 known_producers: vec![],
                 peer_counter: 0,
+                peer_latencies: vec![],
             },
             last_validator_announce_time: None,
             info_helper,
@@ -375,9 +376,9 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     hash,
                     state_response.part().as_ref().map(|(part_id, data)| (part_id, data.len()))
                 );
-                // Get the download that matches the shard_id and hash
+                // Get the download that matches the shard_id and hash, and the state sync which requested it
                 let download = {
-                    let mut download: Option<&mut ShardSyncDownload> = None;
+                    let mut download: Option<(&mut ShardSyncDownload, &mut StateSync)> = None;
 
                     // ... It could be that the state was requested by the state sync
                     if let SyncStatus::StateSync(sync_hash, shards_to_download) =
@@ -395,7 +396,7 @@ impl Handler<NetworkClientMessages> for ClientActor {
                                     download.is_none(),
                                     "Internal downloads set has duplicates"
                                 );
-                                download = Some(shard_download);
+                                download = Some((shard_download, &mut self.client.state_sync));
                             } else {
                                 // This may happen because of sending too many StateRequests to different peers.
                                 // For example, we received StateResponse after StateSync completion.
@@ -404,16 +405,16 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     }
 
                     // ... Or one of the catchups
-                    if let Some((_, shards_to_download)) =
+                    if let Some((state_sync, shards_to_download)) =
                         self.client.catchup_state_syncs.get_mut(&hash)
                     {
                         if let Some(part_id) = state_response.part_id() {
-                            self.client.state_sync.received_requested_part(part_id, shard_id, hash);
+                            state_sync.received_requested_part(part_id, shard_id, hash);
                         }
 
                         if let Some(shard_download) = shards_to_download.get_mut(&shard_id) {
                             assert!(download.is_none(), "Internal downloads set has duplicates");
-                            download = Some(shard_download);
+                            download = Some((shard_download, state_sync));
                         } else {
                             // This may happen because of sending too many StateRequests to different peers.
                             // For example, we received StateResponse after StateSync completion.
@@ -423,7 +424,7 @@ impl Handler<NetworkClientMessages> for ClientActor {
                     download
                 };
 
-                if let Some(shard_sync_download) = download {
+                if let Some((shard_sync_download, state_sync)) = download {
                     match shard_sync_download.status {
                         ShardSyncStatus::StateDownloadHeader => {
                             if let Some(header) = state_response.take_header() {
@@ -457,17 +458,18 @@ impl Handler<NetworkClientMessages> for ClientActor {
                                     return NetworkClientResponses::NoResponse;
                                 }
                                 if !shard_sync_download.downloads[part_id as usize].done {
-                                    match self
-                                        .client
-                                        .chain
-                                        .set_state_part(shard_id, hash, part_id, num_parts, &data)
-                                    {
-                                        Ok(()) => {
-                                            shard_sync_download.downloads[part_id as usize].done =
-                                                true;
-                                        }
+                                    match self.client.chain.get_state_header(shard_id, hash) {
+                                        Ok(shard_state_header) => state_sync.validate_state_part(
+                                            self.client.runtime_adapter.clone(),
+                                            shard_state_header.chunk_prev_state_root(),
+                                            shard_id,
+                                            hash,
+                                            part_id,
+                                            num_parts,
+                                            data,
+                                        ),
                                         Err(err) => {
-                                            error!(target: "sync", "State sync set_state_part error, shard = {}, part = {}, hash = {}: {:?}", shard_id, part_id, hash, err);
+                                            error!(target: "sync", "State sync get_state_header error, shard = {}, part = {}, hash = {}: {:?}", shard_id, part_id, hash, err);
                                             shard_sync_download.downloads[part_id as usize].error =
                                                 true;
                                         }
@@ -648,6 +650,7 @@ impl Handler<GetNetworkInfo> for ClientActor {
             sent_bytes_per_sec: self.network_info.sent_bytes_per_sec,
             received_bytes_per_sec: self.network_info.received_bytes_per_sec,
             known_producers: self.network_info.known_producers.clone(),
+            peer_latencies: self.network_info.peer_latencies.clone(),
         })
     }
 }
@@ -745,8 +748,8 @@ impl ClientActor {
                 == Some(&next_block_producer_account)
             {
                 let num_chunks = self.client.shards_mgr.num_chunks_for_block(&head.last_block_hash);
-                let have_all_chunks =
-                    head.height == 0 || num_chunks == self.client.runtime_adapter.num_shards();
+                let have_all_chunks = head.height == 0
+                    || num_chunks == self.client.runtime_adapter.num_shards(&epoch_id)?;
 
                 if self.client.doomslug.ready_to_produce_block(
                     Instant::now(),
@@ -1328,7 +1331,10 @@ impl ClientActor {
                 let block_header =
                     unwrap_or_run_later!(self.client.chain.get_block_header(&sync_hash));
                 let prev_hash = block_header.prev_hash().clone();
-                let shards_to_sync = (0..self.client.runtime_adapter.num_shards())
+                let epoch_id = block_header.epoch_id().clone();
+                let num_shards =
+                    unwrap_or_run_later!(self.client.runtime_adapter.num_shards(&epoch_id));
+                let shards_to_sync = (0..num_shards)
                     .filter(|x| {
                         self.client.shards_mgr.cares_about_shard_this_or_next_epoch(
                             me.as_ref(),
@@ -1351,6 +1357,9 @@ impl ClientActor {
                     &self.client.runtime_adapter,
                     &self.network_info.highest_height_peers,
                     shards_to_sync,
+                    // States are split when catching up from `sync_hash`, which is the first
+                    // block processed after state sync.
+                    false,
                 )) {
                     StateSyncResult::Unchanged => (),
                     StateSyncResult::Changed(fetch_block) => {
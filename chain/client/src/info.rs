@@ -247,6 +247,9 @@ fn display_sync_status(
                             ShardSyncStatus::StateDownloadParts => format!("parts"),
                             ShardSyncStatus::StateDownloadFinalize => format!("finalization"),
                             ShardSyncStatus::StateDownloadComplete => format!("done"),
+                            ShardSyncStatus::StateSplitScheduling => format!("split scheduling"),
+                            ShardSyncStatus::StateSplitApplying => format!("split applying"),
+                            ShardSyncStatus::StateSplitDone => format!("split done"),
                         }
                     )
                     .as_str();
@@ -3,10 +3,12 @@ extern crate lazy_static;
 
 pub use near_client_primitives::types::{
     Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetStateChangesWithCauseInBlock, GetValidatorInfo, GetValidatorOrdered,
-    Query, QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    GetDelayedReceipts, GetDelayedReceiptsResponse, GetExecutionOutcome,
+    GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice, GetNetworkInfo,
+    GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
+    GetStateChangesHistory, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
+    GetValidatorInfo, GetValidatorOrdered, Query, QueryError, Status, StatusResponse, SyncStatus,
+    TxStatus, TxStatusError,
 };
 
 pub use crate::client::Client;
@@ -19,6 +21,7 @@ mod client;
 mod client_actor;
 mod info;
 mod metrics;
+pub mod state_dump;
 pub mod sync;
 pub mod test_utils;
 mod view_client;
@@ -30,4 +30,9 @@ lazy_static! {
         try_create_int_gauge("near_memory_usage_bytes", "Amount of RAM memory usage");
     pub static ref GC_TIME: near_metrics::Result<Histogram> =
         try_create_histogram("near_gc_time", "Time taken to do garbage collection");
+    pub static ref TRANSACTIONS_POSTPONED_BY_CONGESTION_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_transactions_postponed_by_congestion_total",
+            "Total number of transactions left in the pool because their receiver shard is congested"
+        );
 }
@@ -0,0 +1,152 @@
+//! Dumping state parts to a directory and reading them back for state sync.
+//!
+//! Layout of the directory:
+//! `<dir>/<sync_hash>/shard_<shard_id>/header` holds the borsh serialized
+//! `ShardStateSyncResponseHeader` and `<dir>/<sync_hash>/shard_<shard_id>/part_<part_id>_of_<num_parts>`
+//! holds the state parts exactly as returned by `RuntimeAdapter::obtain_state_part`.
+//! Nothing read from the directory is trusted: headers go through `Chain::set_state_header` and
+//! parts through `RuntimeAdapter::validate_state_part` like the ones received from peers.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use log::{error, info};
+
+use near_chain::RuntimeAdapter;
+use near_primitives::hash::CryptoHash;
+use near_primitives::syncing::{get_num_state_parts, ShardStateSyncResponseHeader};
+use near_primitives::types::ShardId;
+
+fn shard_dir(dir: &Path, sync_hash: &CryptoHash, shard_id: ShardId) -> PathBuf {
+    dir.join(sync_hash.to_string()).join(format!("shard_{}", shard_id))
+}
+
+pub fn state_header_path(dir: &Path, sync_hash: &CryptoHash, shard_id: ShardId) -> PathBuf {
+    shard_dir(dir, sync_hash, shard_id).join("header")
+}
+
+pub fn state_part_path(
+    dir: &Path,
+    sync_hash: &CryptoHash,
+    shard_id: ShardId,
+    part_id: u64,
+    num_parts: u64,
+) -> PathBuf {
+    shard_dir(dir, sync_hash, shard_id).join(format!("part_{}_of_{}", part_id, num_parts))
+}
+
+/// Writes the file through a temporary one, so that readers never see a partially written file.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Returns `None` if the file doesn't exist.
+fn read_if_exists(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Dumps the header and all state parts of the shard for the given sync hash.
+/// Parts which are already present in the directory are not generated again.
+pub fn dump_state_parts(
+    runtime_adapter: &dyn RuntimeAdapter,
+    dir: &Path,
+    sync_hash: &CryptoHash,
+    shard_id: ShardId,
+    header: &ShardStateSyncResponseHeader,
+) -> io::Result<()> {
+    fs::create_dir_all(shard_dir(dir, sync_hash, shard_id))?;
+    let state_root = header.chunk_prev_state_root();
+    let num_parts = get_num_state_parts(header.state_root_node().memory_usage);
+    for part_id in 0..num_parts {
+        let path = state_part_path(dir, sync_hash, shard_id, part_id, num_parts);
+        if path.exists() {
+            continue;
+        }
+        let part = runtime_adapter
+            .obtain_state_part(shard_id, &state_root, part_id, num_parts)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        write_atomically(&path, &part)?;
+    }
+    // The header is written last, so its presence means that the dump is complete.
+    write_atomically(&state_header_path(dir, sync_hash, shard_id), &header.try_to_vec()?)?;
+    info!(target: "sync", "Dumped {} state parts for shard {} at {}", num_parts, shard_id, sync_hash);
+    Ok(())
+}
+
+struct DumpRequest {
+    sync_hash: CryptoHash,
+    shard_id: ShardId,
+    header: ShardStateSyncResponseHeader,
+}
+
+/// Dumps state parts on a background thread, since generating all of them takes a while.
+/// A single thread serves all the dumps one after another, so a slow dump delays the next ones
+/// instead of competing with them and with block processing for the disk.
+pub struct StateDumper {
+    sender: Sender<DumpRequest>,
+}
+
+impl StateDumper {
+    pub fn new(runtime_adapter: Arc<dyn RuntimeAdapter>, dir: PathBuf) -> Self {
+        let (sender, receiver) = channel::<DumpRequest>();
+        std::thread::Builder::new()
+            .name("state_dump".to_string())
+            .spawn(move || {
+                // The loop ends once the dumper is dropped.
+                for DumpRequest { sync_hash, shard_id, header } in receiver {
+                    if let Err(err) = dump_state_parts(
+                        runtime_adapter.as_ref(),
+                        &dir,
+                        &sync_hash,
+                        shard_id,
+                        &header,
+                    ) {
+                        error!(target: "sync", "Failed to dump state parts for shard {} at {}: {}", shard_id, sync_hash, err);
+                    }
+                }
+            })
+            .expect("Failed to start the state dump thread");
+        StateDumper { sender }
+    }
+
+    /// Queues the dump of the state parts of the shard for the given sync hash.
+    pub fn dump(
+        &self,
+        sync_hash: CryptoHash,
+        shard_id: ShardId,
+        header: ShardStateSyncResponseHeader,
+    ) {
+        // The receiver only goes away if the thread panicked, which was logged already.
+        let _ = self.sender.send(DumpRequest { sync_hash, shard_id, header });
+    }
+}
+
+pub fn read_state_header(
+    dir: &Path,
+    sync_hash: &CryptoHash,
+    shard_id: ShardId,
+) -> io::Result<Option<ShardStateSyncResponseHeader>> {
+    match read_if_exists(&state_header_path(dir, sync_hash, shard_id))? {
+        Some(data) => Ok(Some(ShardStateSyncResponseHeader::try_from_slice(&data)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn read_state_part(
+    dir: &Path,
+    sync_hash: &CryptoHash,
+    shard_id: ShardId,
+    part_id: u64,
+    num_parts: u64,
+) -> io::Result<Option<Vec<u8>>> {
+    read_if_exists(&state_part_path(dir, sync_hash, shard_id, part_id, num_parts))
+}
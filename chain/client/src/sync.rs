@@ -1,7 +1,9 @@
 use near_chain::{ChainStoreAccess, Error};
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 use std::{ops::Add, time::Duration as TimeDuration};
 
@@ -18,11 +20,15 @@ use near_network::{FullPeerInfo, NetworkAdapter, NetworkRequests};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::syncing::get_num_state_parts;
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, EpochId, ShardId};
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockHeightDelta, EpochId, ShardId, StateRoot,
+};
 use near_primitives::utils::to_timestamp;
 
+use crate::state_dump;
 use cached::{Cached, SizedCache};
 use near_client_primitives::types::{
     DownloadStatus, ShardSyncDownload, ShardSyncStatus, SyncStatus,
@@ -36,11 +42,17 @@ pub const MAX_BLOCK_HEADER_HASHES: usize = 20;
 
 const BLOCK_REQUEST_TIMEOUT: i64 = 2;
 
-/// Maximum number of state parts to request per peer on each round when node is trying to download the state.
+/// Number of state parts requested from a peer at once before we know how fast the peer is.
 pub const MAX_STATE_PART_REQUEST: u64 = 16;
+/// Upper bound on the number of state parts in flight to a single peer.
+/// The window of each peer starts at `MAX_STATE_PART_REQUEST`, doubles every time the peer delivers
+/// all requested parts in time and halves every time a request to the peer times out.
+pub const MAX_STATE_PART_REQUEST_WINDOW: u64 = MAX_STATE_PART_REQUEST * 16;
 /// Number of state parts already requested stored as pending.
 /// This number should not exceed MAX_STATE_PART_REQUEST times (number of peers in the network).
 pub const MAX_PENDING_PART: u64 = MAX_STATE_PART_REQUEST * 10000;
+/// Maximum number of state parts which are read from the dump directory and validated at once.
+pub const MAX_DUMP_PARTS_IN_FLIGHT: u64 = 16;
 
 pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
@@ -586,11 +598,45 @@ impl PendingRequestStatus {
     fn new(timeout: Duration) -> Self {
         Self { missing_parts: 1, wait_until: Utc::now().add(timeout) }
     }
+    fn add_part(&mut self, timeout: Duration) {
+        self.missing_parts += 1;
+        self.wait_until = Utc::now().add(timeout);
+    }
     fn expired(&self) -> bool {
         Utc::now() > self.wait_until
     }
 }
 
+/// State part which was checked by `validate_state_part` on the worker pool.
+struct ValidatedStatePart {
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    part_id: u64,
+    /// Part data, `None` if the part is invalid.
+    data: Option<Vec<u8>>,
+}
+
+fn validate_part(
+    runtime_adapter: &dyn RuntimeAdapter,
+    state_root: &StateRoot,
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    part_id: u64,
+    num_parts: u64,
+    data: Vec<u8>,
+) -> ValidatedStatePart {
+    let is_valid = runtime_adapter.validate_state_part(state_root, part_id, num_parts, &data);
+    let data = if is_valid { Some(data) } else { None };
+    ValidatedStatePart { shard_id, sync_hash, part_id, data }
+}
+
+/// States of the shards that a shard is split into, built in a background thread.
+struct SplitStates {
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    state_roots: Result<HashMap<ShardUId, StateRoot>, near_chain::Error>,
+}
+
 /// Helper to track state sync.
 pub struct StateSync {
     network_adapter: Arc<dyn NetworkAdapter>,
@@ -601,18 +647,46 @@ pub struct StateSync {
     last_part_id_requested: HashMap<(AccountOrPeerIdOrHash, ShardId), PendingRequestStatus>,
     /// Map from which part we requested to whom.
     requested_target: SizedCache<(u64, CryptoHash), AccountOrPeerIdOrHash>,
+    /// Number of parts which may be in flight to each target, see `MAX_STATE_PART_REQUEST_WINDOW`.
+    request_windows: HashMap<AccountOrPeerIdOrHash, u64>,
+
+    /// Received parts are validated on the worker pool and sent back through this channel.
+    validated_parts_sender: Sender<ValidatedStatePart>,
+    validated_parts_receiver: Receiver<ValidatedStatePart>,
+
+    /// Split states are built in background threads and sent back through this channel.
+    split_states_sender: Sender<SplitStates>,
+    split_states_receiver: Receiver<SplitStates>,
+
+    /// Directory with dumped state parts which are used instead of requesting them from peers.
+    dump_dir: Option<PathBuf>,
+    /// Number of parts which are being read from the dump directory on the worker pool.
+    dump_parts_in_flight: Arc<AtomicU64>,
 
     timeout: Duration,
 }
 
 impl StateSync {
-    pub fn new(network_adapter: Arc<dyn NetworkAdapter>, timeout: TimeDuration) -> Self {
+    pub fn new(
+        network_adapter: Arc<dyn NetworkAdapter>,
+        timeout: TimeDuration,
+        dump_dir: Option<PathBuf>,
+    ) -> Self {
+        let (validated_parts_sender, validated_parts_receiver) = channel();
+        let (split_states_sender, split_states_receiver) = channel();
         StateSync {
             network_adapter,
             state_sync_time: Default::default(),
             last_time_block_requested: None,
             last_part_id_requested: Default::default(),
             requested_target: SizedCache::with_size(MAX_PENDING_PART as usize),
+            request_windows: Default::default(),
+            validated_parts_sender,
+            validated_parts_receiver,
+            split_states_sender,
+            split_states_receiver,
+            dump_dir,
+            dump_parts_in_flight: Default::default(),
             timeout: Duration::from_std(timeout).unwrap(),
         }
     }
@@ -654,10 +728,13 @@ impl StateSync {
         runtime_adapter: &Arc<dyn RuntimeAdapter>,
         highest_height_peers: &Vec<FullPeerInfo>,
         tracking_shards: Vec<ShardId>,
+        need_to_split_states: bool,
         now: DateTime<Utc>,
     ) -> Result<(bool, bool), near_chain::Error> {
         let mut all_done = true;
-        let mut update_sync_status = false;
+        let mut update_sync_status =
+            self.process_validated_parts(sync_hash, new_shard_sync, chain)?;
+        update_sync_status |= self.process_split_states(sync_hash, new_shard_sync, chain)?;
         let init_sync_download = ShardSyncDownload {
             downloads: vec![
                 DownloadStatus {
@@ -684,23 +761,40 @@ impl StateSync {
             let mut this_done = false;
             match shard_sync_download.status {
                 ShardSyncStatus::StateDownloadHeader => {
+                    if !shard_sync_download.downloads[0].done
+                        && chain.get_state_header(shard_id, sync_hash).is_ok()
+                    {
+                        // The header was downloaded and validated before the node restarted.
+                        info!(target: "sync", "State sync: reusing saved header, shard = {}, hash = {}", shard_id, sync_hash);
+                        shard_sync_download.downloads[0].done = true;
+                    }
                     if shard_sync_download.downloads[0].done {
                         let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
                         let state_num_parts =
                             get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+                        let mut downloads = vec![
+                            DownloadStatus {
+                                start_time: now,
+                                prev_update_time: now,
+                                run_me: Arc::new(AtomicBool::new(true)),
+                                error: false,
+                                done: false,
+                                state_requests_count: 0,
+                                last_target: None,
+                            };
+                            state_num_parts as usize
+                        ];
+                        let saved_part_ids =
+                            chain.get_saved_state_part_ids(shard_id, sync_hash, state_num_parts)?;
+                        if !saved_part_ids.is_empty() {
+                            info!(target: "sync", "State sync: reusing {} of {} saved parts, shard = {}, hash = {}", saved_part_ids.len(), state_num_parts, shard_id, sync_hash);
+                        }
+                        for part_id in saved_part_ids {
+                            downloads[part_id as usize].done = true;
+                            downloads[part_id as usize].run_me.store(false, Ordering::SeqCst);
+                        }
                         *shard_sync_download = ShardSyncDownload {
-                            downloads: vec![
-                                DownloadStatus {
-                                    start_time: now,
-                                    prev_update_time: now,
-                                    run_me: Arc::new(AtomicBool::new(true)),
-                                    error: false,
-                                    done: false,
-                                    state_requests_count: 0,
-                                    last_target: None,
-                                };
-                                state_num_parts as usize
-                            ],
+                            downloads,
                             status: ShardSyncStatus::StateDownloadParts,
                         };
                         need_shard = true;
@@ -768,11 +862,33 @@ impl StateSync {
                     }
                 }
                 ShardSyncStatus::StateDownloadComplete => {
-                    this_done = true;
                     let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
                     let state_num_parts =
                         get_num_state_parts(shard_state_header.state_root_node().memory_usage);
                     chain.clear_downloaded_parts(shard_id, sync_hash, state_num_parts)?;
+                    if need_to_split_states {
+                        update_sync_status = true;
+                        *shard_sync_download = ShardSyncDownload {
+                            downloads: vec![],
+                            status: ShardSyncStatus::StateSplitScheduling,
+                        };
+                    } else {
+                        this_done = true;
+                    }
+                }
+                ShardSyncStatus::StateSplitScheduling => {
+                    self.schedule_split_states(chain, shard_id, sync_hash)?;
+                    update_sync_status = true;
+                    *shard_sync_download = ShardSyncDownload {
+                        downloads: vec![],
+                        status: ShardSyncStatus::StateSplitApplying,
+                    };
+                }
+                ShardSyncStatus::StateSplitApplying => {
+                    // Waiting for `process_split_states` to receive the split states.
+                }
+                ShardSyncStatus::StateSplitDone => {
+                    this_done = true;
                 }
             }
             all_done &= this_done;
@@ -858,9 +974,7 @@ impl StateSync {
         let timeout = self.timeout;
         self.last_part_id_requested
             .entry((target, shard_id))
-            .and_modify(|pending_request| {
-                pending_request.missing_parts += 1;
-            })
+            .and_modify(|pending_request| pending_request.add_part(timeout))
             .or_insert_with(|| PendingRequestStatus::new(timeout));
     }
 
@@ -880,13 +994,162 @@ impl StateSync {
                 },
             ) {
                 self.last_part_id_requested.remove(&(target.clone(), shard_id));
+                // The target delivered everything we asked for in time, ask for more next time.
+                let window =
+                    self.request_windows.entry(target.clone()).or_insert(MAX_STATE_PART_REQUEST);
+                *window = min(*window * 2, MAX_STATE_PART_REQUEST_WINDOW);
+            }
+        }
+    }
+
+    /// Validates a received state part on the worker pool.
+    /// The part is saved to `ColStateParts` by the next `run` if it is valid, so that the download
+    /// can be resumed after a restart.
+    pub fn validate_state_part(
+        &self,
+        runtime_adapter: Arc<dyn RuntimeAdapter>,
+        state_root: StateRoot,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+        num_parts: u64,
+        data: Vec<u8>,
+    ) {
+        let sender = self.validated_parts_sender.clone();
+        rayon::spawn(move || {
+            let part = validate_part(
+                runtime_adapter.as_ref(),
+                &state_root,
+                shard_id,
+                sync_hash,
+                part_id,
+                num_parts,
+                data,
+            );
+            // The receiver is gone if state sync has finished in the meantime.
+            let _ = sender.send(part);
+        });
+    }
+
+    /// Builds the states of the shards that shard `shard_id` is split into in a background thread.
+    /// The result is saved by `process_split_states`.
+    fn schedule_split_states(
+        &self,
+        chain: &mut Chain,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    ) -> Result<(), near_chain::Error> {
+        let (shard_uid, state_root, next_epoch_shard_layout) =
+            chain.get_state_to_split(shard_id, sync_hash)?;
+        let runtime_adapter = chain.runtime_adapter.clone();
+        let sender = self.split_states_sender.clone();
+        info!(target: "sync", "State sync: splitting the state of shard {}, hash = {}", shard_id, sync_hash);
+        // Building the states takes a while, so it doesn't run on the worker pool.
+        std::thread::spawn(move || {
+            let state_roots = runtime_adapter.build_state_for_split_shards(
+                shard_uid,
+                &state_root,
+                &next_epoch_shard_layout,
+            );
+            // The receiver is gone if the node is shutting down.
+            let _ = sender.send(SplitStates { shard_id, sync_hash, state_roots });
+        });
+        Ok(())
+    }
+
+    /// Saves the split states built since the last call.
+    /// Returns whether the status of any shard has changed.
+    fn process_split_states(
+        &mut self,
+        sync_hash: CryptoHash,
+        new_shard_sync: &mut HashMap<u64, ShardSyncDownload>,
+        chain: &mut Chain,
+    ) -> Result<bool, near_chain::Error> {
+        let mut changed = false;
+        for split_states in self.split_states_receiver.try_iter() {
+            if split_states.sync_hash != sync_hash {
+                continue;
+            }
+            let shard_sync_download = match new_shard_sync.get_mut(&split_states.shard_id) {
+                Some(shard_sync_download)
+                    if matches!(
+                        shard_sync_download.status,
+                        ShardSyncStatus::StateSplitApplying
+                    ) =>
+                {
+                    shard_sync_download
+                }
+                _ => continue,
+            };
+            let status = match split_states.state_roots {
+                Ok(state_roots) => {
+                    chain.set_split_state_roots(split_states.shard_id, sync_hash, state_roots)?;
+                    ShardSyncStatus::StateSplitDone
+                }
+                Err(err) => {
+                    error!(target: "sync", "State sync: splitting the state failed, shard = {}, hash = {}: {:?}", split_states.shard_id, sync_hash, err);
+                    ShardSyncStatus::StateSplitScheduling
+                }
+            };
+            *shard_sync_download = ShardSyncDownload { downloads: vec![], status };
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Saves the parts validated since the last call and marks them as done.
+    /// Returns whether the status of any part has changed.
+    fn process_validated_parts(
+        &mut self,
+        sync_hash: CryptoHash,
+        new_shard_sync: &mut HashMap<u64, ShardSyncDownload>,
+        chain: &mut Chain,
+    ) -> Result<bool, near_chain::Error> {
+        let mut changed = false;
+        for part in self.validated_parts_receiver.try_iter() {
+            if part.sync_hash != sync_hash {
+                continue;
             }
+            let download = match new_shard_sync.get_mut(&part.shard_id) {
+                Some(ShardSyncDownload {
+                    downloads,
+                    status: ShardSyncStatus::StateDownloadParts,
+                }) => match downloads.get_mut(part.part_id as usize) {
+                    Some(download) if !download.done => download,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            match part.data {
+                Some(data) => {
+                    chain.save_state_part(part.shard_id, sync_hash, part.part_id, &data)?;
+                    download.done = true;
+                }
+                None => {
+                    error!(target: "sync", "State sync received invalid part, shard = {}, part = {}, hash = {}", part.shard_id, part.part_id, sync_hash);
+                    download.error = true;
+                }
+            }
+            changed = true;
         }
+        Ok(changed)
+    }
+
+    /// Number of parts which may be requested from `target` right now.
+    fn available_request_slots(&self, target: &AccountOrPeerIdOrHash, shard_id: ShardId) -> u64 {
+        let window = self.request_windows.get(target).copied().unwrap_or(MAX_STATE_PART_REQUEST);
+        let in_flight = self
+            .last_part_id_requested
+            .get(&(target.clone(), shard_id))
+            .map_or(0, |request| request.missing_parts as u64);
+        window.saturating_sub(in_flight)
     }
 
-    /// Find possible targets to download state from.
-    /// Candidates are validators at current epoch and peers at highest height.
-    /// Only select candidates that we have no pending request currently ongoing.
+    /// Find possible targets to download state from, together with the number of parts which can
+    /// be requested from each of them.
+    /// Candidates are validators at current epoch and peers at highest height which track the shard,
+    /// whether they are validators or not.
+    /// Only select candidates whose request window isn't filled by pending requests.
     fn possible_targets(
         &mut self,
         me: &Option<AccountId>,
@@ -895,9 +1158,20 @@ impl StateSync {
         runtime_adapter: &Arc<dyn RuntimeAdapter>,
         sync_hash: CryptoHash,
         highest_height_peers: &Vec<FullPeerInfo>,
-    ) -> Result<Vec<AccountOrPeerIdOrHash>, Error> {
-        // Remove candidates from pending list if request expired due to timeout
-        self.last_part_id_requested.retain(|_, request| !request.expired());
+    ) -> Result<Vec<(AccountOrPeerIdOrHash, u64)>, Error> {
+        // Remove candidates from pending list if request expired due to timeout, and shrink their
+        // request windows.
+        let request_windows = &mut self.request_windows;
+        self.last_part_id_requested.retain(|(target, _), request| {
+            if request.expired() {
+                let window =
+                    request_windows.entry(target.clone()).or_insert(MAX_STATE_PART_REQUEST);
+                *window = (*window / 2).max(1);
+                false
+            } else {
+                true
+            }
+        });
 
         let prev_block_hash = chain.get_block_header(&sync_hash)?.prev_hash();
         let epoch_hash = runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash)?;
@@ -929,12 +1203,116 @@ impl StateSync {
                     None
                 }
             }))
-            .filter(|candidate| {
-                !self.last_part_id_requested.contains_key(&(candidate.clone(), shard_id))
+            .filter_map(|candidate| {
+                let slots = self.available_request_slots(&candidate, shard_id);
+                if slots > 0 {
+                    Some((candidate, slots))
+                } else {
+                    None
+                }
             })
             .collect::<Vec<_>>())
     }
 
+    /// Takes the header and parts available in the dump directory instead of requesting them from
+    /// peers. Only the first attempt to get each of them uses the dump, so if the dump is invalid
+    /// or incomplete the retries go to the network.
+    /// Parts are read and validated on the worker pool, at most `MAX_DUMP_PARTS_IN_FLIGHT` at once.
+    /// A part missing from the dump is marked to be requested from peers.
+    fn read_from_dump(
+        &self,
+        dump_dir: &Path,
+        shard_id: ShardId,
+        chain: &mut Chain,
+        runtime_adapter: &Arc<dyn RuntimeAdapter>,
+        sync_hash: CryptoHash,
+        shard_sync_download: &mut ShardSyncDownload,
+    ) -> Result<(), near_chain::Error> {
+        match shard_sync_download.status {
+            ShardSyncStatus::StateDownloadHeader => {
+                let download = &mut shard_sync_download.downloads[0];
+                if download.done
+                    || download.state_requests_count > 0
+                    || !download.run_me.load(Ordering::SeqCst)
+                {
+                    return Ok(());
+                }
+                download.state_requests_count += 1;
+                match state_dump::read_state_header(dump_dir, &sync_hash, shard_id) {
+                    Ok(Some(header)) => match chain.set_state_header(shard_id, sync_hash, header) {
+                        Ok(()) => {
+                            download.run_me.store(false, Ordering::SeqCst);
+                            download.done = true;
+                        }
+                        Err(err) => {
+                            error!(target: "sync", "State sync: invalid header in the dump, shard = {}, hash = {}: {:?}", shard_id, sync_hash, err);
+                        }
+                    },
+                    Ok(None) => {}
+                    Err(err) => {
+                        error!(target: "sync", "State sync: can't read header from the dump, shard = {}, hash = {}: {}", shard_id, sync_hash, err);
+                    }
+                }
+            }
+            ShardSyncStatus::StateDownloadParts => {
+                let num_parts = shard_sync_download.downloads.len() as u64;
+                let state_root =
+                    chain.get_state_header(shard_id, sync_hash)?.chunk_prev_state_root();
+                for (part_id, download) in shard_sync_download.downloads.iter_mut().enumerate() {
+                    if download.done
+                        || download.state_requests_count > 0
+                        || !download.run_me.load(Ordering::SeqCst)
+                    {
+                        continue;
+                    }
+                    if self.dump_parts_in_flight.load(Ordering::SeqCst) >= MAX_DUMP_PARTS_IN_FLIGHT
+                    {
+                        break;
+                    }
+                    self.dump_parts_in_flight.fetch_add(1, Ordering::SeqCst);
+                    download.run_me.store(false, Ordering::SeqCst);
+                    download.state_requests_count += 1;
+
+                    let part_id = part_id as u64;
+                    let dump_dir = dump_dir.to_path_buf();
+                    let runtime_adapter = runtime_adapter.clone();
+                    let sender = self.validated_parts_sender.clone();
+                    let run_me = download.run_me.clone();
+                    let dump_parts_in_flight = self.dump_parts_in_flight.clone();
+                    rayon::spawn(move || {
+                        match state_dump::read_state_part(
+                            &dump_dir, &sync_hash, shard_id, part_id, num_parts,
+                        ) {
+                            Ok(Some(data)) => {
+                                let part = validate_part(
+                                    runtime_adapter.as_ref(),
+                                    &state_root,
+                                    shard_id,
+                                    sync_hash,
+                                    part_id,
+                                    num_parts,
+                                    data,
+                                );
+                                let _ = sender.send(part);
+                            }
+                            Ok(None) => {
+                                // Request the part from peers on the next iteration.
+                                run_me.store(true, Ordering::SeqCst);
+                            }
+                            Err(err) => {
+                                error!(target: "sync", "State sync: can't read part from the dump, shard = {}, part = {}, hash = {}: {}", shard_id, part_id, sync_hash, err);
+                                run_me.store(true, Ordering::SeqCst);
+                            }
+                        }
+                        dump_parts_in_flight.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     /// Returns new ShardSyncDownload if successful, otherwise returns given shard_sync_download
     pub fn request_shard(
         &mut self,
@@ -943,9 +1321,20 @@ impl StateSync {
         chain: &mut Chain,
         runtime_adapter: &Arc<dyn RuntimeAdapter>,
         sync_hash: CryptoHash,
-        shard_sync_download: ShardSyncDownload,
+        mut shard_sync_download: ShardSyncDownload,
         highest_height_peers: &Vec<FullPeerInfo>,
     ) -> Result<ShardSyncDownload, near_chain::Error> {
+        if let Some(dump_dir) = self.dump_dir.clone() {
+            self.read_from_dump(
+                &dump_dir,
+                shard_id,
+                chain,
+                runtime_adapter,
+                sync_hash,
+                &mut shard_sync_download,
+            )?;
+        }
+
         let possible_targets = self.possible_targets(
             me,
             shard_id,
@@ -964,8 +1353,11 @@ impl StateSync {
 
         match shard_sync_download.status {
             ShardSyncStatus::StateDownloadHeader => {
-                let target = possible_targets.choose(&mut thread_rng()).cloned().unwrap();
-                assert!(new_shard_sync_download.downloads[0].run_me.load(Ordering::SeqCst));
+                if !new_shard_sync_download.downloads[0].run_me.load(Ordering::SeqCst) {
+                    // The header was taken from the dump.
+                    return Ok(new_shard_sync_download);
+                }
+                let (target, _) = possible_targets.choose(&mut thread_rng()).cloned().unwrap();
                 new_shard_sync_download.downloads[0].run_me.store(false, Ordering::SeqCst);
                 new_shard_sync_download.downloads[0].state_requests_count += 1;
                 new_shard_sync_download.downloads[0].last_target = Some(target.clone());
@@ -986,18 +1378,22 @@ impl StateSync {
                 );
             }
             ShardSyncStatus::StateDownloadParts => {
-                let possible_targets_sampler =
-                    SamplerLimited::new(possible_targets, MAX_STATE_PART_REQUEST);
+                let possible_targets_sampler = SamplerLimited::new(possible_targets);
 
                 // Iterate over all parts that needs to be requested (i.e. download.run_me is true).
                 // Parts are ordered such that its index match its part_id.
                 // Finally, for every part that needs to be requested it is selected one peer (target) randomly
-                // to request the part from
+                // to request the part from, without exceeding the request window of any peer
+                // With a dump directory, parts which weren't looked up in the dump yet are skipped.
+                let use_dump = self.dump_dir.is_some();
                 for ((part_id, download), target) in new_shard_sync_download
                     .downloads
                     .iter_mut()
                     .enumerate()
-                    .filter(|(_, download)| download.run_me.load(Ordering::SeqCst))
+                    .filter(|(_, download)| {
+                        download.run_me.load(Ordering::SeqCst)
+                            && (!use_dump || download.state_requests_count > 0)
+                    })
                     .zip(possible_targets_sampler)
                 {
                     self.sent_request_part(target.clone(), part_id as u64, shard_id, sync_hash);
@@ -1042,6 +1438,7 @@ impl StateSync {
         runtime_adapter: &Arc<dyn RuntimeAdapter>,
         highest_height_peers: &Vec<FullPeerInfo>,
         tracking_shards: Vec<ShardId>,
+        need_to_split_states: bool,
     ) -> Result<StateSyncResult, near_chain::Error> {
         let prev_hash = chain.get_block_header(&sync_hash)?.prev_hash().clone();
         let now = Utc::now();
@@ -1067,6 +1464,7 @@ impl StateSync {
             runtime_adapter,
             highest_height_peers,
             tracking_shards,
+            need_to_split_states,
             now,
         )?;
 
@@ -1084,11 +1482,11 @@ impl StateSync {
 }
 
 /// Create an abstract collection of elements to be shuffled.
-/// Each element will appear in the shuffled output exactly `limit` times.
+/// Each element will appear in the shuffled output exactly as many times as its limit.
 /// Use it as an iterator to access the shuffled collection.
 ///
 /// ```rust,ignore
-/// let sampler = SamplerLimited::new(vec![1, 2, 3], 2);
+/// let sampler = SamplerLimited::new(vec![(1, 2), (2, 2), (3, 2)]);
 ///
 /// let res = sampler.collect::<Vec<_>>();
 ///
@@ -1109,13 +1507,9 @@ struct SamplerLimited<T> {
 }
 
 impl<T> SamplerLimited<T> {
-    fn new(data: Vec<T>, limit: u64) -> Self {
-        if limit == 0 {
-            Self { data: vec![], limit: vec![] }
-        } else {
-            let len = data.len();
-            Self { data, limit: vec![limit; len] }
-        }
+    fn new(data_with_limits: Vec<(T, u64)>) -> Self {
+        let (data, limit) = data_with_limits.into_iter().filter(|(_, limit)| *limit > 0).unzip();
+        Self { data, limit }
     }
 }
 
@@ -1508,4 +1902,37 @@ mod test {
             blocks.iter().take(1).map(|b| *b.hash()).collect::<HashSet<_>>()
         );
     }
+
+    #[test]
+    fn test_sampler_limited() {
+        let sampler = SamplerLimited::new(vec![(1, 3), (2, 0), (3, 1)]);
+        let res = sampler.collect::<Vec<_>>();
+        assert_eq!(res.len(), 4);
+        assert_eq!(res.iter().filter(|v| **v == 1).count(), 3);
+        assert_eq!(res.iter().filter(|v| **v == 2).count(), 0);
+        assert_eq!(res.iter().filter(|v| **v == 3).count(), 1);
+    }
+
+    #[test]
+    fn test_state_part_request_window() {
+        let mut state_sync = StateSync::new(
+            Arc::new(MockNetworkAdapter::default()),
+            TimeDuration::from_secs(60),
+            None,
+        );
+        let target = AccountOrPeerIdOrHash::PeerId(PeerId::random());
+        let sync_hash = CryptoHash::default();
+        assert_eq!(state_sync.available_request_slots(&target, 0), MAX_STATE_PART_REQUEST);
+
+        for part_id in 0..MAX_STATE_PART_REQUEST {
+            state_sync.sent_request_part(target.clone(), part_id, 0, sync_hash);
+        }
+        assert_eq!(state_sync.available_request_slots(&target, 0), 0);
+
+        // All parts arrived in time, so the window grows.
+        for part_id in 0..MAX_STATE_PART_REQUEST {
+            state_sync.received_requested_part(part_id, 0, sync_hash);
+        }
+        assert_eq!(state_sync.available_request_slots(&target, 0), 2 * MAX_STATE_PART_REQUEST);
+    }
 }
@@ -486,6 +486,7 @@ pub fn setup_mock_all_validators(
                             received_bytes_per_sec: 0,
                             known_producers: vec![],
                             peer_counter: 0,
+                            peer_latencies: vec![],
                         };
                         client_addr.do_send(NetworkClientMessages::NetworkInfo(info));
                     }
@@ -1139,6 +1140,7 @@ impl TestEnv {
                 last_block.header().hash(),
                 last_block.header().epoch_id(),
                 &QueryRequest::ViewAccount { account_id },
+                false,
             )
             .unwrap();
         match response.kind {
@@ -1162,6 +1164,7 @@ impl TestEnv {
                 last_block.header().hash(),
                 last_block.header().epoch_id(),
                 &QueryRequest::ViewState { account_id, prefix: vec![].into() },
+                false,
             )
             .unwrap();
         match response.kind {
@@ -1266,6 +1269,9 @@ pub fn create_chunk(
             transactions,
             decoded_chunk.receipts(),
             header.outgoing_receipts_root(),
+            header.congestion_info().unwrap_or_default(),
+            header.congested_shards().to_vec(),
+            header.priority_fees(),
             &*signer,
             PROTOCOL_VERSION,
         )
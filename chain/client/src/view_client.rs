@@ -15,16 +15,17 @@ use log::{debug, error, info, trace, warn};
 use near_chain::types::ValidatorInfoIdentifier;
 use near_chain::{
     get_epoch_block_producers_view, Chain, ChainGenesis, ChainStoreAccess, DoomslugThresholdMode,
-    ErrorKind, RuntimeAdapter,
+    ErrorKind, GCDataCategory, RuntimeAdapter, MAX_STATE_CHANGES_HISTORY_HEIGHTS,
 };
 use near_chain_configs::{ClientConfig, ProtocolConfigView};
 use near_client_primitives::types::{
     Error, GetBlock, GetBlockError, GetBlockProof, GetBlockProofError, GetBlockProofResponse,
-    GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome, GetExecutionOutcomeError,
+    GetBlockWithMerkleTree, GetChunkError, GetDelayedReceipts, GetDelayedReceiptsError,
+    GetDelayedReceiptsResponse, GetExecutionOutcome, GetExecutionOutcomeError,
     GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError, GetNextLightClientBlockError,
     GetProtocolConfig, GetProtocolConfigError, GetReceipt, GetReceiptError, GetStateChangesError,
-    GetStateChangesWithCauseInBlock, GetValidatorInfoError, Query, QueryError, TxStatus,
-    TxStatusError,
+    GetStateChangesHistory, GetStateChangesWithCauseInBlock, GetValidatorInfoError, Query,
+    QueryError, TxStatus, TxStatusError,
 };
 #[cfg(feature = "adversarial")]
 use near_network::types::NetworkAdversarialMessage;
@@ -45,15 +46,15 @@ use near_primitives::syncing::{
     ShardStateSyncResponseV2,
 };
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockId, BlockReference, EpochReference, Finality, MaybeBlockId,
-    ShardId, TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockId, BlockReference, EpochId, EpochReference, Finality,
+    MaybeBlockId, ShardId, TransactionOrReceiptId,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
     BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
     FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView,
+    LightClientBlockView, QueryRequest, QueryResponse, QueryResponseKind, ReceiptView,
+    StateChangesHistoryView, StateChangesInBlockView, StateChangesKindsView, StateChangesView,
 };
 
 use crate::{
@@ -68,6 +69,11 @@ const REQUEST_WAIT_TIME: u64 = 1000;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
+/// Number of delayed receipts returned when the request doesn't set the page size.
+const DEFAULT_DELAYED_RECEIPTS_PAGE_SIZE: u64 = 100;
+/// Max number of delayed receipts returned in one page.
+const MAX_DELAYED_RECEIPTS_PAGE_SIZE: u64 = 1000;
+
 /// Request and response manager across all instances of ViewClientActor.
 pub struct ViewClientRequestManager {
     /// Transaction query that needs to be forwarded to other shards
@@ -160,6 +166,21 @@ impl ViewClientActor {
         }
     }
 
+    /// Turns a not found error for the block into a `GarbageCollected` error if the block is
+    /// below the GC tail. Headers are never garbage collected, so the height is known.
+    fn gc_error_for_block(
+        &mut self,
+        error: near_chain::Error,
+        block_hash: &CryptoHash,
+    ) -> near_chain::Error {
+        match self.chain.get_block_header(block_hash).map(|header| header.height()) {
+            Ok(height) => {
+                self.chain.store().gc_error_for_height(error, GCDataCategory::Blocks, height)
+            }
+            Err(_) => error,
+        }
+    }
+
     fn need_request<K: Hash + Eq + Clone>(key: K, cache: &mut SizedCache<K, Instant>) -> bool {
         let now = Instant::now();
         let need_request = match cache.cache_get(&key) {
@@ -196,6 +217,43 @@ impl ViewClientActor {
         }
     }
 
+    /// Looks the account up in the account history kept by archive nodes, if the history
+    /// covers the block and the block is on the canonical chain.
+    fn view_account_from_history(
+        &mut self,
+        account_id: &AccountId,
+        header: &BlockHeader,
+    ) -> Result<Option<QueryResponse>, QueryError> {
+        let internal_error =
+            |err: near_chain::Error| QueryError::InternalError { error_message: err.to_string() };
+        match self.chain.get_header_by_height(header.height()) {
+            Ok(canonical_header) if canonical_header.hash() == header.hash() => {}
+            _ => return Ok(None),
+        }
+        let account = match self
+            .chain
+            .mut_store()
+            .get_account_from_history(account_id, header.height())
+            .map_err(internal_error)?
+        {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        match account {
+            Some(account) => Ok(Some(QueryResponse {
+                kind: QueryResponseKind::ViewAccount(account.into()),
+                block_height: header.height(),
+                block_hash: *header.hash(),
+                proof: None,
+            })),
+            None => Err(QueryError::UnknownAccount {
+                requested_account_id: account_id.clone(),
+                block_height: header.height(),
+                block_hash: *header.hash(),
+            }),
+        }
+    }
+
     fn handle_query(&mut self, msg: Query) -> Result<QueryResponse, QueryError> {
         let header = match msg.block_reference {
             BlockReference::BlockId(BlockId::Height(block_height)) => {
@@ -240,6 +298,14 @@ impl ViewClientActor {
             })?
             .clone();
 
+        if let (BlockReference::BlockId(_), QueryRequest::ViewAccount { account_id }, false) =
+            (&msg.block_reference, &msg.request, msg.include_proof)
+        {
+            if let Some(response) = self.view_account_from_history(account_id, &header)? {
+                return Ok(response);
+            }
+        }
+
         let account_id = match &msg.request {
             QueryRequest::ViewAccount { account_id, .. } => account_id,
             QueryRequest::ViewState { account_id, .. } => account_id,
@@ -248,9 +314,16 @@ impl ViewClientActor {
             QueryRequest::CallFunction { account_id, .. } => account_id,
             QueryRequest::ViewCode { account_id, .. } => account_id,
         };
-        let shard_id = self.runtime_adapter.account_id_to_shard_id(account_id);
+        let shard_id =
+            self.runtime_adapter
+                .account_id_to_shard_id(account_id, header.epoch_id())
+                .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        let shard_uid = self
+            .runtime_adapter
+            .shard_id_to_uid(shard_id, header.epoch_id())
+            .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
 
-        let chunk_extra = self.chain.get_chunk_extra(header.hash(), shard_id).map_err(|err| {
+        let chunk_extra = self.chain.get_chunk_extra(header.hash(), &shard_uid).map_err(|err| {
             match err.kind() {
                 near_chain::near_chain_primitives::ErrorKind::DBNotFoundErr(_) => {
                     QueryError::UnavailableShard { requested_shard_id: shard_id }
@@ -272,6 +345,7 @@ impl ViewClientActor {
             header.hash(),
             header.epoch_id(),
             &msg.request,
+            msg.include_proof,
         ) {
             Ok(query_response) => Ok(query_response),
             Err(query_error) => Err(match query_error {
@@ -327,7 +401,17 @@ impl ViewClientActor {
         last_block_hash: &CryptoHash,
     ) -> Result<(), TxStatusError> {
         if let Ok(&dst_shard_id) = self.chain.get_shard_id_for_receipt_id(&receipt_id) {
-            if self.chain.get_chunk_extra(last_block_hash, dst_shard_id).is_err() {
+            let epoch_id = self
+                .chain
+                .get_block_header(last_block_hash)
+                .map_err(|e| TxStatusError::ChainError(e))?
+                .epoch_id()
+                .clone();
+            let dst_shard_uid = self
+                .runtime_adapter
+                .shard_id_to_uid(dst_shard_id, &epoch_id)
+                .map_err(|e| TxStatusError::ChainError(e))?;
+            if self.chain.get_chunk_extra(last_block_hash, &dst_shard_uid).is_err() {
                 let mut request_manager = self.request_manager.write().expect(POISONED_LOCK_ERR);
                 if Self::need_request(receipt_id, &mut request_manager.receipt_outcome_requests) {
                     let validator = self
@@ -358,7 +442,10 @@ impl ViewClientActor {
         }
 
         let head = self.chain.head().map_err(|e| TxStatusError::ChainError(e))?;
-        let target_shard_id = self.runtime_adapter.account_id_to_shard_id(&signer_account_id);
+        let target_shard_id = self
+            .runtime_adapter
+            .account_id_to_shard_id(&signer_account_id, &head.epoch_id)
+            .map_err(|e| TxStatusError::ChainError(e))?;
         // Check if we are tracking this shard.
         if self.runtime_adapter.cares_about_shard(
             self.validator_account_id.as_ref(),
@@ -384,7 +471,12 @@ impl ViewClientActor {
                         let final_result = self
                             .chain
                             .get_final_transaction_result_with_receipt(tx_result)
-                            .map_err(|e| TxStatusError::ChainError(e))?;
+                            .map_err(|e| match e.kind() {
+                                ErrorKind::GarbageCollected(error_message) => {
+                                    TxStatusError::GarbageCollected(error_message)
+                                }
+                                _ => TxStatusError::ChainError(e),
+                            })?;
                         return Ok(Some(
                             FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(
                                 final_result,
@@ -407,6 +499,9 @@ impl ViewClientActor {
                             return Err(TxStatusError::MissingTransaction(tx_hash));
                         }
                     }
+                    ErrorKind::GarbageCollected(error_message) => {
+                        return Err(TxStatusError::GarbageCollected(error_message));
+                    }
                     _ => {
                         warn!(target: "client", "Error trying to get transaction result: {}", e.to_string());
                         return Err(TxStatusError::ChainError(e));
@@ -416,8 +511,10 @@ impl ViewClientActor {
         } else {
             let mut request_manager = self.request_manager.write().expect(POISONED_LOCK_ERR);
             if Self::need_request(tx_hash, &mut request_manager.tx_status_requests) {
-                let target_shard_id =
-                    self.runtime_adapter.account_id_to_shard_id(&signer_account_id);
+                let target_shard_id = self
+                    .runtime_adapter
+                    .account_id_to_shard_id(&signer_account_id, &head.epoch_id)
+                    .map_err(|e| TxStatusError::ChainError(e))?;
                 let validator = self
                     .chain
                     .find_validator_for_forwarding(target_shard_id)
@@ -527,11 +624,15 @@ impl Handler<GetBlock> for ViewClientActor {
                 self.chain.get_block(&block_hash).map(Clone::clone)
             }
             BlockReference::BlockId(BlockId::Height(height)) => {
-                self.chain.get_block_by_height(height).map(Clone::clone)
-            }
-            BlockReference::BlockId(BlockId::Hash(hash)) => {
-                self.chain.get_block(&hash).map(Clone::clone)
+                self.chain.get_block_by_height(height).map(Clone::clone).map_err(|err| {
+                    self.chain.store().gc_error_for_height(err, GCDataCategory::Blocks, height)
+                })
             }
+            BlockReference::BlockId(BlockId::Hash(hash)) => self
+                .chain
+                .get_block(&hash)
+                .map(Clone::clone)
+                .map_err(|err| self.gc_error_for_block(err, &hash)),
             BlockReference::SyncCheckpoint(sync_checkpoint) => {
                 if let Some(block_hash) =
                     self.get_block_hash_by_sync_checkpoint(&sync_checkpoint)?
@@ -593,11 +694,18 @@ impl Handler<GetChunk> for ViewClientActor {
         let chunk = match msg {
             GetChunk::ChunkHash(chunk_hash) => self.chain.get_chunk(&chunk_hash)?.clone(),
             GetChunk::BlockHash(block_hash, shard_id) => {
-                let block = self.chain.get_block(&block_hash)?.clone();
+                let block = self
+                    .chain
+                    .get_block(&block_hash)
+                    .map(Clone::clone)
+                    .map_err(|err| self.gc_error_for_block(err, &block_hash))?;
                 get_chunk_from_block(block, shard_id, &mut self.chain)?
             }
             GetChunk::Height(height, shard_id) => {
-                let block = self.chain.get_block_by_height(height)?.clone();
+                let block =
+                    self.chain.get_block_by_height(height).map(Clone::clone).map_err(|err| {
+                        self.chain.store().gc_error_for_height(err, GCDataCategory::Blocks, height)
+                    })?;
                 get_chunk_from_block(block, shard_id, &mut self.chain)?
             }
         };
@@ -717,6 +825,38 @@ impl Handler<GetStateChanges> for ViewClientActor {
     }
 }
 
+/// Returns the changes in a store filtered by the state changes request for each block in a range
+/// of heights.
+impl Handler<GetStateChangesHistory> for ViewClientActor {
+    type Result = Result<StateChangesHistoryView, GetStateChangesError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetStateChangesHistory, _: &mut Self::Context) -> Self::Result {
+        if msg.to_height.saturating_sub(msg.from_height) >= MAX_STATE_CHANGES_HISTORY_HEIGHTS {
+            return Err(GetStateChangesError::TooManyHeights {
+                from_height: msg.from_height,
+                to_height: msg.to_height,
+                max_heights: MAX_STATE_CHANGES_HISTORY_HEIGHTS,
+            });
+        }
+        Ok(self
+            .chain
+            .store()
+            .get_state_changes_history(
+                msg.from_height,
+                msg.to_height,
+                &msg.state_changes_request.into(),
+            )?
+            .into_iter()
+            .map(|(block_height, block_hash, changes)| StateChangesInBlockView {
+                block_height,
+                block_hash,
+                changes: changes.into_iter().map(Into::into).collect(),
+            })
+            .collect())
+    }
+}
+
 /// Returns a list of changes in a store with causes for a given block.
 impl Handler<GetStateChangesWithCauseInBlock> for ViewClientActor {
     type Result = Result<StateChangesView, GetStateChangesError>;
@@ -789,21 +929,25 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
 
     #[perf]
     fn handle(&mut self, msg: GetExecutionOutcome, _: &mut Self::Context) -> Self::Result {
+        let head = self.chain.head()?;
         let (id, target_shard_id) = match msg.id {
-            TransactionOrReceiptId::Transaction { transaction_hash, sender_id } => {
-                (transaction_hash, self.runtime_adapter.account_id_to_shard_id(&sender_id))
-            }
-            TransactionOrReceiptId::Receipt { receipt_id, receiver_id } => {
-                (receipt_id, self.runtime_adapter.account_id_to_shard_id(&receiver_id))
-            }
+            TransactionOrReceiptId::Transaction { transaction_hash, sender_id } => (
+                transaction_hash,
+                self.runtime_adapter.account_id_to_shard_id(&sender_id, &head.epoch_id)?,
+            ),
+            TransactionOrReceiptId::Receipt { receipt_id, receiver_id } => (
+                receipt_id,
+                self.runtime_adapter.account_id_to_shard_id(&receiver_id, &head.epoch_id)?,
+            ),
         };
         match self.chain.get_execution_outcome(&id) {
             Ok(outcome) => {
                 let mut outcome_proof = outcome.clone();
                 let next_block_hash = self
                     .chain
-                    .get_next_block_hash_with_new_chunk(&outcome_proof.block_hash, target_shard_id)?
-                    .cloned();
+                    .get_next_block_hash_with_new_chunk(&outcome_proof.block_hash, target_shard_id)
+                    .map(|hash| hash.cloned())
+                    .map_err(|err| self.gc_error_for_block(err, &outcome_proof.block_hash))?;
                 match next_block_hash {
                     Some(h) => {
                         outcome_proof.block_hash = h;
@@ -811,11 +955,15 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
                         // should be fast
                         let outcome_roots = self
                             .chain
-                            .get_block(&h)?
-                            .chunks()
-                            .iter()
-                            .map(|header| header.outcome_root())
-                            .collect::<Vec<_>>();
+                            .get_block(&h)
+                            .map(|block| {
+                                block
+                                    .chunks()
+                                    .iter()
+                                    .map(|header| header.outcome_root())
+                                    .collect::<Vec<_>>()
+                            })
+                            .map_err(|err| self.gc_error_for_block(err, &h))?;
                         if target_shard_id >= (outcome_roots.len() as u64) {
                             return Err(GetExecutionOutcomeError::InconsistentState {
                                 number_or_shards: outcome_roots.len(),
@@ -836,7 +984,6 @@ impl Handler<GetExecutionOutcome> for ViewClientActor {
             }
             Err(e) => match e.kind() {
                 ErrorKind::DBNotFoundErr(_) => {
-                    let head = self.chain.head().map_err(|e| TxStatusError::ChainError(e))?;
                     if self.runtime_adapter.cares_about_shard(
                         self.validator_account_id.as_ref(),
                         &head.last_block_hash,
@@ -902,6 +1049,47 @@ impl Handler<GetBlockProof> for ViewClientActor {
     }
 }
 
+/// Returns a page of the delayed receipts queue of a shard in the state after the given block.
+impl Handler<GetDelayedReceipts> for ViewClientActor {
+    type Result = Result<GetDelayedReceiptsResponse, GetDelayedReceiptsError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetDelayedReceipts, ctx: &mut Self::Context) -> Self::Result {
+        let block = self.handle(GetBlock(msg.block_reference), ctx)?;
+        let epoch_id = EpochId(block.header.epoch_id);
+        if msg.shard_id >= self.runtime_adapter.num_shards(&epoch_id)? {
+            return Err(GetDelayedReceiptsError::InvalidShardId { shard_id: msg.shard_id });
+        }
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(msg.shard_id, &epoch_id)?;
+        let state_root = match self.chain.get_chunk_extra(&block.header.hash, &shard_uid) {
+            Ok(chunk_extra) => *chunk_extra.state_root(),
+            Err(err) => match err.kind() {
+                ErrorKind::DBNotFoundErr(_) => {
+                    return Err(GetDelayedReceiptsError::UnavailableShard {
+                        shard_id: msg.shard_id,
+                    })
+                }
+                _ => return Err(err.into()),
+            },
+        };
+        let limit = msg
+            .limit
+            .unwrap_or(DEFAULT_DELAYED_RECEIPTS_PAGE_SIZE)
+            .min(MAX_DELAYED_RECEIPTS_PAGE_SIZE);
+        let delayed_receipts = self.runtime_adapter.view_delayed_receipts(
+            msg.shard_id,
+            &state_root,
+            msg.from_index,
+            limit,
+        )?;
+        Ok(GetDelayedReceiptsResponse {
+            block_hash: block.header.hash,
+            block_height: block.header.height,
+            delayed_receipts,
+        })
+    }
+}
+
 impl Handler<GetProtocolConfig> for ViewClientActor {
     type Result = Result<ProtocolConfigView, GetProtocolConfigError>;
 
@@ -90,6 +90,8 @@ fn update_chunk_height_created(
             match &mut header.inner {
                 ShardChunkHeaderInner::V1(inner) => inner.height_created = new_height,
                 ShardChunkHeaderInner::V2(inner) => inner.height_created = new_height,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                ShardChunkHeaderInner::V3(inner) => inner.height_created = new_height,
             }
             ShardChunkHeader::V3(header)
         }
@@ -383,6 +383,7 @@ impl EpochManager {
                 validator_block_chunk_stats,
                 &validator_stake,
                 *block_info.total_supply(),
+                *block_info.epoch_priority_fees(),
                 epoch_protocol_version,
                 self.genesis_protocol_version,
                 epoch_duration,
@@ -488,6 +489,10 @@ impl EpochManager {
                     }
                 }
 
+                if !is_epoch_start {
+                    block_info.add_epoch_priority_fees(*prev_block_info.epoch_priority_fees());
+                }
+
                 if is_epoch_start {
                     self.save_epoch_start(
                         &mut store_update,
@@ -1190,8 +1195,14 @@ impl EpochManager {
 
     pub fn get_shard_layout(&mut self, epoch_id: &EpochId) -> Result<ShardLayout, EpochError> {
         let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
-        let shard_layout = &self.config.for_protocol_version(protocol_version).shard_layout;
-        Ok(shard_layout.clone())
+        Ok(self.get_shard_layout_for_protocol_version(protocol_version))
+    }
+
+    pub fn get_shard_layout_for_protocol_version(
+        &self,
+        protocol_version: ProtocolVersion,
+    ) -> ShardLayout {
+        self.config.for_protocol_version(protocol_version).shard_layout.clone()
     }
 
     pub fn get_epoch_info(&mut self, epoch_id: &EpochId) -> Result<&EpochInfo, EpochError> {
@@ -2167,6 +2178,7 @@ mod tests {
             validator_online_ratio,
             &validator_stakes,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
@@ -2272,6 +2284,7 @@ mod tests {
             validator_online_ratio,
             &validators_stakes,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
@@ -2375,6 +2388,7 @@ mod tests {
             validator_online_ratio,
             &validators_stakes,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
@@ -3616,6 +3630,7 @@ mod tests {
             vec!["aurora".parse().unwrap()],
             vec!["hhhh", "oooo"].into_iter().map(|x| x.parse().unwrap()).collect(),
             Some(vec![0, 0, 0, 0]),
+            1,
         );
         let shard_config = ShardConfig {
             num_block_producer_seats_per_shard: get_num_seats_per_shard(4, 2),
@@ -38,12 +38,14 @@ impl RewardCalculator {
     }
     /// Calculate validator reward for an epoch based on their block and chunk production stats.
     /// Returns map of validators with their rewards and amount of newly minted tokens including to protocol's treasury.
+    /// The priority fees burnt during the epoch are minted back to the validators in proportion to the chunks they produced.
     /// See spec https://nomicon.io/Economics/README.html#rewards-calculation
     pub fn calculate_reward(
         &self,
         validator_block_chunk_stats: HashMap<AccountId, BlockChunkValidatorStats>,
         validator_stake: &HashMap<AccountId, Balance>,
         total_supply: Balance,
+        priority_fees: Balance,
         protocol_version: ProtocolVersion,
         genesis_protocol_version: ProtocolVersion,
         epoch_duration: u64,
@@ -84,6 +86,8 @@ impl RewardCalculator {
         let epoch_validator_reward = epoch_total_reward - epoch_protocol_treasury;
         let mut epoch_actual_reward = epoch_protocol_treasury;
         let total_stake: Balance = validator_stake.values().sum();
+        let total_produced_chunks: u64 =
+            validator_block_chunk_stats.values().map(|stats| stats.chunk_stats.produced).sum();
         for (account_id, stats) in validator_block_chunk_stats {
             // Uptime is an average of block produced / expected and chunk produced / expected.
             let average_produced_numer = U256::from(
@@ -122,6 +126,14 @@ impl RewardCalculator {
                     / U256::from(total_stake))
                 .as_u128()
             };
+            let priority_fee_reward = if total_produced_chunks == 0 {
+                0
+            } else {
+                (U256::from(priority_fees) * U256::from(stats.chunk_stats.produced)
+                    / U256::from(total_produced_chunks))
+                .as_u128()
+            };
+            let reward = reward + priority_fee_reward;
             res.insert(account_id, reward);
             epoch_actual_reward += reward;
         }
@@ -178,6 +190,7 @@ mod tests {
             validator_block_chunk_stats,
             &validator_stake,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
@@ -245,6 +258,7 @@ mod tests {
             validator_block_chunk_stats,
             &validator_stake,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
@@ -265,6 +279,65 @@ mod tests {
         assert_eq!(result.1, 4_999_999u128);
     }
 
+    /// Test that the priority fees are minted to the validators in proportion to the chunks they
+    /// produced, regardless of their uptime.
+    #[test]
+    fn test_reward_priority_fees() {
+        let epoch_length = 1000;
+        let reward_calculator = RewardCalculator {
+            max_inflation_rate: Rational::new(0, 1),
+            num_blocks_per_year: 1000,
+            epoch_length,
+            protocol_reward_rate: Rational::new(0, 1),
+            protocol_treasury_account: "near".parse().unwrap(),
+            online_min_threshold: Rational::new(9, 10),
+            online_max_threshold: Rational::new(99, 100),
+            num_seconds_per_year: 1000,
+        };
+        let validator_block_chunk_stats = vec![
+            (
+                "test1".parse().unwrap(),
+                BlockChunkValidatorStats {
+                    block_stats: ValidatorStats { produced: 1000, expected: 1000 },
+                    chunk_stats: ValidatorStats { produced: 1000, expected: 1000 },
+                },
+            ),
+            (
+                "test2".parse().unwrap(),
+                BlockChunkValidatorStats {
+                    block_stats: ValidatorStats { produced: 500, expected: 1000 },
+                    chunk_stats: ValidatorStats { produced: 500, expected: 1000 },
+                },
+            ),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        let validator_stake =
+            vec![("test1".parse().unwrap(), 500_000), ("test2".parse().unwrap(), 500_000)]
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+        let result = reward_calculator.calculate_reward(
+            validator_block_chunk_stats,
+            &validator_stake,
+            1_000_000_000,
+            3_000,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+            epoch_length * NUM_NS_IN_SECOND,
+        );
+        assert_eq!(
+            result.0,
+            vec![
+                ("near".parse().unwrap(), 0),
+                ("test1".parse().unwrap(), 2_000u128),
+                ("test2".parse().unwrap(), 1_000u128)
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(result.1, 3_000u128);
+    }
+
     /// Test that under an extreme setting (total supply 100b, epoch length half a day),
     /// reward calculation will not overflow.
     #[test]
@@ -299,6 +372,7 @@ mod tests {
             validator_block_chunk_stats,
             &validator_stake,
             total_supply,
+            0,
             PROTOCOL_VERSION,
             PROTOCOL_VERSION,
             epoch_length * NUM_NS_IN_SECOND,
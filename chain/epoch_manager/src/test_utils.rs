@@ -279,6 +279,7 @@ pub fn record_block_with_final_block_hash(
                 vec![],
                 vec![],
                 DEFAULT_TOTAL_SUPPLY,
+                0,
                 PROTOCOL_VERSION,
                 height * NUM_NS_IN_SECOND,
             ),
@@ -309,6 +310,7 @@ pub fn record_block_with_slashes(
                 vec![],
                 slashed,
                 DEFAULT_TOTAL_SUPPLY,
+                0,
                 PROTOCOL_VERSION,
                 height * NUM_NS_IN_SECOND,
             ),
@@ -382,6 +384,7 @@ pub fn block_info(
         slashed: Default::default(),
         total_supply,
         timestamp_nanosec: height * NUM_NS_IN_SECOND,
+        epoch_priority_fees: 0,
     })
 }
 
@@ -2,6 +2,7 @@ use std::convert::TryFrom;
 
 use actix::Addr;
 
+use near_primitives::types::NumShards;
 use near_primitives::views;
 use node_runtime::config::tx_cost;
 
@@ -38,9 +39,11 @@ pub(crate) async fn convert_transactions_sir_into_local_receipts(
                                 near_primitives::transaction::Action::try_from(action).unwrap()
                             })
                             .collect(),
+                        priority_fee: tx.transaction.priority_fee,
                     },
                     prev_block_gas_price,
                     true,
+                    block.chunks.len() as NumShards,
                     protocol_config.clone().protocol_version,
                 );
                 views::ReceiptView {
@@ -58,6 +61,7 @@ pub(crate) async fn convert_transactions_sir_into_local_receipts(
                         output_data_receivers: vec![],
                         input_data_ids: vec![],
                         actions: tx.transaction.actions.clone(),
+                        refund_to: None,
                     },
                 }
             })
@@ -20,6 +20,8 @@ pub enum RpcBlockError {
         #[serde(skip_serializing)]
         error_message: String,
     },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
@@ -58,6 +60,9 @@ impl From<near_client_primitives::types::GetBlockError> for RpcBlockError {
             near_client_primitives::types::GetBlockError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             }
+            near_client_primitives::types::GetBlockError::GarbageCollected { error_message } => {
+                Self::GarbageCollected { error_message }
+            }
             near_client_primitives::types::GetBlockError::NotSyncedYet => Self::NotSyncedYet,
             near_client_primitives::types::GetBlockError::IOError { error_message } => {
                 Self::InternalError { error_message }
@@ -87,9 +92,9 @@ impl From<RpcBlockError> for crate::errors::RpcError {
                 "DB Not Found Error: {} \n Cause: Unknown",
                 error_message
             ))),
-            RpcBlockError::NotSyncedYet | RpcBlockError::InternalError { .. } => {
-                Some(Value::String(error.to_string()))
-            }
+            RpcBlockError::GarbageCollected { .. }
+            | RpcBlockError::NotSyncedYet
+            | RpcBlockError::InternalError { .. } => Some(Value::String(error.to_string())),
         };
 
         let error_data_value = match serde_json::to_value(error) {
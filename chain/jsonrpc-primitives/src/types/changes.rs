@@ -27,6 +27,19 @@ pub struct RpcStateChangesInBlockResponse {
     pub changes: near_primitives::views::StateChangesKindsView,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcStateChangesHistoryRequest {
+    pub from_height: near_primitives::types::BlockHeight,
+    pub to_height: near_primitives::types::BlockHeight,
+    #[serde(flatten)]
+    pub state_changes_request: near_primitives::views::StateChangesRequestView,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcStateChangesHistoryResponse {
+    pub blocks: near_primitives::views::StateChangesHistoryView,
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcStateChangesError {
@@ -37,6 +50,14 @@ pub enum RpcStateChangesError {
     },
     #[error("There are no fully synchronized blocks yet")]
     NotSyncedYet,
+    #[error(
+        "The range of heights {from_height}..={to_height} spans more than {max_heights} heights"
+    )]
+    TooManyHeights {
+        from_height: near_primitives::types::BlockHeight,
+        to_height: near_primitives::types::BlockHeight,
+        max_heights: near_primitives::types::BlockHeightDelta,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
@@ -53,12 +74,21 @@ impl RpcStateChangesInBlockRequest {
     }
 }
 
+impl RpcStateChangesHistoryRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
 impl From<near_client_primitives::types::GetBlockError> for RpcStateChangesError {
     fn from(error: near_client_primitives::types::GetBlockError) -> Self {
         match error {
             near_client_primitives::types::GetBlockError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             }
+            near_client_primitives::types::GetBlockError::GarbageCollected { error_message } => {
+                Self::UnknownBlock { error_message }
+            }
             near_client_primitives::types::GetBlockError::NotSyncedYet => Self::NotSyncedYet,
             near_client_primitives::types::GetBlockError::IOError { error_message } => {
                 Self::InternalError { error_message }
@@ -85,6 +115,11 @@ impl From<near_client_primitives::types::GetStateChangesError> for RpcStateChang
                 Self::UnknownBlock { error_message }
             }
             near_client_primitives::types::GetStateChangesError::NotSyncedYet => Self::NotSyncedYet,
+            near_client_primitives::types::GetStateChangesError::TooManyHeights {
+                from_height,
+                to_height,
+                max_heights,
+            } => Self::TooManyHeights { from_height, to_height, max_heights },
             near_client_primitives::types::GetStateChangesError::Unreachable {
                 ref error_message,
             } => {
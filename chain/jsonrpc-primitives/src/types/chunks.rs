@@ -35,6 +35,8 @@ pub enum RpcChunkError {
         #[serde(skip_serializing)]
         error_message: String,
     },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("Shard id {shard_id} does not exist")]
     InvalidShardId { shard_id: u64 },
     #[error("Chunk with hash {chunk_hash:?} has never been observed on this node")]
@@ -84,6 +86,9 @@ impl From<near_client_primitives::types::GetChunkError> for RpcChunkError {
             near_client_primitives::types::GetChunkError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             }
+            near_client_primitives::types::GetChunkError::GarbageCollected { error_message } => {
+                Self::GarbageCollected { error_message }
+            }
             near_client_primitives::types::GetChunkError::InvalidShardId { shard_id } => {
                 Self::InvalidShardId { shard_id }
             }
@@ -116,7 +121,9 @@ impl From<RpcChunkError> for crate::errors::RpcError {
                 "DB Not Found Error: {} \n Cause: Unknown",
                 error_message
             ))),
-            RpcChunkError::InvalidShardId { .. } => Some(Value::String(error.to_string())),
+            RpcChunkError::GarbageCollected { .. } | RpcChunkError::InvalidShardId { .. } => {
+                Some(Value::String(error.to_string()))
+            }
             RpcChunkError::UnknownChunk { chunk_hash } => Some(Value::String(format!(
                 "Chunk Missing (unavailable on the node): ChunkHash(`{}`) \n Cause: Unknown",
                 chunk_hash.0.to_string()
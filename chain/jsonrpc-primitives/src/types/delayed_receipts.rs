@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcDelayedReceiptsRequest {
+    #[serde(flatten)]
+    pub block_reference: near_primitives::types::BlockReference,
+    pub shard_id: near_primitives::types::ShardId,
+    /// Queue position of the first receipt to return, defaults to the head of the queue.
+    #[serde(default)]
+    pub from_index: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcDelayedReceiptsResponse {
+    pub block_hash: near_primitives::hash::CryptoHash,
+    pub block_height: near_primitives::types::BlockHeight,
+    pub shard_id: near_primitives::types::ShardId,
+    #[serde(flatten)]
+    pub delayed_receipts: near_primitives::views::DelayedReceiptsView,
+}
+
+#[derive(thiserror::Error, Debug, Serialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcDelayedReceiptsError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
+    UnknownBlock {
+        #[serde(skip_serializing)]
+        error_message: String,
+    },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
+    #[error("There are no fully synchronized blocks on the node yet")]
+    NotSyncedYet,
+    #[error("Shard id {shard_id} does not exist")]
+    InvalidShardId { shard_id: near_primitives::types::ShardId },
+    #[error("The node does not track the shard ID {shard_id}")]
+    UnavailableShard { shard_id: near_primitives::types::ShardId },
+}
+
+impl RpcDelayedReceiptsRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
+impl From<RpcDelayedReceiptsRequest> for near_client_primitives::types::GetDelayedReceipts {
+    fn from(request: RpcDelayedReceiptsRequest) -> Self {
+        Self {
+            block_reference: request.block_reference,
+            shard_id: request.shard_id,
+            from_index: request.from_index,
+            limit: request.limit,
+        }
+    }
+}
+
+impl From<near_client_primitives::types::GetDelayedReceiptsError> for RpcDelayedReceiptsError {
+    fn from(error: near_client_primitives::types::GetDelayedReceiptsError) -> Self {
+        match error {
+            near_client_primitives::types::GetDelayedReceiptsError::IOError { error_message }
+            | near_client_primitives::types::GetDelayedReceiptsError::InternalError {
+                error_message,
+            } => Self::InternalError { error_message },
+            near_client_primitives::types::GetDelayedReceiptsError::UnknownBlock {
+                error_message,
+            } => Self::UnknownBlock { error_message },
+            near_client_primitives::types::GetDelayedReceiptsError::GarbageCollected {
+                error_message,
+            } => Self::GarbageCollected { error_message },
+            near_client_primitives::types::GetDelayedReceiptsError::NotSyncedYet => {
+                Self::NotSyncedYet
+            }
+            near_client_primitives::types::GetDelayedReceiptsError::InvalidShardId { shard_id } => {
+                Self::InvalidShardId { shard_id }
+            }
+            near_client_primitives::types::GetDelayedReceiptsError::UnavailableShard {
+                shard_id,
+            } => Self::UnavailableShard { shard_id },
+            near_client_primitives::types::GetDelayedReceiptsError::Unreachable {
+                ref error_message,
+            } => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                near_metrics::inc_counter_vec(
+                    &crate::metrics::RPC_UNREACHABLE_ERROR_COUNT,
+                    &["RpcDelayedReceiptsError"],
+                );
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcDelayedReceiptsError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcDelayedReceiptsError> for crate::errors::RpcError {
+    fn from(error: RpcDelayedReceiptsError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcDelayedReceiptsError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
@@ -21,7 +21,7 @@ pub struct RpcLightClientExecutionProofResponse {
     pub block_proof: near_primitives::merkle::MerklePath,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RpcLightClientNextBlockResponse {
     #[serde(flatten)]
     pub light_client_block: Option<near_primitives::views::LightClientBlockView>,
@@ -35,6 +35,8 @@ pub enum RpcLightClientProofError {
         #[serde(skip_serializing)]
         error_message: String,
     },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("Inconsistent state. Total number of shards is {number_or_shards} but the execution outcome is in shard {execution_outcome_shard_id}")]
     InconsistentState {
         number_or_shards: usize,
@@ -99,6 +101,9 @@ impl From<near_client_primitives::types::GetExecutionOutcomeError> for RpcLightC
             near_client_primitives::types::GetExecutionOutcomeError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             },
+            near_client_primitives::types::GetExecutionOutcomeError::GarbageCollected { error_message } => {
+                Self::GarbageCollected { error_message }
+            },
             near_client_primitives::types::GetExecutionOutcomeError::InconsistentState {
                 number_or_shards, execution_outcome_shard_id
             } => Self::InconsistentState { number_or_shards, execution_outcome_shard_id },
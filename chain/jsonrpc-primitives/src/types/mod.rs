@@ -2,6 +2,7 @@ pub mod blocks;
 pub mod changes;
 pub mod chunks;
 pub mod config;
+pub mod delayed_receipts;
 pub mod gas_price;
 pub mod light_client;
 pub mod network_info;
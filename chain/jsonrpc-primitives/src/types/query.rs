@@ -10,6 +10,9 @@ pub struct RpcQueryRequest {
     pub block_reference: near_primitives::types::BlockReference,
     #[serde(flatten)]
     pub request: near_primitives::views::QueryRequest,
+    /// Return the trie nodes needed to verify the result against the chunk state root.
+    #[serde(default)]
+    pub include_proof: bool,
 }
 
 #[derive(thiserror::Error, Debug, Serialize)]
@@ -69,6 +72,8 @@ pub struct RpcQueryResponse {
     pub kind: QueryResponseKind,
     pub block_height: near_primitives::types::BlockHeight,
     pub block_hash: near_primitives::hash::CryptoHash,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<near_primitives::views::TrieProofPath>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -151,6 +156,7 @@ impl RpcQueryRequest {
             RpcQueryRequest {
                 request,
                 block_reference: near_primitives::types::BlockReference::latest(),
+                include_proof: false,
             }
         } else {
             crate::utils::parse_params::<RpcQueryRequest>(value)?
@@ -220,6 +226,7 @@ impl From<near_primitives::views::QueryResponse> for RpcQueryResponse {
             kind: query_response.kind.into(),
             block_hash: query_response.block_hash,
             block_height: query_response.block_height,
+            proof: query_response.proof,
         }
     }
 }
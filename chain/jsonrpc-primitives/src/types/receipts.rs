@@ -25,6 +25,8 @@ pub enum RpcReceiptError {
     InternalError { error_message: String },
     #[error("Receipt with id {receipt_id} has never been observed on this node")]
     UnknownReceipt { receipt_id: near_primitives::hash::CryptoHash },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
 }
 
 impl From<ReceiptReference> for near_client_primitives::types::GetReceipt {
@@ -49,6 +51,9 @@ impl From<near_client_primitives::types::GetReceiptError> for RpcReceiptError {
             near_client_primitives::types::GetReceiptError::UnknownReceipt(hash) => {
                 Self::UnknownReceipt { receipt_id: hash }
             }
+            near_client_primitives::types::GetReceiptError::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
             near_client_primitives::types::GetReceiptError::Unreachable(ref error_message) => {
                 tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
                 near_metrics::inc_counter_vec(
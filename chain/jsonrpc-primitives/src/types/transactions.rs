@@ -36,6 +36,8 @@ pub enum RpcTransactionError {
     RequestRouted { transaction_hash: near_primitives::hash::CryptoHash },
     #[error("Transaction {requested_transaction_hash} doesn't exist")]
     UnknownTransaction { requested_transaction_hash: near_primitives::hash::CryptoHash },
+    #[error("{error_message}")]
+    GarbageCollected { error_message: String },
     #[error("The node reached its limits. Try again later. More details: {debug_info}")]
     InternalError { debug_info: String },
     #[error("Timeout")]
@@ -86,6 +88,9 @@ impl From<near_client_primitives::types::TxStatusError> for RpcTransactionError
             near_client_primitives::types::TxStatusError::MissingTransaction(
                 requested_transaction_hash,
             ) => Self::UnknownTransaction { requested_transaction_hash },
+            near_client_primitives::types::TxStatusError::GarbageCollected(error_message) => {
+                Self::GarbageCollected { error_message }
+            }
             near_client_primitives::types::TxStatusError::InvalidTx(context) => {
                 Self::InvalidTransaction { context }
             }
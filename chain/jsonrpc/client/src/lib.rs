@@ -7,7 +7,10 @@ use serde::Serialize;
 
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{from_slice, Message};
-use near_jsonrpc_primitives::types::changes::{RpcStateChangesRequest, RpcStateChangesResponse};
+use near_jsonrpc_primitives::types::changes::{
+    RpcStateChangesHistoryRequest, RpcStateChangesHistoryResponse, RpcStateChangesRequest,
+    RpcStateChangesResponse,
+};
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, BlockId, BlockReference, MaybeBlockId, ShardId};
@@ -225,6 +228,23 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "block", request)
     }
 
+    pub fn next_light_client_block(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientNextBlockRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::light_client::RpcLightClientNextBlockResponse>
+    {
+        call_method(&self.client, &self.server_addr, "next_light_client_block", request)
+    }
+
+    pub fn light_client_proof(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofRequest,
+    ) -> RpcRequest<
+        near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofResponse,
+    > {
+        call_method(&self.client, &self.server_addr, "light_client_proof", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_changes(
         &self,
@@ -233,6 +253,14 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_changes", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_changes_history(
+        &self,
+        request: RpcStateChangesHistoryRequest,
+    ) -> RpcRequest<RpcStateChangesHistoryResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_changes_history", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_validators_ordered(
         &self,
@@ -249,6 +277,15 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_delayed_receipts(
+        &self,
+        request: near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsResponse>
+    {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_delayed_receipts", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
@@ -13,10 +13,10 @@ use tracing::info;
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    ClientActor, GetBlock, GetBlockProof, GetChunk, GetDelayedReceipts, GetExecutionOutcome,
+    GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt,
+    GetStateChanges, GetStateChangesHistory, GetStateChangesInBlock, GetValidatorInfo,
+    GetValidatorOrdered, Query, Status, TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
@@ -368,6 +368,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(state_changes)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_changes_history" => {
+                let rpc_state_changes_request =
+                    near_jsonrpc_primitives::types::changes::RpcStateChangesHistoryRequest::parse(
+                        request.params,
+                    )?;
+                let state_changes = self.changes_history(rpc_state_changes_request).await?;
+                serde_json::to_value(state_changes)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_check_tx" => {
                 let rpc_transaction_request =
                     near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
@@ -390,6 +399,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_light_client_execution_proof_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_delayed_receipts" => {
+                let rpc_delayed_receipts_request =
+                    near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsRequest::parse(
+                        request.params,
+                    )?;
+                let delayed_receipts = self.delayed_receipts(rpc_delayed_receipts_request).await?;
+                serde_json::to_value(delayed_receipts)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_protocol_config" => {
                 let rpc_protocol_config_request =
                     near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest::parse(
@@ -776,7 +794,11 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::query::RpcQueryResponse,
         near_jsonrpc_primitives::types::query::RpcQueryError,
     > {
-        let query = Query::new(request_data.block_reference, request_data.request);
+        let query = if request_data.include_proof {
+            Query::with_proof(request_data.block_reference, request_data.request)
+        } else {
+            Query::new(request_data.block_reference, request_data.request)
+        };
         Ok(self.view_client_addr.send(query).await??.into())
     }
 
@@ -815,6 +837,23 @@ impl JsonRpcHandler {
         Ok(near_jsonrpc_primitives::types::chunks::RpcChunkResponse { chunk_view })
     }
 
+    async fn delayed_receipts(
+        &self,
+        request: near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsResponse,
+        near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsError,
+    > {
+        let shard_id = request.shard_id;
+        let response = self.view_client_addr.send(GetDelayedReceipts::from(request)).await??;
+        Ok(near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsResponse {
+            block_hash: response.block_hash,
+            block_height: response.block_height,
+            shard_id,
+            delayed_receipts: response.delayed_receipts,
+        })
+    }
+
     async fn receipt(
         &self,
         request_data: near_jsonrpc_primitives::types::receipts::RpcReceiptRequest,
@@ -880,6 +919,25 @@ impl JsonRpcHandler {
         })
     }
 
+    async fn changes_history(
+        &self,
+        request: near_jsonrpc_primitives::types::changes::RpcStateChangesHistoryRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::changes::RpcStateChangesHistoryResponse,
+        near_jsonrpc_primitives::types::changes::RpcStateChangesError,
+    > {
+        let blocks = self
+            .view_client_addr
+            .send(GetStateChangesHistory {
+                from_height: request.from_height,
+                to_height: request.to_height,
+                state_changes_request: request.state_changes_request,
+            })
+            .await??;
+
+        Ok(near_jsonrpc_primitives::types::changes::RpcStateChangesHistoryResponse { blocks })
+    }
+
     async fn next_light_client_block(
         &self,
         request: near_jsonrpc_primitives::types::light_client::RpcLightClientNextBlockRequest,
@@ -8,14 +8,16 @@ use near_actix_test_utils::run_actix;
 use near_crypto::{KeyType, PublicKey, Signature};
 use near_jsonrpc::client::new_client;
 use near_jsonrpc_client::ChunkId;
+use near_jsonrpc_primitives::types::delayed_receipts::RpcDelayedReceiptsRequest;
 use near_jsonrpc_primitives::types::query::QueryResponseKind;
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::WaitOrTimeout;
 use near_primitives::account::{AccessKey, AccessKeyPermission};
-use near_primitives::hash::CryptoHash;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::state_proof::StateProof;
 use near_primitives::types::{BlockId, BlockReference, ShardId, SyncCheckpoint};
-use near_primitives::views::QueryRequest;
+use near_primitives::views::{AccountView, QueryRequest};
 
 #[macro_use]
 pub mod test_utils;
@@ -131,6 +133,74 @@ fn test_chunk_invalid_shard_id() {
     });
 }
 
+/// Retrieve the delayed receipts queue of a shard via json rpc
+#[test]
+fn test_delayed_receipts() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let delayed_receipts = client
+            .EXPERIMENTAL_delayed_receipts(RpcDelayedReceiptsRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(0)),
+                shard_id: 0,
+                from_index: None,
+                limit: Some(10),
+            })
+            .await
+            .unwrap();
+        assert_eq!(delayed_receipts.block_height, 0);
+        assert_eq!(delayed_receipts.shard_id, 0);
+        assert_eq!(delayed_receipts.delayed_receipts.length, 0);
+        assert!(delayed_receipts.delayed_receipts.receipts.is_empty());
+
+        let error = client
+            .EXPERIMENTAL_delayed_receipts(RpcDelayedReceiptsRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(0)),
+                shard_id: 100,
+                from_index: None,
+                limit: None,
+            })
+            .await
+            .unwrap_err();
+        let s = serde_json::to_string(&error.data.unwrap()).unwrap();
+        assert!(s.contains("INVALID_SHARD_ID"), "{}", s);
+    });
+}
+
+/// Connect to json rpc, query account info with a proof and check it against the state root.
+#[test]
+fn test_query_account_with_proof() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let query_response = client
+            .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(0)),
+                request: QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+                include_proof: true,
+            })
+            .await
+            .unwrap();
+        let account_info = if let QueryResponseKind::ViewAccount(ref account) = query_response.kind
+        {
+            account.clone()
+        } else {
+            panic!("queried account, but received something else: {:?}", query_response.kind);
+        };
+        let chunk = client
+            .chunk(ChunkId::BlockShardId(BlockId::Height(0), ShardId::from(0u64)))
+            .await
+            .unwrap();
+        let state_root = chunk.header.prev_state_root;
+
+        let proof = StateProof::from_trie_proof_path(&query_response.proof.unwrap()).unwrap();
+        let account = proof.get_account(&state_root, &"test".parse().unwrap()).unwrap().unwrap();
+        assert_eq!(AccountView::from(account), account_info);
+        // The proof is only valid for the state root it was generated for.
+        assert!(proof
+            .get_account(&CryptoHash::default(), &"test".parse().unwrap())
+            .unwrap()
+            .is_none());
+        assert!(proof.get_account(&hash(b"other"), &"test".parse().unwrap()).is_err());
+    });
+}
+
 /// Connect to json rpc and query account info with soft-deprecated query API.
 #[test]
 fn test_query_by_path_account() {
@@ -164,6 +234,7 @@ fn test_query_account() {
             .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
                 block_reference: BlockReference::latest(),
                 request: QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -171,6 +242,7 @@ fn test_query_account() {
             .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
                 block_reference: BlockReference::BlockId(BlockId::Height(0)),
                 request: QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -178,6 +250,7 @@ fn test_query_account() {
             .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
                 block_reference: BlockReference::BlockId(BlockId::Hash(block_hash)),
                 request: QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -227,6 +300,7 @@ fn test_query_access_keys() {
             .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
                 block_reference: BlockReference::latest(),
                 request: QueryRequest::ViewAccessKeyList { account_id: "test".parse().unwrap() },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -278,6 +352,7 @@ fn test_query_access_key() {
                         .parse()
                         .unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -303,6 +378,7 @@ fn test_query_state() {
                     account_id: "test".parse().unwrap(),
                     prefix: vec![].into(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -328,6 +404,7 @@ fn test_query_call_function() {
                     method_name: "method".to_string(),
                     args: vec![].into(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -353,6 +430,7 @@ fn test_query_contract_code() {
             .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
                 block_reference: BlockReference::latest(),
                 request: QueryRequest::ViewCode { account_id: "test".parse().unwrap() },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -564,6 +642,7 @@ fn test_query_view_account_non_existing_account_must_return_error() {
                 request: QueryRequest::ViewAccount {
                     account_id: "invalidaccount".parse().unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -586,6 +665,7 @@ fn test_view_access_key_non_existing_account_id_and_public_key_must_return_error
                     account_id: "\u{0}\u{0}\u{0}\u{0}\u{0}9".parse().unwrap(),
                     public_key: "99999999999999999999999999999999999999999999".parse().unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -613,6 +693,7 @@ fn test_call_function_non_existing_account_method_name() {
                         .parse()
                         .unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -634,6 +715,7 @@ fn test_view_access_key_list_non_existing_account() {
                 request: QueryRequest::ViewAccessKeyList {
                     account_id: "\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{c}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0},".parse().unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -656,6 +738,7 @@ fn test_view_state_non_existing_account_invalid_prefix() {
                     account_id: "\u{0}\u{0}\u{0}\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{8}\u{0}\u{0}\u{0}\u{0}\u{0}eeeeeeeeeeeeeeeeeeeeeeeeeeeee".parse().unwrap(),
                     prefix: "eeeeeeeeeeee".as_bytes().to_vec().into(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
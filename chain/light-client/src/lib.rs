@@ -0,0 +1,375 @@
+//! Light client that follows the chain by verifying light client blocks produced by
+//! `next_light_client_block` and checks execution outcome proofs returned by `light_client_proof`.
+//!
+//! The verification rules follow the light client specification
+//! (https://nomicon.io/ChainSpec/LightClient.html).
+use std::collections::HashMap;
+
+use borsh::BorshSerialize;
+
+use near_primitives::block::{Approval, ApprovalInner};
+use near_primitives::block_header::BlockHeaderInnerLite;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::{
+    combine_hash, compute_root_from_path, compute_root_from_path_and_item, verify_hash, MerklePath,
+};
+use near_primitives::types::validator_stake::ValidatorStake;
+use near_primitives::types::{Balance, BlockHeight};
+use near_primitives::views::validator_stake_view::ValidatorStakeView;
+use near_primitives::views::{
+    BlockHeaderInnerLiteView, ExecutionOutcomeWithIdView, LightClientBlockLiteView,
+    LightClientBlockView,
+};
+
+pub use crate::rpc::sync_with_rpc;
+
+mod rpc;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum LightClientError {
+    #[error("Block at height {height} is not newer than the head at height {head_height}")]
+    NotNewerThanHead { height: BlockHeight, head_height: BlockHeight },
+    #[error("Block epoch {epoch_id} is neither the epoch nor the next epoch of the head")]
+    UnexpectedEpoch { epoch_id: CryptoHash },
+    #[error("Block producers of epoch {epoch_id} are unknown")]
+    UnknownBlockProducers { epoch_id: CryptoHash },
+    #[error("Block starts a new epoch but doesn't contain the next block producers")]
+    MissingNextBlockProducers,
+    #[error("Approval of {account_id} has invalid signature")]
+    InvalidApprovalSignature { account_id: near_primitives::types::AccountId },
+    #[error("Approved stake {approved_stake} out of {total_stake} is not more than 2/3")]
+    NotEnoughApprovals { approved_stake: Balance, total_stake: Balance },
+    #[error("Hash of the next block producers doesn't match `next_bp_hash`")]
+    InvalidNextBlockProducersHash,
+    #[error("Execution outcome has a malformed success value")]
+    MalformedExecutionOutcome,
+    #[error("Execution outcome is not included in the outcome root of block {block_hash}")]
+    InvalidOutcomeProof { block_hash: CryptoHash },
+    #[error("Block header hash {computed} doesn't match outcome block hash {expected}")]
+    InvalidBlockHeader { expected: CryptoHash, computed: CryptoHash },
+    #[error("Block {block_hash} is not included in the block merkle root of the head")]
+    InvalidBlockProof { block_hash: CryptoHash },
+}
+
+/// Hash of the block described by the light client view of its header.
+pub fn compute_block_hash(
+    prev_block_hash: &CryptoHash,
+    inner_lite: &BlockHeaderInnerLiteView,
+    inner_rest_hash: &CryptoHash,
+) -> CryptoHash {
+    let inner_lite = BlockHeaderInnerLite::from(inner_lite.clone());
+    let inner_lite_hash = hash(&inner_lite.try_to_vec().expect("Failed to serialize"));
+    let inner_hash = combine_hash(inner_lite_hash, *inner_rest_hash);
+    combine_hash(inner_hash, *prev_block_hash)
+}
+
+pub fn light_client_block_hash(block: &LightClientBlockView) -> CryptoHash {
+    compute_block_hash(&block.prev_block_hash, &block.inner_lite, &block.inner_rest_hash)
+}
+
+pub fn light_client_block_lite_hash(block: &LightClientBlockLiteView) -> CryptoHash {
+    compute_block_hash(&block.prev_block_hash, &block.inner_lite, &block.inner_rest_hash)
+}
+
+/// Light client state: the last verified block and block producers of the epochs it knows about.
+pub struct LightClient {
+    head: LightClientBlockView,
+    head_hash: CryptoHash,
+    epoch_block_producers: HashMap<CryptoHash, Vec<ValidatorStakeView>>,
+}
+
+impl LightClient {
+    /// Creates a light client from a trusted block and the ordered block producers of its epoch.
+    pub fn new(head: LightClientBlockView, block_producers: Vec<ValidatorStakeView>) -> Self {
+        let mut epoch_block_producers = HashMap::new();
+        epoch_block_producers.insert(head.inner_lite.epoch_id, block_producers);
+        if let Some(next_bps) = &head.next_bps {
+            epoch_block_producers.insert(head.inner_lite.next_epoch_id, next_bps.clone());
+        }
+        let head_hash = light_client_block_hash(&head);
+        Self { head, head_hash, epoch_block_producers }
+    }
+
+    pub fn head(&self) -> &LightClientBlockView {
+        &self.head
+    }
+
+    pub fn head_hash(&self) -> &CryptoHash {
+        &self.head_hash
+    }
+
+    /// Checks that `block` is a valid successor of the current head without updating the head.
+    pub fn validate_block(&self, block: &LightClientBlockView) -> Result<(), LightClientError> {
+        if block.inner_lite.height <= self.head.inner_lite.height {
+            return Err(LightClientError::NotNewerThanHead {
+                height: block.inner_lite.height,
+                head_height: self.head.inner_lite.height,
+            });
+        }
+
+        let epoch_id = block.inner_lite.epoch_id;
+        if epoch_id != self.head.inner_lite.epoch_id
+            && epoch_id != self.head.inner_lite.next_epoch_id
+        {
+            return Err(LightClientError::UnexpectedEpoch { epoch_id });
+        }
+        if epoch_id == self.head.inner_lite.next_epoch_id && block.next_bps.is_none() {
+            return Err(LightClientError::MissingNextBlockProducers);
+        }
+
+        let block_producers = self
+            .epoch_block_producers
+            .get(&epoch_id)
+            .ok_or(LightClientError::UnknownBlockProducers { epoch_id })?;
+
+        let next_block_hash =
+            combine_hash(block.next_block_inner_hash, light_client_block_hash(block));
+        let approval_message = Approval::get_data_for_sig(
+            &ApprovalInner::Endorsement(next_block_hash),
+            block.inner_lite.height + 2,
+        );
+
+        // The stake of every block producer of the epoch counts towards the total, so that
+        // dropping approvals from the end of the list doesn't lower the threshold. Approvals past
+        // the block producers of the epoch belong to the producers of the next one and are ignored.
+        let mut total_stake = 0;
+        let mut approved_stake = 0;
+        for (index, block_producer) in block_producers.iter().enumerate() {
+            let block_producer = ValidatorStake::from(block_producer.clone());
+            total_stake += block_producer.stake();
+
+            let signature = match block.approvals_after_next.get(index) {
+                Some(Some(signature)) => signature,
+                _ => continue,
+            };
+            if !signature.verify(&approval_message, block_producer.public_key()) {
+                return Err(LightClientError::InvalidApprovalSignature {
+                    account_id: block_producer.take_account_id(),
+                });
+            }
+            approved_stake += block_producer.stake();
+        }
+
+        if approved_stake * 3 <= total_stake * 2 {
+            return Err(LightClientError::NotEnoughApprovals { approved_stake, total_stake });
+        }
+
+        if let Some(next_bps) = &block.next_bps {
+            if !verify_next_bp_hash(next_bps, &block.inner_lite.next_bp_hash) {
+                return Err(LightClientError::InvalidNextBlockProducersHash);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates `block` and makes it the new head.
+    pub fn update_head(&mut self, block: LightClientBlockView) -> Result<(), LightClientError> {
+        self.validate_block(&block)?;
+        if let Some(next_bps) = &block.next_bps {
+            self.epoch_block_producers.insert(block.inner_lite.next_epoch_id, next_bps.clone());
+        }
+        self.head_hash = light_client_block_hash(&block);
+        self.head = block;
+        Ok(())
+    }
+
+    /// Verifies an execution outcome proof returned by `light_client_proof` requested with the
+    /// current head as `light_client_head`.
+    ///
+    /// # Arguments
+    ///  * `outcome_proof` - execution outcome with the path to the chunk outcome root
+    ///  * `outcome_root_proof` - path from the chunk outcome root to the block outcome root
+    ///  * `block_header_lite` - header of the block containing the outcome root
+    ///  * `block_proof` - path from the block to the block merkle root of the head
+    pub fn verify_execution_outcome(
+        &self,
+        outcome_proof: &ExecutionOutcomeWithIdView,
+        outcome_root_proof: &MerklePath,
+        block_header_lite: &LightClientBlockLiteView,
+        block_proof: &MerklePath,
+    ) -> Result<(), LightClientError> {
+        let outcome_hashes =
+            outcome_proof.to_hashes().ok_or(LightClientError::MalformedExecutionOutcome)?;
+        let shard_outcome_root =
+            compute_root_from_path_and_item(&outcome_proof.proof, &outcome_hashes);
+        let block_outcome_root =
+            compute_root_from_path(outcome_root_proof, hash(shard_outcome_root.as_ref()));
+        if block_outcome_root != block_header_lite.inner_lite.outcome_root {
+            return Err(LightClientError::InvalidOutcomeProof {
+                block_hash: outcome_proof.block_hash,
+            });
+        }
+
+        let block_hash = light_client_block_lite_hash(block_header_lite);
+        if block_hash != outcome_proof.block_hash {
+            return Err(LightClientError::InvalidBlockHeader {
+                expected: outcome_proof.block_hash,
+                computed: block_hash,
+            });
+        }
+
+        if !verify_hash(self.head.inner_lite.block_merkle_root, block_proof, block_hash) {
+            return Err(LightClientError::InvalidBlockProof { block_hash });
+        }
+        Ok(())
+    }
+}
+
+/// Block producers hash is computed over `ValidatorStake` or, before `BlockHeaderV3`, over
+/// `ValidatorStakeV1`, so accept either of them.
+fn verify_next_bp_hash(next_bps: &[ValidatorStakeView], next_bp_hash: &CryptoHash) -> bool {
+    let stakes: Vec<ValidatorStake> = next_bps.iter().cloned().map(ValidatorStake::from).collect();
+    if hash(&stakes.try_to_vec().expect("Failed to serialize")) == *next_bp_hash {
+        return true;
+    }
+    let stakes_v1 = stakes.into_iter().map(|stake| stake.into_v1()).collect::<Vec<_>>();
+    hash(&stakes_v1.try_to_vec().expect("Failed to serialize")) == *next_bp_hash
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
+    use near_primitives::types::validator_stake::ValidatorStake;
+    use near_primitives::views::validator_stake_view::ValidatorStakeView;
+
+    use super::*;
+
+    fn block_producers() -> Vec<ValidatorStakeView> {
+        vec![
+            ValidatorStake::new(
+                "test0".parse().unwrap(),
+                PublicKey::empty(KeyType::ED25519),
+                1_000,
+            )
+            .into(),
+            ValidatorStake::new(
+                "test1".parse().unwrap(),
+                PublicKey::empty(KeyType::ED25519),
+                2_000,
+            )
+            .into(),
+        ]
+    }
+
+    fn head() -> LightClientBlockView {
+        LightClientBlockView {
+            prev_block_hash: CryptoHash::default(),
+            next_block_inner_hash: CryptoHash::default(),
+            inner_lite: BlockHeaderInnerLiteView {
+                height: 10,
+                epoch_id: CryptoHash::default(),
+                next_epoch_id: hash(&[1]),
+                prev_state_root: CryptoHash::default(),
+                outcome_root: CryptoHash::default(),
+                timestamp: 0,
+                timestamp_nanosec: 0,
+                next_bp_hash: CryptoHash::default(),
+                block_merkle_root: CryptoHash::default(),
+            },
+            inner_rest_hash: CryptoHash::default(),
+            next_bps: None,
+            approvals_after_next: vec![],
+        }
+    }
+
+    /// Approval of `block` by `signer`, as included in `approvals_after_next`.
+    fn approve(signer: &InMemorySigner, block: &LightClientBlockView) -> Option<Signature> {
+        let next_block_hash =
+            combine_hash(block.next_block_inner_hash, light_client_block_hash(block));
+        Some(signer.sign(&Approval::get_data_for_sig(
+            &ApprovalInner::Endorsement(next_block_hash),
+            block.inner_lite.height + 2,
+        )))
+    }
+
+    #[test]
+    fn test_next_bp_hash() {
+        let bps = block_producers();
+        let stakes: Vec<ValidatorStake> = bps.iter().cloned().map(ValidatorStake::from).collect();
+        let bp_hash = hash(&stakes.try_to_vec().unwrap());
+        assert!(verify_next_bp_hash(&bps, &bp_hash));
+        assert!(!verify_next_bp_hash(&bps[1..], &bp_hash));
+    }
+
+    #[test]
+    fn test_reject_old_block() {
+        let head = head();
+        let client = LightClient::new(head.clone(), block_producers());
+
+        let mut block = head.clone();
+        block.inner_lite.height = 9;
+        assert_eq!(
+            client.validate_block(&block),
+            Err(LightClientError::NotNewerThanHead { height: 9, head_height: 10 })
+        );
+
+        block.inner_lite.height = 11;
+        block.inner_lite.epoch_id = hash(&[2]);
+        assert_eq!(
+            client.validate_block(&block),
+            Err(LightClientError::UnexpectedEpoch { epoch_id: hash(&[2]) })
+        );
+
+        block.inner_lite.epoch_id = hash(&[1]);
+        assert_eq!(client.validate_block(&block), Err(LightClientError::MissingNextBlockProducers));
+
+        block.inner_lite.epoch_id = CryptoHash::default();
+        block.approvals_after_next = vec![None, None];
+        assert_eq!(
+            client.validate_block(&block),
+            Err(LightClientError::NotEnoughApprovals { approved_stake: 0, total_stake: 3_000 })
+        );
+    }
+
+    #[test]
+    fn test_validate_approvals() {
+        let signers: Vec<_> = ["test0", "test1", "test2", "test3"]
+            .iter()
+            .map(|account_id| {
+                InMemorySigner::from_seed(account_id.parse().unwrap(), KeyType::ED25519, account_id)
+            })
+            .collect();
+        let block_producers: Vec<ValidatorStakeView> = signers
+            .iter()
+            .zip([1_000, 1_000, 1_000, 3_000].iter())
+            .map(|(signer, stake)| {
+                ValidatorStake::new(signer.account_id.clone(), signer.public_key(), *stake).into()
+            })
+            .collect();
+        let client = LightClient::new(head(), block_producers);
+
+        let mut block = head();
+        block.inner_lite.height = 11;
+        let approvals: Vec<_> = signers.iter().map(|signer| approve(signer, &block)).collect();
+        block.approvals_after_next = approvals.clone();
+        assert_eq!(client.validate_block(&block), Ok(()));
+
+        // The approvals don't sign a block with a changed header.
+        let mut forged_block = block.clone();
+        forged_block.inner_lite.outcome_root = hash(&[3]);
+        assert_eq!(
+            client.validate_block(&forged_block),
+            Err(LightClientError::InvalidApprovalSignature {
+                account_id: "test0".parse().unwrap()
+            })
+        );
+
+        // The missing approvals of a truncated list still count towards the total stake.
+        block.approvals_after_next = approvals[..3].to_vec();
+        assert_eq!(
+            client.validate_block(&block),
+            Err(LightClientError::NotEnoughApprovals { approved_stake: 3_000, total_stake: 6_000 })
+        );
+
+        // Exactly 2/3 of the stake is not enough.
+        block.approvals_after_next = vec![approvals[0].clone(), None, None, approvals[3].clone()];
+        assert_eq!(
+            client.validate_block(&block),
+            Err(LightClientError::NotEnoughApprovals { approved_stake: 4_000, total_stake: 6_000 })
+        );
+        block.approvals_after_next =
+            vec![approvals[0].clone(), approvals[1].clone(), None, approvals[3].clone()];
+        assert_eq!(client.validate_block(&block), Ok(()));
+    }
+}
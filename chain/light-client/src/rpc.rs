@@ -0,0 +1,96 @@
+use near_jsonrpc_client::JsonRpcClient;
+use near_jsonrpc_primitives::errors::RpcError;
+use near_jsonrpc_primitives::types::light_client::{
+    RpcLightClientExecutionProofRequest, RpcLightClientNextBlockRequest,
+};
+use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockId, TransactionOrReceiptId};
+use tracing::debug;
+
+use crate::{light_client_block_hash, LightClient, LightClientError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SyncError {
+    #[error("RPC request failed: {0:?}")]
+    Rpc(RpcError),
+    #[error("Node returned a block that failed verification: {0}")]
+    Verification(#[from] LightClientError),
+    #[error("Node doesn't have a light client block after {0}")]
+    NoLightClientBlock(CryptoHash),
+}
+
+impl From<RpcError> for SyncError {
+    fn from(error: RpcError) -> Self {
+        SyncError::Rpc(error)
+    }
+}
+
+impl LightClient {
+    /// Creates a light client from the light client block the node returns after
+    /// `last_block_hash`.
+    ///
+    /// The node is trusted to return the correct block and block producers here, so only use this
+    /// with a node you trust or with a checkpoint obtained out of band.
+    pub async fn bootstrap_from_rpc(
+        rpc: &JsonRpcClient,
+        last_block_hash: CryptoHash,
+    ) -> Result<Self, SyncError> {
+        let head = rpc
+            .next_light_client_block(RpcLightClientNextBlockRequest { last_block_hash })
+            .await?
+            .light_client_block
+            .ok_or(SyncError::NoLightClientBlock(last_block_hash))?;
+        let block_producers = rpc
+            .EXPERIMENTAL_validators_ordered(RpcValidatorsOrderedRequest {
+                block_id: Some(BlockId::Hash(light_client_block_hash(&head))),
+            })
+            .await?;
+        Ok(LightClient::new(head, block_producers))
+    }
+
+    /// Requests an execution outcome proof for the current head and verifies it.
+    pub async fn verify_execution_outcome_with_rpc(
+        &self,
+        rpc: &JsonRpcClient,
+        id: TransactionOrReceiptId,
+    ) -> Result<(), SyncError> {
+        let response = rpc
+            .light_client_proof(RpcLightClientExecutionProofRequest {
+                id,
+                light_client_head: *self.head_hash(),
+            })
+            .await?;
+        self.verify_execution_outcome(
+            &response.outcome_proof,
+            &response.outcome_root_proof,
+            &response.block_header_lite,
+            &response.block_proof,
+        )?;
+        Ok(())
+    }
+}
+
+/// Advances the light client as far as the node allows by repeatedly requesting the next light
+/// client block and verifying it. Returns the number of blocks applied.
+pub async fn sync_with_rpc(
+    light_client: &mut LightClient,
+    rpc: &JsonRpcClient,
+) -> Result<usize, SyncError> {
+    let mut applied = 0;
+    loop {
+        let last_block_hash = *light_client.head_hash();
+        let block = rpc
+            .next_light_client_block(RpcLightClientNextBlockRequest { last_block_hash })
+            .await?
+            .light_client_block;
+        match block {
+            Some(block) if block.inner_lite.height > light_client.head().inner_lite.height => {
+                debug!(target: "light_client", "Applying light client block at height {}", block.inner_lite.height);
+                light_client.update_head(block)?;
+                applied += 1;
+            }
+            _ => return Ok(applied),
+        }
+    }
+}
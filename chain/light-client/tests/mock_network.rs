@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use actix::{Actor, System};
+
+use near_actix_test_utils::run_actix;
+use near_client::test_utils::setup_mock_all_validators;
+use near_client::{GetNextLightClientBlock, GetValidatorOrdered};
+use near_light_client::{light_client_block_hash, LightClient};
+use near_logger_utils::init_test_logger;
+use near_network::test_utils::WaitOrTimeout;
+use near_network::{NetworkResponses, PeerInfo};
+use near_primitives::types::{AccountId, BlockId};
+
+/// Runs four validators with a short epoch and follows the chain with a light client that only
+/// trusts the first light client block it receives. Every following block must pass verification
+/// against the block producers learned from the previous ones, across several epoch switches.
+#[test]
+fn test_light_client_follows_epoch_switches() {
+    init_test_logger();
+
+    run_actix(async {
+        let validators: Vec<Vec<AccountId>> = vec![["test1", "test2", "test3", "test4"]
+            .iter()
+            .map(|account_id| account_id.parse().unwrap())
+            .collect()];
+        let key_pairs = (0..4).map(|_| PeerInfo::random()).collect::<Vec<_>>();
+
+        let (genesis_block, conn, _) = setup_mock_all_validators(
+            validators,
+            key_pairs,
+            1,
+            true,
+            200,
+            false,
+            false,
+            5,
+            true,
+            vec![false; 4],
+            vec![false; 4],
+            false,
+            Arc::new(RwLock::new(Box::new(|_, _| (NetworkResponses::NoResponse, true)))),
+        );
+        let genesis_hash = *genesis_block.hash();
+        let view_client = conn[0].1.clone();
+
+        let light_client: Arc<RwLock<Option<LightClient>>> = Arc::new(RwLock::new(None));
+        let seen_epochs = Arc::new(RwLock::new(HashSet::new()));
+        let request_in_flight = Arc::new(AtomicBool::new(false));
+
+        WaitOrTimeout::new(
+            Box::new(move |_ctx| {
+                if request_in_flight.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let view_client = view_client.clone();
+                let light_client = light_client.clone();
+                let seen_epochs = seen_epochs.clone();
+                let request_in_flight = request_in_flight.clone();
+
+                actix::spawn(async move {
+                    let last_block_hash = light_client
+                        .read()
+                        .unwrap()
+                        .as_ref()
+                        .map_or(genesis_hash, |light_client| *light_client.head_hash());
+                    let block = view_client
+                        .send(GetNextLightClientBlock { last_block_hash })
+                        .await
+                        .unwrap()
+                        .unwrap();
+
+                    if let Some(block) = block {
+                        let is_bootstrapped = light_client.read().unwrap().is_some();
+                        if is_bootstrapped {
+                            light_client
+                                .write()
+                                .unwrap()
+                                .as_mut()
+                                .unwrap()
+                                .update_head(block)
+                                .unwrap();
+                        } else {
+                            let block_producers = view_client
+                                .send(GetValidatorOrdered {
+                                    block_id: Some(BlockId::Hash(light_client_block_hash(&block))),
+                                })
+                                .await
+                                .unwrap()
+                                .unwrap();
+                            *light_client.write().unwrap() =
+                                Some(LightClient::new(block, block_producers));
+                        }
+
+                        let epoch_id = light_client
+                            .read()
+                            .unwrap()
+                            .as_ref()
+                            .unwrap()
+                            .head()
+                            .inner_lite
+                            .epoch_id;
+                        let mut seen_epochs = seen_epochs.write().unwrap();
+                        seen_epochs.insert(epoch_id);
+                        if seen_epochs.len() >= 4 {
+                            System::current().stop();
+                        }
+                    }
+                    request_in_flight.store(false, Ordering::SeqCst);
+                });
+            }),
+            100,
+            60000,
+        )
+        .start();
+    });
+}
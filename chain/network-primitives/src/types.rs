@@ -521,6 +521,9 @@ pub struct NetworkConfig {
     pub highest_peer_horizon: u64,
     /// Period between pushing network info to client
     pub push_info_period: Duration,
+    /// Period between pinging all reachable peers to measure round trip time.
+    /// Latency probing is disabled if not set.
+    pub ping_period: Option<Duration>,
     /// Peers on blacklist by IP:Port.
     /// Nodes will not accept or try to establish connection to such peers.
     pub blacklist: HashMap<IpAddr, BlockedPorts>,
@@ -563,6 +566,7 @@ impl NetworkConfig {
             max_routes_to_store: 1,
             highest_peer_horizon: 5,
             push_info_period: Duration::from_millis(100),
+            ping_period: None,
             blacklist: HashMap::new(),
             outbound_disabled: false,
             archive: false,
@@ -792,6 +796,20 @@ pub struct KnownProducer {
     pub peer_id: PeerId,
 }
 
+/// Round trip time statistics to a reachable peer, measured with routed pings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeerLatency {
+    pub peer_id: PeerId,
+    /// Round trip time of the last pong received, in milliseconds.
+    pub last_rtt_ms: f64,
+    /// Average round trip time over the recent samples, in milliseconds.
+    pub avg_rtt_ms: f64,
+    /// Minimum round trip time over the recent samples, in milliseconds.
+    pub min_rtt_ms: f64,
+    /// Number of samples the statistics are computed from.
+    pub num_samples: usize,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, BorshSerialize, BorshDeserialize)]
 pub struct StateResponseInfoV1 {
     pub shard_id: ShardId,
@@ -63,6 +63,16 @@ lazy_static! {
             "near_peer_reachable",
             "Total peers such that there is a path potentially through other peers"
         );
+    pub static ref PEER_PING_RTT: near_metrics::Result<Histogram> =
+        try_create_histogram(
+            "near_peer_ping_rtt_seconds",
+            "Round trip time of pings sent to reachable peers"
+        );
+    pub static ref PEER_WITH_LATENCY: near_metrics::Result<IntGauge> =
+        try_create_int_gauge(
+            "near_peer_with_latency",
+            "Total reachable peers with known round trip time"
+        );
     pub static ref DROP_MESSAGE_UNKNOWN_ACCOUNT: near_metrics::Result<IntCounter> =
         try_create_int_counter(
             "near_drop_message_unknown_account",
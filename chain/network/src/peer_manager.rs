@@ -1175,10 +1175,38 @@ impl PeerManagerActor {
         self.routing_table.add_ping(ping);
     }
 
-    /// Handle pong messages. Add pong temporary to the routing table, mostly used for testing.
+    /// Handle pong messages. Add pong temporary to the routing table and record round trip time.
     fn handle_pong(&mut self, _ctx: &mut Context<Self>, pong: Pong) {
-        #[allow(unused_variables)]
-        let latency = self.routing_table.add_pong(pong);
+        let source = pong.source.clone();
+        if let Some(latency) = self.routing_table.add_pong(pong) {
+            trace!(target: "network", "Round trip time to {}: {}ms", source, latency);
+        }
+    }
+
+    /// Periodically ping all reachable peers to keep round trip time statistics up to date.
+    fn monitor_peer_latency(&mut self, ctx: &mut Context<Self>) {
+        let ping_period = match self.config.ping_period {
+            Some(ping_period) => ping_period,
+            None => return,
+        };
+
+        let targets = self.routing_table.reachable_peers().cloned().collect::<Vec<_>>();
+        for target in targets {
+            let nonce = self.routing_table.get_ping(target.clone());
+            self.send_ping(ctx, nonce, target);
+        }
+
+        self.routing_table.save_latency();
+
+        near_performance_metrics::actix::run_later(
+            ctx,
+            file!(),
+            line!(),
+            ping_period,
+            move |act, ctx| {
+                act.monitor_peer_latency(ctx);
+            },
+        );
     }
 
     pub(crate) fn get_network_info(&mut self) -> NetworkInfo {
@@ -1206,6 +1234,7 @@ impl PeerManagerActor {
                 })
                 .collect(),
             peer_counter: self.peer_counter.load(Ordering::SeqCst),
+            peer_latencies: self.routing_table.latency_info(),
         }
     }
 
@@ -1290,6 +1319,9 @@ impl Actor for PeerManagerActor {
         // Start active peer stats querying.
         self.monitor_peer_stats(ctx);
 
+        // Start measuring round trip time to reachable peers.
+        self.monitor_peer_latency(ctx);
+
         self.broadcast_edges(ctx);
     }
 
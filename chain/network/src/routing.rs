@@ -1,4 +1,5 @@
 use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::ops::Sub;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -16,14 +17,14 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::types::AccountId;
 use near_primitives::utils::index_to_bytes;
 use near_store::{
-    ColAccountAnnouncements, ColComponentEdges, ColLastComponentNonce, ColPeerComponent, Store,
-    StoreUpdate,
+    ColAccountAnnouncements, ColComponentEdges, ColLastComponentNonce, ColPeerComponent,
+    ColPeerLatency, Store, StoreUpdate,
 };
 
 use crate::metrics;
 use crate::{
     cache::RouteBackCache,
-    types::{PeerIdOrHash, Ping, Pong},
+    types::{PeerIdOrHash, PeerLatency, Ping, Pong},
     utils::cache_to_hashmap,
 };
 use conqueue::{QueueReceiver, QueueSender};
@@ -35,6 +36,15 @@ const ROUTE_BACK_CACHE_SIZE: u64 = 100_000;
 const ROUTE_BACK_CACHE_EVICT_TIMEOUT: u64 = 120_000; // 120 seconds
 const ROUTE_BACK_CACHE_REMOVE_BATCH: u64 = 100;
 const PING_PONG_CACHE_SIZE: usize = 1_000;
+/// Number of round trip time samples kept per peer.
+const LATENCY_SAMPLES: usize = 20;
+/// Round trip time statistics of peers without new samples for this long (in milliseconds)
+/// are forgotten, both in memory and on disk.
+const LATENCY_STATS_TTL_MS: i64 = 24 * 3_600_000;
+/// Next hops whose average round trip time is within this factor (plus slack) of the fastest
+/// next hop are considered equally good routes.
+const LOW_LATENCY_ROUTE_FACTOR: f64 = 1.5;
+const LOW_LATENCY_ROUTE_SLACK_MS: f64 = 10.0;
 const ROUND_ROBIN_MAX_NONCE_DIFFERENCE_ALLOWED: usize = 10;
 const ROUND_ROBIN_NONCE_CACHE_SIZE: usize = 10_000;
 /// Routing table will clean edges if there is at least one node that is not reachable
@@ -273,6 +283,69 @@ impl Default for EdgeVerifierHelper {
     }
 }
 
+/// Rolling window of round trip times (in milliseconds) measured to a single peer.
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    samples: VecDeque<f64>,
+    /// Unix timestamp in milliseconds of the last sample.
+    last_updated_ms: i64,
+}
+
+/// Round trip time statistics of a peer as stored in `ColPeerLatency`.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct StoredLatencyStats {
+    last_updated_ms: i64,
+    samples: Vec<f64>,
+}
+
+impl From<&LatencyStats> for StoredLatencyStats {
+    fn from(stats: &LatencyStats) -> Self {
+        Self {
+            last_updated_ms: stats.last_updated_ms,
+            samples: stats.samples.iter().cloned().collect(),
+        }
+    }
+}
+
+impl From<StoredLatencyStats> for LatencyStats {
+    fn from(stats: StoredLatencyStats) -> Self {
+        Self {
+            samples: stats.samples.into_iter().collect(),
+            last_updated_ms: stats.last_updated_ms,
+        }
+    }
+}
+
+impl LatencyStats {
+    pub fn add_sample(&mut self, rtt_ms: f64) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt_ms);
+        self.last_updated_ms = chrono::Utc::now().timestamp_millis();
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.samples.back().cloned()
+    }
+
+    pub fn average(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+        }
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.samples.iter().cloned().fold(None, |acc, x| Some(acc.map_or(x, |acc: f64| acc.min(x))))
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+}
+
 pub struct RoutingTable {
     /// PeerId associated for every known account id.
     account_peers: SizedCache<AccountId, AnnounceAccount>,
@@ -300,6 +373,9 @@ pub struct RoutingTable {
     waiting_pong: SizedCache<PeerId, SizedCache<usize, Instant>>,
     /// Last nonce sent to each peer through pings.
     last_ping_nonce: SizedCache<PeerId, usize>,
+    /// Round trip time statistics for peers, built from pongs received. Persisted in
+    /// `ColPeerLatency` to survive restarts.
+    peer_latency: HashMap<PeerId, LatencyStats>,
     /// Last nonce used to store edges on disk.
     pub component_nonce: u64,
 }
@@ -319,6 +395,7 @@ impl RoutingTable {
             .get_ser::<u64>(ColLastComponentNonce, &[])
             .unwrap_or(None)
             .map_or(0, |nonce| nonce + 1);
+        let peer_latency = Self::load_latency(&store);
 
         Self {
             account_peers: SizedCache::with_size(ANNOUNCE_ACCOUNT_CACHE_SIZE),
@@ -337,6 +414,7 @@ impl RoutingTable {
             pong_info: SizedCache::with_size(PING_PONG_CACHE_SIZE),
             waiting_pong: SizedCache::with_size(PING_PONG_CACHE_SIZE),
             last_ping_nonce: SizedCache::with_size(PING_PONG_CACHE_SIZE),
+            peer_latency,
             component_nonce,
         }
     }
@@ -356,6 +434,7 @@ impl RoutingTable {
             if routes.is_empty() {
                 return Err(FindRouteError::Disconnected);
             }
+            let routes = self.low_latency_routes(routes);
 
             // Strategy similar to Round Robin. Select node with least nonce and send it. Increase its
             // nonce by one. Additionally if the difference between the highest nonce and the lowest
@@ -386,6 +465,36 @@ impl RoutingTable {
         }
     }
 
+    /// Filter out next hops whose link is significantly slower than the fastest one.
+    /// Next hops without round trip time measurements are always kept, so routing works as
+    /// plain round robin until latency information is available.
+    fn low_latency_routes(&self, routes: Vec<PeerId>) -> Vec<PeerId> {
+        let best = routes
+            .iter()
+            .filter_map(|peer_id| self.link_rtt(peer_id))
+            .fold(None, |acc, x| Some(acc.map_or(x, |acc: f64| acc.min(x))));
+
+        if let Some(best) = best {
+            let threshold = best * LOW_LATENCY_ROUTE_FACTOR + LOW_LATENCY_ROUTE_SLACK_MS;
+            routes
+                .into_iter()
+                .filter(|peer_id| self.link_rtt(peer_id).map_or(true, |rtt| rtt <= threshold))
+                .collect()
+        } else {
+            routes
+        }
+    }
+
+    /// Average round trip time of the direct link to an adjacent peer. Pings to adjacent peers
+    /// are not routed through other peers, so their round trip time is the one of the link.
+    /// Round trip times to other peers include the whole route and are not used.
+    fn link_rtt(&self, peer_id: &PeerId) -> Option<f64> {
+        if !self.raw_graph.contains_edge(self.peer_id(), peer_id) {
+            return None;
+        }
+        self.peer_latency.get(peer_id).and_then(|stats| stats.average())
+    }
+
     pub fn find_route(&mut self, target: &PeerIdOrHash) -> Result<PeerId, FindRouteError> {
         match target {
             PeerIdOrHash::PeerId(peer_id) => self.find_route_from_peer_id(&peer_id),
@@ -579,6 +688,11 @@ impl RoutingTable {
                 .and_then(|sent| Some(Instant::now().duration_since(sent).as_secs_f64() * 1000f64));
         }
 
+        if let Some(rtt_ms) = res {
+            self.peer_latency.entry(pong.source.clone()).or_default().add_sample(rtt_ms);
+            near_metrics::observe(&metrics::PEER_PING_RTT, rtt_ms / 1000f64);
+        }
+
         self.pong_info.cache_set(pong.nonce as usize, pong);
 
         res
@@ -609,6 +723,52 @@ impl RoutingTable {
         (cache_to_hashmap(&self.ping_info), cache_to_hashmap(&self.pong_info))
     }
 
+    /// Round trip time statistics for every reachable peer we have received a pong from.
+    pub fn latency_info(&self) -> Vec<PeerLatency> {
+        self.peer_latency
+            .iter()
+            .filter(|(peer_id, _)| self.peer_forwarding.contains_key(peer_id))
+            .filter_map(|(peer_id, stats)| {
+                Some(PeerLatency {
+                    peer_id: peer_id.clone(),
+                    last_rtt_ms: stats.last()?,
+                    avg_rtt_ms: stats.average()?,
+                    min_rtt_ms: stats.min()?,
+                    num_samples: stats.len(),
+                })
+            })
+            .collect()
+    }
+
+    fn load_latency(store: &Store) -> HashMap<PeerId, LatencyStats> {
+        store
+            .iter(ColPeerLatency)
+            .filter_map(|(key, value)| {
+                let peer_id = PeerId::try_from(key.to_vec()).ok()?;
+                let stats = StoredLatencyStats::try_from_slice(&value).ok()?;
+                Some((peer_id, stats.into()))
+            })
+            .collect()
+    }
+
+    /// Store round trip time statistics on disk, replacing the ones stored before.
+    pub fn save_latency(&mut self) {
+        let mut update = self.store.store_update();
+        for (key, _) in self.store.iter(ColPeerLatency) {
+            update.delete(ColPeerLatency, &key);
+        }
+        for (peer_id, stats) in self.peer_latency.iter() {
+            let _ = update.set_ser(
+                ColPeerLatency,
+                Vec::from(peer_id.clone()).as_ref(),
+                &StoredLatencyStats::from(stats),
+            );
+        }
+        if let Err(e) = update.commit() {
+            warn!(target: "network", "Error storing peer latency to store. {:?}", e);
+        }
+    }
+
     pub fn info(&mut self) -> RoutingTableInfo {
         let account_peers = self
             .get_announce_accounts()
@@ -693,12 +853,23 @@ impl RoutingTable {
             self.peer_last_time_reachable.insert(peer.clone(), now);
         }
 
+        // Statistics of unreachable peers are kept for a while, so they are still known when the
+        // peers are reachable again, for example after a restart.
+        let now_ms = now.timestamp_millis();
+        self.peer_latency.retain(|_, stats| now_ms - stats.last_updated_ms < LATENCY_STATS_TTL_MS);
+
         if can_save_edges {
             self.try_save_edges();
         }
 
         near_metrics::inc_counter_by(&metrics::ROUTING_TABLE_RECALCULATIONS, 1);
         near_metrics::set_gauge(&metrics::PEER_REACHABLE, self.peer_forwarding.len() as i64);
+        let peer_forwarding = &self.peer_forwarding;
+        near_metrics::set_gauge(
+            &metrics::PEER_WITH_LATENCY,
+            self.peer_latency.keys().filter(|peer_id| peer_forwarding.contains_key(peer_id)).count()
+                as i64,
+        );
     }
 
     /// Public interface for `account_peers`
@@ -928,8 +1099,82 @@ impl Graph {
 
 #[cfg(test)]
 mod test {
-    use crate::routing::Graph;
+    use near_crypto::Signature;
+    use near_store::test_utils::create_test_store;
+
+    use crate::routing::{Edge, Graph, LatencyStats, RoutingTable, LATENCY_SAMPLES};
     use crate::test_utils::{expected_routing_tables, random_peer_id};
+    use crate::types::PeerIdOrHash;
+
+    #[test]
+    fn latency_stats_rolling_window() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.average(), None);
+
+        for rtt in 0..LATENCY_SAMPLES + 5 {
+            stats.add_sample(rtt as f64);
+        }
+
+        assert_eq!(stats.len(), LATENCY_SAMPLES);
+        assert_eq!(stats.min(), Some(5.0));
+        assert_eq!(stats.last(), Some((LATENCY_SAMPLES + 4) as f64));
+        assert_eq!(stats.average(), Some((5 + LATENCY_SAMPLES + 4) as f64 / 2.0));
+    }
+
+    /// Two routes to the target: through `fast` and through `slow`.
+    /// Once latency is known only the low latency next hop should be used.
+    #[test]
+    fn find_route_prefers_low_latency() {
+        let source = random_peer_id();
+        let fast = random_peer_id();
+        let slow = random_peer_id();
+        let target = random_peer_id();
+
+        let mut routing_table = RoutingTable::new(source.clone(), create_test_store());
+        let edges = vec![(&source, &fast), (&source, &slow), (&fast, &target), (&slow, &target)]
+            .into_iter()
+            .map(|(peer0, peer1)| {
+                Edge::new(
+                    peer0.clone(),
+                    peer1.clone(),
+                    1,
+                    Signature::default(),
+                    Signature::default(),
+                )
+            })
+            .collect();
+        routing_table.process_edges(edges);
+        routing_table.update(false);
+
+        let target = PeerIdOrHash::PeerId(target);
+        let next_hops =
+            (0..4).map(|_| routing_table.find_route(&target).unwrap()).collect::<Vec<_>>();
+        assert!(next_hops.contains(&fast) && next_hops.contains(&slow));
+
+        routing_table.peer_latency.entry(fast.clone()).or_default().add_sample(20.0);
+        routing_table.peer_latency.entry(slow.clone()).or_default().add_sample(200.0);
+
+        for _ in 0..4 {
+            assert_eq!(routing_table.find_route(&target).unwrap(), fast);
+        }
+        assert_eq!(routing_table.latency_info().len(), 2);
+    }
+
+    #[test]
+    fn latency_stats_survive_restart() {
+        let store = create_test_store();
+        let peer = random_peer_id();
+        let mut routing_table = RoutingTable::new(random_peer_id(), store.clone());
+        routing_table.peer_latency.entry(peer.clone()).or_default().add_sample(20.0);
+        routing_table.save_latency();
+
+        let mut routing_table = RoutingTable::new(random_peer_id(), store);
+        assert_eq!(routing_table.peer_latency.get(&peer).unwrap().last(), Some(20.0));
+        // The peer isn't reachable yet, so its statistics are kept but not reported.
+        routing_table.update(false);
+        assert!(routing_table.peer_latency.contains_key(&peer));
+        assert!(routing_table.latency_info().is_empty());
+    }
 
     #[test]
     fn graph_contains_edge() {
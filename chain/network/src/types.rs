@@ -580,6 +580,8 @@ pub struct NetworkInfo {
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
     pub peer_counter: usize,
+    /// Round trip time statistics to reachable peers.
+    pub peer_latencies: Vec<PeerLatency>,
 }
 
 impl<A, M> MessageResponse<A, M> for NetworkInfo
@@ -1,11 +1,11 @@
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 use crate::types::{PoolIterator, PoolKey, TransactionGroup};
 use borsh::BorshSerialize;
 use near_crypto::PublicKey;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, Balance};
 use rand::RngCore;
 use std::ops::Bound;
 
@@ -23,6 +23,11 @@ pub struct TransactionPool {
     key_seed: Vec<u8>,
     /// The key after which the pool iterator starts. Doesn't have to be present in the pool.
     last_used_key: PoolKey,
+    /// Keys of the groups containing transactions with a priority fee, ordered by the largest
+    /// priority fee in the group.
+    priority_keys: BTreeSet<(Balance, PoolKey)>,
+    /// The largest priority fee of every group present in `priority_keys`.
+    priority_fees: HashMap<PoolKey, Balance>,
 }
 
 impl TransactionPool {
@@ -32,6 +37,8 @@ impl TransactionPool {
             transactions: BTreeMap::new(),
             unique_transactions: HashSet::new(),
             last_used_key: CryptoHash::default(),
+            priority_keys: BTreeSet::new(),
+            priority_fees: HashMap::new(),
         }
     }
 
@@ -49,13 +56,29 @@ impl TransactionPool {
         }
         let signer_id = &signed_transaction.transaction.signer_id;
         let signer_public_key = &signed_transaction.transaction.public_key;
-        self.transactions
-            .entry(self.key(signer_id, signer_public_key))
-            .or_insert_with(Vec::new)
-            .push(signed_transaction);
+        let key = self.key(signer_id, signer_public_key);
+        self.transactions.entry(key).or_insert_with(Vec::new).push(signed_transaction);
+        self.update_priority_fee(key);
         true
     }
 
+    /// Recomputes the largest priority fee of the group with the given key after the group
+    /// changed, was removed from or inserted back into the pool.
+    fn update_priority_fee(&mut self, key: PoolKey) {
+        if let Some(priority_fee) = self.priority_fees.remove(&key) {
+            self.priority_keys.remove(&(priority_fee, key));
+        }
+        let priority_fee = self
+            .transactions
+            .get(&key)
+            .and_then(|group| group.iter().filter_map(|tx| tx.transaction.priority_fee).max())
+            .unwrap_or_default();
+        if priority_fee > 0 {
+            self.priority_fees.insert(key, priority_fee);
+            self.priority_keys.insert((priority_fee, key));
+        }
+    }
+
     /// Returns a pool iterator wrapper that implements an iterator like trait to iterate over
     /// transaction groups in the proper order defined by the protocol.
     /// When the iterator is dropped, all remaining groups are inserted back into the pool.
@@ -86,6 +109,7 @@ impl TransactionPool {
             if remove_entry {
                 self.transactions.remove(&key);
             }
+            self.update_priority_fee(key);
             for hash in hashes {
                 self.unique_transactions.remove(&hash);
             }
@@ -128,7 +152,9 @@ impl<'a> PoolIteratorWrapper<'a> {
 /// The iterator works with the following algorithm:
 /// On next(), the iterator tries to get a transaction group from the pool, sorts transactions in
 /// it, and add it to the back of the sorted groups queue.
-/// Remembers the last used key, so it can continue from the next key.
+/// Groups containing transactions with a priority fee are taken first, starting from the group
+/// with the largest priority fee. The other groups are taken in the randomized key order.
+/// Remembers the last used key of these groups, so it can continue from the next key.
 ///
 /// If the pool is empty, the iterator gets the group from the front of the sorted groups queue.
 ///
@@ -145,22 +171,29 @@ impl<'a> PoolIteratorWrapper<'a> {
 impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
     fn next(&mut self) -> Option<&mut TransactionGroup> {
         if !self.pool.transactions.is_empty() {
-            let key = *self
-                .pool
-                .transactions
-                .range((Bound::Excluded(self.pool.last_used_key), Bound::Unbounded))
-                .next()
-                .map(|(k, _v)| k)
-                .unwrap_or_else(|| {
-                    self.pool
+            let key = match self.pool.priority_keys.iter().next_back() {
+                Some(&(_, key)) => key,
+                None => {
+                    let key = *self
+                        .pool
                         .transactions
-                        .keys()
+                        .range((Bound::Excluded(self.pool.last_used_key), Bound::Unbounded))
                         .next()
-                        .expect("we've just checked that the map is not empty")
-                });
-            self.pool.last_used_key = key;
+                        .map(|(k, _v)| k)
+                        .unwrap_or_else(|| {
+                            self.pool
+                                .transactions
+                                .keys()
+                                .next()
+                                .expect("we've just checked that the map is not empty")
+                        });
+                    self.pool.last_used_key = key;
+                    key
+                }
+            };
             let mut transactions =
                 self.pool.transactions.remove(&key).expect("just checked existence");
+            self.pool.update_priority_fee(key);
             transactions.sort_by_key(|st| std::cmp::Reverse(st.transaction.nonce));
             self.sorted_groups.push_back(TransactionGroup {
                 key,
@@ -195,6 +228,7 @@ impl<'a> Drop for PoolIteratorWrapper<'a> {
             }
             if !group.transactions.is_empty() {
                 self.pool.transactions.insert(group.key, group.transactions);
+                self.pool.update_priority_fee(group.key);
             }
         }
     }
@@ -375,6 +409,53 @@ mod tests {
         assert_eq!(pool_txs, expected_txs);
     }
 
+    /// Add transactions of nonce from 1..=3 from 3 signers, two of them paying different priority
+    /// fees. Check that the groups with the larger priority fee are pulled first.
+    #[test]
+    fn test_order_priority_fee() {
+        let with_priority_fee = |signer_id: &str, priority_fee: Balance| {
+            let signer =
+                InMemorySigner::from_seed(signer_id.parse().unwrap(), KeyType::ED25519, signer_id);
+            generate_transactions(signer_id, signer_id, 1, 3)
+                .into_iter()
+                .map(|mut tx| {
+                    tx.transaction.priority_fee = Some(priority_fee);
+                    tx.transaction.sign(&signer)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        transactions.extend(with_priority_fee("bob.near", 10));
+        transactions.extend(with_priority_fee("carol.near", 20));
+
+        let mut pool = TransactionPool::new();
+        transactions.shuffle(&mut thread_rng());
+        for tx in transactions {
+            pool.insert_transaction(tx);
+        }
+        let signers: Vec<_> = prepare_transactions(&mut pool, 6)
+            .into_iter()
+            .map(|tx| (tx.transaction.signer_id.to_string(), tx.transaction.nonce))
+            .collect();
+        assert_eq!(
+            signers,
+            vec![
+                ("carol.near".to_string(), 1),
+                ("bob.near".to_string(), 1),
+                ("alice.near".to_string(), 1),
+                ("carol.near".to_string(), 2),
+                ("bob.near".to_string(), 2),
+                ("alice.near".to_string(), 2),
+            ]
+        );
+        // The groups with the remaining transactions keep their priority in the pool.
+        let signers: Vec<_> = prepare_transactions(&mut pool, 1)
+            .into_iter()
+            .map(|tx| tx.transaction.signer_id.to_string())
+            .collect();
+        assert_eq!(signers, vec!["carol.near".to_string()]);
+    }
+
     /// Add transactions of nonce from 1..=3 and transactions with nonce 21..=31. Pull 10.
     /// Then try to get another 10.
     #[test]
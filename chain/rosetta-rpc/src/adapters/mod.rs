@@ -189,6 +189,11 @@ fn convert_block_changes_to_transactions(
             StateChangeCauseView::Migration => {
                 format!("migration:{}", block_hash)
             }
+            StateChangeCauseView::Resharding => {
+                return Err(crate::errors::ErrorKind::InternalInvariantError(
+                    "State Change 'Resharding' should never be observed".to_string(),
+                ));
+            }
         };
 
         let current_transaction =
@@ -515,6 +520,30 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                 }
 
+                near_primitives::transaction::Action::UpdateAccessKey(action) => {
+                    let initiate_update_access_key_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateUpdateAccessKeyOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_update_access_key_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::UpdateAccessKeyOperation {
+                            account: receiver_account_identifier.clone(),
+                            public_key: (&action.public_key).into(),
+                            allowance: action.allowance,
+                            method_names: action.method_names,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_update_access_key_operation_id],
+                        ),
+                    );
+                }
+
                 near_primitives::transaction::Action::Transfer(action) => {
                     let transfer_amount = crate::models::Amount::from_yoctonear(action.deposit);
 
@@ -573,6 +602,97 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                 }
 
+                near_primitives::transaction::Action::DeploySharedContract(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::DeploySharedContractOperation {
+                            account: receiver_account_identifier.clone(),
+                            code: action.code,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+                }
+
+                near_primitives::transaction::Action::DeployContractByHash(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::DeployContractByHashOperation {
+                            account: receiver_account_identifier.clone(),
+                            code_hash: action.code_hash,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+                }
+
+                near_primitives::transaction::Action::RemoveSharedContract(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::RemoveSharedContractOperation {
+                            account: receiver_account_identifier.clone(),
+                            code_hash: action.code_hash,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+                }
+
+                near_primitives::transaction::Action::DeployContractAndMigrate(action) => {
+                    let initiate_deploy_contract_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDeployContractOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_deploy_contract_operation_id.clone()),
+                    );
+
+                    operations.push(
+                        validated_operations::DeployContractAndMigrateOperation {
+                            account: receiver_account_identifier.clone(),
+                            code: action.code,
+                            method_name: action.method_name,
+                            args: action.args,
+                            attached_gas: action.gas,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_deploy_contract_operation_id],
+                        ),
+                    );
+                }
+
                 near_primitives::transaction::Action::FunctionCall(action) => {
                     let attached_amount = crate::models::Amount::from_yoctonear(action.deposit);
 
@@ -613,6 +733,49 @@ impl From<NearActions> for Vec<crate::models::Operation> {
                     );
                     operations.push(deploy_contract_operation);
                 }
+
+                near_primitives::transaction::Action::Delegate(action) => {
+                    let initiate_delegate_action_operation_id =
+                        crate::models::OperationIdentifier::new(&operations);
+                    operations.push(
+                        validated_operations::InitiateDelegateActionOperation {
+                            sender_account: sender_account_identifier.clone(),
+                        }
+                        .into_operation(initiate_delegate_action_operation_id.clone()),
+                    );
+
+                    // The delegated actions are sent by the account which signed them, their
+                    // operations are numbered after the ones of the preceding actions.
+                    let delegate_action = action.delegate_action;
+                    let delegated_operations: Vec<crate::models::Operation> = NearActions {
+                        sender_account_id: delegate_action.sender_id,
+                        receiver_account_id: delegate_action.receiver_id,
+                        actions: delegate_action.actions,
+                    }
+                    .into();
+                    let index_offset = crate::models::OperationIdentifier::new(&operations).index;
+                    for mut operation in delegated_operations {
+                        operation.operation_identifier.index += index_offset;
+                        for related_operation in operation.related_operations.iter_mut().flatten() {
+                            related_operation.index += index_offset;
+                        }
+                        operations.push(operation);
+                    }
+
+                    operations.push(
+                        validated_operations::DelegateActionOperation {
+                            account: receiver_account_identifier.clone(),
+                            public_key: (&delegate_action.public_key).into(),
+                            nonce: delegate_action.nonce,
+                            max_block_height: delegate_action.max_block_height,
+                            signature: action.signature,
+                        }
+                        .into_related_operation(
+                            crate::models::OperationIdentifier::new(&operations),
+                            vec![initiate_delegate_action_operation_id],
+                        ),
+                    );
+                }
             }
         }
         operations
@@ -731,6 +894,36 @@ impl std::convert::TryFrom<Vec<crate::models::Operation>> for NearActions {
                         .push(near_primitives::transaction::DeleteKeyAction { public_key }.into())
                 }
 
+                crate::models::OperationType::UpdateAccessKey => {
+                    let update_access_key_operation =
+                        validated_operations::UpdateAccessKeyOperation::try_from(tail_operation)?;
+                    receiver_account_id.try_set(&update_access_key_operation.account)?;
+
+                    let initiate_update_access_key_operation =
+                        validated_operations::InitiateUpdateAccessKeyOperation::try_from_option(
+                            operations.next(),
+                        )?;
+                    sender_account_id
+                        .try_set(&initiate_update_access_key_operation.sender_account)?;
+
+                    let public_key =
+                        (&update_access_key_operation.public_key).try_into().map_err(|_| {
+                            crate::errors::ErrorKind::InvalidInput(format!(
+                                "Invalid public_key: {:?}",
+                                update_access_key_operation.public_key
+                            ))
+                        })?;
+
+                    actions.push(
+                        near_primitives::transaction::UpdateAccessKeyAction {
+                            public_key,
+                            allowance: update_access_key_operation.allowance,
+                            method_names: update_access_key_operation.method_names,
+                        }
+                        .into(),
+                    )
+                }
+
                 crate::models::OperationType::Transfer => {
                     let receiver_transfer_operation =
                         validated_operations::TransferOperation::try_from(tail_operation)?;
@@ -804,6 +997,93 @@ impl std::convert::TryFrom<Vec<crate::models::Operation>> for NearActions {
                         .into(),
                     )
                 }
+                crate::models::OperationType::DeploySharedContract => {
+                    let deploy_shared_contract_operation =
+                        validated_operations::DeploySharedContractOperation::try_from(
+                            tail_operation,
+                        )?;
+                    receiver_account_id.try_set(&deploy_shared_contract_operation.account)?;
+
+                    let initiate_deploy_contract_operation =
+                        validated_operations::InitiateDeployContractOperation::try_from_option(
+                            operations.next(),
+                        )?;
+                    sender_account_id
+                        .try_set(&initiate_deploy_contract_operation.sender_account)?;
+
+                    actions.push(
+                        near_primitives::transaction::DeploySharedContractAction {
+                            code: deploy_shared_contract_operation.code,
+                        }
+                        .into(),
+                    )
+                }
+                crate::models::OperationType::DeployContractByHash => {
+                    let deploy_contract_by_hash_operation =
+                        validated_operations::DeployContractByHashOperation::try_from(
+                            tail_operation,
+                        )?;
+                    receiver_account_id.try_set(&deploy_contract_by_hash_operation.account)?;
+
+                    let initiate_deploy_contract_operation =
+                        validated_operations::InitiateDeployContractOperation::try_from_option(
+                            operations.next(),
+                        )?;
+                    sender_account_id
+                        .try_set(&initiate_deploy_contract_operation.sender_account)?;
+
+                    actions.push(
+                        near_primitives::transaction::DeployContractByHashAction {
+                            code_hash: deploy_contract_by_hash_operation.code_hash,
+                        }
+                        .into(),
+                    )
+                }
+                crate::models::OperationType::RemoveSharedContract => {
+                    let remove_shared_contract_operation =
+                        validated_operations::RemoveSharedContractOperation::try_from(
+                            tail_operation,
+                        )?;
+                    receiver_account_id.try_set(&remove_shared_contract_operation.account)?;
+
+                    let initiate_deploy_contract_operation =
+                        validated_operations::InitiateDeployContractOperation::try_from_option(
+                            operations.next(),
+                        )?;
+                    sender_account_id
+                        .try_set(&initiate_deploy_contract_operation.sender_account)?;
+
+                    actions.push(
+                        near_primitives::transaction::RemoveSharedContractAction {
+                            code_hash: remove_shared_contract_operation.code_hash,
+                        }
+                        .into(),
+                    )
+                }
+                crate::models::OperationType::DeployContractAndMigrate => {
+                    let deploy_contract_and_migrate_operation =
+                        validated_operations::DeployContractAndMigrateOperation::try_from(
+                            tail_operation,
+                        )?;
+                    receiver_account_id.try_set(&deploy_contract_and_migrate_operation.account)?;
+
+                    let initiate_deploy_contract_operation =
+                        validated_operations::InitiateDeployContractOperation::try_from_option(
+                            operations.next(),
+                        )?;
+                    sender_account_id
+                        .try_set(&initiate_deploy_contract_operation.sender_account)?;
+
+                    actions.push(
+                        near_primitives::transaction::DeployContractAndMigrateAction {
+                            code: deploy_contract_and_migrate_operation.code,
+                            method_name: deploy_contract_and_migrate_operation.method_name,
+                            args: deploy_contract_and_migrate_operation.args,
+                            gas: deploy_contract_and_migrate_operation.attached_gas,
+                        }
+                        .into(),
+                    )
+                }
                 crate::models::OperationType::FunctionCall => {
                     let function_call_operation =
                         validated_operations::FunctionCallOperation::try_from(tail_operation)?;
@@ -844,10 +1124,76 @@ impl std::convert::TryFrom<Vec<crate::models::Operation>> for NearActions {
                     )
                 }
 
+                crate::models::OperationType::DelegateAction => {
+                    let delegate_action_operation =
+                        validated_operations::DelegateActionOperation::try_from(tail_operation)?;
+                    receiver_account_id.try_set(&delegate_action_operation.account)?;
+
+                    // The operations of the delegated actions precede the DELEGATE_ACTION
+                    // operation up to the INITIATE_DELEGATE_ACTION one.
+                    let mut delegated_operations = vec![];
+                    let initiate_delegate_action_operation = loop {
+                        match operations.next() {
+                            Some(operation)
+                                if operation.type_
+                                    == crate::models::OperationType::InitiateDelegateAction =>
+                            {
+                                break validated_operations::InitiateDelegateActionOperation::try_from(
+                                    operation,
+                                )?;
+                            }
+                            Some(operation) => delegated_operations.push(operation),
+                            None => {
+                                return Err(crate::errors::ErrorKind::InvalidInput(
+                                    "INITIATE_DELEGATE_ACTION operation is missing".to_string(),
+                                ))
+                            }
+                        }
+                    };
+                    sender_account_id
+                        .try_set(&initiate_delegate_action_operation.sender_account)?;
+
+                    delegated_operations.reverse();
+                    let delegated_actions = NearActions::try_from(delegated_operations)?;
+                    if delegated_actions.sender_account_id
+                        != delegate_action_operation.account.address
+                    {
+                        return Err(crate::errors::ErrorKind::InvalidInput(
+                            "The delegated actions must be sent by the account of the DELEGATE_ACTION operation"
+                                .to_string(),
+                        ));
+                    }
+
+                    let public_key =
+                        (&delegate_action_operation.public_key).try_into().map_err(|_| {
+                            crate::errors::ErrorKind::InvalidInput(format!(
+                                "Invalid public_key: {:?}",
+                                delegate_action_operation.public_key
+                            ))
+                        })?;
+
+                    actions.push(
+                        near_primitives::transaction::SignedDelegateAction {
+                            delegate_action: near_primitives::transaction::DelegateAction {
+                                sender_id: delegated_actions.sender_account_id,
+                                receiver_id: delegated_actions.receiver_account_id,
+                                actions: delegated_actions.actions,
+                                nonce: delegate_action_operation.nonce,
+                                max_block_height: delegate_action_operation.max_block_height,
+                                public_key,
+                            },
+                            signature: delegate_action_operation.signature,
+                        }
+                        .into(),
+                    )
+                }
+
                 crate::models::OperationType::InitiateCreateAccount
+                | crate::models::OperationType::InitiateDelegateAction
                 | crate::models::OperationType::InitiateDeleteAccount
                 | crate::models::OperationType::InitiateAddKey
                 | crate::models::OperationType::InitiateDeleteKey
+                | crate::models::OperationType::InitiateUpdateAccessKey
                 | crate::models::OperationType::InitiateDeployContract
                 | crate::models::OperationType::InitiateFunctionCall
                 | crate::models::OperationType::DeleteAccount => {
@@ -1028,6 +1374,13 @@ mod tests {
                 .public_key(),
         }
         .into()];
+        let update_access_key_actions = vec![near_primitives::transaction::UpdateAccessKeyAction {
+            public_key: near_crypto::SecretKey::from_random(near_crypto::KeyType::ED25519)
+                .public_key(),
+            allowance: Some(near_primitives::types::Balance::MAX),
+            method_names: vec!["method-name".to_string()],
+        }
+        .into()];
         let transfer_actions = vec![near_primitives::transaction::TransferAction {
             deposit: near_primitives::types::Balance::MAX,
         }
@@ -1042,6 +1395,29 @@ mod tests {
             code: b"binary-data".to_vec(),
         }
         .into()];
+        let deploy_shared_contract_actions =
+            vec![near_primitives::transaction::DeploySharedContractAction {
+                code: b"binary-data".to_vec(),
+            }
+            .into()];
+        let deploy_contract_by_hash_actions =
+            vec![near_primitives::transaction::DeployContractByHashAction {
+                code_hash: near_primitives::hash::hash(b"binary-data"),
+            }
+            .into()];
+        let remove_shared_contract_actions =
+            vec![near_primitives::transaction::RemoveSharedContractAction {
+                code_hash: near_primitives::hash::hash(b"binary-data"),
+            }
+            .into()];
+        let deploy_contract_and_migrate_actions =
+            vec![near_primitives::transaction::DeployContractAndMigrateAction {
+                code: b"binary-data".to_vec(),
+                method_name: "migrate".to_string(),
+                args: b"args".to_vec(),
+                gas: 100500,
+            }
+            .into()];
         let function_call_without_balance_actions =
             vec![near_primitives::transaction::FunctionCallAction {
                 method_name: "method-name".parse().unwrap(),
@@ -1082,8 +1458,13 @@ mod tests {
             delete_account_actions,
             add_key_actions,
             delete_key_actions,
-            transfer_actions,
+            update_access_key_actions,
+            transfer_actions.clone(),
             deploy_contract_actions,
+            deploy_shared_contract_actions,
+            deploy_contract_by_hash_actions,
+            remove_shared_contract_actions,
+            deploy_contract_and_migrate_actions,
             function_call_without_balance_actions,
             function_call_with_balance_actions,
             wallet_style_create_account_actions,
@@ -1132,6 +1513,46 @@ mod tests {
             );
             assert_eq!(near_actions_recreated.actions, near_actions.actions);
         }
+
+        // Delegated actions are signed by the receiver of the relayer's transaction.
+        let delegate_signer = near_crypto::InMemorySigner::from_seed(
+            "receiver.near".parse().unwrap(),
+            near_crypto::KeyType::ED25519,
+            "receiver.near",
+        );
+        let delegate_action = near_primitives::transaction::DelegateAction {
+            sender_id: "receiver.near".parse().unwrap(),
+            receiver_id: "beneficiary.near".parse().unwrap(),
+            actions: transfer_actions.clone(),
+            nonce: 1,
+            max_block_height: 100,
+            public_key: delegate_signer.public_key.clone(),
+        };
+        let delegate_actions = vec![near_primitives::transaction::SignedDelegateAction::new(
+            &delegate_signer,
+            delegate_action,
+        )
+        .into()];
+        for actions in vec![delegate_actions.clone(), [transfer_actions, delegate_actions].concat()]
+        {
+            let near_actions = NearActions {
+                sender_account_id: "relayer.near".parse().unwrap(),
+                receiver_account_id: "receiver.near".parse().unwrap(),
+                actions,
+            };
+            println!("NEAR Actions: {:#?}", near_actions);
+            let operations: Vec<crate::models::Operation> = near_actions.clone().into();
+            println!("Operations: {:#?}", operations);
+
+            let near_actions_recreated = NearActions::try_from(operations).unwrap();
+
+            assert_eq!(near_actions_recreated.sender_account_id, near_actions.sender_account_id);
+            assert_eq!(
+                near_actions_recreated.receiver_account_id,
+                near_actions.receiver_account_id
+            );
+            assert_eq!(near_actions_recreated.actions, near_actions.actions);
+        }
     }
 
     #[test]
@@ -0,0 +1,66 @@
+use super::ValidatedOperation;
+
+pub(crate) struct DeployContractAndMigrateOperation {
+    pub(crate) account: crate::models::AccountIdentifier,
+    pub(crate) code: Vec<u8>,
+    pub(crate) method_name: String,
+    pub(crate) args: Vec<u8>,
+    pub(crate) attached_gas: near_primitives::types::Gas,
+}
+
+impl ValidatedOperation for DeployContractAndMigrateOperation {
+    const OPERATION_TYPE: crate::models::OperationType =
+        crate::models::OperationType::DeployContractAndMigrate;
+
+    fn into_operation(
+        self,
+        operation_identifier: crate::models::OperationIdentifier,
+    ) -> crate::models::Operation {
+        crate::models::Operation {
+            operation_identifier,
+
+            account: self.account,
+            amount: None,
+            metadata: Some(crate::models::OperationMetadata {
+                code: Some(self.code.into()),
+                method_name: Some(self.method_name),
+                args: Some(self.args.into()),
+                attached_gas: Some(self.attached_gas.into()),
+                ..Default::default()
+            }),
+
+            related_operations: None,
+            type_: Self::OPERATION_TYPE,
+            status: None,
+        }
+    }
+}
+
+fn required_fields_error() -> crate::errors::ErrorKind {
+    crate::errors::ErrorKind::InvalidInput(
+        "DEPLOY_CONTRACT_AND_MIGRATE operation requires `code`, `method_name`, `args`, and `attached_gas` being passed in the metadata".into(),
+    )
+}
+
+impl std::convert::TryFrom<crate::models::Operation> for DeployContractAndMigrateOperation {
+    type Error = crate::errors::ErrorKind;
+
+    fn try_from(operation: crate::models::Operation) -> Result<Self, Self::Error> {
+        Self::validate_operation_type(operation.type_)?;
+        let metadata = operation.metadata.ok_or_else(required_fields_error)?;
+        let code = metadata.code.ok_or_else(required_fields_error)?.into_inner();
+        let method_name = metadata.method_name.ok_or_else(required_fields_error)?;
+        let args = metadata.args.ok_or_else(required_fields_error)?.into_inner();
+        let attached_gas = metadata.attached_gas.ok_or_else(required_fields_error)?;
+        let attached_gas = if attached_gas.is_positive() {
+            attached_gas.absolute_difference()
+        } else {
+            return Err(crate::errors::ErrorKind::InvalidInput(
+                "DEPLOY_CONTRACT_AND_MIGRATE operation requires `attached_gas` to be positive"
+                    .into(),
+            ));
+        };
+
+        Ok(Self { account: operation.account, code, method_name, args, attached_gas })
+    }
+}
@@ -2,35 +2,51 @@ use std::convert::TryFrom;
 
 pub(crate) use self::add_key::AddKeyOperation;
 pub(crate) use self::create_account::CreateAccountOperation;
+pub(crate) use self::delegate_action::DelegateActionOperation;
 pub(crate) use self::delete_account::DeleteAccountOperation;
 pub(crate) use self::delete_key::DeleteKeyOperation;
 pub(crate) use self::deploy_contract::DeployContractOperation;
+pub(crate) use self::deploy_contract_and_migrate::DeployContractAndMigrateOperation;
+pub(crate) use self::deploy_contract_by_hash::DeployContractByHashOperation;
+pub(crate) use self::deploy_shared_contract::DeploySharedContractOperation;
 pub(crate) use self::function_call::FunctionCallOperation;
 pub(crate) use self::initiate_add_key::InitiateAddKeyOperation;
 pub(crate) use self::initiate_create_account::InitiateCreateAccountOperation;
+pub(crate) use self::initiate_delegate_action::InitiateDelegateActionOperation;
 pub(crate) use self::initiate_delete_account::InitiateDeleteAccountOperation;
 pub(crate) use self::initiate_delete_key::InitiateDeleteKeyOperation;
 pub(crate) use self::initiate_deploy_contract::InitiateDeployContractOperation;
 pub(crate) use self::initiate_function_call::InitiateFunctionCallOperation;
+pub(crate) use self::initiate_update_access_key::InitiateUpdateAccessKeyOperation;
 pub(crate) use self::refund_delete_account::RefundDeleteAccountOperation;
+pub(crate) use self::remove_shared_contract::RemoveSharedContractOperation;
 pub(crate) use self::stake::StakeOperation;
 pub(crate) use self::transfer::TransferOperation;
+pub(crate) use self::update_access_key::UpdateAccessKeyOperation;
 
 mod add_key;
 mod create_account;
+mod delegate_action;
 mod delete_account;
 mod delete_key;
 mod deploy_contract;
+mod deploy_contract_and_migrate;
+mod deploy_contract_by_hash;
+mod deploy_shared_contract;
 mod function_call;
 mod initiate_add_key;
 mod initiate_create_account;
+mod initiate_delegate_action;
 mod initiate_delete_account;
 mod initiate_delete_key;
 mod initiate_deploy_contract;
 mod initiate_function_call;
+mod initiate_update_access_key;
 mod refund_delete_account;
+mod remove_shared_contract;
 mod stake;
 mod transfer;
+mod update_access_key;
 
 pub(crate) trait ValidatedOperation:
     TryFrom<crate::models::Operation, Error = crate::errors::ErrorKind>
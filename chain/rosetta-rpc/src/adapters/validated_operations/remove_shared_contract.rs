@@ -0,0 +1,52 @@
+use super::ValidatedOperation;
+
+pub(crate) struct RemoveSharedContractOperation {
+    pub(crate) account: crate::models::AccountIdentifier,
+    pub(crate) code_hash: near_primitives::hash::CryptoHash,
+}
+
+impl ValidatedOperation for RemoveSharedContractOperation {
+    const OPERATION_TYPE: crate::models::OperationType =
+        crate::models::OperationType::RemoveSharedContract;
+
+    fn into_operation(
+        self,
+        operation_identifier: crate::models::OperationIdentifier,
+    ) -> crate::models::Operation {
+        crate::models::Operation {
+            operation_identifier,
+
+            account: self.account,
+            amount: None,
+            metadata: Some(crate::models::OperationMetadata {
+                code_hash: Some(self.code_hash.to_string()),
+                ..Default::default()
+            }),
+
+            related_operations: None,
+            type_: Self::OPERATION_TYPE,
+            status: None,
+        }
+    }
+}
+
+fn required_fields_error() -> crate::errors::ErrorKind {
+    crate::errors::ErrorKind::InvalidInput(
+        "REMOVE_SHARED_CONTRACT operation requires `code_hash` being passed in the metadata".into(),
+    )
+}
+
+impl std::convert::TryFrom<crate::models::Operation> for RemoveSharedContractOperation {
+    type Error = crate::errors::ErrorKind;
+
+    fn try_from(operation: crate::models::Operation) -> Result<Self, Self::Error> {
+        Self::validate_operation_type(operation.type_)?;
+        let metadata = operation.metadata.ok_or_else(required_fields_error)?;
+        let code_hash = metadata.code_hash.ok_or_else(required_fields_error)?;
+        let code_hash = code_hash.parse().map_err(|_| {
+            crate::errors::ErrorKind::InvalidInput(format!("Invalid code_hash: {:?}", code_hash))
+        })?;
+
+        Ok(Self { account: operation.account, code_hash })
+    }
+}
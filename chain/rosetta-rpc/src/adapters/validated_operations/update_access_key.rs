@@ -0,0 +1,65 @@
+use super::ValidatedOperation;
+
+pub(crate) struct UpdateAccessKeyOperation {
+    pub(crate) account: crate::models::AccountIdentifier,
+    pub(crate) public_key: crate::models::PublicKey,
+    pub(crate) allowance: Option<near_primitives::types::Balance>,
+    pub(crate) method_names: Vec<String>,
+}
+
+impl ValidatedOperation for UpdateAccessKeyOperation {
+    const OPERATION_TYPE: crate::models::OperationType =
+        crate::models::OperationType::UpdateAccessKey;
+
+    fn into_operation(
+        self,
+        operation_identifier: crate::models::OperationIdentifier,
+    ) -> crate::models::Operation {
+        crate::models::Operation {
+            operation_identifier,
+
+            account: self.account,
+            amount: None,
+            metadata: Some(crate::models::OperationMetadata {
+                public_key: Some(self.public_key),
+                allowance: self.allowance.map(|allowance| allowance.to_string()),
+                method_names: Some(self.method_names),
+                ..Default::default()
+            }),
+
+            related_operations: None,
+            type_: Self::OPERATION_TYPE,
+            status: None,
+        }
+    }
+}
+
+fn required_fields_error() -> crate::errors::ErrorKind {
+    crate::errors::ErrorKind::InvalidInput(
+        "UPDATE_ACCESS_KEY operation requires `public_key` being passed in the metadata".into(),
+    )
+}
+
+impl std::convert::TryFrom<crate::models::Operation> for UpdateAccessKeyOperation {
+    type Error = crate::errors::ErrorKind;
+
+    fn try_from(operation: crate::models::Operation) -> Result<Self, Self::Error> {
+        Self::validate_operation_type(operation.type_)?;
+        let metadata = operation.metadata.ok_or_else(required_fields_error)?;
+        let public_key = metadata.public_key.ok_or_else(required_fields_error)?;
+        let allowance = metadata
+            .allowance
+            .map(|allowance| {
+                allowance.parse().map_err(|_| {
+                    crate::errors::ErrorKind::InvalidInput(format!(
+                        "Invalid allowance: {:?}",
+                        allowance
+                    ))
+                })
+            })
+            .transpose()?;
+        let method_names = metadata.method_names.unwrap_or_default();
+
+        Ok(Self { account: operation.account, public_key, allowance, method_names })
+    }
+}
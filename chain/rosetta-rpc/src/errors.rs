@@ -33,6 +33,7 @@ impl std::convert::From<near_client::TxStatusError> for ErrorKind {
             near_client::TxStatusError::MissingTransaction(err) => {
                 Self::NotFound(format!("Transaction is missing: {:?}", err))
             }
+            near_client::TxStatusError::GarbageCollected(err) => Self::NotFound(err),
             near_client::TxStatusError::InvalidTx(err) => Self::NotFound(format!(
                 "Transaction is invalid, so it will never be included to the chain: {:?}",
                 err
@@ -63,6 +64,9 @@ impl std::convert::From<near_client_primitives::types::GetStateChangesError> for
             near_client_primitives::types::GetStateChangesError::UnknownBlock { error_message } => {
                 Self::NotFound(error_message)
             }
+            near_client_primitives::types::GetStateChangesError::TooManyHeights { .. } => {
+                Self::InvalidInput(err.to_string())
+            }
             near_client_primitives::types::GetStateChangesError::Unreachable { error_message } => {
                 Self::InternalError(error_message)
             }
@@ -709,6 +709,14 @@ pub(crate) enum OperationType {
     DeployContract,
     InitiateFunctionCall,
     FunctionCall,
+    InitiateDelegateAction,
+    DelegateAction,
+    DeploySharedContract,
+    DeployContractByHash,
+    RemoveSharedContract,
+    InitiateUpdateAccessKey,
+    UpdateAccessKey,
+    DeployContractAndMigrate,
 }
 
 #[derive(
@@ -736,7 +744,7 @@ impl OperationStatusKind {
 
 #[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize, Apiv2Schema)]
 pub(crate) struct OperationMetadata {
-    /// Has to be specified for ADD_KEY, REMOVE_KEY, and STAKE operations
+    /// Has to be specified for ADD_KEY, REMOVE_KEY, UPDATE_ACCESS_KEY, and STAKE operations
     #[serde(skip_serializing_if = "Option::is_none")]
     pub public_key: Option<PublicKey>,
     // /// Has to be specified for ADD_KEY
@@ -744,9 +752,18 @@ pub(crate) struct OperationMetadata {
     // now
     //#[serde(skip_serializing_if = "Option::is_none")]
     // pub access_key: Option<TODO>,
-    /// Has to be specified for DEPLOY_CONTRACT operation
+    /// Has to be specified for DEPLOY_CONTRACT and DEPLOY_SHARED_CONTRACT operations
     #[serde(skip_serializing_if = "Option::is_none")]
     pub code: Option<BlobInHexString<Vec<u8>>>,
+    /// Has to be specified for DEPLOY_CONTRACT_BY_HASH operation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<String>,
+    /// The allowance in yoctoNEAR for UPDATE_ACCESS_KEY operation, unlimited if not specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowance: Option<String>,
+    /// The allowed method names for UPDATE_ACCESS_KEY operation, any method if not specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method_names: Option<Vec<String>>,
     /// Has to be specified for FUNCTION_CALL operation
     #[serde(skip_serializing_if = "Option::is_none")]
     pub method_name: Option<String>,
@@ -756,6 +773,15 @@ pub(crate) struct OperationMetadata {
     /// Has to be specified for FUNCTION_CALL operation
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attached_gas: Option<crate::utils::SignedDiff<near_primitives::types::Gas>>,
+    /// Has to be specified for DELEGATE_ACTION operation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<near_primitives::types::Nonce>,
+    /// Has to be specified for DELEGATE_ACTION operation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_block_height: Option<near_primitives::types::BlockHeight>,
+    /// Has to be specified for DELEGATE_ACTION operation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 /// Operations contain all balance-changing information within a transaction.
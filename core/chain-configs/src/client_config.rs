@@ -1,5 +1,6 @@
 //! Chain Client Configuration
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -17,6 +18,20 @@ pub enum LogSummaryStyle {
     Colored,
 }
 
+/// Number of epochs GC keeps each category of data for. Blocks, chunks and state changes are
+/// always kept for the number of epochs needed to process blocks, which is also used for the
+/// categories that aren't set or are set to a lower value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GCRetentionConfig {
+    /// Signed transactions.
+    pub transactions: Option<u64>,
+    /// Receipts.
+    pub receipts: Option<u64>,
+    /// Execution outcomes of transactions and receipts.
+    pub outcomes: Option<u64>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// Version of the binary.
@@ -83,12 +98,17 @@ pub struct ClientConfig {
     pub block_header_fetch_horizon: BlockHeightDelta,
     /// Number of blocks to garbage collect at every gc call.
     pub gc_blocks_limit: NumBlocks,
+    /// Number of epochs to keep each category of data for.
+    pub gc_retention: GCRetentionConfig,
     /// Accounts that this client tracks
     pub tracked_accounts: Vec<AccountId>,
     /// Shards that this client tracks
     pub tracked_shards: Vec<ShardId>,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Keep the accounts after each block changing them, to answer the queries of accounts at
+    /// old blocks without reading the trie. Only used by archive nodes.
+    pub account_history: bool,
     /// Number of threads for ViewClientActor pool.
     pub view_client_threads: usize,
     /// Run Epoch Sync on the start.
@@ -101,6 +121,16 @@ pub struct ClientConfig {
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
     pub max_gas_burnt_view: Option<Gas>,
+    /// If set, all state parts of the tracked shards are dumped to this directory at the start of
+    /// every epoch, so that state sync can be served without generating them on request.
+    pub state_parts_dump_dir: Option<PathBuf>,
+    /// If set, state sync reads state headers and parts from this directory, as written with
+    /// `state_parts_dump_dir`, and only requests the missing ones from peers.
+    pub state_sync_from_dump_dir: Option<PathBuf>,
+    /// If set, the store is validated in the background, one batch of entries every period.
+    pub store_validator_period: Option<Duration>,
+    /// Number of entries the background store validator checks at once.
+    pub store_validator_batch_size: u64,
 }
 
 impl ClientConfig {
@@ -150,15 +180,21 @@ impl ClientConfig {
             doosmslug_step_period: Duration::from_millis(100),
             block_header_fetch_horizon: 50,
             gc_blocks_limit: 100,
+            gc_retention: GCRetentionConfig::default(),
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive,
+            account_history: false,
             log_summary_style: LogSummaryStyle::Colored,
             view_client_threads: 1,
             epoch_sync_enabled,
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
             max_gas_burnt_view: None,
+            state_parts_dump_dir: None,
+            state_sync_from_dump_dir: None,
+            store_validator_period: None,
+            store_validator_batch_size: 1000,
         }
     }
 }
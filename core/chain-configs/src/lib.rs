@@ -2,7 +2,9 @@ mod client_config;
 mod genesis_config;
 pub mod genesis_validate;
 
-pub use client_config::{ClientConfig, LogSummaryStyle, TEST_STATE_SYNC_TIMEOUT};
+pub use client_config::{
+    ClientConfig, GCRetentionConfig, LogSummaryStyle, TEST_STATE_SYNC_TIMEOUT,
+};
 pub use genesis_config::{
     get_initial_supply, Genesis, GenesisConfig, GenesisRecords, ProtocolConfig, ProtocolConfigView,
 };
@@ -180,6 +180,22 @@ pub enum AccessKeyPermission {
     /// Grants full access to the account.
     /// NOTE: It's used to replace account-level public keys.
     FullAccess,
+
+    /// Same as `FunctionCall`, but allows calling any of several receivers.
+    FunctionCallMultiReceiver(FunctionCallMultiReceiverPermission),
+}
+
+impl AccessKeyPermission {
+    /// Returns the allowance of a function call access key, or `None` for full access keys.
+    pub fn allowance_mut(&mut self) -> Option<&mut Option<Balance>> {
+        match self {
+            AccessKeyPermission::FunctionCall(permission) => Some(&mut permission.allowance),
+            AccessKeyPermission::FunctionCallMultiReceiver(permission) => {
+                Some(&mut permission.allowance)
+            }
+            AccessKeyPermission::FullAccess => None,
+        }
+    }
 }
 
 /// Grants limited permission to make transactions with FunctionCallActions
@@ -194,8 +210,8 @@ pub struct FunctionCallPermission {
     /// transaction fees. When this access key is used, both account balance and the allowance is
     /// decreased by the same value.
     /// `None` means unlimited allowance.
-    /// NOTE: To change or increase the allowance, the old access key needs to be deleted and a new
-    /// access key should be created.
+    /// NOTE: Before `UpdateAccessKey` action is enabled, the only way to change or increase the
+    /// allowance is to delete the old access key and create a new one.
     #[serde(with = "option_u128_dec_format")]
     pub allowance: Option<Balance>,
 
@@ -208,6 +224,30 @@ pub struct FunctionCallPermission {
     pub method_names: Vec<String>,
 }
 
+/// Grants the same permission as `FunctionCallPermission`, except that function calls can be made
+/// to any account from the list of receivers.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug,
+)]
+pub struct FunctionCallMultiReceiverPermission {
+    /// Balance limit to pay for function call gas and transaction fees, see
+    /// `FunctionCallPermission::allowance`. `None` means unlimited allowance.
+    #[serde(with = "option_u128_dec_format")]
+    pub allowance: Option<Balance>,
+
+    /// The access key only allows transactions with one of the given receivers' account ids.
+    pub receiver_ids: Vec<String>,
+
+    /// A list of method names that can be used.
+    /// Empty list means any method name can be used.
+    pub method_names: Vec<String>,
+}
+
+impl FunctionCallMultiReceiverPermission {
+    /// Max number of receivers a single access key can be restricted to.
+    pub const MAX_RECEIVER_IDS: u64 = 16;
+}
+
 #[cfg(test)]
 mod tests {
     use borsh::BorshSerialize;
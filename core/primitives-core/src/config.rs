@@ -81,6 +81,23 @@ pub struct VMLimitConfig {
     pub max_promises_per_function_call_action: u64,
     /// Max number of input data dependencies
     pub max_number_input_data_dependencies: u64,
+
+    /// WebAssembly features beyond the MVP accepted in contracts.
+    pub wasm_features: WasmFeatures,
+}
+
+/// WebAssembly proposals accepted in contracts on top of the MVP. The code using them is
+/// rewritten into MVP code when the contract is prepared, so it is metered like the code
+/// compilers emit without these features.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct WasmFeatures {
+    /// Sign-extension operators, e.g. `i32.extend8_s`.
+    pub sign_extension: bool,
+    /// `memory.copy` and `memory.fill` from the bulk memory operations.
+    pub bulk_memory: bool,
+    /// Functions and blocks with several results and blocks with parameters.
+    pub multi_value: bool,
 }
 
 impl Default for VMConfig {
@@ -162,6 +179,9 @@ impl Default for VMLimitConfig {
             max_promises_per_function_call_action: 1024,
             // Unlikely to hit it for normal development.
             max_number_input_data_dependencies: 128,
+
+            // Enabled by a runtime config diff at the protocol version of `WasmExtensions`.
+            wasm_features: WasmFeatures::default(),
         }
     }
 }
@@ -1,4 +1,8 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
 use crate::hash::{hash as sha256, CryptoHash};
+use crate::types::{AccountId, StorageUsage};
 
 pub struct ContractCode {
     pub code: Vec<u8>,
@@ -19,3 +23,34 @@ impl ContractCode {
         &self.code
     }
 }
+
+/// Registration of a code in the shared contract code registry.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Debug,
+)]
+pub struct SharedContractCodeOwner {
+    /// The account which registered the code and can remove it.
+    pub account_id: AccountId,
+    /// Storage usage charged to the account for the copies of the code on all the shards. It is
+    /// released when the registration is removed.
+    pub storage_usage: StorageUsage,
+}
+
+/// Code in the shared contract code registry, which has a copy on every shard.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SharedContractCode {
+    /// Registrations of the code. An account which registered the code twice is listed twice.
+    /// Accounts can deploy the code by hash only while it has registrations.
+    pub owners: Vec<SharedContractCodeOwner>,
+    /// Number of the accounts of the shard which deployed the code by hash. The copy of the code
+    /// is kept for them after the last registration is removed.
+    pub num_users: u64,
+    pub code: Vec<u8>,
+}
+
+impl SharedContractCode {
+    /// Whether the copy of the code isn't needed on the shard anymore.
+    pub fn is_unused(&self) -> bool {
+        self.owners.is_empty() && self.num_users == 0
+    }
+}
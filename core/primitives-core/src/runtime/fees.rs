@@ -12,7 +12,7 @@ use crate::types::Gas;
 /// by the receiver).
 /// NOTE: `send_sir` or `send_not_sir` fees are usually burned when the item is being created.
 /// And `execution` fee is burned when the item is being executed.
-#[derive(Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 pub struct Fee {
     /// Fee for sending an object from the sender to itself, guaranteeing that it does not leave
     /// the shard.
@@ -114,6 +114,12 @@ pub struct ActionCreationConfig {
 
     /// Base cost of deleting an account.
     pub delete_account_cost: Fee,
+
+    /// Base cost of a delegate action, excluding the actions it carries. Missing in the configs
+    /// made before delegate actions were added, the cost is set by the config of the protocol
+    /// version which enables them.
+    #[serde(default)]
+    pub delegate_cost: Fee,
 }
 
 /// Describes the cost of creating an access key.
@@ -220,6 +226,11 @@ impl Default for RuntimeFeesConfig {
                     send_not_sir: 147489000000,
                     execution: 147489000000,
                 },
+                delegate_cost: Fee {
+                    send_sir: 200000000000,
+                    send_not_sir: 200000000000,
+                    execution: 200000000000,
+                },
             },
             storage_usage_config: StorageUsageConfig {
                 // See Account in core/primitives/src/account.rs for the data structure.
@@ -256,7 +267,8 @@ impl RuntimeFeesConfig {
                     function_call_cost_per_byte: free.clone(),
                 },
                 delete_key_cost: free.clone(),
-                delete_account_cost: free,
+                delete_account_cost: free.clone(),
+                delegate_cost: free,
             },
             storage_usage_config: StorageUsageConfig {
                 num_bytes_account: 0,
@@ -30,6 +30,7 @@ fn create_transaction() -> SignedTransaction {
             receiver_id: "1231231232131".parse().unwrap(),
             block_hash: Default::default(),
             actions,
+            priority_fee: None,
         },
     )
 }
@@ -14,13 +14,14 @@ pub use crate::block_header::*;
 use crate::challenge::{Challenges, ChallengesResult};
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::{merklize, verify_path, MerklePath};
+use crate::receipt::CongestionInfo;
 use crate::sharding::{
     ChunkHashHeight, EncodedShardChunk, ReedSolomonWrapper, ShardChunk, ShardChunkHeader,
     ShardChunkHeaderV1,
 };
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use crate::types::NumBlocks;
-use crate::types::{Balance, BlockHeight, EpochId, Gas, NumShards, StateRoot};
+use crate::types::{Balance, BlockHeight, EpochId, Gas, NumShards, ShardId, StateRoot};
 use crate::utils::to_timestamp;
 use crate::validator_signer::{EmptyValidatorSigner, ValidatorSigner};
 use crate::version::{ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
@@ -101,6 +102,9 @@ pub fn genesis_chunks(
                 vec![],
                 &vec![],
                 CryptoHash::default(),
+                CongestionInfo::default(),
+                vec![],
+                0,
                 &EmptyValidatorSigner::default(),
                 genesis_protocol_version,
             )
@@ -297,6 +301,8 @@ impl Block {
             block_merkle_root,
             #[cfg(feature = "protocol_feature_block_header_v3")]
             prev.height(),
+            #[cfg(feature = "protocol_feature_block_header_v3")]
+            Block::compute_priority_fees(&chunks, height),
         );
 
         Self::block_from_protocol_version(
@@ -430,6 +436,34 @@ impl Block {
         })
     }
 
+    pub fn compute_priority_fees<'a, T: IntoIterator<Item = &'a ShardChunkHeader>>(
+        chunks: T,
+        height: BlockHeight,
+    ) -> Balance {
+        chunks.into_iter().fold(0, |acc, chunk| {
+            if chunk.height_included() == height {
+                acc + chunk.priority_fees()
+            } else {
+                acc
+            }
+        })
+    }
+
+    /// Shards whose latest chunk reports a delayed receipts queue too large for their gas limit.
+    pub fn compute_congested_shards<'a, T: IntoIterator<Item = &'a ShardChunkHeader>>(
+        chunks: T,
+    ) -> Vec<ShardId> {
+        chunks
+            .into_iter()
+            .filter(|chunk| {
+                chunk.congestion_info().map_or(false, |congestion_info| {
+                    congestion_info.is_congested(chunk.gas_limit())
+                })
+            })
+            .map(|chunk| chunk.shard_id())
+            .collect()
+    }
+
     pub fn validate_chunk_header_proof(
         chunk: &ShardChunkHeader,
         chunk_root: &CryptoHash,
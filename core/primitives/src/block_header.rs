@@ -110,6 +110,7 @@ pub struct BlockHeaderInnerRestV2 {
 /// Add `prev_height`
 /// Add `block_ordinal`
 /// Add `epoch_sync_data_hash`
+/// Add `priority_fees`
 /// Use new `ValidatorStake` struct
 #[cfg(feature = "protocol_feature_block_header_v3")]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
@@ -147,6 +148,9 @@ pub struct BlockHeaderInnerRestV3 {
 
     pub epoch_sync_data_hash: Option<CryptoHash>,
 
+    /// Priority fees of the transactions of the new chunks included in this block
+    pub priority_fees: Balance,
+
     /// All the approvals included in this block
     pub approvals: Vec<Option<Signature>>,
 
@@ -362,6 +366,7 @@ impl BlockHeader {
         next_bp_hash: CryptoHash,
         block_merkle_root: CryptoHash,
         #[cfg(feature = "protocol_feature_block_header_v3")] prev_height: BlockHeight,
+        #[cfg(feature = "protocol_feature_block_header_v3")] priority_fees: Balance,
     ) -> Self {
         let inner_lite = BlockHeaderInnerLite {
             height,
@@ -467,6 +472,7 @@ impl BlockHeader {
                     last_ds_final_block,
                     prev_height,
                     epoch_sync_data_hash,
+                    priority_fees,
                     approvals,
                     latest_protocol_version: PROTOCOL_VERSION,
                 };
@@ -598,6 +604,7 @@ impl BlockHeader {
                     last_ds_final_block: CryptoHash::default(),
                     prev_height: 0,
                     epoch_sync_data_hash: None, // Epoch Sync cannot be executed up to Genesis
+                    priority_fees: 0,
                     approvals: vec![],
                     latest_protocol_version: genesis_protocol_version,
                 };
@@ -667,6 +674,16 @@ impl BlockHeader {
         }
     }
 
+    #[inline]
+    pub fn priority_fees(&self) -> Balance {
+        match self {
+            BlockHeader::BlockHeaderV1(_) => 0,
+            BlockHeader::BlockHeaderV2(_) => 0,
+            #[cfg(feature = "protocol_feature_block_header_v3")]
+            BlockHeader::BlockHeaderV3(header) => header.inner_rest.priority_fees,
+        }
+    }
+
     #[inline]
     pub fn epoch_id(&self) -> &EpochId {
         match self {
@@ -137,6 +137,7 @@ pub mod block_info {
             validator_mask: Vec<bool>,
             slashed: Vec<SlashedValidator>,
             total_supply: Balance,
+            priority_fees: Balance,
             latest_protocol_version: ProtocolVersion,
             timestamp_nanosec: u64,
         ) -> Self {
@@ -164,6 +165,7 @@ pub mod block_info {
                 epoch_first_block: Default::default(),
                 epoch_id: Default::default(),
                 timestamp_nanosec,
+                epoch_priority_fees: priority_fees,
             })
         }
 
@@ -294,6 +296,23 @@ pub mod block_info {
                 Self::V2(v2) => &v2.timestamp_nanosec,
             }
         }
+
+        #[inline]
+        pub fn epoch_priority_fees(&self) -> &Balance {
+            match self {
+                Self::V1(_) => &0,
+                Self::V2(v2) => &v2.epoch_priority_fees,
+            }
+        }
+
+        /// Adds the priority fees of the previous blocks of the epoch.
+        #[inline]
+        pub fn add_epoch_priority_fees(&mut self, priority_fees: Balance) {
+            match self {
+                Self::V1(_) => {}
+                Self::V2(v2) => v2.epoch_priority_fees += priority_fees,
+            }
+        }
     }
 
     // V1 -> V2: Use versioned ValidatorStake structure in proposals
@@ -315,6 +334,8 @@ pub mod block_info {
         /// Total supply at this block.
         pub total_supply: Balance,
         pub timestamp_nanosec: u64,
+        /// Priority fees of the blocks of the epoch up to and including this one.
+        pub epoch_priority_fees: Balance,
     }
 }
 
@@ -409,6 +430,14 @@ pub mod block_info {
         pub fn timestamp_nanosec(&self) -> &u64 {
             &self.timestamp_nanosec
         }
+
+        #[inline]
+        pub fn epoch_priority_fees(&self) -> &Balance {
+            &0
+        }
+
+        #[inline]
+        pub fn add_epoch_priority_fees(&mut self, _priority_fees: Balance) {}
     }
 }
 
@@ -444,6 +473,7 @@ impl BlockInfoV1 {
         validator_mask: Vec<bool>,
         slashed: Vec<SlashedValidator>,
         total_supply: Balance,
+        _priority_fees: Balance,
         latest_protocol_version: ProtocolVersion,
         timestamp_nanosec: u64,
     ) -> Self {
@@ -1,5 +1,6 @@
 use crate::serialize::u128_dec_format;
 use crate::types::{AccountId, Balance, EpochId, Gas, Nonce};
+use crate::version::ProtocolVersion;
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::PublicKey;
 use serde::{Deserialize, Serialize};
@@ -145,6 +146,8 @@ pub enum InvalidTxError {
     ActionsValidation(ActionsValidationError),
     /// The size of serialized transaction exceeded the limit.
     TransactionSizeExceeded { size: u64, limit: u64 },
+    /// TX signer_id is an account whose deletion is in progress
+    SignerIsBeingDeleted { signer_id: AccountId },
 }
 
 #[derive(
@@ -170,6 +173,8 @@ pub enum InvalidAccessKeyError {
     },
     /// Having a deposit with a function call action is not allowed with a function call access key.
     DepositWithFunctionCall,
+    /// Transaction `receiver_id` is not one of the access key receiver_ids
+    ReceiverNotAllowed { tx_receiver: AccountId, ak_receivers: Vec<String> },
 }
 
 /// Describes the error for validating a list of actions.
@@ -201,6 +206,15 @@ pub enum ActionsValidationError {
     UnsuitableStakingKey { public_key: PublicKey },
     /// The attached amount of gas in a FunctionCall action has to be a positive number.
     FunctionCallZeroAttachedGas,
+    /// A delegate action can't contain another delegate action.
+    DelegateActionNested,
+    /// The action, or the priority fee of the transaction, is not enabled in the current
+    /// protocol version.
+    UnsupportedProtocolFeature { protocol_feature: String, version: ProtocolVersion },
+    /// The number of receiver ids exceeded the limit in a Add Key action.
+    AddKeyReceiverIdsNumberExceeded { number_of_receiver_ids: u64, limit: u64 },
+    /// Some receiver id is not a valid account id in a Add Key action.
+    AddKeyInvalidReceiverId { receiver_id: String },
 }
 
 /// Describes the error for validating a receipt.
@@ -315,6 +329,25 @@ impl Display for ActionsValidationError {
                 f,
                 "The attached amount of gas in a FunctionCall action has to be a positive number",
             ),
+            ActionsValidationError::DelegateActionNested => write!(
+                f,
+                "A Delegate action can't contain another Delegate action",
+            ),
+            ActionsValidationError::UnsupportedProtocolFeature { protocol_feature, version } => write!(
+                f,
+                "{} is not enabled in the protocol version {}",
+                protocol_feature, version
+            ),
+            ActionsValidationError::AddKeyReceiverIdsNumberExceeded { number_of_receiver_ids, limit } => write!(
+                f,
+                "The number of allowed receiver ids {} exceeds the maximum allowed number {} in a AddKey action",
+                number_of_receiver_ids, limit
+            ),
+            ActionsValidationError::AddKeyInvalidReceiverId { receiver_id } => write!(
+                f,
+                "The allowed receiver id {:?} is not a valid account id in a AddKey action",
+                receiver_id
+            ),
         }
     }
 }
@@ -431,6 +464,40 @@ pub enum ActionErrorKind {
     OnlyImplicitAccountCreationAllowed { account_id: AccountId },
     /// Delete account whose state is large is temporarily banned.
     DeleteAccountWithLargeState { account_id: AccountId },
+    /// The signature of a `DelegateAction` doesn't match its public key.
+    DelegateActionInvalidSignature,
+    /// The `sender_id` of a `DelegateAction` is not the receiver of the transaction carrying it.
+    DelegateActionSenderDoesNotMatchTxReceiver { sender_id: AccountId, receiver_id: AccountId },
+    /// The `max_block_height` of a `DelegateAction` has passed.
+    DelegateActionExpired,
+    /// The access key a `DelegateAction` is signed with doesn't allow its actions.
+    DelegateActionAccessKeyError(InvalidAccessKeyError),
+    /// The nonce of a `DelegateAction` has to be larger than the nonce of its access key.
+    DelegateActionInvalidNonce { delegate_nonce: Nonce, ak_nonce: Nonce },
+    /// The nonce of a `DelegateAction` exceeds the upper bound of the access key nonces.
+    DelegateActionNonceTooLarge { delegate_nonce: Nonce, upper_bound: Nonce },
+    /// `DeployContractByHash` references a code missing from the shared contract code registry.
+    SharedContractCodeNotFound { code_hash: CryptoHash },
+    /// `RemoveSharedContract` removes a registration of a code which the account didn't register
+    /// in the shared contract code registry.
+    SharedContractCodeNotOwned { account_id: AccountId, code_hash: CryptoHash },
+    /// An account can't be deleted while it has registrations in the shared contract code
+    /// registry, which it pays the storage for.
+    DeleteAccountWithSharedContractCode { account_id: AccountId },
+    /// Account tries to update an access key that doesn't exist
+    UpdateKeyDoesNotExist { account_id: AccountId, public_key: PublicKey },
+    /// `UpdateAccessKey` can only change function call access keys
+    UpdateKeyRequiresFunctionCallKey { account_id: AccountId, public_key: PublicKey },
+    /// The migration method called by `DeployContractAndMigrate` failed, the deployment of the
+    /// new code is reverted
+    ContractMigrationFailed {
+        account_id: AccountId,
+        method_name: String,
+        error: FunctionCallErrorSer,
+    },
+    /// The receipt is sent to an account whose storage is being removed by a multi-step
+    /// account deletion. Only the deletion itself and balance refunds are applied to it.
+    AccountIsBeingDeleted { account_id: AccountId },
 }
 
 impl From<ActionErrorKind> for ActionError {
@@ -492,6 +559,9 @@ impl Display for InvalidTxError {
             InvalidTxError::TransactionSizeExceeded { size, limit } => {
                 write!(f, "Size of serialized transaction {} exceeded the limit {}", size, limit)
             }
+            InvalidTxError::SignerIsBeingDeleted { signer_id } => {
+                write!(f, "Signer {:?} is being deleted", signer_id)
+            }
         }
     }
 }
@@ -539,6 +609,11 @@ impl Display for InvalidAccessKeyError {
             InvalidAccessKeyError::DepositWithFunctionCall => {
                 write!(f, "Having a deposit with a function call action is not allowed with a function call access key.")
             }
+            InvalidAccessKeyError::ReceiverNotAllowed { tx_receiver, ak_receivers } => write!(
+                f,
+                "Transaction receiver_id {:?} is not one of the access key receiver_ids {:?}",
+                tx_receiver, ak_receivers
+            ),
         }
     }
 }
@@ -732,6 +807,41 @@ impl Display for ActionErrorKind {
             ActionErrorKind::InsufficientStake { account_id, stake, minimum_stake } => write!(f, "Account {} tries to stake {} but minimum required stake is {}", account_id, stake, minimum_stake),
             ActionErrorKind::OnlyImplicitAccountCreationAllowed { account_id } => write!(f, "CreateAccount action is called on hex-characters account of length 64 {}", account_id),
             ActionErrorKind::DeleteAccountWithLargeState { account_id } => write!(f, "The state of account {} is too large and therefore cannot be deleted", account_id),
+            ActionErrorKind::DelegateActionInvalidSignature => write!(f, "DelegateAction is not signed with the given public key"),
+            ActionErrorKind::DelegateActionSenderDoesNotMatchTxReceiver { sender_id, receiver_id } => write!(f, "Transaction receiver {} doesn't match DelegateAction sender {}", receiver_id, sender_id),
+            ActionErrorKind::DelegateActionExpired => write!(f, "DelegateAction has expired"),
+            ActionErrorKind::DelegateActionAccessKeyError(access_key_error) => Display::fmt(&access_key_error, f),
+            ActionErrorKind::DelegateActionInvalidNonce { delegate_nonce, ak_nonce } => write!(f, "DelegateAction nonce {} must be larger than nonce of the used access key {}", delegate_nonce, ak_nonce),
+            ActionErrorKind::DelegateActionNonceTooLarge { delegate_nonce, upper_bound } => write!(f, "DelegateAction nonce {} must be smaller than the access key nonce upper bound {}", delegate_nonce, upper_bound),
+            ActionErrorKind::SharedContractCodeNotFound { code_hash } => write!(f, "Contract code with hash {} is not registered in the shared contract code registry", code_hash),
+            ActionErrorKind::SharedContractCodeNotOwned { account_id, code_hash } => write!(
+                f,
+                "Account {:?} didn't register the contract code with hash {} in the shared contract code registry",
+                account_id, code_hash
+            ),
+            ActionErrorKind::DeleteAccountWithSharedContractCode { account_id } => write!(
+                f,
+                "Account {:?} has registrations in the shared contract code registry and can't be deleted",
+                account_id
+            ),
+            ActionErrorKind::UpdateKeyDoesNotExist { account_id, .. } => write!(
+                f,
+                "Account {:?} tries to update an access key that doesn't exist",
+                account_id
+            ),
+            ActionErrorKind::UpdateKeyRequiresFunctionCallKey { public_key, .. } => write!(
+                f,
+                "The access key {:?} is not a function call access key and can't be updated",
+                public_key
+            ),
+            ActionErrorKind::ContractMigrationFailed { account_id, method_name, error } => write!(
+                f,
+                "Migration method {} of the contract deployed on account {:?} failed: {:?}",
+                method_name, account_id, error
+            ),
+            ActionErrorKind::AccountIsBeingDeleted { account_id } => {
+                write!(f, "Account {:?} is being deleted", account_id)
+            }
         }
     }
 }
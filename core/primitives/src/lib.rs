@@ -19,12 +19,14 @@ pub mod runtime;
 pub mod serialize;
 pub mod shard_layout;
 pub mod sharding;
+pub mod state_proof;
 pub mod state_record;
 pub mod syncing;
 pub mod telemetry;
 pub mod test_utils;
 pub mod transaction;
 pub mod trie_key;
+pub mod trie_node;
 pub mod types;
 pub mod utils;
 pub mod validator_signer;
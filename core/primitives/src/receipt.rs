@@ -9,12 +9,13 @@ use near_crypto::{KeyType, PublicKey};
 use crate::borsh::maybestd::collections::HashMap;
 use crate::hash::CryptoHash;
 use crate::logging;
-use crate::serialize::{option_base64_format, u128_dec_format_compatible};
-use crate::transaction::{Action, TransferAction};
-use crate::types::{AccountId, Balance, ShardId};
+use crate::serialize::{base64_format, option_base64_format, u128_dec_format_compatible};
+use crate::transaction::{Action, DeleteAccountAction, TransferAction};
+use crate::types::{AccountId, Balance, Gas, ShardId, StorageUsage};
 
 /// Receipts are used for a cross-shard communication.
-/// Receipts could be 2 types (determined by a `ReceiptEnum`): `ReceiptEnum::Action` of `ReceiptEnum::Data`.
+/// Receipts could be 4 types (determined by a `ReceiptEnum`): `ReceiptEnum::Action`, `ReceiptEnum::Data`,
+/// `ReceiptEnum::Delegated` or `ReceiptEnum::SharedContractCode`.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Receipt {
     /// An issuer account_id of a particular receipt.
@@ -60,6 +61,29 @@ impl Receipt {
         }
     }
 
+    /// Generates a receipt from system continuing the deletion of the given account without a
+    /// receipt_id. Its execution, whose gas is paid from the balance of the account, removes the
+    /// next batch of the account records and either sends the balance to the beneficiary or
+    /// generates the next receipt of the deletion.
+    pub fn new_account_deletion(account_id: &AccountId, beneficiary_id: &AccountId) -> Self {
+        Receipt {
+            predecessor_id: AccountId::system_account(),
+            receiver_id: account_id.clone(),
+            receipt_id: CryptoHash::default(),
+
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: AccountId::system_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::DeleteAccount(DeleteAccountAction {
+                    beneficiary_id: beneficiary_id.clone(),
+                })],
+            }),
+        }
+    }
+
     /// Generates a receipt with a transfer action from system for a given balance without a
     /// receipt_id. It contains `signer_id` and `signer_public_key` to indicate this is a gas
     /// refund. The execution of this receipt will try to refund the allowance of the
@@ -88,11 +112,27 @@ impl Receipt {
     }
 }
 
-/// Receipt could be either ActionReceipt or DataReceipt
+/// Receipt could be either ActionReceipt, DataReceipt, DelegatedActionReceipt or
+/// SharedContractCodeReceipt
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum ReceiptEnum {
     Action(ActionReceipt),
     Data(DataReceipt),
+    Delegated(DelegatedActionReceipt),
+    SharedContractCode(SharedContractCodeReceipt),
+}
+
+impl ReceiptEnum {
+    /// The action receipt of `Action` and `Delegated` receipts.
+    pub fn action_receipt(&self) -> Option<&ActionReceipt> {
+        match self {
+            ReceiptEnum::Action(action_receipt)
+            | ReceiptEnum::Delegated(DelegatedActionReceipt { action_receipt, .. }) => {
+                Some(action_receipt)
+            }
+            ReceiptEnum::Data(_) | ReceiptEnum::SharedContractCode(_) => None,
+        }
+    }
 }
 
 /// ActionReceipt is derived from an Action from `Transaction or from Receipt`
@@ -117,6 +157,30 @@ pub struct ActionReceipt {
     pub actions: Vec<Action>,
 }
 
+/// Receipt with the actions of a `DelegateAction`, sent on behalf of its sender. It is executed
+/// like an `ActionReceipt`, except that the deposits of failed actions are refunded to
+/// `refund_to` rather than to the predecessor.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct DelegatedActionReceipt {
+    pub action_receipt: ActionReceipt,
+    /// The relayer which paid the deposits.
+    pub refund_to: AccountId,
+}
+
+/// Update of the shared contract code registry by the account `predecessor_id` of the receipt.
+/// It is sent to every shard, to the accounts from `ShardLayout::shard_receiver_ids`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum SharedContractCodeReceipt {
+    /// Registers `code`, for which the account was charged `storage_usage`.
+    Register {
+        storage_usage: StorageUsage,
+        #[serde(with = "base64_format")]
+        code: Vec<u8>,
+    },
+    /// Removes a registration of the code with `code_hash`.
+    Remove { code_hash: CryptoHash },
+}
+
 /// An incoming (ingress) `DataReceipt` which is going to a Receipt's `receiver` input_data_ids
 /// Which will be converted to `PromiseResult::Successful(value)` or `PromiseResult::Failed`
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Hash, PartialEq, Eq, Clone)]
@@ -136,6 +200,21 @@ pub struct DataReceiver {
     pub receiver_id: AccountId,
 }
 
+impl fmt::Debug for SharedContractCodeReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharedContractCodeReceipt::Register { storage_usage, code } => f
+                .debug_struct("Register")
+                .field("storage_usage", storage_usage)
+                .field("code", &format_args!("{}", logging::pretty_utf8(code)))
+                .finish(),
+            SharedContractCodeReceipt::Remove { code_hash } => {
+                f.debug_struct("Remove").field("code_hash", code_hash).finish()
+            }
+        }
+    }
+}
+
 impl fmt::Debug for DataReceipt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("DataReceipt")
@@ -171,5 +250,43 @@ pub struct DelayedReceiptIndices {
     pub next_available_index: u64,
 }
 
+/// Number of chunks worth of gas (by the chunk gas limit) that can be backlogged in the delayed
+/// receipts queue of a shard before the shard is considered congested.
+pub const CONGESTION_GAS_BACKLOG_CHUNKS: u64 = 10;
+
+/// Number of receipts in the delayed receipts queue of a shard at which the shard is considered
+/// congested, no matter how much gas they carry.
+pub const CONGESTION_MAX_DELAYED_RECEIPTS: u64 = 10_000;
+
+/// Size of the delayed receipts queue of a shard, published in the chunk header so that other
+/// shards stop sending new work to the shard while it's congested.
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    Serialize,
+    Deserialize,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+pub struct CongestionInfo {
+    /// Number of receipts in the delayed receipts queue.
+    pub delayed_receipts_count: u64,
+    /// Total gas attached to the receipts in the delayed receipts queue.
+    /// NOTE: Receipts delayed before `ProtocolFeature::CongestionControl` are not accounted.
+    pub delayed_receipts_gas: Gas,
+}
+
+impl CongestionInfo {
+    /// Whether the shard with the given chunk gas limit can't keep up with its delayed receipts.
+    pub fn is_congested(&self, gas_limit: Gas) -> bool {
+        self.delayed_receipts_count >= CONGESTION_MAX_DELAYED_RECEIPTS
+            || self.delayed_receipts_gas > gas_limit.saturating_mul(CONGESTION_GAS_BACKLOG_CHUNKS)
+    }
+}
+
 /// Map of shard to list of receipts to send to it.
 pub type ReceiptResult = HashMap<ShardId, Vec<Receipt>>;
@@ -1,8 +1,9 @@
 use crate::runtime::migration_data::{MigrationData, MigrationFlags};
+use crate::shard_layout::ShardLayout;
 use crate::{
     hash::CryptoHash,
     runtime::config::RuntimeConfig,
-    types::{Balance, BlockHeight, CompiledContractCache, EpochHeight, EpochId, Gas},
+    types::{Balance, BlockHeight, CompiledContractCache, EpochHeight, EpochId, Gas, ShardId},
     version::ProtocolVersion,
 };
 use std::sync::Arc;
@@ -37,6 +38,12 @@ pub struct ApplyState {
     pub cache: Option<Arc<dyn CompiledContractCache>>,
     /// Whether the chunk being applied is new.
     pub is_new_chunk: bool,
+    /// Layout of the shards of the current epoch, which the updates of the shared contract code
+    /// registry are sent to.
+    pub shard_layout: ShardLayout,
+    /// Other shards reported congested in the previous block. The receipts sent towards them by
+    /// the chunk are limited, the receipts left over wait in the delayed receipts queue.
+    pub congested_shards: Vec<ShardId>,
     /// Data for migrations that may need to be applied at the start of an epoch when protocol
     /// version changes
     pub migration_data: Arc<MigrationData>,
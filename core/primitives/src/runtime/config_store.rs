@@ -0,0 +1,192 @@
+//! Settings of the parameters of the runtime for each protocol version.
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::runtime::config::RuntimeConfig;
+use crate::version::{ProtocolFeature, ProtocolVersion};
+
+/// Changes of the parameters, by the protocol version they are made in. Each diff is a JSON
+/// object with the changed parameters, shaped like the serialized `RuntimeConfig`.
+static CONFIG_DIFFS: &[(ProtocolVersion, &str)] = &[
+    (
+        ProtocolFeature::LowerStorageCost.protocol_version(),
+        include_str!("../../res/runtime_configs/42.json"),
+    ),
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    (
+        ProtocolFeature::DelegateAction.protocol_version(),
+        include_str!("../../res/runtime_configs/116.json"),
+    ),
+    #[cfg(feature = "protocol_feature_wasm_extensions")]
+    (
+        ProtocolFeature::WasmExtensions.protocol_version(),
+        include_str!("../../res/runtime_configs/121.json"),
+    ),
+];
+
+/// Runtime configs of all the protocol versions. The config of the genesis is used until
+/// the first diff, and each diff is applied on top of the config of the previous one.
+#[derive(Debug)]
+pub struct RuntimeConfigStore {
+    store: BTreeMap<ProtocolVersion, Arc<RuntimeConfig>>,
+}
+
+impl RuntimeConfigStore {
+    /// Applies the checked in diffs to the runtime config from genesis.
+    ///
+    /// Panics if a diff is invalid, so that a broken diff stops the node on start.
+    pub fn new(genesis_runtime_config: &RuntimeConfig) -> Self {
+        Self::from_diffs(genesis_runtime_config, CONFIG_DIFFS)
+            .unwrap_or_else(|err| panic!("Invalid runtime config diff: {}", err))
+    }
+
+    fn from_diffs(
+        genesis_runtime_config: &RuntimeConfig,
+        diffs: &[(ProtocolVersion, &str)],
+    ) -> Result<Self, String> {
+        let mut store = BTreeMap::new();
+        store.insert(0, Arc::new(genesis_runtime_config.clone()));
+        let mut config = serde_json::to_value(genesis_runtime_config).map_err(|e| e.to_string())?;
+        let mut last_protocol_version = 0;
+        for (protocol_version, diff) in diffs {
+            if *protocol_version <= last_protocol_version {
+                return Err(format!(
+                    "diffs must be ordered by protocol version, got {} after {}",
+                    protocol_version, last_protocol_version
+                ));
+            }
+            last_protocol_version = *protocol_version;
+            let with_version =
+                |err: String| format!("protocol version {}: {}", protocol_version, err);
+            let diff: Value =
+                serde_json::from_str(diff).map_err(|e| with_version(e.to_string()))?;
+            apply_diff(&mut config, &diff, "").map_err(with_version)?;
+            let runtime_config: RuntimeConfig =
+                serde_json::from_value(config.clone()).map_err(|e| with_version(e.to_string()))?;
+            store.insert(*protocol_version, Arc::new(runtime_config));
+        }
+        Ok(Self { store })
+    }
+
+    /// Returns the config for the given protocol version.
+    pub fn for_protocol_version(&self, protocol_version: ProtocolVersion) -> &Arc<RuntimeConfig> {
+        self.store
+            .range((Bound::Unbounded, Bound::Included(protocol_version)))
+            .next_back()
+            .expect("the config of the genesis is always stored")
+            .1
+    }
+}
+
+/// Overwrites the parameters of `config` with the ones from `diff`. Every parameter of the diff
+/// has to exist in the config, so that a typo doesn't get ignored.
+fn apply_diff(config: &mut Value, diff: &Value, path: &str) -> Result<(), String> {
+    let (config, diff) = match (config, diff) {
+        (Value::Object(config), Value::Object(diff)) => (config, diff),
+        _ => return Err(format!("`{}` must be an object", path)),
+    };
+    for (key, diff_value) in diff {
+        let key_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+        let config_value =
+            config.get_mut(key).ok_or_else(|| format!("unknown parameter `{}`", key_path))?;
+        match (config_value.is_object(), diff_value.is_object()) {
+            (true, true) => apply_diff(config_value, diff_value, &key_path)?,
+            (false, false) => *config_value = diff_value.clone(),
+            _ => return Err(format!("`{}` has a wrong type", key_path)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_in_diffs() {
+        let config = RuntimeConfig::default();
+        let default_amount = config.storage_amount_per_byte;
+        let store = RuntimeConfigStore::new(&config);
+        let base_cfg = store.for_protocol_version(0);
+        let new_cfg = store.for_protocol_version(ProtocolVersion::MAX);
+        assert_eq!(default_amount, base_cfg.storage_amount_per_byte);
+        assert!(default_amount > new_cfg.storage_amount_per_byte);
+        let lower_storage_cost_version = ProtocolFeature::LowerStorageCost.protocol_version();
+        assert_eq!(store.for_protocol_version(lower_storage_cost_version - 1), base_cfg);
+        assert_eq!(
+            store.for_protocol_version(lower_storage_cost_version).storage_amount_per_byte,
+            new_cfg.storage_amount_per_byte
+        );
+        #[cfg(feature = "protocol_feature_delegate_action")]
+        {
+            // Genesis configs made before delegate actions don't have the cost.
+            let mut genesis_config = config.clone();
+            genesis_config.transaction_costs.action_creation_config.delegate_cost =
+                Default::default();
+            let store = RuntimeConfigStore::new(&genesis_config);
+            let delegate_action_version = ProtocolFeature::DelegateAction.protocol_version();
+            let delegate_cost = |protocol_version| {
+                store
+                    .for_protocol_version(protocol_version)
+                    .transaction_costs
+                    .action_creation_config
+                    .delegate_cost
+                    .clone()
+            };
+            assert_eq!(delegate_cost(delegate_action_version - 1), Default::default());
+            assert_eq!(
+                delegate_cost(delegate_action_version),
+                config.transaction_costs.action_creation_config.delegate_cost
+            );
+        }
+        #[cfg(feature = "protocol_feature_wasm_extensions")]
+        {
+            let wasm_extensions_version = ProtocolFeature::WasmExtensions.protocol_version();
+            let wasm_features = |protocol_version| {
+                store.for_protocol_version(protocol_version).wasm_config.limit_config.wasm_features
+            };
+            assert!(!wasm_features(wasm_extensions_version - 1).bulk_memory);
+            assert!(wasm_features(wasm_extensions_version).bulk_memory);
+            assert!(wasm_features(wasm_extensions_version).sign_extension);
+            assert!(wasm_features(wasm_extensions_version).multi_value);
+        }
+    }
+
+    #[test]
+    fn test_diffs_apply_in_order() {
+        let config = RuntimeConfig::default();
+        let store = RuntimeConfigStore::from_diffs(
+            &config,
+            &[
+                (10, r#"{"storage_amount_per_byte": "5", "wasm_config": {"regular_op_cost": 7}}"#),
+                (20, r#"{"storage_amount_per_byte": "3"}"#),
+            ],
+        )
+        .unwrap();
+        assert_eq!(**store.for_protocol_version(9), config);
+        let config_10 = store.for_protocol_version(15);
+        assert_eq!(config_10.storage_amount_per_byte, 5);
+        assert_eq!(config_10.wasm_config.regular_op_cost, 7);
+        assert_eq!(config_10.transaction_costs, config.transaction_costs);
+        let config_20 = store.for_protocol_version(20);
+        assert_eq!(config_20.storage_amount_per_byte, 3);
+        assert_eq!(config_20.wasm_config.regular_op_cost, 7);
+    }
+
+    #[test]
+    fn test_invalid_diffs() {
+        let config = RuntimeConfig::default();
+        for diffs in &[
+            vec![(10, r#"{"storage_amount_per_bytes": "5"}"#)],
+            vec![(10, r#"{"wasm_config": 5}"#)],
+            vec![(10, r#"{"storage_amount_per_byte": "five"}"#)],
+            vec![(10, r#"[]"#)],
+            vec![(20, r#"{}"#), (10, r#"{}"#)],
+        ] {
+            assert!(RuntimeConfigStore::from_diffs(&config, diffs).is_err(), "{:?}", diffs);
+        }
+    }
+}
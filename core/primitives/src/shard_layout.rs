@@ -1,11 +1,16 @@
 use crate::borsh::maybestd::io::Cursor;
 use crate::types::{AccountId, NumShards};
+use borsh::{BorshDeserialize, BorshSerialize};
 use byteorder::{LittleEndian, ReadBytesExt};
 use near_primitives_core::hash::hash;
 use near_primitives_core::types::ShardId;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering::Greater;
 
+/// Version of the shard layout. Together with the shard id it identifies a shard across layout
+/// changes, see `ShardUId`.
+pub type ShardVersion = u32;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum ShardLayout {
     V0(ShardLayoutV0),
@@ -28,6 +33,8 @@ pub struct ShardLayoutV1 {
     /// Parent shards for the shards, useful for constructing states for the shards.
     /// None for the genesis shard layout
     parent_shards: Option<Vec<ShardId>>,
+    /// Version of the shard layout, must be different from the version of the layout it replaces.
+    version: ShardVersion,
 }
 
 impl ShardLayout {
@@ -39,8 +46,9 @@ impl ShardLayout {
         fixed_shards: Vec<AccountId>,
         boundary_accounts: Vec<AccountId>,
         parent_shards: Option<Vec<ShardId>>,
+        version: ShardVersion,
     ) -> Self {
-        Self::V1(ShardLayoutV1 { fixed_shards, boundary_accounts, parent_shards })
+        Self::V1(ShardLayoutV1 { fixed_shards, boundary_accounts, parent_shards, version })
     }
 
     #[inline]
@@ -51,6 +59,33 @@ impl ShardLayout {
         }
     }
 
+    /// Returns the shard of the previous layout the state of `shard_id` is built from.
+    /// `None` if the layout has no parent layout.
+    pub fn get_parent_shard_id(&self, shard_id: ShardId) -> Option<ShardId> {
+        self.parent_shards().map(|parent_shards| parent_shards[shard_id as usize])
+    }
+
+    /// Returns the shards of this layout whose state is built from `parent_shard_id` of the
+    /// previous layout. `None` if the layout has no parent layout.
+    pub fn get_split_shards(&self, parent_shard_id: ShardId) -> Option<Vec<ShardId>> {
+        self.parent_shards().map(|parent_shards| {
+            parent_shards
+                .iter()
+                .enumerate()
+                .filter(|(_, parent)| **parent == parent_shard_id)
+                .map(|(shard_id, _)| shard_id as ShardId)
+                .collect()
+        })
+    }
+
+    #[inline]
+    pub fn version(&self) -> ShardVersion {
+        match self {
+            Self::V0(_) => 0,
+            Self::V1(v1) => v1.version,
+        }
+    }
+
     #[inline]
     pub fn num_shards(&self) -> NumShards {
         match self {
@@ -58,6 +93,99 @@ impl ShardLayout {
             Self::V1(v1) => (v1.fixed_shards.len() + v1.boundary_accounts.len() + 1) as NumShards,
         }
     }
+
+    pub fn get_shard_uids(&self) -> Vec<ShardUId> {
+        (0..self.num_shards())
+            .map(|shard_id| ShardUId::from_shard_id_and_layout(shard_id, self))
+            .collect()
+    }
+
+    /// Returns an account of every shard, which the receipts meant for the whole shard rather
+    /// than for a particular account are addressed to. The accounts don't have to exist.
+    /// Shards which can't have any accounts are skipped.
+    pub fn shard_receiver_ids(&self) -> Vec<AccountId> {
+        // The accounts of a range shard of version 1 start with its boundary account, the ones
+        // of version 0 are spread evenly across the shards.
+        let layout_candidates = match self {
+            Self::V0(_) => vec![],
+            Self::V1(v1) => v1
+                .fixed_shards
+                .iter()
+                .map(|account_id| account_id.to_string())
+                .chain(std::iter::once("00".to_string()))
+                .chain(v1.boundary_accounts.iter().flat_map(|account_id| {
+                    vec![
+                        account_id.to_string(),
+                        format!("{}0", account_id),
+                        format!("{}-0", account_id),
+                    ]
+                }))
+                .collect(),
+        };
+        let candidates = layout_candidates
+            .into_iter()
+            .chain((0..MAX_SHARD_RECEIVER_CANDIDATES).map(|i| format!("shard-{}", i)));
+        let mut receiver_ids: Vec<Option<AccountId>> = vec![None; self.num_shards() as usize];
+        let mut num_found = 0;
+        for candidate in candidates {
+            let account_id: AccountId = match candidate.parse() {
+                Ok(account_id) => account_id,
+                Err(_) => continue,
+            };
+            let receiver_id = &mut receiver_ids[account_id_to_shard_id(&account_id, self) as usize];
+            if receiver_id.is_none() {
+                *receiver_id = Some(account_id);
+                num_found += 1;
+                if num_found == receiver_ids.len() {
+                    break;
+                }
+            }
+        }
+        receiver_ids.into_iter().flatten().collect()
+    }
+}
+
+/// Number of the generated accounts `ShardLayout::shard_receiver_ids` tries before giving up on
+/// the shards it hasn't found an account of.
+const MAX_SHARD_RECEIVER_CANDIDATES: u64 = 100_000;
+
+/// Unique identifier of a shard across shard layouts.
+///
+/// The shard ids are reused between layouts, so the data used to split the states of a layout
+/// into the states of the next one (state roots of the new shards and the state changes in
+/// `ColStateChangesForSplitStates`) is keyed by `ShardUId`. The trie nodes in `ColState` and the
+/// trie caches are still keyed by the plain shard id: the nodes are addressed by their hash and
+/// reference counted, so the shards of both layouts which share an id can share their nodes.
+#[derive(
+    BorshSerialize, BorshDeserialize, Hash, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct ShardUId {
+    pub version: ShardVersion,
+    pub shard_id: u32,
+}
+
+impl ShardUId {
+    pub fn from_shard_id_and_layout(shard_id: ShardId, shard_layout: &ShardLayout) -> Self {
+        assert!(shard_id < shard_layout.num_shards());
+        ShardUId { version: shard_layout.version(), shard_id: shard_id as u32 }
+    }
+
+    /// Uid of the only shard of the single shard layout used in tests.
+    pub fn single_shard() -> Self {
+        ShardUId { version: 0, shard_id: 0 }
+    }
+
+    pub fn shard_id(&self) -> ShardId {
+        self.shard_id as ShardId
+    }
+
+    /// Same bytes as `ShardId::to_le_bytes` of the shard id for version 0.
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut res = [0; 8];
+        res[0..4].copy_from_slice(&self.shard_id.to_le_bytes());
+        res[4..].copy_from_slice(&self.version.to_le_bytes());
+        res
+    }
 }
 
 /// Maps account_id to shard_id given a shard_layout
@@ -103,7 +231,7 @@ fn is_top_level_account(top_account: &AccountId, account: &AccountId) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::shard_layout::{account_id_to_shard_id, ShardLayout};
+    use crate::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
     use rand::distributions::Alphanumeric;
     use rand::rngs::StdRng;
     use rand::{Rng, SeedableRng};
@@ -137,6 +265,7 @@ mod tests {
                 .collect(),
             vec!["abc", "foo", "paz"].into_iter().map(|s| s.parse().unwrap()).collect(),
             None,
+            1,
         );
         assert_eq!(account_id_to_shard_id(&"aurora".parse().unwrap(), &shard_layout), 0);
         assert_eq!(account_id_to_shard_id(&"foo.aurora".parse().unwrap(), &shard_layout), 0);
@@ -155,4 +284,48 @@ mod tests {
         assert_eq!(account_id_to_shard_id(&"goo".parse().unwrap(), &shard_layout), 6);
         assert_eq!(account_id_to_shard_id(&"zoo".parse().unwrap(), &shard_layout), 7);
     }
+
+    #[test]
+    fn test_shard_receiver_ids() {
+        let v1_layout = ShardLayout::v1(
+            vec!["aurora", "bar", "foo", "foo.baz"]
+                .into_iter()
+                .map(|s| s.parse().unwrap())
+                .collect(),
+            vec!["abc", "foo", "paz"].into_iter().map(|s| s.parse().unwrap()).collect(),
+            None,
+            1,
+        );
+        for shard_layout in &[ShardLayout::v0(1), ShardLayout::v0(16), v1_layout] {
+            let receiver_ids = shard_layout.shard_receiver_ids();
+            assert_eq!(receiver_ids.len() as u64, shard_layout.num_shards());
+            for (shard_id, receiver_id) in receiver_ids.iter().enumerate() {
+                assert_eq!(account_id_to_shard_id(receiver_id, shard_layout), shard_id as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parent_and_split_shards() {
+        let shard_layout = ShardLayout::v1(
+            vec!["aurora".parse().unwrap()],
+            vec!["near".parse().unwrap()],
+            Some(vec![0, 0, 1]),
+            1,
+        );
+        assert_eq!(shard_layout.get_parent_shard_id(0), Some(0));
+        assert_eq!(shard_layout.get_parent_shard_id(2), Some(1));
+        assert_eq!(shard_layout.get_split_shards(0), Some(vec![0, 1]));
+        assert_eq!(shard_layout.get_split_shards(1), Some(vec![2]));
+        assert_eq!(ShardLayout::v0(1).get_split_shards(0), None);
+    }
+
+    #[test]
+    fn test_shard_uid_bytes() {
+        assert_eq!(ShardUId { version: 0, shard_id: 3 }.to_bytes(), 3u64.to_le_bytes());
+        assert_ne!(
+            ShardUId { version: 1, shard_id: 3 }.to_bytes(),
+            ShardUId { version: 0, shard_id: 3 }.to_bytes()
+        );
+    }
 }
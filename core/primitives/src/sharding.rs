@@ -4,9 +4,11 @@ use serde::Serialize;
 
 use near_crypto::Signature;
 
+#[cfg(feature = "protocol_feature_block_header_v3")]
+use crate::checked_feature;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::{combine_hash, merklize, MerklePath};
-use crate::receipt::Receipt;
+use crate::receipt::{CongestionInfo, Receipt};
 use crate::transaction::SignedTransaction;
 use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, ValidatorStakeV1};
 use crate::types::{Balance, BlockHeight, Gas, MerkleHash, ShardId, StateRoot};
@@ -64,6 +66,8 @@ pub struct StateSyncInfo {
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
 pub mod shard_chunk_header_inner;
+#[cfg(feature = "protocol_feature_congestion_control")]
+pub use shard_chunk_header_inner::ShardChunkHeaderInnerV3;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 pub use shard_chunk_header_inner::{
     ShardChunkHeaderInner, ShardChunkHeaderInnerV1, ShardChunkHeaderInnerV2,
@@ -285,8 +289,37 @@ impl ShardChunkHeaderV3 {
         outgoing_receipts_root: CryptoHash,
         tx_root: CryptoHash,
         validator_proposals: Vec<ValidatorStake>,
+        congestion_info: Option<CongestionInfo>,
+        congested_shards: Vec<ShardId>,
+        priority_fees: Balance,
         signer: &dyn ValidatorSigner,
     ) -> Self {
+        #[cfg(feature = "protocol_feature_congestion_control")]
+        if let Some(congestion_info) = congestion_info {
+            let inner = ShardChunkHeaderInner::V3(ShardChunkHeaderInnerV3 {
+                prev_block_hash,
+                prev_state_root,
+                outcome_root,
+                encoded_merkle_root,
+                encoded_length,
+                height_created: height,
+                shard_id,
+                gas_used,
+                gas_limit,
+                balance_burnt,
+                outgoing_receipts_root,
+                tx_root,
+                validator_proposals,
+                congestion_info,
+                congested_shards,
+                priority_fees,
+            });
+            return Self::from_inner(inner, signer);
+        }
+        // Workaround unused variable warning
+        #[cfg(not(feature = "protocol_feature_congestion_control"))]
+        let _ = (congestion_info, congested_shards, priority_fees);
+
         let inner = ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
             prev_block_hash,
             prev_state_root,
@@ -302,6 +335,10 @@ impl ShardChunkHeaderV3 {
             tx_root,
             validator_proposals,
         });
+        Self::from_inner(inner, signer)
+    }
+
+    fn from_inner(inner: ShardChunkHeaderInner, signer: &dyn ValidatorSigner) -> Self {
         let hash = Self::compute_hash(&inner);
         let signature = signer.sign_chunk_hash(&hash);
         Self { inner, height_included: 0, signature, hash }
@@ -539,7 +576,70 @@ impl ShardChunkHeader {
                 SHARD_CHUNK_HEADER_UPGRADE_VERSION,
                 Some(block_header_v3_version),
             ),
+            #[cfg(not(feature = "protocol_feature_congestion_control"))]
             ShardChunkHeader::V3(_) => ProtocolVersionRange::new(block_header_v3_version, None),
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            ShardChunkHeader::V3(header) => {
+                let congestion_control_version =
+                    ProtocolFeature::CongestionControl.protocol_version();
+                match header.inner {
+                    ShardChunkHeaderInner::V3(_) => {
+                        ProtocolVersionRange::new(congestion_control_version, None)
+                    }
+                    _ => ProtocolVersionRange::new(
+                        block_header_v3_version,
+                        Some(congestion_control_version),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Size of the delayed receipts queue of the shard after the previous chunk, `None` for the
+    /// chunks produced before `ProtocolFeature::CongestionControl`.
+    #[inline]
+    pub fn congestion_info(&self) -> Option<CongestionInfo> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            #[cfg(all(
+                feature = "protocol_feature_block_header_v3",
+                not(feature = "protocol_feature_congestion_control")
+            ))]
+            Self::V3(_) => None,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(header) => header.inner.congestion_info(),
+        }
+    }
+
+    /// Shards reported congested in the previous block, empty for the chunks produced before
+    /// `ProtocolFeature::CongestionControl`.
+    #[inline]
+    pub fn congested_shards(&self) -> &[ShardId] {
+        match self {
+            Self::V1(_) | Self::V2(_) => &[],
+            #[cfg(all(
+                feature = "protocol_feature_block_header_v3",
+                not(feature = "protocol_feature_congestion_control")
+            ))]
+            Self::V3(_) => &[],
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(header) => header.inner.congested_shards(),
+        }
+    }
+
+    /// Priority fees of the transactions of the previous chunk, zero for the chunks produced
+    /// before `ProtocolFeature::CongestionControl`.
+    #[inline]
+    pub fn priority_fees(&self) -> Balance {
+        match self {
+            Self::V1(_) | Self::V2(_) => 0,
+            #[cfg(all(
+                feature = "protocol_feature_block_header_v3",
+                not(feature = "protocol_feature_congestion_control")
+            ))]
+            Self::V3(_) => 0,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(header) => header.inner.priority_fees(),
         }
     }
 }
@@ -814,6 +914,14 @@ impl ShardChunk {
         }
     }
 
+    #[inline]
+    pub fn prev_block_hash(&self) -> CryptoHash {
+        match self {
+            Self::V1(chunk) => chunk.header.inner.prev_block_hash,
+            Self::V2(chunk) => chunk.header.prev_block_hash(),
+        }
+    }
+
     #[inline]
     pub fn prev_state_root(&self) -> StateRoot {
         match self {
@@ -1092,6 +1200,9 @@ impl EncodedShardChunk {
         transactions: Vec<SignedTransaction>,
         outgoing_receipts: &Vec<Receipt>,
         outgoing_receipts_root: CryptoHash,
+        congestion_info: CongestionInfo,
+        congested_shards: Vec<ShardId>,
+        priority_fees: Balance,
         signer: &dyn ValidatorSigner,
         protocol_version: ProtocolVersion,
     ) -> Result<(Self, Vec<MerklePath>), std::io::Error> {
@@ -1155,9 +1266,22 @@ impl EncodedShardChunk {
             Ok((Self::V2(chunk), merkle_paths))
         } else {
             #[cfg(not(feature = "protocol_feature_block_header_v3"))]
-            unreachable!();
+            {
+                // Workaround unused variable warning
+                let _ = (congestion_info, congested_shards, priority_fees);
+                unreachable!();
+            }
             #[cfg(feature = "protocol_feature_block_header_v3")]
             {
+                let congestion_info = if checked_feature!(
+                    "protocol_feature_congestion_control",
+                    CongestionControl,
+                    protocol_version
+                ) {
+                    Some(congestion_info)
+                } else {
+                    None
+                };
                 let header = ShardChunkHeaderV3::new(
                     prev_block_hash,
                     prev_state_root,
@@ -1172,6 +1296,9 @@ impl EncodedShardChunk {
                     outgoing_receipts_root,
                     tx_root,
                     validator_proposals,
+                    congestion_info,
+                    congested_shards,
+                    priority_fees,
                     signer,
                 );
                 let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V3(header), content };
@@ -1,3 +1,5 @@
+#[cfg(feature = "protocol_feature_congestion_control")]
+use crate::receipt::CongestionInfo;
 use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, ValidatorStakeV1};
 use crate::types::StateRoot;
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -8,6 +10,8 @@ use near_primitives_core::types::{Balance, BlockHeight, Gas, ShardId};
 pub enum ShardChunkHeaderInner {
     V1(ShardChunkHeaderInnerV1),
     V2(ShardChunkHeaderInnerV2),
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    V3(ShardChunkHeaderInnerV3),
 }
 
 impl ShardChunkHeaderInner {
@@ -16,6 +20,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_state_root,
             Self::V2(inner) => &inner.prev_state_root,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.prev_state_root,
         }
     }
 
@@ -24,6 +30,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_block_hash,
             Self::V2(inner) => &inner.prev_block_hash,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.prev_block_hash,
         }
     }
 
@@ -32,6 +40,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.gas_limit,
             Self::V2(inner) => inner.gas_limit,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.gas_limit,
         }
     }
 
@@ -40,6 +50,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.gas_used,
             Self::V2(inner) => inner.gas_used,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.gas_used,
         }
     }
 
@@ -48,6 +60,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => ValidatorStakeIter::v1(&inner.validator_proposals),
             Self::V2(inner) => ValidatorStakeIter::new(&inner.validator_proposals),
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => ValidatorStakeIter::new(&inner.validator_proposals),
         }
     }
 
@@ -56,6 +70,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.height_created,
             Self::V2(inner) => inner.height_created,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.height_created,
         }
     }
 
@@ -64,6 +80,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.shard_id,
             Self::V2(inner) => inner.shard_id,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.shard_id,
         }
     }
 
@@ -72,6 +90,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.outcome_root,
             Self::V2(inner) => &inner.outcome_root,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.outcome_root,
         }
     }
 
@@ -80,6 +100,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.encoded_merkle_root,
             Self::V2(inner) => &inner.encoded_merkle_root,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.encoded_merkle_root,
         }
     }
 
@@ -88,6 +110,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.encoded_length,
             Self::V2(inner) => inner.encoded_length,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.encoded_length,
         }
     }
 
@@ -96,6 +120,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.balance_burnt,
             Self::V2(inner) => inner.balance_burnt,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => inner.balance_burnt,
         }
     }
 
@@ -104,6 +130,8 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.outgoing_receipts_root,
             Self::V2(inner) => &inner.outgoing_receipts_root,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.outgoing_receipts_root,
         }
     }
 
@@ -112,6 +140,35 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.tx_root,
             Self::V2(inner) => &inner.tx_root,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            Self::V3(inner) => &inner.tx_root,
+        }
+    }
+
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    #[inline]
+    pub fn congestion_info(&self) -> Option<CongestionInfo> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(inner) => Some(inner.congestion_info),
+        }
+    }
+
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    #[inline]
+    pub fn congested_shards(&self) -> &[ShardId] {
+        match self {
+            Self::V1(_) | Self::V2(_) => &[],
+            Self::V3(inner) => &inner.congested_shards,
+        }
+    }
+
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    #[inline]
+    pub fn priority_fees(&self) -> Balance {
+        match self {
+            Self::V1(_) | Self::V2(_) => 0,
+            Self::V3(inner) => inner.priority_fees,
         }
     }
 }
@@ -168,3 +225,38 @@ pub struct ShardChunkHeaderInnerV2 {
     /// Validator proposals.
     pub validator_proposals: Vec<ValidatorStake>,
 }
+
+// V2 -> V3: Add the size of the delayed receipts queue, the congested shards and the priority fees
+#[cfg(feature = "protocol_feature_congestion_control")]
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ShardChunkHeaderInnerV3 {
+    /// Previous block hash.
+    pub prev_block_hash: CryptoHash,
+    pub prev_state_root: StateRoot,
+    /// Root of the outcomes from execution transactions and results.
+    pub outcome_root: CryptoHash,
+    pub encoded_merkle_root: CryptoHash,
+    pub encoded_length: u64,
+    pub height_created: BlockHeight,
+    /// Shard index.
+    pub shard_id: ShardId,
+    /// Gas used in this chunk.
+    pub gas_used: Gas,
+    /// Gas limit voted by validators.
+    pub gas_limit: Gas,
+    /// Total balance burnt in previous chunk
+    pub balance_burnt: Balance,
+    /// Outgoing receipts merkle root.
+    pub outgoing_receipts_root: CryptoHash,
+    /// Tx merkle root.
+    pub tx_root: CryptoHash,
+    /// Validator proposals.
+    pub validator_proposals: Vec<ValidatorStake>,
+    /// Size of the delayed receipts queue of the shard after the previous chunk.
+    pub congestion_info: CongestionInfo,
+    /// Shards reported congested in the previous block, the receipts towards which are limited
+    /// when the chunk is applied.
+    pub congested_shards: Vec<ShardId>,
+    /// Priority fees of the transactions of the previous chunk, included in `balance_burnt`.
+    pub priority_fees: Balance,
+}
@@ -0,0 +1,252 @@
+//! Verification of state values against a state root using the trie nodes returned by the `query`
+//! RPC when `include_proof` is set.
+use std::collections::HashMap;
+use std::fmt;
+
+use borsh::BorshDeserialize;
+use near_crypto::PublicKey;
+
+use crate::account::{AccessKey, Account};
+use crate::hash::{hash, CryptoHash};
+use crate::serialize::from_base64;
+use crate::trie_key::TrieKey;
+use crate::trie_node::{RawTrieNode, RawTrieNodeWithSize};
+use crate::types::{AccountId, StateRoot};
+use crate::views::TrieProofPath;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateProofError {
+    /// Proof item is not valid base64.
+    InvalidEncoding(String),
+    /// Node or value with the given hash is needed to walk the trie but is not in the proof.
+    MissingNode(CryptoHash),
+    /// Node with the given hash could not be decoded.
+    InvalidNode(CryptoHash),
+    /// Value was found in the trie but could not be deserialized into the requested type.
+    InvalidValue(String),
+}
+
+impl fmt::Display for StateProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateProofError::InvalidEncoding(err) => write!(f, "Invalid proof encoding: {}", err),
+            StateProofError::MissingNode(hash) => write!(f, "Proof is missing node {}", hash),
+            StateProofError::InvalidNode(hash) => write!(f, "Proof node {} is malformed", hash),
+            StateProofError::InvalidValue(err) => write!(f, "Proven value is malformed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StateProofError {}
+
+/// Set of trie nodes and values, indexed by their hashes.
+///
+/// Every item is addressed by its hash, so the proof can only ever be used to reconstruct the part
+/// of the trie under the state root it is checked against, regardless of where it came from.
+pub struct StateProof {
+    nodes: HashMap<CryptoHash, Vec<u8>>,
+}
+
+impl StateProof {
+    pub fn new(nodes: Vec<Vec<u8>>) -> Self {
+        StateProof { nodes: nodes.into_iter().map(|node| (hash(&node), node)).collect() }
+    }
+
+    pub fn from_trie_proof_path(proof: &TrieProofPath) -> Result<Self, StateProofError> {
+        let nodes = proof
+            .iter()
+            .map(|node| {
+                from_base64(node).map_err(|err| StateProofError::InvalidEncoding(err.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(nodes))
+    }
+
+    /// Looks up `key` in the trie with the given root.
+    ///
+    /// `state_root` must come from a trusted source, e.g. the `prev_state_root` of a chunk in the
+    /// block following the one the query was made for. Returns `Ok(None)` if the proof shows that
+    /// the key is absent.
+    pub fn get(
+        &self,
+        state_root: &StateRoot,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StateProofError> {
+        let key = bytes_to_nibbles(key);
+        let mut key = &key[..];
+        let mut node_hash = *state_root;
+        let value_hash = loop {
+            if node_hash == CryptoHash::default() {
+                return Ok(None);
+            }
+            match self.get_node(&node_hash)? {
+                RawTrieNode::Leaf(existing_key, _value_length, value_hash) => {
+                    if decode_key(&node_hash, &existing_key)? == key {
+                        break value_hash;
+                    }
+                    return Ok(None);
+                }
+                RawTrieNode::Extension(existing_key, child) => {
+                    let existing_key = decode_key(&node_hash, &existing_key)?;
+                    if !key.starts_with(&existing_key) {
+                        return Ok(None);
+                    }
+                    key = &key[existing_key.len()..];
+                    node_hash = child;
+                }
+                RawTrieNode::Branch(children, value) => {
+                    if key.is_empty() {
+                        match value {
+                            Some((_value_length, value_hash)) => break value_hash,
+                            None => return Ok(None),
+                        }
+                    }
+                    match children[key[0] as usize] {
+                        Some(child) => {
+                            key = &key[1..];
+                            node_hash = child;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+            }
+        };
+        self.nodes
+            .get(&value_hash)
+            .cloned()
+            .map(Some)
+            .ok_or(StateProofError::MissingNode(value_hash))
+    }
+
+    pub fn get_account(
+        &self,
+        state_root: &StateRoot,
+        account_id: &AccountId,
+    ) -> Result<Option<Account>, StateProofError> {
+        self.get_typed(state_root, &TrieKey::Account { account_id: account_id.clone() })
+    }
+
+    pub fn get_access_key(
+        &self,
+        state_root: &StateRoot,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<Option<AccessKey>, StateProofError> {
+        self.get_typed(
+            state_root,
+            &TrieKey::AccessKey { account_id: account_id.clone(), public_key: public_key.clone() },
+        )
+    }
+
+    pub fn get_contract_data(
+        &self,
+        state_root: &StateRoot,
+        account_id: &AccountId,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, StateProofError> {
+        self.get(
+            state_root,
+            &TrieKey::ContractData { account_id: account_id.clone(), key: key.to_vec() }.to_vec(),
+        )
+    }
+
+    fn get_typed<T: BorshDeserialize>(
+        &self,
+        state_root: &StateRoot,
+        key: &TrieKey,
+    ) -> Result<Option<T>, StateProofError> {
+        match self.get(state_root, &key.to_vec())? {
+            Some(value) => T::try_from_slice(&value)
+                .map(Some)
+                .map_err(|err| StateProofError::InvalidValue(err.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn get_node(&self, node_hash: &CryptoHash) -> Result<RawTrieNode, StateProofError> {
+        let bytes = self.nodes.get(node_hash).ok_or(StateProofError::MissingNode(*node_hash))?;
+        RawTrieNodeWithSize::decode(bytes)
+            .map(|node| node.node)
+            .map_err(|_| StateProofError::InvalidNode(*node_hash))
+    }
+}
+
+fn bytes_to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| vec![byte >> 4, byte & 0x0f]).collect()
+}
+
+/// Decodes a hex-prefix encoded key of the node with the given hash into nibbles.
+fn decode_key(node_hash: &CryptoHash, encoded: &[u8]) -> Result<Vec<u8>, StateProofError> {
+    let (first, rest) = encoded.split_first().ok_or(StateProofError::InvalidNode(*node_hash))?;
+    let mut nibbles = Vec::with_capacity(rest.len() * 2 + 1);
+    if first & 0x10 != 0 {
+        nibbles.push(first & 0x0f);
+    }
+    nibbles.extend(bytes_to_nibbles(rest));
+    Ok(nibbles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_node(node: RawTrieNode) -> Vec<u8> {
+        RawTrieNodeWithSize { node, memory_usage: 0 }.encode().unwrap()
+    }
+
+    fn encode_leaf(nibbles: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut key = vec![];
+        let mut i = nibbles.len() % 2;
+        key.push(if i == 1 { 0x30 + nibbles[0] } else { 0x20 });
+        while i < nibbles.len() {
+            key.push(nibbles[i] * 16 + nibbles[i + 1]);
+            i += 2;
+        }
+        encode_node(RawTrieNode::Leaf(key, value.len() as u32, hash(value)))
+    }
+
+    fn encode_branch(children: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut hashes: [Option<CryptoHash>; 16] = Default::default();
+        for (i, child) in children {
+            hashes[*i as usize] = Some(hash(child));
+        }
+        encode_node(RawTrieNode::Branch(hashes, None))
+    }
+
+    #[test]
+    fn test_single_leaf() {
+        let leaf = encode_leaf(&bytes_to_nibbles(b"key"), b"value");
+        let root = hash(&leaf);
+        let proof = StateProof::new(vec![leaf, b"value".to_vec()]);
+        assert_eq!(proof.get(&root, b"key"), Ok(Some(b"value".to_vec())));
+        assert_eq!(proof.get(&root, b"other"), Ok(None));
+        assert_eq!(proof.get(&CryptoHash::default(), b"key"), Ok(None));
+    }
+
+    #[test]
+    fn test_branch() {
+        // Keys 0x12 and 0x34 differ in the first nibble, so the root is a branch.
+        let left = encode_leaf(&[2], b"left");
+        let right = encode_leaf(&[4], b"right");
+        let branch = encode_branch(&[(1, &left), (3, &right)]);
+        let root = hash(&branch);
+
+        let proof = StateProof::new(vec![branch.clone(), left, b"left".to_vec()]);
+        assert_eq!(proof.get(&root, &[0x12]), Ok(Some(b"left".to_vec())));
+        assert_eq!(proof.get(&root, &[0x56]), Ok(None));
+        assert_eq!(proof.get(&root, &[0x34]), Err(StateProofError::MissingNode(hash(&right))));
+
+        // The proof can't be checked against a different root.
+        let other_root = hash(b"other");
+        assert_eq!(proof.get(&other_root, &[0x12]), Err(StateProofError::MissingNode(other_root)));
+    }
+
+    #[test]
+    fn test_from_trie_proof_path() {
+        let proof = vec!["not base64!".to_string()];
+        assert!(matches!(
+            StateProof::from_trie_proof_path(&proof),
+            Err(StateProofError::InvalidEncoding(_))
+        ));
+    }
+}
@@ -5,6 +5,7 @@ use std::fmt::{Display, Formatter};
 use near_crypto::PublicKey;
 
 use crate::account::{AccessKey, Account};
+use crate::contract::{SharedContractCode, SharedContractCodeOwner};
 use crate::hash::{hash, CryptoHash};
 use crate::receipt::{Receipt, ReceivedData};
 use crate::serialize::{base64_format, option_base64_format};
@@ -50,6 +51,15 @@ pub enum StateRecord {
     /// Delayed Receipt.
     /// The receipt was delayed because the shard was overwhelmed.
     DelayedReceipt(Box<Receipt>),
+    /// Contract code in the shared contract code registry encoded in base64, with its
+    /// registrations and the number of the accounts of the shard which deployed it by hash.
+    /// It doesn't belong to any account, so it is added to the states of all the shards.
+    SharedContractCode {
+        owners: Vec<SharedContractCodeOwner>,
+        num_users: u64,
+        #[serde(with = "base64_format")]
+        code: Vec<u8>,
+    },
 }
 
 impl StateRecord {
@@ -96,6 +106,15 @@ impl StateRecord {
                 Some(StateRecord::DelayedReceipt(Box::new(receipt)))
             }
             col::DELAYED_RECEIPT_INDICES => None,
+            col::DELAYED_RECEIPTS_GAS => None,
+            col::PENDING_ACCOUNT_DELETION => None,
+            col::SHARED_CONTRACT_CODE => {
+                let SharedContractCode { owners, num_users, code } =
+                    SharedContractCode::try_from_slice(&value).unwrap();
+                Some(StateRecord::SharedContractCode { owners, num_users, code })
+            }
+            // Restored from the owners of `StateRecord::SharedContractCode`.
+            col::SHARED_CONTRACT_CODE_OWNER => None,
             _ => unreachable!(),
         }
     }
@@ -129,6 +148,13 @@ impl Display for StateRecord {
             ),
             StateRecord::PostponedReceipt(receipt) => write!(f, "Postponed receipt {:?}", receipt),
             StateRecord::DelayedReceipt(receipt) => write!(f, "Delayed receipt {:?}", receipt),
+            StateRecord::SharedContractCode { owners, num_users, code } => write!(
+                f,
+                "Shared code {:?} owned by {:?}, used by {} accounts: ...",
+                hash(code),
+                owners,
+                num_users
+            ),
         }
     }
 }
@@ -148,16 +174,18 @@ fn to_printable(blob: &[u8]) -> String {
     }
 }
 
-pub fn state_record_to_account_id(state_record: &StateRecord) -> &AccountId {
+/// Returns the account the record belongs to, `None` for the records of all the shards.
+pub fn state_record_to_account_id(state_record: &StateRecord) -> Option<&AccountId> {
     match state_record {
         StateRecord::Account { account_id, .. }
         | StateRecord::AccessKey { account_id, .. }
         | StateRecord::Contract { account_id, .. }
         | StateRecord::ReceivedData { account_id, .. }
-        | StateRecord::Data { account_id, .. } => account_id,
+        | StateRecord::Data { account_id, .. } => Some(account_id),
         StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
-            &receipt.receiver_id
+            Some(&receipt.receiver_id)
         }
+        StateRecord::SharedContractCode { .. } => None,
     }
 }
 
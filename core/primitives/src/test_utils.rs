@@ -113,6 +113,7 @@ impl SignedTransaction {
             receiver_id,
             block_hash,
             actions,
+            priority_fee: None,
         }
         .sign(signer)
     }
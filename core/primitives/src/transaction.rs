@@ -1,23 +1,24 @@
 use std::borrow::Borrow;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
-use near_crypto::{PublicKey, Signature};
+use near_crypto::{PublicKey, Signature, Signer};
 
 use crate::account::AccessKey;
 use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
-use crate::serialize::{base64_format, u128_dec_format_compatible};
-use crate::types::{AccountId, Balance, Gas, Nonce};
+use crate::serialize::{base64_format, option_u128_dec_format, u128_dec_format_compatible};
+use crate::types::{AccountId, Balance, BlockHeight, Gas, Nonce};
 
 pub type LogEntry = String;
 
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Transaction {
     /// An account on which behalf transaction is signed
     pub signer_id: AccountId,
@@ -33,9 +34,83 @@ pub struct Transaction {
     pub block_hash: CryptoHash,
     /// A list of actions to be applied
     pub actions: Vec<Action>,
+    /// Fee paid by the signer on top of the regular transaction cost to the producer of the chunk
+    /// including the transaction. Transactions with a priority fee are serialized as
+    /// `TransactionVersion::V1`, all other transactions keep the original format.
+    pub priority_fee: Option<Balance>,
+}
+
+/// Serialization versions of a `Transaction`.
+///
+/// `V0` has no version tag: it starts with the length of the signer account id, which is
+/// little-endian `u32` in `MIN_ACCOUNT_ID_LEN..=MAX_ACCOUNT_ID_LEN`, so its first byte is never
+/// `1` for a valid account id. `V1` is prefixed with the `1u8` tag and ends with the priority fee.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TransactionVersion {
+    V0,
+    V1,
+}
+
+const TRANSACTION_V1_TAG: u8 = 1;
+
+impl BorshSerialize for Transaction {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.priority_fee.is_some() {
+            TRANSACTION_V1_TAG.serialize(writer)?;
+        }
+        self.signer_id.serialize(writer)?;
+        self.public_key.serialize(writer)?;
+        self.nonce.serialize(writer)?;
+        self.receiver_id.serialize(writer)?;
+        self.block_hash.serialize(writer)?;
+        self.actions.serialize(writer)?;
+        if let Some(priority_fee) = self.priority_fee {
+            priority_fee.serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl BorshDeserialize for Transaction {
+    fn deserialize(buf: &mut &[u8]) -> Result<Self, io::Error> {
+        // The second byte of a `V0` transaction is always 0, see `TransactionVersion`.
+        let version = if buf.len() >= 2 && buf[0] == TRANSACTION_V1_TAG && buf[1] != 0 {
+            *buf = &buf[1..];
+            TransactionVersion::V1
+        } else {
+            TransactionVersion::V0
+        };
+        let signer_id = AccountId::deserialize(buf)?;
+        let public_key = PublicKey::deserialize(buf)?;
+        let nonce = Nonce::deserialize(buf)?;
+        let receiver_id = AccountId::deserialize(buf)?;
+        let block_hash = CryptoHash::deserialize(buf)?;
+        let actions = Vec::<Action>::deserialize(buf)?;
+        let priority_fee = match version {
+            TransactionVersion::V0 => None,
+            TransactionVersion::V1 => Some(Balance::deserialize(buf)?),
+        };
+        Ok(Transaction {
+            signer_id,
+            public_key,
+            nonce,
+            receiver_id,
+            block_hash,
+            actions,
+            priority_fee,
+        })
+    }
 }
 
 impl Transaction {
+    pub fn version(&self) -> TransactionVersion {
+        if self.priority_fee.is_some() {
+            TransactionVersion::V1
+        } else {
+            TransactionVersion::V0
+        }
+    }
+
     /// Computes a hash of the transaction for signing and size of serialized transaction
     pub fn get_hash_and_size(&self) -> (CryptoHash, u64) {
         let bytes = self.try_to_vec().expect("Failed to deserialize");
@@ -56,12 +131,29 @@ pub enum Action {
     AddKey(AddKeyAction),
     DeleteKey(DeleteKeyAction),
     DeleteAccount(DeleteAccountAction),
+    /// Executes actions signed by the `receiver_id` on its behalf, with the gas and the deposits
+    /// paid by the signer of the transaction.
+    Delegate(SignedDelegateAction),
+    /// Registers a Wasm code in the shared contract code registry, paid by the receiver_id
+    DeploySharedContract(DeploySharedContractAction),
+    /// Sets a Wasm code from the shared contract code registry to a receiver_id
+    DeployContractByHash(DeployContractByHashAction),
+    /// Removes a registration of a Wasm code in the shared contract code registry made by the
+    /// receiver_id
+    RemoveSharedContract(RemoveSharedContractAction),
+    /// Changes the allowance and the method names of a function call access key of the
+    /// receiver_id, keeping its nonce
+    UpdateAccessKey(UpdateAccessKeyAction),
+    /// Sets a Wasm code to a receiver_id and calls its migration method, the code and the state
+    /// are reverted if the migration fails
+    DeployContractAndMigrate(DeployContractAndMigrateAction),
 }
 
 impl Action {
     pub fn get_prepaid_gas(&self) -> Gas {
         match self {
             Action::FunctionCall(a) => a.gas,
+            Action::DeployContractAndMigrate(a) => a.gas,
             _ => 0,
         }
     }
@@ -106,6 +198,85 @@ impl fmt::Debug for DeployContractAction {
     }
 }
 
+/// Deploy shared contract action
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeploySharedContractAction {
+    /// WebAssembly binary
+    #[serde(with = "base64_format")]
+    pub code: Vec<u8>,
+}
+
+impl From<DeploySharedContractAction> for Action {
+    fn from(deploy_shared_contract_action: DeploySharedContractAction) -> Self {
+        Self::DeploySharedContract(deploy_shared_contract_action)
+    }
+}
+
+impl fmt::Debug for DeploySharedContractAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeploySharedContractAction")
+            .field("code", &format_args!("{}", logging::pretty_utf8(&self.code)))
+            .finish()
+    }
+}
+
+/// Deploy contract by hash action
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct DeployContractByHashAction {
+    /// Hash of a WebAssembly binary registered with `DeploySharedContractAction`
+    pub code_hash: CryptoHash,
+}
+
+impl From<DeployContractByHashAction> for Action {
+    fn from(deploy_contract_by_hash_action: DeployContractByHashAction) -> Self {
+        Self::DeployContractByHash(deploy_contract_by_hash_action)
+    }
+}
+
+/// Remove shared contract action
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct RemoveSharedContractAction {
+    /// Hash of a WebAssembly binary registered with `DeploySharedContractAction`
+    pub code_hash: CryptoHash,
+}
+
+impl From<RemoveSharedContractAction> for Action {
+    fn from(remove_shared_contract_action: RemoveSharedContractAction) -> Self {
+        Self::RemoveSharedContract(remove_shared_contract_action)
+    }
+}
+
+/// Deploy contract and migrate action
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeployContractAndMigrateAction {
+    /// WebAssembly binary
+    #[serde(with = "base64_format")]
+    pub code: Vec<u8>,
+    /// Method of the new code migrating the state of the contract
+    pub method_name: String,
+    #[serde(with = "base64_format")]
+    pub args: Vec<u8>,
+    /// Gas attached to the migration call
+    pub gas: Gas,
+}
+
+impl From<DeployContractAndMigrateAction> for Action {
+    fn from(deploy_contract_and_migrate_action: DeployContractAndMigrateAction) -> Self {
+        Self::DeployContractAndMigrate(deploy_contract_and_migrate_action)
+    }
+}
+
+impl fmt::Debug for DeployContractAndMigrateAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeployContractAndMigrateAction")
+            .field("code", &format_args!("{}", logging::pretty_utf8(&self.code)))
+            .field("method_name", &format_args!("{}", &self.method_name))
+            .field("args", &format_args!("{}", logging::pretty_utf8(&self.args)))
+            .field("gas", &format_args!("{}", &self.gas))
+            .finish()
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct FunctionCallAction {
     pub method_name: String,
@@ -187,6 +358,24 @@ impl From<DeleteKeyAction> for Action {
     }
 }
 
+/// Update access key action
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct UpdateAccessKeyAction {
+    /// A public key associated with the function call access key to be updated.
+    pub public_key: PublicKey,
+    /// New allowance of the access key, `None` means unlimited allowance.
+    #[serde(with = "option_u128_dec_format")]
+    pub allowance: Option<Balance>,
+    /// New list of method names that can be called with the access key.
+    pub method_names: Vec<String>,
+}
+
+impl From<UpdateAccessKeyAction> for Action {
+    fn from(update_access_key_action: UpdateAccessKeyAction) -> Self {
+        Self::UpdateAccessKey(update_access_key_action)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct DeleteAccountAction {
     pub beneficiary_id: AccountId,
@@ -198,6 +387,64 @@ impl From<DeleteAccountAction> for Action {
     }
 }
 
+/// Prefix of the messages signed for a `DelegateAction`. It is not a possible length of the
+/// `signer_id` a serialized `Transaction` starts with, so a signature of one can't be used for
+/// the other.
+pub const DELEGATE_ACTION_SIGNATURE_PREFIX: u32 = (1 << 30) + 366;
+
+/// Actions which `sender_id` allows another account, the relayer, to submit on its behalf.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct DelegateAction {
+    /// The account on which behalf the actions are executed. It has to be the receiver of the
+    /// transaction carrying the delegate action.
+    pub sender_id: AccountId,
+    /// The account the actions are sent to.
+    pub receiver_id: AccountId,
+    /// The actions to execute. They can't contain another delegate action.
+    pub actions: Vec<Action>,
+    /// Nonce of the access key of `sender_id` with `public_key`. It has to be larger than the
+    /// nonce of the access key, which is updated to it.
+    pub nonce: Nonce,
+    /// The last block height at which the actions can be executed.
+    pub max_block_height: BlockHeight,
+    /// The access key of `sender_id` the action is signed with.
+    pub public_key: PublicKey,
+}
+
+impl DelegateAction {
+    /// Hash of the message signed by `sender_id`.
+    pub fn get_hash(&self) -> CryptoHash {
+        let mut bytes = DELEGATE_ACTION_SIGNATURE_PREFIX.to_le_bytes().to_vec();
+        bytes.extend(self.try_to_vec().expect("Failed to serialize"));
+        hash(&bytes)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SignedDelegateAction {
+    pub delegate_action: DelegateAction,
+    pub signature: Signature,
+}
+
+impl SignedDelegateAction {
+    pub fn new(signer: &dyn Signer, delegate_action: DelegateAction) -> Self {
+        let signature = signer.sign(delegate_action.get_hash().as_ref());
+        Self { delegate_action, signature }
+    }
+
+    /// Checks that the action is signed with its `public_key`.
+    pub fn verify(&self) -> bool {
+        self.signature
+            .verify(self.delegate_action.get_hash().as_ref(), &self.delegate_action.public_key)
+    }
+}
+
+impl From<SignedDelegateAction> for Action {
+    fn from(signed_delegate_action: SignedDelegateAction) -> Self {
+        Self::Delegate(signed_delegate_action)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Eq, Debug, Clone)]
 #[borsh_init(init)]
 pub struct SignedTransaction {
@@ -453,6 +700,7 @@ mod tests {
             receiver_id: AccountId::test_account(),
             block_hash: Default::default(),
             actions: vec![],
+            priority_fee: None,
         }
         .sign(&signer);
         let wrong_public_key = PublicKey::from_seed(KeyType::ED25519, "wrong");
@@ -467,6 +715,32 @@ mod tests {
         assert!(verify_transaction_signature(&decoded_tx, &valid_keys));
     }
 
+    #[test]
+    fn test_verify_delegate_action() {
+        let signer = InMemorySigner::from_random(AccountId::test_account(), KeyType::ED25519);
+        let delegate_action = DelegateAction {
+            sender_id: AccountId::test_account(),
+            receiver_id: "receiver.near".parse().unwrap(),
+            actions: vec![Action::Transfer(TransferAction { deposit: 1 })],
+            nonce: 1,
+            max_block_height: 100,
+            public_key: signer.public_key(),
+        };
+        let signed_delegate_action = SignedDelegateAction::new(&signer, delegate_action.clone());
+        assert!(signed_delegate_action.verify());
+
+        let mut tampered = signed_delegate_action.clone();
+        tampered.delegate_action.nonce = 2;
+        assert!(!tampered.verify());
+
+        // Signing the serialized action without the prefix doesn't make a valid signature.
+        let unprefixed = SignedDelegateAction {
+            signature: signer.sign(&delegate_action.try_to_vec().unwrap()),
+            delegate_action,
+        };
+        assert!(!unprefixed.verify());
+    }
+
     /// This test is change checker for a reason - we don't expect transaction format to change.
     /// If it does - you MUST update all of the dependencies: like nearlib and other clients.
     #[test]
@@ -505,6 +779,7 @@ mod tests {
                     beneficiary_id: "123".parse().unwrap(),
                 }),
             ],
+            priority_fee: None,
         };
         let signed_tx = SignedTransaction::new(Signature::empty(KeyType::ED25519), transaction);
         let new_signed_tx =
@@ -516,6 +791,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_transaction_with_priority_fee() {
+        let signer = InMemorySigner::from_random(AccountId::test_account(), KeyType::ED25519);
+        let mut transaction = Transaction {
+            signer_id: AccountId::test_account(),
+            public_key: signer.public_key(),
+            nonce: 1,
+            receiver_id: "123".parse().unwrap(),
+            block_hash: Default::default(),
+            actions: vec![Action::Transfer(TransferAction { deposit: 123 })],
+            priority_fee: None,
+        };
+        let v0_bytes = transaction.try_to_vec().unwrap();
+        assert_eq!(transaction.version(), TransactionVersion::V0);
+
+        transaction.priority_fee = Some(1_000);
+        let v1_bytes = transaction.try_to_vec().unwrap();
+        assert_eq!(transaction.version(), TransactionVersion::V1);
+        assert_eq!(v1_bytes[0], TRANSACTION_V1_TAG);
+        assert_eq!(&v1_bytes[1..v0_bytes.len() + 1], &v0_bytes[..]);
+        assert_eq!(Transaction::try_from_slice(&v1_bytes).unwrap(), transaction);
+
+        let signed_tx = transaction.sign(&signer);
+        let new_signed_tx =
+            SignedTransaction::try_from_slice(&signed_tx.try_to_vec().unwrap()).unwrap();
+        assert_eq!(new_signed_tx.transaction.priority_fee, Some(1_000));
+        assert_eq!(new_signed_tx.get_hash(), signed_tx.get_hash());
+        assert!(verify_transaction_signature(&new_signed_tx, &[signer.public_key()]));
+    }
+
     #[test]
     fn test_outcome_to_hashes() {
         let outcome = ExecutionOutcome {
@@ -7,7 +7,7 @@ use std::mem::size_of;
 pub(crate) const ACCOUNT_DATA_SEPARATOR: &[u8; 1] = b",";
 
 /// Type identifiers used for DB key generation to store values in the key-value storage.
-pub(crate) mod col {
+pub mod col {
     /// This column id is used when storing `primitives::account::Account` type about a given
     /// `account_id`.
     pub const ACCOUNT: &[u8] = &[0];
@@ -35,6 +35,19 @@ pub(crate) mod col {
     pub const DELAYED_RECEIPT: &[u8] = &[8];
     /// This column id is used when storing Key-Value data from a contract on an `account_id`.
     pub const CONTRACT_DATA: &[u8] = &[9];
+    /// This column id is used when storing contract blobs registered in the shared contract code
+    /// registry for a given code hash.
+    /// NOTE: It doesn't belong to any account.
+    pub const SHARED_CONTRACT_CODE: &[u8] = &[10];
+    /// This column id is used when storing the number of registrations of a code in the shared
+    /// contract code registry by a given `account_id`.
+    pub const SHARED_CONTRACT_CODE_OWNER: &[u8] = &[11];
+    /// This column id is used when storing the total gas attached to the delayed receipts.
+    /// NOTE: It is a singleton per shard.
+    pub const DELAYED_RECEIPTS_GAS: &[u8] = &[12];
+    /// This column id is used when storing the beneficiary `AccountId` of an account whose
+    /// deletion is still in progress for a given `account_id`.
+    pub const PENDING_ACCOUNT_DELETION: &[u8] = &[13];
 }
 
 /// Describes the key of a specific key-value record in a state trie.
@@ -72,6 +85,20 @@ pub enum TrieKey {
     /// Used to store a key-value record `Vec<u8>` within a contract deployed on a given `AccountId`
     /// and a given key.
     ContractData { account_id: AccountId, key: Vec<u8> },
+    /// Used to store `primitives::contract::SharedContractCode` registered in the shared contract
+    /// code registry for a given `code_hash`. Accounts deploy it with `DeployContractByHashAction`.
+    /// NOTE: Every shard has a copy, which is updated by the `SharedContractCodeReceipt`s sent to
+    /// all the shards, and it is copied to all the split shards on resharding.
+    SharedContractCode { code_hash: CryptoHash },
+    /// Used to store the number of registrations `u64` of the code with a given `code_hash` in
+    /// the shared contract code registry by a given `AccountId`.
+    SharedContractCodeOwner { account_id: AccountId, code_hash: CryptoHash },
+    /// Used to store the total gas `Gas` attached to the receipts in the delayed receipts queue.
+    /// NOTE: It is a singleton per shard.
+    DelayedReceiptsGas,
+    /// Used to store the beneficiary `AccountId` of a given `AccountId` whose storage is still
+    /// being removed by account deletion receipts.
+    PendingAccountDeletion { account_id: AccountId },
 }
 
 impl TrieKey {
@@ -114,6 +141,40 @@ impl TrieKey {
                     + ACCOUNT_DATA_SEPARATOR.len()
                     + key.len()
             }
+            TrieKey::SharedContractCode { code_hash } => {
+                col::SHARED_CONTRACT_CODE.len() + code_hash.as_ref().len()
+            }
+            TrieKey::SharedContractCodeOwner { account_id, code_hash } => {
+                col::SHARED_CONTRACT_CODE_OWNER.len()
+                    + account_id.len()
+                    + ACCOUNT_DATA_SEPARATOR.len()
+                    + code_hash.as_ref().len()
+            }
+            TrieKey::DelayedReceiptsGas => col::DELAYED_RECEIPTS_GAS.len(),
+            TrieKey::PendingAccountDeletion { account_id } => {
+                col::PENDING_ACCOUNT_DELETION.len() + account_id.len()
+            }
+        }
+    }
+
+    /// Returns the account the key belongs to, or `None` for the per-shard keys of the delayed
+    /// receipts queue and the shared contract code registry.
+    pub fn get_account_id(&self) -> Option<AccountId> {
+        match self {
+            TrieKey::Account { account_id, .. }
+            | TrieKey::ContractCode { account_id, .. }
+            | TrieKey::AccessKey { account_id, .. }
+            | TrieKey::ContractData { account_id, .. }
+            | TrieKey::SharedContractCodeOwner { account_id, .. }
+            | TrieKey::PendingAccountDeletion { account_id } => Some(account_id.clone()),
+            TrieKey::ReceivedData { receiver_id, .. }
+            | TrieKey::PostponedReceiptId { receiver_id, .. }
+            | TrieKey::PendingDataCount { receiver_id, .. }
+            | TrieKey::PostponedReceipt { receiver_id, .. } => Some(receiver_id.clone()),
+            TrieKey::DelayedReceiptIndices
+            | TrieKey::DelayedReceipt { .. }
+            | TrieKey::SharedContractCode { .. }
+            | TrieKey::DelayedReceiptsGas => None,
         }
     }
 
@@ -172,6 +233,23 @@ impl TrieKey {
                 res.extend(ACCOUNT_DATA_SEPARATOR);
                 res.extend(key);
             }
+            TrieKey::SharedContractCode { code_hash } => {
+                res.extend(col::SHARED_CONTRACT_CODE);
+                res.extend(code_hash.as_ref());
+            }
+            TrieKey::SharedContractCodeOwner { account_id, code_hash } => {
+                res.extend(col::SHARED_CONTRACT_CODE_OWNER);
+                res.extend(account_id.as_ref().as_bytes());
+                res.extend(ACCOUNT_DATA_SEPARATOR);
+                res.extend(code_hash.as_ref());
+            }
+            TrieKey::DelayedReceiptsGas => {
+                res.extend(col::DELAYED_RECEIPTS_GAS);
+            }
+            TrieKey::PendingAccountDeletion { account_id } => {
+                res.extend(col::PENDING_ACCOUNT_DELETION);
+                res.extend(account_id.as_ref().as_bytes());
+            }
         };
         debug_assert_eq!(res.len(), expected_len);
         res
@@ -397,6 +475,75 @@ pub mod trie_key_parsers {
         })
     }
 
+    /// Parses the account from a raw key of any column. Returns `None` for the per-shard keys of
+    /// the delayed receipts queue and the shared contract code registry.
+    pub fn parse_account_id_from_raw_key(
+        raw_key: &[u8],
+    ) -> Result<Option<AccountId>, std::io::Error> {
+        if raw_key.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "raw key is empty"));
+        }
+        let column = &raw_key[..1];
+        let account_id_prefix = &raw_key[1..];
+        // Columns where the account id is followed by some other data use a single byte
+        // separator which can't be a part of a valid account id.
+        let separator = if column == col::ACCOUNT
+            || column == col::CONTRACT_CODE
+            || column == col::PENDING_ACCOUNT_DELETION
+        {
+            None
+        } else if column == col::ACCESS_KEY {
+            Some(col::ACCESS_KEY[0])
+        } else if column == col::RECEIVED_DATA
+            || column == col::POSTPONED_RECEIPT_ID
+            || column == col::PENDING_DATA_COUNT
+            || column == col::POSTPONED_RECEIPT
+            || column == col::CONTRACT_DATA
+            || column == col::SHARED_CONTRACT_CODE_OWNER
+        {
+            Some(ACCOUNT_DATA_SEPARATOR[0])
+        } else if column == col::DELAYED_RECEIPT_INDICES
+            || column == col::DELAYED_RECEIPT
+            || column == col::SHARED_CONTRACT_CODE
+            || column == col::DELAYED_RECEIPTS_GAS
+        {
+            return Ok(None);
+        } else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not start with a known column marker",
+            ));
+        };
+        let account_id = match separator {
+            None => account_id_prefix,
+            Some(separator) => {
+                let position =
+                    account_id_prefix.iter().position(|c| *c == separator).ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "raw key does not have a separator after AccountId",
+                        )
+                    })?;
+                &account_id_prefix[..position]
+            }
+        };
+        std::str::from_utf8(account_id)
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "raw key AccountId has invalid UTF-8 format",
+                )
+            })?
+            .parse()
+            .map(Some)
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "raw key does not have a valid AccountId",
+                )
+            })
+    }
+
     pub fn get_raw_prefix_for_access_keys(account_id: &AccountId) -> Vec<u8> {
         let mut res = Vec::with_capacity(col::ACCESS_KEY.len() * 2 + account_id.len());
         res.extend(col::ACCESS_KEY);
@@ -418,6 +565,27 @@ pub mod trie_key_parsers {
         res.extend(prefix);
         res
     }
+
+    /// Prefix of the keys of the given column indexed by the receiver and a hash, i.e. of
+    /// `ReceivedData`, `PostponedReceiptId`, `PendingDataCount` and `PostponedReceipt`.
+    pub fn get_raw_prefix_for_receiver_records(column: &[u8], receiver_id: &AccountId) -> Vec<u8> {
+        let mut res =
+            Vec::with_capacity(column.len() + receiver_id.len() + ACCOUNT_DATA_SEPARATOR.len());
+        res.extend(column);
+        res.extend(receiver_id.as_ref().as_bytes());
+        res.extend(ACCOUNT_DATA_SEPARATOR);
+        res
+    }
+
+    pub fn get_raw_prefix_for_shared_contract_code_owner(account_id: &AccountId) -> Vec<u8> {
+        let mut res = Vec::with_capacity(
+            col::SHARED_CONTRACT_CODE_OWNER.len() + account_id.len() + ACCOUNT_DATA_SEPARATOR.len(),
+        );
+        res.extend(col::SHARED_CONTRACT_CODE_OWNER);
+        res.extend(account_id.as_ref().as_bytes());
+        res.extend(ACCOUNT_DATA_SEPARATOR);
+        res
+    }
 }
 
 #[cfg(test)]
@@ -521,4 +689,51 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_parse_account_id_from_raw_key() {
+        let public_key = PublicKey::empty(KeyType::ED25519);
+        let hash = CryptoHash::default();
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| x.parse::<AccountId>().unwrap()) {
+            let keys = vec![
+                TrieKey::Account { account_id: account_id.clone() },
+                TrieKey::ContractCode { account_id: account_id.clone() },
+                TrieKey::AccessKey {
+                    account_id: account_id.clone(),
+                    public_key: public_key.clone(),
+                },
+                TrieKey::ReceivedData { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PostponedReceiptId { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PendingDataCount { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::PostponedReceipt { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::ContractData { account_id: account_id.clone(), key: b"a,b".to_vec() },
+                TrieKey::SharedContractCodeOwner {
+                    account_id: account_id.clone(),
+                    code_hash: hash,
+                },
+                TrieKey::PendingAccountDeletion { account_id: account_id.clone() },
+            ];
+            for key in keys {
+                assert_eq!(key.get_account_id(), Some(account_id.clone()));
+                assert_eq!(
+                    trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                    Some(account_id.clone())
+                );
+            }
+        }
+        for key in vec![
+            TrieKey::DelayedReceiptIndices,
+            TrieKey::DelayedReceipt { index: 10 },
+            TrieKey::SharedContractCode { code_hash: hash },
+            TrieKey::DelayedReceiptsGas,
+        ] {
+            assert_eq!(key.get_account_id(), None);
+            assert_eq!(
+                trie_key_parsers::parse_account_id_from_raw_key(&key.to_vec()).unwrap(),
+                None
+            );
+        }
+        assert!(trie_key_parsers::parse_account_id_from_raw_key(&[]).is_err());
+        assert!(trie_key_parsers::parse_account_id_from_raw_key(&[42]).is_err());
+    }
 }
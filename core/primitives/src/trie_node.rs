@@ -0,0 +1,191 @@
+//! Serialization of the trie nodes as they are stored in the state and returned in state proofs.
+use std::convert::TryFrom;
+use std::io::{Cursor, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::hash::CryptoHash;
+
+/// Trie node as it is stored in the database. Node keys are hex-prefix encoded nibbles and values
+/// are referenced by their length and hash.
+#[derive(Debug, Eq, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum RawTrieNode {
+    Leaf(Vec<u8>, u32, CryptoHash),
+    Branch([Option<CryptoHash>; 16], Option<(u32, CryptoHash)>),
+    Extension(Vec<u8>, CryptoHash),
+}
+
+/// Trie node + memory cost of its subtree
+/// memory_usage is serialized, stored, and contributes to hash
+#[derive(Debug, Eq, PartialEq)]
+pub struct RawTrieNodeWithSize {
+    pub node: RawTrieNode,
+    pub memory_usage: u64,
+}
+
+const LEAF_NODE: u8 = 0;
+const BRANCH_NODE_NO_VALUE: u8 = 1;
+const BRANCH_NODE_WITH_VALUE: u8 = 2;
+const EXTENSION_NODE: u8 = 3;
+
+fn decode_children(cursor: &mut Cursor<&[u8]>) -> Result<[Option<CryptoHash>; 16], std::io::Error> {
+    let mut children: [Option<CryptoHash>; 16] = Default::default();
+    let bitmap = cursor.read_u16::<LittleEndian>()?;
+    let mut pos = 1;
+    for child in &mut children {
+        if bitmap & pos != 0 {
+            let mut arr = [0; 32];
+            cursor.read_exact(&mut arr)?;
+            *child = Some(CryptoHash::try_from(&arr[..]).unwrap());
+        }
+        pos <<= 1;
+    }
+    Ok(children)
+}
+
+impl RawTrieNode {
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        let mut cursor = Cursor::new(out);
+        // size in state_parts = size + 8 for RawTrieNodeWithSize + 8 for borsh vector length
+        match &self {
+            // size <= 1 + 4 + 4 + 32 + key_length + value_length
+            RawTrieNode::Leaf(key, value_length, value_hash) => {
+                cursor.write_u8(LEAF_NODE)?;
+                cursor.write_u32::<LittleEndian>(key.len() as u32)?;
+                cursor.write_all(&key)?;
+                cursor.write_u32::<LittleEndian>(*value_length)?;
+                cursor.write_all(value_hash.as_ref())?;
+            }
+            // size <= 1 + 4 + 32 + value_length + 2 + 32 * num_children
+            RawTrieNode::Branch(children, value) => {
+                if let Some((value_length, value_hash)) = value {
+                    cursor.write_u8(BRANCH_NODE_WITH_VALUE)?;
+                    cursor.write_u32::<LittleEndian>(*value_length)?;
+                    cursor.write_all(value_hash.as_ref())?;
+                } else {
+                    cursor.write_u8(BRANCH_NODE_NO_VALUE)?;
+                }
+                let mut bitmap: u16 = 0;
+                let mut pos: u16 = 1;
+                for child in children.iter() {
+                    if child.is_some() {
+                        bitmap |= pos
+                    }
+                    pos <<= 1;
+                }
+                cursor.write_u16::<LittleEndian>(bitmap)?;
+                for child in children.iter() {
+                    if let Some(hash) = child {
+                        cursor.write_all(hash.as_ref())?;
+                    }
+                }
+            }
+            // size <= 1 + 4 + key_length + 32
+            RawTrieNode::Extension(key, child) => {
+                cursor.write_u8(EXTENSION_NODE)?;
+                cursor.write_u32::<LittleEndian>(key.len() as u32)?;
+                cursor.write_all(&key)?;
+                cursor.write_all(child.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let mut cursor = Cursor::new(bytes);
+        match cursor.read_u8()? {
+            LEAF_NODE => {
+                let key_length = cursor.read_u32::<LittleEndian>()?;
+                let mut key = vec![0; key_length as usize];
+                cursor.read_exact(&mut key)?;
+                let value_length = cursor.read_u32::<LittleEndian>()?;
+                let mut arr = [0; 32];
+                cursor.read_exact(&mut arr)?;
+                let value_hash = CryptoHash::try_from(&arr[..]).unwrap();
+                Ok(RawTrieNode::Leaf(key, value_length, value_hash))
+            }
+            BRANCH_NODE_NO_VALUE => {
+                let children = decode_children(&mut cursor)?;
+                Ok(RawTrieNode::Branch(children, None))
+            }
+            BRANCH_NODE_WITH_VALUE => {
+                let value_length = cursor.read_u32::<LittleEndian>()?;
+                let mut arr = [0; 32];
+                cursor.read_exact(&mut arr)?;
+                let value_hash = CryptoHash::try_from(&arr[..]).unwrap();
+                let children = decode_children(&mut cursor)?;
+                Ok(RawTrieNode::Branch(children, Some((value_length, value_hash))))
+            }
+            EXTENSION_NODE => {
+                let key_length = cursor.read_u32::<LittleEndian>()?;
+                let mut key = vec![0; key_length as usize];
+                cursor.read_exact(&mut key)?;
+                let mut child = vec![0; 32];
+                cursor.read_exact(&mut child)?;
+                Ok(RawTrieNode::Extension(key, CryptoHash::try_from(child).unwrap()))
+            }
+            _ => Err(std::io::Error::new(std::io::ErrorKind::Other, "Wrong type")),
+        }
+    }
+}
+
+impl RawTrieNodeWithSize {
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        self.node.encode_into(out)?;
+        out.write_u64::<LittleEndian>(self.memory_usage)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        if bytes.len() < 8 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Wrong type"));
+        }
+        let node = RawTrieNode::decode(&bytes[0..bytes.len() - 8])?;
+        let mut arr: [u8; 8] = Default::default();
+        arr.copy_from_slice(&bytes[bytes.len() - 8..]);
+        let memory_usage = u64::from_le_bytes(arr);
+        Ok(RawTrieNodeWithSize { node, memory_usage })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::hash;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let value = vec![123, 245, 255];
+        let value_length = 3;
+        let value_hash = hash(&value);
+        let node = RawTrieNode::Leaf(vec![1, 2, 3], value_length, value_hash);
+        let buf = node.encode().expect("Failed to serialize");
+        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
+        assert_eq!(node, new_node);
+
+        let mut children: [Option<CryptoHash>; 16] = Default::default();
+        children[3] = Some(CryptoHash::default());
+        let node = RawTrieNode::Branch(children, Some((value_length, value_hash)));
+        let buf = node.encode().expect("Failed to serialize");
+        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
+        assert_eq!(node, new_node);
+
+        let node = RawTrieNode::Extension(vec![123, 245, 255], CryptoHash::default());
+        let buf = node.encode().expect("Failed to serialize");
+        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
+        assert_eq!(node, new_node);
+    }
+}
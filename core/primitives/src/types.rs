@@ -155,6 +155,9 @@ pub enum StateChangeCause {
     /// State change that is happens due to migration that happens in first block of an epoch
     /// after protocol upgrade
     Migration,
+    /// State changes for building states for split shards. They are recorded only in the
+    /// child shards and never exposed through RPC.
+    Resharding,
 }
 
 /// This represents the committed changes in the Trie with a change cause.
@@ -292,6 +295,10 @@ impl StateChanges {
                 TrieKey::PostponedReceipt { .. } => {}
                 TrieKey::DelayedReceiptIndices => {}
                 TrieKey::DelayedReceipt { .. } => {}
+                TrieKey::SharedContractCode { .. } => {}
+                TrieKey::SharedContractCodeOwner { .. } => {}
+                TrieKey::DelayedReceiptsGas => {}
+                TrieKey::PendingAccountDeletion { .. } => {}
             }
         }
 
@@ -685,6 +692,7 @@ pub struct BlockExtra {
 
 #[cfg(feature = "protocol_feature_block_header_v3")]
 pub mod chunk_extra {
+    use crate::receipt::CongestionInfo;
     use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
     use crate::types::StateRoot;
     use borsh::{BorshDeserialize, BorshSerialize};
@@ -698,6 +706,8 @@ pub mod chunk_extra {
     pub enum ChunkExtra {
         V1(ChunkExtraV1),
         V2(ChunkExtraV2),
+        #[cfg(feature = "protocol_feature_congestion_control")]
+        V3(ChunkExtraV3),
     }
 
     #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize, Clone, Eq)]
@@ -716,7 +726,30 @@ pub mod chunk_extra {
         pub balance_burnt: Balance,
     }
 
+    // V2 -> V3: Add the size of the delayed receipts queue and the priority fees
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize, Clone, Eq)]
+    pub struct ChunkExtraV3 {
+        /// Post state root after applying give chunk.
+        pub state_root: StateRoot,
+        /// Root of merklizing results of receipts (transactions) execution.
+        pub outcome_root: CryptoHash,
+        /// Validator proposals produced by given chunk.
+        pub validator_proposals: Vec<ValidatorStake>,
+        /// Actually how much gas were used.
+        pub gas_used: Gas,
+        /// Gas limit, allows to increase or decrease limit based on expected time vs real time for computing the chunk.
+        pub gas_limit: Gas,
+        /// Total balance burnt after processing the current chunk.
+        pub balance_burnt: Balance,
+        /// Size of the delayed receipts queue after processing the current chunk.
+        pub congestion_info: CongestionInfo,
+        /// Priority fees of the transactions of the current chunk, included in `balance_burnt`.
+        pub priority_fees: Balance,
+    }
+
     impl ChunkExtra {
+        #[cfg(not(feature = "protocol_feature_congestion_control"))]
         pub fn new(
             state_root: &StateRoot,
             outcome_root: CryptoHash,
@@ -724,6 +757,8 @@ pub mod chunk_extra {
             gas_used: Gas,
             gas_limit: Gas,
             balance_burnt: Balance,
+            _congestion_info: CongestionInfo,
+            _priority_fees: Balance,
         ) -> Self {
             Self::V2(ChunkExtraV2 {
                 state_root: state_root.clone(),
@@ -735,11 +770,36 @@ pub mod chunk_extra {
             })
         }
 
+        #[cfg(feature = "protocol_feature_congestion_control")]
+        pub fn new(
+            state_root: &StateRoot,
+            outcome_root: CryptoHash,
+            validator_proposals: Vec<ValidatorStake>,
+            gas_used: Gas,
+            gas_limit: Gas,
+            balance_burnt: Balance,
+            congestion_info: CongestionInfo,
+            priority_fees: Balance,
+        ) -> Self {
+            Self::V3(ChunkExtraV3 {
+                state_root: state_root.clone(),
+                outcome_root,
+                validator_proposals,
+                gas_used,
+                gas_limit,
+                balance_burnt,
+                congestion_info,
+                priority_fees,
+            })
+        }
+
         #[inline]
         pub fn outcome_root(&self) -> &StateRoot {
             match self {
                 Self::V1(v1) => &v1.outcome_root,
                 Self::V2(v2) => &v2.outcome_root,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => &v3.outcome_root,
             }
         }
 
@@ -748,6 +808,8 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => &v1.state_root,
                 Self::V2(v2) => &v2.state_root,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => &v3.state_root,
             }
         }
 
@@ -756,6 +818,8 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => &mut v1.state_root,
                 Self::V2(v2) => &mut v2.state_root,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => &mut v3.state_root,
             }
         }
 
@@ -764,6 +828,8 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => ValidatorStakeIter::v1(&v1.validator_proposals),
                 Self::V2(v2) => ValidatorStakeIter::new(&v2.validator_proposals),
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => ValidatorStakeIter::new(&v3.validator_proposals),
             }
         }
 
@@ -772,6 +838,8 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => v1.gas_limit,
                 Self::V2(v2) => v2.gas_limit,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => v3.gas_limit,
             }
         }
 
@@ -780,6 +848,8 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => v1.gas_used,
                 Self::V2(v2) => v2.gas_used,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => v3.gas_used,
             }
         }
 
@@ -788,6 +858,26 @@ pub mod chunk_extra {
             match self {
                 Self::V1(v1) => v1.balance_burnt,
                 Self::V2(v2) => v2.balance_burnt,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => v3.balance_burnt,
+            }
+        }
+
+        #[inline]
+        pub fn congestion_info(&self) -> Option<CongestionInfo> {
+            match self {
+                Self::V1(_) | Self::V2(_) => None,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => Some(v3.congestion_info),
+            }
+        }
+
+        #[inline]
+        pub fn priority_fees(&self) -> Balance {
+            match self {
+                Self::V1(_) | Self::V2(_) => 0,
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                Self::V3(v3) => v3.priority_fees,
             }
         }
     }
@@ -795,6 +885,7 @@ pub mod chunk_extra {
 
 #[cfg(not(feature = "protocol_feature_block_header_v3"))]
 pub mod chunk_extra {
+    use crate::receipt::CongestionInfo;
     use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
     use crate::types::StateRoot;
     use near_primitives_core::hash::CryptoHash;
@@ -811,6 +902,8 @@ pub mod chunk_extra {
             gas_used: Gas,
             gas_limit: Gas,
             balance_burnt: Balance,
+            _congestion_info: CongestionInfo,
+            _priority_fees: Balance,
         ) -> Self {
             Self {
                 state_root: state_root.clone(),
@@ -856,6 +949,16 @@ pub mod chunk_extra {
         pub fn balance_burnt(&self) -> Balance {
             self.balance_burnt
         }
+
+        #[inline]
+        pub fn congestion_info(&self) -> Option<CongestionInfo> {
+            None
+        }
+
+        #[inline]
+        pub fn priority_fees(&self) -> Balance {
+            0
+        }
     }
 }
 
@@ -10,6 +10,7 @@ use serde;
 
 use crate::hash::{hash, CryptoHash};
 use crate::receipt::Receipt;
+use crate::shard_layout::ShardUId;
 use crate::transaction::SignedTransaction;
 use crate::types::{CompiledContractCache, NumSeats, NumShards, ShardId};
 use crate::version::{
@@ -76,6 +77,14 @@ pub fn get_block_shard_id(block_hash: &CryptoHash, shard_id: ShardId) -> Vec<u8>
     res
 }
 
+/// Same as `get_block_shard_id`, but for the data which must not clash between shard layouts.
+pub fn get_block_shard_uid(block_hash: &CryptoHash, shard_uid: &ShardUId) -> Vec<u8> {
+    let mut res = Vec::with_capacity(40);
+    res.extend_from_slice(block_hash.as_ref());
+    res.extend_from_slice(&shard_uid.to_bytes());
+    res
+}
+
 pub fn get_block_shard_id_rev(
     key: &[u8],
 ) -> Result<(CryptoHash, ShardId), Box<dyn std::error::Error>> {
@@ -92,6 +101,27 @@ pub fn get_block_shard_id_rev(
     Ok((block_hash, shard_id))
 }
 
+pub fn get_block_shard_uid_rev(
+    key: &[u8],
+) -> Result<(CryptoHash, ShardUId), Box<dyn std::error::Error>> {
+    if key.len() != 40 {
+        return Err(
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid key length").into()
+        );
+    }
+    let block_hash_vec: Vec<u8> = key[0..32].iter().cloned().collect();
+    let block_hash = CryptoHash::try_from(block_hash_vec)?;
+    let mut shard_id_arr: [u8; 4] = Default::default();
+    shard_id_arr.copy_from_slice(&key[32..36]);
+    let mut version_arr: [u8; 4] = Default::default();
+    version_arr.copy_from_slice(&key[36..40]);
+    let shard_uid = ShardUId {
+        version: u32::from_le_bytes(version_arr),
+        shard_id: u32::from_le_bytes(shard_id_arr),
+    };
+    Ok((block_hash, shard_uid))
+}
+
 /// Creates a new Receipt ID from a given signed transaction and a block hash.
 /// This method is backward compatible, so it takes the current protocol version.
 pub fn create_receipt_id_from_transaction(
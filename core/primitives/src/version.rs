@@ -13,7 +13,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 26;
+pub const DB_VERSION: DbVersion = 32;
 
 /// Protocol version type.
 pub use near_primitives_core::types::ProtocolVersion;
@@ -107,6 +107,40 @@ pub enum ProtocolFeature {
     AltBn128,
     #[cfg(feature = "protocol_feature_simple_nightshade")]
     SimpleNightshade,
+    /// Serve trie reads of the runtime from the flat state and stop charging for the trie nodes
+    /// touched while looking a key up.
+    #[cfg(feature = "protocol_feature_flat_state")]
+    FlatStorageReads,
+    /// Allow relayers to submit actions signed by other accounts and pay for them
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    DelegateAction,
+    /// Deploy contract code once into a registry and let accounts deploy it by its hash
+    #[cfg(feature = "protocol_feature_shared_contract_code")]
+    SharedContractCode,
+    /// Publish the size of the delayed receipts queue in the chunk header and stop accepting
+    /// transactions towards congested shards
+    #[cfg(feature = "protocol_feature_congestion_control")]
+    CongestionControl,
+    /// Function call access keys restricted to several receivers, and the `UpdateAccessKey` action
+    /// changing the allowance and method names of an existing function call access key
+    #[cfg(feature = "protocol_feature_access_key_update")]
+    AccessKeyUpdate,
+    /// Optional priority fee in transactions, ordering the transaction pool and paid to the
+    /// chunk producer
+    #[cfg(feature = "protocol_feature_priority_fee")]
+    PriorityFee,
+    /// Accept the sign-extension operators and the `memory.copy` and `memory.fill` instructions
+    /// in contracts
+    #[cfg(feature = "protocol_feature_wasm_extensions")]
+    WasmExtensions,
+    /// The `DeployContractAndMigrate` action deploying a contract and calling its migration
+    /// method, both reverted if the migration fails
+    #[cfg(feature = "protocol_feature_deploy_and_migrate")]
+    DeployAndMigrate,
+    /// Delete accounts with more than `Account::MAX_ACCOUNT_DELETION_STORAGE_USAGE` bytes of
+    /// storage in several receipts, each removing a batch of the account records
+    #[cfg(feature = "protocol_feature_multi_step_account_deletion")]
+    MultiStepAccountDeletion,
 }
 
 /// Current latest stable version of the protocol.
@@ -117,7 +151,7 @@ pub const PROTOCOL_VERSION: ProtocolVersion = 47;
 
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 114;
+pub const PROTOCOL_VERSION: ProtocolVersion = 123;
 
 impl ProtocolFeature {
     pub const fn protocol_version(self) -> ProtocolVersion {
@@ -144,6 +178,24 @@ impl ProtocolFeature {
             ProtocolFeature::BlockHeaderV3 => 109,
             #[cfg(feature = "protocol_feature_simple_nightshade")]
             ProtocolFeature::SimpleNightshade => 114,
+            #[cfg(feature = "protocol_feature_flat_state")]
+            ProtocolFeature::FlatStorageReads => 115,
+            #[cfg(feature = "protocol_feature_delegate_action")]
+            ProtocolFeature::DelegateAction => 116,
+            #[cfg(feature = "protocol_feature_shared_contract_code")]
+            ProtocolFeature::SharedContractCode => 117,
+            #[cfg(feature = "protocol_feature_congestion_control")]
+            ProtocolFeature::CongestionControl => 118,
+            #[cfg(feature = "protocol_feature_access_key_update")]
+            ProtocolFeature::AccessKeyUpdate => 119,
+            #[cfg(feature = "protocol_feature_priority_fee")]
+            ProtocolFeature::PriorityFee => 120,
+            #[cfg(feature = "protocol_feature_wasm_extensions")]
+            ProtocolFeature::WasmExtensions => 121,
+            #[cfg(feature = "protocol_feature_deploy_and_migrate")]
+            ProtocolFeature::DeployAndMigrate => 122,
+            #[cfg(feature = "protocol_feature_multi_step_account_deletion")]
+            ProtocolFeature::MultiStepAccountDeletion => 123,
         }
     }
 }
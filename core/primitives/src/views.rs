@@ -13,7 +13,10 @@ use serde::{Deserialize, Serialize};
 
 use near_crypto::{PublicKey, Signature};
 
-use crate::account::{AccessKey, AccessKeyPermission, Account, FunctionCallPermission};
+use crate::account::{
+    AccessKey, AccessKeyPermission, Account, FunctionCallMultiReceiverPermission,
+    FunctionCallPermission,
+};
 use crate::block::{Block, BlockHeader};
 use crate::block_header::{
     BlockHeaderInnerLite, BlockHeaderInnerRest, BlockHeaderInnerRestV2, BlockHeaderV1,
@@ -27,20 +30,28 @@ use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
-use crate::receipt::{ActionReceipt, DataReceipt, DataReceiver, Receipt, ReceiptEnum};
+use crate::receipt::{
+    ActionReceipt, CongestionInfo, DataReceipt, DataReceiver, DelegatedActionReceipt, Receipt,
+    ReceiptEnum, SharedContractCodeReceipt,
+};
 use crate::serialize::{
     base64_format, from_base64, option_base64_format, option_u128_dec_format, to_base64,
     u128_dec_format, u64_dec_format,
 };
+#[cfg(feature = "protocol_feature_congestion_control")]
+use crate::sharding::ShardChunkHeaderInnerV3;
 #[cfg(not(feature = "protocol_feature_block_header_v3"))]
 use crate::sharding::ShardChunkHeaderV2;
 use crate::sharding::{ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner};
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use crate::sharding::{ShardChunkHeaderInnerV2, ShardChunkHeaderV3};
 use crate::transaction::{
-    Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
-    DeployContractAction, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithIdAndProof,
-    ExecutionStatus, FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
+    Action, AddKeyAction, CreateAccountAction, DelegateAction, DeleteAccountAction,
+    DeleteKeyAction, DeployContractAction, DeployContractAndMigrateAction,
+    DeployContractByHashAction, DeploySharedContractAction, ExecutionMetadata, ExecutionOutcome,
+    ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof, ExecutionStatus, FunctionCallAction,
+    RemoveSharedContractAction, SignedDelegateAction, SignedTransaction, StakeAction,
+    TransferAction, UpdateAccessKeyAction,
 };
 use crate::types::{
     AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
@@ -145,6 +156,12 @@ pub enum AccessKeyPermissionView {
         method_names: Vec<String>,
     },
     FullAccess,
+    FunctionCallMultiReceiver {
+        #[serde(with = "option_u128_dec_format")]
+        allowance: Option<Balance>,
+        receiver_ids: Vec<String>,
+        method_names: Vec<String>,
+    },
 }
 
 impl From<AccessKeyPermission> for AccessKeyPermissionView {
@@ -156,6 +173,13 @@ impl From<AccessKeyPermission> for AccessKeyPermissionView {
                 method_names: func_call.method_names,
             },
             AccessKeyPermission::FullAccess => AccessKeyPermissionView::FullAccess,
+            AccessKeyPermission::FunctionCallMultiReceiver(func_call) => {
+                AccessKeyPermissionView::FunctionCallMultiReceiver {
+                    allowance: func_call.allowance,
+                    receiver_ids: func_call.receiver_ids,
+                    method_names: func_call.method_names,
+                }
+            }
         }
     }
 }
@@ -171,6 +195,13 @@ impl From<AccessKeyPermissionView> for AccessKeyPermission {
                 })
             }
             AccessKeyPermissionView::FullAccess => AccessKeyPermission::FullAccess,
+            AccessKeyPermissionView::FunctionCallMultiReceiver {
+                allowance,
+                receiver_ids,
+                method_names,
+            } => AccessKeyPermission::FunctionCallMultiReceiver(
+                FunctionCallMultiReceiverPermission { allowance, receiver_ids, method_names },
+            ),
         }
     }
 }
@@ -285,6 +316,9 @@ pub struct QueryResponse {
     pub kind: QueryResponseKind,
     pub block_height: BlockHeight,
     pub block_hash: CryptoHash,
+    /// Trie nodes read while answering the query, if a proof was requested.
+    /// See `near_primitives::state_proof::StateProof` for verifying them.
+    pub proof: Option<TrieProofPath>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -381,6 +415,9 @@ pub struct BlockHeaderView {
     pub block_merkle_root: CryptoHash,
     #[cfg(feature = "protocol_feature_block_header_v3")]
     pub epoch_sync_data_hash: Option<CryptoHash>,
+    #[cfg(feature = "protocol_feature_block_header_v3")]
+    #[serde(default, with = "u128_dec_format")]
+    pub priority_fees: Balance,
     pub approvals: Vec<Option<Signature>>,
     pub signature: Signature,
     pub latest_protocol_version: ProtocolVersion,
@@ -425,6 +462,8 @@ impl From<BlockHeader> for BlockHeaderView {
             block_merkle_root: header.block_merkle_root().clone(),
             #[cfg(feature = "protocol_feature_block_header_v3")]
             epoch_sync_data_hash: header.epoch_sync_data_hash(),
+            #[cfg(feature = "protocol_feature_block_header_v3")]
+            priority_fees: header.priority_fees(),
             approvals: header.approvals().to_vec(),
             signature: header.signature().clone(),
             latest_protocol_version: header.latest_protocol_version(),
@@ -551,6 +590,7 @@ impl From<BlockHeaderView> for BlockHeader {
                         last_ds_final_block: view.last_ds_final_block,
                         prev_height: view.prev_height.unwrap_or_default(),
                         epoch_sync_data_hash: view.epoch_sync_data_hash,
+                        priority_fees: view.priority_fees,
                         approvals: view.approvals.clone(),
                         latest_protocol_version: view.latest_protocol_version,
                     },
@@ -660,6 +700,12 @@ pub struct ChunkHeaderView {
     pub tx_root: CryptoHash,
     pub validator_proposals: Vec<ValidatorStakeView>,
     pub signature: Signature,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub congestion_info: Option<CongestionInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub congested_shards: Vec<ShardId>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "option_u128_dec_format")]
+    pub priority_fees: Option<Balance>,
 }
 
 impl From<ShardChunkHeader> for ChunkHeaderView {
@@ -667,6 +713,10 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
         let hash = chunk.chunk_hash();
         let signature = chunk.signature().clone();
         let height_included = chunk.height_included();
+        let congestion_info = chunk.congestion_info();
+        let congested_shards = chunk.congested_shards().to_vec();
+        // The priority fees are reported by the same chunks as the congestion info.
+        let priority_fees = congestion_info.map(|_| chunk.priority_fees());
         let inner = chunk.take_inner();
         ChunkHeaderView {
             chunk_hash: hash.0,
@@ -687,6 +737,9 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
             tx_root: *inner.tx_root(),
             validator_proposals: inner.validator_proposals().map(Into::into).collect(),
             signature,
+            congestion_info,
+            congested_shards,
+            priority_fees,
         }
     }
 }
@@ -694,6 +747,38 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
 #[cfg(feature = "protocol_feature_block_header_v3")]
 impl From<ChunkHeaderView> for ShardChunkHeader {
     fn from(view: ChunkHeaderView) -> Self {
+        #[cfg(feature = "protocol_feature_congestion_control")]
+        if let Some(congestion_info) = view.congestion_info {
+            let mut header = ShardChunkHeaderV3 {
+                inner: ShardChunkHeaderInner::V3(ShardChunkHeaderInnerV3 {
+                    prev_block_hash: view.prev_block_hash,
+                    prev_state_root: view.prev_state_root,
+                    outcome_root: view.outcome_root,
+                    encoded_merkle_root: view.encoded_merkle_root,
+                    encoded_length: view.encoded_length,
+                    height_created: view.height_created,
+                    shard_id: view.shard_id,
+                    gas_used: view.gas_used,
+                    gas_limit: view.gas_limit,
+                    balance_burnt: view.balance_burnt,
+                    outgoing_receipts_root: view.outgoing_receipts_root,
+                    tx_root: view.tx_root,
+                    validator_proposals: view
+                        .validator_proposals
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    congestion_info,
+                    congested_shards: view.congested_shards,
+                    priority_fees: view.priority_fees.unwrap_or_default(),
+                }),
+                height_included: view.height_included,
+                signature: view.signature,
+                hash: ChunkHash::default(),
+            };
+            header.init();
+            return ShardChunkHeader::V3(header);
+        }
         let mut header = ShardChunkHeaderV3 {
             inner: ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
                 prev_block_hash: view.prev_block_hash,
@@ -823,6 +908,31 @@ pub enum ActionView {
     DeleteAccount {
         beneficiary_id: AccountId,
     },
+    Delegate {
+        delegate_action: DelegateAction,
+        signature: Signature,
+    },
+    DeploySharedContract {
+        code: String,
+    },
+    DeployContractByHash {
+        code_hash: CryptoHash,
+    },
+    RemoveSharedContract {
+        code_hash: CryptoHash,
+    },
+    UpdateAccessKey {
+        public_key: PublicKey,
+        #[serde(with = "option_u128_dec_format")]
+        allowance: Option<Balance>,
+        method_names: Vec<String>,
+    },
+    DeployContractAndMigrate {
+        code: String,
+        method_name: String,
+        args: String,
+        gas: Gas,
+    },
 }
 
 impl From<Action> for ActionView {
@@ -850,6 +960,30 @@ impl From<Action> for ActionView {
             Action::DeleteAccount(action) => {
                 ActionView::DeleteAccount { beneficiary_id: action.beneficiary_id }
             }
+            Action::Delegate(action) => ActionView::Delegate {
+                delegate_action: action.delegate_action,
+                signature: action.signature,
+            },
+            Action::DeploySharedContract(action) => {
+                ActionView::DeploySharedContract { code: to_base64(&hash(&action.code)) }
+            }
+            Action::DeployContractByHash(action) => {
+                ActionView::DeployContractByHash { code_hash: action.code_hash }
+            }
+            Action::RemoveSharedContract(action) => {
+                ActionView::RemoveSharedContract { code_hash: action.code_hash }
+            }
+            Action::UpdateAccessKey(action) => ActionView::UpdateAccessKey {
+                public_key: action.public_key,
+                allowance: action.allowance,
+                method_names: action.method_names,
+            },
+            Action::DeployContractAndMigrate(action) => ActionView::DeployContractAndMigrate {
+                code: to_base64(&hash(&action.code)),
+                method_name: action.method_name,
+                args: to_base64(&action.args),
+                gas: action.gas,
+            },
         }
     }
 }
@@ -884,6 +1018,35 @@ impl TryFrom<ActionView> for Action {
             ActionView::DeleteAccount { beneficiary_id } => {
                 Action::DeleteAccount(DeleteAccountAction { beneficiary_id })
             }
+            ActionView::Delegate { delegate_action, signature } => {
+                Action::Delegate(SignedDelegateAction { delegate_action, signature })
+            }
+            ActionView::DeploySharedContract { code } => {
+                Action::DeploySharedContract(DeploySharedContractAction {
+                    code: from_base64(&code)?,
+                })
+            }
+            ActionView::DeployContractByHash { code_hash } => {
+                Action::DeployContractByHash(DeployContractByHashAction { code_hash })
+            }
+            ActionView::RemoveSharedContract { code_hash } => {
+                Action::RemoveSharedContract(RemoveSharedContractAction { code_hash })
+            }
+            ActionView::UpdateAccessKey { public_key, allowance, method_names } => {
+                Action::UpdateAccessKey(UpdateAccessKeyAction {
+                    public_key,
+                    allowance,
+                    method_names,
+                })
+            }
+            ActionView::DeployContractAndMigrate { code, method_name, args, gas } => {
+                Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                    code: from_base64(&code)?,
+                    method_name,
+                    args: from_base64(&args)?,
+                    gas,
+                })
+            }
         })
     }
 }
@@ -895,6 +1058,8 @@ pub struct SignedTransactionView {
     pub nonce: Nonce,
     pub receiver_id: AccountId,
     pub actions: Vec<ActionView>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "option_u128_dec_format")]
+    pub priority_fee: Option<Balance>,
     pub signature: Signature,
     pub hash: CryptoHash,
 }
@@ -913,6 +1078,7 @@ impl From<SignedTransaction> for SignedTransactionView {
                 .into_iter()
                 .map(|action| action.into())
                 .collect(),
+            priority_fee: signed_tx.transaction.priority_fee,
             signature: signed_tx.signature,
             hash,
         }
@@ -1045,6 +1211,33 @@ pub struct ExecutionOutcomeWithIdView {
     pub outcome: ExecutionOutcomeView,
 }
 
+impl ExecutionOutcomeWithIdView {
+    /// Hashes of the outcome the way they are merklized into the chunk outcome root.
+    /// Returns `None` if the success value is not valid base64.
+    pub fn to_hashes(&self) -> Option<Vec<CryptoHash>> {
+        let status = match &self.outcome.status {
+            ExecutionStatusView::Unknown => ExecutionStatus::Unknown,
+            ExecutionStatusView::Failure(e) => ExecutionStatus::Failure(e.clone()),
+            ExecutionStatusView::SuccessValue(v) => {
+                ExecutionStatus::SuccessValue(from_base64(v).ok()?)
+            }
+            ExecutionStatusView::SuccessReceiptId(receipt_id) => {
+                ExecutionStatus::SuccessReceiptId(*receipt_id)
+            }
+        };
+        let outcome = ExecutionOutcome {
+            logs: self.outcome.logs.clone(),
+            receipt_ids: self.outcome.receipt_ids.clone(),
+            gas_burnt: self.outcome.gas_burnt,
+            tokens_burnt: self.outcome.tokens_burnt,
+            executor_id: self.outcome.executor_id.clone(),
+            status,
+            metadata: self.outcome.metadata.clone(),
+        };
+        Some(ExecutionOutcomeWithId { id: self.id, outcome }.to_hashes())
+    }
+}
+
 impl From<ExecutionOutcomeWithIdAndProof> for ExecutionOutcomeWithIdView {
     fn from(outcome_with_id_and_proof: ExecutionOutcomeWithIdAndProof) -> Self {
         Self {
@@ -1231,12 +1424,16 @@ pub enum ReceiptEnumView {
         output_data_receivers: Vec<DataReceiverView>,
         input_data_ids: Vec<CryptoHash>,
         actions: Vec<ActionView>,
+        /// The relayer the deposits are refunded to, for the actions of delegate actions.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        refund_to: Option<AccountId>,
     },
     Data {
         data_id: CryptoHash,
         #[serde(with = "option_base64_format")]
         data: Option<Vec<u8>>,
     },
+    SharedContractCode(SharedContractCodeReceipt),
 }
 
 impl From<Receipt> for ReceiptView {
@@ -1246,33 +1443,44 @@ impl From<Receipt> for ReceiptView {
             receiver_id: receipt.receiver_id,
             receipt_id: receipt.receipt_id,
             receipt: match receipt.receipt {
-                ReceiptEnum::Action(action_receipt) => ReceiptEnumView::Action {
-                    signer_id: action_receipt.signer_id,
-                    signer_public_key: action_receipt.signer_public_key,
-                    gas_price: action_receipt.gas_price,
-                    output_data_receivers: action_receipt
-                        .output_data_receivers
-                        .into_iter()
-                        .map(|data_receiver| DataReceiverView {
-                            data_id: data_receiver.data_id,
-                            receiver_id: data_receiver.receiver_id,
-                        })
-                        .collect(),
-                    input_data_ids: action_receipt
-                        .input_data_ids
-                        .into_iter()
-                        .map(Into::into)
-                        .collect(),
-                    actions: action_receipt.actions.into_iter().map(Into::into).collect(),
-                },
+                ReceiptEnum::Action(action_receipt) => {
+                    ReceiptEnumView::from_action_receipt(action_receipt, None)
+                }
+                ReceiptEnum::Delegated(DelegatedActionReceipt { action_receipt, refund_to }) => {
+                    ReceiptEnumView::from_action_receipt(action_receipt, Some(refund_to))
+                }
                 ReceiptEnum::Data(data_receipt) => {
                     ReceiptEnumView::Data { data_id: data_receipt.data_id, data: data_receipt.data }
                 }
+                ReceiptEnum::SharedContractCode(shared_contract_code_receipt) => {
+                    ReceiptEnumView::SharedContractCode(shared_contract_code_receipt)
+                }
             },
         }
     }
 }
 
+impl ReceiptEnumView {
+    fn from_action_receipt(action_receipt: ActionReceipt, refund_to: Option<AccountId>) -> Self {
+        ReceiptEnumView::Action {
+            signer_id: action_receipt.signer_id,
+            signer_public_key: action_receipt.signer_public_key,
+            gas_price: action_receipt.gas_price,
+            output_data_receivers: action_receipt
+                .output_data_receivers
+                .into_iter()
+                .map(|data_receiver| DataReceiverView {
+                    data_id: data_receiver.data_id,
+                    receiver_id: data_receiver.receiver_id,
+                })
+                .collect(),
+            input_data_ids: action_receipt.input_data_ids.into_iter().map(Into::into).collect(),
+            actions: action_receipt.actions.into_iter().map(Into::into).collect(),
+            refund_to,
+        }
+    }
+}
+
 impl TryFrom<ReceiptView> for Receipt {
     type Error = Box<dyn std::error::Error>;
 
@@ -1289,31 +1497,66 @@ impl TryFrom<ReceiptView> for Receipt {
                     output_data_receivers,
                     input_data_ids,
                     actions,
-                } => ReceiptEnum::Action(ActionReceipt {
-                    signer_id,
-                    signer_public_key,
-                    gas_price,
-                    output_data_receivers: output_data_receivers
-                        .into_iter()
-                        .map(|data_receiver_view| DataReceiver {
-                            data_id: data_receiver_view.data_id,
-                            receiver_id: data_receiver_view.receiver_id,
-                        })
-                        .collect(),
-                    input_data_ids: input_data_ids.into_iter().map(Into::into).collect(),
-                    actions: actions
-                        .into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<Result<Vec<_>, _>>()?,
-                }),
+                    refund_to,
+                } => {
+                    let action_receipt = ActionReceipt {
+                        signer_id,
+                        signer_public_key,
+                        gas_price,
+                        output_data_receivers: output_data_receivers
+                            .into_iter()
+                            .map(|data_receiver_view| DataReceiver {
+                                data_id: data_receiver_view.data_id,
+                                receiver_id: data_receiver_view.receiver_id,
+                            })
+                            .collect(),
+                        input_data_ids: input_data_ids.into_iter().map(Into::into).collect(),
+                        actions: actions
+                            .into_iter()
+                            .map(TryInto::try_into)
+                            .collect::<Result<Vec<_>, _>>()?,
+                    };
+                    match refund_to {
+                        Some(refund_to) => ReceiptEnum::Delegated(DelegatedActionReceipt {
+                            action_receipt,
+                            refund_to,
+                        }),
+                        None => ReceiptEnum::Action(action_receipt),
+                    }
+                }
                 ReceiptEnumView::Data { data_id, data } => {
                     ReceiptEnum::Data(DataReceipt { data_id, data })
                 }
+                ReceiptEnumView::SharedContractCode(shared_contract_code_receipt) => {
+                    ReceiptEnum::SharedContractCode(shared_contract_code_receipt)
+                }
             },
         })
     }
 }
 
+/// Receipt waiting in the delayed receipts queue of a shard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DelayedReceiptView {
+    /// Position of the receipt in the queue, see `DelayedReceiptIndices`.
+    pub index: u64,
+    pub receipt: ReceiptView,
+}
+
+/// Page of the delayed receipts queue of a shard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DelayedReceiptsView {
+    pub first_index: u64,
+    pub next_available_index: u64,
+    /// Number of receipts in the whole queue.
+    pub length: u64,
+    /// Gas attached to all the receipts in the whole queue. It's tracked in the state since
+    /// congestion control, before that it's `None` if the queue is too long to sum it up.
+    pub total_prepaid_gas: Option<Gas>,
+    /// Receipts of the requested page, in queue order.
+    pub receipts: Vec<DelayedReceiptView>,
+}
+
 /// Information about this epoch validators and next epoch validators
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EpochValidatorInfo {
@@ -1362,7 +1605,7 @@ pub struct NextEpochValidatorInfo {
     pub shards: Vec<ShardId>,
 }
 
-#[derive(Serialize, PartialEq, Eq, Debug, Clone, BorshDeserialize, BorshSerialize)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, BorshDeserialize, BorshSerialize)]
 pub struct LightClientBlockView {
     pub prev_block_hash: CryptoHash,
     pub next_block_inner_hash: CryptoHash,
@@ -1487,6 +1730,7 @@ pub enum StateChangeCauseView {
     UpdatedDelayedReceipts,
     ValidatorAccountsUpdate,
     Migration,
+    Resharding,
 }
 
 impl From<StateChangeCause> for StateChangeCauseView {
@@ -1512,6 +1756,7 @@ impl From<StateChangeCause> for StateChangeCauseView {
             StateChangeCause::UpdatedDelayedReceipts => Self::UpdatedDelayedReceipts,
             StateChangeCause::ValidatorAccountsUpdate => Self::ValidatorAccountsUpdate,
             StateChangeCause::Migration => Self::Migration,
+            StateChangeCause::Resharding => Self::Resharding,
         }
     }
 }
@@ -1604,3 +1849,14 @@ impl From<StateChangeWithCause> for StateChangeWithCauseView {
 }
 
 pub type StateChangesView = Vec<StateChangeWithCauseView>;
+
+/// Changes made to the state by a single block.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateChangesInBlockView {
+    pub block_height: BlockHeight,
+    pub block_hash: CryptoHash,
+    pub changes: StateChangesView,
+}
+
+/// Changes made to the state by the blocks in a range of heights, ordered by height.
+pub type StateChangesHistoryView = Vec<StateChangesInBlockView>;
@@ -119,10 +119,42 @@ pub enum DBCol {
     ColEpochValidatorInfo = 47,
     /// Header Hashes indexed by Height
     ColHeaderHashesByHeight = 48,
+    /// Round trip time statistics of peers
+    /// Key: peer id
+    /// Value: round trip time samples and the time of the last one
+    ColPeerLatency = 49,
+    /// State changes made by a chunk, used for splitting states
+    /// Key: block_hash || parent shard uid
+    /// Value: StateChangesForSplitStates
+    ColStateChangesForSplitStates = 50,
+    /// Values of the state at the flat storage head, indexed by trie key
+    /// Key: shard uid || trie key
+    /// Value: ValueRef
+    ColFlatState = 51,
+    /// Changes made to the flat state by blocks after the flat storage head
+    /// Key: shard uid || block hash
+    /// Value: FlatStateDelta
+    ColFlatStateDeltas = 52,
+    /// Flat storage heads
+    /// Key: shard uid
+    /// Value: FlatStorageHead
+    ColFlatStateMisc = 53,
+    /// Keys of the data which GC keeps longer than the blocks it belongs to
+    /// Key: data category || height (big endian)
+    /// Value: Vec<Vec<u8>>
+    ColGCRetainedData = 54,
+    /// Index of `ColStateChanges` by the changed trie key
+    /// Key: trie key || height (big endian) || block hash
+    /// Value: empty
+    ColStateChangesByKey = 55,
+    /// Accounts after each block changing them, kept by archival nodes if enabled
+    /// Key: account id || ',' || !height (big endian) || block hash
+    /// Value: Option<Account>
+    ColAccountHistory = 56,
 }
 
 // Do not move this line from enum DBCol
-pub const NUM_COLS: usize = 49;
+pub const NUM_COLS: usize = 57;
 
 impl std::fmt::Display for DBCol {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -176,6 +208,16 @@ impl std::fmt::Display for DBCol {
             Self::ColCachedContractCode => "cached code",
             Self::ColEpochValidatorInfo => "epoch validator info",
             Self::ColHeaderHashesByHeight => "header hashes indexed by their height",
+            Self::ColPeerLatency => "round trip time statistics of peers",
+            Self::ColStateChangesForSplitStates => {
+                "state changes indexed by block hash and shard id, used for splitting states"
+            }
+            Self::ColFlatState => "flat state",
+            Self::ColFlatStateDeltas => "flat state deltas of blocks after the flat storage head",
+            Self::ColFlatStateMisc => "flat storage heads",
+            Self::ColGCRetainedData => "keys of the data kept by GC longer than its blocks",
+            Self::ColStateChangesByKey => "key value changes indexed by key and height",
+            Self::ColAccountHistory => "accounts indexed by account id and height",
         };
         write!(formatter, "{}", desc)
     }
@@ -204,11 +246,18 @@ lazy_static! {
         col_gc[DBCol::ColPeerComponent as usize] = false; // Peer related info doesn't GC
         col_gc[DBCol::ColLastComponentNonce as usize] = false;
         col_gc[DBCol::ColComponentEdges as usize] = false;
+        col_gc[DBCol::ColPeerLatency as usize] = false;
         col_gc[DBCol::ColBlockOrdinal as usize] = false;
         col_gc[DBCol::ColEpochInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColEpochValidatorInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColCachedContractCode as usize] = false;
+        // Flat state is pruned when the flat storage head moves, not by block GC
+        col_gc[DBCol::ColFlatState as usize] = false;
+        col_gc[DBCol::ColFlatStateDeltas as usize] = false;
+        col_gc[DBCol::ColFlatStateMisc as usize] = false;
+        // Only kept by archival nodes
+        col_gc[DBCol::ColAccountHistory as usize] = false;
         col_gc
     };
 }
@@ -222,6 +271,8 @@ lazy_static! {
         col_gc[DBCol::ColStateHeaders as usize] = true;
         // True until #2515
         col_gc[DBCol::ColStateParts as usize] = true;
+        // Only used if GC retention is configured
+        col_gc[DBCol::ColGCRetainedData as usize] = true;
         col_gc
     };
 }
@@ -242,13 +293,21 @@ pub const HEAD_KEY: &[u8; 4] = b"HEAD";
 pub const TAIL_KEY: &[u8; 4] = b"TAIL";
 pub const CHUNK_TAIL_KEY: &[u8; 10] = b"CHUNK_TAIL";
 pub const FORK_TAIL_KEY: &[u8; 9] = b"FORK_TAIL";
+/// Prefix of the keys of the lowest heights of the data categories kept longer than blocks.
+pub const GC_RETAINED_TAIL_KEY: &[u8; 16] = b"GC_RETAINED_TAIL";
+/// Height from which `ColAccountHistory` has all the changes of the accounts.
+pub const ACCOUNT_HISTORY_START_KEY: &[u8; 21] = b"ACCOUNT_HISTORY_START";
 pub const HEADER_HEAD_KEY: &[u8; 11] = b"HEADER_HEAD";
 pub const FINAL_HEAD_KEY: &[u8; 10] = b"FINAL_HEAD";
 pub const LATEST_KNOWN_KEY: &[u8; 12] = b"LATEST_KNOWN";
 pub const LARGEST_TARGET_HEIGHT_KEY: &[u8; 21] = b"LARGEST_TARGET_HEIGHT";
 pub const VERSION_KEY: &[u8; 7] = b"VERSION";
+/// Progress of the migration being applied, stored in `ColDbVersion` next to the version.
+pub const MIGRATION_CHECKPOINT_KEY: &[u8; 20] = b"MIGRATION_CHECKPOINT";
 pub const GENESIS_JSON_HASH_KEY: &[u8; 17] = b"GENESIS_JSON_HASH";
 pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
+/// Progress of the background store validation.
+pub const STORE_VALIDATOR_CHECKPOINT_KEY: &[u8; 26] = b"STORE_VALIDATOR_CHECKPOINT";
 
 pub struct DBTransaction {
     pub ops: Vec<DBOp>,
@@ -455,6 +514,12 @@ pub trait Database: Sync + Send {
         col: DBCol,
         key_prefix: &'a [u8],
     ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
+    /// Iterates over the keys of the column which are not lower than `key_from`, in key order.
+    fn iter_from_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+        key_from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
     fn write(&self, batch: DBTransaction) -> Result<(), DBError>;
     fn as_rocksdb(&self) -> Option<&RocksDB> {
         None
@@ -514,6 +579,23 @@ impl Database for RocksDB {
         }
     }
 
+    fn iter_from_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+        key_from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let read_options = rocksdb_read_options();
+        unsafe {
+            let cf_handle = &*self.cfs[col as usize];
+            let iterator = self.db.iterator_cf_opt(
+                cf_handle,
+                read_options,
+                IteratorMode::From(key_from, Direction::Forward),
+            );
+            Box::new(iterator)
+        }
+    }
+
     fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
         if let Err(check) = self.pre_write_check() {
             if check.is_io() {
@@ -590,6 +672,20 @@ impl Database for TestDB {
         )
     }
 
+    fn iter_from_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+        key_from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let mut items: Vec<_> = self.db.read().unwrap()[col as usize]
+            .iter()
+            .filter(|(key, _value)| key.as_slice() >= key_from)
+            .map(|(k, v)| (k.clone().into_boxed_slice(), v.clone().into_boxed_slice()))
+            .collect();
+        items.sort();
+        Box::new(items.into_iter())
+    }
+
     fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
         let mut db = self.db.write().unwrap();
         for op in transaction.ops {
@@ -700,6 +796,12 @@ impl RocksDB {
         RocksDBOptions::default().read_write(path)
     }
 
+    /// Returns the estimate of the number of keys in the column.
+    pub fn estimate_num_keys(&self, col: DBCol) -> Result<Option<u64>, DBError> {
+        let cf_handle = unsafe { &*self.cfs[col as usize] };
+        Ok(self.db.property_int_value_cf(cf_handle, "rocksdb.estimate-num-keys")?)
+    }
+
     /// Checks if there is enough memory left to perform a write. Not having enough memory left can
     /// lead to difficult to recover from state, thus a PreWriteCheckErr is pretty much
     /// unrecoverable in most cases.
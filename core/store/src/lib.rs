@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::ops::Deref;
@@ -15,17 +16,18 @@ use cached::{Cached, SizedCache};
 
 pub use db::DBCol::{self, *};
 pub use db::{
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
+    ACCOUNT_HISTORY_START_KEY, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, GC_RETAINED_TAIL_KEY,
+    HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, NUM_COLS,
+    SHOULD_COL_GC, SKIP_COL_GC, STORE_VALIDATOR_CHECKPOINT_KEY, TAIL_KEY,
 };
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, Account};
-use near_primitives::contract::ContractCode;
+use near_primitives::contract::{ContractCode, SharedContractCode};
 pub use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{Receipt, ReceivedData};
 use near_primitives::serialize::to_base;
-use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::trie_key::{col, trie_key_parsers, TrieKey};
 use near_primitives::types::{AccountId, CompiledContractCache, StateRoot};
 
 pub use crate::db::refcount::decode_value_with_rc;
@@ -34,12 +36,14 @@ use crate::db::{
     DBOp, DBTransaction, Database, RocksDB, GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY,
 };
 pub use crate::trie::{
-    iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateIterator,
-    update::TrieUpdateValuePtr, ApplyStatePartResult, KeyForStateChanges, PartialStorage,
-    ShardTries, Trie, TrieChanges, WrappedTrieChanges,
+    get_delayed_receipts, iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateIterator,
+    update::TrieUpdateValuePtr, ApplyStatePartResult, FlatState, FlatStateDelta, FlatStorageHead,
+    KeyForStateChanges, KeyForStateChangesByKey, PartialStorage, ShardTries,
+    StateChangesForSplitStates, Trie, TrieCacheConfig, TrieChanges, ValueRef, WrappedTrieChanges,
 };
 
 pub mod db;
+mod metrics;
 pub mod migrations;
 pub mod test_utils;
 mod trie;
@@ -103,6 +107,14 @@ impl Store {
         self.storage.iter_prefix(column, key_prefix)
     }
 
+    pub fn iter_from_without_rc_logic<'a>(
+        &'a self,
+        column: DBCol,
+        key_from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter_from_without_rc_logic(column, key_from)
+    }
+
     pub fn iter_prefix_ser<'a, T: BorshDeserialize>(
         &'a self,
         column: DBCol,
@@ -432,6 +444,77 @@ pub fn get_code(
         .map(|opt| opt.map(|code| ContractCode::new(code, code_hash)))
 }
 
+pub fn set_shared_code(
+    state_update: &mut TrieUpdate,
+    code_hash: CryptoHash,
+    shared_code: &SharedContractCode,
+) {
+    set(state_update, TrieKey::SharedContractCode { code_hash }, shared_code)
+}
+
+pub fn get_shared_code(
+    state_update: &TrieUpdate,
+    code_hash: CryptoHash,
+) -> Result<Option<SharedContractCode>, StorageError> {
+    get(state_update, &TrieKey::SharedContractCode { code_hash })
+}
+
+pub fn remove_shared_code(state_update: &mut TrieUpdate, code_hash: CryptoHash) {
+    state_update.remove(TrieKey::SharedContractCode { code_hash });
+}
+
+/// Returns the number of registrations of the code in the shared contract code registry by the
+/// account.
+pub fn get_shared_code_registrations(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+    code_hash: CryptoHash,
+) -> Result<u64, StorageError> {
+    get(
+        state_update,
+        &TrieKey::SharedContractCodeOwner { account_id: account_id.clone(), code_hash },
+    )
+    .map(|num_registrations| num_registrations.unwrap_or(0))
+}
+
+pub fn set_shared_code_registrations(
+    state_update: &mut TrieUpdate,
+    account_id: AccountId,
+    code_hash: CryptoHash,
+    num_registrations: u64,
+) {
+    let trie_key = TrieKey::SharedContractCodeOwner { account_id, code_hash };
+    if num_registrations == 0 {
+        state_update.remove(trie_key);
+    } else {
+        set(state_update, trie_key, &num_registrations);
+    }
+}
+
+/// Whether the account has any registrations in the shared contract code registry.
+pub fn has_shared_code_registrations(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<bool, StorageError> {
+    let prefix = trie_key_parsers::get_raw_prefix_for_shared_contract_code_owner(account_id);
+    Ok(state_update.iter(&prefix)?.next().transpose()?.is_some())
+}
+
+/// Returns the code the account executes: the code deployed on the account itself, or the one
+/// from the shared contract code registry if the account has deployed it by `code_hash`.
+pub fn get_deployed_code(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+    code_hash: CryptoHash,
+) -> Result<Option<ContractCode>, StorageError> {
+    match get_code(state_update, account_id, Some(code_hash))? {
+        Some(code) => Ok(Some(code)),
+        None if code_hash != CryptoHash::default() => Ok(get_shared_code(state_update, code_hash)?
+            .map(|shared_code| ContractCode::new(shared_code.code, Some(code_hash)))),
+        None => Ok(None),
+    }
+}
+
 /// Removes account, code and all access keys associated to it.
 pub fn remove_account(
     state_update: &mut TrieUpdate,
@@ -476,6 +559,121 @@ pub fn remove_account(
     Ok(())
 }
 
+/// Removes access keys, contract data and then the data and postponed receipts received by the
+/// account one record at a time for as long as `can_remove` accepts the column of the record, the
+/// length of its key (the serialized public key, the data key or the hash) and the length of its
+/// value. Returns whether all of them were removed.
+pub fn remove_account_storage_batch(
+    state_update: &mut TrieUpdate,
+    account_id: &AccountId,
+    mut can_remove: impl FnMut(&[u8], u64, u64) -> bool,
+) -> Result<bool, StorageError> {
+    let mut trie_keys = vec![];
+    let mut is_complete = true;
+    let prefixes = [
+        trie_key_parsers::get_raw_prefix_for_access_keys(account_id),
+        trie_key_parsers::get_raw_prefix_for_contract_data(account_id, &[]),
+        trie_key_parsers::get_raw_prefix_for_receiver_records(col::RECEIVED_DATA, account_id),
+        trie_key_parsers::get_raw_prefix_for_receiver_records(
+            col::POSTPONED_RECEIPT_ID,
+            account_id,
+        ),
+        trie_key_parsers::get_raw_prefix_for_receiver_records(col::PENDING_DATA_COUNT, account_id),
+        trie_key_parsers::get_raw_prefix_for_receiver_records(col::POSTPONED_RECEIPT, account_id),
+    ];
+    'prefixes: for prefix in prefixes.iter() {
+        for raw_key in state_update.iter(prefix)? {
+            let raw_key = raw_key?;
+            let (column, trie_key, key_len) = if raw_key.starts_with(col::ACCESS_KEY) {
+                let public_key =
+                    trie_key_parsers::parse_public_key_from_access_key_key(&raw_key, account_id)
+                        .map_err(|_e| {
+                            StorageError::StorageInconsistentState(
+                                "Can't parse public key from raw key for AccessKey".to_string(),
+                            )
+                        })?;
+                let key_len = public_key.len();
+                (
+                    col::ACCESS_KEY,
+                    TrieKey::AccessKey { account_id: account_id.clone(), public_key },
+                    key_len,
+                )
+            } else if raw_key.starts_with(col::CONTRACT_DATA) {
+                let key =
+                    trie_key_parsers::parse_data_key_from_contract_data_key(&raw_key, account_id)
+                        .map_err(|_e| {
+                            StorageError::StorageInconsistentState(
+                                "Can't parse data key from raw key for ContractData".to_string(),
+                            )
+                        })?
+                        .to_vec();
+                let key_len = key.len();
+                (
+                    col::CONTRACT_DATA,
+                    TrieKey::ContractData { account_id: account_id.clone(), key },
+                    key_len,
+                )
+            } else {
+                let hash = CryptoHash::try_from(&raw_key[prefix.len()..]).map_err(|_e| {
+                    StorageError::StorageInconsistentState(
+                        "Can't parse hash from raw key for a received record".to_string(),
+                    )
+                })?;
+                let receiver_id = account_id.clone();
+                let (column, trie_key) = if raw_key.starts_with(col::RECEIVED_DATA) {
+                    (col::RECEIVED_DATA, TrieKey::ReceivedData { receiver_id, data_id: hash })
+                } else if raw_key.starts_with(col::POSTPONED_RECEIPT_ID) {
+                    (
+                        col::POSTPONED_RECEIPT_ID,
+                        TrieKey::PostponedReceiptId { receiver_id, data_id: hash },
+                    )
+                } else if raw_key.starts_with(col::PENDING_DATA_COUNT) {
+                    (
+                        col::PENDING_DATA_COUNT,
+                        TrieKey::PendingDataCount { receiver_id, receipt_id: hash },
+                    )
+                } else {
+                    (
+                        col::POSTPONED_RECEIPT,
+                        TrieKey::PostponedReceipt { receiver_id, receipt_id: hash },
+                    )
+                };
+                (column, trie_key, hash.as_ref().len())
+            };
+            let value_len = state_update.get_ref(&trie_key)?.map_or(0, |value| value.len());
+            if !can_remove(column, key_len as u64, value_len as u64) {
+                is_complete = false;
+                break 'prefixes;
+            }
+            trie_keys.push(trie_key);
+        }
+    }
+    for trie_key in trie_keys {
+        state_update.remove(trie_key);
+    }
+    Ok(is_complete)
+}
+
+/// Returns the beneficiary of the account if its deletion is still in progress.
+pub fn get_pending_account_deletion(
+    state_update: &TrieUpdate,
+    account_id: &AccountId,
+) -> Result<Option<AccountId>, StorageError> {
+    get(state_update, &TrieKey::PendingAccountDeletion { account_id: account_id.clone() })
+}
+
+pub fn set_pending_account_deletion(
+    state_update: &mut TrieUpdate,
+    account_id: AccountId,
+    beneficiary_id: &AccountId,
+) {
+    set(state_update, TrieKey::PendingAccountDeletion { account_id }, beneficiary_id)
+}
+
+pub fn remove_pending_account_deletion(state_update: &mut TrieUpdate, account_id: &AccountId) {
+    state_update.remove(TrieKey::PendingAccountDeletion { account_id: account_id.clone() });
+}
+
 pub fn get_genesis_state_roots(store: &Store) -> Result<Option<Vec<StateRoot>>, std::io::Error> {
     store.get_ser::<Vec<StateRoot>>(DBCol::ColBlockMisc, GENESIS_STATE_ROOTS_KEY)
 }
@@ -518,9 +716,78 @@ impl CompiledContractCache for StoreCompiledContractCache {
 
 #[cfg(test)]
 mod tests {
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::trie_key::{col, TrieKey};
+    use near_primitives::types::{AccountId, StateChangeCause};
+
+    use crate::test_utils::create_tries;
+    use crate::{remove_account_storage_batch, TrieUpdate};
+
     #[test]
     fn test_no_cache_disabled() {
         #[cfg(feature = "no_cache")]
         panic!("no cache is enabled");
     }
+
+    fn account_records(account_id: &AccountId) -> Vec<TrieKey> {
+        let receiver_id = account_id.clone();
+        vec![
+            TrieKey::AccessKey {
+                account_id: account_id.clone(),
+                public_key: PublicKey::empty(KeyType::ED25519),
+            },
+            TrieKey::ContractData { account_id: account_id.clone(), key: b"key".to_vec() },
+            TrieKey::ReceivedData { receiver_id: receiver_id.clone(), data_id: hash(b"data") },
+            TrieKey::PostponedReceiptId {
+                receiver_id: receiver_id.clone(),
+                data_id: hash(b"data"),
+            },
+            TrieKey::PendingDataCount { receiver_id: receiver_id.clone(), receipt_id: hash(b"id") },
+            TrieKey::PostponedReceipt { receiver_id, receipt_id: hash(b"id") },
+        ]
+    }
+
+    #[test]
+    fn test_remove_account_storage_batch() {
+        let tries = create_tries();
+        let mut state_update = tries.new_trie_update(0, CryptoHash::default());
+        let alice: AccountId = "alice".parse().unwrap();
+        let bob: AccountId = "bob".parse().unwrap();
+        for trie_key in account_records(&alice).into_iter().chain(account_records(&bob)) {
+            state_update.set(trie_key, vec![1, 2, 3]);
+        }
+        state_update.commit(StateChangeCause::InitialState);
+
+        let columns = [
+            col::ACCESS_KEY,
+            col::CONTRACT_DATA,
+            col::RECEIVED_DATA,
+            col::POSTPONED_RECEIPT_ID,
+            col::PENDING_DATA_COUNT,
+        ];
+        let mut removed = 0;
+        let is_complete =
+            remove_account_storage_batch(&mut state_update, &alice, |column, _, value_len| {
+                assert_eq!(column, columns[removed]);
+                assert_eq!(value_len, 3);
+                removed += 1;
+                removed <= 4
+            })
+            .unwrap();
+        assert!(!is_complete);
+        let remaining = |state_update: &TrieUpdate| {
+            account_records(&alice)
+                .into_iter()
+                .filter(|trie_key| state_update.get(trie_key).unwrap().is_some())
+                .count()
+        };
+        assert_eq!(remaining(&state_update), 2);
+
+        assert!(remove_account_storage_batch(&mut state_update, &alice, |_, _, _| true).unwrap());
+        assert_eq!(remaining(&state_update), 0);
+        for trie_key in account_records(&bob) {
+            assert_eq!(state_update.get(&trie_key).unwrap(), Some(vec![1, 2, 3]));
+        }
+    }
 }
@@ -0,0 +1,45 @@
+use near_metrics::{
+    try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge_vec, IntCounter,
+    IntCounterVec, IntGaugeVec,
+};
+
+lazy_static! {
+    pub static ref TRIE_CACHE_HITS: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_trie_cache_hits_total",
+            "Total number of trie node reads served by the trie cache",
+            &["shard_id", "is_view"]
+        );
+    pub static ref TRIE_CACHE_PREFETCHED_HITS: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_trie_cache_prefetched_hits_total",
+            "Total number of trie node reads served by nodes the prefetcher loaded into the cache",
+            &["shard_id", "is_view"]
+        );
+    pub static ref TRIE_CACHE_MISSES: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_trie_cache_misses_total",
+            "Total number of trie node reads that missed the trie cache",
+            &["shard_id", "is_view"]
+        );
+    pub static ref TRIE_CACHE_EVICTIONS: near_metrics::Result<IntCounterVec> =
+        try_create_int_counter_vec(
+            "near_trie_cache_evictions_total",
+            "Total number of trie nodes evicted from the trie cache to make room for others",
+            &["shard_id", "is_view"]
+        );
+    pub static ref TRIE_CACHE_SIZE: near_metrics::Result<IntGaugeVec> = try_create_int_gauge_vec(
+        "near_trie_cache_size_bytes",
+        "Approximate memory taken by the trie cache, pinned nodes included",
+        &["shard_id", "is_view"]
+    );
+    pub static ref TRIE_PREFETCH_REQUESTS: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_trie_prefetch_requests_total",
+            "Total number of batches of keys queued for prefetching into the trie cache"
+        );
+    pub static ref TRIE_PREFETCH_DROPPED: near_metrics::Result<IntCounter> = try_create_int_counter(
+        "near_trie_prefetch_dropped_total",
+        "Total number of batches of keys not prefetched because the prefetch queue was full"
+    );
+}
@@ -14,13 +14,17 @@ use near_primitives::version::DbVersion;
 
 use crate::db::DBCol::{ColBlockHeader, ColBlockMisc, ColChunks, ColPartialChunks, ColStateParts};
 use crate::db::{DBCol, RocksDB, GENESIS_JSON_HASH_KEY, VERSION_KEY};
+use crate::migrations::runner::MigrationProgress;
 use crate::migrations::v6_to_v7::{
     col_state_refcount_8byte, migrate_col_transaction_refcount, migrate_receipts_refcount,
 };
 use crate::migrations::v8_to_v9::{
     recompute_col_rc, repair_col_receipt_id_to_shard_id, repair_col_transactions,
 };
-use crate::{create_store, Store, StoreUpdate, Trie, TrieUpdate, FINAL_HEAD_KEY, HEAD_KEY};
+use crate::{
+    create_store, KeyForStateChangesByKey, Store, StoreUpdate, Trie, TrieUpdate, FINAL_HEAD_KEY,
+    HEAD_KEY,
+};
 
 use crate::trie::{TrieCache, TrieCachingStorage};
 use near_crypto::KeyType;
@@ -30,16 +34,17 @@ use near_primitives::block_header::BlockHeader;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfoV1;
 use near_primitives::merkle::merklize;
-use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum};
+use near_primitives::receipt::{CongestionInfo, DelayedReceiptIndices, Receipt, ReceiptEnum};
 use near_primitives::syncing::{ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1};
 use near_primitives::trie_key::TrieKey;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::{AccountId, Balance};
+use near_primitives::types::{AccountId, Balance, BlockHeight};
 use near_primitives::utils::{create_receipt_id_from_transaction, get_block_shard_id};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use std::rc::Rc;
 
+pub mod runner;
 pub mod v6_to_v7;
 pub mod v8_to_v9;
 
@@ -199,6 +204,9 @@ pub fn migrate_9_to_10(path: &String, is_archival: bool) {
                 transactions,
                 &receipts,
                 header.inner.outgoing_receipts_root,
+                CongestionInfo::default(),
+                vec![],
+                0,
                 &signer,
                 protocol_version,
             )
@@ -345,14 +353,17 @@ where
 {
     let keys: Vec<_> = store.iter(col).map(|(key, _)| key).collect();
     let mut store_update = BatchedStoreUpdate::new(store, 10_000_000);
+    let mut progress = MigrationProgress::new(store, "Migrating", col);
 
     for key in keys {
         let value: T = store.get_ser(col, key.as_ref())?.unwrap();
         let new_value = f(value);
         store_update.set_ser(col, key.as_ref(), &new_value)?;
+        progress.inc();
     }
 
     store_update.finish()?;
+    progress.finish();
 
     Ok(())
 }
@@ -366,11 +377,13 @@ where
     let mut store_update = store.store_update();
     let batch_size_limit = 10_000_000;
     let mut batch_size = 0;
+    let mut progress = MigrationProgress::new(store, "Migrating", col);
     for (key, _) in store.iter(col) {
         let new_value = f(&key);
         let new_bytes = new_value.try_to_vec()?;
         batch_size += key.as_ref().len() + new_bytes.len() + 8;
         store_update.set(col, key.as_ref(), &new_bytes);
+        progress.inc();
 
         if batch_size > batch_size_limit {
             store_update.commit()?;
@@ -382,6 +395,7 @@ where
     if batch_size > 0 {
         store_update.commit()?;
     }
+    progress.finish();
 
     Ok(())
 }
@@ -476,7 +490,7 @@ pub fn migrate_14_to_15(path: &String) {
 
             let mut process_receipt =
                 |receipt: &Receipt, state_update: &mut TrieUpdate| match &receipt.receipt {
-                    ReceiptEnum::Action(_) => {
+                    ReceiptEnum::Action(_) | ReceiptEnum::Delegated(_) => {
                         if execution_outcome_ids.contains(&receipt.receipt_id) {
                             new_execution_outcome_ids.push(receipt.receipt_id);
                         }
@@ -503,6 +517,8 @@ pub fn migrate_14_to_15(path: &String) {
                                 .set(trie_key, (pending_receipt_count - 1).try_to_vec().unwrap())
                         }
                     }
+                    // Didn't exist at the time of this migration.
+                    ReceiptEnum::SharedContractCode(_) => {}
                 };
 
             // Step 2: delayed receipts
@@ -717,6 +733,35 @@ pub fn migrate_25_to_26(path: &String) {
     set_store_version(&store, 26);
 }
 
+/// Indexes the stored state changes by the trie key and the height of the block.
+pub fn migrate_30_to_31(path: &String) {
+    use std::convert::TryFrom;
+
+    let store = create_store(path);
+    let mut heights: HashMap<CryptoHash, Option<BlockHeight>> = HashMap::new();
+    let mut store_update = BatchedStoreUpdate::new(&store, 10_000_000);
+    let mut progress =
+        MigrationProgress::new(&store, "Indexing state changes", DBCol::ColStateChanges);
+    for (key, _) in store.iter(DBCol::ColStateChanges) {
+        progress.inc();
+        let (block_hash, trie_key) = key.split_at(std::mem::size_of::<CryptoHash>());
+        let block_hash = CryptoHash::try_from(block_hash).unwrap();
+        let height = *heights.entry(block_hash).or_insert_with(|| {
+            store
+                .get_ser::<BlockHeader>(ColBlockHeader, block_hash.as_ref())
+                .unwrap()
+                .map(|header| header.height())
+        });
+        // The changes of blocks without headers are never looked up by height.
+        if let Some(height) = height {
+            let index_key = KeyForStateChangesByKey::new(trie_key, height, &block_hash);
+            store_update.set_ser(DBCol::ColStateChangesByKey, index_key.as_ref(), &()).unwrap();
+        }
+    }
+    store_update.finish().unwrap();
+    progress.finish();
+}
+
 #[cfg(feature = "protocol_feature_block_header_v3")]
 pub fn migrate_18_to_new_validator_stake(store: &Store) {
     use near_primitives::epoch_manager::block_info::{BlockInfo, BlockInfoV1};
@@ -0,0 +1,402 @@
+//! Applying database migrations with progress reporting, checkpoints and snapshots.
+//!
+//! Before a migration runs, the columns it changes are copied to a snapshot database next to
+//! the main one. The progress is kept in `ColDbVersion` under `MIGRATION_CHECKPOINT_KEY`:
+//! an interrupted snapshot continues from the last copied key, and an interrupted or failed
+//! migration is reverted from the snapshot. An interrupted migration is then run again.
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use rocksdb::{Options, DB};
+use strum::IntoEnumIterator;
+use tracing::{error, info, warn};
+
+use near_primitives::version::DbVersion;
+
+use crate::db::{DBError, RocksDB, RocksDBOptions, MIGRATION_CHECKPOINT_KEY};
+use crate::migrations::{get_store_version, set_store_version};
+use crate::{create_store, DBCol, Store};
+
+/// Databases of lower versions can't be opened with the current column options, so migrations
+/// from them run without snapshots and checkpoints.
+const MIN_SNAPSHOT_VERSION: DbVersion = 7;
+
+const SNAPSHOT_DIR: &str = "migration_snapshot";
+
+const BATCH_SIZE_LIMIT: usize = 10_000_000;
+
+const PROGRESS_REPORT_PERIOD: Duration = Duration::from_secs(10);
+
+/// Migration of the database from `from_version` to the next version.
+pub struct Migration<'a> {
+    pub from_version: DbVersion,
+    pub description: &'static str,
+    /// Columns the migration changes, they are snapshotted before it runs.
+    pub columns: &'static [DBCol],
+    /// Applies the migration to the database at the given path.
+    run: Box<dyn Fn(&String) + 'a>,
+}
+
+impl<'a> Migration<'a> {
+    pub fn new(
+        from_version: DbVersion,
+        description: &'static str,
+        columns: &'static [DBCol],
+        run: impl Fn(&String) + 'a,
+    ) -> Self {
+        Self { from_version, description, columns, run: Box::new(run) }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum MigrationCheckpoint {
+    /// Snapshot of the columns is being made, `col` is copied up to `last_key`.
+    Snapshot { from_version: DbVersion, col: DBCol, last_key: Option<Vec<u8>> },
+    /// Snapshot is complete and the migration has started.
+    Migrate { from_version: DbVersion },
+}
+
+/// Work a pending migration is expected to do.
+#[derive(Debug)]
+pub struct MigrationEstimate {
+    pub from_version: DbVersion,
+    pub description: &'static str,
+    /// Estimated number of rows in each of the columns the migration changes.
+    pub num_rows: Vec<(DBCol, u64)>,
+}
+
+/// Logs the number of rows of the column processed so far.
+pub struct MigrationProgress {
+    description: String,
+    col: DBCol,
+    num_rows: u64,
+    total_rows: Option<u64>,
+    last_report: Instant,
+}
+
+impl MigrationProgress {
+    pub fn new(store: &Store, description: &str, col: DBCol) -> Self {
+        let total_rows = store
+            .get_rocksdb()
+            .and_then(|db| db.estimate_num_keys(col).ok().flatten())
+            .filter(|total_rows| *total_rows > 0);
+        Self {
+            description: description.to_string(),
+            col,
+            num_rows: 0,
+            total_rows,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn inc(&mut self) {
+        self.num_rows += 1;
+        if self.last_report.elapsed() >= PROGRESS_REPORT_PERIOD {
+            self.report();
+            self.last_report = Instant::now();
+        }
+    }
+
+    pub fn finish(self) {
+        self.report();
+    }
+
+    fn report(&self) {
+        match self.total_rows {
+            Some(total_rows) => {
+                info!(target: "near", "{}: {:?} {} rows processed of about {}", self.description, self.col, self.num_rows, total_rows)
+            }
+            None => {
+                info!(target: "near", "{}: {:?} {} rows processed", self.description, self.col, self.num_rows)
+            }
+        }
+    }
+}
+
+fn snapshot_path(path: &str) -> PathBuf {
+    Path::new(path).with_file_name(SNAPSHOT_DIR)
+}
+
+fn get_checkpoint(store: &Store) -> Option<MigrationCheckpoint> {
+    store
+        .get_ser(DBCol::ColDbVersion, MIGRATION_CHECKPOINT_KEY)
+        .expect("Failed to read migration checkpoint")
+}
+
+fn set_checkpoint(store: &Store, checkpoint: &MigrationCheckpoint) {
+    let mut store_update = store.store_update();
+    store_update
+        .set_ser(DBCol::ColDbVersion, MIGRATION_CHECKPOINT_KEY, checkpoint)
+        .expect("Borsh serialization should not fail");
+    store_update.commit().expect("Failed to write migration checkpoint");
+}
+
+fn remove_checkpoint_and_snapshot(store: &Store, snapshot_path: &Path) {
+    if snapshot_path.exists() {
+        std::fs::remove_dir_all(snapshot_path).expect("Failed to remove migration snapshot");
+    }
+    let mut store_update = store.store_update();
+    store_update.delete(DBCol::ColDbVersion, MIGRATION_CHECKPOINT_KEY);
+    store_update.commit().expect("Failed to remove migration checkpoint");
+}
+
+/// Copies the column in raw form, refcounts included, starting after `last_key`.
+/// `on_batch` is called with the last copied key after every written batch.
+fn copy_col(
+    from: &Store,
+    to: &Store,
+    col: DBCol,
+    last_key: Option<Vec<u8>>,
+    description: &str,
+    mut on_batch: impl FnMut(&[u8]),
+) {
+    let mut progress = MigrationProgress::new(from, description, col);
+    let key_from = last_key.clone().unwrap_or_default();
+    let mut store_update = to.store_update();
+    let mut batch_size = 0;
+    for (key, value) in from.iter_from_without_rc_logic(col, &key_from) {
+        if last_key.as_ref().map_or(false, |last_key| &key[..] == &last_key[..]) {
+            continue;
+        }
+        batch_size += key.len() + value.len() + 8;
+        store_update.set(col, &key, &value);
+        progress.inc();
+        if batch_size > BATCH_SIZE_LIMIT {
+            store_update.commit().expect("Failed to write migration snapshot");
+            store_update = to.store_update();
+            batch_size = 0;
+            on_batch(&key);
+        }
+    }
+    store_update.commit().expect("Failed to write migration snapshot");
+    progress.finish();
+}
+
+fn make_snapshot(
+    store: &Store,
+    snapshot_path: &str,
+    migration: &Migration,
+    start_col: DBCol,
+    mut last_key: Option<Vec<u8>>,
+) {
+    let snapshot = create_store(snapshot_path);
+    let from_version = migration.from_version;
+    let description = format!("Snapshot before migration from version {}", from_version);
+    for col in migration.columns.iter().skip_while(|col| **col != start_col) {
+        let col = *col;
+        set_checkpoint(
+            store,
+            &MigrationCheckpoint::Snapshot { from_version, col, last_key: last_key.clone() },
+        );
+        copy_col(store, &snapshot, col, last_key.take(), &description, |key| {
+            set_checkpoint(
+                store,
+                &MigrationCheckpoint::Snapshot { from_version, col, last_key: Some(key.to_vec()) },
+            )
+        });
+    }
+}
+
+fn restore_snapshot(store: &Store, snapshot_path: &str, migration: &Migration) {
+    if !migration.columns.is_empty() {
+        let snapshot = create_store(snapshot_path);
+        let description = format!("Reverting migration from version {}", migration.from_version);
+        for col in migration.columns {
+            let mut store_update = store.store_update();
+            store_update.delete_all(*col);
+            store_update.commit().expect("Failed to revert migration");
+            copy_col(&snapshot, store, *col, None, &description, |_| {});
+        }
+    }
+    set_store_version(store, migration.from_version);
+}
+
+fn apply_migration(path: &String, migration: &Migration) {
+    let from_version = migration.from_version;
+    info!(target: "near", "Migrate DB from version {} to {}: {}", from_version, from_version + 1, migration.description);
+    if from_version < MIN_SNAPSHOT_VERSION {
+        (migration.run)(path);
+        set_store_version(&create_store(path), from_version + 1);
+        return;
+    }
+
+    let snapshot_path = snapshot_path(path);
+    let snapshot_path_str = snapshot_path.to_str().expect("Snapshot path should be valid unicode");
+    {
+        let store = create_store(path);
+        match get_checkpoint(&store) {
+            Some(MigrationCheckpoint::Migrate { from_version: version })
+                if version == from_version =>
+            {
+                warn!(target: "near", "Migration from version {} was interrupted, reverting it", from_version);
+                restore_snapshot(&store, snapshot_path_str, migration);
+            }
+            Some(MigrationCheckpoint::Snapshot { from_version: version, col, last_key })
+                if version == from_version =>
+            {
+                info!(target: "near", "Resuming snapshot before migration from version {}", from_version);
+                make_snapshot(&store, snapshot_path_str, migration, col, last_key);
+            }
+            checkpoint => {
+                // Left from a migration which has completed or has been reverted
+                if checkpoint.is_some() || snapshot_path.exists() {
+                    remove_checkpoint_and_snapshot(&store, &snapshot_path);
+                }
+                if let Some(col) = migration.columns.first() {
+                    make_snapshot(&store, snapshot_path_str, migration, *col, None);
+                }
+            }
+        }
+        set_checkpoint(&store, &MigrationCheckpoint::Migrate { from_version });
+    }
+
+    // Migrations open the database themselves, so it must be closed here
+    let result = panic::catch_unwind(AssertUnwindSafe(|| (migration.run)(path)));
+
+    let store = create_store(path);
+    match result {
+        Ok(()) => {
+            set_store_version(&store, from_version + 1);
+            remove_checkpoint_and_snapshot(&store, &snapshot_path);
+        }
+        Err(err) => {
+            error!(target: "near", "Migration from version {} failed, reverting it", from_version);
+            restore_snapshot(&store, snapshot_path_str, migration);
+            remove_checkpoint_and_snapshot(&store, &snapshot_path);
+            panic::resume_unwind(err);
+        }
+    }
+}
+
+/// Applies the migrations from the current version of the database in order.
+pub fn apply_migrations(path: &String, migrations: &[Migration]) {
+    let db_version = get_store_version(path);
+    for migration in migrations.iter().filter(|migration| migration.from_version >= db_version) {
+        apply_migration(path, migration);
+    }
+}
+
+/// Estimates the work the migrations from the current version of the database have to do,
+/// without changing the database.
+pub fn estimate_migrations(
+    path: &String,
+    migrations: &[Migration],
+) -> Result<Vec<MigrationEstimate>, DBError> {
+    let db_version = get_store_version(path);
+    let existing_cf_names = DB::list_cf(&Options::default(), path)?;
+    // Columns are added at the end, so the ones which exist come first
+    let cf_names: Vec<_> = DBCol::iter()
+        .map(|col| format!("col{}", col as usize))
+        .take_while(|cf_name| existing_cf_names.contains(cf_name))
+        .collect();
+    let num_cfs = cf_names.len();
+    let db: RocksDB = RocksDBOptions::default().cf_names(cf_names).read_only(path)?;
+    let mut estimates = vec![];
+    for migration in migrations.iter().filter(|migration| migration.from_version >= db_version) {
+        let mut num_rows = vec![];
+        for col in migration.columns {
+            let col_num_rows = if (*col as usize) < num_cfs {
+                db.estimate_num_keys(*col)?.unwrap_or_default()
+            } else {
+                0
+            };
+            num_rows.push((*col, col_num_rows));
+        }
+        estimates.push(MigrationEstimate {
+            from_version: migration.from_version,
+            description: migration.description,
+            num_rows,
+        });
+    }
+    Ok(estimates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_store(path: &String) {
+        let store = create_store(path);
+        set_store_version(&store, 10);
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::ColBlockMisc, b"key", b"value");
+        store_update.commit().unwrap();
+    }
+
+    fn set_value(path: &String) {
+        let store = create_store(path);
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::ColBlockMisc, b"key", b"new value");
+        store_update.commit().unwrap();
+    }
+
+    #[test]
+    fn test_apply_migration() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_apply_migration").tempdir().unwrap();
+        let path = tmp_dir.path().join("data").to_str().unwrap().to_string();
+        init_store(&path);
+        let migrations = vec![Migration::new(10, "set value", &[DBCol::ColBlockMisc], set_value)];
+        apply_migrations(&path, &migrations);
+
+        assert_eq!(get_store_version(&path), 11);
+        let store = create_store(&path);
+        assert_eq!(store.get(DBCol::ColBlockMisc, b"key").unwrap(), Some(b"new value".to_vec()));
+        assert_eq!(get_checkpoint(&store), None);
+        assert!(!snapshot_path(&path).exists());
+    }
+
+    #[test]
+    fn test_revert_failed_migration() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_revert_migration").tempdir().unwrap();
+        let path = tmp_dir.path().join("data").to_str().unwrap().to_string();
+        init_store(&path);
+        let migrations = vec![Migration::new(10, "fail", &[DBCol::ColBlockMisc], |path| {
+            set_value(path);
+            panic!("migration failed");
+        })];
+        assert!(
+            panic::catch_unwind(AssertUnwindSafe(|| apply_migrations(&path, &migrations))).is_err()
+        );
+
+        assert_eq!(get_store_version(&path), 10);
+        let store = create_store(&path);
+        assert_eq!(store.get(DBCol::ColBlockMisc, b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(get_checkpoint(&store), None);
+        assert!(!snapshot_path(&path).exists());
+    }
+
+    #[test]
+    fn test_resume_interrupted_migration() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_resume_migration").tempdir().unwrap();
+        let path = tmp_dir.path().join("data").to_str().unwrap().to_string();
+        init_store(&path);
+        let migration = Migration::new(10, "set value", &[DBCol::ColBlockMisc], set_value);
+        {
+            // Migration was interrupted after it changed the value
+            let store = create_store(&path);
+            make_snapshot(
+                &store,
+                snapshot_path(&path).to_str().unwrap(),
+                &migration,
+                DBCol::ColBlockMisc,
+                None,
+            );
+            set_checkpoint(&store, &MigrationCheckpoint::Migrate { from_version: 10 });
+        }
+        set_value(&path);
+        let migrations =
+            vec![Migration::new(10, "check reverted", &[DBCol::ColBlockMisc], |path| {
+                let store = create_store(path);
+                assert_eq!(
+                    store.get(DBCol::ColBlockMisc, b"key").unwrap(),
+                    Some(b"value".to_vec())
+                );
+            })];
+        apply_migrations(&path, &migrations);
+
+        assert_eq!(get_store_version(&path), 11);
+        assert_eq!(get_checkpoint(&create_store(&path)), None);
+    }
+}
@@ -0,0 +1,654 @@
+//! Flat state: the values of the trie keys of a shard stored directly in `ColFlatState`, so that
+//! reading a key takes a single DB lookup instead of a walk over the trie nodes.
+//!
+//! `ColFlatState` holds the state of a shard at the flat storage head, which is a final block.
+//! The changes made by the blocks after the head, which may still be reverted, are kept as
+//! deltas, in `ColFlatStateDeltas` and in memory. A read at some state root finds the block after
+//! which the shard has that root, walks the deltas back to the head and then reads the column.
+//! If the state root is unknown or the deltas don't connect it to the head, the read is left to
+//! the trie, which remains the source of truth for state roots and proofs.
+//!
+//! The flat storage of a shard is created from the trie in the background. The deltas of the
+//! blocks applied meanwhile are recorded from the start of the creation, so that they connect the
+//! head the creation ends at to the blocks applied after it.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::sync::{Arc, RwLock};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use tracing::debug;
+
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{BlockHeight, RawStateChangesWithTrieKey, StateRoot};
+
+use crate::db::DBCol;
+use crate::trie::POISONED_LOCK_ERR;
+use crate::{StorageError, Store, StoreUpdate};
+
+/// Length and hash of a value stored in `ColState`, as kept in the trie leaves.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueRef {
+    pub length: u32,
+    pub hash: CryptoHash,
+}
+
+impl ValueRef {
+    pub fn new(value: &[u8]) -> Self {
+        ValueRef { length: value.len() as u32, hash: hash(value) }
+    }
+}
+
+/// Changes made to the flat state of a shard by a block after the flat storage head.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FlatStateDelta {
+    pub prev_block_hash: CryptoHash,
+    pub height: BlockHeight,
+    /// State root of the shard after the block.
+    pub new_root: StateRoot,
+    /// New values of the changed trie keys, `None` for the removed keys.
+    pub changes: BTreeMap<Vec<u8>, Option<ValueRef>>,
+}
+
+impl FlatStateDelta {
+    pub fn from_state_changes(
+        prev_block_hash: CryptoHash,
+        height: BlockHeight,
+        new_root: StateRoot,
+        state_changes: &[RawStateChangesWithTrieKey],
+    ) -> Self {
+        let changes = state_changes
+            .iter()
+            .map(|changes_with_trie_key| {
+                let data = &changes_with_trie_key
+                    .changes
+                    .last()
+                    .expect("Committed entry should have at least one change")
+                    .data;
+                (changes_with_trie_key.trie_key.to_vec(), data.as_deref().map(ValueRef::new))
+            })
+            .collect();
+        FlatStateDelta { prev_block_hash, height, new_root, changes }
+    }
+}
+
+/// Final block the values in `ColFlatState` of a shard correspond to.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlatStorageHead {
+    pub block_hash: CryptoHash,
+    pub height: BlockHeight,
+    pub state_root: StateRoot,
+}
+
+pub(crate) fn flat_state_key(shard_uid: &ShardUId, trie_key: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(8 + trie_key.len());
+    res.extend_from_slice(&shard_uid.to_bytes());
+    res.extend_from_slice(trie_key);
+    res
+}
+
+pub(crate) fn flat_state_delta_key(shard_uid: &ShardUId, block_hash: &CryptoHash) -> Vec<u8> {
+    let mut res = Vec::with_capacity(40);
+    res.extend_from_slice(&shard_uid.to_bytes());
+    res.extend_from_slice(block_hash.as_ref());
+    res
+}
+
+fn parse_shard_uid(bytes: &[u8]) -> Result<ShardUId, StorageError> {
+    if bytes.len() < 8 {
+        return Err(StorageError::StorageInconsistentState(
+            "Flat storage key is too short".to_string(),
+        ));
+    }
+    let mut shard_id = [0u8; 4];
+    shard_id.copy_from_slice(&bytes[0..4]);
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&bytes[4..8]);
+    Ok(ShardUId { version: u32::from_le_bytes(version), shard_id: u32::from_le_bytes(shard_id) })
+}
+
+pub(crate) fn parse_flat_state_delta_key(
+    key: &[u8],
+) -> Result<(ShardUId, CryptoHash), StorageError> {
+    let shard_uid = parse_shard_uid(key)?;
+    let block_hash = CryptoHash::try_from(&key[8..]).map_err(|_| {
+        StorageError::StorageInconsistentState("Invalid flat state delta key".to_string())
+    })?;
+    Ok((shard_uid, block_hash))
+}
+
+fn decode<T: BorshDeserialize>(bytes: &[u8]) -> Result<T, StorageError> {
+    T::try_from_slice(bytes).map_err(|err| {
+        StorageError::StorageInconsistentState(format!(
+            "Failed to decode flat storage data: {}",
+            err
+        ))
+    })
+}
+
+struct FlatStorage {
+    head: FlatStorageHead,
+    deltas: HashMap<CryptoHash, FlatStateDelta>,
+}
+
+/// In-memory view of the flat storage heads and deltas of all shards.
+#[derive(Default)]
+pub(crate) struct FlatStorages {
+    shards: HashMap<ShardUId, FlatStorage>,
+    /// Shard and block after which the shard has the given state root, for the heads and the
+    /// deltas. Any such block serves, as the state is determined by its root.
+    roots: HashMap<StateRoot, (ShardUId, CryptoHash)>,
+    /// Deltas recorded for the shards whose flat storage is being created.
+    creating: HashMap<ShardUId, HashMap<CryptoHash, FlatStateDelta>>,
+}
+
+impl FlatStorages {
+    pub(crate) fn load(store: &Store) -> Result<Self, StorageError> {
+        let mut flat_storages = FlatStorages::default();
+        for (key, value) in store.iter(DBCol::ColFlatStateMisc) {
+            let shard_uid = parse_shard_uid(&key)?;
+            flat_storages.set_head(shard_uid, decode(&value)?);
+        }
+        for (key, value) in store.iter(DBCol::ColFlatStateDeltas) {
+            let (shard_uid, block_hash) = parse_flat_state_delta_key(&key)?;
+            flat_storages.add_delta(shard_uid, block_hash, decode(&value)?);
+        }
+        Ok(flat_storages)
+    }
+
+    /// Whether the deltas of the shard are recorded, i.e. it has a flat storage or it is being
+    /// created.
+    pub(crate) fn records_deltas(&self, shard_uid: &ShardUId) -> bool {
+        self.shards.contains_key(shard_uid) || self.creating.contains_key(shard_uid)
+    }
+
+    pub(crate) fn is_creating(&self, shard_uid: &ShardUId) -> bool {
+        self.creating.contains_key(shard_uid)
+    }
+
+    /// Drops the flat storage of the shard, if any, and starts recording its deltas.
+    pub(crate) fn start_creation(&mut self, shard_uid: ShardUId) {
+        self.remove_shard(&shard_uid);
+        self.creating.insert(shard_uid, HashMap::new());
+    }
+
+    pub(crate) fn stop_creation(&mut self, shard_uid: &ShardUId) {
+        self.creating.remove(shard_uid);
+    }
+
+    /// Sets the head of the shard whose flat storage has been created, together with the deltas
+    /// recorded above it. Returns the blocks of the recorded deltas at or below the head, which
+    /// are not needed anymore.
+    pub(crate) fn finish_creation(
+        &mut self,
+        shard_uid: ShardUId,
+        head: FlatStorageHead,
+    ) -> Vec<CryptoHash> {
+        let deltas = self.creating.remove(&shard_uid).unwrap_or_default();
+        self.set_head(shard_uid, head);
+        let mut pruned_blocks = vec![];
+        for (block_hash, delta) in deltas {
+            if delta.height > head.height {
+                self.add_delta(shard_uid, block_hash, delta);
+            } else {
+                pruned_blocks.push(block_hash);
+            }
+        }
+        pruned_blocks
+    }
+
+    pub(crate) fn head(&self, shard_uid: &ShardUId) -> Option<FlatStorageHead> {
+        self.shards.get(shard_uid).map(|flat_storage| flat_storage.head)
+    }
+
+    pub(crate) fn set_head(&mut self, shard_uid: ShardUId, head: FlatStorageHead) {
+        let flat_storage = self
+            .shards
+            .entry(shard_uid)
+            .or_insert_with(|| FlatStorage { head, deltas: HashMap::new() });
+        let old_head = std::mem::replace(&mut flat_storage.head, head);
+        if self.roots.get(&old_head.state_root) == Some(&(shard_uid, old_head.block_hash)) {
+            self.roots.remove(&old_head.state_root);
+        }
+        self.roots.insert(head.state_root, (shard_uid, head.block_hash));
+    }
+
+    /// Deltas of the shards without flat storage are ignored, unless it is being created.
+    pub(crate) fn add_delta(
+        &mut self,
+        shard_uid: ShardUId,
+        block_hash: CryptoHash,
+        delta: FlatStateDelta,
+    ) {
+        if let Some(flat_storage) = self.shards.get_mut(&shard_uid) {
+            self.roots.insert(delta.new_root, (shard_uid, block_hash));
+            flat_storage.deltas.insert(block_hash, delta);
+        } else if let Some(deltas) = self.creating.get_mut(&shard_uid) {
+            deltas.insert(block_hash, delta);
+        }
+    }
+
+    pub(crate) fn remove_delta(&mut self, shard_uid: &ShardUId, block_hash: &CryptoHash) {
+        if let Some(flat_storage) = self.shards.get_mut(shard_uid) {
+            if let Some(delta) = flat_storage.deltas.remove(block_hash) {
+                if self.roots.get(&delta.new_root) == Some(&(*shard_uid, *block_hash)) {
+                    self.roots.remove(&delta.new_root);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove_shard(&mut self, shard_uid: &ShardUId) {
+        self.shards.remove(shard_uid);
+        self.roots.retain(|_, (root_shard_uid, _)| root_shard_uid != shard_uid);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.shards.clear();
+        self.roots.clear();
+        self.creating.clear();
+    }
+
+    /// Blocks from `block_hash` back to the flat storage head, excluding the head, or `None` if
+    /// the deltas don't connect the block to the head.
+    fn blocks_to_head(
+        &self,
+        shard_uid: &ShardUId,
+        block_hash: &CryptoHash,
+    ) -> Option<Vec<CryptoHash>> {
+        let flat_storage = self.shards.get(shard_uid)?;
+        let mut blocks = vec![];
+        let mut block_hash = *block_hash;
+        while block_hash != flat_storage.head.block_hash {
+            let delta = flat_storage.deltas.get(&block_hash)?;
+            if delta.height <= flat_storage.head.height {
+                return None;
+            }
+            blocks.push(block_hash);
+            block_hash = delta.prev_block_hash;
+        }
+        Some(blocks)
+    }
+
+    /// Value of `key` at `state_root`, or `None` if the flat storage can't serve it.
+    fn get_ref(
+        &self,
+        store: &Store,
+        state_root: &StateRoot,
+        key: &[u8],
+    ) -> Result<Option<Option<ValueRef>>, StorageError> {
+        let (shard_uid, block_hash) = match self.roots.get(state_root) {
+            Some(shard_and_block) => *shard_and_block,
+            None => return Ok(None),
+        };
+        let blocks = match self.blocks_to_head(&shard_uid, &block_hash) {
+            Some(blocks) => blocks,
+            None => return Ok(None),
+        };
+        let deltas = &self.shards[&shard_uid].deltas;
+        for block_hash in blocks.iter() {
+            if let Some(value_ref) = deltas[block_hash].changes.get(key) {
+                return Ok(Some(*value_ref));
+            }
+        }
+        let value_ref = store
+            .get_ser(DBCol::ColFlatState, &flat_state_key(&shard_uid, key))
+            .map_err(|_| StorageError::StorageInternalError)?;
+        Ok(Some(value_ref))
+    }
+
+    /// Moves the flat storage head of every shard whose deltas reach `block_hash` to that block,
+    /// applying the deltas in between to `ColFlatState` and pruning the deltas at or below the
+    /// new head. Returns the new heads to set once the update is committed.
+    pub(crate) fn move_heads_update(
+        &self,
+        block_hash: &CryptoHash,
+        store_update: &mut StoreUpdate,
+    ) -> Result<Vec<(ShardUId, FlatStorageHead, Vec<CryptoHash>)>, StorageError> {
+        let mut new_heads = vec![];
+        for (shard_uid, flat_storage) in self.shards.iter() {
+            if flat_storage.head.block_hash == *block_hash {
+                continue;
+            }
+            let blocks = match self.blocks_to_head(shard_uid, block_hash) {
+                Some(blocks) => blocks,
+                None => {
+                    debug!(target: "store", "Flat storage head of shard {:?} can't move to {}", shard_uid, block_hash);
+                    continue;
+                }
+            };
+            let mut changes = BTreeMap::new();
+            for block_hash in blocks.iter().rev() {
+                for (key, value_ref) in flat_storage.deltas[block_hash].changes.iter() {
+                    changes.insert(key, *value_ref);
+                }
+            }
+            for (key, value_ref) in changes {
+                let key = flat_state_key(shard_uid, key);
+                match value_ref {
+                    Some(value_ref) => store_update
+                        .set_ser(DBCol::ColFlatState, &key, &value_ref)
+                        .map_err(|_| StorageError::StorageInternalError)?,
+                    None => store_update.delete(DBCol::ColFlatState, &key),
+                }
+            }
+            let delta = &flat_storage.deltas[block_hash];
+            let new_head = FlatStorageHead {
+                block_hash: *block_hash,
+                height: delta.height,
+                state_root: delta.new_root,
+            };
+            store_update
+                .set_ser(DBCol::ColFlatStateMisc, &shard_uid.to_bytes(), &new_head)
+                .map_err(|_| StorageError::StorageInternalError)?;
+            let pruned_blocks: Vec<_> = flat_storage
+                .deltas
+                .iter()
+                .filter(|(_, delta)| delta.height <= new_head.height)
+                .map(|(block_hash, _)| *block_hash)
+                .collect();
+            for block_hash in pruned_blocks.iter() {
+                store_update.delete(
+                    DBCol::ColFlatStateDeltas,
+                    &flat_state_delta_key(shard_uid, block_hash),
+                );
+            }
+            new_heads.push((*shard_uid, new_head, pruned_blocks));
+        }
+        Ok(new_heads)
+    }
+}
+
+/// Handle for reading the flat state of all shards.
+#[derive(Clone)]
+pub struct FlatState {
+    pub(crate) store: Arc<Store>,
+    pub(crate) flat_storages: Arc<RwLock<FlatStorages>>,
+}
+
+impl FlatState {
+    /// Value of `key` at `state_root`, or `None` if the read has to be served by the trie.
+    pub fn get_ref(
+        &self,
+        state_root: &StateRoot,
+        key: &[u8],
+    ) -> Result<Option<Option<ValueRef>>, StorageError> {
+        // The lock is held during the DB read, so the flat storage heads can't move under it.
+        let flat_storages = self.flat_storages.read().expect(POISONED_LOCK_ERR);
+        flat_storages.get_ref(&self.store, state_root, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::{
+        AccountId, BlockHeight, RawStateChange, RawStateChangesWithTrieKey, ShardId,
+        StateChangeCause, StateRoot,
+    };
+
+    use crate::test_utils::{create_test_store, create_tries};
+    use crate::{
+        DBCol, ShardTries, StateChangesForSplitStates, StoreUpdate, Trie, WrappedTrieChanges,
+    };
+
+    use super::{flat_state_delta_key, ValueRef};
+
+    fn key(name: &str) -> TrieKey {
+        account_key("alice", name)
+    }
+
+    fn account_key(account_id: &str, name: &str) -> TrieKey {
+        TrieKey::ContractData {
+            account_id: account_id.parse().unwrap(),
+            key: name.as_bytes().to_vec(),
+        }
+    }
+
+    fn apply_block(
+        tries: &ShardTries,
+        root: StateRoot,
+        prev_block_hash: CryptoHash,
+        block_hash: CryptoHash,
+        height: BlockHeight,
+        changes: Vec<(TrieKey, Option<&str>)>,
+    ) -> StateRoot {
+        let mut trie_update = tries.new_trie_update(0, root);
+        for (key, value) in changes {
+            match value {
+                Some(value) => trie_update.set(key, value.as_bytes().to_vec()),
+                None => trie_update.remove(key),
+            }
+        }
+        trie_update.commit(StateChangeCause::InitialState);
+        let (trie_changes, state_changes) = trie_update.finalize().unwrap();
+        let mut wrapped_trie_changes = WrappedTrieChanges::new(
+            tries.clone(),
+            ShardUId::single_shard(),
+            trie_changes,
+            state_changes,
+            block_hash,
+        );
+        let mut store_update = StoreUpdate::new_with_tries(tries.clone());
+        wrapped_trie_changes
+            .flat_state_delta_into(prev_block_hash, height, &mut store_update)
+            .unwrap();
+        wrapped_trie_changes.wrapped_into(height, &mut store_update).unwrap();
+        store_update.commit().unwrap();
+        wrapped_trie_changes.new_root()
+    }
+
+    fn check_reads(tries: &ShardTries, root: &StateRoot, keys: &[TrieKey]) {
+        check_shard_reads(tries, 0, root, keys);
+    }
+
+    fn check_shard_reads(
+        tries: &ShardTries,
+        shard_id: ShardId,
+        root: &StateRoot,
+        keys: &[TrieKey],
+    ) {
+        let flat_state = tries.get_flat_state();
+        let trie = tries.get_trie_for_shard(shard_id);
+        for key in keys {
+            let expected = trie
+                .get_ref(root, &key.to_vec())
+                .unwrap()
+                .map(|(length, hash)| ValueRef { length, hash });
+            assert_eq!(flat_state.get_ref(root, &key.to_vec()).unwrap(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_flat_state_reads_follow_deltas() {
+        let tries = create_tries();
+        let keys = vec![key("a"), key("b"), key("c")];
+        let genesis_hash = hash(&[0]);
+        let root0 = apply_block(
+            &tries,
+            Trie::empty_root(),
+            CryptoHash::default(),
+            genesis_hash,
+            0,
+            vec![(key("a"), Some("a0")), (key("b"), Some("b0"))],
+        );
+        tries.create_flat_storage(ShardUId::single_shard(), genesis_hash, 0, root0).unwrap();
+        check_reads(&tries, &root0, &keys);
+
+        let block1 = hash(&[1]);
+        let root1 = apply_block(
+            &tries,
+            root0,
+            genesis_hash,
+            block1,
+            1,
+            vec![(key("a"), Some("a1")), (key("b"), None)],
+        );
+        let fork1 = hash(&[2]);
+        let fork_root1 =
+            apply_block(&tries, root0, genesis_hash, fork1, 1, vec![(key("c"), Some("c1"))]);
+        let block2 = hash(&[3]);
+        let root2 = apply_block(&tries, root1, block1, block2, 2, vec![(key("b"), Some("b2"))]);
+        for root in [root0, root1, fork_root1, root2].iter() {
+            check_reads(&tries, root, &keys);
+        }
+        assert_eq!(tries.get_flat_state().get_ref(&hash(&[4]), &key("a").to_vec()).unwrap(), None);
+
+        tries.move_flat_storage_heads(&block1).unwrap();
+        assert_eq!(
+            tries.get_flat_storage_head(&ShardUId::single_shard()).unwrap().block_hash,
+            block1
+        );
+        for root in [root1, root2].iter() {
+            check_reads(&tries, root, &keys);
+        }
+        // The fork is pruned, so its reads are left to the trie.
+        assert_eq!(tries.get_flat_state().get_ref(&fork_root1, &key("c").to_vec()).unwrap(), None);
+
+        // The heads and the deltas are loaded back from the DB.
+        let tries = ShardTries::new(tries.get_store(), 1);
+        for root in [root1, root2].iter() {
+            check_reads(&tries, root, &keys);
+        }
+    }
+
+    #[test]
+    fn test_flat_state_reads_do_not_touch_trie_nodes() {
+        let tries = create_tries();
+        let genesis_hash = hash(&[0]);
+        let root = apply_block(
+            &tries,
+            Trie::empty_root(),
+            CryptoHash::default(),
+            genesis_hash,
+            0,
+            vec![(key("a"), Some("a0")), (key("b"), Some("b0"))],
+        );
+        let trie = tries.get_trie_for_shard(0);
+        trie.get(&root, &key("a").to_vec()).unwrap();
+        assert!(trie.counter.get() > 1);
+
+        // The lookups are not counted with or without the flat storage, only the value is.
+        for create_flat_storage in [false, true].iter() {
+            if *create_flat_storage {
+                tries.create_flat_storage(ShardUId::single_shard(), genesis_hash, 0, root).unwrap();
+            }
+            let trie = tries.get_trie_for_shard(0).with_flat_state_reads(tries.get_flat_state());
+            assert_eq!(trie.get(&root, &key("a").to_vec()).unwrap(), Some(b"a0".to_vec()));
+            assert_eq!(trie.counter.get(), 1);
+        }
+    }
+
+    #[test]
+    fn test_flat_storage_created_while_blocks_are_applied() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let keys = vec![key("a"), key("b")];
+        let genesis_hash = hash(&[0]);
+        let root0 = apply_block(
+            &tries,
+            Trie::empty_root(),
+            CryptoHash::default(),
+            genesis_hash,
+            0,
+            vec![(key("a"), Some("a0")), (key("b"), Some("b0"))],
+        );
+        tries.start_flat_storage_creation(shard_uid).unwrap();
+        let block1 = hash(&[1]);
+        let root1 =
+            apply_block(&tries, root0, genesis_hash, block1, 1, vec![(key("a"), Some("a1"))]);
+        let block2 = hash(&[2]);
+        let root2 = apply_block(&tries, root1, block1, block2, 2, vec![(key("b"), None)]);
+        // The reads are left to the trie until the flat storage is created.
+        assert_eq!(tries.get_flat_state().get_ref(&root2, &key("a").to_vec()).unwrap(), None);
+
+        // The flat storage is created at a block older than the last applied one.
+        tries.create_flat_storage(shard_uid, block1, 1, root1).unwrap();
+        for root in [root1, root2].iter() {
+            check_reads(&tries, root, &keys);
+        }
+        let store = tries.get_store();
+        assert!(store
+            .get(DBCol::ColFlatStateDeltas, &flat_state_delta_key(&shard_uid, &block1))
+            .unwrap()
+            .is_none());
+
+        let tries = ShardTries::new(store, 1);
+        check_reads(&tries, &root2, &keys);
+    }
+
+    #[test]
+    fn test_flat_storage_of_split_shard() {
+        let account_id_to_shard_id = |account_id: &AccountId| {
+            let account_id: &str = account_id.as_ref();
+            ShardUId { version: 1, shard_id: if account_id < "m" { 0 } else { 1 } }
+        };
+        let left = ShardUId { version: 1, shard_id: 0 };
+        let right = ShardUId { version: 1, shard_id: 1 };
+        let tries = ShardTries::new(create_test_store(), 2);
+        let empty_roots: HashMap<_, _> =
+            [left, right].iter().map(|shard_uid| (*shard_uid, Trie::empty_root())).collect();
+        let values = vec![
+            (key("a").to_vec(), b"a0".to_vec()),
+            (account_key("zoe", "z").to_vec(), b"z0".to_vec()),
+        ];
+        let (store_update, roots) = tries
+            .add_values_to_split_states(&empty_roots, values, &account_id_to_shard_id)
+            .unwrap();
+        store_update.commit().unwrap();
+        let block0 = hash(&[0]);
+        tries.create_flat_storage(left, block0, 0, roots[&left]).unwrap();
+
+        // The changes of the parent shard are applied to the split shards.
+        let change = |trie_key: TrieKey, data: Option<&str>| RawStateChangesWithTrieKey {
+            trie_key,
+            changes: vec![RawStateChange {
+                cause: StateChangeCause::InitialState,
+                data: data.map(|data| data.as_bytes().to_vec()),
+            }],
+        };
+        let changes = StateChangesForSplitStates {
+            changes: vec![
+                change(key("a"), Some("a1")),
+                change(key("b"), Some("b1")),
+                change(account_key("zoe", "z"), None),
+            ],
+            processed_delayed_receipts: vec![],
+        };
+        let block1 = hash(&[1]);
+        let mut new_roots = HashMap::new();
+        for (shard_uid, (trie_changes, state_changes)) in tries
+            .apply_state_changes_to_split_states(&roots, changes, &account_id_to_shard_id)
+            .unwrap()
+        {
+            let mut wrapped_trie_changes = WrappedTrieChanges::new_for_split_state(
+                tries.clone(),
+                shard_uid,
+                trie_changes,
+                state_changes,
+                block1,
+            );
+            let mut store_update = StoreUpdate::new_with_tries(tries.clone());
+            wrapped_trie_changes.flat_state_delta_into(block0, 1, &mut store_update).unwrap();
+            wrapped_trie_changes.wrapped_into(1, &mut store_update).unwrap();
+            store_update.commit().unwrap();
+            new_roots.insert(shard_uid, wrapped_trie_changes.new_root());
+        }
+
+        check_shard_reads(&tries, 0, &new_roots[&left], &[key("a"), key("b")]);
+        // The shard without flat storage is read from the trie.
+        assert_eq!(
+            tries
+                .get_flat_state()
+                .get_ref(&new_roots[&right], &account_key("zoe", "z").to_vec())
+                .unwrap(),
+            None
+        );
+        // The state changes are saved only for the parent shard.
+        assert_eq!(tries.get_store().iter(DBCol::ColStateChanges).count(), 0);
+    }
+}
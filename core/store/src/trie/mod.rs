@@ -1,33 +1,39 @@
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::convert::TryFrom;
 use std::fmt;
-use std::io::{Cursor, Read, Write};
 use std::sync::Arc;
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use near_primitives::challenge::PartialState;
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::trie_node::{RawTrieNode, RawTrieNodeWithSize};
 use near_primitives::types::{ShardId, StateRoot, StateRootNode};
 
+pub use crate::trie::flat_state::{FlatState, FlatStateDelta, FlatStorageHead, ValueRef};
 use crate::trie::insert_delete::NodesStorage;
 use crate::trie::iterator::TrieIterator;
 use crate::trie::nibble_slice::NibbleSlice;
-pub use crate::trie::shard_tries::{KeyForStateChanges, ShardTries, WrappedTrieChanges};
+pub use crate::trie::shard_tries::{
+    KeyForStateChanges, KeyForStateChangesByKey, ShardTries, WrappedTrieChanges,
+};
+pub use crate::trie::split_state::{get_delayed_receipts, StateChangesForSplitStates};
+pub use crate::trie::trie_storage::TrieCacheConfig;
 use crate::trie::trie_storage::{
     TouchedNodesCounter, TrieMemoryPartialStorage, TrieRecordingStorage, TrieStorage,
 };
 pub(crate) use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::StorageError;
 
+mod flat_state;
 mod insert_delete;
 pub mod iterator;
 mod nibble_slice;
+mod prefetcher;
 mod shard_tries;
+mod split_state;
 mod state_parts;
 mod trie_storage;
 pub mod update;
@@ -251,162 +257,14 @@ impl TrieNode {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-#[allow(clippy::large_enum_variant)]
-enum RawTrieNode {
-    Leaf(Vec<u8>, u32, CryptoHash),
-    Branch([Option<CryptoHash>; 16], Option<(u32, CryptoHash)>),
-    Extension(Vec<u8>, CryptoHash),
-}
-
-/// Trie node + memory cost of its subtree
-/// memory_usage is serialized, stored, and contributes to hash
-#[derive(Debug, Eq, PartialEq)]
-struct RawTrieNodeWithSize {
-    node: RawTrieNode,
-    memory_usage: u64,
-}
-
-const LEAF_NODE: u8 = 0;
-const BRANCH_NODE_NO_VALUE: u8 = 1;
-const BRANCH_NODE_WITH_VALUE: u8 = 2;
-const EXTENSION_NODE: u8 = 3;
-
-fn decode_children(cursor: &mut Cursor<&[u8]>) -> Result<[Option<CryptoHash>; 16], std::io::Error> {
-    let mut children: [Option<CryptoHash>; 16] = Default::default();
-    let bitmap = cursor.read_u16::<LittleEndian>()?;
-    let mut pos = 1;
-    for child in &mut children {
-        if bitmap & pos != 0 {
-            let mut arr = [0; 32];
-            cursor.read_exact(&mut arr)?;
-            *child = Some(CryptoHash::try_from(&arr[..]).unwrap());
-        }
-        pos <<= 1;
-    }
-    Ok(children)
-}
-
-impl RawTrieNode {
-    fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
-        let mut cursor = Cursor::new(out);
-        // size in state_parts = size + 8 for RawTrieNodeWithSize + 8 for borsh vector length
-        match &self {
-            // size <= 1 + 4 + 4 + 32 + key_length + value_length
-            RawTrieNode::Leaf(key, value_length, value_hash) => {
-                cursor.write_u8(LEAF_NODE)?;
-                cursor.write_u32::<LittleEndian>(key.len() as u32)?;
-                cursor.write_all(&key)?;
-                cursor.write_u32::<LittleEndian>(*value_length)?;
-                cursor.write_all(value_hash.as_ref())?;
-            }
-            // size <= 1 + 4 + 32 + value_length + 2 + 32 * num_children
-            RawTrieNode::Branch(children, value) => {
-                if let Some((value_length, value_hash)) = value {
-                    cursor.write_u8(BRANCH_NODE_WITH_VALUE)?;
-                    cursor.write_u32::<LittleEndian>(*value_length)?;
-                    cursor.write_all(value_hash.as_ref())?;
-                } else {
-                    cursor.write_u8(BRANCH_NODE_NO_VALUE)?;
-                }
-                let mut bitmap: u16 = 0;
-                let mut pos: u16 = 1;
-                for child in children.iter() {
-                    if child.is_some() {
-                        bitmap |= pos
-                    }
-                    pos <<= 1;
-                }
-                cursor.write_u16::<LittleEndian>(bitmap)?;
-                for child in children.iter() {
-                    if let Some(hash) = child {
-                        cursor.write_all(hash.as_ref())?;
-                    }
-                }
-            }
-            // size <= 1 + 4 + key_length + 32
-            RawTrieNode::Extension(key, child) => {
-                cursor.write_u8(EXTENSION_NODE)?;
-                cursor.write_u32::<LittleEndian>(key.len() as u32)?;
-                cursor.write_all(&key)?;
-                cursor.write_all(child.as_ref())?;
-            }
-        }
-        Ok(())
-    }
-
-    #[allow(dead_code)]
-    fn encode(&self) -> Result<Vec<u8>, std::io::Error> {
-        let mut out = Vec::new();
-        self.encode_into(&mut out)?;
-        Ok(out)
-    }
-
-    fn decode(bytes: &[u8]) -> Result<Self, std::io::Error> {
-        let mut cursor = Cursor::new(bytes);
-        match cursor.read_u8()? {
-            LEAF_NODE => {
-                let key_length = cursor.read_u32::<LittleEndian>()?;
-                let mut key = vec![0; key_length as usize];
-                cursor.read_exact(&mut key)?;
-                let value_length = cursor.read_u32::<LittleEndian>()?;
-                let mut arr = [0; 32];
-                cursor.read_exact(&mut arr)?;
-                let value_hash = CryptoHash::try_from(&arr[..]).unwrap();
-                Ok(RawTrieNode::Leaf(key, value_length, value_hash))
-            }
-            BRANCH_NODE_NO_VALUE => {
-                let children = decode_children(&mut cursor)?;
-                Ok(RawTrieNode::Branch(children, None))
-            }
-            BRANCH_NODE_WITH_VALUE => {
-                let value_length = cursor.read_u32::<LittleEndian>()?;
-                let mut arr = [0; 32];
-                cursor.read_exact(&mut arr)?;
-                let value_hash = CryptoHash::try_from(&arr[..]).unwrap();
-                let children = decode_children(&mut cursor)?;
-                Ok(RawTrieNode::Branch(children, Some((value_length, value_hash))))
-            }
-            EXTENSION_NODE => {
-                let key_length = cursor.read_u32::<LittleEndian>()?;
-                let mut key = vec![0; key_length as usize];
-                cursor.read_exact(&mut key)?;
-                let mut child = vec![0; 32];
-                cursor.read_exact(&mut child)?;
-                Ok(RawTrieNode::Extension(key, CryptoHash::try_from(child).unwrap()))
-            }
-            _ => Err(std::io::Error::new(std::io::ErrorKind::Other, "Wrong type")),
-        }
-    }
-}
-
-impl RawTrieNodeWithSize {
-    fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
-        self.node.encode_into(out)?;
-        out.write_u64::<LittleEndian>(self.memory_usage)
-    }
-
-    #[allow(dead_code)]
-    fn encode(&self) -> Result<Vec<u8>, std::io::Error> {
-        let mut out = Vec::new();
-        self.encode_into(&mut out)?;
-        Ok(out)
-    }
-
-    fn decode(bytes: &[u8]) -> Result<Self, std::io::Error> {
-        if bytes.len() < 8 {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Wrong type"));
-        }
-        let node = RawTrieNode::decode(&bytes[0..bytes.len() - 8])?;
-        let mut arr: [u8; 8] = Default::default();
-        arr.copy_from_slice(&bytes[bytes.len() - 8..]);
-        let memory_usage = u64::from_le_bytes(arr);
-        Ok(RawTrieNodeWithSize { node, memory_usage })
-    }
-}
-
 pub struct Trie {
     pub(crate) storage: Box<dyn TrieStorage>,
+    /// Flat state serving the lookups, if any.
+    pub(crate) flat_state: Option<FlatState>,
+    /// Whether the protocol reads from the flat state. The nodes touched by lookups are not
+    /// counted then, even when a lookup falls back to the trie, so that the cost of a read
+    /// doesn't depend on whether the node has the flat state.
+    pub(crate) flat_state_reads: bool,
     pub counter: TouchedNodesCounter,
 }
 
@@ -471,7 +329,22 @@ pub struct ApplyStatePartResult {
 
 impl Trie {
     pub fn new(store: Box<dyn TrieStorage>, _shard_id: ShardId) -> Self {
-        Trie { storage: store, counter: TouchedNodesCounter::default() }
+        Trie {
+            storage: store,
+            flat_state: None,
+            flat_state_reads: false,
+            counter: TouchedNodesCounter::default(),
+        }
+    }
+
+    /// Makes the lookups read from `flat_state` when it has the requested state root. Tries
+    /// recording or replaying the reads for proofs keep walking the trie for all the lookups.
+    pub fn with_flat_state_reads(mut self, flat_state: FlatState) -> Self {
+        self.flat_state_reads = true;
+        if self.storage.as_caching_storage().is_some() {
+            self.flat_state = Some(flat_state);
+        }
+        self
     }
 
     pub fn recording_reads(&self) -> Self {
@@ -482,7 +355,12 @@ impl Trie {
             shard_id: storage.shard_id,
             recorded: RefCell::new(Default::default()),
         };
-        Trie { storage: Box::new(storage), counter: TouchedNodesCounter::default() }
+        Trie {
+            storage: Box::new(storage),
+            flat_state: None,
+            flat_state_reads: self.flat_state_reads,
+            counter: TouchedNodesCounter::default(),
+        }
     }
 
     pub fn empty_root() -> StateRoot {
@@ -505,6 +383,8 @@ impl Trie {
                 recorded_storage,
                 visited_nodes: Default::default(),
             }),
+            flat_state: None,
+            flat_state_reads: false,
             counter: TouchedNodesCounter::default(),
         }
     }
@@ -639,12 +519,17 @@ impl Trie {
         mut key: NibbleSlice<'_>,
     ) -> Result<Option<(u32, CryptoHash)>, StorageError> {
         let mut hash = *root;
+        let mut depth = 0;
 
         loop {
             if hash == Trie::empty_root() {
                 return Ok(None);
             }
-            let bytes = self.retrieve_raw_bytes(&hash)?;
+            if !self.flat_state_reads {
+                self.counter.increment();
+            }
+            let bytes = self.storage.retrieve_raw_node_bytes(&hash, depth)?;
+            depth += 1;
             let node = RawTrieNodeWithSize::decode(&bytes).map_err(|_| {
                 StorageError::StorageInconsistentState("RawTrieNode decode failed".to_string())
             })?;
@@ -693,6 +578,11 @@ impl Trie {
         root: &CryptoHash,
         key: &[u8],
     ) -> Result<Option<(u32, CryptoHash)>, StorageError> {
+        if let Some(flat_state) = &self.flat_state {
+            if let Some(value_ref) = flat_state.get_ref(root, key)? {
+                return Ok(value_ref.map(|value_ref| (value_ref.length, value_ref.hash)));
+            }
+        }
         let key = NibbleSlice::new(key);
         self.lookup(root, key)
     }
@@ -792,29 +682,6 @@ mod tests {
         root
     }
 
-    #[test]
-    fn test_encode_decode() {
-        let value = vec![123, 245, 255];
-        let value_length = 3;
-        let value_hash = hash(&value);
-        let node = RawTrieNode::Leaf(vec![1, 2, 3], value_length, value_hash);
-        let buf = node.encode().expect("Failed to serialize");
-        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
-        assert_eq!(node, new_node);
-
-        let mut children: [Option<CryptoHash>; 16] = Default::default();
-        children[3] = Some(CryptoHash::default());
-        let node = RawTrieNode::Branch(children, Some((value_length, value_hash)));
-        let buf = node.encode().expect("Failed to serialize");
-        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
-        assert_eq!(node, new_node);
-
-        let node = RawTrieNode::Extension(vec![123, 245, 255], CryptoHash::default());
-        let buf = node.encode().expect("Failed to serialize");
-        let new_node = RawTrieNode::decode(&buf).expect("Failed to deserialize");
-        assert_eq!(node, new_node);
-    }
-
     #[test]
     fn test_basic_trie() {
         let tries = create_tries();
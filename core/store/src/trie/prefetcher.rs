@@ -0,0 +1,137 @@
+//! Loads the trie nodes which the runtime is going to read into the trie cache in the
+//! background, so that applying a chunk doesn't wait for the disk on every account read.
+
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tracing::{debug, error};
+
+use near_primitives::receipt::Receipt;
+use near_primitives::transaction::{Action, SignedTransaction};
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{AccountId, ShardId, StateRoot};
+
+use crate::metrics;
+use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
+use crate::trie::POISONED_LOCK_ERR;
+use crate::{Store, Trie};
+
+/// Maximum number of requests waiting for the prefetcher threads. Requests over the limit are
+/// dropped, as prefetching them would likely finish after the chunk is applied.
+const PREFETCH_QUEUE_SIZE: usize = 1024;
+
+struct PrefetchRequest {
+    store: Arc<Store>,
+    cache: TrieCache,
+    shard_id: ShardId,
+    state_root: StateRoot,
+    keys: Vec<TrieKey>,
+}
+
+impl PrefetchRequest {
+    fn run(self) {
+        let storage = TrieCachingStorage::new_prefetching(self.store, self.cache, self.shard_id);
+        let trie = Trie::new(Box::new(storage), self.shard_id);
+        for key in self.keys {
+            // The state may be already garbage collected, the read is just skipped then.
+            if let Err(err) = trie.get(&self.state_root, &key.to_vec()) {
+                debug!(target: "store", "Failed to prefetch {:?}: {:?}", key, err);
+            }
+        }
+    }
+}
+
+/// Pool of threads reading the trie in the background. The threads are started by the first
+/// request and stop once the prefetcher is dropped.
+pub(crate) struct TriePrefetcher {
+    num_threads: usize,
+    sender: Mutex<Option<SyncSender<PrefetchRequest>>>,
+}
+
+impl TriePrefetcher {
+    pub(crate) fn new(num_threads: usize) -> Self {
+        TriePrefetcher { num_threads, sender: Mutex::new(None) }
+    }
+
+    /// Queues reading the keys from the state with the given root into the cache.
+    pub(crate) fn prefetch(
+        &self,
+        store: Arc<Store>,
+        cache: TrieCache,
+        shard_id: ShardId,
+        state_root: StateRoot,
+        keys: Vec<TrieKey>,
+    ) {
+        if self.num_threads == 0 || keys.is_empty() {
+            return;
+        }
+        let mut sender = self.sender.lock().expect(POISONED_LOCK_ERR);
+        let sender = sender.get_or_insert_with(|| self.start_threads());
+        let request = PrefetchRequest { store, cache, shard_id, state_root, keys };
+        match sender.try_send(request) {
+            Ok(()) => near_metrics::inc_counter(&metrics::TRIE_PREFETCH_REQUESTS),
+            Err(TrySendError::Full(_)) => {
+                near_metrics::inc_counter(&metrics::TRIE_PREFETCH_DROPPED)
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!(target: "store", "Trie prefetcher threads have stopped")
+            }
+        }
+    }
+
+    fn start_threads(&self) -> SyncSender<PrefetchRequest> {
+        let (sender, receiver) = sync_channel(PREFETCH_QUEUE_SIZE);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.num_threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name("trie_prefetcher".to_string())
+                .spawn(move || loop {
+                    let request = receiver.lock().expect(POISONED_LOCK_ERR).recv();
+                    match request {
+                        Ok(request) => request.run(),
+                        // The prefetcher is dropped
+                        Err(_) => break,
+                    }
+                })
+                .expect("Failed to start trie prefetcher thread");
+        }
+        sender
+    }
+}
+
+fn push_receiver_keys(receiver_id: &AccountId, actions: &[Action], keys: &mut Vec<TrieKey>) {
+    keys.push(TrieKey::Account { account_id: receiver_id.clone() });
+    if actions.iter().any(|action| matches!(action, Action::FunctionCall(_))) {
+        keys.push(TrieKey::ContractCode { account_id: receiver_id.clone() });
+    }
+}
+
+/// Keys of the accounts, access keys and contracts read by applying the transactions and
+/// the receipts. Contracts over the cached value size limit are not cached, but the nodes on
+/// the way to them are.
+pub(crate) fn keys_to_prefetch(
+    transactions: &[SignedTransaction],
+    receipts: &[Receipt],
+) -> Vec<TrieKey> {
+    let mut keys = vec![];
+    for transaction in transactions {
+        let transaction = &transaction.transaction;
+        keys.push(TrieKey::Account { account_id: transaction.signer_id.clone() });
+        keys.push(TrieKey::AccessKey {
+            account_id: transaction.signer_id.clone(),
+            public_key: transaction.public_key.clone(),
+        });
+        // Receipts to the signer itself are applied in the same chunk
+        if transaction.receiver_id == transaction.signer_id {
+            push_receiver_keys(&transaction.receiver_id, &transaction.actions, &mut keys);
+        }
+    }
+    for receipt in receipts {
+        if let Some(action_receipt) = receipt.receipt.action_receipt() {
+            push_receiver_keys(&receipt.receiver_id, &action_receipt.actions, &mut keys);
+        }
+    }
+    keys
+}
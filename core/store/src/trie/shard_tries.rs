@@ -1,16 +1,30 @@
 use crate::db::{DBCol, DBOp, DBTransaction};
-use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
-use crate::trie::TrieRefcountChange;
+use crate::trie::flat_state::{
+    flat_state_delta_key, flat_state_key, parse_flat_state_delta_key, FlatState, FlatStateDelta,
+    FlatStorageHead, FlatStorages, ValueRef,
+};
+use crate::trie::prefetcher::{keys_to_prefetch, TriePrefetcher};
+use crate::trie::trie_storage::{TrieCache, TrieCacheConfig, TrieCachingStorage};
+use crate::trie::{TrieRefcountChange, POISONED_LOCK_ERR};
 use crate::{StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
-use borsh::BorshSerialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::transaction::SignedTransaction;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
-    NumShards, RawStateChange, RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+    BlockHeight, NumShards, RawStateChange, RawStateChangesWithTrieKey, ShardId, StateChangeCause,
+    StateRoot,
 };
-use near_primitives::utils::get_block_shard_id;
+use near_primitives::utils::get_block_shard_uid;
+use std::convert::{TryFrom, TryInto};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// Number of flat state values written by a single store update while creating a flat storage.
+const FLAT_STORAGE_CREATION_BATCH_SIZE: usize = 10_000;
 
 #[derive(Clone)]
 pub struct ShardTries {
@@ -19,19 +33,42 @@ pub struct ShardTries {
     pub(crate) caches: Arc<Vec<TrieCache>>,
     /// Cache for readers.
     pub(crate) view_caches: Arc<Vec<TrieCache>>,
+    /// Heads and deltas of the flat storages of all shards.
+    pub(crate) flat_storages: Arc<RwLock<FlatStorages>>,
+    /// Warms up the client actor caches for the upcoming transactions and receipts.
+    pub(crate) prefetcher: Arc<TriePrefetcher>,
 }
 
 impl ShardTries {
-    fn get_new_cache(num_shards: NumShards) -> Arc<Vec<TrieCache>> {
-        Arc::new((0..num_shards).map(|_| TrieCache::new()).collect::<Vec<_>>())
+    fn get_new_cache(
+        num_shards: NumShards,
+        cache_config: &TrieCacheConfig,
+        is_view: bool,
+    ) -> Arc<Vec<TrieCache>> {
+        Arc::new(
+            (0..num_shards)
+                .map(|shard_id| TrieCache::with_config(cache_config, shard_id, is_view))
+                .collect::<Vec<_>>(),
+        )
     }
 
     pub fn new(store: Arc<Store>, num_shards: NumShards) -> Self {
+        Self::with_cache_config(store, num_shards, &TrieCacheConfig::default())
+    }
+
+    pub fn with_cache_config(
+        store: Arc<Store>,
+        num_shards: NumShards,
+        cache_config: &TrieCacheConfig,
+    ) -> Self {
         assert_ne!(num_shards, 0);
+        let flat_storages = FlatStorages::load(&store).expect("Failed to load flat storages");
         ShardTries {
             store,
-            caches: Self::get_new_cache(num_shards),
-            view_caches: Self::get_new_cache(num_shards),
+            caches: Self::get_new_cache(num_shards, cache_config, false),
+            view_caches: Self::get_new_cache(num_shards, cache_config, true),
+            flat_storages: Arc::new(RwLock::new(flat_storages)),
+            prefetcher: Arc::new(TriePrefetcher::new(cache_config.num_prefetch_threads)),
         }
     }
 
@@ -43,6 +80,24 @@ impl ShardTries {
         TrieUpdate::new(Rc::new(self.get_view_trie_for_shard(shard_id)), state_root)
     }
 
+    /// Same as `new_trie_update`, but reads from the flat state where possible.
+    /// See `Trie::with_flat_state_reads` for how this changes the touched nodes counter.
+    pub fn new_flat_trie_update(&self, shard_id: ShardId, state_root: CryptoHash) -> TrieUpdate {
+        let trie = self.get_trie_for_shard(shard_id).with_flat_state_reads(self.get_flat_state());
+        TrieUpdate::new(Rc::new(trie), state_root)
+    }
+
+    /// Same as `new_trie_update_view`, but reads from the flat state where possible.
+    pub fn new_flat_trie_update_view(
+        &self,
+        shard_id: ShardId,
+        state_root: CryptoHash,
+    ) -> TrieUpdate {
+        let trie =
+            self.get_view_trie_for_shard(shard_id).with_flat_state_reads(self.get_flat_state());
+        TrieUpdate::new(Rc::new(trie), state_root)
+    }
+
     fn get_trie_for_shard_internal(&self, shard_id: ShardId, is_view: bool) -> Trie {
         let cache = if is_view {
             self.view_caches[shard_id as usize].clone()
@@ -61,14 +116,175 @@ impl ShardTries {
         self.get_trie_for_shard_internal(shard_id, true)
     }
 
+    /// Loads the accounts, access keys and contracts which the transactions and the receipts
+    /// are going to read from the given state into the cache of the shard in the background.
+    pub fn prefetch(
+        &self,
+        shard_id: ShardId,
+        state_root: StateRoot,
+        transactions: &[SignedTransaction],
+        receipts: &[Receipt],
+    ) {
+        self.prefetcher.prefetch(
+            self.store.clone(),
+            self.caches[shard_id as usize].clone(),
+            shard_id,
+            state_root,
+            keys_to_prefetch(transactions, receipts),
+        );
+    }
+
     pub fn get_store(&self) -> Arc<Store> {
         self.store.clone()
     }
 
+    /// Handle for reading the flat state, see `Trie::with_flat_state_reads`.
+    pub fn get_flat_state(&self) -> FlatState {
+        FlatState { store: self.store.clone(), flat_storages: self.flat_storages.clone() }
+    }
+
+    pub fn get_flat_storage_head(&self, shard_uid: &ShardUId) -> Option<FlatStorageHead> {
+        self.flat_storages.read().expect(POISONED_LOCK_ERR).head(shard_uid)
+    }
+
+    /// Starts recording the deltas of the blocks applied to `shard_uid`, for the flat storage of
+    /// the shard which is then created by `create_flat_storage`. The existing flat storage of the
+    /// shard, if any, is dropped and its reads are served by the trie until the creation is done.
+    pub fn start_flat_storage_creation(&self, shard_uid: ShardUId) -> Result<(), StorageError> {
+        let mut flat_storages = self.flat_storages.write().expect(POISONED_LOCK_ERR);
+        flat_storages.start_creation(shard_uid);
+        // Removes the leftovers of an interrupted creation. The values are overwritten or
+        // removed by `create_flat_storage`.
+        let prefix = shard_uid.to_bytes();
+        let mut store_update = self.store.store_update();
+        store_update.delete(DBCol::ColFlatStateMisc, &prefix);
+        for (key, _) in self.store.iter_prefix(DBCol::ColFlatStateDeltas, &prefix) {
+            store_update.delete(DBCol::ColFlatStateDeltas, &key);
+        }
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)
+    }
+
+    /// Creates the flat storage of `shard_uid` from the state of the shard after `block_hash`,
+    /// replacing the existing one if any. Takes a while for large states, so it's meant to run
+    /// in the background after `start_flat_storage_creation`, which makes the deltas of the
+    /// blocks applied in the meantime available once the creation is done.
+    pub fn create_flat_storage(
+        &self,
+        shard_uid: ShardUId,
+        block_hash: CryptoHash,
+        height: BlockHeight,
+        state_root: StateRoot,
+    ) -> Result<(), StorageError> {
+        if !self.flat_storages.read().expect(POISONED_LOCK_ERR).is_creating(&shard_uid) {
+            self.start_flat_storage_creation(shard_uid)?;
+        }
+        let result = self.write_flat_storage(shard_uid, block_hash, height, state_root);
+        if result.is_err() {
+            self.flat_storages.write().expect(POISONED_LOCK_ERR).stop_creation(&shard_uid);
+        }
+        result
+    }
+
+    fn write_flat_storage(
+        &self,
+        shard_uid: ShardUId,
+        block_hash: CryptoHash,
+        height: BlockHeight,
+        state_root: StateRoot,
+    ) -> Result<(), StorageError> {
+        let prefix = shard_uid.to_bytes();
+        let mut store_update = self.store.store_update();
+        for (key, _) in self.store.iter_prefix(DBCol::ColFlatState, &prefix) {
+            store_update.delete(DBCol::ColFlatState, &key);
+        }
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+
+        let trie = self.get_trie_for_shard(shard_uid.shard_id());
+        let mut store_update = self.store.store_update();
+        let mut num_values = 0;
+        for item in trie.iter(&state_root)? {
+            let (key, value) = item?;
+            store_update
+                .set_ser(
+                    DBCol::ColFlatState,
+                    &flat_state_key(&shard_uid, &key),
+                    &ValueRef::new(&value),
+                )
+                .map_err(|_| StorageError::StorageInternalError)?;
+            num_values += 1;
+            if num_values % FLAT_STORAGE_CREATION_BATCH_SIZE == 0 {
+                store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+                store_update = self.store.store_update();
+                info!(target: "store", "Creating flat storage of shard {:?}: {} values written", shard_uid, num_values);
+            }
+        }
+
+        // The lock is held until the head is in the DB, so that the creation can't be cancelled
+        // in between and no delta recorded meanwhile is missed.
+        let mut flat_storages = self.flat_storages.write().expect(POISONED_LOCK_ERR);
+        if !flat_storages.is_creating(&shard_uid) {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "Creation of the flat storage of shard {:?} was cancelled",
+                shard_uid
+            )));
+        }
+        // The head is written last, so that an interrupted creation leaves no flat storage.
+        let head = FlatStorageHead { block_hash, height, state_root };
+        store_update
+            .set_ser(DBCol::ColFlatStateMisc, &prefix, &head)
+            .map_err(|_| StorageError::StorageInternalError)?;
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+        let pruned_blocks = flat_storages.finish_creation(shard_uid, head);
+        drop(flat_storages);
+
+        let mut store_update = self.store.store_update();
+        for block_hash in pruned_blocks.iter() {
+            store_update
+                .delete(DBCol::ColFlatStateDeltas, &flat_state_delta_key(&shard_uid, block_hash));
+        }
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+        info!(target: "store", "Created flat storage of shard {:?} with {} values at {}", shard_uid, num_values, block_hash);
+        Ok(())
+    }
+
+    /// Moves the flat storage heads to the final block `block_hash`. The shards whose deltas
+    /// don't reach the block yet, e.g. the ones still catching up, keep their heads.
+    pub fn move_flat_storage_heads(&self, block_hash: &CryptoHash) -> Result<(), StorageError> {
+        // The lock is held until the memory matches the DB, so that no read sees the flat state
+        // of the new head together with the deltas of the old one.
+        let mut flat_storages = self.flat_storages.write().expect(POISONED_LOCK_ERR);
+        let mut store_update = self.store.store_update();
+        let new_heads = flat_storages.move_heads_update(block_hash, &mut store_update)?;
+        if new_heads.is_empty() {
+            return Ok(());
+        }
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+        for (shard_uid, head, pruned_blocks) in new_heads {
+            for block_hash in pruned_blocks.iter() {
+                flat_storages.remove_delta(&shard_uid, block_hash);
+            }
+            flat_storages.set_head(shard_uid, head);
+        }
+        Ok(())
+    }
+
     pub fn update_cache(&self, transaction: &DBTransaction) -> std::io::Result<()> {
         let mut shards = vec![Vec::new(); self.caches.len()];
         for op in &transaction.ops {
             match op {
+                DBOp::Insert { col, ref key, ref value } if *col == DBCol::ColFlatStateDeltas => {
+                    let (shard_uid, block_hash) = parse_flat_state_delta_key(key)
+                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+                    let delta = FlatStateDelta::try_from_slice(value)?;
+                    self.flat_storages
+                        .write()
+                        .expect(POISONED_LOCK_ERR)
+                        .add_delta(shard_uid, block_hash, delta);
+                }
+                DBOp::DeleteAll { col } if *col == DBCol::ColFlatStateMisc => {
+                    // Delete is possible in reset_data_pre_state_sync
+                    self.flat_storages.write().expect(POISONED_LOCK_ERR).clear();
+                }
                 DBOp::UpdateRefcount { col, ref key, ref value } if *col == DBCol::ColState => {
                     let (shard_id, hash) = TrieCachingStorage::get_shard_id_and_hash_from_key(key)?;
                     shards[shard_id as usize].push((hash, Some(value.clone())));
@@ -242,31 +458,117 @@ impl ShardTries {
 
 pub struct WrappedTrieChanges {
     tries: ShardTries,
-    shard_id: ShardId,
+    shard_uid: ShardUId,
     trie_changes: TrieChanges,
     state_changes: Vec<RawStateChangesWithTrieKey>,
     block_hash: CryptoHash,
+    /// Whether `state_changes_into` saves the state changes. They are only used for the flat
+    /// storage deltas otherwise.
+    save_state_changes: bool,
 }
 
 impl WrappedTrieChanges {
     pub fn new(
         tries: ShardTries,
-        shard_id: ShardId,
+        shard_uid: ShardUId,
         trie_changes: TrieChanges,
         state_changes: Vec<RawStateChangesWithTrieKey>,
         block_hash: CryptoHash,
     ) -> Self {
-        WrappedTrieChanges { tries, shard_id, trie_changes, state_changes, block_hash }
+        WrappedTrieChanges {
+            tries,
+            shard_uid,
+            trie_changes,
+            state_changes,
+            block_hash,
+            save_state_changes: true,
+        }
+    }
+
+    /// Changes made to a split state by applying a chunk of its parent shard. The state changes
+    /// are saved only for the parent shard, so that they are not duplicated in the storage and in
+    /// the RPC responses, but they still make the flat storage delta of the split shard.
+    pub fn new_for_split_state(
+        tries: ShardTries,
+        shard_uid: ShardUId,
+        trie_changes: TrieChanges,
+        state_changes: Vec<RawStateChangesWithTrieKey>,
+        block_hash: CryptoHash,
+    ) -> Self {
+        WrappedTrieChanges {
+            tries,
+            shard_uid,
+            trie_changes,
+            state_changes,
+            block_hash,
+            save_state_changes: false,
+        }
+    }
+
+    pub fn state_changes(&self) -> &[RawStateChangesWithTrieKey] {
+        &self.state_changes
+    }
+
+    /// Whether the state changes are saved, see `new_for_split_state`.
+    pub fn saves_state_changes(&self) -> bool {
+        self.save_state_changes
+    }
+
+    pub fn new_root(&self) -> StateRoot {
+        self.trie_changes.new_root
+    }
+
+    pub fn block_hash(&self) -> &CryptoHash {
+        &self.block_hash
+    }
+
+    /// Saves the changes to the flat state of the shard made by the block, if the shard has a
+    /// flat storage or it is being created.
+    ///
+    /// NOTE: must be called before `state_changes_into`, which drains the changes.
+    pub fn flat_state_delta_into(
+        &self,
+        prev_block_hash: CryptoHash,
+        height: BlockHeight,
+        store_update: &mut StoreUpdate,
+    ) -> Result<(), StorageError> {
+        if !self
+            .tries
+            .flat_storages
+            .read()
+            .expect(POISONED_LOCK_ERR)
+            .records_deltas(&self.shard_uid)
+        {
+            return Ok(());
+        }
+        let delta = FlatStateDelta::from_state_changes(
+            prev_block_hash,
+            height,
+            self.trie_changes.new_root,
+            &self.state_changes,
+        );
+        store_update.tries = Some(self.tries.clone());
+        store_update
+            .set_ser(
+                DBCol::ColFlatStateDeltas,
+                &flat_state_delta_key(&self.shard_uid, &self.block_hash),
+                &delta,
+            )
+            .map_err(|_| StorageError::StorageInternalError)
     }
 
     pub fn insertions_into(&self, store_update: &mut StoreUpdate) -> Result<(), StorageError> {
-        self.tries.apply_insertions(&self.trie_changes, self.shard_id, store_update)
+        self.tries.apply_insertions(&self.trie_changes, self.shard_uid.shard_id(), store_update)
     }
 
-    /// Save state changes into Store.
+    /// Save state changes into Store, indexed by the block at `height` and by the trie key.
     ///
     /// NOTE: the changes are drained from `self`.
-    pub fn state_changes_into(&mut self, store_update: &mut StoreUpdate) {
+    pub fn state_changes_into(&mut self, height: BlockHeight, store_update: &mut StoreUpdate) {
+        if !self.save_state_changes {
+            self.state_changes.clear();
+            return;
+        }
         for change_with_trie_key in self.state_changes.drain(..) {
             assert!(
                 !change_with_trie_key.changes.iter().any(|RawStateChange { cause, .. }| matches!(
@@ -294,18 +596,25 @@ impl WrappedTrieChanges {
                 storage_key.as_ref(),
                 &change_with_trie_key.try_to_vec().expect("Borsh serialize cannot fail"),
             );
+            let index_key = KeyForStateChangesByKey::new(
+                &change_with_trie_key.trie_key.to_vec(),
+                height,
+                &self.block_hash,
+            );
+            store_update.set(DBCol::ColStateChangesByKey, index_key.as_ref(), &[]);
         }
     }
 
     pub fn wrapped_into(
         &mut self,
+        height: BlockHeight,
         mut store_update: &mut StoreUpdate,
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.insertions_into(&mut store_update)?;
-        self.state_changes_into(&mut store_update);
+        self.state_changes_into(height, &mut store_update);
         store_update.set_ser(
             DBCol::ColTrieChanges,
-            &get_block_shard_id(&self.block_hash, self.shard_id),
+            &get_block_shard_uid(&self.block_hash, &self.shard_uid),
             &self.trie_changes,
         )?;
         Ok(())
@@ -381,3 +690,36 @@ impl KeyForStateChanges {
         })
     }
 }
+
+/// Key of `ColStateChangesByKey`, which indexes the state changes by the changed trie key and
+/// the height of the block, so that the history of a key is read without scanning every block.
+#[derive(derive_more::AsRef, derive_more::Into)]
+pub struct KeyForStateChangesByKey(Vec<u8>);
+
+impl KeyForStateChangesByKey {
+    const SUFFIX_LEN: usize =
+        std::mem::size_of::<BlockHeight>() + std::mem::size_of::<CryptoHash>();
+
+    pub fn new(trie_key: &[u8], height: BlockHeight, block_hash: &CryptoHash) -> Self {
+        let mut key = Vec::with_capacity(trie_key.len() + Self::SUFFIX_LEN);
+        key.extend(trie_key);
+        key.extend(&height.to_be_bytes());
+        key.extend(block_hash.as_ref());
+        Self(key)
+    }
+
+    /// Splits the key into the trie key, the height and the hash of the block.
+    pub fn parse(key: &[u8]) -> Result<(&[u8], BlockHeight, CryptoHash), std::io::Error> {
+        if key.len() < Self::SUFFIX_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Key of the state changes index is too short",
+            ));
+        }
+        let (trie_key, suffix) = key.split_at(key.len() - Self::SUFFIX_LEN);
+        let (height, block_hash) = suffix.split_at(std::mem::size_of::<BlockHeight>());
+        let height = BlockHeight::from_be_bytes(height.try_into().unwrap());
+        let block_hash = CryptoHash::try_from(block_hash).unwrap();
+        Ok((trie_key, height, block_hash))
+    }
+}
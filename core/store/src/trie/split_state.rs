@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::trie_key::trie_key_parsers::parse_account_id_from_raw_key;
+use near_primitives::trie_key::{col, TrieKey};
+use near_primitives::types::{AccountId, RawStateChangesWithTrieKey, StateChangeCause, StateRoot};
+
+use crate::{get, set, ShardTries, StorageError, StoreUpdate, Trie, TrieChanges, TrieUpdate};
+
+/// State changes made by applying a chunk of a parent shard, together with the delayed receipts
+/// that the chunk removed from the queue. Removed receipts are needed to pop them from the queues
+/// of the split shards, since the state changes only record that the parent queue entry was
+/// deleted.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct StateChangesForSplitStates {
+    pub changes: Vec<RawStateChangesWithTrieKey>,
+    /// Delayed receipts removed from the queue of the parent shard, in queue order.
+    pub processed_delayed_receipts: Vec<Receipt>,
+}
+
+impl StateChangesForSplitStates {
+    /// `trie` and `old_root` point to the state of the parent shard before `changes` were made.
+    pub fn from_raw_state_changes(
+        trie: &Trie,
+        old_root: &StateRoot,
+        changes: Vec<RawStateChangesWithTrieKey>,
+    ) -> Result<Self, StorageError> {
+        let mut removed_indices: Vec<u64> = changes
+            .iter()
+            .filter_map(|RawStateChangesWithTrieKey { trie_key, changes }| match trie_key {
+                TrieKey::DelayedReceipt { index }
+                    if changes.last().map_or(false, |change| change.data.is_none()) =>
+                {
+                    Some(*index)
+                }
+                _ => None,
+            })
+            .collect();
+        removed_indices.sort();
+        let processed_delayed_receipts = removed_indices
+            .into_iter()
+            .map(|index| {
+                let data = trie.get(old_root, &TrieKey::DelayedReceipt { index }.to_vec())?;
+                let data = data.ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Delayed receipt #{} must be in the state",
+                        index
+                    ))
+                })?;
+                Receipt::try_from_slice(&data).map_err(|_| {
+                    StorageError::StorageInconsistentState("Failed to deserialize".to_string())
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { changes, processed_delayed_receipts })
+    }
+}
+
+/// Reads the whole delayed receipts queue, in queue order.
+pub fn get_delayed_receipts(state_update: &TrieUpdate) -> Result<Vec<Receipt>, StorageError> {
+    let indices: DelayedReceiptIndices =
+        get(state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+    (indices.first_index..indices.next_available_index)
+        .map(|index| {
+            get(state_update, &TrieKey::DelayedReceipt { index })?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Delayed receipt #{} must be in the state",
+                    index
+                ))
+            })
+        })
+        .collect()
+}
+
+impl ShardTries {
+    fn new_trie_updates(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+    ) -> HashMap<ShardUId, TrieUpdate> {
+        state_roots
+            .iter()
+            .map(|(shard_uid, state_root)| {
+                (*shard_uid, self.new_trie_update(shard_uid.shard_id(), *state_root))
+            })
+            .collect()
+    }
+
+    fn finalize_and_apply_trie_updates(
+        &self,
+        trie_updates: HashMap<ShardUId, TrieUpdate>,
+    ) -> Result<(StoreUpdate, HashMap<ShardUId, StateRoot>), StorageError> {
+        let mut store_update = StoreUpdate::new_with_tries(self.clone());
+        let mut new_state_roots = HashMap::new();
+        for (shard_uid, mut trie_update) in trie_updates {
+            trie_update.commit(StateChangeCause::Resharding);
+            let (trie_changes, _) = trie_update.finalize()?;
+            let (update, state_root) = self.apply_all(&trie_changes, shard_uid.shard_id())?;
+            new_state_roots.insert(shard_uid, state_root);
+            store_update.merge(update);
+        }
+        Ok((store_update, new_state_roots))
+    }
+
+    /// Adds `values` (raw key-value pairs of the parent state) to the states of the split shards,
+    /// whose current roots are `state_roots`. Every key is routed by the account it belongs to,
+    /// the shared contract code registry is copied to all the split shards. The number of users of
+    /// a copied code becomes an upper bound for every split shard, so the copy may be kept after
+    /// its last user on the shard is gone.
+    /// Delayed receipts keys are skipped, use `add_delayed_receipts_to_split_states` for them.
+    /// The returned store update must be committed before adding the next batch of values.
+    pub fn add_values_to_split_states(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        values: Vec<(Vec<u8>, Vec<u8>)>,
+        account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+    ) -> Result<(StoreUpdate, HashMap<ShardUId, StateRoot>), StorageError> {
+        let mut changes_by_shard: HashMap<ShardUId, Vec<_>> = HashMap::new();
+        for (raw_key, value) in values {
+            let account_id = parse_account_id_from_raw_key(&raw_key)
+                .map_err(|err| StorageError::StorageInconsistentState(err.to_string()))?;
+            if let Some(account_id) = account_id {
+                let shard_uid = account_id_to_shard_id(&account_id);
+                changes_by_shard.entry(shard_uid).or_default().push((raw_key, Some(value)));
+            } else if raw_key.starts_with(col::SHARED_CONTRACT_CODE) {
+                for shard_uid in state_roots.keys() {
+                    changes_by_shard
+                        .entry(*shard_uid)
+                        .or_default()
+                        .push((raw_key.clone(), Some(value.clone())));
+                }
+            }
+        }
+        let mut store_update = StoreUpdate::new_with_tries(self.clone());
+        let mut new_state_roots = state_roots.clone();
+        for (shard_uid, changes) in changes_by_shard {
+            let state_root = new_state_roots.get_mut(&shard_uid).ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Missing state root for split shard {:?}",
+                    shard_uid
+                ))
+            })?;
+            let trie = self.get_trie_for_shard(shard_uid.shard_id());
+            let trie_changes = trie.update(state_root, changes.into_iter())?;
+            let (update, new_root) = self.apply_all(&trie_changes, shard_uid.shard_id())?;
+            *state_root = new_root;
+            store_update.merge(update);
+        }
+        Ok((store_update, new_state_roots))
+    }
+
+    /// Appends `receipts` to the delayed receipts queues of the split shards of their receivers.
+    /// The updates of the shared contract code registry are appended to all the split shards.
+    pub fn add_delayed_receipts_to_split_states(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        receipts: Vec<Receipt>,
+        account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+    ) -> Result<(StoreUpdate, HashMap<ShardUId, StateRoot>), StorageError> {
+        let mut trie_updates = self.new_trie_updates(state_roots);
+        insert_delayed_receipts_to_split_states(
+            &mut trie_updates,
+            receipts,
+            account_id_to_shard_id,
+        )?;
+        self.finalize_and_apply_trie_updates(trie_updates)
+    }
+
+    /// Applies the changes made to the parent shard by one chunk to the states of the split
+    /// shards. Returns the trie changes and the state changes for every split shard; they are not
+    /// written to the store.
+    pub fn apply_state_changes_to_split_states(
+        &self,
+        state_roots: &HashMap<ShardUId, StateRoot>,
+        changes: StateChangesForSplitStates,
+        account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+    ) -> Result<HashMap<ShardUId, (TrieChanges, Vec<RawStateChangesWithTrieKey>)>, StorageError>
+    {
+        let mut trie_updates = self.new_trie_updates(state_roots);
+        let StateChangesForSplitStates { changes, processed_delayed_receipts } = changes;
+        let mut inserted_receipts = vec![];
+        for RawStateChangesWithTrieKey { trie_key, changes } in changes {
+            let data = changes
+                .into_iter()
+                .last()
+                .expect("Committed entry should have at least one change")
+                .data;
+            match &trie_key {
+                // The gas of the delayed receipts isn't split between the shards, the split
+                // shards start accounting it from zero.
+                TrieKey::DelayedReceiptIndices | TrieKey::DelayedReceiptsGas => {}
+                TrieKey::DelayedReceipt { index } => {
+                    if let Some(data) = data {
+                        let receipt = Receipt::try_from_slice(&data).map_err(|_| {
+                            StorageError::StorageInconsistentState(
+                                "Failed to deserialize".to_string(),
+                            )
+                        })?;
+                        inserted_receipts.push((*index, receipt));
+                    }
+                }
+                TrieKey::SharedContractCode { .. } => {
+                    for trie_update in trie_updates.values_mut() {
+                        match &data {
+                            Some(value) => trie_update.set(trie_key.clone(), value.clone()),
+                            None => trie_update.remove(trie_key.clone()),
+                        }
+                    }
+                }
+                _ => {
+                    let account_id = trie_key.get_account_id().ok_or_else(|| {
+                        StorageError::StorageInconsistentState(format!(
+                            "Trie key {:?} has no account id",
+                            trie_key
+                        ))
+                    })?;
+                    let shard_uid = account_id_to_shard_id(&account_id);
+                    let trie_update = get_trie_update(&mut trie_updates, &shard_uid)?;
+                    match data {
+                        Some(value) => trie_update.set(trie_key, value),
+                        None => trie_update.remove(trie_key),
+                    }
+                }
+            }
+        }
+        remove_delayed_receipts_from_split_states(
+            &mut trie_updates,
+            &processed_delayed_receipts,
+            account_id_to_shard_id,
+        )?;
+        inserted_receipts.sort_by_key(|(index, _)| *index);
+        insert_delayed_receipts_to_split_states(
+            &mut trie_updates,
+            inserted_receipts.into_iter().map(|(_, receipt)| receipt),
+            account_id_to_shard_id,
+        )?;
+
+        let mut trie_changes = HashMap::new();
+        for (shard_uid, mut trie_update) in trie_updates {
+            trie_update.commit(StateChangeCause::Resharding);
+            trie_changes.insert(shard_uid, trie_update.finalize()?);
+        }
+        Ok(trie_changes)
+    }
+}
+
+fn get_trie_update<'a>(
+    trie_updates: &'a mut HashMap<ShardUId, TrieUpdate>,
+    shard_uid: &ShardUId,
+) -> Result<&'a mut TrieUpdate, StorageError> {
+    trie_updates.get_mut(shard_uid).ok_or_else(|| {
+        StorageError::StorageInconsistentState(format!(
+            "Missing state root for split shard {:?}",
+            shard_uid
+        ))
+    })
+}
+
+fn insert_delayed_receipts_to_split_states(
+    trie_updates: &mut HashMap<ShardUId, TrieUpdate>,
+    receipts: impl IntoIterator<Item = Receipt>,
+    account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+) -> Result<(), StorageError> {
+    for receipt in receipts {
+        for shard_uid in receipt_split_shards(trie_updates, &receipt, account_id_to_shard_id) {
+            let trie_update = get_trie_update(trie_updates, &shard_uid)?;
+            let mut indices: DelayedReceiptIndices =
+                get(trie_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+            set(
+                trie_update,
+                TrieKey::DelayedReceipt { index: indices.next_available_index },
+                &receipt,
+            );
+            indices.next_available_index += 1;
+            set(trie_update, TrieKey::DelayedReceiptIndices, &indices);
+        }
+    }
+    Ok(())
+}
+
+fn remove_delayed_receipts_from_split_states(
+    trie_updates: &mut HashMap<ShardUId, TrieUpdate>,
+    receipts: &[Receipt],
+    account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+) -> Result<(), StorageError> {
+    for receipt in receipts {
+        for shard_uid in receipt_split_shards(trie_updates, receipt, account_id_to_shard_id) {
+            let trie_update = get_trie_update(trie_updates, &shard_uid)?;
+            let mut indices: DelayedReceiptIndices =
+                get(trie_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+            let key = TrieKey::DelayedReceipt { index: indices.first_index };
+            let first_receipt: Option<Receipt> =
+                if indices.first_index < indices.next_available_index {
+                    get(trie_update, &key)?
+                } else {
+                    None
+                };
+            if first_receipt.as_ref().map(|r| r.receipt_id) != Some(receipt.receipt_id) {
+                return Err(StorageError::StorageInconsistentState(format!(
+                    "Delayed receipt {} is not at the front of the queue of shard {:?}",
+                    receipt.receipt_id, shard_uid
+                )));
+            }
+            trie_update.remove(key);
+            indices.first_index += 1;
+            set(trie_update, TrieKey::DelayedReceiptIndices, &indices);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the split shards whose delayed receipts queues get the receipt: the shard of its
+/// receiver, or all of them for the updates of the shared contract code registry, which has a
+/// copy on every shard.
+fn receipt_split_shards(
+    trie_updates: &HashMap<ShardUId, TrieUpdate>,
+    receipt: &Receipt,
+    account_id_to_shard_id: &dyn Fn(&AccountId) -> ShardUId,
+) -> Vec<ShardUId> {
+    match receipt.receipt {
+        ReceiptEnum::SharedContractCode(_) => {
+            let mut shard_uids: Vec<_> = trie_updates.keys().copied().collect();
+            shard_uids.sort();
+            shard_uids
+        }
+        _ => vec![account_id_to_shard_id(&receipt.receiver_id)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+    use near_primitives::receipt::SharedContractCodeReceipt;
+    use near_primitives::types::RawStateChange;
+
+    use crate::test_utils::create_test_store;
+
+    use super::*;
+
+    fn account_id_to_shard_id(account_id: &AccountId) -> ShardUId {
+        let account_id: &str = account_id.as_ref();
+        let shard_id = if account_id < "m" { 0 } else { 1 };
+        ShardUId { version: 1, shard_id }
+    }
+
+    fn new_receipt(receiver_id: &str, i: u8) -> Receipt {
+        let mut receipt = Receipt::new_balance_refund(&receiver_id.parse().unwrap(), 1);
+        receipt.receipt_id = hash(&[i]);
+        receipt
+    }
+
+    fn empty_split_roots() -> HashMap<ShardUId, StateRoot> {
+        (0..2).map(|shard_id| (ShardUId { version: 1, shard_id }, Trie::empty_root())).collect()
+    }
+
+    fn get_account_key(account_id: &str) -> Vec<u8> {
+        TrieKey::Account { account_id: account_id.parse().unwrap() }.to_vec()
+    }
+
+    #[test]
+    fn test_add_values_to_split_states() {
+        let tries = ShardTries::new(create_test_store(), 2);
+        let values = vec![
+            (get_account_key("alice"), vec![1]),
+            (get_account_key("zoe"), vec![2]),
+            (TrieKey::DelayedReceiptIndices.to_vec(), vec![3]),
+        ];
+        let (store_update, state_roots) = tries
+            .add_values_to_split_states(&empty_split_roots(), values, &account_id_to_shard_id)
+            .unwrap();
+        store_update.commit().unwrap();
+
+        let left = ShardUId { version: 1, shard_id: 0 };
+        let right = ShardUId { version: 1, shard_id: 1 };
+        let left_trie = tries.get_trie_for_shard(0);
+        let right_trie = tries.get_trie_for_shard(1);
+        assert_eq!(
+            left_trie.get(&state_roots[&left], &get_account_key("alice")),
+            Ok(Some(vec![1]))
+        );
+        assert_eq!(left_trie.get(&state_roots[&left], &get_account_key("zoe")), Ok(None));
+        assert_eq!(
+            right_trie.get(&state_roots[&right], &get_account_key("zoe")),
+            Ok(Some(vec![2]))
+        );
+        assert_eq!(
+            right_trie.get(&state_roots[&right], &TrieKey::DelayedReceiptIndices.to_vec()),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_apply_state_changes_to_split_states() {
+        let tries = ShardTries::new(create_test_store(), 2);
+        let receipts = vec![new_receipt("alice", 0), new_receipt("zoe", 1), new_receipt("bob", 2)];
+        let (store_update, state_roots) = tries
+            .add_delayed_receipts_to_split_states(
+                &empty_split_roots(),
+                receipts.clone(),
+                &account_id_to_shard_id,
+            )
+            .unwrap();
+        store_update.commit().unwrap();
+
+        let change = |trie_key: TrieKey, data: Option<Vec<u8>>| RawStateChangesWithTrieKey {
+            trie_key,
+            changes: vec![RawStateChange { cause: StateChangeCause::InitialState, data }],
+        };
+        let new_receipt = new_receipt("zack", 3);
+        let changes = StateChangesForSplitStates {
+            changes: vec![
+                change(TrieKey::Account { account_id: "alice".parse().unwrap() }, Some(vec![1])),
+                change(TrieKey::DelayedReceipt { index: 0 }, None),
+                change(
+                    TrieKey::DelayedReceipt { index: 3 },
+                    Some(new_receipt.try_to_vec().unwrap()),
+                ),
+            ],
+            processed_delayed_receipts: vec![receipts[0].clone()],
+        };
+        let trie_changes = tries
+            .apply_state_changes_to_split_states(&state_roots, changes, &account_id_to_shard_id)
+            .unwrap();
+        let mut new_state_roots = HashMap::new();
+        for (shard_uid, (trie_changes, _)) in trie_changes {
+            let (store_update, root) =
+                tries.apply_all(&trie_changes, shard_uid.shard_id()).unwrap();
+            store_update.commit().unwrap();
+            new_state_roots.insert(shard_uid, root);
+        }
+
+        let left = ShardUId { version: 1, shard_id: 0 };
+        let right = ShardUId { version: 1, shard_id: 1 };
+        let left_update = tries.new_trie_update(0, new_state_roots[&left]);
+        let right_update = tries.new_trie_update(1, new_state_roots[&right]);
+        assert_eq!(
+            left_update.get(&TrieKey::Account { account_id: "alice".parse().unwrap() }),
+            Ok(Some(vec![1]))
+        );
+        assert_eq!(get_delayed_receipts(&left_update).unwrap(), vec![receipts[2].clone()]);
+        assert_eq!(
+            get_delayed_receipts(&right_update).unwrap(),
+            vec![receipts[1].clone(), new_receipt]
+        );
+    }
+
+    #[test]
+    fn test_shared_contract_code_receipts_copied_to_split_states() {
+        let tries = ShardTries::new(create_test_store(), 2);
+        let mut shared_code_receipt = new_receipt("alice", 0);
+        shared_code_receipt.receipt =
+            ReceiptEnum::SharedContractCode(SharedContractCodeReceipt::Remove {
+                code_hash: hash(b"code"),
+            });
+        let receipts = vec![shared_code_receipt.clone(), new_receipt("zoe", 1)];
+        let (store_update, state_roots) = tries
+            .add_delayed_receipts_to_split_states(
+                &empty_split_roots(),
+                receipts.clone(),
+                &account_id_to_shard_id,
+            )
+            .unwrap();
+        store_update.commit().unwrap();
+
+        let left = ShardUId { version: 1, shard_id: 0 };
+        let right = ShardUId { version: 1, shard_id: 1 };
+        assert_eq!(
+            get_delayed_receipts(&tries.new_trie_update(0, state_roots[&left])).unwrap(),
+            vec![shared_code_receipt.clone()]
+        );
+        assert_eq!(
+            get_delayed_receipts(&tries.new_trie_update(1, state_roots[&right])).unwrap(),
+            receipts
+        );
+
+        // Processing the receipt on the parent shard removes it from both split shards.
+        let changes = StateChangesForSplitStates {
+            changes: vec![],
+            processed_delayed_receipts: vec![shared_code_receipt],
+        };
+        let trie_changes = tries
+            .apply_state_changes_to_split_states(&state_roots, changes, &account_id_to_shard_id)
+            .unwrap();
+        let mut new_state_roots = HashMap::new();
+        for (shard_uid, (trie_changes, _)) in trie_changes {
+            let (store_update, root) =
+                tries.apply_all(&trie_changes, shard_uid.shard_id()).unwrap();
+            store_update.commit().unwrap();
+            new_state_roots.insert(shard_uid, root);
+        }
+        assert_eq!(
+            get_delayed_receipts(&tries.new_trie_update(0, new_state_roots[&left])).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            get_delayed_receipts(&tries.new_trie_update(1, new_state_roots[&right])).unwrap(),
+            vec![receipts[1].clone()]
+        );
+    }
+
+    #[test]
+    fn test_apply_state_changes_wrong_delayed_receipt() {
+        let tries = ShardTries::new(create_test_store(), 2);
+        let receipts = vec![new_receipt("alice", 0), new_receipt("bob", 1)];
+        let (store_update, state_roots) = tries
+            .add_delayed_receipts_to_split_states(
+                &empty_split_roots(),
+                receipts.clone(),
+                &account_id_to_shard_id,
+            )
+            .unwrap();
+        store_update.commit().unwrap();
+        let changes = StateChangesForSplitStates {
+            changes: vec![],
+            processed_delayed_receipts: vec![receipts[1].clone()],
+        };
+        assert!(tries
+            .apply_state_changes_to_split_states(&state_roots, changes, &account_id_to_shard_id)
+            .is_err());
+    }
+}
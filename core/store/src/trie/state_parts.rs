@@ -70,6 +70,38 @@ impl Trie {
         Ok(())
     }
 
+    /// Returns all key-value pairs stored in the state part `part_id`, in key order.
+    /// Used for building the states of split shards part by part, so that the whole state of
+    /// the parent shard is never held in memory.
+    pub fn get_trie_items_for_part(
+        &self,
+        part_id: u64,
+        num_parts: u64,
+        state_root: &StateRoot,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StorageError> {
+        assert!(part_id < num_parts);
+        let path_begin = self.find_path_for_part_boundary(state_root, part_id, num_parts)?;
+        let path_end = self.find_path_for_part_boundary(state_root, part_id + 1, num_parts)?;
+        let mut iterator = self.iter(state_root)?;
+        let mut keys: Vec<Vec<u8>> = iterator
+            .visit_nodes_interval(&path_begin, &path_end)?
+            .into_iter()
+            .filter_map(|item| item.key)
+            .collect();
+        keys.dedup();
+        keys.into_iter()
+            .map(|key| {
+                let value = self.get(state_root, &key)?.ok_or_else(|| {
+                    StorageError::StorageInconsistentState(format!(
+                        "Value for key {:?} is missing",
+                        key
+                    ))
+                })?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
     /// Part part_id has nodes with paths [ path(part_id) .. path(part_id + 1) )
     /// path is returned as nibbles, last path is vec![16], previous paths end in nodes
     fn find_path_for_part_boundary(
@@ -695,4 +727,26 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_get_trie_items_for_part() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let tries = create_tries();
+            let trie = tries.get_trie_for_shard(0);
+            let trie_changes = gen_changes(&mut rng, 20);
+            let state_root =
+                test_populate_trie(&tries, &Trie::empty_root(), 0, trie_changes.clone());
+            let expected: Vec<_> = trie.iter(&state_root).unwrap().map(Result::unwrap).collect();
+
+            let num_parts = rng.gen_range(1, 10);
+            let items: Vec<_> = (0..num_parts)
+                .map(|part_id| {
+                    trie.get_trie_items_for_part(part_id, num_parts, &state_root).unwrap()
+                })
+                .flatten()
+                .collect();
+            assert_eq!(items, expected);
+        }
+    }
 }
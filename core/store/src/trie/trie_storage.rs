@@ -1,12 +1,14 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use cached::{Cached, SizedCache};
+use serde::{Deserialize, Serialize};
 
+use near_metrics::{IntCounter, IntGauge};
 use near_primitives::hash::CryptoHash;
 
 use crate::db::refcount::decode_value_with_rc;
+use crate::metrics;
 use crate::trie::POISONED_LOCK_ERR;
 use crate::{ColState, StorageError, Store};
 use near_primitives::types::ShardId;
@@ -14,31 +16,325 @@ use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
 use std::io::ErrorKind;
 
+/// Configuration of the trie caches. Every shard has a cache of its own.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TrieCacheConfig {
+    /// Capacity of the cache of each shard in bytes.
+    pub shard_cache_size: u64,
+    /// Number of independently locked parts the cache of each shard is split into.
+    pub num_lock_shards: usize,
+    /// Number of top levels of the trie kept apart from the other nodes, so that reading
+    /// many values doesn't evict them. Zero disables pinning.
+    pub pinned_levels: usize,
+    /// Capacity of the pinned nodes of each shard in bytes, on top of `shard_cache_size`.
+    pub pinned_cache_size: u64,
+    /// Values above this size (in bytes) are never cached.
+    /// Note that Trie inner nodes are always smaller than the default.
+    pub max_cached_value_size: usize,
+    /// Number of threads loading the nodes read by upcoming transactions and receipts into
+    /// the caches in the background. Zero disables prefetching.
+    pub num_prefetch_threads: usize,
+}
+
+impl Default for TrieCacheConfig {
+    fn default() -> Self {
+        TrieCacheConfig {
+            shard_cache_size: DEFAULT_SHARD_CACHE_SIZE,
+            num_lock_shards: 16,
+            pinned_levels: 0,
+            pinned_cache_size: 10_000_000,
+            max_cached_value_size: 4000,
+            num_prefetch_threads: 4,
+        }
+    }
+}
+
+/// Default capacity of the cache of each shard in bytes.
+#[cfg(not(feature = "no_cache"))]
+const DEFAULT_SHARD_CACHE_SIZE: u64 = 50_000_000;
+
+#[cfg(feature = "no_cache")]
+const DEFAULT_SHARD_CACHE_SIZE: u64 = 0;
+
+/// Approximate memory taken by a cache entry besides the value itself.
+const CACHE_ENTRY_OVERHEAD: u64 = 100;
+
+/// Least recently used cache bounded by the total size of the values.
+struct CacheEntry {
+    last_access: u64,
+    value: Vec<u8>,
+    /// Whether the value was loaded by the prefetcher and hasn't been read since.
+    prefetched: bool,
+}
+
+struct BoundedCache {
+    capacity: u64,
+    size: u64,
+    /// Incremented on every access, orders the entries by the time of the last access.
+    tick: u64,
+    entries: HashMap<CryptoHash, CacheEntry>,
+    by_last_access: BTreeMap<u64, CryptoHash>,
+}
+
+fn entry_size(value: &[u8]) -> u64 {
+    value.len() as u64 + CACHE_ENTRY_OVERHEAD
+}
+
+impl BoundedCache {
+    fn new(capacity: u64) -> Self {
+        BoundedCache {
+            capacity,
+            size: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            by_last_access: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the value and whether it was prefetched. Reads by the prefetcher itself
+    /// don't reset the prefetched flag.
+    fn get(&mut self, hash: &CryptoHash, is_prefetch: bool) -> Option<(Vec<u8>, bool)> {
+        let tick = self.tick;
+        let entry = self.entries.get_mut(hash)?;
+        self.by_last_access.remove(&entry.last_access);
+        self.by_last_access.insert(tick, *hash);
+        entry.last_access = tick;
+        self.tick += 1;
+        let prefetched = entry.prefetched;
+        if !is_prefetch {
+            entry.prefetched = false;
+        }
+        Some((entry.value.clone(), prefetched))
+    }
+
+    /// Inserts the value and evicts the least recently used entries until the cache fits
+    /// its capacity. Returns the change of the size and the number of evicted entries.
+    fn put(&mut self, hash: CryptoHash, value: Vec<u8>, prefetched: bool) -> (i64, u64) {
+        let old_size = self.size;
+        let size = entry_size(&value);
+        if size > self.capacity {
+            self.remove(&hash);
+            return (self.size as i64 - old_size as i64, 0);
+        }
+        let tick = self.tick;
+        self.tick += 1;
+        let entry = CacheEntry { last_access: tick, value, prefetched };
+        if let Some(old_entry) = self.entries.insert(hash, entry) {
+            self.by_last_access.remove(&old_entry.last_access);
+            self.size -= entry_size(&old_entry.value);
+        }
+        self.by_last_access.insert(tick, hash);
+        self.size += size;
+
+        let mut evicted = 0;
+        while self.size > self.capacity {
+            let (&last_access, &evicted_hash) =
+                self.by_last_access.iter().next().expect("Cache size is positive");
+            self.by_last_access.remove(&last_access);
+            let evicted_entry =
+                self.entries.remove(&evicted_hash).expect("Every access has an entry");
+            self.size -= entry_size(&evicted_entry.value);
+            evicted += 1;
+        }
+        (self.size as i64 - old_size as i64, evicted)
+    }
+
+    /// Removes the value, returns the change of the size.
+    fn remove(&mut self, hash: &CryptoHash) -> i64 {
+        match self.entries.remove(hash) {
+            Some(entry) => {
+                self.by_last_access.remove(&entry.last_access);
+                let size = entry_size(&entry.value);
+                self.size -= size;
+                -(size as i64)
+            }
+            None => 0,
+        }
+    }
+
+    /// Removes all values, returns the change of the size.
+    fn clear(&mut self) -> i64 {
+        let size = self.size;
+        self.entries.clear();
+        self.by_last_access.clear();
+        self.size = 0;
+        -(size as i64)
+    }
+}
+
+struct TrieCacheMetrics {
+    hits: Option<IntCounter>,
+    prefetched_hits: Option<IntCounter>,
+    misses: Option<IntCounter>,
+    evictions: Option<IntCounter>,
+    size: Option<IntGauge>,
+}
+
+impl TrieCacheMetrics {
+    fn new(shard_id: ShardId, is_view: bool) -> Self {
+        let labels = [shard_id.to_string(), is_view.to_string()];
+        let labels = [labels[0].as_str(), labels[1].as_str()];
+        TrieCacheMetrics {
+            hits: metrics::TRIE_CACHE_HITS.as_ref().ok().map(|c| c.with_label_values(&labels)),
+            prefetched_hits: metrics::TRIE_CACHE_PREFETCHED_HITS
+                .as_ref()
+                .ok()
+                .map(|c| c.with_label_values(&labels)),
+            misses: metrics::TRIE_CACHE_MISSES.as_ref().ok().map(|c| c.with_label_values(&labels)),
+            evictions: metrics::TRIE_CACHE_EVICTIONS
+                .as_ref()
+                .ok()
+                .map(|c| c.with_label_values(&labels)),
+            size: metrics::TRIE_CACHE_SIZE.as_ref().ok().map(|g| g.with_label_values(&labels)),
+        }
+    }
+
+    fn record_put(&self, (size_change, evicted): (i64, u64)) {
+        self.record_size_change(size_change);
+        near_metrics::inc_counter_by_opt(self.evictions.as_ref(), evicted);
+    }
+
+    fn record_size_change(&self, size_change: i64) {
+        if let Some(size) = &self.size {
+            size.add(size_change);
+        }
+    }
+}
+
+struct TrieCacheInner {
+    /// Parts of the cache, a node goes to the part chosen by the first byte of its hash.
+    parts: Vec<Mutex<BoundedCache>>,
+    /// Nodes from the top `pinned_levels` levels of the trie.
+    pinned: Mutex<BoundedCache>,
+    pinned_levels: usize,
+    max_cached_value_size: usize,
+    metrics: TrieCacheMetrics,
+}
+
+impl Drop for TrieCacheInner {
+    fn drop(&mut self) {
+        // The size gauge is shared by all caches of the shard, e.g. ones of temporary tries.
+        let mut size = self.pinned.get_mut().map_or(0, |pinned| pinned.size);
+        for part in self.parts.iter_mut() {
+            size += part.get_mut().map_or(0, |part| part.size);
+        }
+        self.metrics.record_size_change(-(size as i64));
+    }
+}
+
+/// Cache of the trie nodes and values of a shard, shared by the tries of the shard.
 #[derive(Clone)]
-pub struct TrieCache(Arc<Mutex<SizedCache<CryptoHash, Vec<u8>>>>);
+pub struct TrieCache(Arc<TrieCacheInner>);
 
 impl TrieCache {
     pub fn new() -> Self {
-        Self(Arc::new(Mutex::new(SizedCache::with_size(TRIE_MAX_CACHE_SIZE))))
+        Self::with_config(&TrieCacheConfig::default(), 0, false)
+    }
+
+    pub fn with_config(config: &TrieCacheConfig, shard_id: ShardId, is_view: bool) -> Self {
+        let num_lock_shards = std::cmp::max(config.num_lock_shards, 1);
+        let part_capacity = config.shard_cache_size / num_lock_shards as u64;
+        Self(Arc::new(TrieCacheInner {
+            parts: (0..num_lock_shards)
+                .map(|_| Mutex::new(BoundedCache::new(part_capacity)))
+                .collect(),
+            pinned: Mutex::new(BoundedCache::new(config.pinned_cache_size)),
+            pinned_levels: config.pinned_levels,
+            max_cached_value_size: config.max_cached_value_size,
+            metrics: TrieCacheMetrics::new(shard_id, is_view),
+        }))
+    }
+
+    fn part(&self, hash: &CryptoHash) -> &Mutex<BoundedCache> {
+        let parts = &self.0.parts;
+        &parts[hash.as_ref()[0] as usize % parts.len()]
+    }
+
+    fn is_pinned_depth(&self, depth: usize) -> bool {
+        depth < self.0.pinned_levels
+    }
+
+    /// Gets the value, looking among the pinned nodes first if the node is `depth` levels
+    /// below the root. Reads by the prefetcher are not counted in the metrics.
+    fn get(&self, hash: &CryptoHash, depth: Option<usize>, is_prefetch: bool) -> Option<Vec<u8>> {
+        let is_pinned = depth.map_or(false, |depth| self.is_pinned_depth(depth));
+        let mut result = None;
+        if is_pinned {
+            result = self.0.pinned.lock().expect(POISONED_LOCK_ERR).get(hash, is_prefetch);
+        }
+        if result.is_none() {
+            result = self.part(hash).lock().expect(POISONED_LOCK_ERR).get(hash, is_prefetch);
+            if is_pinned {
+                if let Some((value, prefetched)) = &result {
+                    self.pin(*hash, value.clone(), is_prefetch && *prefetched);
+                }
+            }
+        }
+        if !is_prefetch {
+            let metrics = &self.0.metrics;
+            match &result {
+                Some((_, prefetched)) => {
+                    near_metrics::inc_counter_opt(metrics.hits.as_ref());
+                    if *prefetched {
+                        near_metrics::inc_counter_opt(metrics.prefetched_hits.as_ref());
+                    }
+                }
+                None => near_metrics::inc_counter_opt(metrics.misses.as_ref()),
+            }
+        }
+        result.map(|(value, _)| value)
+    }
+
+    /// Caches the value read from the store, pinned if the node is `depth` levels below the root.
+    fn put(&self, hash: CryptoHash, value: &[u8], depth: Option<usize>, is_prefetch: bool) {
+        if value.len() >= self.0.max_cached_value_size {
+            return;
+        }
+        let cache = if depth.map_or(false, |depth| self.is_pinned_depth(depth)) {
+            &self.0.pinned
+        } else {
+            self.part(&hash)
+        };
+        let result = cache.lock().expect(POISONED_LOCK_ERR).put(hash, value.to_vec(), is_prefetch);
+        self.0.metrics.record_put(result);
+    }
+
+    /// Moves the node to the pinned ones. Nodes written by `update_cache` don't know their depth,
+    /// so they go to the pinned nodes once they are read at a pinned depth.
+    fn pin(&self, hash: CryptoHash, value: Vec<u8>, prefetched: bool) {
+        let size_change = self.part(&hash).lock().expect(POISONED_LOCK_ERR).remove(&hash);
+        let (pinned_size_change, evicted) =
+            self.0.pinned.lock().expect(POISONED_LOCK_ERR).put(hash, value, prefetched);
+        self.0.metrics.record_put((size_change + pinned_size_change, evicted));
+    }
+
+    fn remove(&self, hash: &CryptoHash) {
+        let mut size_change = self.part(hash).lock().expect(POISONED_LOCK_ERR).remove(hash);
+        if self.0.pinned_levels > 0 {
+            size_change += self.0.pinned.lock().expect(POISONED_LOCK_ERR).remove(hash);
+        }
+        self.0.metrics.record_size_change(size_change);
     }
 
     pub fn clear(&self) {
-        self.0.lock().expect(POISONED_LOCK_ERR).cache_clear()
+        let mut size_change = self.0.pinned.lock().expect(POISONED_LOCK_ERR).clear();
+        for part in self.0.parts.iter() {
+            size_change += part.lock().expect(POISONED_LOCK_ERR).clear();
+        }
+        self.0.metrics.record_size_change(size_change);
     }
 
     pub fn update_cache(&self, ops: Vec<(CryptoHash, Option<Vec<u8>>)>) {
-        let mut guard = self.0.lock().expect(POISONED_LOCK_ERR);
         for (hash, opt_value_rc) in ops {
             if let Some(value_rc) = opt_value_rc {
                 if let (Some(value), _rc) = decode_value_with_rc(&value_rc) {
-                    if value.len() < TRIE_LIMIT_CACHED_VALUE_SIZE {
-                        guard.cache_set(hash, value.to_vec());
-                    }
+                    self.put(hash, value, None, false);
                 } else {
-                    guard.cache_remove(&hash);
+                    self.remove(&hash);
                 }
             } else {
-                guard.cache_remove(&hash);
+                self.remove(&hash);
             }
         }
     }
@@ -50,6 +346,16 @@ pub trait TrieStorage {
     /// StorageError if the storage fails internally or the hash is not present.
     fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError>;
 
+    /// Same as `retrieve_raw_bytes` for a trie node `depth` levels below the root.
+    /// Lets the caching storage keep the top levels of the trie pinned.
+    fn retrieve_raw_node_bytes(
+        &self,
+        hash: &CryptoHash,
+        _depth: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.retrieve_raw_bytes(hash)
+    }
+
     fn as_caching_storage(&self) -> Option<&TrieCachingStorage> {
         None
     }
@@ -118,26 +424,25 @@ impl TrieStorage for TrieMemoryPartialStorage {
     }
 }
 
-/// Maximum number of cache entries.
-#[cfg(not(feature = "no_cache"))]
-const TRIE_MAX_CACHE_SIZE: usize = 10000;
-
-#[cfg(feature = "no_cache")]
-const TRIE_MAX_CACHE_SIZE: usize = 1;
-
-/// Values above this size (in bytes) are never cached.
-/// Note that Trie inner nodes are always smaller than this.
-const TRIE_LIMIT_CACHED_VALUE_SIZE: usize = 4000;
-
 pub struct TrieCachingStorage {
     pub(crate) store: Arc<Store>,
     pub(crate) cache: TrieCache,
     pub(crate) shard_id: ShardId,
+    /// Whether the storage is used by the prefetcher to load nodes into the cache.
+    pub(crate) is_prefetch: bool,
 }
 
 impl TrieCachingStorage {
     pub fn new(store: Arc<Store>, cache: TrieCache, shard_id: ShardId) -> TrieCachingStorage {
-        TrieCachingStorage { store, cache, shard_id }
+        TrieCachingStorage { store, cache, shard_id, is_prefetch: false }
+    }
+
+    pub(crate) fn new_prefetching(
+        store: Arc<Store>,
+        cache: TrieCache,
+        shard_id: ShardId,
+    ) -> TrieCachingStorage {
+        TrieCachingStorage { store, cache, shard_id, is_prefetch: true }
     }
 
     pub(crate) fn get_shard_id_and_hash_from_key(
@@ -159,28 +464,42 @@ impl TrieCachingStorage {
     }
 }
 
-impl TrieStorage for TrieCachingStorage {
-    fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
-        let mut guard = self.cache.0.lock().expect(POISONED_LOCK_ERR);
-        if let Some(val) = guard.cache_get(hash) {
-            Ok(val.clone())
+impl TrieCachingStorage {
+    fn retrieve_raw_bytes_at_depth(
+        &self,
+        hash: &CryptoHash,
+        depth: Option<usize>,
+    ) -> Result<Vec<u8>, StorageError> {
+        if let Some(val) = self.cache.get(hash, depth, self.is_prefetch) {
+            return Ok(val);
+        }
+        let key = Self::get_key_from_shard_id_and_hash(self.shard_id, hash);
+        let val = self
+            .store
+            .get(ColState, key.as_ref())
+            .map_err(|_| StorageError::StorageInternalError)?;
+        if let Some(val) = val {
+            self.cache.put(*hash, &val, depth, self.is_prefetch);
+            Ok(val)
         } else {
-            let key = Self::get_key_from_shard_id_and_hash(self.shard_id, hash);
-            let val = self
-                .store
-                .get(ColState, key.as_ref())
-                .map_err(|_| StorageError::StorageInternalError)?;
-            if let Some(val) = val {
-                if val.len() < TRIE_LIMIT_CACHED_VALUE_SIZE {
-                    guard.cache_set(*hash, val.clone());
-                }
-                Ok(val)
-            } else {
-                // not StorageError::TrieNodeMissing because it's only for TrieMemoryPartialStorage
-                Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
-            }
+            // not StorageError::TrieNodeMissing because it's only for TrieMemoryPartialStorage
+            Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
         }
     }
+}
+
+impl TrieStorage for TrieCachingStorage {
+    fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
+        self.retrieve_raw_bytes_at_depth(hash, None)
+    }
+
+    fn retrieve_raw_node_bytes(
+        &self,
+        hash: &CryptoHash,
+        depth: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.retrieve_raw_bytes_at_depth(hash, Some(depth))
+    }
 
     fn as_caching_storage(&self) -> Option<&TrieCachingStorage> {
         Some(self)
@@ -207,3 +526,113 @@ impl TouchedNodesCounter {
         self.counter.load(Ordering::SeqCst)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use near_primitives::hash::hash;
+    use near_primitives::receipt::Receipt;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::AccountId;
+
+    use crate::test_utils::{create_test_store, test_populate_trie};
+    use crate::{ShardTries, Trie};
+
+    use super::*;
+
+    #[test]
+    fn test_bounded_cache_evicts_least_recently_used() {
+        let values: Vec<_> = (0..4u8).map(|i| vec![i; 100]).collect();
+        let hashes: Vec<_> = values.iter().map(|value| hash(value)).collect();
+        let mut cache = BoundedCache::new(3 * entry_size(&values[0]));
+        for i in 0..3 {
+            let size = entry_size(&values[i]) as i64;
+            assert_eq!(cache.put(hashes[i], values[i].clone(), false), (size, 0));
+        }
+        assert_eq!(cache.get(&hashes[0], false), Some((values[0].clone(), false)));
+        assert_eq!(cache.put(hashes[3], values[3].clone(), true), (0, 1));
+        assert_eq!(cache.get(&hashes[1], false), None);
+        assert_eq!(cache.get(&hashes[0], false), Some((values[0].clone(), false)));
+        // The prefetched flag is reset by the first read which is not a prefetch
+        assert_eq!(cache.get(&hashes[3], true), Some((values[3].clone(), true)));
+        assert_eq!(cache.get(&hashes[3], false), Some((values[3].clone(), true)));
+        assert_eq!(cache.get(&hashes[3], false), Some((values[3].clone(), false)));
+        assert_eq!(cache.size, 3 * entry_size(&values[0]));
+
+        // Values larger than the whole cache are not cached
+        let mut cache = BoundedCache::new(entry_size(&values[0]) - 1);
+        assert_eq!(cache.put(hashes[0], values[0].clone(), false), (0, 0));
+        assert_eq!(cache.get(&hashes[0], false), None);
+    }
+
+    #[test]
+    fn test_trie_cache_pins_top_levels() {
+        let config =
+            TrieCacheConfig { shard_cache_size: 0, pinned_levels: 1, ..TrieCacheConfig::default() };
+        let tries = ShardTries::with_cache_config(create_test_store(), 1, &config);
+        let changes = (0..10u8).map(|i| (vec![i, i], Some(vec![i; 10]))).collect();
+        let root = test_populate_trie(&tries, &Trie::empty_root(), 0, changes);
+
+        let trie = tries.get_trie_for_shard(0);
+        assert_eq!(trie.get(&root, &[1, 1]), Ok(Some(vec![1; 10])));
+        let cache = &trie.storage.as_caching_storage().unwrap().cache;
+        let pinned = cache.0.pinned.lock().unwrap();
+        assert!(pinned.entries.contains_key(&root));
+        assert!(!pinned.entries.contains_key(&hash(&[1; 10])));
+        assert!(cache.0.parts.iter().all(|part| part.lock().unwrap().entries.is_empty()));
+    }
+
+    #[test]
+    fn test_trie_cache_pins_nodes_written_by_update() {
+        let config = TrieCacheConfig { pinned_levels: 1, ..TrieCacheConfig::default() };
+        let tries = ShardTries::with_cache_config(create_test_store(), 1, &config);
+        let changes = (0..10u8).map(|i| (vec![i, i], Some(vec![i; 10])));
+        let trie = tries.get_trie_for_shard(0);
+        let trie_changes = trie.update(&Trie::empty_root(), changes).unwrap();
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let cache = &trie.storage.as_caching_storage().unwrap().cache;
+        let is_in_parts = |hash: &CryptoHash| {
+            cache.0.parts.iter().any(|part| part.lock().unwrap().entries.contains_key(hash))
+        };
+        // Applying the changes caches the nodes without knowing their depth
+        assert!(is_in_parts(&root));
+        assert!(!cache.0.pinned.lock().unwrap().entries.contains_key(&root));
+
+        assert_eq!(trie.get(&root, &[1, 1]), Ok(Some(vec![1; 10])));
+        assert!(!is_in_parts(&root));
+        assert!(cache.0.pinned.lock().unwrap().entries.contains_key(&root));
+        assert!(!cache.0.pinned.lock().unwrap().entries.contains_key(&hash(&[1; 10])));
+        assert!(is_in_parts(&hash(&[1; 10])));
+    }
+
+    #[test]
+
+    fn test_prefetch_receipts() {
+        let tries =
+            ShardTries::with_cache_config(create_test_store(), 1, &TrieCacheConfig::default());
+        let account_id: AccountId = "alice.near".parse().unwrap();
+        let key = TrieKey::Account { account_id: account_id.clone() }.to_vec();
+        let changes = vec![(key.clone(), Some(vec![1; 10])), (vec![2, 2], Some(vec![2; 10]))];
+        let root = test_populate_trie(&tries, &Trie::empty_root(), 0, changes);
+        let cache = tries.caches[0].clone();
+        cache.clear();
+
+        tries.prefetch(0, root, &[], &[Receipt::new_balance_refund(&account_id, 1)]);
+        let value_hash = hash(&[1; 10]);
+        let is_cached =
+            || cache.part(&value_hash).lock().unwrap().entries.contains_key(&value_hash);
+        let start = Instant::now();
+        while !is_cached() {
+            assert!(start.elapsed() < Duration::from_secs(10), "Account was not prefetched");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let trie = tries.get_trie_for_shard(0);
+        assert_eq!(trie.get(&root, &key), Ok(Some(vec![1; 10])));
+        assert!(!cache.part(&value_hash).lock().unwrap().entries[&value_hash].prefetched);
+        assert!(!cache.part(&hash(&[2; 10])).lock().unwrap().entries.contains_key(&hash(&[2; 10])));
+    }
+}
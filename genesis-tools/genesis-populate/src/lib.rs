@@ -18,6 +18,7 @@ use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{genesis_chunks, Tip};
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::receipt::CongestionInfo;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::{AccountId, Balance, EpochId, ShardId, StateChangeCause, StateRoot};
@@ -121,7 +122,8 @@ impl GenesisBuilder {
         self.unflushed_records =
             self.roots.keys().cloned().map(|shard_idx| (shard_idx, vec![])).collect();
 
-        let total_accounts_num = self.additional_accounts_num * self.runtime.num_shards();
+        let num_shards = self.runtime.num_shards(&EpochId::default())?;
+        let total_accounts_num = self.additional_accounts_num * num_shards;
         let bar = ProgressBar::new(total_accounts_num as _);
         bar.set_style(ProgressStyle::default_bar().template(
             "[elapsed {elapsed_precise} remaining {eta_precise}] Writing into storage {bar} {pos:>7}/{len:7}",
@@ -133,7 +135,7 @@ impl GenesisBuilder {
             bar.inc(1);
         }
 
-        for shard_id in 0..self.runtime.num_shards() {
+        for shard_id in 0..num_shards {
             self.flush_shard_records(shard_id)?;
         }
         bar.finish();
@@ -181,7 +183,7 @@ impl GenesisBuilder {
     fn write_genesis_block(&mut self) -> Result<()> {
         let genesis_chunks = genesis_chunks(
             self.roots.values().cloned().collect(),
-            self.runtime.num_shards(),
+            self.runtime.num_shards(&EpochId::default())?,
             self.genesis.config.gas_limit,
             self.genesis.config.genesis_height,
             self.genesis.config.protocol_version,
@@ -212,7 +214,7 @@ impl GenesisBuilder {
         for (chunk_header, state_root) in genesis.chunks().iter().zip(self.roots.values()) {
             store_update.save_chunk_extra(
                 &genesis.hash(),
-                chunk_header.shard_id(),
+                &self.runtime.shard_id_to_uid(chunk_header.shard_id(), &EpochId::default())?,
                 ChunkExtra::new(
                     state_root,
                     CryptoHash::default(),
@@ -220,6 +222,8 @@ impl GenesisBuilder {
                     0,
                     self.genesis.config.gas_limit.clone(),
                     0,
+                    CongestionInfo::default(),
+                    0,
                 ),
             );
         }
@@ -235,7 +239,7 @@ impl GenesisBuilder {
     fn add_additional_account(&mut self, account_id: AccountId) -> Result<()> {
         let testing_init_balance: Balance = 10u128.pow(30);
         let testing_init_stake: Balance = 0;
-        let shard_id = self.runtime.account_id_to_shard_id(&account_id);
+        let shard_id = self.runtime.account_id_to_shard_id(&account_id, &EpochId::default())?;
         let mut records = self.unflushed_records.remove(&shard_id).unwrap_or_default();
         let mut state_update =
             self.state_updates.remove(&shard_id).expect("State update should have been added");
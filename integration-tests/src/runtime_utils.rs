@@ -29,7 +29,7 @@ pub fn get_runtime_and_trie_from_genesis(genesis: &Genesis) -> (Runtime, ShardTr
     let runtime = Runtime::new();
     let mut account_ids: HashSet<AccountId> = HashSet::new();
     genesis.for_each_record(|record: &StateRecord| {
-        account_ids.insert(state_record_to_account_id(record).clone());
+        account_ids.extend(state_record_to_account_id(record).cloned());
     });
     let genesis_root = runtime.apply_genesis_state(
         tries.clone(),
@@ -9,6 +9,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::Receipt;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::test_utils::MockEpochInfoProvider;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockHeightDelta, MerkleHash};
@@ -144,6 +145,8 @@ impl RuntimeUser {
             config: self.runtime_config.clone(),
             cache: None,
             is_new_chunk: true,
+            shard_layout: ShardLayout::v0(1),
+            congested_shards: vec![],
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
         }
@@ -324,6 +324,9 @@ fn test_verify_chunk_invalid_state_challenge() {
             &vec![],
             last_block.chunks()[0].outgoing_receipts_root(),
             CryptoHash::default(),
+            Default::default(),
+            vec![],
+            0,
             &validator_signer,
             &mut rs,
             PROTOCOL_VERSION,
@@ -478,9 +481,12 @@ fn test_receive_invalid_chunk_as_chunk_producer() {
     assert!(result.is_err());
     assert_eq!(client.chain.head().unwrap().height, 1);
     // But everyone who doesn't track this shard have accepted.
-    let receipts_hashes = env.clients[0].runtime_adapter.build_receipts_hashes(&receipts);
+    let epoch_id = env.clients[0].chain.head().unwrap().epoch_id;
+    let receipts_hashes =
+        env.clients[0].runtime_adapter.build_receipts_hashes(&receipts, &epoch_id).unwrap();
     let (_receipts_root, receipts_proofs) = merklize(&receipts_hashes);
-    let receipts_by_shard = env.clients[0].shards_mgr.group_receipts_by_shard(receipts.clone());
+    let receipts_by_shard =
+        env.clients[0].shards_mgr.group_receipts_by_shard(receipts.clone(), &epoch_id).unwrap();
     let one_part_receipt_proofs = env.clients[0].shards_mgr.receipts_recipient_filter(
         0,
         Vec::default(),
@@ -0,0 +1,166 @@
+use near_chain::ChainGenesis;
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_crypto::{InMemorySigner, KeyType};
+use near_logger_utils::init_test_logger;
+use near_primitives::block::Block;
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::AccountId;
+use nearcore::config::GenesisExt;
+
+use crate::process_blocks::create_nightshade_runtimes;
+
+/// Chunks with a tiny gas limit delay most of the receipts generated by a burst of transfers.
+/// Check that every chunk header publishes the delayed receipt queue left behind by the previous
+/// chunk of its shard and that the queue drains back to empty.
+#[test]
+fn test_chunk_headers_report_delayed_receipts() {
+    init_test_logger();
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = 5;
+    // set gas limit to be small
+    genesis.config.gas_limit = 1_000_000;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    for i in 0..10 {
+        let tx = SignedTransaction::send_money(
+            i + 1,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            1,
+            *genesis_block.hash(),
+        );
+        env.clients[0].process_tx(tx, false, false);
+    }
+
+    let mut max_delayed_receipts = 0;
+    let mut height = 1;
+    loop {
+        height += 1;
+        assert!(height < 50, "delayed receipts were never processed");
+        env.produce_block(0, height);
+        let block = env.clients[0].chain.get_block_by_height(height).unwrap().clone();
+        let congestion_info = block.chunks()[0].congestion_info().unwrap();
+        let prev_chunk_extra = env.clients[0]
+            .chain
+            .get_chunk_extra(block.header().prev_hash(), &ShardUId::single_shard())
+            .unwrap();
+        assert_eq!(prev_chunk_extra.congestion_info(), Some(congestion_info));
+        // Transfers don't carry prepaid gas, so none of them count towards the backlog gas.
+        assert_eq!(congestion_info.delayed_receipts_gas, 0);
+        assert!(!congestion_info.is_congested(genesis.config.gas_limit));
+        max_delayed_receipts = max_delayed_receipts.max(congestion_info.delayed_receipts_count);
+        if max_delayed_receipts > 0 && congestion_info.delayed_receipts_count == 0 {
+            break;
+        }
+    }
+    assert!(max_delayed_receipts > 0);
+}
+
+/// Two shards with a tiny chunk gas limit. Function calls within the first shard fill its delayed
+/// receipts queue with prepaid gas until the shard reports congestion. Check that a transfer
+/// towards the congested shard from the other shard stays in the pool while the congestion is
+/// reported, and is included once the shard catches up.
+#[test]
+fn test_transactions_postponed_towards_congested_shard() {
+    init_test_logger();
+    let accounts: Vec<AccountId> = (0..4).map(|i| format!("test{}", i).parse().unwrap()).collect();
+    let mut genesis = Genesis::test_sharded(accounts.clone(), 1, vec![1, 1]);
+    genesis.config.epoch_length = 100;
+    // set gas limit to be small
+    genesis.config.gas_limit = 1_000_000;
+    let shard_layout = ShardLayout::v0(2);
+    let congested_account = accounts[0].clone();
+    let congested_shard_id = account_id_to_shard_id(&congested_account, &shard_layout);
+    let sender = accounts
+        .iter()
+        .find(|account_id| account_id_to_shard_id(account_id, &shard_layout) != congested_shard_id)
+        .expect("all the accounts are in the same shard")
+        .clone();
+    let sender_shard_id = account_id_to_shard_id(&sender, &shard_layout);
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
+    let signer = InMemorySigner::from_seed(
+        congested_account.clone(),
+        KeyType::ED25519,
+        congested_account.as_ref(),
+    );
+    // Every chunk converts a single transaction, and the receipts of the function calls are
+    // delayed with their prepaid gas.
+    for i in 0..3 {
+        let tx = SignedTransaction::call(
+            i + 1,
+            congested_account.clone(),
+            congested_account.clone(),
+            &signer,
+            0,
+            "hello".to_string(),
+            vec![],
+            10u64.pow(14),
+            *genesis_block.hash(),
+        );
+        env.clients[0].process_tx(tx, false, false);
+    }
+
+    let sender_signer =
+        InMemorySigner::from_seed(sender.clone(), KeyType::ED25519, sender.as_ref());
+    // The transfer towards the congested shard and the height of the block it was sent after.
+    let mut transfer: Option<(SignedTransaction, u64)> = None;
+    let mut num_postponed_chunks = 0;
+    let mut prev_is_congested = false;
+    let mut height = 1;
+    loop {
+        height += 1;
+        assert!(height < 50, "the transfer was never included");
+        env.produce_block(0, height);
+        let block = env.clients[0].chain.get_block_by_height(height).unwrap().clone();
+        let chunk_header = block.chunks()[sender_shard_id as usize].clone();
+        let is_included = match &transfer {
+            Some((tx, _)) if chunk_header.height_included() == height => env.clients[0]
+                .chain
+                .get_chunk(&chunk_header.chunk_hash())
+                .unwrap()
+                .transactions()
+                .contains(tx),
+            _ => false,
+        };
+        if let Some((tx, sent_height)) = &transfer {
+            // The chunk was prepared on top of the previous block, while the transfer was in the
+            // pool.
+            if *sent_height < height - 1 && prev_is_congested {
+                assert!(!is_included, "transfer included towards a congested shard");
+                num_postponed_chunks += 1;
+            }
+            if is_included {
+                break;
+            }
+            assert!(
+                !env.clients[0].shards_mgr.insert_transaction(sender_shard_id, tx.clone()),
+                "transfer left the pool without being included"
+            );
+        }
+
+        prev_is_congested =
+            Block::compute_congested_shards(block.chunks().iter()).contains(&congested_shard_id);
+        if prev_is_congested && transfer.is_none() {
+            let tx = SignedTransaction::send_money(
+                1,
+                sender.clone(),
+                congested_account.clone(),
+                &sender_signer,
+                1,
+                *block.hash(),
+            );
+            env.clients[0].process_tx(tx.clone(), false, false);
+            transfer = Some((tx, height));
+        }
+    }
+    assert!(num_postponed_chunks > 0);
+}
@@ -1,7 +1,12 @@
 mod challenges;
+#[cfg(feature = "protocol_feature_congestion_control")]
+mod congestion_control;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 mod chunks_management;
 mod process_blocks;
 mod runtimes;
 #[cfg(feature = "sandbox")]
 mod sandbox;
+#[cfg(feature = "protocol_feature_simple_nightshade")]
+mod sharding_upgrade;
+mod state_dump;
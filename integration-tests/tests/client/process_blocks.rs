@@ -15,14 +15,17 @@ use near_chain::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA;
 use near_chain::types::LatestKnown;
 use near_chain::validate::validate_chunk_with_chunk_extra;
 use near_chain::{
-    Block, ChainGenesis, ChainStore, ChainStoreAccess, ErrorKind, Provenance, RuntimeAdapter,
+    Block, ChainGenesis, ChainStore, ChainStoreAccess, ErrorKind, GCDataCategory, Provenance,
+    RuntimeAdapter,
 };
-use near_chain_configs::{ClientConfig, Genesis};
+use near_chain_configs::{ClientConfig, GCRetentionConfig, Genesis};
 use near_chunks::{ChunkStatus, ShardsManager};
 use near_client::test_utils::{create_chunk_on_height, setup_mock_all_validators};
 use near_client::test_utils::{setup_client, setup_mock, TestEnv};
 use near_client::{Client, GetBlock, GetBlockWithMerkleTree};
+use near_client_primitives::types::TxStatusError;
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
+use near_jsonrpc_primitives::types::transactions::RpcTransactionError;
 use near_logger_utils::init_test_logger;
 use near_network::routing::EdgeInfo;
 use near_network::test_utils::{wait_or_panic, MockNetworkAdapter};
@@ -39,6 +42,7 @@ use near_primitives::errors::TxExecutionError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::verify_hash;
 use near_primitives::receipt::DelayedReceiptIndices;
+use near_primitives::shard_layout::ShardUId;
 #[cfg(not(feature = "protocol_feature_block_header_v3"))]
 use near_primitives::sharding::ShardChunkHeaderV2;
 use near_primitives::sharding::{EncodedShardChunk, ReedSolomonWrapper, ShardChunkHeader};
@@ -915,6 +919,7 @@ fn client_sync_headers() {
             received_bytes_per_sec: 0,
             known_producers: vec![],
             peer_counter: 0,
+            peer_latencies: vec![],
         }));
         wait_or_panic(2000);
     });
@@ -963,6 +968,7 @@ fn test_process_invalid_tx() {
             receiver_id: AccountId::test_account(),
             block_hash: *client.chain.genesis().hash(),
             actions: vec![],
+            priority_fee: None,
         },
     );
     produce_blocks(&mut client, 12);
@@ -979,6 +985,7 @@ fn test_process_invalid_tx() {
             receiver_id: AccountId::test_account(),
             block_hash: hash(&[1]),
             actions: vec![],
+            priority_fee: None,
         },
     );
     assert_eq!(
@@ -1191,6 +1198,8 @@ fn test_bad_orphan() {
                 match &mut chunk.inner {
                     ShardChunkHeaderInner::V1(inner) => inner.outcome_root = CryptoHash([1; 32]),
                     ShardChunkHeaderInner::V2(inner) => inner.outcome_root = CryptoHash([1; 32]),
+                    #[cfg(feature = "protocol_feature_congestion_control")]
+                    ShardChunkHeaderInner::V3(inner) => inner.outcome_root = CryptoHash([1; 32]),
                 }
                 chunk.hash = ShardChunkHeaderV3::compute_hash(&chunk.inner);
             }
@@ -1508,6 +1517,92 @@ fn test_gc_execution_outcome() {
     assert!(env.clients[0].chain.get_final_transaction_result(&tx_hash).is_err());
 }
 
+/// Transactions and outcomes are kept for the number of epochs set in `GCRetentionConfig` after
+/// their blocks are garbage collected, and queries for them fail with `GarbageCollected` once
+/// they leave the retention window.
+#[test]
+fn test_gc_retention() {
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+    env.clients[0].config.gc_retention = GCRetentionConfig {
+        transactions: Some(NUM_EPOCHS_TO_KEEP_STORE_DATA + 2),
+        receipts: None,
+        outcomes: Some(NUM_EPOCHS_TO_KEEP_STORE_DATA + 4),
+    };
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    let tx_hash = tx.get_hash();
+
+    env.clients[0].process_tx(tx, false, false);
+    for i in 1..epoch_length {
+        env.produce_block(0, i);
+    }
+    let tx_block_hash = env.clients[0]
+        .chain
+        .get_final_transaction_result(&tx_hash)
+        .unwrap()
+        .transaction_outcome
+        .block_hash;
+    let tx_block_height = env.clients[0].chain.get_block_header(&tx_block_hash).unwrap().height();
+
+    // The blocks of the first epoch are garbage collected, the transaction and its outcome are not.
+    let mut height = epoch_length;
+    while height <= epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 1) + 1 {
+        env.produce_block(0, height);
+        height += 1;
+    }
+    let err = env.clients[0].chain.get_block(&tx_block_hash).unwrap_err();
+    let err = env.clients[0].chain.store().gc_error_for_height(
+        err,
+        GCDataCategory::Blocks,
+        tx_block_height,
+    );
+    assert!(matches!(err.kind(), ErrorKind::GarbageCollected(_)));
+    assert!(env.clients[0].chain.mut_store().get_transaction(&tx_hash).unwrap().is_some());
+    assert!(env.clients[0].chain.get_execution_outcome(&tx_hash).is_ok());
+    assert!(env.clients[0].chain.get_final_transaction_result(&tx_hash).is_ok());
+
+    // The transaction leaves its retention window, the outcome is still kept.
+    while height <= epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 3) + 1 {
+        env.produce_block(0, height);
+        height += 1;
+    }
+    assert!(env.clients[0].chain.mut_store().get_transaction(&tx_hash).unwrap().is_none());
+    assert!(env.clients[0].chain.get_execution_outcome(&tx_hash).is_ok());
+    let error_message = match env.clients[0].chain.get_final_transaction_result(&tx_hash) {
+        Err(err) => match err.kind() {
+            ErrorKind::GarbageCollected(error_message) => error_message,
+            kind => panic!("unexpected error: {:?}", kind),
+        },
+        Ok(_) => panic!("transaction must be garbage collected"),
+    };
+    assert!(matches!(
+        RpcTransactionError::from(TxStatusError::GarbageCollected(error_message)),
+        RpcTransactionError::GarbageCollected { .. }
+    ));
+
+    // The outcome leaves its retention window as well.
+    while height <= epoch_length * (NUM_EPOCHS_TO_KEEP_STORE_DATA + 5) + 1 {
+        env.produce_block(0, height);
+        height += 1;
+    }
+    assert!(env.clients[0].chain.get_execution_outcome(&tx_hash).is_err());
+    assert!(env.clients[0].chain.get_final_transaction_result(&tx_hash).is_err());
+}
+
 #[cfg(feature = "expensive_tests")]
 #[test]
 fn test_gc_after_state_sync() {
@@ -1537,7 +1632,7 @@ fn test_gc_after_state_sync() {
     // mimic what we do in possible_targets
     assert!(env.clients[1].runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash).is_ok());
     let tries = env.clients[1].runtime_adapter.get_tries();
-    assert!(env.clients[1].chain.clear_data(tries, 2).is_ok());
+    assert!(env.clients[1].chain.clear_data(tries, 2, &GCRetentionConfig::default()).is_ok());
 }
 
 #[test]
@@ -1555,7 +1650,11 @@ fn test_process_block_after_state_sync() {
     let sync_height = epoch_length * 4 + 1;
     let sync_block = env.clients[0].chain.get_block_by_height(sync_height).unwrap().clone();
     let sync_hash = *sync_block.hash();
-    let chunk_extra = env.clients[0].chain.get_chunk_extra(&sync_hash, 0).unwrap().clone();
+    let chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(&sync_hash, &ShardUId::single_shard())
+        .unwrap()
+        .clone();
     let state_part = env.clients[0]
         .runtime_adapter
         .obtain_state_part(0, chunk_extra.state_root(), 0, 1)
@@ -1975,6 +2074,7 @@ fn test_data_reset_before_state_sync() {
             &head.last_block_hash,
             head_block.header().epoch_id(),
             &QueryRequest::ViewAccount { account_id: "test_account".parse().unwrap() },
+            false,
         )
         .unwrap();
     assert!(matches!(response.kind, QueryResponseKind::ViewAccount(_)));
@@ -1989,6 +2089,7 @@ fn test_data_reset_before_state_sync() {
         &head.last_block_hash,
         head_block.header().epoch_id(),
         &QueryRequest::ViewAccount { account_id: "test_account".parse().unwrap() },
+        false,
     );
     // TODO(#3742): ViewClient still has data in cache by current design.
     assert!(response.is_ok());
@@ -2172,7 +2273,11 @@ fn test_validate_chunk_extra() {
 
     // About to produce a block on top of block1. Validate that this chunk is legit.
     let chunks = env.clients[0].shards_mgr.prepare_chunks(block1.hash());
-    let chunk_extra = env.clients[0].chain.get_chunk_extra(block1.hash(), 0).unwrap().clone();
+    let chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(block1.hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
     assert!(validate_chunk_with_chunk_extra(
         &mut chain_store,
         &*env.clients[0].runtime_adapter,
@@ -2256,7 +2361,10 @@ fn test_catchup_gas_price_change() {
     }
 
     assert_ne!(blocks[3].header().gas_price(), blocks[4].header().gas_price());
-    assert!(env.clients[1].chain.get_chunk_extra(blocks[4].hash(), 0).is_err());
+    assert!(env.clients[1]
+        .chain
+        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
+        .is_err());
 
     // Simulate state sync
     let sync_hash = *blocks[5].hash();
@@ -2284,10 +2392,16 @@ fn test_catchup_gas_price_change() {
             .unwrap();
     }
     env.clients[1].chain.set_state_finalize(0, sync_hash, num_parts).unwrap();
-    let chunk_extra_after_sync =
-        env.clients[1].chain.get_chunk_extra(blocks[4].hash(), 0).unwrap().clone();
-    let expected_chunk_extra =
-        env.clients[0].chain.get_chunk_extra(blocks[4].hash(), 0).unwrap().clone();
+    let chunk_extra_after_sync = env.clients[1]
+        .chain
+        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
+    let expected_chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(blocks[4].hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
     // The chunk extra of the prev block of sync block should be the same as the node that it is syncing from
     assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
 }
@@ -2413,8 +2527,11 @@ fn test_refund_receipts_processing() {
         let block = env.clients[0].chain.get_block_by_height(block_height).unwrap().clone();
         let prev_block =
             env.clients[0].chain.get_block_by_height(block_height - 1).unwrap().clone();
-        let chunk_extra =
-            env.clients[0].chain.get_chunk_extra(prev_block.hash(), 0).unwrap().clone();
+        let chunk_extra = env.clients[0]
+            .chain
+            .get_chunk_extra(prev_block.hash(), &ShardUId::single_shard())
+            .unwrap()
+            .clone();
         let state_update = env.clients[0]
             .runtime_adapter
             .get_tries()
@@ -2472,7 +2589,11 @@ fn test_refund_receipts_processing() {
         execution_outcomes_from_block.iter().for_each(|outcome| {
             processed_refund_receipt_ids.insert(outcome.outcome_with_id.id);
         });
-        let chunk_extra = env.clients[0].chain.get_chunk_extra(block.hash(), 0).unwrap().clone();
+        let chunk_extra = env.clients[0]
+            .chain
+            .get_chunk_extra(block.hash(), &ShardUId::single_shard())
+            .unwrap()
+            .clone();
         assert_eq!(execution_outcomes_from_block.len(), 1);
         assert!(chunk_extra.gas_used() >= chunk_extra.gas_limit());
     }
@@ -2585,6 +2706,7 @@ fn test_query_final_state() {
                 last_final_block.hash(),
                 last_final_block.header().epoch_id(),
                 &QueryRequest::ViewAccount { account_id },
+                false,
             )
             .unwrap();
         match response.kind {
@@ -2931,7 +3053,11 @@ fn test_congestion_receipt_execution() {
     let height = 4;
     env.produce_block(0, height);
     let prev_block = env.clients[0].chain.get_block_by_height(height).unwrap().clone();
-    let chunk_extra = env.clients[0].chain.get_chunk_extra(prev_block.hash(), 0).unwrap().clone();
+    let chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(prev_block.hash(), &ShardUId::single_shard())
+        .unwrap()
+        .clone();
     assert!(chunk_extra.gas_used() >= chunk_extra.gas_limit());
     let state_update =
         env.clients[0].runtime_adapter.get_tries().new_trie_update(0, *chunk_extra.state_root());
@@ -3336,8 +3462,12 @@ mod storage_usage_fix_tests {
             assert!(res.is_ok());
             env.clients[0].run_catchup(&vec![]).unwrap();
 
-            let root =
-                env.clients[0].chain.get_chunk_extra(block.hash(), 0).unwrap().state_root().clone();
+            let root = env.clients[0]
+                .chain
+                .get_chunk_extra(block.hash(), &ShardUId::single_shard())
+                .unwrap()
+                .state_root()
+                .clone();
             let trie = Rc::new(env.clients[0].runtime_adapter.get_trie_for_shard(0));
             let state_update = TrieUpdate::new(trie.clone(), root);
             use near_primitives::account::Account;
@@ -3442,7 +3572,11 @@ mod contract_precompilation_tests {
     fn state_sync_on_height(env: &mut TestEnv, height: BlockHeight) {
         let sync_block = env.clients[0].chain.get_block_by_height(height).unwrap().clone();
         let sync_hash = *sync_block.hash();
-        let chunk_extra = env.clients[0].chain.get_chunk_extra(&sync_hash, 0).unwrap().clone();
+        let chunk_extra = env.clients[0]
+            .chain
+            .get_chunk_extra(&sync_hash, &ShardUId::single_shard())
+            .unwrap()
+            .clone();
         let epoch_id =
             env.clients[0].chain.get_block_header(&sync_hash).unwrap().epoch_id().clone();
         let state_part = env.clients[0]
@@ -3521,7 +3655,8 @@ mod contract_precompilation_tests {
         // Note that we can't test that behaviour is the same on two clients, because
         // compile_module_cached_wasmer0 is cached by contract key via macro.
         let block = env.clients[0].chain.get_block_by_height(EPOCH_LENGTH).unwrap().clone();
-        let chunk_extra = env.clients[0].chain.get_chunk_extra(block.hash(), 0).unwrap();
+        let chunk_extra =
+            env.clients[0].chain.get_chunk_extra(block.hash(), &ShardUId::single_shard()).unwrap();
         let state_root = chunk_extra.state_root().clone();
 
         let viewer = TrieViewer::default();
@@ -150,6 +150,10 @@ fn test_process_partial_encoded_chunk_with_missing_block() {
                 ShardChunkHeaderInner::V2(inner) => {
                     inner.prev_block_hash = hash(b"some_prev_block")
                 }
+                #[cfg(feature = "protocol_feature_congestion_control")]
+                ShardChunkHeaderInner::V3(inner) => {
+                    inner.prev_block_hash = hash(b"some_prev_block")
+                }
             }
             header.init();
         }
@@ -0,0 +1,179 @@
+use near_chain::{ChainGenesis, RuntimeAdapter};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_crypto::{InMemorySigner, KeyType};
+use near_logger_utils::init_test_logger;
+use near_network::types::NetworkClientResponses;
+use near_primitives::epoch_manager::ShardConfig;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Balance, BlockHeight, NumShards};
+use near_primitives::version::ProtocolFeature;
+use near_primitives::views::{QueryRequest, QueryResponseKind};
+use nearcore::config::GenesisExt;
+
+use crate::process_blocks::create_nightshade_runtimes;
+
+const EPOCH_LENGTH: u64 = 5;
+/// Upper bound on the number of epochs the protocol upgrade may take to be voted in.
+const MAX_UPGRADE_EPOCHS: u64 = 6;
+/// Upper bound on the gas an account may have paid for the transfers it sent.
+const GAS_SLACK: Balance = 10u128.pow(22);
+
+fn accounts() -> Vec<AccountId> {
+    vec!["test0", "test1", "test2", "test3", "test4"]
+        .into_iter()
+        .map(|a| a.parse().unwrap())
+        .collect()
+}
+
+/// Layout splitting the only shard of the genesis layout into three shards.
+fn split_shard_layout() -> ShardLayout {
+    ShardLayout::v1(
+        vec![],
+        vec!["test1".parse().unwrap(), "test3".parse().unwrap()],
+        Some(vec![0, 0, 0]),
+        1,
+    )
+}
+
+/// Produces the block at `height` and waits until the background state split, if any, catches up.
+fn produce_block_and_catchup(env: &mut TestEnv, height: BlockHeight) {
+    env.produce_block(0, height);
+    while !env.clients[0].chain.store().iterate_state_sync_infos().is_empty() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let accepted_blocks = env.clients[0].run_catchup(&vec![]).unwrap();
+        for accepted_block in accepted_blocks {
+            env.clients[0].on_block_accepted(
+                accepted_block.hash,
+                accepted_block.status,
+                accepted_block.provenance,
+            );
+        }
+    }
+}
+
+fn num_shards_at_head(env: &TestEnv) -> NumShards {
+    let head = env.clients[0].chain.head().unwrap();
+    env.clients[0].runtime_adapter.num_shards(&head.epoch_id).unwrap()
+}
+
+/// Reads the balance of `account_id` from the shard it belongs to at the head of the chain.
+fn query_balance(env: &mut TestEnv, account_id: &AccountId) -> Balance {
+    let client = &mut env.clients[0];
+    let head = client.chain.head().unwrap();
+    let block = client.chain.get_block(&head.last_block_hash).unwrap().clone();
+    let runtime_adapter = client.runtime_adapter.clone();
+    let shard_id = runtime_adapter.account_id_to_shard_id(account_id, &head.epoch_id).unwrap();
+    let shard_uid = runtime_adapter.shard_id_to_uid(shard_id, &head.epoch_id).unwrap();
+    let state_root =
+        *client.chain.get_chunk_extra(&head.last_block_hash, &shard_uid).unwrap().state_root();
+    let response = runtime_adapter
+        .query(
+            shard_id,
+            &state_root,
+            block.header().height(),
+            block.header().raw_timestamp(),
+            block.header().prev_hash(),
+            block.hash(),
+            block.header().epoch_id(),
+            &QueryRequest::ViewAccount { account_id: account_id.clone() },
+            false,
+        )
+        .unwrap();
+    match response.kind {
+        QueryResponseKind::ViewAccount(account_view) => account_view.amount,
+        _ => panic!("Wrong return value"),
+    }
+}
+
+fn send_money(
+    env: &mut TestEnv,
+    from: &AccountId,
+    to: &AccountId,
+    nonce: u64,
+    amount: Balance,
+    block_hash: CryptoHash,
+) {
+    let signer = InMemorySigner::from_seed(from.clone(), KeyType::ED25519, from.as_ref());
+    let tx =
+        SignedTransaction::send_money(nonce, from.clone(), to.clone(), &signer, amount, block_hash);
+    assert_eq!(env.clients[0].process_tx(tx, false, false), NetworkClientResponses::ValidTx);
+}
+
+/// Upgrades a running single node network to the simple nightshade shard layout and checks
+/// that the state of the parent shard is split between the child shards, and that transactions
+/// and cross shard receipts keep working before, during and after the split.
+#[test]
+fn test_shard_layout_upgrade() {
+    init_test_logger();
+    let accounts = accounts();
+    let mut genesis = Genesis::test(accounts.clone(), 1);
+    genesis.config.epoch_length = EPOCH_LENGTH;
+    genesis.config.protocol_version = ProtocolFeature::SimpleNightshade.protocol_version() - 1;
+    genesis.config.simple_nightshade_shard_config = Some(ShardConfig {
+        num_block_producer_seats_per_shard: vec![1, 1, 1],
+        avg_hidden_validator_seats_per_shard: vec![0, 0, 0],
+        shard_layout: split_shard_layout(),
+    });
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 1, 1, create_nightshade_runtimes(&genesis, 1));
+
+    let amount = 10u128.pow(24);
+    let mut expected_balances: Vec<Balance> =
+        accounts.iter().map(|account_id| query_balance(&mut env, account_id)).collect();
+    let mut nonce = 1;
+    let mut height = 1;
+    // Keep sending money around the accounts of different child shards while the network upgrades.
+    while num_shards_at_head(&env) == 1 {
+        assert!(height < EPOCH_LENGTH * MAX_UPGRADE_EPOCHS, "protocol upgrade did not happen");
+        if height % 2 == 0 {
+            let from = nonce as usize % accounts.len();
+            let to = (from + 2) % accounts.len();
+            let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+            send_money(&mut env, &accounts[from], &accounts[to], nonce, amount, block_hash);
+            expected_balances[from] -= amount;
+            expected_balances[to] += amount;
+            nonce += 1;
+        }
+        produce_block_and_catchup(&mut env, height);
+        height += 1;
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(
+        env.clients[0].runtime_adapter.get_shard_layout(&head.epoch_id).unwrap(),
+        split_shard_layout()
+    );
+
+    // Let all the transactions and receipts sent before the upgrade land.
+    for _ in 0..EPOCH_LENGTH {
+        produce_block_and_catchup(&mut env, height);
+        height += 1;
+    }
+    for (i, account_id) in accounts.iter().enumerate() {
+        // Senders also pay for gas, so the balances are only known up to the gas spent.
+        let balance = query_balance(&mut env, account_id);
+        assert!(balance <= expected_balances[i], "unexpected balance of {}", account_id);
+        assert!(balance + GAS_SLACK > expected_balances[i], "unexpected balance of {}", account_id);
+    }
+
+    // Cross shard transfers keep working in the new layout.
+    let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    let receiver = &accounts[4];
+    let receiver_balance = query_balance(&mut env, receiver);
+    send_money(&mut env, &accounts[0], receiver, nonce, amount, block_hash);
+    for _ in 0..3 {
+        produce_block_and_catchup(&mut env, height);
+        height += 1;
+    }
+    assert_eq!(query_balance(&mut env, receiver), receiver_balance + amount);
+    // Every child shard gets its own chunks.
+    let head = env.clients[0].chain.head().unwrap();
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap().clone();
+    assert_eq!(block.chunks().len(), 3);
+    for chunk_header in block.chunks().iter() {
+        assert_eq!(chunk_header.height_included(), head.height);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::System;
+use near_actix_test_utils::run_actix;
+use near_chain::{ChainGenesis, Provenance};
+use near_chain_configs::Genesis;
+use near_client::state_dump::{dump_state_parts, state_part_path};
+use near_client::sync::{StateSync, StateSyncResult};
+use near_client::test_utils::TestEnv;
+use near_client_primitives::types::ShardSyncDownload;
+use near_logger_utils::init_test_logger;
+use near_network::routing::EdgeInfo;
+use near_network::test_utils::MockNetworkAdapter;
+use near_network::types::PeerChainInfoV2;
+use near_network::{FullPeerInfo, NetworkRequests, PeerInfo};
+use near_primitives::block::GenesisId;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::syncing::get_num_state_parts;
+use nearcore::config::GenesisExt;
+
+use crate::process_blocks::create_nightshade_runtimes;
+
+/// Produces two epochs of blocks on the validator `clients[0]`, which the non-validator
+/// `clients[1]` processes without applying the chunks. Dumps the state of the shard at the
+/// returned sync hash to `dir`.
+fn setup_env_with_dump(dir: &Path) -> (TestEnv, CryptoHash) {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env =
+        TestEnv::new_with_runtime(chain_genesis, 2, 1, create_nightshade_runtimes(&genesis, 2));
+    for i in 1..=epoch_length + 1 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let sync_hash = *env.clients[0].chain.get_block_by_height(epoch_length + 1).unwrap().hash();
+    assert!(env.clients[0].chain.check_sync_hash_validity(&sync_hash).unwrap());
+
+    let header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    dump_state_parts(env.clients[0].runtime_adapter.as_ref(), dir, &sync_hash, 0, &header).unwrap();
+    (env, sync_hash)
+}
+
+fn num_state_parts(env: &mut TestEnv, sync_hash: CryptoHash) -> u64 {
+    let header = env.clients[0].chain.get_state_response_header(0, sync_hash).unwrap();
+    get_num_state_parts(header.state_root_node().memory_usage)
+}
+
+/// Peer which tracks shard 0, so that the parts missing from the dump are requested from it.
+fn peer_tracking_shard(env: &TestEnv) -> FullPeerInfo {
+    FullPeerInfo {
+        peer_info: PeerInfo::random(),
+        chain_info: PeerChainInfoV2 {
+            genesis_id: GenesisId {
+                chain_id: "unittest".to_string(),
+                hash: *env.clients[0].chain.genesis().hash(),
+            },
+            height: env.clients[0].chain.head().unwrap().height,
+            tracked_shards: vec![0],
+            archival: false,
+        },
+        edge_info: EdgeInfo::default(),
+    }
+}
+
+/// Runs state sync of shard 0 on `clients[1]` until it completes.
+/// `on_request` is called for every request sent to the network.
+fn run_state_sync(
+    env: &mut TestEnv,
+    state_sync: &mut StateSync,
+    network_adapter: &MockNetworkAdapter,
+    sync_hash: CryptoHash,
+    peers: Vec<FullPeerInfo>,
+    mut on_request: impl FnMut(&mut TestEnv, &StateSync, NetworkRequests),
+) {
+    let mut new_shard_sync: HashMap<u64, ShardSyncDownload> = HashMap::new();
+    for _ in 0..500 {
+        let runtime_adapter = env.clients[1].runtime_adapter.clone();
+        let result = state_sync
+            .run(
+                &None,
+                sync_hash,
+                &mut new_shard_sync,
+                &mut env.clients[1].chain,
+                &runtime_adapter,
+                &peers,
+                vec![0],
+                false,
+            )
+            .unwrap();
+        if let StateSyncResult::Completed = result {
+            return;
+        }
+        while let Some(request) = network_adapter.pop() {
+            on_request(env, state_sync, request);
+        }
+        // The parts are validated on the worker pool.
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("State sync didn't complete");
+}
+
+fn assert_state_synced(env: &mut TestEnv, sync_hash: CryptoHash) {
+    let prev_hash = *env.clients[0].chain.get_block_header(&sync_hash).unwrap().prev_hash();
+    let expected_chunk_extra = env.clients[0]
+        .chain
+        .get_chunk_extra(&prev_hash, &ShardUId::single_shard())
+        .unwrap()
+        .clone();
+    let chunk_extra_after_sync = env.clients[1]
+        .chain
+        .get_chunk_extra(&prev_hash, &ShardUId::single_shard())
+        .unwrap()
+        .clone();
+    assert_eq!(chunk_extra_after_sync, expected_chunk_extra);
+}
+
+/// Syncs the state only from the dump, the network never answers.
+#[test]
+fn test_state_sync_from_dump() {
+    let dir = tempfile::Builder::new().prefix("state_dump").tempdir().unwrap();
+    let (mut env, sync_hash) = setup_env_with_dump(dir.path());
+
+    let network_adapter = Arc::new(MockNetworkAdapter::default());
+    let mut state_sync = StateSync::new(
+        network_adapter.clone(),
+        Duration::from_secs(60),
+        Some(dir.path().to_path_buf()),
+    );
+    let peers = vec![peer_tracking_shard(&env)];
+    run_state_sync(
+        &mut env,
+        &mut state_sync,
+        &network_adapter,
+        sync_hash,
+        peers,
+        |_, _, request| panic!("Unexpected network request {:?}", request),
+    );
+    assert_state_synced(&mut env, sync_hash);
+}
+
+/// Removes or corrupts the dumped part and checks that it is requested from the peer instead.
+fn test_state_sync_falls_back_to_peers(corrupt: bool) {
+    let dir = tempfile::Builder::new().prefix("state_dump").tempdir().unwrap();
+    let (mut env, sync_hash) = setup_env_with_dump(dir.path());
+    let num_parts = num_state_parts(&mut env, sync_hash);
+    let path = state_part_path(dir.path(), &sync_hash, 0, 0, num_parts);
+    if corrupt {
+        std::fs::write(&path, b"corrupted").unwrap();
+    } else {
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    run_actix(async move {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let mut state_sync = StateSync::new(
+            network_adapter.clone(),
+            Duration::from_secs(60),
+            Some(dir.path().to_path_buf()),
+        );
+        let peers = vec![peer_tracking_shard(&env)];
+        let mut requested_parts = vec![];
+        run_state_sync(
+            &mut env,
+            &mut state_sync,
+            &network_adapter,
+            sync_hash,
+            peers,
+            |env, state_sync, request| match request {
+                NetworkRequests::StateRequestPart { shard_id, sync_hash, part_id, .. } => {
+                    requested_parts.push(part_id);
+                    // Answer on behalf of the peer with the part of the validator.
+                    let header = env.clients[0]
+                        .chain
+                        .get_state_response_header(shard_id, sync_hash)
+                        .unwrap();
+                    let state_root = header.chunk_prev_state_root();
+                    let data = env.clients[0]
+                        .runtime_adapter
+                        .obtain_state_part(shard_id, &state_root, part_id, num_parts)
+                        .unwrap();
+                    state_sync.validate_state_part(
+                        env.clients[1].runtime_adapter.clone(),
+                        state_root,
+                        shard_id,
+                        sync_hash,
+                        part_id,
+                        num_parts,
+                        data,
+                    );
+                }
+                request => panic!("Unexpected network request {:?}", request),
+            },
+        );
+        assert_eq!(requested_parts, vec![0]);
+        assert_state_synced(&mut env, sync_hash);
+        System::current().stop();
+    });
+}
+
+#[test]
+fn test_state_sync_from_dump_missing_part() {
+    test_state_sync_falls_back_to_peers(false);
+}
+
+#[test]
+fn test_state_sync_from_dump_corrupted_part() {
+    test_state_sync_falls_back_to_peers(true);
+}
@@ -286,6 +286,7 @@ fn test_query_rpc_account_view_must_succeed() {
                 request: near_primitives::views::QueryRequest::ViewAccount {
                     account_id: "near.0".parse().unwrap(),
                 },
+                include_proof: false,
             })
             .await
             .unwrap();
@@ -324,6 +325,7 @@ fn test_query_rpc_account_view_account_doesnt_exist_must_return_error() {
                 request: near_primitives::views::QueryRequest::ViewAccount {
                     account_id: "accountdoesntexist.0".parse().unwrap(),
                 },
+                include_proof: false,
             })
             .await;
 
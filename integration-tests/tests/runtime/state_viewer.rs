@@ -1,16 +1,19 @@
 use integration_tests::runtime_utils::{get_runtime_and_trie, get_test_trie_viewer};
+use near_crypto::{KeyType, PublicKey};
 use near_primitives::{
     account::Account,
-    hash::CryptoHash,
+    hash::{hash, CryptoHash},
+    receipt::{ActionReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum},
+    transaction::{Action, FunctionCallAction},
     views::{StateItem, ViewApplyState},
 };
 use near_primitives::{
     test_utils::MockEpochInfoProvider,
     trie_key::TrieKey,
-    types::{EpochId, StateChangeCause},
+    types::{EpochId, Gas, StateChangeCause},
     version::PROTOCOL_VERSION,
 };
-use near_store::set_account;
+use near_store::{set, set_account};
 use node_runtime::state_viewer::errors;
 use node_runtime::state_viewer::*;
 use testlib::runtime_utils::{alice_account, encode_int};
@@ -207,3 +210,62 @@ fn test_log_when_panic() {
 
     assert_eq!(logs, vec!["hello".to_string()]);
 }
+
+#[test]
+fn test_view_delayed_receipts() {
+    let (_, tries, root) = get_runtime_and_trie();
+    let mut state_update = tries.new_trie_update(0, root);
+    // The first two receipts of the queue were already processed.
+    let indices = DelayedReceiptIndices { first_index: 2, next_available_index: 7 };
+    for index in indices.first_index..indices.next_available_index {
+        let receipt = Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: "test.contract".parse().unwrap(),
+            receipt_id: hash(&index.to_le_bytes()),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 100,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(FunctionCallAction {
+                    method_name: "run_test".to_string(),
+                    args: vec![],
+                    gas: 1000 * index,
+                    deposit: 0,
+                })],
+            }),
+        };
+        set(&mut state_update, TrieKey::DelayedReceipt { index }, &receipt);
+    }
+    set(&mut state_update, TrieKey::DelayedReceiptIndices, &indices);
+    // The gas of the whole queue is tracked in the state.
+    let total_prepaid_gas: Gas = 1000 * (2 + 3 + 4 + 5 + 6);
+    set(&mut state_update, TrieKey::DelayedReceiptsGas, &total_prepaid_gas);
+
+    let trie_viewer = TrieViewer::default();
+    let result = trie_viewer.view_delayed_receipts(&state_update, None, 2).unwrap();
+    assert_eq!((result.first_index, result.next_available_index, result.length), (2, 7, 5));
+    assert_eq!(result.total_prepaid_gas, Some(total_prepaid_gas));
+    let page: Vec<_> = result.receipts.iter().map(|receipt| receipt.index).collect();
+    assert_eq!(page, vec![2, 3]);
+    assert_eq!(result.receipts[0].receipt.receipt_id, hash(&2u64.to_le_bytes()));
+
+    let result = trie_viewer.view_delayed_receipts(&state_update, Some(5), 10).unwrap();
+    let page: Vec<_> = result.receipts.iter().map(|receipt| receipt.index).collect();
+    assert_eq!(page, vec![5, 6]);
+    let result = trie_viewer.view_delayed_receipts(&state_update, Some(7), 10).unwrap();
+    assert!(result.receipts.is_empty());
+    assert_eq!(result.length, 5);
+
+    // Without the tracked gas, it's computed from the receipts of the queue.
+    state_update.remove(TrieKey::DelayedReceiptsGas);
+    let result = trie_viewer.view_delayed_receipts(&state_update, None, 2).unwrap();
+    assert_eq!(result.total_prepaid_gas, Some(total_prepaid_gas));
+    // Unless the queue is too long to read it all.
+    let indices = DelayedReceiptIndices { first_index: 2, next_available_index: 2 + 1001 };
+    set(&mut state_update, TrieKey::DelayedReceiptIndices, &indices);
+    let result = trie_viewer.view_delayed_receipts(&state_update, None, 2).unwrap();
+    assert_eq!(result.total_prepaid_gas, None);
+    assert_eq!(result.length, 1001);
+}
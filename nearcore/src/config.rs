@@ -13,7 +13,7 @@ use serde::{Deserialize, Serialize};
 use tracing::info;
 
 use near_chain_configs::{
-    get_initial_supply, ClientConfig, Genesis, GenesisConfig, LogSummaryStyle,
+    get_initial_supply, ClientConfig, GCRetentionConfig, Genesis, GenesisConfig, LogSummaryStyle,
 };
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 #[cfg(feature = "json_rpc")]
@@ -35,6 +35,7 @@ use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner
 use near_primitives::version::PROTOCOL_VERSION;
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::RosettaRpcConfig;
+use near_store::TrieCacheConfig;
 use near_telemetry::TelemetryConfig;
 
 /// Initial balance used in tests.
@@ -241,6 +242,10 @@ pub struct Network {
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// Period to ping reachable peers to measure round trip time. Every node pinging every
+    /// reachable peer is quadratic in the size of the network, so it is disabled by default.
+    #[serde(default)]
+    pub ping_period: Option<Duration>,
 }
 
 impl Default for Network {
@@ -263,6 +268,7 @@ impl Default for Network {
             blacklist: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            ping_period: None,
         }
     }
 }
@@ -300,6 +306,10 @@ fn default_sync_step_period() -> Duration {
     Duration::from_millis(10)
 }
 
+fn is_default_gc_retention(gc_retention: &GCRetentionConfig) -> bool {
+    gc_retention == &GCRetentionConfig::default()
+}
+
 fn default_gc_blocks_limit() -> NumBlocks {
     2
 }
@@ -320,6 +330,14 @@ fn default_trie_viewer_state_size_limit() -> Option<u64> {
     Some(50_000)
 }
 
+fn default_store_validator_batch_size() -> u64 {
+    1000
+}
+
+fn is_default_trie_cache(trie_cache: &TrieCacheConfig) -> bool {
+    trie_cache == &TrieCacheConfig::default()
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -420,9 +438,15 @@ pub struct Config {
     pub tracked_accounts: Vec<AccountId>,
     pub tracked_shards: Vec<ShardId>,
     pub archive: bool,
+    /// Whether an archive node indexes the accounts by height for the queries at old blocks.
+    #[serde(default)]
+    pub account_history: bool,
     pub log_summary_style: LogSummaryStyle,
     #[serde(default = "default_gc_blocks_limit")]
     pub gc_blocks_limit: NumBlocks,
+    /// Number of epochs to keep transactions, receipts and outcomes for, if longer than blocks.
+    #[serde(default, skip_serializing_if = "is_default_gc_retention")]
+    pub gc_retention: GCRetentionConfig,
     #[serde(default = "default_view_client_threads")]
     pub view_client_threads: usize,
     pub epoch_sync_enabled: bool,
@@ -433,6 +457,20 @@ pub struct Config {
     /// If set, overrides value in genesis configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_gas_burnt_view: Option<Gas>,
+    /// Directory to dump state parts to at the start of every epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_parts_dump_dir: Option<PathBuf>,
+    /// Directory with dumped state parts to sync state from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_sync_from_dump_dir: Option<PathBuf>,
+    /// If set, the store is validated in the background, one batch of entries every period.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_validator_period: Option<Duration>,
+    #[serde(default = "default_store_validator_batch_size")]
+    pub store_validator_batch_size: u64,
+    /// Sizes of the trie caches of every shard.
+    #[serde(default, skip_serializing_if = "is_default_trie_cache")]
+    pub trie_cache: TrieCacheConfig,
 }
 
 impl Default for Config {
@@ -452,13 +490,20 @@ impl Default for Config {
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive: false,
+            account_history: false,
             log_summary_style: LogSummaryStyle::Colored,
             gc_blocks_limit: default_gc_blocks_limit(),
+            gc_retention: GCRetentionConfig::default(),
             epoch_sync_enabled: true,
             view_client_threads: default_view_client_threads(),
             view_client_throttle_period: default_view_client_throttle_period(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
             max_gas_burnt_view: None,
+            state_parts_dump_dir: None,
+            state_sync_from_dump_dir: None,
+            store_validator_period: None,
+            store_validator_batch_size: default_store_validator_batch_size(),
+            trie_cache: TrieCacheConfig::default(),
         }
     }
 }
@@ -591,6 +636,7 @@ pub struct NearConfig {
     #[cfg(feature = "rosetta_rpc")]
     pub rosetta_rpc_config: Option<RosettaRpcConfig>,
     pub telemetry_config: TelemetryConfig,
+    pub trie_cache_config: TrieCacheConfig,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
 }
@@ -641,13 +687,19 @@ impl NearConfig {
                 tracked_accounts: config.tracked_accounts,
                 tracked_shards: config.tracked_shards,
                 archive: config.archive,
+                account_history: config.account_history,
                 log_summary_style: config.log_summary_style,
                 gc_blocks_limit: config.gc_blocks_limit,
+                gc_retention: config.gc_retention.clone(),
                 view_client_threads: config.view_client_threads,
                 epoch_sync_enabled: config.epoch_sync_enabled,
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
+                state_parts_dump_dir: config.state_parts_dump_dir,
+                state_sync_from_dump_dir: config.state_sync_from_dump_dir,
+                store_validator_period: config.store_validator_period,
+                store_validator_batch_size: config.store_validator_batch_size,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -689,11 +741,13 @@ impl NearConfig {
                 max_routes_to_store: MAX_ROUTES_TO_STORE,
                 highest_peer_horizon: HIGHEST_PEER_HORIZON,
                 push_info_period: Duration::from_millis(100),
+                ping_period: config.network.ping_period,
                 blacklist: blacklist_from_iter(config.network.blacklist),
                 outbound_disabled: false,
                 archive: config.archive,
             },
             telemetry_config: config.telemetry,
+            trie_cache_config: config.trie_cache,
             #[cfg(feature = "json_rpc")]
             rpc_config: config.rpc,
             #[cfg(feature = "rosetta_rpc")]
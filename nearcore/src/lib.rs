@@ -9,6 +9,7 @@ use actix_web;
 use near_rust_allocator_proxy::allocator::reset_memory_usage_max;
 use tracing::{error, info, trace};
 
+use near_chain::store_validator::spawn_background_store_validator;
 use near_chain::ChainGenesis;
 #[cfg(feature = "adversarial")]
 use near_client::AdversarialControls;
@@ -16,16 +17,25 @@ use near_client::{start_client, start_view_client, ClientActor, ViewClientActor}
 use near_network::{NetworkRecipient, PeerManagerActor};
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::start_rosetta_rpc;
+use near_store::db::DBError;
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_store::migrations::migrate_18_to_new_validator_stake;
 use near_store::migrations::migrate_20_to_21;
+use near_store::migrations::runner::{
+    apply_migrations, estimate_migrations, Migration, MigrationEstimate,
+};
 use near_store::migrations::{
     fill_col_outcomes_by_hash, fill_col_transaction_refcount, get_store_version, migrate_10_to_11,
     migrate_11_to_12, migrate_13_to_14, migrate_14_to_15, migrate_17_to_18, migrate_21_to_22,
-    migrate_25_to_26, migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10,
-    set_store_version,
+    migrate_25_to_26, migrate_30_to_31, migrate_6_to_7, migrate_7_to_8, migrate_8_to_9,
+    migrate_9_to_10, set_store_version,
+};
+use near_store::{
+    create_store, ColBlockInfo, ColBlockMisc, ColCachedContractCode, ColChunks,
+    ColEpochValidatorInfo, ColInvalidChunks, ColOutcomeIds, ColPartialChunks,
+    ColReceiptIdToShardId, ColReceipts, ColStateChangesByKey, ColStateHeaders, ColStateParts,
+    ColTransactionResult, ColTransactions, Store,
 };
-use near_store::{create_store, Store};
 use near_telemetry::TelemetryActor;
 
 pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
@@ -70,6 +80,148 @@ pub fn get_default_home() -> String {
     }
 }
 
+/// Migrations of the database, in order of versions.
+fn store_migrations(near_config: &NearConfig) -> Vec<Migration> {
+    vec![
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        // Nevertheless need to bump db version, because db_version 1 binary can't open db_version 2 db
+        Migration::new(1, "add gc column", &[], |_| {}),
+        // The column number is the same, so we don't need additional updates
+        Migration::new(
+            2,
+            "add ColOutcomesByBlockHash + rename LastComponentNonce -> ColLastComponentNonce",
+            &[],
+            |path| fill_col_outcomes_by_hash(&create_store(path)),
+        ),
+        Migration::new(3, "add ColTransactionRefCount", &[], |path| {
+            fill_col_transaction_refcount(&create_store(path))
+        }),
+        // we don't need to backfill the old heights since at worst we will just process some heights
+        // again.
+        Migration::new(4, "add ColProcessedBlockHeights", &[], |_| {}),
+        // we don't have merge records before so old storage works
+        Migration::new(5, "add merge operator to ColState", &[], |_| {}),
+        Migration::new(
+            6,
+            "make ColState use 8 bytes for refcount, move ColTransactionRefCount into \
+             ColTransactions, make ColReceiptIdToShardId refcounted",
+            &[],
+            migrate_6_to_7,
+        ),
+        Migration::new(
+            7,
+            "delete values in column `StateColParts`",
+            &[ColStateParts],
+            migrate_7_to_8,
+        ),
+        Migration::new(
+            8,
+            "repair `ColTransactions`, `ColReceiptIdToShardId`",
+            &[ColTransactions, ColReceiptIdToShardId],
+            migrate_8_to_9,
+        ),
+        Migration::new(
+            9,
+            "populate partial encoded chunks for chunks that exist in storage",
+            &[ColPartialChunks],
+            move |path| migrate_9_to_10(path, near_config.client_config.archive),
+        ),
+        Migration::new(10, "add final head", &[ColBlockMisc], migrate_10_to_11),
+        Migration::new(
+            11,
+            "populate ColReceipts with existing receipts",
+            &[ColReceipts],
+            migrate_11_to_12,
+        ),
+        Migration::new(
+            12,
+            "migrate ColTransactionResult to fix the inconsistencies there",
+            &[ColTransactionResult],
+            move |path| migrate_12_to_13(path, near_config),
+        ),
+        Migration::new(
+            13,
+            "store versioned enums for shard chunks",
+            &[ColChunks, ColPartialChunks, ColInvalidChunks, ColStateHeaders],
+            migrate_13_to_14,
+        ),
+        Migration::new(
+            14,
+            "change ColOutcomesByBlockHash to be ordered within each shard",
+            &[ColOutcomeIds],
+            migrate_14_to_15,
+        ),
+        Migration::new(15, "add column for compiled contracts", &[], |_| {}),
+        Migration::new(16, "add column for storing epoch validator info", &[], |_| {}),
+        Migration::new(
+            17,
+            "add `hash` to `BlockInfo` and ColHeaderHashesByHeight",
+            &[ColBlockInfo],
+            migrate_17_to_18,
+        ),
+        Migration::new(
+            18,
+            "populate ColEpochValidatorInfo for archival nodes",
+            &[ColEpochValidatorInfo],
+            move |path| migrate_18_to_19(path, near_config),
+        ),
+        Migration::new(
+            19,
+            "fix execution outcome",
+            &[ColTransactionResult, ColOutcomeIds],
+            move |path| migrate_19_to_20(path, near_config),
+        ),
+        Migration::new(
+            20,
+            "delete genesis json hash due to change in Genesis::json_hash function",
+            &[ColBlockMisc],
+            migrate_20_to_21,
+        ),
+        Migration::new(
+            21,
+            "rectify inflation: add `timestamp` to `BlockInfo`",
+            &[ColBlockInfo],
+            migrate_21_to_22,
+        ),
+        Migration::new(
+            22,
+            "fix execution outcomes missing in mainnet archival databases",
+            &[ColTransactionResult, ColOutcomeIds],
+            move |path| migrate_22_to_23(path, near_config),
+        ),
+        Migration::new(
+            23,
+            "put receipts restored after the apply_chunks fix to storage",
+            &[ColReceipts],
+            move |path| migrate_23_to_24(path, near_config),
+        ),
+        Migration::new(
+            24,
+            "add metadata to execution outcomes",
+            &[ColTransactionResult],
+            migrate_24_to_25,
+        ),
+        Migration::new(
+            25,
+            "clear compiled contracts cache",
+            &[ColCachedContractCode],
+            migrate_25_to_26,
+        ),
+        Migration::new(26, "add a column for the round trip times of peers", &[], |_| {}),
+        Migration::new(27, "add a column for the state changes of split states", &[], |_| {}),
+        // the flat storages are created on start
+        Migration::new(28, "add columns for flat state", &[], |_| {}),
+        Migration::new(29, "add a column for the data GC keeps longer than blocks", &[], |_| {}),
+        Migration::new(
+            30,
+            "index state changes by key and height",
+            &[ColStateChangesByKey],
+            migrate_30_to_31,
+        ),
+        Migration::new(31, "add a column for the account history of archival nodes", &[], |_| {}),
+    ]
+}
+
 /// Function checks current version of the database and applies migrations to the database.
 pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
     let db_version = get_store_version(path);
@@ -81,154 +233,8 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         return;
     }
 
-    // Add migrations here based on `db_version`.
-    if db_version <= 1 {
-        // version 1 => 2: add gc column
-        // Does not need to do anything since open db with option `create_missing_column_families`
-        // Nevertheless need to bump db version, because db_version 1 binary can't open db_version 2 db
-        info!(target: "near", "Migrate DB from version 1 to 2");
-        let store = create_store(&path);
-        set_store_version(&store, 2);
-    }
-    if db_version <= 2 {
-        // version 2 => 3: add ColOutcomesByBlockHash + rename LastComponentNonce -> ColLastComponentNonce
-        // The column number is the same, so we don't need additional updates
-        info!(target: "near", "Migrate DB from version 2 to 3");
-        let store = create_store(&path);
-        fill_col_outcomes_by_hash(&store);
-        set_store_version(&store, 3);
-    }
-    if db_version <= 3 {
-        // version 3 => 4: add ColTransactionRefCount
-        info!(target: "near", "Migrate DB from version 3 to 4");
-        let store = create_store(&path);
-        fill_col_transaction_refcount(&store);
-        set_store_version(&store, 4);
-    }
-    if db_version <= 4 {
-        info!(target: "near", "Migrate DB from version 4 to 5");
-        // version 4 => 5: add ColProcessedBlockHeights
-        // we don't need to backfill the old heights since at worst we will just process some heights
-        // again.
-        let store = create_store(&path);
-        set_store_version(&store, 5);
-    }
-    if db_version <= 5 {
-        info!(target: "near", "Migrate DB from version 5 to 6");
-        // version 5 => 6: add merge operator to ColState
-        // we don't have merge records before so old storage works
-        let store = create_store(&path);
-        set_store_version(&store, 6);
-    }
-    if db_version <= 6 {
-        info!(target: "near", "Migrate DB from version 6 to 7");
-        // version 6 => 7:
-        // - make ColState use 8 bytes for refcount (change to merge operator)
-        // - move ColTransactionRefCount into ColTransactions
-        // - make ColReceiptIdToShardId refcounted
-        migrate_6_to_7(path);
-    }
-    if db_version <= 7 {
-        info!(target: "near", "Migrate DB from version 7 to 8");
-        // version 7 => 8:
-        // delete values in column `StateColParts`
-        migrate_7_to_8(path);
-    }
-    if db_version <= 8 {
-        info!(target: "near", "Migrate DB from version 8 to 9");
-        // version 8 => 9:
-        // Repair `ColTransactions`, `ColReceiptIdToShardId`
-        migrate_8_to_9(path);
-    }
-    if db_version <= 9 {
-        info!(target: "near", "Migrate DB from version 9 to 10");
-        // version 9 => 10;
-        // populate partial encoded chunks for chunks that exist in storage
-        migrate_9_to_10(path, near_config.client_config.archive);
-    }
-    if db_version <= 10 {
-        info!(target: "near", "Migrate DB from version 10 to 11");
-        // version 10 => 11
-        // Add final head
-        migrate_10_to_11(path);
-    }
-    if db_version <= 11 {
-        info!(target: "near", "Migrate DB from version 11 to 12");
-        // version 11 => 12;
-        // populate ColReceipts with existing receipts
-        migrate_11_to_12(path);
-    }
-    if db_version <= 12 {
-        info!(target: "near", "Migrate DB from version 12 to 13");
-        // version 12 => 13;
-        // migrate ColTransactionResult to fix the inconsistencies there
-        migrate_12_to_13(path, near_config);
-    }
-    if db_version <= 13 {
-        info!(target: "near", "Migrate DB from version 13 to 14");
-        // version 13 => 14;
-        // store versioned enums for shard chunks
-        migrate_13_to_14(path);
-    }
-    if db_version <= 14 {
-        info!(target: "near", "Migrate DB from version 14 to 15");
-        // version 14 => 15;
-        // Change ColOutcomesByBlockHash to be ordered within each shard
-        migrate_14_to_15(path);
-    }
-    if db_version <= 15 {
-        info!(target: "near", "Migrate DB from version 15 to 16");
-        // version 15 => 16: add column for compiled contracts
-        let store = create_store(&path);
-        set_store_version(&store, 16);
-    }
-    if db_version <= 16 {
-        info!(target: "near", "Migrate DB from version 16 to 17");
-        // version 16 => 17: add column for storing epoch validator info
-        let store = create_store(&path);
-        set_store_version(&store, 17);
-    }
-    if db_version <= 17 {
-        info!(target: "near", "Migrate DB from version 17 to 18");
-        // version 17 => 18: add `hash` to `BlockInfo` and ColHeaderHashesByHeight
-        migrate_17_to_18(&path);
-    }
-    if db_version <= 18 {
-        info!(target: "near", "Migrate DB from version 18 to 19");
-        // version 18 => 19: populate ColEpochValidatorInfo for archival nodes
-        migrate_18_to_19(&path, near_config);
-    }
-    if db_version <= 19 {
-        info!(target: "near", "Migrate DB from version 19 to 20");
-        // version 19 => 20: fix execution outcome
-        migrate_19_to_20(&path, &near_config);
-    }
-    if db_version <= 20 {
-        info!(target: "near", "Migrate DB from version 20 to 21");
-        // version 20 => 21: delete genesis json hash due to change in Genesis::json_hash function
-        migrate_20_to_21(&path);
-    }
-    if db_version <= 21 {
-        info!(target: "near", "Migrate DB from version 21 to 22");
-        // version 21 => 22: rectify inflation: add `timestamp` to `BlockInfo`
-        migrate_21_to_22(&path);
-    }
-    if db_version <= 22 {
-        info!(target: "near", "Migrate DB from version 22 to 23");
-        migrate_22_to_23(&path, &near_config);
-    }
-    if db_version <= 23 {
-        info!(target: "near", "Migrate DB from version 23 to 24");
-        migrate_23_to_24(&path, &near_config);
-    }
-    if db_version <= 24 {
-        info!(target: "near", "Migrate DB from version 24 to 25");
-        migrate_24_to_25(&path);
-    }
-    if db_version <= 25 {
-        info!(target: "near", "Migrate DB from version 25 to 26");
-        migrate_25_to_26(&path);
-    }
+    apply_migrations(path, &store_migrations(near_config));
+
     #[cfg(feature = "nightly_protocol")]
     {
         let store = create_store(&path);
@@ -249,6 +255,14 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
     }
 }
 
+/// Estimates the work of the migrations the database needs, without changing it.
+pub fn estimate_store_migrations(
+    path: &String,
+    near_config: &NearConfig,
+) -> Result<Vec<MigrationEstimate>, DBError> {
+    estimate_migrations(path, &store_migrations(near_config))
+}
+
 pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Arc<Store> {
     let path = get_store_path(home_dir);
     let store_exists = store_path_exists(&path);
@@ -272,7 +286,7 @@ pub struct NearNode {
 pub fn start_with_config(home_dir: &Path, config: NearConfig) -> NearNode {
     let store = init_and_migrate_store(home_dir, &config);
 
-    let runtime = Arc::new(NightshadeRuntime::new(
+    let runtime = Arc::new(NightshadeRuntime::with_config(
         home_dir,
         Arc::clone(&store),
         &config.genesis,
@@ -280,6 +294,7 @@ pub fn start_with_config(home_dir: &Path, config: NearConfig) -> NearNode {
         config.client_config.tracked_shards.clone(),
         config.client_config.trie_viewer_state_size_limit,
         config.client_config.max_gas_burnt_view,
+        &config.trie_cache_config,
     ));
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
@@ -299,6 +314,16 @@ pub fn start_with_config(home_dir: &Path, config: NearConfig) -> NearNode {
         #[cfg(feature = "adversarial")]
         adv.clone(),
     );
+    if let Some(period) = config.client_config.store_validator_period {
+        spawn_background_store_validator(
+            config.validator_signer.as_ref().map(|signer| signer.validator_id().clone()),
+            config.genesis.config.clone(),
+            runtime.clone(),
+            store.clone(),
+            period,
+            config.client_config.store_validator_batch_size,
+        );
+    }
     let (client_actor, client_arbiter_handle) = start_client(
         config.client_config,
         chain_genesis,
@@ -10,6 +10,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::MerklePath;
 use near_primitives::receipt::ReceiptResult;
 use near_primitives::runtime::migration_data::MigrationData;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader, ShardChunkV1};
 use near_primitives::transaction::{
     ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof,
@@ -48,6 +49,7 @@ fn apply_block_at_height(
     let prev_block = chain_store.get_block(&block.header().prev_hash())?.clone();
     let mut chain_store_update = ChainStoreUpdate::new(chain_store);
     let receipt_proof_response = chain_store_update.get_incoming_receipts_for_shard(
+        runtime_adapter,
         shard_id,
         block_hash,
         prev_block.chunks()[shard_id as usize].height_included(),
@@ -75,6 +77,7 @@ fn apply_block_at_height(
             chunk_header.validator_proposals(),
             prev_block.header().gas_price(),
             chunk_header.gas_limit(),
+            &[],
             &block.header().challenges_result(),
             *block.header().random_value(),
             true,
@@ -238,7 +241,7 @@ pub fn migrate_19_to_20(path: &String, near_config: &NearConfig) {
                 if block.chunks()[shard_id as usize].height_included() != block.header().height() {
                     let mut chain_store_update = ChainStoreUpdate::new(&mut chain_store);
                     let new_extra = chain_store_update
-                        .get_chunk_extra(block.header().prev_hash(), shard_id)
+                        .get_chunk_extra(block.header().prev_hash(), &ShardUId::single_shard())
                         .unwrap()
                         .clone();
 
@@ -255,6 +258,7 @@ pub fn migrate_19_to_20(path: &String, near_config: &NearConfig) {
                             new_extra.validator_proposals(),
                             block.header().gas_price(),
                             new_extra.gas_limit(),
+                            &[],
                             &block.header().challenges_result(),
                             *block.header().random_value(),
                             // doesn't really matter here since the old blocks are on the old version
@@ -311,6 +315,7 @@ pub fn migrate_22_to_23(path: &String, near_config: &NearConfig) {
                     chain_store_update.get_block(block.header().prev_hash()).unwrap().clone();
                 let receipt_proof_response = chain_store_update
                     .get_incoming_receipts_for_shard(
+                        &runtime,
                         shard_id,
                         block_hash,
                         prev_block.chunks()[shard_id as usize].height_included(),
@@ -331,6 +336,7 @@ pub fn migrate_22_to_23(path: &String, near_config: &NearConfig) {
                         chunk_header.validator_proposals(),
                         block.header().gas_price(),
                         chunk_header.gas_limit(),
+                        &[],
                         &block.header().challenges_result(),
                         *block.header().random_value(),
                         true,
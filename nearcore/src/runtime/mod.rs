@@ -2,14 +2,16 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
 use borsh::ser::BorshSerialize;
 use borsh::BorshDeserialize;
 use tracing::{debug, error, info, warn};
 
-use near_chain::chain::NUM_EPOCHS_TO_KEEP_STORE_DATA;
-use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo, ValidatorInfoIdentifier};
+use near_chain::types::{
+    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ValidatorInfoIdentifier,
+};
 use near_chain::{BlockHeader, Error, ErrorKind, RuntimeAdapter};
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use near_chain::{Doomslug, DoomslugThresholdMode};
@@ -20,6 +22,7 @@ use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{Approval, ApprovalInner};
 use near_primitives::challenge::ChallengesResult;
+use near_primitives::checked_feature;
 use near_primitives::contract::ContractCode;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
@@ -27,8 +30,10 @@ use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig};
 use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::Receipt;
+use near_primitives::serialize::to_base64;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::{state_record_to_account_id, StateRecord};
+use near_primitives::syncing::get_num_state_parts;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
 use near_primitives::types::{
@@ -38,17 +43,17 @@ use near_primitives::types::{
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
-    AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewApplyState, ViewStateResult,
+    AccessKeyInfoView, CallResult, DelayedReceiptsView, EpochValidatorInfo, QueryRequest,
+    QueryResponse, QueryResponseKind, TrieProofPath, ViewApplyState, ViewStateResult,
 };
 use near_vm_runner::precompile_contract;
 
 use near_store::{
-    get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots,
-    ApplyStatePartResult, ColState, PartialStorage, ShardTries, Store, StoreCompiledContractCache,
-    StoreUpdate, Trie, WrappedTrieChanges,
+    get_delayed_receipts, get_genesis_hash, get_genesis_state_roots, set_genesis_hash,
+    set_genesis_state_roots, ApplyStatePartResult, ColState, PartialStorage, ShardTries,
+    StateChangesForSplitStates, Store, StoreCompiledContractCache, StoreUpdate, Trie,
+    TrieCacheConfig, TrieUpdate, WrappedTrieChanges,
 };
-use node_runtime::adapter::ViewRuntimeAdapter;
 use node_runtime::state_viewer::TrieViewer;
 use node_runtime::{
     validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
@@ -56,12 +61,12 @@ use node_runtime::{
 };
 
 use crate::shard_tracker::ShardTracker;
-use near_primitives::runtime::config::ActualRuntimeConfig;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
 
 use crate::migrations::load_migration_data;
 use errors::FromStateViewerErrors;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
-use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout, ShardUId};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 pub mod errors;
@@ -125,10 +130,9 @@ impl EpochInfoProvider for SafeEpochManager {
 /// TODO: this possibly should be merged with the runtime cargo or at least reconciled on the interfaces.
 pub struct NightshadeRuntime {
     genesis_config: GenesisConfig,
-    /// Runtime configuration.  Note that it may be slightly different than
-    /// `genesis_config.runtime_config`.  Consider `max_gas_burnt_view` value
-    /// which may be configured per node.
-    runtime_config: ActualRuntimeConfig,
+    /// Runtime configuration of each protocol version, made from
+    /// `genesis_config.runtime_config`.
+    runtime_config: RuntimeConfigStore,
 
     store: Arc<Store>,
     tries: ShardTries,
@@ -149,11 +153,34 @@ impl NightshadeRuntime {
         initial_tracking_shards: Vec<ShardId>,
         trie_viewer_state_size_limit: Option<u64>,
         max_gas_burnt_view: Option<Gas>,
+    ) -> Self {
+        Self::with_config(
+            home_dir,
+            store,
+            genesis,
+            initial_tracking_accounts,
+            initial_tracking_shards,
+            trie_viewer_state_size_limit,
+            max_gas_burnt_view,
+            &TrieCacheConfig::default(),
+        )
+    }
+
+    /// Same as `new`, with the trie caches configured by `trie_cache_config`.
+    pub fn with_config(
+        home_dir: &Path,
+        store: Arc<Store>,
+        genesis: &Genesis,
+        initial_tracking_accounts: Vec<AccountId>,
+        initial_tracking_shards: Vec<ShardId>,
+        trie_viewer_state_size_limit: Option<u64>,
+        max_gas_burnt_view: Option<Gas>,
+        trie_cache_config: &TrieCacheConfig,
     ) -> Self {
         let runtime = Runtime::new();
         let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view);
         let genesis_config = genesis.config.clone();
-        let runtime_config = ActualRuntimeConfig::new(genesis_config.runtime_config.clone());
+        let runtime_config = RuntimeConfigStore::new(&genesis_config.runtime_config);
         let initial_epoch_config = EpochConfig::from(&genesis_config);
         let all_epoch_config = AllEpochConfig::new(
             initial_epoch_config.clone(),
@@ -162,10 +189,17 @@ impl NightshadeRuntime {
         let reward_calculator = RewardCalculator::new(&genesis_config);
         let state_roots =
             Self::initialize_genesis_state_if_needed(store.clone(), home_dir, genesis);
-        let tries = ShardTries::new(
-            store.clone(),
+        // The tries keep a cache per shard id, so they must have room for the shards of every
+        // shard layout the chain may switch to.
+        let num_shards = std::cmp::max(
             genesis.config.num_block_producer_seats_per_shard.len() as NumShards,
+            genesis
+                .config
+                .simple_nightshade_shard_config
+                .as_ref()
+                .map_or(0, |shard_config| shard_config.shard_layout.num_shards()),
         );
+        let tries = ShardTries::with_cache_config(store.clone(), num_shards, trie_cache_config);
         let epoch_manager = Arc::new(RwLock::new(
             EpochManager::new(
                 store.clone(),
@@ -236,8 +270,10 @@ impl NightshadeRuntime {
             (0..num_shards).map(|_| HashSet::new()).collect();
         let mut has_protocol_account = false;
         genesis.for_each_record(|record: &StateRecord| {
-            shard_account_ids[state_record_to_shard_id(record, &shard_layout) as usize]
-                .insert(state_record_to_account_id(record).clone());
+            if let Some(account_id) = state_record_to_account_id(record) {
+                shard_account_ids[account_id_to_shard_id(account_id, &shard_layout) as usize]
+                    .insert(account_id.clone());
+            }
             if let StateRecord::Account { account_id, .. } = record {
                 if account_id == &genesis.config.protocol_treasury_account {
                     has_protocol_account = true;
@@ -325,6 +361,12 @@ impl NightshadeRuntime {
         }
     }
 
+    /// Whether the runtime reads the state from the flat state at `protocol_version`, which
+    /// changes the gas charged for the storage reads.
+    fn flat_state_reads_enabled(protocol_version: ProtocolVersion) -> bool {
+        checked_feature!("protocol_feature_flat_state", FlatStorageReads, protocol_version)
+    }
+
     /// Processes state update.
     fn process_state_update(
         &self,
@@ -340,12 +382,14 @@ impl NightshadeRuntime {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges_result: &ChallengesResult,
         random_seed: CryptoHash,
         is_new_chunk: bool,
         is_first_block_with_chunk_of_version: bool,
         states_to_patch: Option<Vec<StateRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
+        let shard_layout = self.get_shard_layout_from_prev_block(prev_block_hash)?;
         let validator_accounts_update = {
             let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
             debug!(target: "runtime",
@@ -357,7 +401,9 @@ impl NightshadeRuntime {
             let mut slashing_info: HashMap<_, _> = challenges_result
                 .iter()
                 .filter_map(|s| {
-                    if self.account_id_to_shard_id(&s.account_id) == shard_id && !s.is_double_sign {
+                    if account_id_to_shard_id(&s.account_id, &shard_layout) == shard_id
+                        && !s.is_double_sign
+                    {
                         Some((s.account_id.clone(), None))
                     } else {
                         None
@@ -370,14 +416,18 @@ impl NightshadeRuntime {
                     epoch_manager.compute_stake_return_info(prev_block_hash)?;
                 let stake_info = stake_info
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        account_id_to_shard_id(account_id, &shard_layout) == shard_id
+                    })
                     .collect();
                 let validator_rewards = validator_reward
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        account_id_to_shard_id(account_id, &shard_layout) == shard_id
+                    })
                     .collect();
                 let last_proposals = last_validator_proposals
-                    .filter(|v| self.account_id_to_shard_id(v.account_id()) == shard_id)
+                    .filter(|v| account_id_to_shard_id(v.account_id(), &shard_layout) == shard_id)
                     .fold(HashMap::new(), |mut acc, v| {
                         let (account_id, stake) = v.account_and_stake();
                         acc.insert(account_id, stake);
@@ -385,7 +435,9 @@ impl NightshadeRuntime {
                     });
                 let double_sign_slashing_info: HashMap<_, _> = double_sign_slashing_info
                     .into_iter()
-                    .filter(|(account_id, _)| self.account_id_to_shard_id(account_id) == shard_id)
+                    .filter(|(account_id, _)| {
+                        account_id_to_shard_id(account_id, &shard_layout) == shard_id
+                    })
                     .map(|(account_id, stake)| (account_id, Some(stake)))
                     .collect();
                 slashing_info.extend(double_sign_slashing_info);
@@ -396,7 +448,9 @@ impl NightshadeRuntime {
                     protocol_treasury_account_id: Some(
                         self.genesis_config.protocol_treasury_account.clone(),
                     )
-                    .filter(|account_id| self.account_id_to_shard_id(account_id) == shard_id),
+                    .filter(|account_id| {
+                        account_id_to_shard_id(account_id, &shard_layout) == shard_id
+                    }),
                     slashing_info,
                 })
             } else if !challenges_result.is_empty() {
@@ -418,7 +472,11 @@ impl NightshadeRuntime {
         let current_protocol_version = self.get_epoch_protocol_version(&epoch_id)?;
         let prev_block_protocol_version = self.get_epoch_protocol_version(&prev_block_epoch_id)?;
         let is_first_block_of_version = current_protocol_version != prev_block_protocol_version;
-
+        let trie = if Self::flat_state_reads_enabled(current_protocol_version) {
+            trie.with_flat_state_reads(self.tries.get_flat_state())
+        } else {
+            trie
+        };
         let apply_state = ApplyState {
             block_index: block_height,
             prev_block_hash: *prev_block_hash,
@@ -433,6 +491,13 @@ impl NightshadeRuntime {
             config: self.runtime_config.for_protocol_version(current_protocol_version).clone(),
             cache: Some(Arc::new(StoreCompiledContractCache { store: self.store.clone() })),
             is_new_chunk,
+            shard_layout: shard_layout.clone(),
+            // The receipts the shard sends to itself are limited by its own gas limit.
+            congested_shards: congested_shards
+                .iter()
+                .copied()
+                .filter(|congested_shard_id| *congested_shard_id != shard_id)
+                .collect(),
             migration_data: Arc::clone(&self.migration_data),
             migration_flags: MigrationFlags {
                 is_first_block_of_version,
@@ -440,6 +505,8 @@ impl NightshadeRuntime {
             },
         };
 
+        // Loads the accounts of the later transactions and receipts while the first ones apply
+        self.tries.prefetch(shard_id, state_root, transactions, receipts);
         let apply_result = self
             .runtime
             .apply(
@@ -481,7 +548,7 @@ impl NightshadeRuntime {
         let mut receipt_result = HashMap::default();
         for receipt in apply_result.outgoing_receipts {
             receipt_result
-                .entry(self.account_id_to_shard_id(&receipt.receiver_id))
+                .entry(account_id_to_shard_id(&receipt.receiver_id, &shard_layout))
                 .or_insert_with(|| vec![])
                 .push(receipt);
         }
@@ -489,7 +556,7 @@ impl NightshadeRuntime {
         let result = ApplyTransactionResult {
             trie_changes: WrappedTrieChanges::new(
                 self.get_tries(),
-                shard_id,
+                ShardUId::from_shard_id_and_layout(shard_id, &shard_layout),
                 apply_result.trie_changes,
                 apply_result.state_changes,
                 block_hash.clone(),
@@ -501,6 +568,8 @@ impl NightshadeRuntime {
             total_gas_burnt,
             total_balance_burnt,
             proof: apply_result.proof,
+            congestion_info: apply_result.congestion_info,
+            total_priority_fees: apply_result.stats.priority_fees,
         };
 
         Ok(result)
@@ -535,8 +604,13 @@ impl NightshadeRuntime {
     }
 }
 
-pub fn state_record_to_shard_id(state_record: &StateRecord, shard_layout: &ShardLayout) -> ShardId {
-    account_id_to_shard_id(state_record_to_account_id(state_record), shard_layout)
+/// Returns the shard the record belongs to, `None` for the records of all the shards.
+pub fn state_record_to_shard_id(
+    state_record: &StateRecord,
+    shard_layout: &ShardLayout,
+) -> Option<ShardId> {
+    state_record_to_account_id(state_record)
+        .map(|account_id| account_id_to_shard_id(account_id, shard_layout))
 }
 
 impl RuntimeAdapter for NightshadeRuntime {
@@ -556,6 +630,16 @@ impl RuntimeAdapter for NightshadeRuntime {
         self.tries.get_view_trie_for_shard(shard_id)
     }
 
+    fn prefetch_state(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        transactions: &[SignedTransaction],
+        receipts: &[Receipt],
+    ) {
+        self.tries.prefetch(shard_id, *state_root, transactions, receipts);
+    }
+
     fn verify_block_vrf(
         &self,
         epoch_id: &EpochId,
@@ -586,10 +670,15 @@ impl RuntimeAdapter for NightshadeRuntime {
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error> {
         let runtime_config = self.runtime_config.for_protocol_version(current_protocol_version);
+        let shard_layout = {
+            let epoch_manager = self.epoch_manager.as_ref().read().expect(POISONED_LOCK_ERR);
+            epoch_manager.get_shard_layout_for_protocol_version(current_protocol_version)
+        };
 
         if let Some(state_root) = state_root {
-            let shard_id = self.account_id_to_shard_id(&transaction.transaction.signer_id);
-            let mut state_update = self.get_tries().new_trie_update(shard_id, state_root);
+            let shard_id =
+                account_id_to_shard_id(&transaction.transaction.signer_id, &shard_layout);
+            let mut state_update = self.get_tries().new_flat_trie_update(shard_id, state_root);
 
             match verify_and_charge_transaction(
                 runtime_config,
@@ -600,6 +689,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 // here we do not know which block the transaction will be included
                 // and therefore skip the check on the nonce upper bound.
                 None,
+                shard_layout.num_shards(),
                 current_protocol_version,
             ) {
                 Ok(_) => Ok(None),
@@ -619,6 +709,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                 gas_price,
                 &transaction,
                 verify_signature,
+                shard_layout.num_shards(),
                 current_protocol_version,
             ) {
                 Ok(_) => Ok(None),
@@ -645,7 +736,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<SignedTransaction>, Error> {
-        let mut state_update = self.get_tries().new_trie_update(shard_id, state_root);
+        let mut state_update = self.get_tries().new_flat_trie_update(shard_id, state_root);
 
         // Total amount of gas burnt for converting transactions towards receipts.
         let mut total_gas_burnt = 0;
@@ -655,6 +746,12 @@ impl RuntimeAdapter for NightshadeRuntime {
         let mut num_checked_transactions = 0;
 
         let runtime_config = self.runtime_config.for_protocol_version(current_protocol_version);
+        let num_shards = {
+            let epoch_manager = self.epoch_manager.as_ref().read().expect(POISONED_LOCK_ERR);
+            epoch_manager
+                .get_shard_layout_for_protocol_version(current_protocol_version)
+                .num_shards()
+        };
 
         while total_gas_burnt < transactions_gas_limit {
             if let Some(iter) = pool_iterator.next() {
@@ -670,6 +767,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                             &tx,
                             false,
                             Some(next_block_height),
+                            num_shards,
                             current_protocol_version,
                         ) {
                             Ok(verification_result) => {
@@ -923,11 +1021,6 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
-    fn num_shards(&self) -> NumShards {
-        // TODO: should be dynamic.
-        self.genesis_config.num_block_producer_seats_per_shard.len() as NumShards
-    }
-
     fn num_total_parts(&self) -> usize {
         let seats = self.genesis_config.num_block_producer_seats;
         if seats > 1 {
@@ -946,9 +1039,9 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
-    fn account_id_to_shard_id(&self, account_id: &AccountId) -> ShardId {
-        // TODO: change this function to take the correct shard layout according to epochs
-        account_id_to_shard_id(account_id, &ShardLayout::v0(self.num_shards()))
+    fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, Error> {
+        let mut epoch_manager = self.epoch_manager.as_ref().write().expect(POISONED_LOCK_ERR);
+        epoch_manager.get_shard_layout(epoch_id).map_err(Error::from)
     }
 
     fn get_part_owner(&self, parent_hash: &CryptoHash, part_id: u64) -> Result<AccountId, Error> {
@@ -1002,7 +1095,11 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_epoch_start_height(block_hash).map_err(Error::from)
     }
 
-    fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight {
+    fn get_gc_stop_height_for_num_epochs(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs: u64,
+    ) -> BlockHeight {
         let genesis_height = self.genesis_config.genesis_height;
         macro_rules! unwrap_result_or_return {
             ($obj: expr) => {
@@ -1022,7 +1119,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             // maintain pointers to avoid cloning.
             let mut last_block_in_prev_epoch = *epoch_first_block_info.prev_hash();
             let mut epoch_start_height = *epoch_first_block_info.height();
-            for _ in 0..NUM_EPOCHS_TO_KEEP_STORE_DATA - 1 {
+            for _ in 0..num_epochs.saturating_sub(1) {
                 let epoch_first_block =
                     *epoch_manager.get_block_info(&last_block_in_prev_epoch)?.epoch_first_block();
                 let epoch_first_block_info = epoch_manager.get_block_info(&epoch_first_block)?;
@@ -1145,6 +1242,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             block_header_info.chunk_mask,
             block_header_info.slashed_validators,
             block_header_info.total_supply,
+            block_header_info.priority_fees,
             block_header_info.latest_protocol_version,
             block_header_info.timestamp_nanosec,
         );
@@ -1165,6 +1263,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges: &ChallengesResult,
         random_seed: CryptoHash,
         generate_storage_proof: bool,
@@ -1187,6 +1286,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             last_validator_proposals,
             gas_price,
             gas_limit,
+            congested_shards,
             challenges,
             random_seed,
             is_new_chunk,
@@ -1217,6 +1317,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         last_validator_proposals: ValidatorStakeIter,
         gas_price: Balance,
         gas_limit: Gas,
+        congested_shards: &[ShardId],
         challenges: &ChallengesResult,
         random_value: CryptoHash,
         is_new_chunk: bool,
@@ -1236,6 +1337,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             last_validator_proposals,
             gas_price,
             gas_limit,
+            congested_shards,
             challenges,
             random_value,
             is_new_chunk,
@@ -1254,27 +1356,37 @@ impl RuntimeAdapter for NightshadeRuntime {
         block_hash: &CryptoHash,
         epoch_id: &EpochId,
         request: &QueryRequest,
+        include_proof: bool,
     ) -> Result<QueryResponse, near_chain::near_chain_primitives::error::QueryError> {
-        match request {
+        let trie = self.get_tries().get_view_trie_for_shard(shard_id);
+        let flat_state_reads = match request {
+            // The gas of view calls depends on the nodes touched by the reads.
+            QueryRequest::CallFunction { .. } => self
+                .get_epoch_protocol_version(epoch_id)
+                .map_or(false, Self::flat_state_reads_enabled),
+            _ => true,
+        };
+        let trie = if flat_state_reads {
+            trie.with_flat_state_reads(self.tries.get_flat_state())
+        } else {
+            trie
+        };
+        let trie = Rc::new(if include_proof { trie.recording_reads() } else { trie });
+        let state_update = TrieUpdate::new(Rc::clone(&trie), *state_root);
+        let kind = match request {
             QueryRequest::ViewAccount { account_id } => {
                 let account = self
-                    .view_account(shard_id, *state_root, account_id)
+                    .trie_viewer
+                    .view_account(&state_update, account_id)
                     .map_err(|err| near_chain::near_chain_primitives::error::QueryError::from_view_account_error(err, block_height, *block_hash))?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::ViewAccount(account.into()),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::ViewAccount(account.into())
             }
             QueryRequest::ViewCode { account_id } => {
                 let contract_code = self
-                    .view_contract_code(shard_id, *state_root, account_id)
+                    .trie_viewer
+                    .view_contract_code(&state_update, account_id)
                     .map_err(|err| near_chain::near_chain_primitives::error::QueryError::from_view_contract_code_error(err, block_height, *block_hash))?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::ViewCode(contract_code.into()),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::ViewCode(contract_code.into())
             }
             QueryRequest::CallFunction { account_id, method_name, args } => {
                 let mut logs = vec![];
@@ -1290,37 +1402,37 @@ impl RuntimeAdapter for NightshadeRuntime {
                     })?;
                     (epoch_info.epoch_height(), epoch_info.protocol_version())
                 };
+                let view_state = ViewApplyState {
+                    block_height,
+                    prev_block_hash: *prev_block_hash,
+                    block_hash: *block_hash,
+                    epoch_id: epoch_id.clone(),
+                    epoch_height,
+                    block_timestamp,
+                    current_protocol_version,
+                    cache: Some(Arc::new(StoreCompiledContractCache {
+                        store: self.tries.get_store(),
+                    })),
+                };
 
                 let call_function_result = self
+                    .trie_viewer
                     .call_function(
-                        shard_id,
-                        *state_root,
-                        block_height,
-                        block_timestamp,
-                        prev_block_hash,
-                        block_hash,
-                        epoch_height,
-                        epoch_id,
+                        state_update,
+                        view_state,
                         account_id,
                         method_name,
                         args.as_ref(),
                         &mut logs,
                         &self.epoch_manager,
-                        current_protocol_version,
                     )
                     .map_err(|err| near_chain::near_chain_primitives::error::QueryError::from_call_function_error(err, block_height, *block_hash))?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::CallResult(CallResult {
-                        result: call_function_result,
-                        logs,
-                    }),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::CallResult(CallResult { result: call_function_result, logs })
             }
             QueryRequest::ViewState { account_id, prefix } => {
                 let view_state_result = self
-                    .view_state(shard_id, *state_root, account_id, prefix.as_ref())
+                    .trie_viewer
+                    .view_state(&state_update, account_id, prefix.as_ref())
                     .map_err(|err| {
                         near_chain::near_chain_primitives::error::QueryError::from_view_state_error(
                             err,
@@ -1328,38 +1440,31 @@ impl RuntimeAdapter for NightshadeRuntime {
                             *block_hash,
                         )
                     })?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::ViewState(view_state_result),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::ViewState(view_state_result)
             }
             QueryRequest::ViewAccessKeyList { account_id } => {
                 let access_key_list =
-                    self.view_access_keys(shard_id, *state_root, account_id).map_err(|err| {
+                    self.trie_viewer.view_access_keys(&state_update, account_id).map_err(|err| {
                         near_chain::near_chain_primitives::error::QueryError::from_view_access_key_error(
                             err,
                             block_height,
                             *block_hash,
                         )
                     })?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::AccessKeyList(
-                        access_key_list
-                            .into_iter()
-                            .map(|(public_key, access_key)| AccessKeyInfoView {
-                                public_key,
-                                access_key: access_key.into(),
-                            })
-                            .collect(),
-                    ),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::AccessKeyList(
+                    access_key_list
+                        .into_iter()
+                        .map(|(public_key, access_key)| AccessKeyInfoView {
+                            public_key,
+                            access_key: access_key.into(),
+                        })
+                        .collect(),
+                )
             }
             QueryRequest::ViewAccessKey { account_id, public_key } => {
                 let access_key = self
-                    .view_access_key(shard_id, *state_root, account_id, public_key)
+                    .trie_viewer
+                    .view_access_key(&state_update, account_id, public_key)
                     .map_err(|err| {
                         near_chain::near_chain_primitives::error::QueryError::from_view_access_key_error(
                             err,
@@ -1367,13 +1472,28 @@ impl RuntimeAdapter for NightshadeRuntime {
                             *block_hash,
                         )
                     })?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::AccessKey(access_key.into()),
-                    block_height,
-                    block_hash: *block_hash,
-                })
+                QueryResponseKind::AccessKey(access_key.into())
             }
-        }
+        };
+        // Every node read while answering the query is recorded, including the contract code and
+        // any storage accessed by a view function call.
+        let proof = trie.recorded_storage().map(|partial_storage| {
+            partial_storage.nodes.0.iter().map(to_base64).collect::<TrieProofPath>()
+        });
+        Ok(QueryResponse { kind, block_height, block_hash: *block_hash, proof })
+    }
+
+    fn view_delayed_receipts(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        from_index: Option<u64>,
+        limit: u64,
+    ) -> Result<DelayedReceiptsView, Error> {
+        let state_update = self.tries.new_trie_update_view(shard_id, *state_root);
+        self.trie_viewer
+            .view_delayed_receipts(&state_update, from_index, limit)
+            .map_err(|err| ErrorKind::Other(err.to_string()).into())
     }
 
     fn get_validator_info(
@@ -1539,6 +1659,111 @@ impl RuntimeAdapter for NightshadeRuntime {
             epoch_manager.get_prev_epoch_id(prev_block_hash).map_err(Error::from)
         }
     }
+
+    fn build_state_for_split_shards(
+        &self,
+        shard_uid: ShardUId,
+        state_root: &StateRoot,
+        next_epoch_shard_layout: &ShardLayout,
+    ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
+        let shard_id = shard_uid.shard_id();
+        let split_shards = next_epoch_shard_layout
+            .get_split_shards(shard_id)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidShardId(shard_id)))?;
+        let mut state_roots: HashMap<_, _> = split_shards
+            .into_iter()
+            .map(|shard_id| {
+                (
+                    ShardUId::from_shard_id_and_layout(shard_id, next_epoch_shard_layout),
+                    StateRoot::default(),
+                )
+            })
+            .collect();
+        let split_shard_uids: HashSet<_> = state_roots.keys().cloned().collect();
+        let checked_account_id_to_shard_id = |account_id: &AccountId| {
+            let new_shard_uid = ShardUId::from_shard_id_and_layout(
+                account_id_to_shard_id(account_id, next_epoch_shard_layout),
+                next_epoch_shard_layout,
+            );
+            // Every account of the parent shard must belong to one of its split shards.
+            assert!(
+                split_shard_uids.contains(&new_shard_uid),
+                "Inconsistent shard layout: account {} of shard {} is mapped to {:?}",
+                account_id,
+                shard_id,
+                new_shard_uid
+            );
+            new_shard_uid
+        };
+
+        let trie = self.tries.get_view_trie_for_shard(shard_id);
+        let state_root_node = trie
+            .retrieve_root_node(state_root)
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        let num_parts = get_num_state_parts(state_root_node.memory_usage);
+        debug!(target: "runtime", "Splitting state of shard {} in {} parts", shard_id, num_parts);
+        for part_id in 0..num_parts {
+            let trie_items = trie
+                .get_trie_items_for_part(part_id, num_parts, state_root)
+                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+            let (store_update, new_state_roots) = self
+                .tries
+                .add_values_to_split_states(
+                    &state_roots,
+                    trie_items,
+                    &checked_account_id_to_shard_id,
+                )
+                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+            state_roots = new_state_roots;
+            store_update.commit()?;
+        }
+
+        let state_update = self.tries.new_trie_update_view(shard_id, *state_root);
+        let delayed_receipts = get_delayed_receipts(&state_update)
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        let (store_update, new_state_roots) = self
+            .tries
+            .add_delayed_receipts_to_split_states(
+                &state_roots,
+                delayed_receipts,
+                &checked_account_id_to_shard_id,
+            )
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        store_update.commit()?;
+        Ok(new_state_roots)
+    }
+
+    fn apply_update_to_split_states(
+        &self,
+        block_hash: &CryptoHash,
+        state_roots: HashMap<ShardUId, StateRoot>,
+        next_epoch_shard_layout: &ShardLayout,
+        state_changes: StateChangesForSplitStates,
+    ) -> Result<Vec<ApplySplitStateResult>, Error> {
+        let trie_changes = self
+            .tries
+            .apply_state_changes_to_split_states(&state_roots, state_changes, &|account_id| {
+                ShardUId::from_shard_id_and_layout(
+                    account_id_to_shard_id(account_id, next_epoch_shard_layout),
+                    next_epoch_shard_layout,
+                )
+            })
+            .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+        Ok(trie_changes
+            .into_iter()
+            .map(|(shard_uid, (trie_changes, state_changes))| ApplySplitStateResult {
+                shard_uid,
+                new_root: trie_changes.new_root,
+                trie_changes: WrappedTrieChanges::new_for_split_state(
+                    self.get_tries(),
+                    shard_uid,
+                    trie_changes,
+                    state_changes,
+                    *block_hash,
+                ),
+            })
+            .collect())
+    }
 }
 
 impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
@@ -1548,7 +1773,7 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         state_root: MerkleHash,
         account_id: &AccountId,
     ) -> Result<Account, node_runtime::state_viewer::errors::ViewAccountError> {
-        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        let state_update = self.get_tries().new_flat_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_account(&state_update, account_id)
     }
 
@@ -1558,7 +1783,7 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         state_root: MerkleHash,
         account_id: &AccountId,
     ) -> Result<ContractCode, node_runtime::state_viewer::errors::ViewContractCodeError> {
-        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        let state_update = self.get_tries().new_flat_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_contract_code(&state_update, account_id)
     }
 
@@ -1579,7 +1804,11 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<u8>, node_runtime::state_viewer::errors::CallFunctionError> {
-        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        let state_update = if Self::flat_state_reads_enabled(current_protocol_version) {
+            self.get_tries().new_flat_trie_update_view(shard_id, state_root)
+        } else {
+            self.get_tries().new_trie_update_view(shard_id, state_root)
+        };
         let view_state = ViewApplyState {
             block_height: height,
             prev_block_hash: *prev_block_hash,
@@ -1608,7 +1837,7 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         account_id: &AccountId,
         public_key: &PublicKey,
     ) -> Result<AccessKey, node_runtime::state_viewer::errors::ViewAccessKeyError> {
-        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        let state_update = self.get_tries().new_flat_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_access_key(&state_update, account_id, public_key)
     }
 
@@ -1619,7 +1848,7 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         account_id: &AccountId,
     ) -> Result<Vec<(PublicKey, AccessKey)>, node_runtime::state_viewer::errors::ViewAccessKeyError>
     {
-        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        let state_update = self.get_tries().new_flat_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_access_keys(&state_update, account_id)
     }
 
@@ -1648,6 +1877,7 @@ mod test {
     use near_primitives::challenge::SlashedValidator;
     use near_primitives::receipt::ReceiptResult;
     use near_primitives::runtime::config::RuntimeConfig;
+    use near_primitives::state_proof::{StateProof, StateProofError};
     use near_primitives::transaction::{Action, DeleteAccountAction, StakeAction};
     use near_primitives::types::{BlockHeightDelta, Nonce, ValidatorId, ValidatorKickoutReason};
     use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
@@ -1655,6 +1885,7 @@ mod test {
         AccountView, CurrentEpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
     };
     use near_store::create_store;
+    use node_runtime::adapter::ViewRuntimeAdapter;
 
     use crate::config::{GenesisExt, TESTING_INIT_BALANCE, TESTING_INIT_STAKE};
     use crate::get_store_path;
@@ -1709,6 +1940,7 @@ mod test {
                     last_proposals,
                     gas_price,
                     gas_limit,
+                    &[],
                     challenges,
                     CryptoHash::default(),
                     true,
@@ -1718,7 +1950,7 @@ mod test {
                 .unwrap();
             let mut store_update = self.store.store_update();
             result.trie_changes.insertions_into(&mut store_update).unwrap();
-            result.trie_changes.state_changes_into(&mut store_update);
+            result.trie_changes.state_changes_into(height, &mut store_update);
             store_update.commit().unwrap();
             (result.new_root, result.validator_proposals, result.receipt_result)
         }
@@ -1787,6 +2019,7 @@ mod test {
                     slashed_validators: vec![],
                     chunk_mask: vec![],
                     total_supply: genesis_total_supply,
+                    priority_fees: 0,
                     latest_protocol_version: genesis_protocol_version,
                     timestamp_nanosec: 0,
                 })
@@ -1817,7 +2050,7 @@ mod test {
             challenges_result: ChallengesResult,
         ) {
             let new_hash = hash(&vec![(self.head.height + 1) as u8]);
-            let num_shards = self.runtime.num_shards();
+            let num_shards = self.runtime.num_shards(&self.head.epoch_id).unwrap();
             assert_eq!(transactions.len() as NumShards, num_shards);
             assert_eq!(chunk_mask.len() as NumShards, num_shards);
             let mut all_proposals = vec![];
@@ -1859,6 +2092,7 @@ mod test {
                     slashed_validators: challenges_result,
                     chunk_mask,
                     total_supply: self.runtime.genesis_config.total_supply,
+                    priority_fees: 0,
                     latest_protocol_version: self.runtime.genesis_config.protocol_version,
                     timestamp_nanosec: self.time + 10u64.pow(9),
                 })
@@ -1890,7 +2124,8 @@ mod test {
         }
 
         pub fn view_account(&self, account_id: &AccountId) -> AccountView {
-            let shard_id = self.runtime.account_id_to_shard_id(account_id);
+            let shard_id =
+                self.runtime.account_id_to_shard_id(account_id, &self.head.epoch_id).unwrap();
             self.runtime
                 .view_account(shard_id, self.state_roots[shard_id as usize], account_id)
                 .unwrap()
@@ -2261,6 +2496,71 @@ mod test {
             .unwrap());
     }
 
+    #[test]
+    fn test_query_with_proof() {
+        let validators = (0..2)
+            .map(|i| AccountId::try_from(format!("test{}", i + 1)).unwrap())
+            .collect::<Vec<_>>();
+        let env = TestEnv::new(
+            "test_query_with_proof",
+            vec![validators.clone()],
+            2,
+            vec![],
+            vec![],
+            true,
+        );
+        let state_root = env.state_roots[0];
+        let query = |request: QueryRequest, include_proof: bool| {
+            env.runtime
+                .query(
+                    0,
+                    &state_root,
+                    env.head.height,
+                    0,
+                    &env.head.prev_block_hash,
+                    &env.head.last_block_hash,
+                    &env.head.epoch_id,
+                    &request,
+                    include_proof,
+                )
+                .unwrap()
+        };
+
+        let response =
+            query(QueryRequest::ViewAccount { account_id: validators[0].clone() }, false);
+        assert!(response.proof.is_none());
+
+        let response = query(QueryRequest::ViewAccount { account_id: validators[0].clone() }, true);
+        let proof = StateProof::from_trie_proof_path(&response.proof.unwrap()).unwrap();
+        let account = proof.get_account(&state_root, &validators[0]).unwrap().unwrap();
+        assert_eq!(account, env.runtime.view_account(0, state_root, &validators[0]).unwrap());
+        // The proof doesn't cover the rest of the state, and can't be used with another root.
+        assert!(matches!(
+            proof.get_account(&state_root, &validators[1]),
+            Err(StateProofError::MissingNode(_))
+        ));
+        assert!(proof.get_account(&hash(b"other root"), &validators[0]).is_err());
+
+        let public_key = InMemorySigner::from_seed(
+            validators[1].clone(),
+            KeyType::ED25519,
+            validators[1].as_ref(),
+        )
+        .public_key;
+        let response = query(
+            QueryRequest::ViewAccessKey {
+                account_id: validators[1].clone(),
+                public_key: public_key.clone(),
+            },
+            true,
+        );
+        let proof = StateProof::from_trie_proof_path(&response.proof.unwrap()).unwrap();
+        assert_eq!(
+            proof.get_access_key(&state_root, &validators[1], &public_key).unwrap(),
+            Some(AccessKey::full_access())
+        );
+    }
+
     #[test]
     fn test_state_sync() {
         init_test_logger();
@@ -2311,6 +2611,7 @@ mod test {
                     slashed_validators: vec![],
                     chunk_mask: vec![true],
                     total_supply: new_env.runtime.genesis_config.total_supply,
+                    priority_fees: 0,
                     latest_protocol_version: new_env.runtime.genesis_config.protocol_version,
                     timestamp_nanosec: new_env.time,
                 })
@@ -2382,7 +2683,10 @@ mod test {
             validators[0].as_ref(),
         );
         let staking_transaction = stake(1, &signer, &block_producers[0], TESTING_INIT_STAKE - 1);
-        let first_account_shard_id = env.runtime.account_id_to_shard_id(&"test1".parse().unwrap());
+        let first_account_shard_id = env
+            .runtime
+            .account_id_to_shard_id(&"test1".parse().unwrap(), &env.head.epoch_id)
+            .unwrap();
         let transactions = if first_account_shard_id == 0 {
             vec![vec![staking_transaction], vec![]]
         } else {
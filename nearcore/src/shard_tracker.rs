@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
 
 use tracing::info;
@@ -6,28 +6,23 @@ use tracing::info;
 use near_epoch_manager::EpochManager;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
-use near_primitives::types::{AccountId, EpochId, ShardId};
+use near_primitives::types::{AccountId, ShardId};
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
 /// Tracker that tracks shard ids and accounts. It maintains two items: `tracked_accounts` and
 /// `tracked_shards`. The shards that are actually tracked are the union of shards that `tracked_accounts`
 /// are in and `tracked_shards`.
+/// The shards of the tracked accounts are computed with the shard layout of the epoch in question,
+/// so the tracked accounts are followed across shard layout changes.
 #[derive(Clone)]
 pub struct ShardTracker {
-    /// Tracked accounts by shard id. For each shard id, the corresponding set of accounts should be
-    /// non empty (otherwise the entry should not exist).
-    tracked_accounts: HashMap<ShardId, HashSet<AccountId>>,
+    /// Tracked accounts.
+    tracked_accounts: HashSet<AccountId>,
     /// Tracked shards.
     tracked_shards: HashSet<ShardId>,
-    /// Combination of shards that correspond to tracked accounts and tracked shards.
-    actual_tracked_shards: HashSet<ShardId>,
     /// Epoch manager that for given block hash computes the epoch id.
     epoch_manager: Arc<RwLock<EpochManager>>,
-    /// Current shard layout
-    /// TODO: ShardTracker does not work if shard_layout is changed,
-    ///       fix this when https://github.com/near/nearcore/pull/4668 is merged
-    shard_layout: ShardLayout,
 }
 
 impl ShardTracker {
@@ -36,37 +31,10 @@ impl ShardTracker {
         shards: Vec<ShardId>,
         epoch_manager: Arc<RwLock<EpochManager>>,
     ) -> Self {
-        let shard_layout = {
-            let mut epoch_manager = epoch_manager.write().expect(POISONED_LOCK_ERR);
-            epoch_manager.get_shard_layout(&EpochId::default()).unwrap()
-        };
-        let tracked_accounts = accounts.into_iter().fold(HashMap::new(), |mut acc, x| {
-            let shard_id = account_id_to_shard_id(&x, &shard_layout);
-            acc.entry(shard_id).or_insert_with(HashSet::new).insert(x);
-            acc
-        });
+        let tracked_accounts: HashSet<_> = accounts.into_iter().collect();
         let tracked_shards: HashSet<_> = shards.into_iter().collect();
-        let mut actual_tracked_shards = tracked_shards.clone();
-        for (shard_id, _) in tracked_accounts.iter() {
-            actual_tracked_shards.insert(*shard_id);
-        }
-        info!(target: "runtime", "Tracking shards: {:?}", actual_tracked_shards);
-        ShardTracker {
-            tracked_accounts,
-            tracked_shards,
-            actual_tracked_shards,
-            epoch_manager,
-            shard_layout,
-        }
-    }
-
-    fn track_account(&mut self, account_id: &AccountId) {
-        let shard_id = account_id_to_shard_id(account_id, &self.shard_layout);
-        self.tracked_accounts
-            .entry(shard_id)
-            .or_insert_with(HashSet::new)
-            .insert(account_id.clone());
-        self.actual_tracked_shards.insert(shard_id);
+        info!(target: "runtime", "Tracking shards: {:?}, accounts: {:?}", tracked_shards, tracked_accounts);
+        ShardTracker { tracked_accounts, tracked_shards, epoch_manager }
     }
 
     /// Track a list of accounts. The tracking will take effect immediately because
@@ -74,21 +42,28 @@ impl ShardTracker {
     /// us to start tracking them earlier.
     #[allow(unused)]
     pub fn track_accounts(&mut self, account_ids: &[AccountId]) {
-        for account_id in account_ids.iter() {
-            self.track_account(account_id);
-        }
-    }
-
-    fn track_shard(&mut self, shard_id: ShardId) {
-        self.tracked_shards.insert(shard_id);
-        self.actual_tracked_shards.insert(shard_id);
+        self.tracked_accounts.extend(account_ids.iter().cloned());
     }
 
     /// Track a list of shards. Similar to tracking accounts, the tracking starts immediately.
     #[allow(unused)]
     pub fn track_shards(&mut self, shard_ids: &[ShardId]) {
-        for shard_id in shard_ids.iter() {
-            self.track_shard(*shard_id);
+        self.tracked_shards.extend(shard_ids.iter().cloned());
+    }
+
+    /// Whether `shard_id` of `shard_layout` is tracked either explicitly or because one of the
+    /// tracked accounts belongs to it. If the shard layout is unknown, only the explicitly tracked
+    /// shards are considered.
+    fn tracks_shard(&self, shard_layout: Option<ShardLayout>, shard_id: ShardId) -> bool {
+        if self.tracked_shards.contains(&shard_id) {
+            return true;
+        }
+        match shard_layout {
+            Some(shard_layout) => self
+                .tracked_accounts
+                .iter()
+                .any(|account_id| account_id_to_shard_id(account_id, &shard_layout) == shard_id),
+            None => false,
         }
     }
 
@@ -99,20 +74,20 @@ impl ShardTracker {
         shard_id: ShardId,
         is_me: bool,
     ) -> bool {
+        let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
         if let Some(account_id) = account_id {
-            let account_cares_about_shard = {
-                let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
-                epoch_manager
-                    .cares_about_shard_from_prev_block(parent_hash, account_id, shard_id)
-                    .unwrap_or(false)
-            };
-            if !is_me {
+            let account_cares_about_shard = epoch_manager
+                .cares_about_shard_from_prev_block(parent_hash, account_id, shard_id)
+                .unwrap_or(false);
+            if !is_me || account_cares_about_shard {
                 return account_cares_about_shard;
             }
-            account_cares_about_shard || self.actual_tracked_shards.contains(&shard_id)
-        } else {
-            self.actual_tracked_shards.contains(&shard_id)
         }
+        let shard_layout = epoch_manager
+            .get_epoch_id_from_prev_block(parent_hash)
+            .and_then(|epoch_id| epoch_manager.get_shard_layout(&epoch_id))
+            .ok();
+        self.tracks_shard(shard_layout, shard_id)
     }
 
     // If ShardLayout will change in the next epoch, `shard_id` refers to shard in the new
@@ -124,20 +99,20 @@ impl ShardTracker {
         shard_id: ShardId,
         is_me: bool,
     ) -> bool {
+        let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
         if let Some(account_id) = account_id {
-            let account_cares_about_shard = {
-                let mut epoch_manager = self.epoch_manager.write().expect(POISONED_LOCK_ERR);
-                epoch_manager
-                    .cares_about_shard_next_epoch_from_prev_block(parent_hash, account_id, shard_id)
-                    .unwrap_or(false)
-            };
-            if !is_me {
+            let account_cares_about_shard = epoch_manager
+                .cares_about_shard_next_epoch_from_prev_block(parent_hash, account_id, shard_id)
+                .unwrap_or(false);
+            if !is_me || account_cares_about_shard {
                 return account_cares_about_shard;
-            } else if account_cares_about_shard {
-                return true;
             }
         }
-        self.actual_tracked_shards.contains(&shard_id)
+        let shard_layout = epoch_manager
+            .get_next_epoch_id_from_prev_block(parent_hash)
+            .and_then(|epoch_id| epoch_manager.get_shard_layout(&epoch_id))
+            .ok();
+        self.tracks_shard(shard_layout, shard_id)
     }
 }
 
@@ -155,8 +130,15 @@ mod tests {
     use near_primitives::types::{AccountId, BlockHeight, NumShards, ProtocolVersion};
     use near_store::test_utils::create_test_store;
 
-    use super::{account_id_to_shard_id, ShardTracker};
+    use super::{account_id_to_shard_id, ShardTracker, POISONED_LOCK_ERR};
+    use near_epoch_manager::test_utils::hash_range;
     use near_primitives::shard_layout::ShardLayout;
+    #[cfg(feature = "protocol_feature_simple_nightshade")]
+    use near_primitives::types::{EpochId, ShardId};
+    #[cfg(feature = "protocol_feature_simple_nightshade")]
+    use near_primitives::utils::get_num_seats_per_shard;
+    #[cfg(feature = "protocol_feature_simple_nightshade")]
+    use near_primitives::version::ProtocolFeature::SimpleNightshade;
 
     use near_primitives::version::PROTOCOL_VERSION;
     use num_rational::Rational;
@@ -231,6 +213,7 @@ mod tests {
                     vec![],
                     vec![],
                     DEFAULT_TOTAL_SUPPLY,
+                    0,
                     protocol_version,
                     height * 10u64.pow(9),
                 ),
@@ -244,20 +227,38 @@ mod tests {
     #[test]
     fn test_track_new_accounts_and_shards() {
         let epoch_manager = get_epoch_manager(PROTOCOL_VERSION, 4, None);
-        let mut tracker = ShardTracker::new(vec![], vec![], epoch_manager);
+        let mut tracker = ShardTracker::new(vec![], vec![], epoch_manager.clone());
         tracker.track_accounts(&["test1".parse().unwrap(), "test2".parse().unwrap()]);
         tracker.track_shards(&[2, 3]);
         let mut total_tracked_shards = HashSet::new();
+        let shard_layout = ShardLayout::v0(4);
         total_tracked_shards
-            .insert(account_id_to_shard_id(&"test1".parse().unwrap(), &tracker.shard_layout));
+            .insert(account_id_to_shard_id(&"test1".parse().unwrap(), &shard_layout));
         total_tracked_shards
-            .insert(account_id_to_shard_id(&"test2".parse().unwrap(), &tracker.shard_layout));
+            .insert(account_id_to_shard_id(&"test2".parse().unwrap(), &shard_layout));
         total_tracked_shards.insert(2);
         total_tracked_shards.insert(3);
-        assert_eq!(tracker.actual_tracked_shards, total_tracked_shards);
+
+        let h = hash_range(1);
+        {
+            let mut epoch_manager = epoch_manager.write().expect(POISONED_LOCK_ERR);
+            record_block(
+                &mut epoch_manager,
+                CryptoHash::default(),
+                h[0],
+                0,
+                vec![],
+                PROTOCOL_VERSION,
+            );
+        }
+        for shard_id in 0..4 {
+            assert_eq!(
+                tracker.care_about_shard(None, &h[0], shard_id, true),
+                total_tracked_shards.contains(&shard_id)
+            );
+        }
     }
 
-    /*
     #[test]
     #[cfg(feature = "protocol_feature_simple_nightshade")]
     fn test_track_shards_shard_layout_change() {
@@ -266,6 +267,7 @@ mod tests {
             vec!["aurora".parse().unwrap()],
             vec!["h", "o"].into_iter().map(|x| x.parse().unwrap()).collect(),
             Some(vec![0, 0, 0, 0]),
+            1,
         );
         let shard_config = ShardConfig {
             num_block_producer_seats_per_shard: get_num_seats_per_shard(4, 2),
@@ -308,7 +310,38 @@ mod tests {
             );
         }
 
-        // TODO: verify tracker is tracking the correct shards before and after resharding
+        // "near" is in shard 2 and "zoo" is in shard 3 of the new layout, shard 0 is tracked
+        // explicitly.
+        let new_tracked_shards: HashSet<ShardId> = vec![0, 2, 3].into_iter().collect();
+        let mut seen_layouts = HashSet::new();
+        for i in 0..3 {
+            let (layout, next_layout) = {
+                let mut epoch_manager = epoch_manager.write().expect(POISONED_LOCK_ERR);
+                let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&h[i]).unwrap();
+                let next_epoch_id = epoch_manager.get_next_epoch_id_from_prev_block(&h[i]).unwrap();
+                (
+                    epoch_manager.get_shard_layout(&epoch_id).unwrap(),
+                    epoch_manager.get_shard_layout(&next_epoch_id).unwrap(),
+                )
+            };
+            for (layout, will_care) in vec![(layout, false), (next_layout, true)] {
+                for shard_id in 0..layout.num_shards() {
+                    let expected = if layout == shard_layout {
+                        new_tracked_shards.contains(&shard_id)
+                    } else {
+                        assert_eq!(layout, ShardLayout::v0(1));
+                        true
+                    };
+                    let tracked = if will_care {
+                        tracker.will_care_about_shard(None, &h[i], shard_id, true)
+                    } else {
+                        tracker.care_about_shard(None, &h[i], shard_id, true)
+                    };
+                    assert_eq!(tracked, expected);
+                }
+                seen_layouts.insert(layout.version());
+            }
+        }
+        assert_eq!(seen_layouts.len(), 2);
     }
-     */
 }
@@ -47,6 +47,7 @@ impl NeardCmd {
             NeardSubCommand::Init(cmd) => cmd.run(&home_dir),
             NeardSubCommand::Testnet(cmd) => cmd.run(&home_dir),
             NeardSubCommand::Run(cmd) => cmd.run(&home_dir),
+            NeardSubCommand::Migrate(cmd) => cmd.run(&home_dir),
 
             NeardSubCommand::UnsafeResetData => {
                 let store_path = get_store_path(&home_dir);
@@ -89,6 +90,9 @@ pub(super) enum NeardSubCommand {
     /// and config)
     #[clap(name = "testnet")]
     Testnet(TestnetCmd),
+    /// Applies the migrations the database needs without starting the node
+    #[clap(name = "migrate")]
+    Migrate(MigrateCmd),
     /// (unsafe) Remove all the config, keys, data and effectively removing all information about
     /// the network
     #[clap(name = "unsafe_reset_all")]
@@ -331,6 +335,45 @@ impl TestnetCmd {
     }
 }
 
+#[derive(Clap)]
+pub(super) struct MigrateCmd {
+    /// Only print the migrations the database needs and the number of rows they process,
+    /// without changing the database.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl MigrateCmd {
+    pub(super) fn run(self, home_dir: &Path) {
+        let near_config = nearcore::config::load_config_without_genesis_records(home_dir);
+        let store_path = get_store_path(home_dir);
+        if !nearcore::store_path_exists(&store_path) {
+            println!("No database found at {}", store_path);
+            return;
+        }
+        if !self.dry_run {
+            nearcore::apply_store_migrations(&store_path, &near_config);
+            return;
+        }
+        let estimates = nearcore::estimate_store_migrations(&store_path, &near_config)
+            .expect("Failed to read the database");
+        if estimates.is_empty() {
+            println!("Database is up to date");
+        }
+        for estimate in estimates {
+            println!(
+                "Migration from version {} to {}: {}",
+                estimate.from_version,
+                estimate.from_version + 1,
+                estimate.description
+            );
+            for (col, num_rows) in estimate.num_rows {
+                println!("    {:?}: about {} rows to snapshot and migrate", col, num_rows);
+            }
+        }
+    }
+}
+
 fn init_logging(verbose: Option<&str>) {
     let mut env_filter = EnvFilter::new(
         "tokio_reactor=info,near=info,stats=info,telemetry=info,delay_detector=info,\
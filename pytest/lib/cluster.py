@@ -202,6 +202,10 @@ class BaseNode(object):
     def get_changes(self, changes_request):
         return self.json_rpc('EXPERIMENTAL_changes', changes_request)
 
+    def get_changes_history(self, changes_history_request):
+        return self.json_rpc('EXPERIMENTAL_changes_history',
+                             changes_history_request)
+
     def validators(self):
         return set(
             map(lambda v: v['account_id'],
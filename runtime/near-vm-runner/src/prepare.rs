@@ -2,12 +2,17 @@
 //! wasm module before execution.
 
 use parity_wasm::builder;
-use parity_wasm::elements::{self, External, MemorySection, Type};
+use parity_wasm::elements::{
+    self, BlockType, BulkInstruction, External, Instruction, Instructions, Local, MemorySection,
+    SignExtInstruction, Type, ValueType,
+};
 use pwasm_utils::{self, rules};
 
 use near_vm_errors::PrepareError;
 use near_vm_logic::VMConfig;
 
+mod multi_value;
+
 struct ContractModule<'a> {
     module: elements::Module,
     config: &'a VMConfig,
@@ -15,12 +20,97 @@ struct ContractModule<'a> {
 
 impl<'a> ContractModule<'a> {
     fn init(original_code: &[u8], config: &'a VMConfig) -> Result<Self, PrepareError> {
-        wasmparser::validate(original_code, None).map_err(|_| PrepareError::Deserialization)?;
-        let module = elements::deserialize_buffer(original_code)
+        let validating_config = wasmparser::ValidatingParserConfig {
+            operator_config: wasmparser::OperatorValidatorConfig {
+                enable_threads: false,
+                enable_reference_types: false,
+                enable_simd: false,
+                enable_bulk_memory: config.limit_config.wasm_features.bulk_memory,
+                enable_multi_value: config.limit_config.wasm_features.multi_value,
+            },
+        };
+        wasmparser::validate(original_code, Some(validating_config))
             .map_err(|_| PrepareError::Deserialization)?;
+        // parity-wasm only represents MVP function and block types.
+        let lowered_code = if config.limit_config.wasm_features.multi_value {
+            multi_value::lower_multi_value(original_code)?
+        } else {
+            None
+        };
+        let code = lowered_code.as_deref().unwrap_or(original_code);
+        let module =
+            elements::deserialize_buffer(code).map_err(|_| PrepareError::Deserialization)?;
+        // Bulk memory support is limited to `memory.copy` and `memory.fill`, the passive data
+        // and element segments used by `memory.init`, `data.drop`, `table.init` and `elem.drop`
+        // are rejected.
+        let has_data_count = module
+            .sections()
+            .iter()
+            .any(|section| matches!(section, elements::Section::DataCount(_)));
+        let has_passive_data = module
+            .data_section()
+            .map_or(false, |section| section.entries().iter().any(|segment| segment.passive()));
+        let has_passive_elements = module
+            .elements_section()
+            .map_or(false, |section| section.entries().iter().any(|segment| segment.passive()));
+        if has_data_count || has_passive_data || has_passive_elements {
+            return Err(PrepareError::Deserialization);
+        }
         Ok(ContractModule { module, config })
     }
 
+    /// Rewrites the instructions of the enabled `WasmFeatures` into MVP instructions, so that
+    /// gas and stack height metering and all the VMs only deal with MVP code.
+    ///
+    /// Sign-extension operators become the shifts older compilers emit instead, while
+    /// `memory.copy` and `memory.fill` become calls of functions appended to the module, which
+    /// copy or fill 8 bytes per loop iteration. Instructions of disabled features, and the other
+    /// bulk memory instructions, reject the contract the same way the deserialization does.
+    fn lower_post_mvp_instructions(self) -> Result<Self, PrepareError> {
+        let Self { mut module, config } = self;
+        let features = config.limit_config.wasm_features;
+        // The appended functions come after all the existing ones.
+        let memory_copy_index = module.functions_space() as u32;
+        let memory_fill_index = memory_copy_index + 1;
+        let mut uses_bulk_memory = false;
+        if let Some(code_section) = module.code_section_mut() {
+            for body in code_section.bodies_mut() {
+                let instructions = body.code_mut().elements_mut();
+                let mut lowered = Vec::with_capacity(instructions.len());
+                for instruction in instructions.drain(..) {
+                    match instruction {
+                        Instruction::SignExt(sign_ext) if features.sign_extension => {
+                            lowered.extend(lower_sign_ext(sign_ext))
+                        }
+                        Instruction::Bulk(BulkInstruction::MemoryCopy) if features.bulk_memory => {
+                            uses_bulk_memory = true;
+                            lowered.push(Instruction::Call(memory_copy_index));
+                        }
+                        Instruction::Bulk(BulkInstruction::MemoryFill) if features.bulk_memory => {
+                            uses_bulk_memory = true;
+                            lowered.push(Instruction::Call(memory_fill_index));
+                        }
+                        Instruction::SignExt(_) | Instruction::Bulk(_) => {
+                            return Err(PrepareError::Deserialization);
+                        }
+                        instruction => lowered.push(instruction),
+                    }
+                }
+                *instructions = lowered;
+            }
+        }
+        if !uses_bulk_memory {
+            return Ok(Self { module, config });
+        }
+        let mut builder = builder::from_module(module);
+        builder.push_function(bulk_memory_function(memory_copy_body(), vec![]));
+        builder.push_function(bulk_memory_function(
+            memory_fill_body(),
+            vec![Local::new(1, ValueType::I64)],
+        ));
+        Ok(Self { module: builder.build(), config })
+    }
+
     fn standardize_mem(self) -> Self {
         let Self { mut module, config } = self;
 
@@ -56,6 +146,8 @@ impl<'a> ContractModule<'a> {
         }
     }
 
+    /// Every instruction costs the same, the instructions of post-MVP features are already
+    /// rewritten into MVP ones by `lower_post_mvp_instructions`.
     fn inject_gas_metering(self) -> Result<Self, PrepareError> {
         let Self { module, config } = self;
         // Free config, no need for gas metering.
@@ -142,6 +234,176 @@ impl<'a> ContractModule<'a> {
     }
 }
 
+/// MVP instructions computing the same value as the given sign-extension operator.
+fn lower_sign_ext(instruction: SignExtInstruction) -> Vec<Instruction> {
+    let i32_extend = |bits| {
+        let shift = 32 - bits;
+        vec![
+            Instruction::I32Const(shift),
+            Instruction::I32Shl,
+            Instruction::I32Const(shift),
+            Instruction::I32ShrS,
+        ]
+    };
+    let i64_extend = |bits| {
+        let shift = 64 - bits;
+        vec![
+            Instruction::I64Const(shift),
+            Instruction::I64Shl,
+            Instruction::I64Const(shift),
+            Instruction::I64ShrS,
+        ]
+    };
+    match instruction {
+        SignExtInstruction::I32Extend8S => i32_extend(8),
+        SignExtInstruction::I32Extend16S => i32_extend(16),
+        SignExtInstruction::I64Extend8S => i64_extend(8),
+        SignExtInstruction::I64Extend16S => i64_extend(16),
+        SignExtInstruction::I64Extend32S => {
+            vec![Instruction::I32WrapI64, Instruction::I64ExtendSI32]
+        }
+    }
+}
+
+/// A function taking the three `i32` operands of `memory.copy` or `memory.fill`.
+fn bulk_memory_function(
+    instructions: Vec<Instruction>,
+    locals: Vec<Local>,
+) -> builder::FunctionDefinition {
+    builder::function()
+        .signature()
+        .with_params(vec![ValueType::I32, ValueType::I32, ValueType::I32])
+        .build()
+        .body()
+        .with_locals(locals)
+        .with_instructions(Instructions::new(instructions))
+        .build()
+        .build()
+}
+
+/// Traps unless the `len` bytes starting at `offset` are within the memory, like the bulk
+/// memory instructions do before writing anything.
+fn trap_out_of_bounds(offset: u32, len: u32) -> Vec<Instruction> {
+    vec![
+        Instruction::GetLocal(offset),
+        Instruction::I64ExtendUI32,
+        Instruction::GetLocal(len),
+        Instruction::I64ExtendUI32,
+        Instruction::I64Add,
+        Instruction::CurrentMemory(0),
+        Instruction::I64ExtendUI32,
+        Instruction::I64Const(16),
+        Instruction::I64Shl,
+        Instruction::I64GtU,
+        Instruction::If(BlockType::NoResult),
+        Instruction::Unreachable,
+        Instruction::End,
+    ]
+}
+
+/// `local += delta` for an `i32` local.
+fn add_to_local(local: u32, delta: i32) -> Vec<Instruction> {
+    vec![
+        Instruction::GetLocal(local),
+        Instruction::I32Const(delta),
+        Instruction::I32Add,
+        Instruction::SetLocal(local),
+    ]
+}
+
+/// Repeats `step` while the `i32` local `len` is at least `step_len`.
+fn loop_while_at_least(len: u32, step_len: i32, step: Vec<Instruction>) -> Vec<Instruction> {
+    let mut instructions = vec![
+        Instruction::Block(BlockType::NoResult),
+        Instruction::Loop(BlockType::NoResult),
+        Instruction::GetLocal(len),
+        Instruction::I32Const(step_len),
+        Instruction::I32LtU,
+        Instruction::BrIf(1),
+    ];
+    instructions.extend(step);
+    instructions.extend(vec![Instruction::Br(0), Instruction::End, Instruction::End]);
+    instructions
+}
+
+/// Body of `(func (param $dst i32) (param $src i32) (param $len i32))` behaving like
+/// `memory.copy`. The ranges may overlap, so it copies forward when `$dst` is before `$src` and
+/// backward otherwise.
+fn memory_copy_body() -> Vec<Instruction> {
+    let (dst, src, len) = (0, 1, 2);
+    let copy_forward = |step_len: i32, load: Instruction, store: Instruction| {
+        let mut step = vec![Instruction::GetLocal(dst), Instruction::GetLocal(src), load, store];
+        step.extend(add_to_local(dst, step_len));
+        step.extend(add_to_local(src, step_len));
+        step.extend(add_to_local(len, -step_len));
+        loop_while_at_least(len, step_len, step)
+    };
+    let copy_backward = |step_len: i32, load: Instruction, store: Instruction| {
+        let mut step = add_to_local(len, -step_len);
+        step.extend(vec![
+            Instruction::GetLocal(dst),
+            Instruction::GetLocal(len),
+            Instruction::I32Add,
+            Instruction::GetLocal(src),
+            Instruction::GetLocal(len),
+            Instruction::I32Add,
+            load,
+            store,
+        ]);
+        loop_while_at_least(len, step_len, step)
+    };
+    let mut instructions = trap_out_of_bounds(src, len);
+    instructions.extend(trap_out_of_bounds(dst, len));
+    instructions.extend(vec![
+        Instruction::GetLocal(dst),
+        Instruction::GetLocal(src),
+        Instruction::I32LeU,
+        Instruction::If(BlockType::NoResult),
+    ]);
+    instructions.extend(copy_forward(8, Instruction::I64Load(0, 0), Instruction::I64Store(0, 0)));
+    instructions.extend(copy_forward(
+        1,
+        Instruction::I32Load8U(0, 0),
+        Instruction::I32Store8(0, 0),
+    ));
+    instructions.push(Instruction::Else);
+    instructions.extend(copy_backward(8, Instruction::I64Load(0, 0), Instruction::I64Store(0, 0)));
+    instructions.extend(copy_backward(
+        1,
+        Instruction::I32Load8U(0, 0),
+        Instruction::I32Store8(0, 0),
+    ));
+    instructions.extend(vec![Instruction::End, Instruction::End]);
+    instructions
+}
+
+/// Body of `(func (param $dst i32) (param $value i32) (param $len i32) (local $pattern i64))`
+/// behaving like `memory.fill`.
+fn memory_fill_body() -> Vec<Instruction> {
+    let (dst, value, len, pattern) = (0, 1, 2, 3);
+    let fill = |step_len: i32, operand: u32, store: Instruction| {
+        let mut step = vec![Instruction::GetLocal(dst), Instruction::GetLocal(operand), store];
+        step.extend(add_to_local(dst, step_len));
+        step.extend(add_to_local(len, -step_len));
+        loop_while_at_least(len, step_len, step)
+    };
+    let mut instructions = trap_out_of_bounds(dst, len);
+    // The low byte of the value repeated in all the 8 bytes of `$pattern`.
+    instructions.extend(vec![
+        Instruction::GetLocal(value),
+        Instruction::I32Const(0xff),
+        Instruction::I32And,
+        Instruction::I64ExtendUI32,
+        Instruction::I64Const(0x0101_0101_0101_0101),
+        Instruction::I64Mul,
+        Instruction::SetLocal(pattern),
+    ]);
+    instructions.extend(fill(8, pattern, Instruction::I64Store(0, 0)));
+    instructions.extend(fill(1, value, Instruction::I32Store8(0, 0)));
+    instructions.push(Instruction::End);
+    instructions
+}
+
 /// Loads the given module given in `original_code`, performs some checks on it and
 /// does some preprocessing.
 ///
@@ -151,9 +413,11 @@ impl<'a> ContractModule<'a> {
 /// - imported memory (if any) doesn't reserve more memory than permitted by the `config`,
 /// - all imported functions from the external environment matches defined by `env` module,
 ///
-/// The preprocessing includes injecting code for gas metering and metering the height of stack.
+/// The preprocessing includes rewriting the code using the enabled post-MVP features into MVP
+/// code, injecting code for gas metering and metering the height of stack.
 pub fn prepare_contract(original_code: &[u8], config: &VMConfig) -> Result<Vec<u8>, PrepareError> {
     ContractModule::init(original_code, config)?
+        .lower_post_mvp_instructions()?
         .standardize_mem()
         .ensure_no_internal_memory()?
         .inject_gas_metering()?
@@ -226,4 +490,21 @@ mod tests {
         assert_matches!(r, Err(Error::Instantiate));
         */
     }
+
+    #[test]
+    fn passive_data() {
+        let mut config = VMConfig::default();
+        config.limit_config.wasm_features.bulk_memory = true;
+
+        let wasm = wat::parse_str(r#"(module (memory 1) (data "abc"))"#).unwrap();
+        assert_matches!(prepare_contract(&wasm, &config), Err(PrepareError::Deserialization));
+
+        let wasm =
+            wat::parse_str(r#"(module (table 1 funcref) (func $f) (elem func $f))"#).unwrap();
+        assert_matches!(prepare_contract(&wasm, &config), Err(PrepareError::Deserialization));
+
+        // An empty module with a data count section.
+        let wasm = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x0c, 0x01, 0x00];
+        assert_matches!(prepare_contract(&wasm, &config), Err(PrepareError::Deserialization));
+    }
 }
@@ -0,0 +1,855 @@
+//! Rewrites the multi-value constructs of a contract into MVP ones, before the contract is
+//! deserialized by parity-wasm which only represents MVP function and block types.
+//!
+//! The values MVP code can't keep on the operand stack, i.e. the results of the functions and
+//! blocks returning several values and the parameters of the blocks, are passed through mutable
+//! globals appended to the module, one per value type and position. They are stored right
+//! before the control transfer and loaded right after it, so no other code runs in between.
+//! The parameters of an `if` are kept in locals instead, since its `else` branch needs them
+//! again once the `then` branch ran.
+
+use std::convert::TryFrom;
+
+use near_vm_errors::PrepareError;
+
+const CUSTOM_SECTION: u8 = 0;
+const TYPE_SECTION: u8 = 1;
+const IMPORT_SECTION: u8 = 2;
+const FUNCTION_SECTION: u8 = 3;
+const GLOBAL_SECTION: u8 = 6;
+const CODE_SECTION: u8 = 10;
+const DATA_SECTION: u8 = 11;
+const DATA_COUNT_SECTION: u8 = 12;
+
+const FUNCTION_TYPE_FORM: u8 = 0x60;
+const EMPTY_BLOCK_TYPE: u8 = 0x40;
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+const F32: u8 = 0x7d;
+const F64: u8 = 0x7c;
+
+const BLOCK: u8 = 0x02;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const GLOBAL_GET: u8 = 0x23;
+const GLOBAL_SET: u8 = 0x24;
+
+/// Rewrites the multi-value function and block types of the given valid module, and the code
+/// using them, into MVP ones. Returns `None` when the module has none of them.
+pub(super) fn lower_multi_value(code: &[u8]) -> Result<Option<Vec<u8>>, PrepareError> {
+    let mut reader = Reader::new(code);
+    let header = reader.bytes(8)?;
+    let mut sections = vec![];
+    while !reader.eof() {
+        let id = reader.byte()?;
+        let len = reader.u32()?;
+        sections.push((id, reader.bytes(len as usize)?));
+    }
+
+    let mut module = ModuleTypes { types: vec![], function_types: vec![] };
+    let mut imported_functions = 0;
+    let mut globals = 0;
+    for (id, content) in &sections {
+        let mut reader = Reader::new(content);
+        match *id {
+            TYPE_SECTION => {
+                for _ in 0..reader.u32()? {
+                    if reader.byte()? != FUNCTION_TYPE_FORM {
+                        return Err(PrepareError::Deserialization);
+                    }
+                    let params = reader.value_types()?;
+                    let results = reader.value_types()?;
+                    module.types.push(FunctionType { params, results });
+                }
+            }
+            IMPORT_SECTION => {
+                for _ in 0..reader.u32()? {
+                    reader.name()?;
+                    reader.name()?;
+                    match reader.byte()? {
+                        0 => {
+                            let type_index = reader.u32()?;
+                            // Host functions return at most one value.
+                            if module.function_type(type_index)?.returns_multi_value() {
+                                return Err(PrepareError::Instantiate);
+                            }
+                            module.function_types.push(type_index);
+                            imported_functions += 1;
+                        }
+                        1 => {
+                            reader.byte()?;
+                            reader.limits()?;
+                        }
+                        2 => reader.limits()?,
+                        3 => {
+                            reader.bytes(2)?;
+                            globals += 1;
+                        }
+                        _ => return Err(PrepareError::Deserialization),
+                    }
+                }
+            }
+            FUNCTION_SECTION => {
+                for _ in 0..reader.u32()? {
+                    module.function_types.push(reader.u32()?);
+                }
+            }
+            GLOBAL_SECTION => globals += reader.u32()?,
+            _ => {}
+        }
+    }
+
+    let mut spill_globals = SpillGlobals { first_index: globals, slots: vec![] };
+    let mut changed = module.types.iter().any(FunctionType::returns_multi_value);
+    let mut code_section = vec![];
+    if let Some((_, content)) = sections.iter().find(|(id, _)| *id == CODE_SECTION) {
+        let mut reader = Reader::new(content);
+        let count = reader.u32()?;
+        write_u32(&mut code_section, count);
+        for index in 0..count {
+            let len = reader.u32()?;
+            let mut body = Reader::new(reader.bytes(len as usize)?);
+            let declarations_count = body.u32()?;
+            let declarations_start = body.position;
+            let mut locals = 0u32;
+            for _ in 0..declarations_count {
+                locals = locals.checked_add(body.u32()?).ok_or(PrepareError::Deserialization)?;
+                body.byte()?;
+            }
+            let declarations = &body.data[declarations_start..body.position];
+            let operators = read_operators(&mut body)?;
+            changed |= operators.iter().any(|(operator, _)| {
+                matches!(operator.opened_frame(), Some((_, BlockType::TypeIndex(_))))
+            });
+
+            let function_type = module.type_of_function(imported_functions + index)?;
+            let spills = spilling_frames(&module, function_type, &operators)?;
+            let first_new_local = (function_type.params.len() as u32)
+                .checked_add(locals)
+                .ok_or(PrepareError::Deserialization)?;
+            let lowered = BodyLowering {
+                module: &module,
+                globals: &mut spill_globals,
+                spills,
+                first_new_local,
+                new_locals: vec![],
+                condition_local: None,
+                code: vec![],
+            }
+            .lower(function_type, declarations, declarations_count, &operators)?;
+            write_u32(&mut code_section, lowered.len() as u32);
+            code_section.extend(lowered);
+        }
+    }
+    if !changed {
+        return Ok(None);
+    }
+
+    let mut lowered = header.to_vec();
+    let mut has_global_section = false;
+    for (id, content) in &sections {
+        if !has_global_section
+            && !spill_globals.slots.is_empty()
+            && *id != CUSTOM_SECTION
+            && section_order(*id) > section_order(GLOBAL_SECTION)
+        {
+            write_section(&mut lowered, GLOBAL_SECTION, &spill_globals.encode_section(None)?);
+            has_global_section = true;
+        }
+        match *id {
+            TYPE_SECTION => write_section(&mut lowered, *id, &encode_types(&module.types)),
+            GLOBAL_SECTION => {
+                write_section(&mut lowered, *id, &spill_globals.encode_section(Some(*content))?);
+                has_global_section = true;
+            }
+            CODE_SECTION => write_section(&mut lowered, *id, &code_section),
+            _ => write_section(&mut lowered, *id, content),
+        }
+    }
+    if !has_global_section && !spill_globals.slots.is_empty() {
+        write_section(&mut lowered, GLOBAL_SECTION, &spill_globals.encode_section(None)?);
+    }
+    Ok(Some(lowered))
+}
+
+struct FunctionType {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+impl FunctionType {
+    /// MVP functions return at most one value.
+    fn returns_multi_value(&self) -> bool {
+        self.results.len() > 1
+    }
+}
+
+struct ModuleTypes {
+    types: Vec<FunctionType>,
+    /// Type index of each function, the imported ones first.
+    function_types: Vec<u32>,
+}
+
+impl ModuleTypes {
+    fn function_type(&self, type_index: u32) -> Result<&FunctionType, PrepareError> {
+        self.types.get(type_index as usize).ok_or(PrepareError::Deserialization)
+    }
+
+    fn type_of_function(&self, function_index: u32) -> Result<&FunctionType, PrepareError> {
+        let type_index = *self
+            .function_types
+            .get(function_index as usize)
+            .ok_or(PrepareError::Deserialization)?;
+        self.function_type(type_index)
+    }
+
+    /// The parameters and results of a block.
+    fn block_signature(&self, block_type: BlockType) -> Result<(Vec<u8>, Vec<u8>), PrepareError> {
+        match block_type {
+            BlockType::Empty => Ok((vec![], vec![])),
+            BlockType::Value(value_type) => Ok((vec![], vec![value_type])),
+            BlockType::TypeIndex(type_index) => {
+                let function_type = self.function_type(type_index)?;
+                Ok((function_type.params.clone(), function_type.results.clone()))
+            }
+        }
+    }
+}
+
+/// The globals appended to the module, one per value type and position of the values passed
+/// through them.
+struct SpillGlobals {
+    first_index: u32,
+    slots: Vec<(u8, usize)>,
+}
+
+impl SpillGlobals {
+    fn index(&mut self, value_type: u8, position: usize) -> u32 {
+        let slot = match self.slots.iter().position(|slot| *slot == (value_type, position)) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push((value_type, position));
+                self.slots.len() - 1
+            }
+        };
+        self.first_index + slot as u32
+    }
+
+    /// Appends the globals to the entries of the existing global section, if any.
+    fn encode_section(&self, existing: Option<&[u8]>) -> Result<Vec<u8>, PrepareError> {
+        let (count, entries) = match existing {
+            Some(content) => {
+                let mut reader = Reader::new(content);
+                let count = reader.u32()?;
+                (count, &content[reader.position..])
+            }
+            None => (0, &[][..]),
+        };
+        let mut section = vec![];
+        write_u32(&mut section, count + self.slots.len() as u32);
+        section.extend_from_slice(entries);
+        for (value_type, _) in &self.slots {
+            // A mutable global initialized to zero.
+            section.extend_from_slice(&[*value_type, 1]);
+            match *value_type {
+                I32 => section.extend_from_slice(&[0x41, 0]),
+                I64 => section.extend_from_slice(&[0x42, 0]),
+                F32 => section.extend_from_slice(&[0x43, 0, 0, 0, 0]),
+                _ => section.extend_from_slice(&[0x44, 0, 0, 0, 0, 0, 0, 0, 0]),
+            }
+            section.push(END);
+        }
+        Ok(section)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BlockType {
+    Empty,
+    Value(u8),
+    TypeIndex(u32),
+}
+
+/// The operators of a function body the lowering deals with.
+enum Operator {
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    CallIndirect(u32),
+    Other,
+}
+
+impl Operator {
+    fn opened_frame(&self) -> Option<(FrameKind, BlockType)> {
+        match *self {
+            Operator::Block(block_type) => Some((FrameKind::Block, block_type)),
+            Operator::Loop(block_type) => Some((FrameKind::Loop, block_type)),
+            Operator::If(block_type) => Some((FrameKind::If, block_type)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FrameKind {
+    Function,
+    Block,
+    Loop,
+    If,
+}
+
+/// A function body, or a block, loop or if within it.
+struct Frame {
+    kind: FrameKind,
+    /// Number of the frame in the order the frames are opened, the function being 0.
+    id: usize,
+    params: Vec<u8>,
+    results: Vec<u8>,
+    /// Locals keeping the parameters of an `if` for its `else` branch.
+    if_params: Vec<u32>,
+    has_else: bool,
+}
+
+impl Frame {
+    fn new(kind: FrameKind, id: usize, params: Vec<u8>, results: Vec<u8>) -> Self {
+        Self { kind, id, params, results, if_params: vec![], has_else: false }
+    }
+
+    /// Types of the values a branch to this frame carries.
+    fn label_types(&self) -> &[u8] {
+        if self.kind == FrameKind::Loop {
+            &self.params
+        } else {
+            &self.results
+        }
+    }
+}
+
+fn frame_at(frames: &[Frame], depth: u32) -> Result<&Frame, PrepareError> {
+    frames
+        .len()
+        .checked_sub(depth as usize + 1)
+        .and_then(|index| frames.get(index))
+        .ok_or(PrepareError::Deserialization)
+}
+
+/// Whether the branches to each frame of a function body, indexed by `Frame::id`, pass their
+/// values through the spill globals.
+///
+/// MVP loops can't take values from their branches and MVP blocks and functions at most one, so
+/// those frames spill the values of their branches. The targets of a `br_table` must agree, so
+/// when some of them spill all of them do.
+fn spilling_frames(
+    module: &ModuleTypes,
+    function_type: &FunctionType,
+    operators: &[(Operator, &[u8])],
+) -> Result<Vec<bool>, PrepareError> {
+    let mut frames =
+        vec![Frame::new(FrameKind::Function, 0, vec![], function_type.results.clone())];
+    let mut spills = vec![function_type.returns_multi_value()];
+    let mut br_table_targets: Vec<Vec<usize>> = vec![];
+    for (operator, _) in operators {
+        if let Some((kind, block_type)) = operator.opened_frame() {
+            let (params, results) = module.block_signature(block_type)?;
+            let frame = Frame::new(kind, spills.len(), params, results);
+            spills.push(if kind == FrameKind::Loop {
+                !frame.params.is_empty()
+            } else {
+                frame.results.len() > 1
+            });
+            frames.push(frame);
+            continue;
+        }
+        match operator {
+            Operator::End => {
+                frames.pop();
+            }
+            Operator::BrTable(targets, default) => {
+                if !frame_at(&frames, *default)?.label_types().is_empty() {
+                    let ids = targets
+                        .iter()
+                        .chain(std::iter::once(default))
+                        .map(|depth| Ok(frame_at(&frames, *depth)?.id))
+                        .collect::<Result<Vec<_>, PrepareError>>()?;
+                    br_table_targets.push(ids);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for ids in &br_table_targets {
+            if ids.iter().any(|id| spills[*id]) && !ids.iter().all(|id| spills[*id]) {
+                for id in ids {
+                    spills[*id] = true;
+                }
+                changed = true;
+            }
+        }
+    }
+    Ok(spills)
+}
+
+/// Rewrites a function body.
+struct BodyLowering<'a> {
+    module: &'a ModuleTypes,
+    globals: &'a mut SpillGlobals,
+    /// See `spilling_frames`.
+    spills: Vec<bool>,
+    /// Index of the first local added to the function.
+    first_new_local: u32,
+    new_locals: Vec<u8>,
+    condition_local: Option<u32>,
+    code: Vec<u8>,
+}
+
+impl BodyLowering<'_> {
+    fn lower(
+        mut self,
+        function_type: &FunctionType,
+        declarations: &[u8],
+        declarations_count: u32,
+        operators: &[(Operator, &[u8])],
+    ) -> Result<Vec<u8>, PrepareError> {
+        let module = self.module;
+        let mut frames =
+            vec![Frame::new(FrameKind::Function, 0, vec![], function_type.results.clone())];
+        // A function returning a single value whose branches spill it gets a block around its
+        // body, which the branches leave before the value is loaded back.
+        let wraps_body = !function_type.returns_multi_value() && self.spills[0];
+        if wraps_body {
+            self.code.extend_from_slice(&[BLOCK, EMPTY_BLOCK_TYPE]);
+        }
+        let mut next_id = 1;
+        for (operator, encoding) in operators {
+            if let Some((kind, block_type)) = operator.opened_frame() {
+                let (params, results) = module.block_signature(block_type)?;
+                let mut frame = Frame::new(kind, next_id, params, results);
+                next_id += 1;
+                let block_type = if self.spills_results(&frame) {
+                    EMPTY_BLOCK_TYPE
+                } else {
+                    frame.results.first().copied().unwrap_or(EMPTY_BLOCK_TYPE)
+                };
+                if kind == FrameKind::If && !frame.params.is_empty() {
+                    let condition = self.condition_local();
+                    self.instruction(LOCAL_SET, condition);
+                    frame.if_params =
+                        frame.params.iter().map(|value_type| self.new_local(*value_type)).collect();
+                    for local in frame.if_params.iter().rev() {
+                        self.instruction(LOCAL_SET, *local);
+                    }
+                    self.instruction(LOCAL_GET, condition);
+                    self.code.extend_from_slice(&[IF, block_type]);
+                    for local in &frame.if_params {
+                        self.instruction(LOCAL_GET, *local);
+                    }
+                } else {
+                    self.spill(&frame.params);
+                    self.code.extend_from_slice(&[encoding[0], block_type]);
+                    self.reload(&frame.params);
+                }
+                frames.push(frame);
+                continue;
+            }
+            match operator {
+                Operator::Else => {
+                    let frame = frames.last_mut().ok_or(PrepareError::Deserialization)?;
+                    if self.spills_results(frame) {
+                        self.spill(&frame.results);
+                    }
+                    self.code.extend_from_slice(encoding);
+                    for local in &frame.if_params {
+                        self.instruction(LOCAL_GET, *local);
+                    }
+                    frame.has_else = true;
+                }
+                Operator::End => {
+                    let frame = frames.pop().ok_or(PrepareError::Deserialization)?;
+                    let spills = self.spills_results(&frame);
+                    if frame.kind == FrameKind::If && !frame.has_else && !frame.params.is_empty() {
+                        // The missing `else` branch passes the parameters through.
+                        if spills {
+                            self.spill(&frame.results);
+                        }
+                        self.code.push(ELSE);
+                        for local in &frame.if_params {
+                            self.instruction(LOCAL_GET, *local);
+                        }
+                    }
+                    if spills {
+                        self.spill(&frame.results);
+                    }
+                    if frame.kind == FrameKind::Function {
+                        if wraps_body {
+                            self.code.push(END);
+                            self.reload(&frame.results);
+                        }
+                        self.code.extend_from_slice(encoding);
+                    } else {
+                        self.code.extend_from_slice(encoding);
+                        if spills {
+                            self.reload(&frame.results);
+                        }
+                    }
+                }
+                Operator::Br(depth) => {
+                    let frame = frame_at(&frames, *depth)?;
+                    if self.spills[frame.id] {
+                        self.spill(frame.label_types());
+                    }
+                    self.code.extend_from_slice(encoding);
+                }
+                Operator::BrIf(depth) => {
+                    let frame = frame_at(&frames, *depth)?;
+                    if self.spills[frame.id] && !frame.label_types().is_empty() {
+                        // The values stay on the stack when the branch isn't taken.
+                        let condition = self.condition_local();
+                        self.instruction(LOCAL_SET, condition);
+                        self.spill(frame.label_types());
+                        self.reload(frame.label_types());
+                        self.instruction(LOCAL_GET, condition);
+                    }
+                    self.code.extend_from_slice(encoding);
+                }
+                Operator::BrTable(_, default) => {
+                    let frame = frame_at(&frames, *default)?;
+                    if self.spills[frame.id] && !frame.label_types().is_empty() {
+                        let index = self.condition_local();
+                        self.instruction(LOCAL_SET, index);
+                        self.spill(frame.label_types());
+                        self.instruction(LOCAL_GET, index);
+                    }
+                    self.code.extend_from_slice(encoding);
+                }
+                Operator::Return => {
+                    if function_type.returns_multi_value() {
+                        self.spill(&function_type.results);
+                    }
+                    self.code.extend_from_slice(encoding);
+                }
+                Operator::Call(function_index) => {
+                    self.code.extend_from_slice(encoding);
+                    let callee = module.type_of_function(*function_index)?;
+                    if callee.returns_multi_value() {
+                        self.reload(&callee.results);
+                    }
+                }
+                Operator::CallIndirect(type_index) => {
+                    self.code.extend_from_slice(encoding);
+                    let callee = module.function_type(*type_index)?;
+                    if callee.returns_multi_value() {
+                        self.reload(&callee.results);
+                    }
+                }
+                _ => self.code.extend_from_slice(encoding),
+            }
+        }
+
+        let mut body = vec![];
+        write_u32(&mut body, declarations_count + self.new_locals.len() as u32);
+        body.extend_from_slice(declarations);
+        for value_type in &self.new_locals {
+            write_u32(&mut body, 1);
+            body.push(*value_type);
+        }
+        body.extend_from_slice(&self.code);
+        Ok(body)
+    }
+
+    /// Whether the results of the frame are passed through the spill globals at its end, in
+    /// which case the frame itself returns nothing.
+    fn spills_results(&self, frame: &Frame) -> bool {
+        match frame.kind {
+            FrameKind::Loop => frame.results.len() > 1,
+            _ => self.spills[frame.id],
+        }
+    }
+
+    fn new_local(&mut self, value_type: u8) -> u32 {
+        self.new_locals.push(value_type);
+        self.first_new_local + self.new_locals.len() as u32 - 1
+    }
+
+    /// An `i32` local keeping a condition or a `br_table` index while the values below it are
+    /// moved.
+    fn condition_local(&mut self) -> u32 {
+        match self.condition_local {
+            Some(local) => local,
+            None => {
+                let local = self.new_local(I32);
+                self.condition_local = Some(local);
+                local
+            }
+        }
+    }
+
+    fn instruction(&mut self, opcode: u8, immediate: u32) {
+        self.code.push(opcode);
+        write_u32(&mut self.code, immediate);
+    }
+
+    /// Moves the values on top of the stack to the spill globals.
+    fn spill(&mut self, value_types: &[u8]) {
+        for (position, value_type) in value_types.iter().enumerate().rev() {
+            let global = self.globals.index(*value_type, position);
+            self.instruction(GLOBAL_SET, global);
+        }
+    }
+
+    /// Pushes the values back from the spill globals.
+    fn reload(&mut self, value_types: &[u8]) {
+        for (position, value_type) in value_types.iter().enumerate() {
+            let global = self.globals.index(*value_type, position);
+            self.instruction(GLOBAL_GET, global);
+        }
+    }
+}
+
+/// Reads the operators of a function body, each with its encoding.
+fn read_operators<'a>(reader: &mut Reader<'a>) -> Result<Vec<(Operator, &'a [u8])>, PrepareError> {
+    let data = reader.data;
+    let mut operators = vec![];
+    while !reader.eof() {
+        let start = reader.position;
+        let operator = match reader.byte()? {
+            BLOCK => Operator::Block(reader.block_type()?),
+            LOOP => Operator::Loop(reader.block_type()?),
+            IF => Operator::If(reader.block_type()?),
+            ELSE => Operator::Else,
+            END => Operator::End,
+            0x0c => Operator::Br(reader.u32()?),
+            0x0d => Operator::BrIf(reader.u32()?),
+            0x0e => {
+                let len = reader.u32()?;
+                let targets: Vec<u32> = (0..len).map(|_| reader.u32()).collect::<Result<_, _>>()?;
+                Operator::BrTable(targets, reader.u32()?)
+            }
+            0x0f => Operator::Return,
+            0x10 => Operator::Call(reader.u32()?),
+            0x11 => {
+                let type_index = reader.u32()?;
+                reader.byte()?;
+                Operator::CallIndirect(type_index)
+            }
+            // Local and global variables.
+            0x20..=0x24 => {
+                reader.u32()?;
+                Operator::Other
+            }
+            // Memory loads and stores.
+            0x28..=0x3e => {
+                reader.u32()?;
+                reader.u32()?;
+                Operator::Other
+            }
+            // `memory.size` and `memory.grow`.
+            0x3f | 0x40 => {
+                reader.byte()?;
+                Operator::Other
+            }
+            // `i32.const` and `i64.const`.
+            0x41 | 0x42 => {
+                reader.skip_signed()?;
+                Operator::Other
+            }
+            0x43 => {
+                reader.bytes(4)?;
+                Operator::Other
+            }
+            0x44 => {
+                reader.bytes(8)?;
+                Operator::Other
+            }
+            // Saturating truncations and bulk memory operations.
+            0xfc => {
+                match reader.u32()? {
+                    0..=7 => {}
+                    8 => {
+                        reader.u32()?;
+                        reader.byte()?;
+                    }
+                    9 | 13 => {
+                        reader.u32()?;
+                    }
+                    10 => {
+                        reader.bytes(2)?;
+                    }
+                    11 => {
+                        reader.byte()?;
+                    }
+                    12 | 14 => {
+                        reader.u32()?;
+                        reader.u32()?;
+                    }
+                    _ => return Err(PrepareError::Deserialization),
+                }
+                Operator::Other
+            }
+            // The other operators have no immediates.
+            _ => Operator::Other,
+        };
+        operators.push((operator, &data[start..reader.position]));
+    }
+    Ok(operators)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn eof(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PrepareError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(PrepareError::Deserialization)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PrepareError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// An unsigned LEB128 integer.
+    fn u32(&mut self) -> Result<u32, PrepareError> {
+        let mut result = 0u64;
+        for i in 0..5 {
+            let byte = self.byte()?;
+            result |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return u32::try_from(result).map_err(|_| PrepareError::Deserialization);
+            }
+        }
+        Err(PrepareError::Deserialization)
+    }
+
+    /// Skips a signed LEB128 integer of at most 64 bits.
+    fn skip_signed(&mut self) -> Result<(), PrepareError> {
+        for _ in 0..10 {
+            if self.byte()? & 0x80 == 0 {
+                return Ok(());
+            }
+        }
+        Err(PrepareError::Deserialization)
+    }
+
+    fn name(&mut self) -> Result<(), PrepareError> {
+        let len = self.u32()?;
+        self.bytes(len as usize)?;
+        Ok(())
+    }
+
+    fn limits(&mut self) -> Result<(), PrepareError> {
+        let flags = self.byte()?;
+        self.u32()?;
+        if flags & 1 != 0 {
+            self.u32()?;
+        }
+        Ok(())
+    }
+
+    fn value_types(&mut self) -> Result<Vec<u8>, PrepareError> {
+        let len = self.u32()?;
+        (0..len).map(|_| self.byte()).collect()
+    }
+
+    fn block_type(&mut self) -> Result<BlockType, PrepareError> {
+        match self.data.get(self.position).copied() {
+            Some(EMPTY_BLOCK_TYPE) => {
+                self.position += 1;
+                Ok(BlockType::Empty)
+            }
+            Some(value_type) if (F64..=I32).contains(&value_type) => {
+                self.position += 1;
+                Ok(BlockType::Value(value_type))
+            }
+            // A type index, encoded as a non-negative signed 33 bits integer.
+            Some(_) => {
+                let mut result = 0u64;
+                for i in 0..5 {
+                    let byte = self.byte()?;
+                    result |= u64::from(byte & 0x7f) << (7 * i);
+                    if byte & 0x80 == 0 {
+                        if byte & 0x40 != 0 {
+                            return Err(PrepareError::Deserialization);
+                        }
+                        return u32::try_from(result)
+                            .map(BlockType::TypeIndex)
+                            .map_err(|_| PrepareError::Deserialization);
+                    }
+                }
+                Err(PrepareError::Deserialization)
+            }
+            None => Err(PrepareError::Deserialization),
+        }
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_section(module: &mut Vec<u8>, id: u8, content: &[u8]) {
+    module.push(id);
+    write_u32(module, content.len() as u32);
+    module.extend_from_slice(content);
+}
+
+/// Position of the section in the module, the data count section comes before the code one.
+fn section_order(id: u8) -> u8 {
+    match id {
+        DATA_COUNT_SECTION => CODE_SECTION,
+        CODE_SECTION | DATA_SECTION => id + 1,
+        _ => id,
+    }
+}
+
+fn encode_types(types: &[FunctionType]) -> Vec<u8> {
+    let mut section = vec![];
+    write_u32(&mut section, types.len() as u32);
+    for function_type in types {
+        section.push(FUNCTION_TYPE_FORM);
+        write_u32(&mut section, function_type.params.len() as u32);
+        section.extend_from_slice(&function_type.params);
+        // The results of the multi-value functions are passed through the spill globals.
+        let results: &[u8] =
+            if function_type.returns_multi_value() { &[] } else { &function_type.results };
+        write_u32(&mut section, results.len() as u32);
+        section.extend_from_slice(results);
+    }
+    section
+}
@@ -3,6 +3,7 @@ mod error_cases;
 mod invalid_contracts;
 mod rs_contract;
 mod ts_contract;
+mod wasm_extensions;
 
 use near_primitives::contract::ContractCode;
 
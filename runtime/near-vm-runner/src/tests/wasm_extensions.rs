@@ -0,0 +1,584 @@
+use near_primitives::contract::ContractCode;
+use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_vm_errors::{CompilationError, FunctionCallError, PrepareError, VMError, WasmTrap};
+use near_vm_logic::mocks::mock_external::MockedExternal;
+use near_vm_logic::types::ReturnData;
+use near_vm_logic::{VMConfig, VMOutcome, WasmFeatures};
+
+use crate::tests::{create_context, with_vm_variants, LATEST_PROTOCOL_VERSION};
+use crate::{run_vm, VMKind};
+
+fn sign_extension_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func (export "main")
+                (i64.store (i32.const 0) (i64.extend_i32_s (i32.extend8_s (i32.const 0x180))))
+                (i64.store (i32.const 8) (i64.extend_i32_s (i32.extend16_s (i32.const 0x7fff))))
+                (i64.store (i32.const 16) (i64.extend8_s (i64.const 0x17f)))
+                (i64.store (i32.const 24) (i64.extend16_s (i64.const 0x8000)))
+                (i64.store (i32.const 32) (i64.extend32_s (i64.const 0x1_8000_0000)))
+                (call $value_return (i64.const 40) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn bulk_memory_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (data (i32.const 100) "0123456789abcdefghij")
+              (func (export "main")
+                (memory.copy (i32.const 0) (i32.const 100) (i32.const 20))
+                ;; Overlapping ranges, copied backward and forward.
+                (memory.copy (i32.const 2) (i32.const 0) (i32.const 11))
+                (memory.copy (i32.const 5) (i32.const 6) (i32.const 9))
+                (memory.fill (i32.const 15) (i32.const 0x22a) (i32.const 3))
+                (call $value_return (i64.const 20) (i64.const 0)))
+              (func (export "fill_small")
+                (memory.fill (i32.const 0) (i32.const 1) (i32.const 8)))
+              (func (export "fill_large")
+                (memory.fill (i32.const 0) (i32.const 1) (i32.const 8000)))
+              (func (export "out_of_bounds")
+                (memory.copy (i32.const -1) (i32.const 0) (i32.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn memory_init_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (memory 1)
+              (data "0123456789")
+              (func (export "main")
+                (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 10)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn multi_value_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func $swap (param i64 i64) (result i64 i64)
+                (local.get 1)
+                (local.get 0))
+              (func $divmod (param i64 i64) (result i64 i64)
+                (i64.div_u (local.get 0) (local.get 1))
+                (i64.rem_u (local.get 0) (local.get 1))
+                return)
+              (func $pair (param i64 i32) (result i64 i64)
+                (local.get 0)
+                (local.get 1)
+                if (param i64) (result i64 i64)
+                  local.tee 0
+                  (i64.mul (local.get 0) (i64.const 2))
+                else
+                  local.tee 0
+                  (i64.add (local.get 0) (i64.const 1))
+                end)
+              (func $pick (param i32) (result i64 i64)
+                block $outer (result i64 i64)
+                  block $inner (result i64 i64)
+                    (i64.const 1)
+                    (i64.const 2)
+                    (br_table $inner $outer (local.get 0))
+                  end
+                  call $swap
+                end)
+              (func $store (param i64 i64 i32)
+                (i64.store (local.get 2) (local.get 0))
+                (i64.store offset=8 (local.get 2) (local.get 1)))
+              (func (export "main")
+                (local $n i64)
+                (i64.const 2)
+                (i64.const 3)
+                block (param i64 i64) (result i64 i64)
+                  call $swap
+                  br 0
+                end
+                (call $store (i32.const 0))
+                (call $store (call $divmod (i64.const 47) (i64.const 5)) (i32.const 16))
+                ;; Sums 5 + 4 + 3 + 2 + 1.
+                (i32.const 32)
+                (i64.const 0)
+                (i64.const 5)
+                loop $sum (param i64 i64) (result i64)
+                  local.set $n
+                  (i64.add (local.get $n))
+                  (i64.sub (local.get $n) (i64.const 1))
+                  (br_if $sum (i64.ne (local.get $n) (i64.const 1)))
+                  drop
+                end
+                i64.store
+                (call $store (call $pair (i64.const 10) (i32.const 1)) (i32.const 40))
+                (call $store (call $pair (i64.const 7) (i32.const 0)) (i32.const 56))
+                ;; The parameter is the result when the condition is false.
+                (i32.const 72)
+                (i64.const 42)
+                (i32.const 0)
+                if (param i64) (result i64)
+                  (i64.add (i64.const 1))
+                end
+                i64.store
+                (call $store (call $pick (i32.const 0)) (i32.const 80))
+                (call $store (call $pick (i32.const 1)) (i32.const 96))
+                (call $value_return (i64.const 112) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn multi_value_params_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func $swap (param i64 i64) (result i64 i64)
+                (local.get 1)
+                (local.get 0))
+              (func $store (param i64 i64 i32)
+                (i64.store (local.get 2) (local.get 0))
+                (i64.store offset=8 (local.get 2) (local.get 1)))
+              (func $add_sub (param i64 i64) (result i64 i64)
+                (local.get 0)
+                (local.get 1)
+                block (param i64 i64) (result i64 i64)
+                  (local.set 1)
+                  (local.set 0)
+                  (i64.add (local.get 0) (local.get 1))
+                  (i64.sub (local.get 0) (local.get 1))
+                end)
+              ;; Both branches of the `if` take the values as parameters.
+              (func $order (param i64 i64) (result i64 i64)
+                (local.get 0)
+                (local.get 1)
+                (i64.lt_u (local.get 0) (local.get 1))
+                if (param i64 i64) (result i64 i64)
+                else
+                  (local.set 0)
+                  (local.set 1)
+                  (local.get 0)
+                  (local.get 1)
+                end)
+              (func (export "main")
+                (call $store (call $add_sub (i64.const 7) (i64.const 3)) (i32.const 0))
+                (call $store (call $order (i64.const 9) (i64.const 4)) (i32.const 16))
+                (call $store (call $order (i64.const 2) (i64.const 5)) (i32.const 32))
+                ;; Without an `else` branch the parameters are the results when the condition is
+                ;; false.
+                (i64.const 1)
+                (i64.const 2)
+                (i32.const 1)
+                if (param i64 i64) (result i64 i64)
+                  call $swap
+                end
+                (call $store (i32.const 48))
+                (i64.const 1)
+                (i64.const 2)
+                (i32.const 0)
+                if (param i64 i64) (result i64 i64)
+                  call $swap
+                end
+                (call $store (i32.const 64))
+                (i32.const 80)
+                (i64.const 6)
+                (i64.const 7)
+                block (param i64 i64) (result i64)
+                  i64.mul
+                end
+                i64.store
+                (call $value_return (i64.const 88) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn multi_value_branches_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func $store (param i64 i64 i32)
+                (i64.store (local.get 2) (local.get 0))
+                (i64.store offset=8 (local.get 2) (local.get 1)))
+              ;; Returns (0, 2 * x) for x < 10 and (1, x) otherwise.
+              (func $classify (param i64) (result i64 i64)
+                block $done (result i64 i64)
+                  block $small (result i64 i64)
+                    (i64.const 0)
+                    (local.get 0)
+                    (br_if $small (i64.lt_u (local.get 0) (i64.const 10)))
+                    drop
+                    drop
+                    (i64.const 1)
+                    (local.get 0)
+                    ;; Leaves both blocks from the inner one.
+                    br $done
+                  end
+                  (local.set 0)
+                  (i64.mul (local.get 0) (i64.const 2))
+                end)
+              ;; Returns (n, 1 + 2 + ... + n).
+              (func $sum_to (param i64) (result i64 i64)
+                (local $i i64)
+                (local $sum i64)
+                block $out (result i64 i64)
+                  loop $next
+                    (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                    (local.set $sum (i64.add (local.get $sum) (local.get $i)))
+                    (local.get $i)
+                    (local.get $sum)
+                    (br_if $out (i64.eq (local.get $i) (local.get 0)))
+                    drop
+                    drop
+                    br $next
+                  end
+                  unreachable
+                end)
+              (func $early (param i32) (result i64 i64)
+                block
+                  (br_if 0 (local.get 0))
+                  (i64.const 1)
+                  (i64.const 2)
+                  return
+                end
+                (i64.const 3)
+                (i64.const 4))
+              (func (export "main")
+                (call $store (call $classify (i64.const 3)) (i32.const 0))
+                (call $store (call $classify (i64.const 12)) (i32.const 16))
+                (call $store (call $sum_to (i64.const 4)) (i32.const 32))
+                (call $store (call $early (i32.const 0)) (i32.const 48))
+                (call $store (call $early (i32.const 1)) (i32.const 64))
+                (call $value_return (i64.const 80) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn multi_value_call_indirect_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (type $binary (func (param i64 i64) (result i64 i64)))
+              (memory 1)
+              (table 2 funcref)
+              (elem (i32.const 0) $swap $divmod)
+              (func $swap (type $binary)
+                (local.get 1)
+                (local.get 0))
+              (func $divmod (type $binary)
+                (i64.div_u (local.get 0) (local.get 1))
+                (i64.rem_u (local.get 0) (local.get 1)))
+              (func $store (param i64 i64 i32)
+                (i64.store (local.get 2) (local.get 0))
+                (i64.store offset=8 (local.get 2) (local.get 1)))
+              (func (export "main")
+                (call $store
+                  (call_indirect (type $binary) (i64.const 1) (i64.const 2) (i32.const 0))
+                  (i32.const 0))
+                (call $store
+                  (call_indirect (type $binary) (i64.const 47) (i64.const 5) (i32.const 1))
+                  (i32.const 16))
+                (call $value_return (i64.const 32) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn multi_value_recursion_contract() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+            (module
+              (import "env" "value_return" (func $value_return (param i64 i64)))
+              (memory 1)
+              (func $store (param i64 i64 i32)
+                (i64.store (local.get 2) (local.get 0))
+                (i64.store offset=8 (local.get 2) (local.get 1)))
+              ;; Returns the Fibonacci numbers n and n + 1.
+              (func $fib (param i64) (result i64 i64)
+                (local $a i64)
+                (local $b i64)
+                (if (result i64 i64) (i64.eqz (local.get 0))
+                  (then
+                    (i64.const 0)
+                    (i64.const 1))
+                  (else
+                    (call $fib (i64.sub (local.get 0) (i64.const 1)))
+                    (local.set $b)
+                    (local.set $a)
+                    (local.get $b)
+                    (i64.add (local.get $a) (local.get $b)))))
+              ;; Returns the number of nodes and leaves of a full binary tree of the given depth.
+              ;; The results of the first recursive call stay on the stack during the second one.
+              (func $tree (param i64) (result i64 i64)
+                (local $left_nodes i64)
+                (local $left_leaves i64)
+                (local $right_nodes i64)
+                (local $right_leaves i64)
+                (if (result i64 i64) (i64.eqz (local.get 0))
+                  (then
+                    (i64.const 1)
+                    (i64.const 1))
+                  (else
+                    (call $tree (i64.sub (local.get 0) (i64.const 1)))
+                    (call $tree (i64.sub (local.get 0) (i64.const 1)))
+                    (local.set $right_leaves)
+                    (local.set $right_nodes)
+                    (local.set $left_leaves)
+                    (local.set $left_nodes)
+                    (i64.add
+                      (i64.add (local.get $left_nodes) (local.get $right_nodes))
+                      (i64.const 1))
+                    (i64.add (local.get $left_leaves) (local.get $right_leaves)))))
+              (func (export "main")
+                (call $store (call $fib (i64.const 10)) (i32.const 0))
+                (call $store (call $tree (i64.const 3)) (i32.const 16))
+                (call $value_return (i64.const 32) (i64.const 0)))
+            )"#,
+    )
+    .unwrap()
+}
+
+fn config_with_features(wasm_features: WasmFeatures) -> VMConfig {
+    let mut config = VMConfig::default();
+    config.limit_config.wasm_features = wasm_features;
+    config
+}
+
+fn all_features() -> VMConfig {
+    config_with_features(WasmFeatures {
+        sign_extension: true,
+        bulk_memory: true,
+        multi_value: true,
+    })
+}
+
+fn call(
+    code: &[u8],
+    method_name: &str,
+    config: &VMConfig,
+    vm_kind: VMKind,
+) -> (Option<VMOutcome>, Option<VMError>) {
+    let mut fake_external = MockedExternal::new();
+    let context = create_context(vec![]);
+    let fees = RuntimeFeesConfig::default();
+    let code = ContractCode::new(code.to_vec(), None);
+    run_vm(
+        &code,
+        method_name,
+        &mut fake_external,
+        context,
+        config,
+        &fees,
+        &[],
+        vm_kind,
+        LATEST_PROTOCOL_VERSION,
+        None,
+    )
+}
+
+fn returned_value((outcome, err): (Option<VMOutcome>, Option<VMError>)) -> Vec<u8> {
+    assert_eq!(err, None);
+    match outcome.expect("the call should succeed").return_data {
+        ReturnData::Value(value) => value,
+        _ => panic!("Value was not returned"),
+    }
+}
+
+fn deserialization_error() -> Option<VMError> {
+    Some(VMError::FunctionCallError(FunctionCallError::CompilationError(
+        CompilationError::PrepareError(PrepareError::Deserialization),
+    )))
+}
+
+#[test]
+fn test_sign_extension() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value =
+            returned_value(call(&sign_extension_contract(), "main", &all_features(), vm_kind));
+        let expected: Vec<u8> = [-128i64, 32767, 127, -32768, -2147483648]
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_multi_value() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value = returned_value(call(&multi_value_contract(), "main", &all_features(), vm_kind));
+        let expected: Vec<u8> = [3u64, 2, 9, 2, 15, 10, 20, 7, 8, 42, 2, 1, 1, 2]
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_multi_value_block_and_if_params() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value =
+            returned_value(call(&multi_value_params_contract(), "main", &all_features(), vm_kind));
+        let expected: Vec<u8> = [10u64, 4, 4, 9, 2, 5, 2, 1, 1, 2, 42]
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_multi_value_branches() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value = returned_value(call(
+            &multi_value_branches_contract(),
+            "main",
+            &all_features(),
+            vm_kind,
+        ));
+        let expected: Vec<u8> = [0u64, 6, 1, 12, 4, 10, 1, 2, 3, 4]
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_multi_value_call_indirect() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value = returned_value(call(
+            &multi_value_call_indirect_contract(),
+            "main",
+            &all_features(),
+            vm_kind,
+        ));
+        let expected: Vec<u8> =
+            [2u64, 1, 9, 2].iter().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_multi_value_recursion() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value = returned_value(call(
+            &multi_value_recursion_contract(),
+            "main",
+            &all_features(),
+            vm_kind,
+        ));
+        let expected: Vec<u8> =
+            [55u64, 89, 15, 8].iter().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_bulk_memory() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let value = returned_value(call(&bulk_memory_contract(), "main", &all_features(), vm_kind));
+        let mut expected = b"0123456789abcdefghij".to_vec();
+        expected.copy_within(0..11, 2);
+        expected.copy_within(6..15, 5);
+        for byte in &mut expected[15..18] {
+            *byte = 0x2a;
+        }
+        assert_eq!(value, expected);
+    });
+}
+
+#[test]
+fn test_bulk_memory_gas_depends_on_length() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let burnt_gas = |method_name| {
+            let (outcome, err) =
+                call(&bulk_memory_contract(), method_name, &all_features(), vm_kind);
+            assert_eq!(err, None);
+            outcome.unwrap().burnt_gas
+        };
+        assert!(burnt_gas("fill_large") > 10 * burnt_gas("fill_small"));
+    });
+}
+
+#[test]
+fn test_bulk_memory_out_of_bounds() {
+    with_vm_variants(|vm_kind: VMKind| {
+        match vm_kind {
+            VMKind::Wasmer0 | VMKind::Wasmer1 => {}
+            // All contracts leading to hardware traps can not run concurrently on Wasmtime and Wasmer,
+            // Check if can restore, once get rid of Wasmer 0.x.
+            VMKind::Wasmtime => return,
+        }
+        let (_, err) = call(&bulk_memory_contract(), "out_of_bounds", &all_features(), vm_kind);
+        assert_eq!(
+            err,
+            Some(VMError::FunctionCallError(FunctionCallError::WasmTrap(WasmTrap::Unreachable)))
+        );
+    });
+}
+
+#[test]
+fn test_wasm_extensions_disabled() {
+    with_vm_variants(|vm_kind: VMKind| {
+        let config = VMConfig::default();
+        assert_eq!(
+            call(&sign_extension_contract(), "main", &config, vm_kind).1,
+            deserialization_error()
+        );
+        assert_eq!(
+            call(&bulk_memory_contract(), "main", &config, vm_kind).1,
+            deserialization_error()
+        );
+
+        let config = config_with_features(WasmFeatures {
+            sign_extension: true,
+            bulk_memory: false,
+            multi_value: true,
+        });
+        assert_eq!(
+            call(&bulk_memory_contract(), "main", &config, vm_kind).1,
+            deserialization_error()
+        );
+        let config = config_with_features(WasmFeatures {
+            sign_extension: false,
+            bulk_memory: true,
+            multi_value: false,
+        });
+        assert_eq!(
+            call(&sign_extension_contract(), "main", &config, vm_kind).1,
+            deserialization_error()
+        );
+        assert_eq!(
+            call(&multi_value_contract(), "main", &config, vm_kind).1,
+            deserialization_error()
+        );
+    });
+}
+
+#[test]
+fn test_unsupported_bulk_memory_instructions() {
+    with_vm_variants(|vm_kind: VMKind| {
+        assert_eq!(
+            call(&memory_init_contract(), "main", &all_features(), vm_kind).1,
+            deserialization_error()
+        );
+    });
+}
@@ -65,6 +65,8 @@ fn runtime_fees_config(cost_table: &CostTable) -> anyhow::Result<RuntimeFeesConf
             },
             delete_key_cost: fee(Cost::ActionDeleteKey)?,
             delete_account_cost: fee(Cost::ActionDeleteAccount)?,
+            // Not estimated yet.
+            delegate_cost: RuntimeFeesConfig::default().action_creation_config.delegate_cost,
         },
         ..RuntimeFeesConfig::default()
     };
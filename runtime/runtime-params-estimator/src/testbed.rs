@@ -2,6 +2,7 @@ use genesis_populate::state_dump::StateDump;
 use near_primitives::receipt::Receipt;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::test_utils::MockEpochInfoProvider;
 use near_primitives::transaction::{ExecutionStatus, SignedTransaction};
 use near_primitives::types::{Gas, MerkleHash};
@@ -75,6 +76,8 @@ impl RuntimeTestbed {
             config: Arc::new(runtime_config),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
             is_new_chunk: true,
+            shard_layout: ShardLayout::v0(1),
+            congested_shards: vec![],
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
         };
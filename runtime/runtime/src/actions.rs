@@ -1,30 +1,42 @@
+use std::mem::size_of;
+
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, AccessKeyPermission, Account};
 use near_primitives::checked_feature;
-use near_primitives::contract::ContractCode;
+use near_primitives::contract::{ContractCode, SharedContractCode, SharedContractCodeOwner};
 use near_primitives::errors::{
-    ActionError, ActionErrorKind, ContractCallError, ExternalError, RuntimeError,
+    ActionError, ActionErrorKind, ContractCallError, ExternalError, InvalidAccessKeyError,
+    RuntimeError,
+};
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::receipt::{
+    ActionReceipt, DelegatedActionReceipt, Receipt, ReceiptEnum, SharedContractCodeReceipt,
 };
-use near_primitives::hash::CryptoHash;
-use near_primitives::receipt::{ActionReceipt, Receipt};
 use near_primitives::runtime::config::AccountCreationConfig;
 use near_primitives::runtime::fees::RuntimeFeesConfig;
+use near_primitives::shard_layout::account_id_to_shard_id;
 use near_primitives::transaction::{
-    Action, AddKeyAction, DeleteAccountAction, DeleteKeyAction, DeployContractAction,
-    FunctionCallAction, StakeAction, TransferAction,
+    Action, AddKeyAction, DelegateAction, DeleteAccountAction, DeleteKeyAction,
+    DeployContractAction, DeployContractAndMigrateAction, DeployContractByHashAction,
+    DeploySharedContractAction, FunctionCallAction, RemoveSharedContractAction,
+    SignedDelegateAction, StakeAction, TransferAction, UpdateAccessKeyAction,
 };
+use near_primitives::trie_key::{col, TrieKey};
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::{AccountId, EpochInfoProvider};
+use near_primitives::types::{AccountId, Balance, EpochInfoProvider, Gas, StorageUsage};
 use near_primitives::utils::create_random_seed;
 use near_primitives::version::{
     is_implicit_account_creation_enabled, ProtocolFeature, ProtocolVersion,
     DELETE_KEY_STORAGE_USAGE_PROTOCOL_VERSION,
 };
 use near_store::{
-    get_access_key, get_code, remove_access_key, remove_account, set_access_key, set_code,
-    StorageError, TrieUpdate,
+    get_access_key, get_code, get_shared_code, get_shared_code_registrations,
+    has_shared_code_registrations, remove_access_key, remove_account, remove_account_storage_batch,
+    remove_pending_account_deletion, remove_shared_code, set_access_key, set_code,
+    set_pending_account_deletion, set_shared_code, set_shared_code_registrations, StorageError,
+    TrieUpdate,
 };
 use near_vm_errors::{
     CacheError, CompilationError, FunctionCallError, InconsistentStateError, VMError,
@@ -32,8 +44,12 @@ use near_vm_errors::{
 use near_vm_logic::types::PromiseResult;
 use near_vm_logic::{VMContext, VMOutcome};
 
-use crate::config::{safe_add_gas, RuntimeConfig};
+use crate::config::{
+    safe_add_balance, safe_add_gas, safe_gas_to_balance, shared_code_receipt_fee, total_deposit,
+    total_prepaid_exec_fees, total_prepaid_gas, total_send_fees, RuntimeConfig,
+};
 use crate::ext::RuntimeExt;
+use crate::verifier::validate_access_key_permission;
 use crate::{ActionResult, ApplyState};
 use near_primitives::config::ViewConfig;
 use near_vm_runner::precompile_contract;
@@ -309,15 +325,11 @@ pub(crate) fn try_refund_allowance(
 ) -> Result<(), StorageError> {
     if let Some(mut access_key) = get_access_key(state_update, account_id, public_key)? {
         let mut updated = false;
-        if let AccessKeyPermission::FunctionCall(function_call_permission) =
-            &mut access_key.permission
-        {
-            if let Some(allowance) = function_call_permission.allowance.as_mut() {
-                let new_allowance = allowance.saturating_add(transfer.deposit);
-                if new_allowance > *allowance {
-                    *allowance = new_allowance;
-                    updated = true;
-                }
+        if let Some(Some(allowance)) = access_key.permission.allowance_mut() {
+            let new_allowance = allowance.saturating_add(transfer.deposit);
+            if new_allowance > *allowance {
+                *allowance = new_allowance;
+                updated = true;
             }
         }
         if updated {
@@ -428,9 +440,80 @@ pub(crate) fn action_deploy_contract(
     apply_state: &ApplyState,
 ) -> Result<(), StorageError> {
     let code = ContractCode::new(deploy_contract.code.clone(), None);
-    let prev_code = get_code(state_update, account_id, Some(account.code_hash()))?;
-    let prev_code_length = prev_code.map(|code| code.code.len() as u64).unwrap_or_default();
-    account.set_storage_usage(account.storage_usage().checked_sub(prev_code_length).unwrap_or(0));
+    deploy_code(state_update, account, account_id, &code, apply_state)
+}
+
+/// Deploys the code of a `DeployContractAndMigrateAction` and calls its migration method. If the
+/// migration fails, the result is a `ContractMigrationFailed` error and the state changes of the
+/// receipt, including the new code, are rolled back in `apply_action_receipt`.
+pub(crate) fn action_deploy_contract_and_migrate(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    account: &mut Account,
+    receipt: &Receipt,
+    action_receipt: &ActionReceipt,
+    promise_results: &[PromiseResult],
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    deploy_contract_and_migrate: &DeployContractAndMigrateAction,
+    action_hash: &CryptoHash,
+    config: &RuntimeConfig,
+    is_last_action: bool,
+    epoch_info_provider: &dyn EpochInfoProvider,
+) -> Result<(), RuntimeError> {
+    let code = ContractCode::new(deploy_contract_and_migrate.code.clone(), None);
+    deploy_code(state_update, account, account_id, &code, apply_state)?;
+    let migration_call = FunctionCallAction {
+        method_name: deploy_contract_and_migrate.method_name.clone(),
+        args: deploy_contract_and_migrate.args.clone(),
+        gas: deploy_contract_and_migrate.gas,
+        deposit: 0,
+    };
+    action_function_call(
+        state_update,
+        apply_state,
+        account,
+        receipt,
+        action_receipt,
+        promise_results,
+        result,
+        account_id,
+        &migration_call,
+        action_hash,
+        config,
+        is_last_action,
+        epoch_info_provider,
+    )?;
+    if let Err(ActionError { kind: ActionErrorKind::FunctionCallError(error), .. }) = &result.result
+    {
+        result.result = Err(ActionErrorKind::ContractMigrationFailed {
+            account_id: account_id.clone(),
+            method_name: migration_call.method_name,
+            error: error.clone(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Sets the code of the account and updates its storage usage.
+fn deploy_code(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    account_id: &AccountId,
+    code: &ContractCode,
+    apply_state: &ApplyState,
+) -> Result<(), StorageError> {
+    let prev_code_storage_usage = deployed_code_storage_usage(
+        state_update,
+        account,
+        account_id,
+        apply_state.current_protocol_version,
+    )?;
+    release_shared_code(state_update, account, account_id, apply_state.current_protocol_version)?;
+    account.set_storage_usage(
+        account.storage_usage().checked_sub(prev_code_storage_usage).unwrap_or(0),
+    );
     account.set_storage_usage(
         account.storage_usage().checked_add(code.code.len() as u64).ok_or_else(|| {
             StorageError::StorageInconsistentState(format!(
@@ -440,14 +523,282 @@ pub(crate) fn action_deploy_contract(
         })?,
     );
     account.set_code_hash(code.get_hash());
-    set_code(state_update, account_id.clone(), &code);
+    set_code(state_update, account_id.clone(), code);
     // Precompile the contract and store result (compiled code or error) in the database.
     // Note, that contract compilation costs are already accounted in deploy cost using
     // special logic in estimator (see get_runtime_config() function).
+    precompile_contract(code, &apply_state.config.wasm_config, apply_state.cache.as_deref()).ok();
+    Ok(())
+}
+
+/// Storage usage charged to an account which deploys a contract from the shared contract code
+/// registry, instead of the size of the code.
+const SHARED_CODE_REFERENCE_STORAGE_USAGE: StorageUsage = size_of::<CryptoHash>() as StorageUsage;
+
+/// Returns the storage usage charged to the account for its deployed contract: the size of the
+/// code deployed on the account, or the size of a reference to the shared contract code registry.
+fn deployed_code_storage_usage(
+    state_update: &TrieUpdate,
+    account: &Account,
+    account_id: &AccountId,
+    current_protocol_version: ProtocolVersion,
+) -> Result<StorageUsage, StorageError> {
+    let code = get_code(state_update, account_id, Some(account.code_hash()))?;
+    Ok(match code {
+        Some(code) => code.code.len() as StorageUsage,
+        None if account.code_hash() != CryptoHash::default()
+            && checked_feature!(
+                "protocol_feature_shared_contract_code",
+                SharedContractCode,
+                current_protocol_version
+            ) =>
+        {
+            SHARED_CODE_REFERENCE_STORAGE_USAGE
+        }
+        None => 0,
+    })
+}
+
+/// Releases the use of the shared contract code by the account which deployed it by hash, before
+/// its code is replaced or the account is deleted. The copy of the code is removed from the shard
+/// once it has no registrations and no users.
+fn release_shared_code(
+    state_update: &mut TrieUpdate,
+    account: &Account,
+    account_id: &AccountId,
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), StorageError> {
+    let code_hash = account.code_hash();
+    if code_hash == CryptoHash::default()
+        || !checked_feature!(
+            "protocol_feature_shared_contract_code",
+            SharedContractCode,
+            current_protocol_version
+        )
+        || state_update
+            .get_ref(&TrieKey::ContractCode { account_id: account_id.clone() })?
+            .is_some()
+    {
+        return Ok(());
+    }
+    if let Some(mut shared_code) = get_shared_code(state_update, code_hash)? {
+        // The number of users is an upper bound after resharding, as the copy of the code is
+        // duplicated to all the split shards.
+        shared_code.num_users = shared_code.num_users.saturating_sub(1);
+        update_shared_code(state_update, code_hash, &shared_code);
+    }
+    Ok(())
+}
+
+/// Stores the copy of the shared contract code on the shard, or removes it if it's unused.
+pub(crate) fn update_shared_code(
+    state_update: &mut TrieUpdate,
+    code_hash: CryptoHash,
+    shared_code: &SharedContractCode,
+) {
+    if shared_code.is_unused() {
+        remove_shared_code(state_update, code_hash);
+    } else {
+        set_shared_code(state_update, code_hash, shared_code);
+    }
+}
+
+/// Sends the update of the shared contract code registry by the account to all the other shards.
+/// The copy of the registry on the shard of the account is updated by the action itself.
+/// Sending and applying each of the receipts was prepaid with the fees of the action.
+fn broadcast_shared_code_receipt(
+    apply_state: &ApplyState,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    shared_code_receipt: SharedContractCodeReceipt,
+) -> Result<(), RuntimeError> {
+    let shard_layout = &apply_state.shard_layout;
+    let shard_id = account_id_to_shard_id(account_id, shard_layout);
+    let num_bytes = match &shared_code_receipt {
+        SharedContractCodeReceipt::Register { code, .. } => code.len() as u64,
+        SharedContractCodeReceipt::Remove { .. } => 0,
+    };
+    let receipt_fee = shared_code_receipt_fee(&apply_state.config.transaction_costs, num_bytes);
+    for receiver_id in shard_layout.shard_receiver_ids() {
+        if account_id_to_shard_id(&receiver_id, shard_layout) == shard_id {
+            continue;
+        }
+        result.gas_burnt = safe_add_gas(result.gas_burnt, receipt_fee)?;
+        result.gas_used = safe_add_gas(result.gas_used, receipt_fee)?;
+        result.new_receipts.push(Receipt {
+            predecessor_id: account_id.clone(),
+            receiver_id,
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::SharedContractCode(shared_code_receipt.clone()),
+        });
+    }
+    Ok(())
+}
+
+/// Applies a `SharedContractCodeReceipt` from `owner_id` to the copy of the registry on the shard.
+pub(crate) fn apply_shared_code_receipt(
+    state_update: &mut TrieUpdate,
+    owner_id: &AccountId,
+    shared_code_receipt: &SharedContractCodeReceipt,
+) -> Result<(), StorageError> {
+    match shared_code_receipt {
+        SharedContractCodeReceipt::Register { storage_usage, code } => {
+            let code_hash = hash(code);
+            let mut shared_code = get_shared_code(state_update, code_hash)?.unwrap_or_else(|| {
+                SharedContractCode { owners: vec![], num_users: 0, code: code.clone() }
+            });
+            shared_code.owners.push(SharedContractCodeOwner {
+                account_id: owner_id.clone(),
+                storage_usage: *storage_usage,
+            });
+            set_shared_code(state_update, code_hash, &shared_code);
+        }
+        SharedContractCodeReceipt::Remove { code_hash } => {
+            if let Some(mut shared_code) = get_shared_code(state_update, *code_hash)? {
+                if let Some(position) =
+                    shared_code.owners.iter().position(|owner| &owner.account_id == owner_id)
+                {
+                    shared_code.owners.remove(position);
+                    update_shared_code(state_update, *code_hash, &shared_code);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Registers the code in the shared contract code registry. The account pays for the copies of
+/// the code on all the shards until it removes the registration.
+pub(crate) fn action_deploy_shared_contract(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    deploy_shared_contract: &DeploySharedContractAction,
+    apply_state: &ApplyState,
+) -> Result<(), RuntimeError> {
+    let code = ContractCode::new(deploy_shared_contract.code.clone(), None);
+    let code_hash = code.get_hash();
+    let num_extra_bytes_record =
+        apply_state.config.transaction_costs.storage_usage_config.num_extra_bytes_record;
+    let num_shards = apply_state.shard_layout.num_shards();
+    let storage_usage = (code.code.len() as u64 + num_extra_bytes_record) * num_shards;
+    account.set_storage_usage(account.storage_usage().checked_add(storage_usage).ok_or_else(
+        || {
+            StorageError::StorageInconsistentState(format!(
+                "Storage usage integer overflow for account {}",
+                account_id
+            ))
+        },
+    )?);
+    let num_registrations = get_shared_code_registrations(state_update, account_id, code_hash)?;
+    set_shared_code_registrations(
+        state_update,
+        account_id.clone(),
+        code_hash,
+        num_registrations + 1,
+    );
+    let shared_code_receipt =
+        SharedContractCodeReceipt::Register { storage_usage, code: code.code.clone() };
+    apply_shared_code_receipt(state_update, account_id, &shared_code_receipt)?;
+    broadcast_shared_code_receipt(apply_state, result, account_id, shared_code_receipt)?;
+    // The compiled contract cache is keyed by the code hash, so all the accounts deploying the
+    // code by hash share the compiled contract.
     precompile_contract(&code, &apply_state.config.wasm_config, apply_state.cache.as_deref()).ok();
     Ok(())
 }
 
+/// Removes a registration of the code by the account from the shared contract code registry and
+/// releases the storage the account paid for it. The copies of the code stay on the shards where
+/// accounts deployed it by hash.
+pub(crate) fn action_remove_shared_contract(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    remove_shared_contract: &RemoveSharedContractAction,
+    apply_state: &ApplyState,
+) -> Result<(), RuntimeError> {
+    let code_hash = remove_shared_contract.code_hash;
+    let num_registrations = get_shared_code_registrations(state_update, account_id, code_hash)?;
+    let owner = get_shared_code(state_update, code_hash)?.and_then(|shared_code| {
+        shared_code.owners.into_iter().find(|owner| &owner.account_id == account_id)
+    });
+    let owner = match owner {
+        Some(owner) if num_registrations > 0 => owner,
+        _ => {
+            result.result = Err(ActionErrorKind::SharedContractCodeNotOwned {
+                account_id: account_id.clone(),
+                code_hash,
+            }
+            .into());
+            return Ok(());
+        }
+    };
+    account.set_storage_usage(account.storage_usage().saturating_sub(owner.storage_usage));
+    set_shared_code_registrations(
+        state_update,
+        account_id.clone(),
+        code_hash,
+        num_registrations - 1,
+    );
+    let shared_code_receipt = SharedContractCodeReceipt::Remove { code_hash };
+    apply_shared_code_receipt(state_update, account_id, &shared_code_receipt)?;
+    broadcast_shared_code_receipt(apply_state, result, account_id, shared_code_receipt)?;
+    Ok(())
+}
+
+pub(crate) fn action_deploy_contract_by_hash(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    deploy_contract_by_hash: &DeployContractByHashAction,
+    apply_state: &ApplyState,
+) -> Result<(), StorageError> {
+    let code_hash = deploy_contract_by_hash.code_hash;
+    // The code which lost all its registrations is kept only for its existing users.
+    match get_shared_code(state_update, code_hash)? {
+        Some(shared_code) if !shared_code.owners.is_empty() => {}
+        _ => {
+            result.result = Err(ActionErrorKind::SharedContractCodeNotFound { code_hash }.into());
+            return Ok(());
+        }
+    }
+    let prev_code_storage_usage = deployed_code_storage_usage(
+        state_update,
+        account,
+        account_id,
+        apply_state.current_protocol_version,
+    )?;
+    release_shared_code(state_update, account, account_id, apply_state.current_protocol_version)?;
+    account.set_storage_usage(
+        account.storage_usage().checked_sub(prev_code_storage_usage).unwrap_or(0),
+    );
+    account.set_storage_usage(
+        account.storage_usage().checked_add(SHARED_CODE_REFERENCE_STORAGE_USAGE).ok_or_else(
+            || {
+                StorageError::StorageInconsistentState(format!(
+                    "Storage usage integer overflow for account {}",
+                    account_id
+                ))
+            },
+        )?,
+    );
+    let mut shared_code = get_shared_code(state_update, code_hash)?.ok_or_else(|| {
+        StorageError::StorageInconsistentState(format!(
+            "Shared contract code {} is missing",
+            code_hash
+        ))
+    })?;
+    shared_code.num_users += 1;
+    set_shared_code(state_update, code_hash, &shared_code);
+    account.set_code_hash(code_hash);
+    // The code deployed on the account takes precedence over the shared one.
+    state_update.remove(TrieKey::ContractCode { account_id: account_id.clone() });
+    Ok(())
+}
+
 pub(crate) fn action_delete_account(
     state_update: &mut TrieUpdate,
     account: &mut Option<Account>,
@@ -458,6 +809,24 @@ pub(crate) fn action_delete_account(
     delete_account: &DeleteAccountAction,
     current_protocol_version: ProtocolVersion,
 ) -> Result<(), StorageError> {
+    if checked_feature!(
+        "protocol_feature_shared_contract_code",
+        SharedContractCode,
+        current_protocol_version
+    ) && has_shared_code_registrations(state_update, account_id)?
+    {
+        result.result = Err(ActionErrorKind::DeleteAccountWithSharedContractCode {
+            account_id: account_id.clone(),
+        }
+        .into());
+        return Ok(());
+    }
+    release_shared_code(
+        state_update,
+        account.as_ref().unwrap(),
+        account_id,
+        current_protocol_version,
+    )?;
     if current_protocol_version >= ProtocolFeature::DeleteActionRestriction.protocol_version() {
         let account = account.as_ref().unwrap();
         let mut account_storage_usage = account.storage_usage();
@@ -469,6 +838,25 @@ pub(crate) fn action_delete_account(
             account_storage_usage = account_storage_usage.saturating_sub(code_len);
         }
         if account_storage_usage > Account::MAX_ACCOUNT_DELETION_STORAGE_USAGE {
+            if checked_feature!(
+                "protocol_feature_multi_step_account_deletion",
+                MultiStepAccountDeletion,
+                current_protocol_version
+            ) {
+                // The records of the account are removed by the following receipts from system
+                // and the account keeps its balance until the last of them.
+                set_pending_account_deletion(
+                    state_update,
+                    account_id.clone(),
+                    &delete_account.beneficiary_id,
+                );
+                result.new_receipts.push(Receipt::new_account_deletion(
+                    account_id,
+                    &delete_account.beneficiary_id,
+                ));
+                *actor_id = receipt.predecessor_id.clone();
+                return Ok(());
+            }
             result.result = Err(ActionErrorKind::DeleteAccountWithLargeState {
                 account_id: account_id.clone(),
             }
@@ -489,6 +877,75 @@ pub(crate) fn action_delete_account(
     Ok(())
 }
 
+/// Continues the deletion of an account with a large state started by `action_delete_account`.
+/// Removes the next batch of the account records, as many as the gas limit of a function call
+/// allows to remove with `storage_remove`, and either finishes the deletion or generates the
+/// receipt removing the next batch.
+pub(crate) fn action_continue_account_deletion(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    account: &mut Option<Account>,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    beneficiary_id: &AccountId,
+) -> Result<(), RuntimeError> {
+    let ext_costs = &apply_state.config.wasm_config.ext_costs;
+    let max_gas_burnt = apply_state.config.wasm_config.limit_config.max_gas_burnt;
+    let num_extra_bytes_record =
+        apply_state.config.transaction_costs.storage_usage_config.num_extra_bytes_record;
+    let mut gas_burnt: Gas = 0;
+    let mut removed_storage_usage: StorageUsage = 0;
+    let is_complete =
+        remove_account_storage_batch(state_update, account_id, |column, key_len, value_len| {
+            let gas = ext_costs.storage_remove_base
+                + ext_costs.storage_remove_key_byte * key_len
+                + ext_costs.storage_remove_ret_value_byte * value_len;
+            match gas_burnt.checked_add(gas) {
+                Some(total_gas) if total_gas <= max_gas_burnt => {
+                    gas_burnt = total_gas;
+                    // Only access keys and contract data are counted in the storage usage of
+                    // the account.
+                    if column == col::ACCESS_KEY || column == col::CONTRACT_DATA {
+                        removed_storage_usage += key_len + value_len + num_extra_bytes_record;
+                    }
+                    true
+                }
+                _ => false,
+            }
+        })?;
+    result.gas_burnt = safe_add_gas(result.gas_burnt, gas_burnt)?;
+    result.gas_used = safe_add_gas(result.gas_used, gas_burnt)?;
+
+    let deleted_account = account.as_mut().unwrap();
+    deleted_account
+        .set_storage_usage(deleted_account.storage_usage().saturating_sub(removed_storage_usage));
+    // The receipts from system continuing the deletion are paid by the account itself, out of
+    // the balance that no longer stakes the removed records.
+    let storage_stake = Balance::from(deleted_account.storage_usage())
+        .saturating_mul(apply_state.config.storage_amount_per_byte);
+    let available_amount = deleted_account
+        .amount()
+        .saturating_add(deleted_account.locked())
+        .saturating_sub(storage_stake)
+        .min(deleted_account.amount());
+    let cost = safe_gas_to_balance(apply_state.gas_price, result.gas_burnt)?.min(available_amount);
+    deleted_account.set_amount(deleted_account.amount() - cost);
+    result.burnt_amount = safe_add_balance(result.burnt_amount, cost)?;
+
+    if !is_complete {
+        result.new_receipts.push(Receipt::new_account_deletion(account_id, beneficiary_id));
+        return Ok(());
+    }
+    let account_balance = deleted_account.amount();
+    if account_balance > 0 {
+        result.new_receipts.push(Receipt::new_balance_refund(beneficiary_id, account_balance));
+    }
+    remove_account(state_update, account_id)?;
+    remove_pending_account_deletion(state_update, account_id);
+    *account = None;
+    Ok(())
+}
+
 pub(crate) fn action_delete_key(
     fee_config: &RuntimeFeesConfig,
     state_update: &mut TrieUpdate,
@@ -524,6 +981,68 @@ pub(crate) fn action_delete_key(
     Ok(())
 }
 
+pub(crate) fn action_update_access_key(
+    state_update: &mut TrieUpdate,
+    account: &mut Account,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    update_access_key: &UpdateAccessKeyAction,
+) -> Result<(), StorageError> {
+    let mut access_key =
+        match get_access_key(state_update, account_id, &update_access_key.public_key)? {
+            Some(access_key) => access_key,
+            None => {
+                result.result = Err(ActionErrorKind::UpdateKeyDoesNotExist {
+                    account_id: account_id.clone(),
+                    public_key: update_access_key.public_key.clone(),
+                }
+                .into());
+                return Ok(());
+            }
+        };
+    let old_access_key_size = access_key.try_to_vec().unwrap().len() as u64;
+    match &mut access_key.permission {
+        AccessKeyPermission::FunctionCall(permission) => {
+            permission.allowance = update_access_key.allowance;
+            permission.method_names = update_access_key.method_names.clone();
+        }
+        AccessKeyPermission::FunctionCallMultiReceiver(permission) => {
+            permission.allowance = update_access_key.allowance;
+            permission.method_names = update_access_key.method_names.clone();
+        }
+        AccessKeyPermission::FullAccess => {
+            result.result = Err(ActionErrorKind::UpdateKeyRequiresFunctionCallKey {
+                account_id: account_id.clone(),
+                public_key: update_access_key.public_key.clone(),
+            }
+            .into());
+            return Ok(());
+        }
+    }
+    // The nonce is kept, so the transactions signed with the access key before the update can't be
+    // replayed.
+    set_access_key(
+        state_update,
+        account_id.clone(),
+        update_access_key.public_key.clone(),
+        &access_key,
+    );
+    let new_access_key_size = access_key.try_to_vec().unwrap().len() as u64;
+    account.set_storage_usage(
+        account
+            .storage_usage()
+            .checked_add(new_access_key_size)
+            .and_then(|storage_usage| storage_usage.checked_sub(old_access_key_size))
+            .ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Storage usage integer overflow for account {}",
+                    account_id
+                ))
+            })?,
+    );
+    Ok(())
+}
+
 pub(crate) fn action_add_key(
     apply_state: &ApplyState,
     state_update: &mut TrieUpdate,
@@ -572,6 +1091,164 @@ pub(crate) fn action_add_key(
     Ok(())
 }
 
+/// Sends the actions of a delegate action signed by `account_id` in a new receipt on its behalf.
+/// The signer of the receipt prepaid the gas and the deposits of the actions, and it receives
+/// the gas refunds and, as the `predecessor_id` of this receipt, the deposit refunds of the new
+/// receipt.
+pub(crate) fn action_delegate(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    action_receipt: &ActionReceipt,
+    predecessor_id: &AccountId,
+    result: &mut ActionResult,
+    account_id: &AccountId,
+    signed_delegate_action: &SignedDelegateAction,
+) -> Result<(), RuntimeError> {
+    let delegate_action = &signed_delegate_action.delegate_action;
+    if !signed_delegate_action.verify() {
+        result.result = Err(ActionErrorKind::DelegateActionInvalidSignature.into());
+        return Ok(());
+    }
+    if apply_state.block_index > delegate_action.max_block_height {
+        result.result = Err(ActionErrorKind::DelegateActionExpired.into());
+        return Ok(());
+    }
+    if &delegate_action.sender_id != account_id {
+        result.result = Err(ActionErrorKind::DelegateActionSenderDoesNotMatchTxReceiver {
+            sender_id: delegate_action.sender_id.clone(),
+            receiver_id: account_id.clone(),
+        }
+        .into());
+        return Ok(());
+    }
+
+    // The new receipt is executed with the gas prepaid for this one, so it's used but not burnt.
+    let fees_config = &apply_state.config.transaction_costs;
+    let mut required_gas = safe_add_gas(
+        fees_config.action_receipt_creation_config.exec_fee(),
+        total_prepaid_exec_fees(
+            fees_config,
+            &delegate_action.actions,
+            &delegate_action.receiver_id,
+            apply_state.shard_layout.num_shards(),
+            apply_state.current_protocol_version,
+        )?,
+    )?;
+    required_gas = safe_add_gas(required_gas, total_prepaid_gas(&delegate_action.actions)?)?;
+
+    // A function call key pays for the inner actions out of its allowance, the same as for
+    // a transaction it signs, even though the balance is paid by the relayer.
+    let sender_is_receiver = delegate_action.sender_id == delegate_action.receiver_id;
+    let send_fees = safe_add_gas(
+        fees_config.action_receipt_creation_config.send_fee(sender_is_receiver),
+        total_send_fees(
+            fees_config,
+            sender_is_receiver,
+            &delegate_action.actions,
+            &delegate_action.receiver_id,
+            apply_state.current_protocol_version,
+        )?,
+    )?;
+    let cost = safe_add_balance(
+        safe_gas_to_balance(action_receipt.gas_price, safe_add_gas(send_fees, required_gas)?)?,
+        total_deposit(&delegate_action.actions)?,
+    )?;
+    check_delegate_action_key(state_update, apply_state, result, delegate_action, cost)?;
+    if result.result.is_err() {
+        return Ok(());
+    }
+
+    result.gas_used = safe_add_gas(result.gas_used, required_gas)?;
+    result.new_receipts.push(Receipt {
+        predecessor_id: account_id.clone(),
+        receiver_id: delegate_action.receiver_id.clone(),
+        receipt_id: CryptoHash::default(),
+        receipt: ReceiptEnum::Delegated(DelegatedActionReceipt {
+            action_receipt: ActionReceipt {
+                signer_id: action_receipt.signer_id.clone(),
+                signer_public_key: action_receipt.signer_public_key.clone(),
+                gas_price: action_receipt.gas_price,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: delegate_action.actions.clone(),
+            },
+            refund_to: predecessor_id.clone(),
+        }),
+    });
+    Ok(())
+}
+
+/// Checks the nonce and the permission of the access key the delegate action is signed with,
+/// and updates the nonce of the key. A function call key is charged `cost` from its allowance.
+fn check_delegate_action_key(
+    state_update: &mut TrieUpdate,
+    apply_state: &ApplyState,
+    result: &mut ActionResult,
+    delegate_action: &DelegateAction,
+    cost: Balance,
+) -> Result<(), RuntimeError> {
+    let sender_id = &delegate_action.sender_id;
+    let public_key = &delegate_action.public_key;
+    let mut access_key = match get_access_key(state_update, sender_id, public_key)? {
+        Some(access_key) => access_key,
+        None => {
+            result.result = Err(ActionErrorKind::DelegateActionAccessKeyError(
+                InvalidAccessKeyError::AccessKeyNotFound {
+                    account_id: sender_id.clone(),
+                    public_key: public_key.clone(),
+                },
+            )
+            .into());
+            return Ok(());
+        }
+    };
+    if delegate_action.nonce <= access_key.nonce {
+        result.result = Err(ActionErrorKind::DelegateActionInvalidNonce {
+            delegate_nonce: delegate_action.nonce,
+            ak_nonce: access_key.nonce,
+        }
+        .into());
+        return Ok(());
+    }
+    let upper_bound = apply_state.block_index * AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER;
+    if delegate_action.nonce >= upper_bound {
+        result.result = Err(ActionErrorKind::DelegateActionNonceTooLarge {
+            delegate_nonce: delegate_action.nonce,
+            upper_bound,
+        }
+        .into());
+        return Ok(());
+    }
+    if let Err(err) = validate_access_key_permission(
+        &access_key.permission,
+        &delegate_action.receiver_id,
+        &delegate_action.actions,
+    ) {
+        result.result = Err(ActionErrorKind::DelegateActionAccessKeyError(err).into());
+        return Ok(());
+    }
+    if let Some(Some(allowance)) = access_key.permission.allowance_mut() {
+        *allowance = match allowance.checked_sub(cost) {
+            Some(remaining) => remaining,
+            None => {
+                result.result = Err(ActionErrorKind::DelegateActionAccessKeyError(
+                    InvalidAccessKeyError::NotEnoughAllowance {
+                        account_id: sender_id.clone(),
+                        public_key: public_key.clone(),
+                        allowance: *allowance,
+                        cost,
+                    },
+                )
+                .into());
+                return Ok(());
+            }
+        };
+    }
+    access_key.nonce = delegate_action.nonce;
+    set_access_key(state_update, sender_id.clone(), public_key.clone(), &access_key);
+    Ok(())
+}
+
 pub(crate) fn check_actor_permissions(
     action: &Action,
     account: &Option<Account>,
@@ -579,7 +1256,15 @@ pub(crate) fn check_actor_permissions(
     account_id: &AccountId,
 ) -> Result<(), ActionError> {
     match action {
-        Action::DeployContract(_) | Action::Stake(_) | Action::AddKey(_) | Action::DeleteKey(_) => {
+        Action::DeployContract(_)
+        | Action::DeploySharedContract(_)
+        | Action::DeployContractByHash(_)
+        | Action::RemoveSharedContract(_)
+        | Action::DeployContractAndMigrate(_)
+        | Action::Stake(_)
+        | Action::AddKey(_)
+        | Action::DeleteKey(_)
+        | Action::UpdateAccessKey(_) => {
             if actor_id != account_id {
                 return Err(ActionErrorKind::ActorNoPermission {
                     account_id: account_id.clone(),
@@ -604,7 +1289,10 @@ pub(crate) fn check_actor_permissions(
                 .into());
             }
         }
-        Action::CreateAccount(_) | Action::FunctionCall(_) | Action::Transfer(_) => (),
+        Action::CreateAccount(_)
+        | Action::FunctionCall(_)
+        | Action::Transfer(_)
+        | Action::Delegate(_) => (),
     };
     Ok(())
 }
@@ -667,11 +1355,17 @@ pub(crate) fn check_account_existence(
             }
         }
         Action::DeployContract(_)
+        | Action::DeploySharedContract(_)
+        | Action::DeployContractByHash(_)
+        | Action::RemoveSharedContract(_)
+        | Action::DeployContractAndMigrate(_)
         | Action::FunctionCall(_)
         | Action::Stake(_)
         | Action::AddKey(_)
         | Action::DeleteKey(_)
-        | Action::DeleteAccount(_) => {
+        | Action::DeleteAccount(_)
+        | Action::Delegate(_)
+        | Action::UpdateAccessKey(_) => {
             if account.is_none() {
                 return Err(ActionErrorKind::AccountDoesNotExist {
                     account_id: account_id.clone(),
@@ -686,7 +1380,6 @@ pub(crate) fn check_account_existence(
 #[cfg(test)]
 mod tests {
     use near_primitives::hash::hash;
-    use near_primitives::trie_key::TrieKey;
     use near_store::test_utils::create_tries;
 
     use super::*;
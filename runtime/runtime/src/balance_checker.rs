@@ -8,11 +8,11 @@ use crate::{ApplyStats, DelayedReceiptIndices, ValidatorAccountsUpdate};
 use near_primitives::errors::{
     BalanceMismatchError, IntegerOverflowError, RuntimeError, StorageError,
 };
-use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::receipt::{DelegatedActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::runtime::fees::RuntimeFeesConfig;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::trie_key::TrieKey;
-use near_primitives::types::{AccountId, Balance};
+use near_primitives::types::{AccountId, Balance, NumShards};
 use near_primitives::version::ProtocolVersion;
 use near_store::{get, get_account, get_postponed_receipt, TrieUpdate};
 use std::collections::HashSet;
@@ -26,6 +26,7 @@ pub(crate) fn check_balance(
     transactions: &[SignedTransaction],
     outgoing_receipts: &[Receipt],
     stats: &ApplyStats,
+    num_shards: NumShards,
     current_protocol_version: ProtocolVersion,
 ) -> Result<(), RuntimeError> {
     // Delayed receipts
@@ -99,7 +100,8 @@ pub(crate) fn check_balance(
     // Receipts
     let receipt_cost = |receipt: &Receipt| -> Result<Balance, IntegerOverflowError> {
         Ok(match &receipt.receipt {
-            ReceiptEnum::Action(action_receipt) => {
+            ReceiptEnum::Action(action_receipt)
+            | ReceiptEnum::Delegated(DelegatedActionReceipt { action_receipt, .. }) => {
                 let mut total_cost = total_deposit(&action_receipt.actions)?;
                 if !AccountId::is_system(&receipt.predecessor_id) {
                     let mut total_gas = safe_add_gas(
@@ -108,6 +110,7 @@ pub(crate) fn check_balance(
                             transaction_costs,
                             &action_receipt.actions,
                             &receipt.receiver_id,
+                            num_shards,
                             current_protocol_version,
                         )?,
                     )?;
@@ -118,7 +121,7 @@ pub(crate) fn check_balance(
                 }
                 total_cost
             }
-            ReceiptEnum::Data(_) => 0,
+            ReceiptEnum::Data(_) | ReceiptEnum::SharedContractCode(_) => 0,
         })
     };
     let receipts_cost = |receipts: &[Receipt]| -> Result<Balance, IntegerOverflowError> {
@@ -143,7 +146,9 @@ pub(crate) fn check_balance(
         .map(|receipt| {
             let account_id = &receipt.receiver_id;
             match &receipt.receipt {
-                ReceiptEnum::Action(_) => Ok(Some((account_id.clone(), receipt.receipt_id))),
+                ReceiptEnum::Action(_) | ReceiptEnum::Delegated(_) => {
+                    Ok(Some((account_id.clone(), receipt.receipt_id)))
+                }
                 ReceiptEnum::Data(data_receipt) => {
                     if let Some(receipt_id) = get(
                         initial_state,
@@ -157,6 +162,7 @@ pub(crate) fn check_balance(
                         Ok(None)
                     }
                 }
+                ReceiptEnum::SharedContractCode(_) => Ok(None),
             }
         })
         .collect::<Result<Vec<Option<_>>, StorageError>>()?
@@ -258,6 +264,7 @@ mod tests {
             &[],
             &[],
             &ApplyStats::default(),
+            1,
             PROTOCOL_VERSION,
         )
         .unwrap();
@@ -279,6 +286,7 @@ mod tests {
             &[],
             &[],
             &ApplyStats::default(),
+            1,
             PROTOCOL_VERSION,
         )
         .unwrap_err();
@@ -314,6 +322,7 @@ mod tests {
             &[],
             &[],
             &ApplyStats::default(),
+            1,
             PROTOCOL_VERSION,
         )
         .unwrap();
@@ -387,7 +396,9 @@ mod tests {
                 gas_deficit_amount: 0,
                 other_burnt_amount: 0,
                 slashed_burnt_amount: 0,
+                priority_fees: 0,
             },
+            1,
             PROTOCOL_VERSION,
         )
         .unwrap();
@@ -441,6 +452,7 @@ mod tests {
                 &[tx],
                 &[],
                 &ApplyStats::default(),
+                1,
                 PROTOCOL_VERSION,
             ),
             Err(RuntimeError::UnexpectedIntegerOverflow)
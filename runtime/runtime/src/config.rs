@@ -9,12 +9,14 @@ use near_primitives::account::AccessKeyPermission;
 use near_primitives::errors::IntegerOverflowError;
 // Just re-exporting RuntimeConfig for backwards compatibility.
 pub use near_primitives::num_rational::Rational;
+use near_primitives::receipt::{DelegatedActionReceipt, Receipt, ReceiptEnum};
 pub use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::fees::{transfer_exec_fee, transfer_send_fee, RuntimeFeesConfig};
 use near_primitives::transaction::{
-    Action, AddKeyAction, DeployContractAction, FunctionCallAction, Transaction,
+    Action, AddKeyAction, DeployContractAction, DeployContractAndMigrateAction,
+    DeploySharedContractAction, FunctionCallAction, Transaction, UpdateAccessKeyAction,
 };
-use near_primitives::types::{AccountId, Balance, Gas};
+use near_primitives::types::{AccountId, Balance, Gas, NumShards};
 use near_primitives::version::{is_implicit_account_creation_enabled, ProtocolVersion};
 
 /// Describes the cost of converting this transaction into a receipt.
@@ -28,7 +30,7 @@ pub struct TransactionCost {
     pub gas_remaining: Gas,
     /// The gas price at which the gas was purchased in the receipt.
     pub receipt_gas_price: Balance,
-    /// Total costs in tokens for this transaction (including all deposits).
+    /// Total costs in tokens for this transaction (including all deposits and the priority fee).
     pub total_cost: Balance,
     /// The amount of tokens burnt by converting this transaction to a receipt.
     pub burnt_amount: Balance,
@@ -118,15 +120,79 @@ pub fn total_send_fees(
                 AccessKeyPermission::FullAccess => {
                     cfg.add_key_cost.full_access_cost.send_fee(sender_is_receiver)
                 }
+                AccessKeyPermission::FunctionCallMultiReceiver(call_perm) => {
+                    let num_bytes = num_bytes_of_names(&call_perm.method_names)
+                        + num_bytes_of_names(&call_perm.receiver_ids);
+                    cfg.add_key_cost.function_call_cost.send_fee(sender_is_receiver)
+                        + num_bytes
+                            * cfg
+                                .add_key_cost
+                                .function_call_cost_per_byte
+                                .send_fee(sender_is_receiver)
+                }
             },
             DeleteKey(_) => cfg.delete_key_cost.send_fee(sender_is_receiver),
             DeleteAccount(_) => cfg.delete_account_cost.send_fee(sender_is_receiver),
+            Delegate(signed_delegate_action) => {
+                // The actions are sent in a new receipt by the sender of the delegate action, but
+                // the fees of sending it are paid upfront.
+                let delegate_action = &signed_delegate_action.delegate_action;
+                let inner_sender_is_receiver =
+                    delegate_action.sender_id == delegate_action.receiver_id;
+                let inner_send_fees = safe_add_gas(
+                    config.action_receipt_creation_config.send_fee(inner_sender_is_receiver),
+                    total_send_fees(
+                        config,
+                        inner_sender_is_receiver,
+                        &delegate_action.actions,
+                        &delegate_action.receiver_id,
+                        current_protocol_version,
+                    )?,
+                )?;
+                safe_add_gas(cfg.delegate_cost.send_fee(sender_is_receiver), inner_send_fees)?
+            }
+            DeploySharedContract(DeploySharedContractAction { code }) => {
+                let num_bytes = code.len() as u64;
+                cfg.deploy_contract_cost.send_fee(sender_is_receiver)
+                    + cfg.deploy_contract_cost_per_byte.send_fee(sender_is_receiver) * num_bytes
+            }
+            // The code is already in the registry, so only the base cost of deploying is charged.
+            DeployContractByHash(_) | RemoveSharedContract(_) => {
+                cfg.deploy_contract_cost.send_fee(sender_is_receiver)
+            }
+            // Updating a key is charged as adding a function call key with the new method names.
+            UpdateAccessKey(UpdateAccessKeyAction { method_names, .. }) => {
+                cfg.add_key_cost.function_call_cost.send_fee(sender_is_receiver)
+                    + num_bytes_of_names(method_names)
+                        * cfg.add_key_cost.function_call_cost_per_byte.send_fee(sender_is_receiver)
+            }
+            // Charged as deploying the code and calling the migration method.
+            DeployContractAndMigrate(DeployContractAndMigrateAction {
+                code,
+                method_name,
+                args,
+                ..
+            }) => {
+                let num_code_bytes = code.len() as u64;
+                let num_call_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
+                cfg.deploy_contract_cost.send_fee(sender_is_receiver)
+                    + cfg.deploy_contract_cost_per_byte.send_fee(sender_is_receiver)
+                        * num_code_bytes
+                    + cfg.function_call_cost.send_fee(sender_is_receiver)
+                    + cfg.function_call_cost_per_byte.send_fee(sender_is_receiver) * num_call_bytes
+            }
         };
         result = safe_add_gas(result, delta)?;
     }
     Ok(result)
 }
 
+/// Total number of bytes of the names stored in a function call access key.
+fn num_bytes_of_names(names: &[String]) -> u64 {
+    // Account for null-terminating characters.
+    names.iter().map(|name| name.as_bytes().len() as u64 + 1).sum()
+}
+
 pub fn exec_fee(
     config: &RuntimeFeesConfig,
     action: &Action,
@@ -168,18 +234,82 @@ pub fn exec_fee(
                     + num_bytes * cfg.add_key_cost.function_call_cost_per_byte.exec_fee()
             }
             AccessKeyPermission::FullAccess => cfg.add_key_cost.full_access_cost.exec_fee(),
+            AccessKeyPermission::FunctionCallMultiReceiver(call_perm) => {
+                let num_bytes = num_bytes_of_names(&call_perm.method_names)
+                    + num_bytes_of_names(&call_perm.receiver_ids);
+                cfg.add_key_cost.function_call_cost.exec_fee()
+                    + num_bytes * cfg.add_key_cost.function_call_cost_per_byte.exec_fee()
+            }
         },
         DeleteKey(_) => cfg.delete_key_cost.exec_fee(),
         DeleteAccount(_) => cfg.delete_account_cost.exec_fee(),
+        Delegate(_) => cfg.delegate_cost.exec_fee(),
+        DeploySharedContract(DeploySharedContractAction { code }) => {
+            let num_bytes = code.len() as u64;
+            cfg.deploy_contract_cost.exec_fee()
+                + cfg.deploy_contract_cost_per_byte.exec_fee() * num_bytes
+        }
+        DeployContractByHash(_) | RemoveSharedContract(_) => cfg.deploy_contract_cost.exec_fee(),
+        UpdateAccessKey(UpdateAccessKeyAction { method_names, .. }) => {
+            cfg.add_key_cost.function_call_cost.exec_fee()
+                + num_bytes_of_names(method_names)
+                    * cfg.add_key_cost.function_call_cost_per_byte.exec_fee()
+        }
+        DeployContractAndMigrate(DeployContractAndMigrateAction {
+            code,
+            method_name,
+            args,
+            ..
+        }) => {
+            let num_code_bytes = code.len() as u64;
+            let num_call_bytes = method_name.as_bytes().len() as u64 + args.len() as u64;
+            cfg.deploy_contract_cost.exec_fee()
+                + cfg.deploy_contract_cost_per_byte.exec_fee() * num_code_bytes
+                + cfg.function_call_cost.exec_fee()
+                + cfg.function_call_cost_per_byte.exec_fee() * num_call_bytes
+        }
     }
 }
 
+/// Returns the gas to apply a `SharedContractCodeReceipt` with `num_bytes` of code on a shard.
+pub fn shared_code_receipt_exec_fee(config: &RuntimeFeesConfig, num_bytes: u64) -> Gas {
+    config.action_receipt_creation_config.exec_fee()
+        + config.action_creation_config.deploy_contract_cost_per_byte.exec_fee() * num_bytes
+}
+
+/// Returns the gas to send a `SharedContractCodeReceipt` with `num_bytes` of code to another shard
+/// and to apply it there. The receipt doesn't carry a gas price, so all of it is burnt by the
+/// sender.
+pub fn shared_code_receipt_fee(config: &RuntimeFeesConfig, num_bytes: u64) -> Gas {
+    config.action_receipt_creation_config.send_fee(false)
+        + config.action_creation_config.deploy_contract_cost_per_byte.send_fee(false) * num_bytes
+        + shared_code_receipt_exec_fee(config, num_bytes)
+}
+
+/// Returns the gas to broadcast the update of the shared contract code registry by the action to
+/// the other `num_shards - 1` shards.
+fn shared_code_broadcast_fee(
+    config: &RuntimeFeesConfig,
+    action: &Action,
+    num_shards: NumShards,
+) -> Result<Gas, IntegerOverflowError> {
+    let num_bytes = match action {
+        Action::DeploySharedContract(DeploySharedContractAction { code }) => code.len() as u64,
+        Action::RemoveSharedContract(_) => 0,
+        _ => return Ok(0),
+    };
+    shared_code_receipt_fee(config, num_bytes)
+        .checked_mul(num_shards.saturating_sub(1))
+        .ok_or_else(|| IntegerOverflowError {})
+}
+
 /// Returns transaction costs for a given transaction.
 pub fn tx_cost(
     config: &RuntimeFeesConfig,
     transaction: &Transaction,
     gas_price: Balance,
     sender_is_receiver: bool,
+    num_shards: NumShards,
     current_protocol_version: ProtocolVersion,
 ) -> Result<TransactionCost, IntegerOverflowError> {
     let mut gas_burnt: Gas = config.action_receipt_creation_config.send_fee(sender_is_receiver);
@@ -196,7 +326,12 @@ pub fn tx_cost(
     let prepaid_gas = total_prepaid_gas(&transaction.actions)?;
     // If signer is equals to receiver the receipt will be processed at the same block as this
     // transaction. Otherwise it will processed in the next block and the gas might be inflated.
-    let initial_receipt_hop = if transaction.signer_id == transaction.receiver_id { 0 } else { 1 };
+    let mut initial_receipt_hop =
+        if transaction.signer_id == transaction.receiver_id { 0 } else { 1 };
+    // Actions of delegate actions are sent in one more receipt.
+    if transaction.actions.iter().any(|action| matches!(action, Action::Delegate(_))) {
+        initial_receipt_hop += 1;
+    }
     let minimum_new_receipt_gas = config.min_receipt_with_function_call_gas();
     // In case the config is free, we don't care about the maximum depth.
     let receipt_gas_price = if gas_price == 0 {
@@ -221,6 +356,7 @@ pub fn tx_cost(
             &config,
             &transaction.actions,
             &transaction.receiver_id,
+            num_shards,
             current_protocol_version,
         )?,
     )?;
@@ -228,35 +364,82 @@ pub fn tx_cost(
     let remaining_gas_amount = safe_gas_to_balance(receipt_gas_price, gas_remaining)?;
     let mut total_cost = safe_add_balance(burnt_amount, remaining_gas_amount)?;
     total_cost = safe_add_balance(total_cost, total_deposit(&transaction.actions)?)?;
+    // The priority fee is burnt with the chunk and minted back to the chunk producers with the
+    // rewards of the epoch.
+    total_cost = safe_add_balance(total_cost, transaction.priority_fee.unwrap_or_default())?;
     Ok(TransactionCost { gas_burnt, gas_remaining, receipt_gas_price, total_cost, burnt_amount })
 }
 
 /// Total sum of gas that would need to be burnt before we start executing the given actions.
+/// For delegate actions it includes executing the receipt with their actions, and for the actions
+/// updating the shared contract code registry sending the update to the other shards.
 pub fn total_prepaid_exec_fees(
     config: &RuntimeFeesConfig,
     actions: &[Action],
     receiver_id: &AccountId,
+    num_shards: NumShards,
     current_protocol_version: ProtocolVersion,
 ) -> Result<Gas, IntegerOverflowError> {
     let mut result = 0;
     for action in actions {
-        let delta = exec_fee(&config, action, receiver_id, current_protocol_version);
+        let mut delta = exec_fee(&config, action, receiver_id, current_protocol_version);
+        delta = safe_add_gas(delta, shared_code_broadcast_fee(config, action, num_shards)?)?;
+        if let Action::Delegate(signed_delegate_action) = action {
+            let delegate_action = &signed_delegate_action.delegate_action;
+            delta = safe_add_gas(delta, config.action_receipt_creation_config.exec_fee())?;
+            delta = safe_add_gas(
+                delta,
+                total_prepaid_exec_fees(
+                    config,
+                    &delegate_action.actions,
+                    &delegate_action.receiver_id,
+                    num_shards,
+                    current_protocol_version,
+                )?,
+            )?;
+        }
         result = safe_add_gas(result, delta)?;
     }
     Ok(result)
 }
-/// Get the total sum of deposits for given actions.
+/// Get the total sum of deposits for given actions, including the actions of delegate actions.
 pub fn total_deposit(actions: &[Action]) -> Result<Balance, IntegerOverflowError> {
     let mut total_balance: Balance = 0;
     for action in actions {
-        total_balance = safe_add_balance(total_balance, action.get_deposit_balance())?;
+        let deposit = match action {
+            Action::Delegate(signed_delegate_action) => {
+                total_deposit(&signed_delegate_action.delegate_action.actions)?
+            }
+            _ => action.get_deposit_balance(),
+        };
+        total_balance = safe_add_balance(total_balance, deposit)?;
     }
     Ok(total_balance)
 }
 
-/// Get the total sum of prepaid gas for given actions.
+/// Get the total sum of prepaid gas for given actions, including the actions of delegate actions.
 pub fn total_prepaid_gas(actions: &[Action]) -> Result<Gas, IntegerOverflowError> {
-    actions.iter().try_fold(0, |acc, action| safe_add_gas(acc, action.get_prepaid_gas()))
+    actions.iter().try_fold(0, |acc, action| {
+        let gas = match action {
+            Action::Delegate(signed_delegate_action) => {
+                total_prepaid_gas(&signed_delegate_action.delegate_action.actions)?
+            }
+            _ => action.get_prepaid_gas(),
+        };
+        safe_add_gas(acc, gas)
+    })
+}
+
+/// Get the total sum of prepaid gas attached to the receipt, data and shared contract code
+/// receipts carry no gas.
+pub fn receipt_prepaid_gas(receipt: &Receipt) -> Result<Gas, IntegerOverflowError> {
+    match &receipt.receipt {
+        ReceiptEnum::Action(action_receipt)
+        | ReceiptEnum::Delegated(DelegatedActionReceipt { action_receipt, .. }) => {
+            total_prepaid_gas(&action_receipt.actions)
+        }
+        ReceiptEnum::Data(_) | ReceiptEnum::SharedContractCode(_) => Ok(0),
+    }
 }
 
 #[cfg(test)]
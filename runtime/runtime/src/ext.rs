@@ -17,7 +17,7 @@ use near_primitives::trie_key::{trie_key_parsers, TrieKey};
 use near_primitives::types::{AccountId, Balance, EpochId, EpochInfoProvider};
 use near_primitives::utils::create_data_id;
 use near_primitives::version::ProtocolVersion;
-use near_store::{get_code, TrieUpdate, TrieUpdateValuePtr};
+use near_store::{get_deployed_code, TrieUpdate, TrieUpdateValuePtr};
 use near_vm_errors::{HostError, InconsistentStateError, VMLogicError};
 use near_vm_logic::{External, ValuePtr};
 
@@ -90,7 +90,7 @@ impl<'a> RuntimeExt<'a> {
         code_hash: CryptoHash,
     ) -> Result<Option<Arc<ContractCode>>, StorageError> {
         debug!(target:"runtime", "Calling the contract at account {}", self.account_id);
-        let code = || get_code(self.trie_update, self.account_id, Some(code_hash));
+        let code = || get_deployed_code(self.trie_update, self.account_id, code_hash);
         crate::cache::get_code(code_hash, code)
     }
 
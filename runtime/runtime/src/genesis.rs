@@ -7,15 +7,17 @@ use near_crypto::PublicKey;
 use near_primitives::runtime::fees::StorageUsageConfig;
 use near_primitives::{
     account::{AccessKey, Account},
-    contract::ContractCode,
-    receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData},
+    contract::{ContractCode, SharedContractCode},
+    hash::hash,
+    receipt::{DelayedReceiptIndices, Receipt, ReceivedData},
     state_record::{state_record_to_account_id, StateRecord},
     trie_key::TrieKey,
     types::{AccountId, Balance, MerkleHash, ShardId, StateChangeCause, StateRoot},
 };
 use near_store::{
-    get_account, get_received_data, set, set_access_key, set_account, set_code,
-    set_postponed_receipt, set_received_data, ShardTries, TrieUpdate,
+    get_account, get_received_data, get_shared_code_registrations, set, set_access_key,
+    set_account, set_code, set_postponed_receipt, set_received_data, set_shared_code,
+    set_shared_code_registrations, ShardTries, TrieUpdate,
 };
 
 use crate::config::RuntimeConfig;
@@ -57,12 +59,22 @@ impl<'a> StorageComputer<'a> {
             StateRecord::PostponedReceipt(_) => None,
             StateRecord::ReceivedData { .. } => None,
             StateRecord::DelayedReceipt(_) => None,
+            StateRecord::SharedContractCode { owners, .. } => {
+                for owner in owners {
+                    self.add_storage_usage(&owner.account_id, owner.storage_usage);
+                }
+                None
+            }
         };
         if let Some((account_id, storage_usage)) = account_and_storage {
-            *self.result.entry(account_id).or_default() += storage_usage;
+            self.add_storage_usage(&account_id, storage_usage);
         }
     }
 
+    fn add_storage_usage(&mut self, account_id: &AccountId, storage_usage: u64) {
+        *self.result.entry(account_id.clone()).or_default() += storage_usage;
+    }
+
     pub fn process_records(&mut self, records: &[StateRecord]) {
         for record in records {
             self.process_record(record);
@@ -108,9 +120,30 @@ impl GenesisStateApplier {
         let mut storage_computer = StorageComputer::new(config);
 
         genesis.for_each_record(|record: &StateRecord| {
-            if !batch_account_ids.contains(state_record_to_account_id(record)) {
+            if let StateRecord::SharedContractCode { owners, code, .. } = record {
+                // The code is added to all the shards by `apply_shared_contract_codes`, only the
+                // registrations and the storage paid by the owners are added here.
+                let code_hash = hash(code);
+                for owner in owners {
+                    if batch_account_ids.contains(&owner.account_id) {
+                        storage_computer.add_storage_usage(&owner.account_id, owner.storage_usage);
+                        let num_registrations =
+                            get_shared_code_registrations(&state_update, &owner.account_id, code_hash)
+                                .unwrap();
+                        set_shared_code_registrations(
+                            &mut state_update,
+                            owner.account_id.clone(),
+                            code_hash,
+                            num_registrations + 1,
+                        );
+                    }
+                }
                 return;
             }
+            match state_record_to_account_id(record) {
+                Some(account_id) if batch_account_ids.contains(account_id) => {}
+                _ => return,
+            }
 
             storage_computer.process_record(record);
 
@@ -151,6 +184,7 @@ impl GenesisStateApplier {
                     )
                         .unwrap();
                 }
+                StateRecord::SharedContractCode { .. } => unreachable!(),
             }
         });
 
@@ -165,9 +199,9 @@ impl GenesisStateApplier {
         // Processing postponed receipts after we stored all received data
         for receipt in postponed_receipts {
             let account_id = &receipt.receiver_id;
-            let action_receipt = match &receipt.receipt {
-                ReceiptEnum::Action(a) => a,
-                _ => panic!("Expected action receipt"),
+            let action_receipt = match receipt.receipt.action_receipt() {
+                Some(a) => a,
+                None => panic!("Expected action receipt"),
             };
             // Logic similar to `apply_receipt`
             let mut pending_data_count: u32 = 0;
@@ -230,6 +264,30 @@ impl GenesisStateApplier {
         }
     }
 
+    fn apply_shared_contract_codes(
+        current_state_root: &mut StateRoot,
+        tries: &mut ShardTries,
+        shard_id: ShardId,
+        genesis: &Genesis,
+    ) {
+        let mut state_update = tries.new_trie_update(shard_id, *current_state_root);
+        let mut has_shared_codes = false;
+        genesis.for_each_record(|record: &StateRecord| {
+            if let StateRecord::SharedContractCode { owners, num_users, code } = record {
+                let shared_code = SharedContractCode {
+                    owners: owners.clone(),
+                    num_users: *num_users,
+                    code: code.clone(),
+                };
+                set_shared_code(&mut state_update, hash(code), &shared_code);
+                has_shared_codes = true;
+            }
+        });
+        if has_shared_codes {
+            Self::commit(state_update, current_state_root, tries, shard_id);
+        }
+    }
+
     pub fn apply(
         mut tries: ShardTries,
         shard_id: ShardId,
@@ -260,6 +318,7 @@ impl GenesisStateApplier {
             &mut tries,
             shard_id,
         );
+        Self::apply_shared_contract_codes(&mut current_state_root, &mut tries, shard_id, genesis);
         current_state_root
     }
 }
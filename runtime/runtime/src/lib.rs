@@ -14,8 +14,10 @@ use near_primitives::{
     errors::{ActionError, ActionErrorKind, RuntimeError, TxExecutionError},
     hash::CryptoHash,
     receipt::{
-        ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData,
+        ActionReceipt, CongestionInfo, DataReceipt, DelayedReceiptIndices, DelegatedActionReceipt,
+        Receipt, ReceiptEnum, ReceivedData, SharedContractCodeReceipt,
     },
+    shard_layout::account_id_to_shard_id,
     state_record::StateRecord,
     transaction::{
         Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, LogEntry,
@@ -23,7 +25,7 @@ use near_primitives::{
     },
     trie_key::TrieKey,
     types::{
-        validator_stake::ValidatorStake, AccountId, Balance, EpochInfoProvider, Gas,
+        validator_stake::ValidatorStake, AccountId, Balance, EpochInfoProvider, Gas, NumShards,
         RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
     },
     utils::{
@@ -31,9 +33,9 @@ use near_primitives::{
     },
 };
 use near_store::{
-    get, get_account, get_postponed_receipt, get_received_data, remove_postponed_receipt, set,
-    set_account, set_postponed_receipt, set_received_data, PartialStorage, ShardTries,
-    StorageError, Trie, TrieChanges, TrieUpdate,
+    get, get_account, get_pending_account_deletion, get_postponed_receipt, get_received_data,
+    remove_postponed_receipt, set, set_account, set_postponed_receipt, set_received_data,
+    PartialStorage, ShardTries, StorageError, Trie, TrieChanges, TrieUpdate,
 };
 #[cfg(feature = "sandbox")]
 use near_store::{set_access_key, set_code};
@@ -44,8 +46,9 @@ pub use near_vm_runner::with_ext_cost_counter;
 use crate::actions::*;
 use crate::balance_checker::check_balance;
 use crate::config::{
-    exec_fee, safe_add_balance, safe_add_gas, safe_gas_to_balance, total_deposit,
-    total_prepaid_exec_fees, total_prepaid_gas, RuntimeConfig,
+    exec_fee, receipt_prepaid_gas, safe_add_balance, safe_add_gas, safe_gas_to_balance,
+    shared_code_receipt_exec_fee, total_deposit, total_prepaid_exec_fees, total_prepaid_gas,
+    RuntimeConfig,
 };
 use crate::genesis::{GenesisStateApplier, StorageComputer};
 use crate::verifier::validate_receipt;
@@ -111,6 +114,9 @@ pub struct ApplyStats {
     /// This is a negative amount. This amount was not charged from the account that issued
     /// the transaction. It's likely due to the delayed queue of the receipts.
     pub gas_deficit_amount: Balance,
+    /// Priority fees of the transactions, included in `tx_burnt_amount`. They are minted back to
+    /// the chunk producers with the rewards of the epoch.
+    pub priority_fees: Balance,
 }
 
 pub struct ApplyResult {
@@ -122,6 +128,8 @@ pub struct ApplyResult {
     pub state_changes: Vec<RawStateChangesWithTrieKey>,
     pub stats: ApplyStats,
     pub proof: Option<PartialStorage>,
+    /// Size of the delayed receipts queue after applying the chunk.
+    pub congestion_info: CongestionInfo,
 }
 
 #[derive(Debug)]
@@ -129,6 +137,9 @@ pub struct ActionResult {
     pub gas_burnt: Gas,
     pub gas_burnt_for_function_call: Gas,
     pub gas_used: Gas,
+    /// Tokens burnt on top of the gas of the receipt, for the gas of the receipts from system
+    /// continuing an account deletion, which the account pays itself.
+    pub burnt_amount: Balance,
     pub result: Result<ReturnData, ActionError>,
     pub logs: Vec<LogEntry>,
     pub new_receipts: Vec<Receipt>,
@@ -151,6 +162,7 @@ impl ActionResult {
             next_result.gas_burnt_for_function_call,
         )?;
         self.gas_used = safe_add_gas(self.gas_used, next_result.gas_used)?;
+        self.burnt_amount = safe_add_balance(self.burnt_amount, next_result.burnt_amount)?;
         self.profile.merge(&next_result.profile);
         self.result = next_result.result;
         self.logs.append(&mut next_result.logs);
@@ -175,6 +187,7 @@ impl Default for ActionResult {
             gas_burnt: 0,
             gas_burnt_for_function_call: 0,
             gas_used: 0,
+            burnt_amount: 0,
             result: Ok(ReturnData::None),
             logs: vec![],
             new_receipts: vec![],
@@ -232,6 +245,7 @@ impl Runtime {
             signed_transaction,
             true,
             Some(apply_state.block_index),
+            apply_state.shard_layout.num_shards(),
             apply_state.current_protocol_version,
         ) {
             Ok(verification_result) => {
@@ -261,6 +275,10 @@ impl Runtime {
                 };
                 stats.tx_burnt_amount =
                     safe_add_balance(stats.tx_burnt_amount, verification_result.burnt_amount)?;
+                if let Some(priority_fee) = transaction.priority_fee {
+                    stats.tx_burnt_amount = safe_add_balance(stats.tx_burnt_amount, priority_fee)?;
+                    stats.priority_fees = safe_add_balance(stats.priority_fees, priority_fee)?;
+                }
                 let outcome = ExecutionOutcomeWithId {
                     id: signed_transaction.get_hash(),
                     outcome: ExecutionOutcome {
@@ -323,6 +341,40 @@ impl Runtime {
             result.result = Err(e);
             return Ok(result);
         }
+        // An account whose deletion is in progress only accepts the receipts from system
+        // continuing its deletion and refunding balance to it.
+        if account.is_some()
+            && checked_feature!(
+                "protocol_feature_multi_step_account_deletion",
+                MultiStepAccountDeletion,
+                apply_state.current_protocol_version
+            )
+        {
+            if let Some(beneficiary_id) = get_pending_account_deletion(state_update, account_id)? {
+                match action {
+                    Action::DeleteAccount(_) if is_refund => {
+                        near_metrics::inc_counter(&metrics::ACTION_DELETE_ACCOUNT_TOTAL);
+                        action_continue_account_deletion(
+                            state_update,
+                            apply_state,
+                            account,
+                            &mut result,
+                            account_id,
+                            &beneficiary_id,
+                        )?;
+                        return Ok(result);
+                    }
+                    Action::Transfer(_) if is_refund => {}
+                    _ => {
+                        result.result = Err(ActionErrorKind::AccountIsBeingDeleted {
+                            account_id: account_id.clone(),
+                        }
+                        .into());
+                        return Ok(result);
+                    }
+                }
+            }
+        }
         // Permission validation
         if let Err(e) = check_actor_permissions(action, account, &actor_id, account_id) {
             result.result = Err(e);
@@ -445,6 +497,79 @@ impl Runtime {
                     apply_state.current_protocol_version,
                 )?;
             }
+            Action::Delegate(signed_delegate_action) => {
+                near_metrics::inc_counter(&metrics::ACTION_DELEGATE_TOTAL);
+                action_delegate(
+                    state_update,
+                    apply_state,
+                    action_receipt,
+                    &receipt.predecessor_id,
+                    &mut result,
+                    account_id,
+                    signed_delegate_action,
+                )?;
+            }
+            Action::DeploySharedContract(deploy_shared_contract) => {
+                near_metrics::inc_counter(&metrics::ACTION_DEPLOY_SHARED_CONTRACT_TOTAL);
+                action_deploy_shared_contract(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &mut result,
+                    &account_id,
+                    deploy_shared_contract,
+                    &apply_state,
+                )?;
+            }
+            Action::RemoveSharedContract(remove_shared_contract) => {
+                near_metrics::inc_counter(&metrics::ACTION_REMOVE_SHARED_CONTRACT_TOTAL);
+                action_remove_shared_contract(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &mut result,
+                    &account_id,
+                    remove_shared_contract,
+                    &apply_state,
+                )?;
+            }
+            Action::DeployContractByHash(deploy_contract_by_hash) => {
+                near_metrics::inc_counter(&metrics::ACTION_DEPLOY_CONTRACT_BY_HASH_TOTAL);
+                action_deploy_contract_by_hash(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &mut result,
+                    &account_id,
+                    deploy_contract_by_hash,
+                    &apply_state,
+                )?;
+            }
+            Action::UpdateAccessKey(update_access_key) => {
+                near_metrics::inc_counter(&metrics::ACTION_UPDATE_ACCESS_KEY_TOTAL);
+                action_update_access_key(
+                    state_update,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    &mut result,
+                    account_id,
+                    update_access_key,
+                )?;
+            }
+            Action::DeployContractAndMigrate(deploy_contract_and_migrate) => {
+                near_metrics::inc_counter(&metrics::ACTION_DEPLOY_CONTRACT_AND_MIGRATE_TOTAL);
+                action_deploy_contract_and_migrate(
+                    state_update,
+                    apply_state,
+                    account.as_mut().expect(EXPECT_ACCOUNT_EXISTS),
+                    receipt,
+                    action_receipt,
+                    promise_results,
+                    &mut result,
+                    account_id,
+                    deploy_contract_and_migrate,
+                    action_hash,
+                    &apply_state.config,
+                    action_index + 1 == actions.len(),
+                    epoch_info_provider,
+                )?;
+            }
         };
         Ok(result)
     }
@@ -460,9 +585,9 @@ impl Runtime {
         stats: &mut ApplyStats,
         epoch_info_provider: &dyn EpochInfoProvider,
     ) -> Result<ExecutionOutcomeWithId, RuntimeError> {
-        let action_receipt = match &receipt.receipt {
-            ReceiptEnum::Action(action_receipt) => action_receipt,
-            _ => unreachable!("given receipt should be an action receipt"),
+        let action_receipt = match receipt.receipt.action_receipt() {
+            Some(action_receipt) => action_receipt,
+            None => unreachable!("given receipt should be an action receipt"),
         };
         let account_id = &receipt.receiver_id;
         // Collecting input data and removing it from the state
@@ -588,6 +713,7 @@ impl Runtime {
                 receipt,
                 action_receipt,
                 &mut result,
+                apply_state.shard_layout.num_shards(),
                 apply_state.current_protocol_version,
                 &apply_state.config.transaction_costs,
             )?
@@ -615,6 +741,9 @@ impl Runtime {
         // `gas_deficit_amount` is strictly less than `gas_price * gas_burnt`.
         let mut tx_burnt_amount =
             safe_gas_to_balance(apply_state.gas_price, gas_burnt)? - gas_deficit_amount;
+        if result.result.is_ok() {
+            tx_burnt_amount = safe_add_balance(tx_burnt_amount, result.burnt_amount)?;
+        }
         // The amount of tokens burnt for the execution of this receipt. It's used in the execution
         // outcome.
         let tokens_burnt = tx_burnt_amount;
@@ -699,10 +828,7 @@ impl Runtime {
                 );
 
                 new_receipt.receipt_id = receipt_id;
-                let is_action = match &new_receipt.receipt {
-                    ReceiptEnum::Action(_) => true,
-                    _ => false,
-                };
+                let is_action = new_receipt.receipt.action_receipt().is_some();
                 outgoing_receipts.push(new_receipt);
                 if is_action {
                     Some(receipt_id)
@@ -750,6 +876,7 @@ impl Runtime {
         receipt: &Receipt,
         action_receipt: &ActionReceipt,
         result: &mut ActionResult,
+        num_shards: NumShards,
         current_protocol_version: ProtocolVersion,
         transaction_costs: &RuntimeFeesConfig,
     ) -> Result<Balance, RuntimeError> {
@@ -760,6 +887,7 @@ impl Runtime {
                 &transaction_costs,
                 &action_receipt.actions,
                 &receipt.receiver_id,
+                num_shards,
                 current_protocol_version,
             )?,
             transaction_costs.action_receipt_creation_config.exec_fee(),
@@ -800,9 +928,13 @@ impl Runtime {
             )?;
         }
         if deposit_refund > 0 {
+            let deposit_refund_receiver = match &receipt.receipt {
+                ReceiptEnum::Delegated(DelegatedActionReceipt { refund_to, .. }) => refund_to,
+                _ => &receipt.predecessor_id,
+            };
             result
                 .new_receipts
-                .push(Receipt::new_balance_refund(&receipt.predecessor_id, deposit_refund));
+                .push(Receipt::new_balance_refund(deposit_refund_receiver, deposit_refund));
         }
         if gas_balance_refund > 0 {
             // Gas refunds refund the allowance of the access key, so if the key exists on the
@@ -916,7 +1048,8 @@ impl Runtime {
                     }
                 }
             }
-            ReceiptEnum::Action(ref action_receipt) => {
+            ReceiptEnum::Action(ref action_receipt)
+            | ReceiptEnum::Delegated(DelegatedActionReceipt { ref action_receipt, .. }) => {
                 // Received a new action receipt. We'll first check how many input data items
                 // were already received before and saved in the state.
                 // And if we have all input data, then we can immediately execute the receipt.
@@ -966,6 +1099,39 @@ impl Runtime {
                     set_postponed_receipt(state_update, &receipt);
                 }
             }
+            ReceiptEnum::SharedContractCode(ref shared_code_receipt) => {
+                // Updating the copy of the shared contract code registry on this shard. The
+                // storage is paid by the owner on its own shard.
+                apply_shared_code_receipt(
+                    state_update,
+                    &receipt.predecessor_id,
+                    shared_code_receipt,
+                )?;
+                state_update.commit(StateChangeCause::ReceiptProcessing {
+                    receipt_hash: receipt.get_hash(),
+                });
+                // The gas is counted towards the limit of the chunk, but the tokens for it were
+                // already burnt by the owner when sending the receipt.
+                let num_bytes = match shared_code_receipt {
+                    SharedContractCodeReceipt::Register { code, .. } => code.len() as u64,
+                    SharedContractCodeReceipt::Remove { .. } => 0,
+                };
+                return Ok(Some(ExecutionOutcomeWithId {
+                    id: receipt.receipt_id,
+                    outcome: ExecutionOutcome {
+                        status: ExecutionStatus::SuccessValue(vec![]),
+                        logs: vec![],
+                        receipt_ids: vec![],
+                        gas_burnt: shared_code_receipt_exec_fee(
+                            &apply_state.config.transaction_costs,
+                            num_bytes,
+                        ),
+                        tokens_burnt: 0,
+                        executor_id: account_id.clone(),
+                        metadata: ExecutionMetadata::ExecutionMetadataV1,
+                    },
+                }));
+            }
         };
         // We didn't trigger execution, so we need to commit the state.
         state_update
@@ -1204,6 +1370,7 @@ impl Runtime {
             && apply_state.current_protocol_version
                 >= ProtocolFeature::FixApplyChunks.protocol_version()
         {
+            let congestion_info = Self::congestion_info(&state_update)?;
             let (trie_changes, state_changes) = state_update.finalize()?;
             let proof = trie.recorded_storage();
             return Ok(ApplyResult {
@@ -1215,6 +1382,7 @@ impl Runtime {
                 state_changes,
                 stats,
                 proof,
+                congestion_info,
             });
         }
 
@@ -1244,15 +1412,30 @@ impl Runtime {
 
             outcomes.push(outcome_with_id);
         }
+        // Gas of the receipts sent towards the congested shards. Once it exceeds the share of the
+        // chunk, the remaining receipts are delayed instead of adding more work to those shards.
+        let mut congested_outgoing_gas =
+            Self::congested_outgoing_gas(apply_state, &outgoing_receipts)?;
+        let congested_outgoing_gas_limit = Self::congested_outgoing_gas_limit(apply_state);
 
         let mut delayed_receipts_indices: DelayedReceiptIndices =
             get(&state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
         let initial_delayed_receipt_indices = delayed_receipts_indices.clone();
+        // The gas attached to the delayed receipts is tracked since `CongestionControl`.
+        let track_delayed_receipts_gas = checked_feature!(
+            "protocol_feature_congestion_control",
+            CongestionControl,
+            apply_state.current_protocol_version
+        );
+        let mut delayed_receipts_gas: Gas =
+            get(&state_update, &TrieKey::DelayedReceiptsGas)?.unwrap_or_default();
+        let initial_delayed_receipts_gas = delayed_receipts_gas;
 
         let mut process_receipt = |receipt: &Receipt,
                                    state_update: &mut TrieUpdate,
                                    total_gas_burnt: &mut Gas|
-         -> Result<_, RuntimeError> {
+         -> Result<Gas, RuntimeError> {
+            let first_new_receipt = outgoing_receipts.len();
             self.process_receipt(
                 state_update,
                 apply_state,
@@ -1271,25 +1454,34 @@ impl Runtime {
                     Ok(())
                 },
             )?;
-            Ok(())
+            Self::congested_outgoing_gas(apply_state, &outgoing_receipts[first_new_receipt..])
         };
 
         let gas_limit = apply_state.gas_limit.unwrap_or(Gas::max_value());
+        let can_process_receipt = |total_gas_burnt: Gas, congested_outgoing_gas: Gas| {
+            total_gas_burnt < gas_limit && congested_outgoing_gas < congested_outgoing_gas_limit
+        };
 
         // We first process local receipts. They contain staking, local contract calls, etc.
         for receipt in local_receipts.iter() {
-            if total_gas_burnt < gas_limit {
+            if can_process_receipt(total_gas_burnt, congested_outgoing_gas) {
                 // NOTE: We don't need to validate the local receipt, because it's just validated in
                 // the `verify_and_charge_transaction`.
-                process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+                let gas = process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+                congested_outgoing_gas = safe_add_gas(congested_outgoing_gas, gas)?;
             } else {
-                Self::delay_receipt(&mut state_update, &mut delayed_receipts_indices, receipt)?;
+                Self::delay_receipt(
+                    &mut state_update,
+                    &mut delayed_receipts_indices,
+                    &mut delayed_receipts_gas,
+                    receipt,
+                )?;
             }
         }
 
         // Then we process the delayed receipts. It's a backlog of receipts from the past blocks.
         while delayed_receipts_indices.first_index < delayed_receipts_indices.next_available_index {
-            if total_gas_burnt >= gas_limit {
+            if !can_process_receipt(total_gas_burnt, congested_outgoing_gas) {
                 break;
             }
             let key = TrieKey::DelayedReceipt { index: delayed_receipts_indices.first_index };
@@ -1313,7 +1505,11 @@ impl Runtime {
             state_update.remove(key);
             // Math checked above: first_index is less than next_available_index
             delayed_receipts_indices.first_index += 1;
-            process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+            // Receipts delayed before the gas was tracked are not accounted in it.
+            delayed_receipts_gas =
+                delayed_receipts_gas.saturating_sub(receipt_prepaid_gas(&receipt)?);
+            let gas = process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+            congested_outgoing_gas = safe_add_gas(congested_outgoing_gas, gas)?;
         }
 
         // And then we process the new incoming receipts. These are receipts from other shards.
@@ -1322,16 +1518,26 @@ impl Runtime {
             // want to store invalid receipts in state as delayed.
             validate_receipt(&apply_state.config.wasm_config.limit_config, &receipt)
                 .map_err(RuntimeError::ReceiptValidationError)?;
-            if total_gas_burnt < gas_limit {
-                process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+            if can_process_receipt(total_gas_burnt, congested_outgoing_gas) {
+                let gas = process_receipt(&receipt, &mut state_update, &mut total_gas_burnt)?;
+                congested_outgoing_gas = safe_add_gas(congested_outgoing_gas, gas)?;
             } else {
-                Self::delay_receipt(&mut state_update, &mut delayed_receipts_indices, receipt)?;
+                Self::delay_receipt(
+                    &mut state_update,
+                    &mut delayed_receipts_indices,
+                    &mut delayed_receipts_gas,
+                    receipt,
+                )?;
             }
         }
 
         if delayed_receipts_indices != initial_delayed_receipt_indices {
             set(&mut state_update, TrieKey::DelayedReceiptIndices, &delayed_receipts_indices);
         }
+        if track_delayed_receipts_gas && delayed_receipts_gas != initial_delayed_receipts_gas {
+            set(&mut state_update, TrieKey::DelayedReceiptsGas, &delayed_receipts_gas);
+        }
+        let congestion_info = Self::congestion_info(&state_update)?;
 
         check_balance(
             &apply_state.config.transaction_costs,
@@ -1342,6 +1548,7 @@ impl Runtime {
             transactions,
             &outgoing_receipts,
             &stats,
+            apply_state.shard_layout.num_shards(),
             apply_state.current_protocol_version,
         )?;
 
@@ -1377,6 +1584,55 @@ impl Runtime {
             state_changes,
             stats,
             proof,
+            congestion_info,
+        })
+    }
+
+    /// Returns the gas needed to execute the given receipts which are sent towards the congested
+    /// shards. Every receipt needs at least the gas of creating an action receipt.
+    fn congested_outgoing_gas(
+        apply_state: &ApplyState,
+        receipts: &[Receipt],
+    ) -> Result<Gas, RuntimeError> {
+        if apply_state.congested_shards.is_empty() {
+            return Ok(0);
+        }
+        let receipt_creation_gas =
+            apply_state.config.transaction_costs.action_receipt_creation_config.exec_fee();
+        let mut gas: Gas = 0;
+        for receipt in receipts {
+            let shard_id = account_id_to_shard_id(&receipt.receiver_id, &apply_state.shard_layout);
+            if apply_state.congested_shards.contains(&shard_id) {
+                gas = safe_add_gas(
+                    gas,
+                    safe_add_gas(receipt_creation_gas, receipt_prepaid_gas(receipt)?)?,
+                )?;
+            }
+        }
+        Ok(gas)
+    }
+
+    /// Returns how much gas the chunk may send towards the congested shards. All shards together
+    /// send at most the gas limit of a chunk towards a congested shard.
+    fn congested_outgoing_gas_limit(apply_state: &ApplyState) -> Gas {
+        if apply_state.congested_shards.is_empty() {
+            return Gas::max_value();
+        }
+        apply_state
+            .gas_limit
+            .map_or(Gas::max_value(), |gas_limit| gas_limit / apply_state.shard_layout.num_shards())
+    }
+
+    /// Returns the size of the delayed receipts queue in the given state.
+    fn congestion_info(state_update: &TrieUpdate) -> Result<CongestionInfo, StorageError> {
+        let delayed_receipts_indices: DelayedReceiptIndices =
+            get(state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+        let delayed_receipts_gas: Gas =
+            get(state_update, &TrieKey::DelayedReceiptsGas)?.unwrap_or_default();
+        Ok(CongestionInfo {
+            delayed_receipts_count: delayed_receipts_indices.next_available_index
+                - delayed_receipts_indices.first_index,
+            delayed_receipts_gas,
         })
     }
 
@@ -1384,8 +1640,9 @@ impl Runtime {
     fn delay_receipt(
         state_update: &mut TrieUpdate,
         delayed_receipts_indices: &mut DelayedReceiptIndices,
+        delayed_receipts_gas: &mut Gas,
         receipt: &Receipt,
-    ) -> Result<(), StorageError> {
+    ) -> Result<(), RuntimeError> {
         set(
             state_update,
             TrieKey::DelayedReceipt { index: delayed_receipts_indices.next_available_index },
@@ -1398,6 +1655,7 @@ impl Runtime {
                         .to_string(),
                 )
             })?;
+        *delayed_receipts_gas = delayed_receipts_gas.saturating_add(receipt_prepaid_gas(receipt)?);
         Ok(())
     }
 
@@ -1461,20 +1719,35 @@ impl Runtime {
 mod tests {
     use super::*;
 
+    use assert_matches::assert_matches;
+    use borsh::BorshSerialize;
     use near_crypto::{InMemorySigner, KeyType, Signer};
-    use near_primitives::account::AccessKey;
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    use near_primitives::account::FunctionCallPermission;
+    use near_primitives::account::{
+        AccessKey, AccessKeyPermission, FunctionCallMultiReceiverPermission,
+    };
     use near_primitives::contract::ContractCode;
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    use near_primitives::errors::InvalidAccessKeyError;
     use near_primitives::hash::hash;
+    use near_primitives::shard_layout::ShardLayout;
     use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
-    use near_primitives::transaction::DeployContractAction;
     use near_primitives::transaction::{
-        AddKeyAction, DeleteKeyAction, FunctionCallAction, TransferAction,
+        AddKeyAction, DeleteKeyAction, FunctionCallAction, TransferAction, UpdateAccessKeyAction,
+    };
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    use near_primitives::transaction::{DelegateAction, SignedDelegateAction};
+    use near_primitives::transaction::{
+        DeployContractAction, DeployContractAndMigrateAction, DeployContractByHashAction,
+        DeploySharedContractAction,
     };
     use near_primitives::types::MerkleHash;
     use near_primitives::version::PROTOCOL_VERSION;
-    use near_store::set_access_key;
     use near_store::test_utils::create_tries;
     use near_store::StoreCompiledContractCache;
+    use near_store::{get_access_key, get_code, set_access_key};
+    use near_vm_errors::{FunctionCallErrorSer, MethodResolveError};
     use near_vm_runner::{get_contract_cache_key, VMKind};
     use testlib::runtime_utils::{alice_account, bob_account};
 
@@ -1583,6 +1856,8 @@ mod tests {
             config: Arc::new(RuntimeConfig::default()),
             cache: Some(Arc::new(StoreCompiledContractCache { store: tries.get_store() })),
             is_new_chunk: true,
+            shard_layout: ShardLayout::v0(1),
+            congested_shards: vec![],
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
         };
@@ -1722,6 +1997,123 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_apply_delayed_receipts_congestion_info() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 1;
+        let (runtime, tries, mut root, apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        let n = 5;
+        let gas = 10u64.pow(14);
+        let mut receipt_id = CryptoHash::default();
+        let receipts: Vec<Receipt> = (0..n)
+            .map(|_| {
+                receipt_id = hash(receipt_id.as_ref());
+                Receipt {
+                    predecessor_id: bob_account(),
+                    receiver_id: alice_account(),
+                    receipt_id,
+                    receipt: ReceiptEnum::Action(ActionReceipt {
+                        signer_id: bob_account(),
+                        signer_public_key: PublicKey::empty(KeyType::ED25519),
+                        gas_price: GAS_PRICE,
+                        output_data_receivers: vec![],
+                        input_data_ids: vec![],
+                        actions: vec![Action::FunctionCall(FunctionCallAction {
+                            method_name: "hello".to_string(),
+                            args: b"world".to_vec(),
+                            gas,
+                            deposit: 0,
+                        })],
+                    }),
+                }
+            })
+            .collect();
+
+        // The first receipt is applied and the others are delayed, then the delayed receipts are
+        // applied one per chunk.
+        for i in 1..=n {
+            let prev_receipts: &[Receipt] = if i == 1 { &receipts } else { &[] };
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    prev_receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            root = new_root;
+            store_update.commit().unwrap();
+            let delayed_receipts_count = n - i;
+            let delayed_receipts_gas = if cfg!(feature = "protocol_feature_congestion_control") {
+                delayed_receipts_count * gas
+            } else {
+                0
+            };
+            assert_eq!(
+                apply_result.congestion_info,
+                CongestionInfo { delayed_receipts_count, delayed_receipts_gas }
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_receipts_limited_towards_congested_shards() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(14);
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        // The function call sends more gas towards bob than the chunk may send to a congested
+        // shard.
+        let transactions = vec![SignedTransaction::call(
+            1,
+            alice_account(),
+            bob_account(),
+            &*signer,
+            0,
+            "hello".to_string(),
+            vec![],
+            2 * gas_limit,
+            CryptoHash::default(),
+        )];
+        let receipts = generate_receipts(to_yocto(10_000), 3);
+        let bob_shard_id = account_id_to_shard_id(&bob_account(), &apply_state.shard_layout);
+
+        let mut apply = |congested_shards: Vec<ShardId>| {
+            apply_state.congested_shards = congested_shards;
+            runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &transactions,
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap()
+        };
+
+        let apply_result = apply(vec![]);
+        assert_eq!(apply_result.outgoing_receipts.len(), 1);
+        assert_eq!(apply_result.congestion_info.delayed_receipts_count, 0);
+
+        // The incoming receipts wait in the delayed receipts queue until bob's shard catches up.
+        let apply_result = apply(vec![bob_shard_id]);
+        assert_eq!(apply_result.outgoing_receipts.len(), 1);
+        assert_eq!(apply_result.congestion_info.delayed_receipts_count, 3);
+    }
+
     #[test]
     fn test_apply_delayed_receipts_add_more_using_chunks() {
         let initial_balance = to_yocto(1_000_000);
@@ -2152,6 +2544,7 @@ mod tests {
                 &apply_state.config.transaction_costs,
                 &actions,
                 &alice_account(),
+                apply_state.shard_layout.num_shards(),
                 PROTOCOL_VERSION,
             )
             .unwrap(),
@@ -2222,6 +2615,7 @@ mod tests {
                 &apply_state.config.transaction_costs,
                 &actions,
                 &alice_account(),
+                apply_state.shard_layout.num_shards(),
                 PROTOCOL_VERSION,
             )
             .unwrap(),
@@ -2343,20 +2737,80 @@ mod tests {
         assert_eq!(final_account_state.storage_usage(), 0);
     }
 
-    #[test]
-    fn test_contract_precompilation() {
-        let initial_balance = to_yocto(1_000_000);
-        let initial_locked = to_yocto(500_000);
-        let gas_limit = 10u64.pow(15);
-        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
-            setup_runtime(initial_balance, initial_locked, gas_limit);
-
-        let wasm_code = near_test_contracts::rs_contract().to_vec();
-        let actions =
-            vec![Action::DeployContract(DeployContractAction { code: wasm_code.clone() })];
+    /// Adds the account of bob with the access key its delegate actions are signed with.
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn setup_delegate_sender(
+        tries: &ShardTries,
+        root: CryptoHash,
+        permission: AccessKeyPermission,
+    ) -> (CryptoHash, InMemorySigner) {
+        let sender_signer =
+            InMemorySigner::from_seed(bob_account(), KeyType::ED25519, bob_account().as_ref());
+        let mut state_update = tries.new_trie_update(0, root);
+        set_account(&mut state_update, bob_account(), &account_new(to_yocto(100), hash(&[])));
+        set_access_key(
+            &mut state_update,
+            bob_account(),
+            sender_signer.public_key(),
+            &AccessKey { nonce: 0, permission },
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        (root, sender_signer)
+    }
 
-        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+    /// Receipt of alice relaying the `actions` signed by bob.
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn create_delegate_receipts(
+        relayer_signer: &InMemorySigner,
+        sender_signer: &InMemorySigner,
+        receiver_id: AccountId,
+        actions: Vec<Action>,
+    ) -> Vec<Receipt> {
+        let delegate_action = DelegateAction {
+            sender_id: bob_account(),
+            receiver_id,
+            actions,
+            nonce: 1,
+            max_block_height: 100,
+            public_key: sender_signer.public_key(),
+        };
+        vec![Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: bob_account(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: relayer_signer.public_key(),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::Delegate(SignedDelegateAction::new(
+                    sender_signer,
+                    delegate_action,
+                ))],
+            }),
+        }]
+    }
 
+    #[test]
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn test_delegate_action_failed_deposit_refunded_to_relayer() {
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        apply_state.current_protocol_version = ProtocolFeature::DelegateAction.protocol_version();
+        let (root, sender_signer) =
+            setup_delegate_sender(&tries, root, AccessKeyPermission::FullAccess);
+
+        // The transfer fails, as the receiver doesn't exist.
+        let receipts = create_delegate_receipts(
+            &signer,
+            &sender_signer,
+            "carol".parse().unwrap(),
+            vec![Action::Transfer(TransferAction { deposit: to_yocto(1) })],
+        );
         let apply_result = runtime
             .apply(
                 tries.get_trie_for_shard(0),
@@ -2369,20 +2823,773 @@ mod tests {
                 None,
             )
             .unwrap();
-        let (store_update, _) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        assert_eq!(apply_result.outgoing_receipts.len(), 1);
+        let delegated_receipt = &apply_result.outgoing_receipts[0];
+        assert_eq!(delegated_receipt.predecessor_id, bob_account());
+        assert_matches!(
+            &delegated_receipt.receipt,
+            ReceiptEnum::Delegated(DelegatedActionReceipt { refund_to, .. })
+                if refund_to == &alice_account()
+        );
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
         store_update.commit().unwrap();
 
-        let contract_code = ContractCode::new(wasm_code, None);
-        let key = get_contract_cache_key(
-            &contract_code,
-            VMKind::default(),
-            &apply_state.config.wasm_config,
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &apply_result.outgoing_receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_matches!(apply_result.outcomes[0].outcome.status, ExecutionStatus::Failure(_));
+        let deposit_refunds = apply_result
+            .outgoing_receipts
+            .iter()
+            .filter(|receipt| match receipt.receipt.action_receipt() {
+                Some(action_receipt) => {
+                    action_receipt.actions
+                        == vec![Action::Transfer(TransferAction { deposit: to_yocto(1) })]
+                }
+                None => false,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(deposit_refunds.len(), 1);
+        assert_eq!(deposit_refunds[0].receiver_id, alice_account());
+        assert!(AccountId::is_system(&deposit_refunds[0].predecessor_id));
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_delegate_action")]
+    fn test_delegate_action_function_call_key_allowance() {
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        apply_state.current_protocol_version = ProtocolFeature::DelegateAction.protocol_version();
+        let allowance = to_yocto(1);
+        let permission = |allowance| {
+            AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                allowance: Some(allowance),
+                receiver_id: "carol".to_string(),
+                method_names: vec![],
+            })
+        };
+        let actions = vec![Action::FunctionCall(FunctionCallAction {
+            method_name: "claim".to_string(),
+            args: vec![],
+            gas: 10u64.pow(12),
+            deposit: 0,
+        })];
+
+        let (key_root, sender_signer) = setup_delegate_sender(&tries, root, permission(allowance));
+        let receipts = create_delegate_receipts(
+            &signer,
+            &sender_signer,
+            "carol".parse().unwrap(),
+            actions.clone(),
         );
-        apply_state
-            .cache
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                key_root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        let (store_update, new_root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        let state_update = tries.new_trie_update(0, new_root);
+        let access_key = get_access_key(&state_update, &bob_account(), &sender_signer.public_key())
+            .unwrap()
+            .unwrap();
+        assert_eq!(access_key.nonce, 1);
+        let remaining_allowance = match access_key.permission {
+            AccessKeyPermission::FunctionCall(FunctionCallPermission { allowance, .. }) => {
+                allowance.unwrap()
+            }
+            permission => panic!("Unexpected permission {:?}", permission),
+        };
+        // The gas of the inner actions is charged at the gas price of the relayer.
+        assert!(remaining_allowance <= allowance - 10u64.pow(12) as Balance * GAS_PRICE);
+
+        // The inner actions aren't sent when the allowance doesn't cover them.
+        let (key_root, sender_signer) = setup_delegate_sender(&tries, root, permission(1));
+        let receipts =
+            create_delegate_receipts(&signer, &sender_signer, "carol".parse().unwrap(), actions);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                key_root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_matches!(
+            &apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                kind: ActionErrorKind::DelegateActionAccessKeyError(
+                    InvalidAccessKeyError::NotEnoughAllowance { allowance: 1, .. }
+                ),
+                ..
+            }))
+        );
+        assert!(apply_result.outgoing_receipts.is_empty());
+    }
+
+    #[test]
+    fn test_contract_precompilation() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(15);
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        let wasm_code = near_test_contracts::rs_contract().to_vec();
+        let actions =
+            vec![Action::DeployContract(DeployContractAction { code: wasm_code.clone() })];
+
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, _) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let contract_code = ContractCode::new(wasm_code, None);
+        let key = get_contract_cache_key(
+            &contract_code,
+            VMKind::default(),
+            &apply_state.config.wasm_config,
+        );
+        apply_state
+            .cache
             .unwrap()
             .get(&key.0)
             .expect("Compiled contract should be cached")
             .expect("Compilation result should be non-empty");
     }
+
+    #[test]
+    fn test_deploy_contract_by_hash() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(15);
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+        let initial_storage_usage = get_account(&tries.new_trie_update(0, root), &alice_account())
+            .unwrap()
+            .unwrap()
+            .storage_usage();
+
+        let wasm_code = near_test_contracts::rs_contract().to_vec();
+        let code_hash = hash(&wasm_code);
+        let actions = vec![
+            Action::DeploySharedContract(DeploySharedContractAction { code: wasm_code.clone() }),
+            Action::DeployContractByHash(DeployContractByHashAction { code_hash }),
+        ];
+
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, root);
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.code_hash(), code_hash);
+        // The account pays for the registered code on the only shard and for the reference to it.
+        let num_extra_bytes_record =
+            apply_state.config.transaction_costs.storage_usage_config.num_extra_bytes_record;
+        assert_eq!(
+            account.storage_usage(),
+            initial_storage_usage
+                + wasm_code.len() as u64
+                + num_extra_bytes_record
+                + CryptoHash::default().as_ref().len() as u64
+        );
+        let shared_code = near_store::get_shared_code(&state_update, code_hash).unwrap().unwrap();
+        assert_eq!(shared_code.owners.len(), 1);
+        assert_eq!(shared_code.num_users, 1);
+        assert!(near_store::get_code(&state_update, &alice_account(), None).unwrap().is_none());
+        let code = near_store::get_deployed_code(&state_update, &alice_account(), code_hash)
+            .unwrap()
+            .expect("Shared contract code should be resolved");
+        assert_eq!(code.code, wasm_code);
+    }
+
+    #[cfg(feature = "protocol_feature_shared_contract_code")]
+    fn apply_receipts(
+        runtime: &Runtime,
+        tries: &ShardTries,
+        root: CryptoHash,
+        apply_state: &ApplyState,
+        receipts: &[Receipt],
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> (ApplyResult, CryptoHash) {
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                apply_state,
+                receipts,
+                &[],
+                epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+        (apply_result, root)
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_shared_contract_code")]
+    fn test_shared_contract_code_registration() {
+        use crate::config::shared_code_receipt_fee;
+        use near_primitives::transaction::{DeleteAccountAction, RemoveSharedContractAction};
+
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        apply_state.shard_layout = ShardLayout::v0(4);
+        let initial_storage_usage = get_account(&tries.new_trie_update(0, root), &alice_account())
+            .unwrap()
+            .unwrap()
+            .storage_usage();
+        let wasm_code = near_test_contracts::rs_contract().to_vec();
+        let code_hash = hash(&wasm_code);
+        let registration_storage_usage = (wasm_code.len() as u64
+            + apply_state.config.transaction_costs.storage_usage_config.num_extra_bytes_record)
+            * 4;
+
+        // The registration is paid for all the shards and sent to the other three of them.
+        let actions = vec![
+            Action::DeploySharedContract(DeploySharedContractAction { code: wasm_code.clone() }),
+            Action::DeployContractByHash(DeployContractByHashAction { code_hash }),
+        ];
+        let fees = &apply_state.config.transaction_costs;
+        let expected_gas_burnt = fees.action_receipt_creation_config.exec_fee()
+            + actions
+                .iter()
+                .map(|action| exec_fee(fees, action, &alice_account(), PROTOCOL_VERSION))
+                .sum::<Gas>()
+            + 3 * shared_code_receipt_fee(fees, wasm_code.len() as u64);
+        let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+        let (apply_result, root) =
+            apply_receipts(&runtime, &tries, root, &apply_state, &receipts, &epoch_info_provider);
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        assert_eq!(apply_result.outcomes[0].outcome.gas_burnt, expected_gas_burnt);
+        let shared_code_receipts: Vec<_> = apply_result
+            .outgoing_receipts
+            .iter()
+            .filter(|receipt| matches!(receipt.receipt, ReceiptEnum::SharedContractCode(_)))
+            .collect();
+        assert_eq!(shared_code_receipts.len(), 3);
+        let state_update = tries.new_trie_update(0, root);
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(
+            account.storage_usage(),
+            initial_storage_usage
+                + registration_storage_usage
+                + CryptoHash::default().as_ref().len() as u64
+        );
+
+        // The account can't be deleted while it pays for the registration.
+        let actions =
+            vec![Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob_account() })];
+        let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+        let (apply_result, root) =
+            apply_receipts(&runtime, &tries, root, &apply_state, &receipts, &epoch_info_provider);
+        assert_eq!(
+            apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::DeleteAccountWithSharedContractCode {
+                    account_id: alice_account()
+                },
+            }))
+        );
+
+        // A registration from another shard is added to the copy of the registry.
+        let register_receipt = Receipt {
+            predecessor_id: bob_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(&[1]),
+            receipt: ReceiptEnum::SharedContractCode(SharedContractCodeReceipt::Register {
+                storage_usage: registration_storage_usage,
+                code: wasm_code.clone(),
+            }),
+        };
+        let (apply_result, root) = apply_receipts(
+            &runtime,
+            &tries,
+            root,
+            &apply_state,
+            &[register_receipt],
+            &epoch_info_provider,
+        );
+        // Applying it burns gas, but no tokens, as they were burnt by the sender.
+        let outcome = &apply_result.outcomes[0].outcome;
+        assert_eq!(
+            outcome.gas_burnt,
+            shared_code_receipt_exec_fee(
+                &apply_state.config.transaction_costs,
+                wasm_code.len() as u64
+            )
+        );
+        assert_eq!(outcome.tokens_burnt, 0);
+        let shared_code =
+            near_store::get_shared_code(&tries.new_trie_update(0, root), code_hash).unwrap();
+        let owners: Vec<_> =
+            shared_code.unwrap().owners.into_iter().map(|owner| owner.account_id).collect();
+        assert_eq!(owners, vec![alice_account(), bob_account()]);
+
+        // Removing the registration releases its storage and is sent to the other shards.
+        let actions = vec![
+            Action::RemoveSharedContract(RemoveSharedContractAction { code_hash }),
+            Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob_account() }),
+        ];
+        let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+        let (apply_result, root) =
+            apply_receipts(&runtime, &tries, root, &apply_state, &receipts, &epoch_info_provider);
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        assert_eq!(
+            apply_result
+                .outgoing_receipts
+                .iter()
+                .filter(|receipt| matches!(
+                    receipt.receipt,
+                    ReceiptEnum::SharedContractCode(SharedContractCodeReceipt::Remove { .. })
+                ))
+                .count(),
+            3
+        );
+        let state_update = tries.new_trie_update(0, root);
+        assert!(get_account(&state_update, &alice_account()).unwrap().is_none());
+        // The code is still registered by the other account, but has no users anymore.
+        let shared_code = near_store::get_shared_code(&state_update, code_hash).unwrap().unwrap();
+        assert_eq!(shared_code.num_users, 0);
+        assert_eq!(shared_code.owners.len(), 1);
+        assert_eq!(shared_code.owners[0].account_id, bob_account());
+
+        // The last registration is removed, so the unused code is removed from the shard.
+        let remove_receipt = Receipt {
+            predecessor_id: bob_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(&[2]),
+            receipt: ReceiptEnum::SharedContractCode(SharedContractCodeReceipt::Remove {
+                code_hash,
+            }),
+        };
+        let (_, root) = apply_receipts(
+            &runtime,
+            &tries,
+            root,
+            &apply_state,
+            &[remove_receipt],
+            &epoch_info_provider,
+        );
+        assert!(near_store::get_shared_code(&tries.new_trie_update(0, root), code_hash)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_shared_contract_code")]
+    fn test_remove_shared_contract_not_owned() {
+        use near_primitives::transaction::RemoveSharedContractAction;
+
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let code_hash = hash(near_test_contracts::rs_contract());
+        let actions = vec![Action::RemoveSharedContract(RemoveSharedContractAction { code_hash })];
+        let receipts = create_receipts_with_actions(alice_account(), signer, actions);
+        let (apply_result, _) =
+            apply_receipts(&runtime, &tries, root, &apply_state, &receipts, &epoch_info_provider);
+        assert_eq!(
+            apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::SharedContractCodeNotOwned {
+                    account_id: alice_account(),
+                    code_hash,
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn test_update_access_key() {
+        let initial_locked = to_yocto(500_000);
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), initial_locked, 10u64.pow(15));
+
+        let public_key =
+            InMemorySigner::from_seed(alice_account(), KeyType::ED25519, "app").public_key();
+        let initial_access_key = AccessKey {
+            nonce: 7,
+            permission: AccessKeyPermission::FunctionCallMultiReceiver(
+                FunctionCallMultiReceiverPermission {
+                    allowance: Some(100),
+                    receiver_ids: vec![bob_account().into(), "carol".to_string()],
+                    method_names: vec![],
+                },
+            ),
+        };
+        let mut state_update = tries.new_trie_update(0, root);
+        set_access_key(&mut state_update, alice_account(), public_key.clone(), &initial_access_key);
+        let initial_account_state = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let actions = vec![Action::UpdateAccessKey(UpdateAccessKeyAction {
+            public_key: public_key.clone(),
+            allowance: Some(to_yocto(1)),
+            method_names: vec!["top_up".to_string(), "claim".to_string()],
+        })];
+        let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(vec![]));
+        let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, root);
+        let access_key =
+            get_access_key(&state_update, &alice_account(), &public_key).unwrap().unwrap();
+        assert_eq!(access_key.nonce, initial_access_key.nonce);
+        assert_eq!(
+            access_key.permission,
+            AccessKeyPermission::FunctionCallMultiReceiver(FunctionCallMultiReceiverPermission {
+                allowance: Some(to_yocto(1)),
+                receiver_ids: vec![bob_account().into(), "carol".to_string()],
+                method_names: vec!["top_up".to_string(), "claim".to_string()],
+            })
+        );
+        let final_account_state = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(
+            final_account_state.storage_usage(),
+            initial_account_state.storage_usage() + access_key.try_to_vec().unwrap().len() as u64
+                - initial_access_key.try_to_vec().unwrap().len() as u64
+        );
+
+        // Full access keys can't be updated.
+        let actions = vec![Action::UpdateAccessKey(UpdateAccessKeyAction {
+            public_key: signer.public_key(),
+            allowance: None,
+            method_names: vec![],
+        })];
+        let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::UpdateKeyRequiresFunctionCallKey {
+                    account_id: alice_account(),
+                    public_key: signer.public_key(),
+                },
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_priority_fee")]
+    fn test_apply_priority_fees() {
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        apply_state.current_protocol_version = ProtocolFeature::PriorityFee.protocol_version();
+
+        let apply = |priority_fees: bool| {
+            let transactions = (1..=2)
+                .map(|nonce| {
+                    let mut signed_tx = SignedTransaction::send_money(
+                        nonce,
+                        alice_account(),
+                        bob_account(),
+                        &*signer,
+                        to_yocto(1),
+                        CryptoHash::default(),
+                    );
+                    if priority_fees {
+                        signed_tx.transaction.priority_fee = Some(nonce as Balance * 1_000);
+                    }
+                    signed_tx.transaction.sign(&*signer)
+                })
+                .collect::<Vec<_>>();
+            runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &[],
+                    &transactions,
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap()
+        };
+
+        let apply_result = apply(false);
+        assert_eq!(apply_result.stats.priority_fees, 0);
+        let tx_burnt_amount = apply_result.stats.tx_burnt_amount;
+
+        // The priority fees are burnt with the chunk and reported to be minted back to the chunk
+        // producers at the end of the epoch.
+        let apply_result = apply(true);
+        assert_eq!(apply_result.outgoing_receipts.len(), 2);
+        assert_eq!(apply_result.stats.priority_fees, 3_000);
+        assert_eq!(apply_result.stats.tx_burnt_amount, tx_burnt_amount + 3_000);
+    }
+
+    #[test]
+    fn test_deploy_contract_and_migrate() {
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let apply = |root, actions| {
+            let receipts = create_receipts_with_actions(alice_account(), signer.clone(), actions);
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            (apply_result.outcomes[0].outcome.status.clone(), root)
+        };
+
+        let wasm_code = near_test_contracts::rs_contract().to_vec();
+        let key = 1u64.to_le_bytes();
+        let args = [key, 10u64.to_le_bytes()].concat();
+        let (status, root) = apply(
+            root,
+            vec![Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                code: wasm_code.clone(),
+                method_name: "write_key_value".to_string(),
+                args,
+                gas: 10u64.pow(14),
+            })],
+        );
+        assert_matches!(status, ExecutionStatus::SuccessValue(_));
+        let state_update = tries.new_trie_update(0, root);
+        let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(account.code_hash(), hash(&wasm_code));
+        let data_key = TrieKey::ContractData { account_id: alice_account(), key: key.to_vec() };
+        assert_eq!(state_update.get(&data_key).unwrap(), Some(10u64.to_le_bytes().to_vec()));
+
+        // The new code doesn't have the migration method, so it is not deployed.
+        let (status, root) = apply(
+            root,
+            vec![Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                code: near_test_contracts::tiny_contract().to_vec(),
+                method_name: "write_key_value".to_string(),
+                args: vec![],
+                gas: 10u64.pow(14),
+            })],
+        );
+        assert_eq!(
+            status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::ContractMigrationFailed {
+                    account_id: alice_account(),
+                    method_name: "write_key_value".to_string(),
+                    error: FunctionCallErrorSer::MethodResolveError(
+                        MethodResolveError::MethodNotFound
+                    ),
+                },
+            }))
+        );
+        let state_update = tries.new_trie_update(0, root);
+        let final_account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        assert_eq!(final_account.code_hash(), hash(&wasm_code));
+        assert_eq!(final_account.storage_usage(), account.storage_usage());
+        let code = get_code(&state_update, &alice_account(), None).unwrap().unwrap();
+        assert_eq!(code.code, wasm_code);
+    }
+
+    #[test]
+    #[cfg(feature = "protocol_feature_multi_step_account_deletion")]
+    fn test_delete_account_with_large_state_in_several_steps() {
+        use near_primitives::transaction::DeleteAccountAction;
+
+        let (runtime, tries, root, mut apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        apply_state.current_protocol_version =
+            ProtocolFeature::MultiStepAccountDeletion.protocol_version();
+        // Each of the receipts continuing the deletion removes at most 4 contract data records.
+        let value = vec![1u8; 1000];
+        let mut config = RuntimeConfig::default();
+        let ext_costs = &config.wasm_config.ext_costs;
+        config.wasm_config.limit_config.max_gas_burnt = 4
+            * (ext_costs.storage_remove_base
+                + ext_costs.storage_remove_key_byte * 8
+                + ext_costs.storage_remove_ret_value_byte * value.len() as u64);
+        apply_state.config = Arc::new(config);
+
+        let mut state_update = tries.new_trie_update(0, root);
+        let mut account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+        for i in 0..20u64 {
+            let key = i.to_le_bytes().to_vec();
+            account.set_storage_usage(account.storage_usage() + 8 + 1000 + 40);
+            state_update
+                .set(TrieKey::ContractData { account_id: alice_account(), key }, value.clone());
+        }
+        set_account(&mut state_update, alice_account(), &account);
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (store_update, root) = tries.apply_all(&trie_changes, 0).unwrap();
+        store_update.commit().unwrap();
+
+        let apply = |root, receipts: &[Receipt]| {
+            let apply_result = runtime
+                .apply(
+                    tries.get_trie_for_shard(0),
+                    root,
+                    &None,
+                    &apply_state,
+                    receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap();
+            let (store_update, root) = tries.apply_all(&apply_result.trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            (apply_result, root)
+        };
+
+        let (apply_result, mut root) = apply(
+            root,
+            &create_receipts_with_actions(
+                alice_account(),
+                signer.clone(),
+                vec![Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob_account() })],
+            ),
+        );
+        assert_matches!(apply_result.outcomes[0].outcome.status, ExecutionStatus::SuccessValue(_));
+        let mut receipts = apply_result.outgoing_receipts;
+
+        // The account doesn't accept other receipts until it's deleted.
+        let (apply_result, _) = apply(
+            root,
+            &create_receipts_with_actions(
+                alice_account(),
+                signer.clone(),
+                vec![Action::Transfer(TransferAction { deposit: 1 })],
+            ),
+        );
+        assert_eq!(
+            apply_result.outcomes[0].outcome.status,
+            ExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+                index: Some(0),
+                kind: ActionErrorKind::AccountIsBeingDeleted { account_id: alice_account() },
+            }))
+        );
+
+        let mut steps = 0;
+        let (refund, balance) = loop {
+            let state_update = tries.new_trie_update(0, root);
+            let account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+            assert_eq!(
+                get_pending_account_deletion(&state_update, &alice_account()).unwrap(),
+                Some(bob_account())
+            );
+            let (apply_result, new_root) = apply(root, &receipts);
+            root = new_root;
+            steps += 1;
+            // The account pays for the gas of the receipts.
+            let burnt_amount = apply_result.stats.tx_burnt_amount;
+            assert!(burnt_amount > 0);
+            if let Some(refund) =
+                apply_result.outgoing_receipts.iter().find(|r| r.receiver_id == bob_account())
+            {
+                break (refund.clone(), account.amount() - burnt_amount);
+            }
+            let state_update = tries.new_trie_update(0, root);
+            let new_account = get_account(&state_update, &alice_account()).unwrap().unwrap();
+            assert!(new_account.storage_usage() < account.storage_usage());
+            if steps > 1 {
+                assert_eq!(
+                    account.storage_usage() - new_account.storage_usage(),
+                    4 * (8 + 1000 + 40)
+                );
+            }
+            assert_eq!(new_account.amount(), account.amount() - burnt_amount);
+            receipts = apply_result.outgoing_receipts;
+        };
+        // The access key and 3 records, 4 records 4 times, and the last record.
+        assert_eq!(steps, 6);
+        assert_eq!(refund.receipt, Receipt::new_balance_refund(&bob_account(), balance).receipt);
+        let state_update = tries.new_trie_update(0, root);
+        assert!(get_account(&state_update, &alice_account()).unwrap().is_none());
+        assert!(get_pending_account_deletion(&state_update, &alice_account()).unwrap().is_none());
+        let data_key = TrieKey::ContractData { account_id: alice_account(), key: vec![0; 8] };
+        assert!(state_update.get(&data_key).unwrap().is_none());
+    }
 }
@@ -38,6 +38,36 @@ lazy_static::lazy_static! {
             "near_action_delete_account_total",
             "The number of DeleteAccount actions called since starting this node"
         );
+    pub static ref ACTION_DELEGATE_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_delegate_total",
+            "The number of Delegate actions called since starting this node"
+        );
+    pub static ref ACTION_DEPLOY_SHARED_CONTRACT_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_deploy_shared_contract_total",
+            "The number of DeploySharedContract actions called since starting this node"
+        );
+    pub static ref ACTION_DEPLOY_CONTRACT_BY_HASH_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_deploy_contract_by_hash_total",
+            "The number of DeployContractByHash actions called since starting this node"
+        );
+    pub static ref ACTION_REMOVE_SHARED_CONTRACT_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_remove_shared_contract_total",
+            "The number of RemoveSharedContract actions called since starting this node"
+        );
+    pub static ref ACTION_UPDATE_ACCESS_KEY_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_update_access_key_total",
+            "The number of UpdateAccessKey actions called since starting this node"
+        );
+    pub static ref ACTION_DEPLOY_CONTRACT_AND_MIGRATE_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_action_deploy_contract_and_migrate_total",
+            "The number of DeployContractAndMigrate actions called since starting this node"
+        );
     pub static ref TRANSACTION_PROCESSED_TOTAL: near_metrics::Result<IntCounter> =
         try_create_int_counter(
             "near_transaction_processed_total",
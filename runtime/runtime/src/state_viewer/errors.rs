@@ -54,6 +54,12 @@ pub enum CallFunctionError {
     VMError { error_message: String },
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum ViewDelayedReceiptsError {
+    #[error("Internal error: #{error_message}")]
+    InternalError { error_message: String },
+}
+
 impl From<ViewAccountError> for ViewContractCodeError {
     fn from(view_account_error: ViewAccountError) -> Self {
         match view_account_error {
@@ -99,3 +105,9 @@ impl From<near_primitives::errors::StorageError> for CallFunctionError {
         Self::InternalError { error_message: storage_error.to_string() }
     }
 }
+
+impl From<near_primitives::errors::StorageError> for ViewDelayedReceiptsError {
+    fn from(storage_error: near_primitives::errors::StorageError) -> Self {
+        Self::InternalError { error_message: storage_error.to_string() }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::{receipt_prepaid_gas, safe_add_gas};
 use crate::{actions::execute_function_call, ext::RuntimeExt};
 use log::debug;
 use near_crypto::{KeyType, PublicKey};
@@ -7,24 +8,29 @@ use near_primitives::{
     config::VMLimitConfig,
     contract::ContractCode,
     hash::CryptoHash,
-    receipt::ActionReceipt,
+    receipt::{ActionReceipt, DelayedReceiptIndices, Receipt},
     runtime::{
         apply_state::ApplyState,
         config::RuntimeConfig,
         migration_data::{MigrationData, MigrationFlags},
     },
     serialize::to_base64,
+    shard_layout::ShardLayout,
     transaction::FunctionCallAction,
-    trie_key::trie_key_parsers,
+    trie_key::{trie_key_parsers, TrieKey},
     types::{AccountId, EpochInfoProvider, Gas},
-    views::{StateItem, ViewApplyState, ViewStateResult},
+    views::{DelayedReceiptView, DelayedReceiptsView, StateItem, ViewApplyState, ViewStateResult},
 };
-use near_store::{get_access_key, get_account, get_code, TrieUpdate};
+use near_store::{get, get_access_key, get_account, get_code, get_deployed_code, TrieUpdate};
 use near_vm_logic::{ReturnData, ViewConfig};
 use std::{str, sync::Arc, time::Instant};
 
 pub mod errors;
 
+/// Maximum number of delayed receipts read to compute their prepaid gas when it isn't tracked in
+/// the state.
+const MAX_DELAYED_RECEIPTS_TO_SUM_GAS: u64 = 1000;
+
 pub struct TrieViewer {
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     state_size_limit: Option<u64>,
@@ -63,7 +69,7 @@ impl TrieViewer {
         account_id: &AccountId,
     ) -> Result<ContractCode, errors::ViewContractCodeError> {
         let account = self.view_account(state_update, account_id)?;
-        get_code(state_update, account_id, Some(account.code_hash()))?.ok_or_else(|| {
+        get_deployed_code(state_update, account_id, account.code_hash())?.ok_or_else(|| {
             errors::ViewContractCodeError::NoContractCode {
                 contract_account_id: account_id.clone(),
             }
@@ -207,6 +213,8 @@ impl TrieViewer {
             config: config.clone(),
             cache: view_state.cache,
             is_new_chunk: false,
+            shard_layout: ShardLayout::v0(1),
+            congested_shards: vec![],
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
         };
@@ -260,4 +268,61 @@ impl TrieViewer {
             Ok(result)
         }
     }
+
+    /// Returns up to `limit` receipts of the delayed receipts queue starting from the queue
+    /// position `from_index`, or from the head of the queue if it is not given. The length and
+    /// the prepaid gas are reported for the whole queue, only the receipts of the page are read.
+    pub fn view_delayed_receipts(
+        &self,
+        state_update: &TrieUpdate,
+        from_index: Option<u64>,
+        limit: u64,
+    ) -> Result<DelayedReceiptsView, errors::ViewDelayedReceiptsError> {
+        let indices: DelayedReceiptIndices =
+            get(state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
+        let length = indices.next_available_index - indices.first_index;
+        // The gas is tracked in the state since `CongestionControl`. Before that, it's only
+        // computed for short queues.
+        let total_prepaid_gas = match get(state_update, &TrieKey::DelayedReceiptsGas)? {
+            Some(gas) => Some(gas),
+            None if length <= MAX_DELAYED_RECEIPTS_TO_SUM_GAS => {
+                let mut gas: Gas = 0;
+                for index in indices.first_index..indices.next_available_index {
+                    let receipt = Self::get_delayed_receipt(state_update, index)?;
+                    gas = receipt_prepaid_gas(&receipt)
+                        .and_then(|receipt_gas| safe_add_gas(gas, receipt_gas))
+                        .map_err(|_| errors::ViewDelayedReceiptsError::InternalError {
+                            error_message: "Delayed receipts gas integer overflow".to_string(),
+                        })?;
+                }
+                Some(gas)
+            }
+            None => None,
+        };
+        let page_start = from_index.unwrap_or(indices.first_index).max(indices.first_index);
+        let page_end = page_start.saturating_add(limit).min(indices.next_available_index);
+        let mut receipts = vec![];
+        for index in page_start..page_end {
+            let receipt = Self::get_delayed_receipt(state_update, index)?;
+            receipts.push(DelayedReceiptView { index, receipt: receipt.into() });
+        }
+        Ok(DelayedReceiptsView {
+            first_index: indices.first_index,
+            next_available_index: indices.next_available_index,
+            length,
+            total_prepaid_gas,
+            receipts,
+        })
+    }
+
+    fn get_delayed_receipt(
+        state_update: &TrieUpdate,
+        index: u64,
+    ) -> Result<Receipt, errors::ViewDelayedReceiptsError> {
+        get(state_update, &TrieKey::DelayedReceipt { index })?.ok_or_else(|| {
+            errors::ViewDelayedReceiptsError::InternalError {
+                error_message: format!("Delayed receipt #{} must be in the state", index),
+            }
+        })
+    }
 }
@@ -1,37 +1,44 @@
 use near_crypto::key_conversion::is_valid_staking_key;
 use near_primitives::runtime::get_insufficient_storage_stake;
 use near_primitives::{
-    account::AccessKeyPermission,
+    account::{AccessKeyPermission, FunctionCallMultiReceiverPermission},
     config::VMLimitConfig,
     errors::{
         ActionsValidationError, InvalidAccessKeyError, InvalidTxError, ReceiptValidationError,
         RuntimeError,
     },
-    receipt::{ActionReceipt, DataReceipt, Receipt, ReceiptEnum},
+    receipt::{
+        ActionReceipt, DataReceipt, DelegatedActionReceipt, Receipt, ReceiptEnum,
+        SharedContractCodeReceipt,
+    },
     transaction::{
-        Action, AddKeyAction, DeployContractAction, FunctionCallAction, SignedTransaction,
-        StakeAction,
+        Action, AddKeyAction, DeployContractAction, DeployContractAndMigrateAction,
+        DeploySharedContractAction, FunctionCallAction, SignedDelegateAction, SignedTransaction,
+        StakeAction, UpdateAccessKeyAction,
     },
-    types::{AccountId, Balance},
+    types::{AccountId, Balance, Gas},
     version::ProtocolVersion,
 };
 use near_store::{
-    get_access_key, get_account, set_access_key, set_account, StorageError, TrieUpdate,
+    get_access_key, get_account, get_pending_account_deletion, set_access_key, set_account,
+    StorageError, TrieUpdate,
 };
 
 use crate::config::{total_prepaid_gas, tx_cost, TransactionCost};
 use crate::VerificationResult;
 use near_primitives::checked_feature;
 use near_primitives::runtime::config::RuntimeConfig;
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{BlockHeight, NumShards};
 
 /// Validates the transaction without using the state. It allows any node to validate a
 /// transaction before forwarding it to the node that tracks the `signer_id` account.
+/// The cost of some actions depends on the number of shards `num_shards`.
 pub fn validate_transaction(
     config: &RuntimeConfig,
     gas_price: Balance,
     signed_transaction: &SignedTransaction,
     verify_signature: bool,
+    num_shards: NumShards,
     current_protocol_version: ProtocolVersion,
 ) -> Result<TransactionCost, RuntimeError> {
     let transaction = &signed_transaction.transaction;
@@ -55,6 +62,21 @@ pub fn validate_transaction(
         .into());
     }
 
+    for action in &transaction.actions {
+        check_action_protocol_features(action, current_protocol_version)
+            .map_err(|e| InvalidTxError::ActionsValidation(e))?;
+    }
+
+    if transaction.priority_fee.is_some()
+        && !checked_feature!("protocol_feature_priority_fee", PriorityFee, current_protocol_version)
+    {
+        return Err(InvalidTxError::ActionsValidation(unsupported_protocol_feature(
+            "PriorityFee",
+            current_protocol_version,
+        ))
+        .into());
+    }
+
     validate_actions(&config.wasm_config.limit_config, &transaction.actions)
         .map_err(|e| InvalidTxError::ActionsValidation(e))?;
 
@@ -65,6 +87,7 @@ pub fn validate_transaction(
         &transaction,
         gas_price,
         sender_is_receiver,
+        num_shards,
         current_protocol_version,
     )
     .map_err(|_| InvalidTxError::CostOverflow.into())
@@ -79,6 +102,7 @@ pub fn verify_and_charge_transaction(
     signed_transaction: &SignedTransaction,
     verify_signature: bool,
     #[allow(unused)] block_height: Option<BlockHeight>,
+    num_shards: NumShards,
     current_protocol_version: ProtocolVersion,
 ) -> Result<VerificationResult, RuntimeError> {
     let TransactionCost { gas_burnt, gas_remaining, receipt_gas_price, total_cost, burnt_amount } =
@@ -87,6 +111,7 @@ pub fn verify_and_charge_transaction(
             gas_price,
             signed_transaction,
             verify_signature,
+            num_shards,
             current_protocol_version,
         )?;
     let transaction = &signed_transaction.transaction;
@@ -98,6 +123,14 @@ pub fn verify_and_charge_transaction(
             return Err(InvalidTxError::SignerDoesNotExist { signer_id: signer_id.clone() }.into());
         }
     };
+    if checked_feature!(
+        "protocol_feature_multi_step_account_deletion",
+        MultiStepAccountDeletion,
+        current_protocol_version
+    ) && get_pending_account_deletion(state_update, signer_id)?.is_some()
+    {
+        return Err(InvalidTxError::SignerIsBeingDeleted { signer_id: signer_id.clone() }.into());
+    }
     let mut access_key = match get_access_key(state_update, &signer_id, &transaction.public_key)? {
         Some(access_key) => access_key,
         None => {
@@ -142,19 +175,15 @@ pub fn verify_and_charge_transaction(
         }
     })?);
 
-    if let AccessKeyPermission::FunctionCall(ref mut function_call_permission) =
-        access_key.permission
-    {
-        if let Some(ref mut allowance) = function_call_permission.allowance {
-            *allowance = allowance.checked_sub(total_cost).ok_or_else(|| {
-                InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::NotEnoughAllowance {
-                    account_id: signer_id.clone(),
-                    public_key: transaction.public_key.clone(),
-                    allowance: *allowance,
-                    cost: total_cost,
-                })
-            })?;
-        }
+    if let Some(Some(allowance)) = access_key.permission.allowance_mut() {
+        *allowance = allowance.checked_sub(total_cost).ok_or_else(|| {
+            InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::NotEnoughAllowance {
+                account_id: signer_id.clone(),
+                public_key: transaction.public_key.clone(),
+                allowance: *allowance,
+                cost: total_cost,
+            })
+        })?;
     }
 
     match get_insufficient_storage_stake(&signer, &config) {
@@ -171,49 +200,12 @@ pub fn verify_and_charge_transaction(
         }
     };
 
-    if let AccessKeyPermission::FunctionCall(ref function_call_permission) = access_key.permission {
-        if transaction.actions.len() != 1 {
-            return Err(InvalidTxError::InvalidAccessKeyError(
-                InvalidAccessKeyError::RequiresFullAccess,
-            )
-            .into());
-        }
-        if let Some(Action::FunctionCall(ref function_call)) = transaction.actions.get(0) {
-            if function_call.deposit > 0 {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::DepositWithFunctionCall,
-                )
-                .into());
-            }
-            if transaction.receiver_id.as_ref() != &function_call_permission.receiver_id {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::ReceiverMismatch {
-                        tx_receiver: transaction.receiver_id.clone(),
-                        ak_receiver: function_call_permission.receiver_id.clone(),
-                    },
-                )
-                .into());
-            }
-            if !function_call_permission.method_names.is_empty()
-                && function_call_permission
-                    .method_names
-                    .iter()
-                    .all(|method_name| &function_call.method_name != method_name)
-            {
-                return Err(InvalidTxError::InvalidAccessKeyError(
-                    InvalidAccessKeyError::MethodNameMismatch {
-                        method_name: function_call.method_name.clone(),
-                    },
-                )
-                .into());
-            }
-        } else {
-            return Err(InvalidTxError::InvalidAccessKeyError(
-                InvalidAccessKeyError::RequiresFullAccess,
-            )
-            .into());
-        }
-    };
+    validate_access_key_permission(
+        &access_key.permission,
+        &transaction.receiver_id,
+        &transaction.actions,
+    )
+    .map_err(InvalidTxError::InvalidAccessKeyError)?;
 
     set_access_key(state_update, signer_id.clone(), transaction.public_key.clone(), &access_key);
     set_account(state_update, signer_id.clone(), &signer);
@@ -221,6 +213,58 @@ pub fn verify_and_charge_transaction(
     Ok(VerificationResult { gas_burnt, gas_remaining, receipt_gas_price, burnt_amount })
 }
 
+/// Checks that an access key allows sending the actions to `receiver_id`. A function call access
+/// key only allows a single function call without a deposit to one of its methods of one of its
+/// receivers. A full access key allows any actions.
+pub(crate) fn validate_access_key_permission(
+    permission: &AccessKeyPermission,
+    receiver_id: &AccountId,
+    actions: &[Action],
+) -> Result<(), InvalidAccessKeyError> {
+    let method_names = match permission {
+        AccessKeyPermission::FullAccess => return Ok(()),
+        AccessKeyPermission::FunctionCall(permission) => &permission.method_names,
+        AccessKeyPermission::FunctionCallMultiReceiver(permission) => &permission.method_names,
+    };
+    if actions.len() != 1 {
+        return Err(InvalidAccessKeyError::RequiresFullAccess);
+    }
+    if let Some(Action::FunctionCall(ref function_call)) = actions.get(0) {
+        if function_call.deposit > 0 {
+            return Err(InvalidAccessKeyError::DepositWithFunctionCall);
+        }
+        match permission {
+            AccessKeyPermission::FunctionCall(permission) => {
+                if receiver_id.as_ref() != &permission.receiver_id {
+                    return Err(InvalidAccessKeyError::ReceiverMismatch {
+                        tx_receiver: receiver_id.clone(),
+                        ak_receiver: permission.receiver_id.clone(),
+                    });
+                }
+            }
+            AccessKeyPermission::FunctionCallMultiReceiver(permission) => {
+                if permission.receiver_ids.iter().all(|id| receiver_id.as_ref() != id) {
+                    return Err(InvalidAccessKeyError::ReceiverNotAllowed {
+                        tx_receiver: receiver_id.clone(),
+                        ak_receivers: permission.receiver_ids.clone(),
+                    });
+                }
+            }
+            AccessKeyPermission::FullAccess => {}
+        }
+        if !method_names.is_empty()
+            && method_names.iter().all(|method_name| &function_call.method_name != method_name)
+        {
+            return Err(InvalidAccessKeyError::MethodNameMismatch {
+                method_name: function_call.method_name.clone(),
+            });
+        }
+        Ok(())
+    } else {
+        Err(InvalidAccessKeyError::RequiresFullAccess)
+    }
+}
+
 /// Validates a given receipt. Checks validity of the Action or Data receipt.
 pub(crate) fn validate_receipt(
     limit_config: &VMLimitConfig,
@@ -239,10 +283,14 @@ pub(crate) fn validate_receipt(
     })?;
 
     match &receipt.receipt {
-        ReceiptEnum::Action(action_receipt) => {
+        ReceiptEnum::Action(action_receipt)
+        | ReceiptEnum::Delegated(DelegatedActionReceipt { action_receipt, .. }) => {
             validate_action_receipt(limit_config, action_receipt)
         }
         ReceiptEnum::Data(data_receipt) => validate_data_receipt(limit_config, data_receipt),
+        ReceiptEnum::SharedContractCode(shared_code_receipt) => {
+            validate_shared_contract_code_receipt(limit_config, shared_code_receipt)
+        }
     }
 }
 
@@ -276,6 +324,28 @@ fn validate_data_receipt(
     Ok(())
 }
 
+/// Validates given shared contract code receipt. Checks that the size of the registered code
+/// doesn't exceed the limit.
+fn validate_shared_contract_code_receipt(
+    limit_config: &VMLimitConfig,
+    receipt: &SharedContractCodeReceipt,
+) -> Result<(), ReceiptValidationError> {
+    match receipt {
+        SharedContractCodeReceipt::Register { code, .. } => {
+            if code.len() as u64 > limit_config.max_contract_size {
+                return Err(ReceiptValidationError::ActionsValidation(
+                    ActionsValidationError::ContractSizeExceeded {
+                        size: code.len() as u64,
+                        limit: limit_config.max_contract_size,
+                    },
+                ));
+            }
+            Ok(())
+        }
+        SharedContractCodeReceipt::Remove { .. } => Ok(()),
+    }
+}
+
 /// Validates given actions:
 ///
 /// - Checks limits if applicable.
@@ -315,6 +385,89 @@ pub(crate) fn validate_actions(
     Ok(())
 }
 
+/// Checks that the protocol features the action depends on are enabled in the given protocol
+/// version. The actions of a delegate action are checked as well.
+pub(crate) fn check_action_protocol_features(
+    action: &Action,
+    current_protocol_version: ProtocolVersion,
+) -> Result<(), ActionsValidationError> {
+    let (enabled, protocol_feature) = match action {
+        Action::Delegate(signed_delegate_action) => {
+            if !checked_feature!(
+                "protocol_feature_delegate_action",
+                DelegateAction,
+                current_protocol_version
+            ) {
+                return Err(unsupported_protocol_feature(
+                    "DelegateAction",
+                    current_protocol_version,
+                ));
+            }
+            for action in &signed_delegate_action.delegate_action.actions {
+                check_action_protocol_features(action, current_protocol_version)?;
+            }
+            return Ok(());
+        }
+        Action::DeploySharedContract(_)
+        | Action::DeployContractByHash(_)
+        | Action::RemoveSharedContract(_) => (
+            checked_feature!(
+                "protocol_feature_shared_contract_code",
+                SharedContractCode,
+                current_protocol_version
+            ),
+            "SharedContractCode",
+        ),
+        Action::UpdateAccessKey(_) => (
+            checked_feature!(
+                "protocol_feature_access_key_update",
+                AccessKeyUpdate,
+                current_protocol_version
+            ),
+            "AccessKeyUpdate",
+        ),
+        Action::AddKey(AddKeyAction { access_key, .. })
+            if matches!(
+                access_key.permission,
+                AccessKeyPermission::FunctionCallMultiReceiver(_)
+            ) =>
+        {
+            (
+                checked_feature!(
+                    "protocol_feature_access_key_update",
+                    AccessKeyUpdate,
+                    current_protocol_version
+                ),
+                "AccessKeyUpdate",
+            )
+        }
+        Action::DeployContractAndMigrate(_) => (
+            checked_feature!(
+                "protocol_feature_deploy_and_migrate",
+                DeployAndMigrate,
+                current_protocol_version
+            ),
+            "DeployAndMigrate",
+        ),
+        _ => return Ok(()),
+    };
+    if enabled {
+        Ok(())
+    } else {
+        Err(unsupported_protocol_feature(protocol_feature, current_protocol_version))
+    }
+}
+
+fn unsupported_protocol_feature(
+    protocol_feature: &str,
+    version: ProtocolVersion,
+) -> ActionsValidationError {
+    ActionsValidationError::UnsupportedProtocolFeature {
+        protocol_feature: protocol_feature.to_string(),
+        version,
+    }
+}
+
 /// Validates a single given action. Checks limits if applicable.
 pub fn validate_action(
     limit_config: &VMLimitConfig,
@@ -329,6 +482,14 @@ pub fn validate_action(
         Action::AddKey(a) => validate_add_key_action(limit_config, a),
         Action::DeleteKey(_) => Ok(()),
         Action::DeleteAccount(_) => Ok(()),
+        Action::Delegate(a) => validate_delegate_action(limit_config, a),
+        Action::DeploySharedContract(a) => validate_deploy_shared_contract_action(limit_config, a),
+        Action::DeployContractByHash(_) => Ok(()),
+        Action::RemoveSharedContract(_) => Ok(()),
+        Action::UpdateAccessKey(a) => validate_update_access_key_action(limit_config, a),
+        Action::DeployContractAndMigrate(a) => {
+            validate_deploy_contract_and_migrate_action(limit_config, a)
+        }
     }
 }
 
@@ -336,6 +497,30 @@ pub fn validate_action(
 fn validate_deploy_contract_action(
     limit_config: &VMLimitConfig,
     action: &DeployContractAction,
+) -> Result<(), ActionsValidationError> {
+    validate_contract_code(limit_config, &action.code)
+}
+
+/// Checks that the given contract code doesn't exceed the size limit.
+fn validate_contract_code(
+    limit_config: &VMLimitConfig,
+    code: &[u8],
+) -> Result<(), ActionsValidationError> {
+    if code.len() as u64 > limit_config.max_contract_size {
+        return Err(ActionsValidationError::ContractSizeExceeded {
+            size: code.len() as u64,
+            limit: limit_config.max_contract_size,
+        });
+    }
+
+    Ok(())
+}
+
+/// Validates `DeploySharedContractAction`. Checks that the given contract size doesn't exceed the
+/// limit.
+fn validate_deploy_shared_contract_action(
+    limit_config: &VMLimitConfig,
+    action: &DeploySharedContractAction,
 ) -> Result<(), ActionsValidationError> {
     if action.code.len() as u64 > limit_config.max_contract_size {
         return Err(ActionsValidationError::ContractSizeExceeded {
@@ -353,20 +538,31 @@ fn validate_function_call_action(
     limit_config: &VMLimitConfig,
     action: &FunctionCallAction,
 ) -> Result<(), ActionsValidationError> {
-    if action.gas == 0 {
+    validate_function_call(limit_config, &action.method_name, &action.args, action.gas)
+}
+
+/// Checks that a function call has gas attached and that its method name and arguments don't
+/// exceed the length limits.
+fn validate_function_call(
+    limit_config: &VMLimitConfig,
+    method_name: &str,
+    args: &[u8],
+    gas: Gas,
+) -> Result<(), ActionsValidationError> {
+    if gas == 0 {
         return Err(ActionsValidationError::FunctionCallZeroAttachedGas);
     }
 
-    if action.method_name.len() as u64 > limit_config.max_length_method_name {
+    if method_name.len() as u64 > limit_config.max_length_method_name {
         return Err(ActionsValidationError::FunctionCallMethodNameLengthExceeded {
-            length: action.method_name.len() as u64,
+            length: method_name.len() as u64,
             limit: limit_config.max_length_method_name,
         });
     }
 
-    if action.args.len() as u64 > limit_config.max_arguments_length {
+    if args.len() as u64 > limit_config.max_arguments_length {
         return Err(ActionsValidationError::FunctionCallArgumentsLengthExceeded {
-            length: action.args.len() as u64,
+            length: args.len() as u64,
             limit: limit_config.max_arguments_length,
         });
     }
@@ -374,6 +570,29 @@ fn validate_function_call_action(
     Ok(())
 }
 
+/// Validates `DeployContractAndMigrateAction`. Checks the limits of a `DeployContractAction`
+/// for the code and the limits of a `FunctionCallAction` for the migration call.
+fn validate_deploy_contract_and_migrate_action(
+    limit_config: &VMLimitConfig,
+    action: &DeployContractAndMigrateAction,
+) -> Result<(), ActionsValidationError> {
+    validate_contract_code(limit_config, &action.code)?;
+    validate_function_call(limit_config, &action.method_name, &action.args, action.gas)
+}
+
+/// Validates `SignedDelegateAction`. Checks that it doesn't contain another delegate action and
+/// validates its actions. The signature is checked when the action is applied.
+fn validate_delegate_action(
+    limit_config: &VMLimitConfig,
+    action: &SignedDelegateAction,
+) -> Result<(), ActionsValidationError> {
+    let actions = &action.delegate_action.actions;
+    if actions.iter().any(|action| matches!(action, Action::Delegate(_))) {
+        return Err(ActionsValidationError::DelegateActionNested);
+    }
+    validate_actions(limit_config, actions)
+}
+
 /// Validates `StakeAction`. Checks that the `public_key` is a valid staking key.
 fn validate_stake_action(action: &StakeAction) -> Result<(), ActionsValidationError> {
     if !is_valid_staking_key(&action.public_key) {
@@ -387,31 +606,78 @@ fn validate_stake_action(action: &StakeAction) -> Result<(), ActionsValidationEr
 
 /// Validates `AddKeyAction`. If the access key permission is `FunctionCall`, checks that the
 /// total number of bytes of the method names doesn't exceed the limit and
-/// every method name length doesn't exceed the limit.
+/// every method name length doesn't exceed the limit. If the permission is
+/// `FunctionCallMultiReceiver`, also checks the number and the validity of the receiver ids.
 fn validate_add_key_action(
     limit_config: &VMLimitConfig,
     action: &AddKeyAction,
 ) -> Result<(), ActionsValidationError> {
-    if let AccessKeyPermission::FunctionCall(fc) = &action.access_key.permission {
-        // Checking method name length limits
-        let mut total_number_of_bytes = 0;
-        for method_name in &fc.method_names {
-            let length = method_name.len() as u64;
-            if length > limit_config.max_length_method_name {
-                return Err(ActionsValidationError::AddKeyMethodNameLengthExceeded {
-                    length,
-                    limit: limit_config.max_length_method_name,
-                });
-            }
-            // Adding terminating character to the total number of bytes
-            total_number_of_bytes += length + 1;
+    match &action.access_key.permission {
+        AccessKeyPermission::FunctionCall(fc) => {
+            validate_method_names(limit_config, &fc.method_names)?;
+        }
+        AccessKeyPermission::FunctionCallMultiReceiver(fc) => {
+            validate_method_names(limit_config, &fc.method_names)?;
+            validate_receiver_ids(&fc.receiver_ids)?;
         }
-        if total_number_of_bytes > limit_config.max_number_bytes_method_names {
-            return Err(ActionsValidationError::AddKeyMethodNamesNumberOfBytesExceeded {
-                total_number_of_bytes,
-                limit: limit_config.max_number_bytes_method_names,
+        AccessKeyPermission::FullAccess => {}
+    }
+
+    Ok(())
+}
+
+/// Validates `UpdateAccessKeyAction`. Checks the method names limits the same way as for
+/// `AddKeyAction`.
+fn validate_update_access_key_action(
+    limit_config: &VMLimitConfig,
+    action: &UpdateAccessKeyAction,
+) -> Result<(), ActionsValidationError> {
+    validate_method_names(limit_config, &action.method_names)
+}
+
+/// Checks that the total number of bytes of the method names of an access key doesn't exceed the
+/// limit and every method name length doesn't exceed the limit.
+fn validate_method_names(
+    limit_config: &VMLimitConfig,
+    method_names: &[String],
+) -> Result<(), ActionsValidationError> {
+    // Checking method name length limits
+    let mut total_number_of_bytes = 0;
+    for method_name in method_names {
+        let length = method_name.len() as u64;
+        if length > limit_config.max_length_method_name {
+            return Err(ActionsValidationError::AddKeyMethodNameLengthExceeded {
+                length,
+                limit: limit_config.max_length_method_name,
             });
         }
+        // Adding terminating character to the total number of bytes
+        total_number_of_bytes += length + 1;
+    }
+    if total_number_of_bytes > limit_config.max_number_bytes_method_names {
+        return Err(ActionsValidationError::AddKeyMethodNamesNumberOfBytesExceeded {
+            total_number_of_bytes,
+            limit: limit_config.max_number_bytes_method_names,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that a multi-receiver access key doesn't list too many receivers and that all of them
+/// are valid account ids.
+fn validate_receiver_ids(receiver_ids: &[String]) -> Result<(), ActionsValidationError> {
+    let number_of_receiver_ids = receiver_ids.len() as u64;
+    if number_of_receiver_ids > FunctionCallMultiReceiverPermission::MAX_RECEIVER_IDS {
+        return Err(ActionsValidationError::AddKeyReceiverIdsNumberExceeded {
+            number_of_receiver_ids,
+            limit: FunctionCallMultiReceiverPermission::MAX_RECEIVER_IDS,
+        });
+    }
+    for receiver_id in receiver_ids {
+        AccountId::validate(receiver_id).map_err(|_| {
+            ActionsValidationError::AddKeyInvalidReceiverId { receiver_id: receiver_id.clone() }
+        })?;
     }
 
     Ok(())
@@ -492,8 +758,15 @@ mod tests {
         expected_err: RuntimeError,
     ) {
         assert_eq!(
-            validate_transaction(&config, gas_price, &signed_transaction, true, PROTOCOL_VERSION)
-                .expect_err("expected an error"),
+            validate_transaction(
+                &config,
+                gas_price,
+                &signed_transaction,
+                true,
+                1,
+                PROTOCOL_VERSION
+            )
+            .expect_err("expected an error"),
             expected_err,
         );
         assert_eq!(
@@ -504,6 +777,7 @@ mod tests {
                 &signed_transaction,
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -528,7 +802,7 @@ mod tests {
             deposit,
             CryptoHash::default(),
         );
-        validate_transaction(&config, gas_price, &transaction, true, PROTOCOL_VERSION)
+        validate_transaction(&config, gas_price, &transaction, true, 1, PROTOCOL_VERSION)
             .expect("valid transaction");
         let verification_result = verify_and_charge_transaction(
             &config,
@@ -537,6 +811,7 @@ mod tests {
             &transaction,
             true,
             None,
+            1,
             PROTOCOL_VERSION,
         )
         .expect("valid transaction");
@@ -609,6 +884,7 @@ mod tests {
                 ),
                 false,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -676,6 +952,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -709,6 +986,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -758,6 +1036,7 @@ mod tests {
             ),
             true,
             None,
+            1,
             PROTOCOL_VERSION,
         )
         .expect_err("expected an error");
@@ -810,6 +1089,7 @@ mod tests {
             ),
             true,
             None,
+            1,
             PROTOCOL_VERSION,
         )
         .expect_err("expected an error");
@@ -852,6 +1132,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -903,6 +1184,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -926,6 +1208,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -949,6 +1232,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -994,6 +1278,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1006,6 +1291,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validate_transaction_receivers_for_multi_receiver_function_call() {
+        let config = RuntimeConfig::default();
+        let (signer, mut state_update, gas_price) = setup_common(
+            TESTING_INIT_BALANCE,
+            0,
+            Some(AccessKey {
+                nonce: 0,
+                permission: AccessKeyPermission::FunctionCallMultiReceiver(
+                    FunctionCallMultiReceiverPermission {
+                        allowance: None,
+                        receiver_ids: vec![bob_account().into(), "carol".to_string()],
+                        method_names: vec![],
+                    },
+                ),
+            }),
+        );
+        let function_call = Action::FunctionCall(FunctionCallAction {
+            method_name: "hello".to_string(),
+            args: b"abc".to_vec(),
+            gas: 100,
+            deposit: 0,
+        });
+
+        assert_eq!(
+            verify_and_charge_transaction(
+                &config,
+                &mut state_update,
+                gas_price,
+                &SignedTransaction::from_actions(
+                    1,
+                    alice_account(),
+                    eve_dot_alice_account(),
+                    &*signer,
+                    vec![function_call.clone()],
+                    CryptoHash::default(),
+                ),
+                true,
+                None,
+                1,
+                PROTOCOL_VERSION,
+            )
+            .expect_err("expected an error"),
+            RuntimeError::InvalidTxError(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::ReceiverNotAllowed {
+                    tx_receiver: eve_dot_alice_account(),
+                    ak_receivers: vec![bob_account().into(), "carol".to_string()],
+                }
+            )),
+        );
+
+        verify_and_charge_transaction(
+            &config,
+            &mut state_update,
+            gas_price,
+            &SignedTransaction::from_actions(
+                1,
+                alice_account(),
+                "carol".parse().unwrap(),
+                &*signer,
+                vec![function_call],
+                CryptoHash::default(),
+            ),
+            true,
+            None,
+            1,
+            PROTOCOL_VERSION,
+        )
+        .expect("valid transaction");
+    }
+
     #[test]
     fn test_validate_transaction_invalid_method_name_for_function_call() {
         let config = RuntimeConfig::default();
@@ -1042,6 +1398,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1087,6 +1444,7 @@ mod tests {
                 ),
                 true,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1123,6 +1481,7 @@ mod tests {
                 &transaction,
                 false,
                 None,
+                1,
                 PROTOCOL_VERSION,
             )
             .expect_err("expected an error"),
@@ -1140,6 +1499,7 @@ mod tests {
             &transaction,
             false,
             None,
+            1,
             PROTOCOL_VERSION,
         )
         .expect("valid transaction");
@@ -1456,6 +1816,106 @@ mod tests {
         .expect("valid action");
     }
 
+    #[test]
+    fn test_validate_action_invalid_add_key_multi_receiver() {
+        let add_key = |receiver_ids: Vec<String>| {
+            Action::AddKey(AddKeyAction {
+                public_key: PublicKey::empty(KeyType::ED25519),
+                access_key: AccessKey {
+                    nonce: 0,
+                    permission: AccessKeyPermission::FunctionCallMultiReceiver(
+                        FunctionCallMultiReceiverPermission {
+                            allowance: Some(1000),
+                            receiver_ids,
+                            method_names: vec!["hello".to_string()],
+                        },
+                    ),
+                },
+            })
+        };
+        validate_action(
+            &VMLimitConfig::default(),
+            &add_key(vec![alice_account().into(), bob_account().into()]),
+        )
+        .expect("valid action");
+        assert_eq!(
+            validate_action(
+                &VMLimitConfig::default(),
+                &add_key(vec![alice_account().into(), "Bob".to_string()]),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::AddKeyInvalidReceiverId { receiver_id: "Bob".to_string() },
+        );
+        let limit = FunctionCallMultiReceiverPermission::MAX_RECEIVER_IDS;
+        assert_eq!(
+            validate_action(
+                &VMLimitConfig::default(),
+                &add_key((0..=limit).map(|i| format!("receiver{}", i)).collect()),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::AddKeyReceiverIdsNumberExceeded {
+                number_of_receiver_ids: limit + 1,
+                limit,
+            },
+        );
+    }
+
+    #[test]
+    fn test_validate_action_invalid_update_access_key_method_name() {
+        let limit_config = VMLimitConfig::default();
+        assert_eq!(
+            validate_action(
+                &limit_config,
+                &Action::UpdateAccessKey(UpdateAccessKeyAction {
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                    allowance: None,
+                    method_names: vec!["a".repeat(limit_config.max_length_method_name as usize + 1)],
+                }),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::AddKeyMethodNameLengthExceeded {
+                length: limit_config.max_length_method_name + 1,
+                limit: limit_config.max_length_method_name,
+            },
+        );
+    }
+
+    #[test]
+    fn test_validate_action_invalid_deploy_contract_and_migrate() {
+        let mut limit_config = VMLimitConfig::default();
+        limit_config.max_contract_size = 4;
+        let action = DeployContractAndMigrateAction {
+            code: b"code".to_vec(),
+            method_name: "migrate".to_string(),
+            args: vec![],
+            gas: 100,
+        };
+        validate_action(&limit_config, &Action::DeployContractAndMigrate(action.clone()))
+            .expect("valid action");
+        assert_eq!(
+            validate_action(
+                &limit_config,
+                &Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                    code: b"large code".to_vec(),
+                    ..action.clone()
+                }),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::ContractSizeExceeded { size: 10, limit: 4 },
+        );
+        assert_eq!(
+            validate_action(
+                &limit_config,
+                &Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                    gas: 0,
+                    ..action
+                }),
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::FunctionCallZeroAttachedGas,
+        );
+    }
+
     #[test]
     fn test_validate_action_valid_delete_key() {
         validate_action(
@@ -1473,4 +1933,28 @@ mod tests {
         )
         .expect("valid action");
     }
+
+    #[test]
+    fn test_check_action_protocol_features() {
+        // None of the features the actions depend on is enabled at the first protocol version.
+        let version = 1;
+        check_action_protocol_features(&Action::Transfer(TransferAction { deposit: 100 }), version)
+            .expect("valid action");
+        assert_eq!(
+            check_action_protocol_features(
+                &Action::DeployContractAndMigrate(DeployContractAndMigrateAction {
+                    code: vec![1; 5],
+                    method_name: "migrate".to_string(),
+                    args: vec![],
+                    gas: 100,
+                }),
+                version,
+            )
+            .expect_err("expected an error"),
+            ActionsValidationError::UnsupportedProtocolFeature {
+                protocol_feature: "DeployAndMigrate".to_string(),
+                version,
+            },
+        );
+    }
 }
@@ -4,6 +4,7 @@ use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::Receipt;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::shard_layout::ShardLayout;
 use near_primitives::state_record::{state_record_to_account_id, StateRecord};
 use near_primitives::test_utils::MockEpochInfoProvider;
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
@@ -70,7 +71,7 @@ impl StandaloneRuntime {
 
         let mut account_ids: HashSet<AccountId> = HashSet::new();
         genesis.for_each_record(|record: &StateRecord| {
-            account_ids.insert(state_record_to_account_id(record).clone());
+            account_ids.extend(state_record_to_account_id(record).cloned());
         });
         let root = runtime.apply_genesis_state(
             tries.clone(),
@@ -95,6 +96,8 @@ impl StandaloneRuntime {
             config: Arc::new(runtime_config),
             cache: None,
             is_new_chunk: true,
+            shard_layout: ShardLayout::v0(1),
+            congested_shards: vec![],
             migration_data: Arc::new(MigrationData::default()),
             migration_flags: MigrationFlags::default(),
         };
@@ -19,17 +19,20 @@ use near_primitives::block::BlockHeader;
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
 use near_primitives::serialize::to_base;
+use near_primitives::shard_layout::ShardUId;
 use near_primitives::state_record::StateRecord;
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_primitives::types::{AccountId, BlockHeight, ShardId, StateChangesRequest, StateRoot};
 use near_store::test_utils::create_test_store;
 use near_store::{create_store, Store, TrieIterator};
 use nearcore::{get_default_home, get_store_path, load_config, NearConfig, NightshadeRuntime};
 use node_runtime::adapter::ViewRuntimeAdapter;
 use state_dump::state_dump;
+use storage_report::storage_report;
 
 mod state_dump;
+mod storage_report;
 
 #[allow(unused)]
 enum LoadTrieMode {
@@ -229,6 +232,7 @@ fn apply_block_at_height(
     ));
     let block_hash = chain_store.get_block_hash_by_height(height).unwrap();
     let block = chain_store.get_block(&block_hash).unwrap().clone();
+    let shard_uid = runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id()).unwrap();
     let apply_result = if block.chunks()[shard_id as usize].height_included() == height {
         let chunk =
             chain_store.get_chunk(&block.chunks()[shard_id as usize].chunk_hash()).unwrap().clone();
@@ -236,6 +240,7 @@ fn apply_block_at_height(
         let mut chain_store_update = ChainStoreUpdate::new(&mut chain_store);
         let receipt_proof_response = chain_store_update
             .get_incoming_receipts_for_shard(
+                runtime_adapter.as_ref(),
                 shard_id,
                 block_hash,
                 prev_block.chunks()[shard_id as usize].height_included(),
@@ -243,6 +248,7 @@ fn apply_block_at_height(
             .unwrap();
         let receipts = collect_receipts_from_response(&receipt_proof_response);
 
+        let congested_shards = chunk.cloned_header().congested_shards().to_vec();
         let chunk_inner = chunk.cloned_header().take_inner();
         let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
             &mut chain_store,
@@ -264,6 +270,7 @@ fn apply_block_at_height(
                 chunk_inner.validator_proposals(),
                 prev_block.header().gas_price(),
                 chunk_inner.gas_limit(),
+                &congested_shards,
                 &block.header().challenges_result(),
                 *block.header().random_value(),
                 true,
@@ -273,7 +280,7 @@ fn apply_block_at_height(
             .unwrap()
     } else {
         let chunk_extra =
-            chain_store.get_chunk_extra(block.header().prev_hash(), shard_id).unwrap().clone();
+            chain_store.get_chunk_extra(block.header().prev_hash(), &shard_uid).unwrap().clone();
 
         runtime_adapter
             .apply_transactions(
@@ -288,6 +295,7 @@ fn apply_block_at_height(
                 chunk_extra.validator_proposals(),
                 block.header().gas_price(),
                 chunk_extra.gas_limit(),
+                &[],
                 &block.header().challenges_result(),
                 *block.header().random_value(),
                 false,
@@ -304,13 +312,15 @@ fn apply_block_at_height(
         apply_result.total_gas_burnt,
         near_config.genesis.config.gas_limit,
         apply_result.total_balance_burnt,
+        apply_result.congestion_info,
+        apply_result.total_priority_fees,
     );
 
     println!(
         "apply chunk for shard {} at height {}, resulting chunk extra {:?}",
         shard_id, height, chunk_extra
     );
-    if let Ok(chunk_extra) = chain_store.get_chunk_extra(&block_hash, shard_id) {
+    if let Ok(chunk_extra) = chain_store.get_chunk_extra(&block_hash, &shard_uid) {
         println!("Existing chunk extra: {:?}", chunk_extra);
     } else {
         println!("no existing chunk extra available");
@@ -319,6 +329,7 @@ fn apply_block_at_height(
 
 fn view_chain(
     store: Arc<Store>,
+    home_dir: &Path,
     near_config: &NearConfig,
     height: Option<BlockHeight>,
     view_block: bool,
@@ -339,29 +350,27 @@ fn view_chain(
         }
     };
 
+    let runtime = NightshadeRuntime::new(
+        &home_dir,
+        store,
+        &near_config.genesis,
+        near_config.client_config.tracked_accounts.clone(),
+        near_config.client_config.tracked_shards.clone(),
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let shard_layout = runtime.get_shard_layout(block.header().epoch_id()).unwrap();
+
     let mut chunk_extras = vec![];
     let mut chunks = vec![];
     for (i, chunk_header) in block.chunks().iter().enumerate() {
         if chunk_header.height_included() == block.header().height() {
-            chunk_extras.push((
-                i,
-                chain_store.get_chunk_extra(&block.hash(), i as ShardId).unwrap().clone(),
-            ));
+            let shard_uid = ShardUId::from_shard_id_and_layout(i as ShardId, &shard_layout);
+            chunk_extras
+                .push((i, chain_store.get_chunk_extra(&block.hash(), &shard_uid).unwrap().clone()));
             chunks.push((i, chain_store.get_chunk(&chunk_header.chunk_hash()).unwrap().clone()));
         }
     }
-    let chunk_extras = block
-        .chunks()
-        .iter()
-        .enumerate()
-        .filter_map(|(i, chunk_header)| {
-            if chunk_header.height_included() == block.header().height() {
-                Some((i, chain_store.get_chunk_extra(&block.hash(), i as ShardId).unwrap().clone()))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
 
     if height.is_none() {
         let head = chain_store.head().unwrap();
@@ -415,6 +424,48 @@ fn dump_code(account: &str, contract_code: ContractCode, output: &str) {
     println!("Dump contract of account {} into file {}", account, output);
 }
 
+fn print_changes_history(
+    store: Arc<Store>,
+    near_config: &NearConfig,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    request: StateChangesRequest,
+) {
+    let chain_store = ChainStore::new(store, near_config.genesis.config.genesis_height);
+    let history = chain_store.get_state_changes_history(from_height, to_height, &request).unwrap();
+    for (height, block_hash, changes) in history {
+        println!("{: >3} {}", height, block_hash);
+        for change in changes {
+            println!("    {:?}", change);
+        }
+    }
+}
+
+fn print_delayed_receipts(
+    runtime: &NightshadeRuntime,
+    shard_id: ShardId,
+    state_root: &StateRoot,
+    from_index: Option<u64>,
+    limit: u64,
+) {
+    let delayed_receipts =
+        runtime.view_delayed_receipts(shard_id, state_root, from_index, limit).unwrap();
+    let total_prepaid_gas = delayed_receipts
+        .total_prepaid_gas
+        .map_or_else(|| "unknown".to_string(), |gas| gas.to_string());
+    println!(
+        "Shard {} has {} delayed receipts (indices {}..{}) with {} prepaid gas in total",
+        shard_id,
+        delayed_receipts.length,
+        delayed_receipts.first_index,
+        delayed_receipts.next_available_index,
+        total_prepaid_gas
+    );
+    for delayed_receipt in delayed_receipts.receipts {
+        println!("{: >6} {:?}", delayed_receipt.index, delayed_receipt.receipt);
+    }
+}
+
 fn main() {
     init_integration_logger();
 
@@ -566,6 +617,108 @@ fn main() {
                 )
                 .help("dump contract data in storage of given account to binary file"),
         )
+        .subcommand(
+            SubCommand::with_name("storage_report")
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .help("Number of the largest contracts and key prefixes to report")
+                        .takes_value(true)
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("prefix_len")
+                        .long("prefix_len")
+                        .help("Length of the contract data key prefixes to group the keys by")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("Format of the report")
+                        .possible_values(&["json", "csv"])
+                        .takes_value(true)
+                        .default_value("json"),
+                )
+                .help("report the bytes of the latest state taken by each account"),
+        )
+        .subcommand(
+            SubCommand::with_name("changes_history")
+                .arg(
+                    Arg::with_name("from_height")
+                        .long("from_height")
+                        .help("Height of the first block to look at")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("to_height")
+                        .long("to_height")
+                        .help("Height of the last block to look at")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("accounts")
+                        .long("accounts")
+                        .help("Comma separated account names")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("changes_type")
+                        .long("changes_type")
+                        .help("Kind of the changes to print")
+                        .possible_values(&[
+                            "account_changes",
+                            "all_access_key_changes",
+                            "contract_code_changes",
+                            "data_changes",
+                        ])
+                        .takes_value(true)
+                        .default_value("account_changes"),
+                )
+                .arg(
+                    Arg::with_name("key_prefix")
+                        .long("key_prefix")
+                        .help("Prefix of the contract data keys, for data_changes")
+                        .takes_value(true)
+                        .default_value(""),
+                )
+                .help("print changes of the given accounts made by blocks in a range of heights"),
+        )
+        .subcommand(
+            SubCommand::with_name("delayed_receipts")
+                .arg(
+                    Arg::with_name("shard_id")
+                        .long("shard_id")
+                        .help("Id of the shard whose queue to print")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("from_index")
+                        .long("from_index")
+                        .help("Queue position of the first receipt to print, defaults to the head")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .help("Max number of receipts to print")
+                        .takes_value(true)
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::with_name("block_height")
+                        .long("block_height")
+                        .help("block height of the state to read, number or \"latest\"")
+                        .takes_value(true)
+                        .default_value("latest"),
+                )
+                .help("print the delayed receipts queue of a shard"),
+        )
         .get_matches();
 
     let home_dir = matches.value_of("home").map(|dir| Path::new(dir)).unwrap();
@@ -639,7 +792,7 @@ fn main() {
             let height = args.value_of("height").map(|s| s.parse::<u64>().unwrap());
             let view_block = args.is_present("block");
             let view_chunks = args.is_present("chunk");
-            view_chain(store, &near_config, height, view_block, view_chunks);
+            view_chain(store, home_dir, &near_config, height, view_block, view_chunks);
         }
         ("check_block", Some(_)) => {
             check_block_chunk_existence(store, &near_config);
@@ -704,6 +857,64 @@ fn main() {
             println!("Storage under key {} of account {} not found", storage_key, account_id);
             std::process::exit(1);
         }
+        ("storage_report", Some(args)) => {
+            let top = args.value_of("top").map(|s| s.parse::<usize>().unwrap()).unwrap();
+            let prefix_len =
+                args.value_of("prefix_len").map(|s| s.parse::<usize>().unwrap()).unwrap();
+            let (runtime, state_roots, header) = load_trie(store, &home_dir, &near_config);
+            println!("Storage roots are {:?}, block height is {}", state_roots, header.height());
+            let report = storage_report(&runtime, &state_roots, top, prefix_len);
+            if args.value_of("format") == Some("csv") {
+                report.write_csv(home_dir).unwrap();
+                println!("Saved storage report into CSV files in {}", home_dir.display());
+            } else {
+                let output_path = home_dir.join("storage_report.json");
+                report.write_json(&output_path).unwrap();
+                println!("Saved storage report into {}", output_path.display());
+            }
+        }
+        ("changes_history", Some(args)) => {
+            let from_height =
+                args.value_of("from_height").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let to_height = args.value_of("to_height").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let account_ids: Vec<AccountId> = args
+                .value_of("accounts")
+                .unwrap()
+                .split(',')
+                .map(|account_id| account_id.parse().unwrap())
+                .collect();
+            let request = match args.value_of("changes_type").unwrap() {
+                "account_changes" => StateChangesRequest::AccountChanges { account_ids },
+                "all_access_key_changes" => {
+                    StateChangesRequest::AllAccessKeyChanges { account_ids }
+                }
+                "contract_code_changes" => StateChangesRequest::ContractCodeChanges { account_ids },
+                "data_changes" => StateChangesRequest::DataChanges {
+                    account_ids,
+                    key_prefix: args.value_of("key_prefix").unwrap().as_bytes().to_vec().into(),
+                },
+                _ => unreachable!(),
+            };
+            print_changes_history(store, &near_config, from_height, to_height, request);
+        }
+        ("delayed_receipts", Some(args)) => {
+            let shard_id = args.value_of("shard_id").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let from_index = args.value_of("from_index").map(|s| s.parse::<u64>().unwrap());
+            let limit = args.value_of("limit").map(|s| s.parse::<u64>().unwrap()).unwrap();
+            let block_height = args.value_of("block_height").unwrap();
+            let mode = if block_height == "latest" {
+                LoadTrieMode::Latest
+            } else if let Ok(height) = block_height.parse::<u64>() {
+                LoadTrieMode::Height(height)
+            } else {
+                panic!("block_height should be either number or \"latest\"")
+            };
+            let (runtime, state_roots, header) =
+                load_trie_stop_at_height(store, &home_dir, &near_config, mode);
+            let state_root = state_roots.get(shard_id as usize).expect("Shard id does not exist");
+            println!("State root is {:?}, block height is {}", state_root, header.height());
+            print_delayed_receipts(&runtime, shard_id, state_root, from_index, limit);
+        }
         (_, _) => unreachable!(),
     }
 }
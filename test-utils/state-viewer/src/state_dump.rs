@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use near_chain::RuntimeAdapter;
 use near_chain_configs::{get_initial_supply, Genesis, GenesisConfig};
 use near_primitives::block::BlockHeader;
+use near_primitives::hash::hash;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{AccountInfo, StateRoot};
 use near_store::TrieIterator;
@@ -36,12 +37,28 @@ pub fn state_dump(
         .collect::<HashMap<_, _>>();
 
     let mut records = vec![];
+    // Every shard has a copy of the shared contract code registry, it is dumped once.
+    let mut shared_codes = BTreeMap::new();
     for (shard_id, state_root) in state_roots.iter().enumerate() {
         let trie = runtime.get_trie_for_shard(shard_id as u64);
         let trie = TrieIterator::new(&trie, &state_root).unwrap();
         for item in trie {
             let (key, value) = item.unwrap();
             if let Some(mut sr) = StateRecord::from_raw_key_value(key, value) {
+                if let StateRecord::SharedContractCode { code, num_users, .. } = &sr {
+                    // The users of the code on all the shards are summed up, as the genesis
+                    // gives the same copy to every shard.
+                    match shared_codes.get_mut(&hash(code)) {
+                        Some(StateRecord::SharedContractCode {
+                            num_users: total_num_users,
+                            ..
+                        }) => *total_num_users += *num_users,
+                        _ => {
+                            shared_codes.insert(hash(code), sr);
+                        }
+                    }
+                    continue;
+                }
                 if let StateRecord::Account { account_id, account } = &mut sr {
                     if account.locked() > 0 {
                         let stake = *validators.get(account_id).map(|(_, s)| s).unwrap_or(&0);
@@ -53,6 +70,7 @@ pub fn state_dump(
             }
         }
     }
+    records.extend(shared_codes.into_iter().map(|(_, shared_code)| shared_code));
 
     let mut genesis_config = genesis_config.clone();
     genesis_config.genesis_height = genesis_height;
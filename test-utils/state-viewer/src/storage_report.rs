@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use near_primitives::trie_key::col;
+use near_primitives::trie_key::trie_key_parsers::{
+    parse_account_id_from_raw_key, parse_data_key_from_contract_data_key,
+};
+use near_primitives::types::{AccountId, StateRoot};
+use near_store::TrieIterator;
+use nearcore::NightshadeRuntime;
+
+/// Bytes of the keys and the values stored in the state for an account, by kind of the key.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AccountStorage {
+    pub account_id: AccountId,
+    pub account: u64,
+    pub contract_code: u64,
+    pub contract_data: u64,
+    pub access_keys: u64,
+    /// Postponed receipts with the data and the ids of the receipts they are waiting for.
+    pub postponed_receipts: u64,
+}
+
+impl AccountStorage {
+    fn new(account_id: AccountId) -> Self {
+        Self {
+            account_id,
+            account: 0,
+            contract_code: 0,
+            contract_data: 0,
+            access_keys: 0,
+            postponed_receipts: 0,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.account
+            + self.contract_code
+            + self.contract_data
+            + self.access_keys
+            + self.postponed_receipts
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ContractSize {
+    pub account_id: AccountId,
+    pub code_size: u64,
+}
+
+/// Contract data of an account under the keys with a common prefix.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct KeyPrefixStorage {
+    pub account_id: AccountId,
+    /// The prefix with the non-printable bytes escaped.
+    pub prefix: String,
+    pub num_keys: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StorageReport {
+    /// Accounts ordered by the total bytes, the largest first.
+    pub accounts: Vec<AccountStorage>,
+    pub largest_contracts: Vec<ContractSize>,
+    /// Largest groups of contract data keys with a common prefix.
+    pub largest_key_prefixes: Vec<KeyPrefixStorage>,
+    /// Bytes not belonging to any account, e.g. the delayed receipts.
+    pub other: u64,
+}
+
+/// Aggregates the records of the state as they are read from the tries.
+pub struct StorageReportBuilder {
+    prefix_len: usize,
+    accounts: BTreeMap<AccountId, AccountStorage>,
+    key_prefixes: HashMap<(AccountId, Vec<u8>), (u64, u64)>,
+    other: u64,
+}
+
+impl StorageReportBuilder {
+    /// `prefix_len` is the number of the first bytes of the contract data keys they are
+    /// grouped by.
+    pub fn new(prefix_len: usize) -> Self {
+        Self { prefix_len, accounts: BTreeMap::new(), key_prefixes: HashMap::new(), other: 0 }
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<(), io::Error> {
+        let bytes = (key.len() + value.len()) as u64;
+        let account_id = match parse_account_id_from_raw_key(key)? {
+            Some(account_id) => account_id,
+            None => {
+                self.other += bytes;
+                return Ok(());
+            }
+        };
+        let column = &key[..1];
+        if column == col::CONTRACT_DATA {
+            let data_key = parse_data_key_from_contract_data_key(key, &account_id)?;
+            let prefix = data_key[..std::cmp::min(self.prefix_len, data_key.len())].to_vec();
+            let entry = self.key_prefixes.entry((account_id.clone(), prefix)).or_default();
+            entry.0 += 1;
+            entry.1 += bytes;
+        }
+        let storage = self
+            .accounts
+            .entry(account_id.clone())
+            .or_insert_with(|| AccountStorage::new(account_id));
+        if column == col::ACCOUNT {
+            storage.account += bytes;
+        } else if column == col::CONTRACT_CODE {
+            storage.contract_code += bytes;
+        } else if column == col::CONTRACT_DATA {
+            storage.contract_data += bytes;
+        } else if column == col::ACCESS_KEY {
+            storage.access_keys += bytes;
+        } else {
+            storage.postponed_receipts += bytes;
+        }
+        Ok(())
+    }
+
+    /// Builds the report listing the `top` largest contracts and key prefixes.
+    pub fn build(self, top: usize) -> StorageReport {
+        let mut accounts: Vec<_> = self.accounts.into_iter().map(|(_, storage)| storage).collect();
+        accounts.sort_by(|a, b| b.total().cmp(&a.total()));
+
+        let mut largest_contracts: Vec<_> = accounts
+            .iter()
+            .filter(|storage| storage.contract_code > 0)
+            .map(|storage| ContractSize {
+                account_id: storage.account_id.clone(),
+                code_size: storage.contract_code,
+            })
+            .collect();
+        largest_contracts.sort_by(|a, b| b.code_size.cmp(&a.code_size));
+        largest_contracts.truncate(top);
+
+        let mut largest_key_prefixes: Vec<_> = self
+            .key_prefixes
+            .into_iter()
+            .map(|((account_id, prefix), (num_keys, bytes))| KeyPrefixStorage {
+                account_id,
+                prefix: escape(&prefix),
+                num_keys,
+                bytes,
+            })
+            .collect();
+        largest_key_prefixes
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.account_id.cmp(&b.account_id)));
+        largest_key_prefixes.truncate(top);
+
+        StorageReport { accounts, largest_contracts, largest_key_prefixes, other: self.other }
+    }
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().flat_map(|byte| std::ascii::escape_default(*byte)).map(char::from).collect()
+}
+
+/// Walks the state of every shard and reports the bytes it takes by account.
+pub fn storage_report(
+    runtime: &NightshadeRuntime,
+    state_roots: &[StateRoot],
+    top: usize,
+    prefix_len: usize,
+) -> StorageReport {
+    let mut builder = StorageReportBuilder::new(prefix_len);
+    for (shard_id, state_root) in state_roots.iter().enumerate() {
+        let trie = runtime.get_trie_for_shard(shard_id as u64);
+        for item in TrieIterator::new(&trie, state_root).unwrap() {
+            let (key, value) = item.unwrap();
+            if let Err(err) = builder.add(&key, &value) {
+                println!("Skipping a record of shard {}: {}", shard_id, err);
+            }
+        }
+    }
+    builder.build(top)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl StorageReport {
+    pub fn write_json(&self, path: &Path) -> Result<(), io::Error> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Writes the accounts, the contracts and the key prefixes into separate files in `dir`.
+    pub fn write_csv(&self, dir: &Path) -> Result<(), io::Error> {
+        let mut file = File::create(dir.join("storage_accounts.csv"))?;
+        writeln!(
+            file,
+            "account_id,total,account,contract_code,contract_data,access_keys,postponed_receipts"
+        )?;
+        for storage in &self.accounts {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                storage.account_id,
+                storage.total(),
+                storage.account,
+                storage.contract_code,
+                storage.contract_data,
+                storage.access_keys,
+                storage.postponed_receipts
+            )?;
+        }
+
+        let mut file = File::create(dir.join("storage_contracts.csv"))?;
+        writeln!(file, "account_id,code_size")?;
+        for contract in &self.largest_contracts {
+            writeln!(file, "{},{}", contract.account_id, contract.code_size)?;
+        }
+
+        let mut file = File::create(dir.join("storage_key_prefixes.csv"))?;
+        writeln!(file, "account_id,prefix,num_keys,bytes")?;
+        for key_prefix in &self.largest_key_prefixes {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                key_prefix.account_id,
+                csv_field(&key_prefix.prefix),
+                key_prefix.num_keys,
+                key_prefix.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::account::{AccessKey, Account};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::AccountId;
+
+    use super::{AccountStorage, ContractSize, KeyPrefixStorage, StorageReportBuilder};
+
+    #[test]
+    fn test_storage_report() {
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let account = Account::new(0, 0, CryptoHash::default(), 0);
+        let account_bytes = borsh::BorshSerialize::try_to_vec(&account).unwrap();
+        let mut builder = StorageReportBuilder::new(2);
+        let mut add = |key: TrieKey, value: &[u8]| builder.add(&key.to_vec(), value).unwrap();
+
+        add(TrieKey::Account { account_id: alice.clone() }, &account_bytes);
+        add(TrieKey::ContractCode { account_id: alice.clone() }, &[0; 100]);
+        for key in &["aa1", "aa2", "b"] {
+            add(
+                TrieKey::ContractData { account_id: alice.clone(), key: key.as_bytes().to_vec() },
+                &[0; 10],
+            );
+        }
+        add(TrieKey::Account { account_id: bob.clone() }, &account_bytes);
+        add(
+            TrieKey::AccessKey {
+                account_id: bob.clone(),
+                public_key: near_crypto::PublicKey::empty(near_crypto::KeyType::ED25519),
+            },
+            &borsh::BorshSerialize::try_to_vec(&AccessKey::full_access()).unwrap(),
+        );
+        add(TrieKey::DelayedReceiptIndices, &[0; 16]);
+
+        let report = builder.build(1);
+        let alice_account_key_len = TrieKey::Account { account_id: alice.clone() }.len() as u64;
+        let alice_data_key_len =
+            TrieKey::ContractData { account_id: alice.clone(), key: vec![] }.len() as u64;
+        assert_eq!(report.accounts.len(), 2);
+        assert_eq!(
+            report.accounts[0],
+            AccountStorage {
+                account_id: alice.clone(),
+                account: alice_account_key_len + account_bytes.len() as u64,
+                contract_code: alice_account_key_len + 100,
+                contract_data: 3 * alice_data_key_len + 3 + 3 + 1 + 3 * 10,
+                access_keys: 0,
+                postponed_receipts: 0,
+            }
+        );
+        assert_eq!(report.accounts[1].account_id, bob);
+        assert!(report.accounts[1].access_keys > 0);
+        assert_eq!(
+            report.largest_contracts,
+            vec![ContractSize {
+                account_id: alice.clone(),
+                code_size: alice_account_key_len + 100
+            }]
+        );
+        assert_eq!(
+            report.largest_key_prefixes,
+            vec![KeyPrefixStorage {
+                account_id: alice,
+                prefix: "aa".to_string(),
+                num_keys: 2,
+                bytes: 2 * alice_data_key_len + 3 + 3 + 2 * 10,
+            }]
+        );
+        assert_eq!(report.other, TrieKey::DelayedReceiptIndices.len() as u64 + 16);
+    }
+}
@@ -23,6 +23,12 @@ fn main() {
                 .help("Directory for config and data (default \"~/.near\")")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("fix")
+                .long("fix")
+                .help("Repair the inconsistencies which can be fixed without losing data")
+                .takes_value(false),
+        )
         .subcommand(SubCommand::with_name("validate"))
         .get_matches();
 
@@ -66,6 +72,20 @@ fn main() {
             error.err
         );
     }
+    for repair in store_validator.repairs.iter() {
+        println!("{}  {}", Yellow.bold().paint("Suggested repair:"), repair);
+    }
+    if matches.is_present("fix") && !store_validator.repairs.is_empty() {
+        match store_validator.apply_repairs() {
+            Ok(num_repairs) => {
+                println!("Repairs applied: {}", Green.bold().paint(num_repairs.to_string()))
+            }
+            Err(err) => {
+                println!("{} {}", Red.bold().paint("Failed to apply repairs:"), err);
+                process::exit(1);
+            }
+        }
+    }
     if store_validator.is_failed() {
         println!("Errors found: {}", Red.bold().paint(store_validator.num_failed().to_string()));
         process::exit(1);
@@ -84,8 +84,9 @@ fn main() -> Result<()> {
             continue;
         }
 
+        let shard_uid = runtime.shard_id_to_uid(shard_id, block.header().epoch_id()).unwrap();
         let chunk_extra =
-            chain_store.get_chunk_extra(block.header().prev_hash(), shard_id).unwrap().clone();
+            chain_store.get_chunk_extra(block.header().prev_hash(), &shard_uid).unwrap().clone();
         let apply_result = runtime
             .apply_transactions(
                 shard_id,
@@ -99,6 +100,7 @@ fn main() -> Result<()> {
                 chunk_extra.validator_proposals(),
                 block.header().gas_price(),
                 chunk_extra.gas_limit(),
+                &[],
                 &block.header().challenges_result(),
                 *block.header().random_value(),
                 false,